@@ -45,6 +45,12 @@ async fn file() -> HttpResponse {
     }
 } 
 
+#[url(APP.reg_from(&[TEST_URL.clone(), LitUrl("missing_file")]))] 
+async fn missing_file() -> Result<HttpResponse, HttpError> {
+    let content = std::fs::read("programfiles/missing.txt")?; 
+    Ok(normal_response(StatusCode::OK, content)) 
+} 
+
 #[url(APP.reg_from(&[TEST_URL.clone(), LitUrl("get")]), config=[HttpSafety::new().with_allowed_method(HttpMethod::GET)])]  
 async fn get_only() -> HttpResponse { 
     text_response("Get only")  
@@ -40,3 +40,11 @@ pub async fn MyMiddleWare5(){
     println!("Local: {}, Params: {}", value, param); 
     req 
 }
+
+#[middleware(config(prefix: String = "Middleware".to_string(), verbose: bool))] 
+pub async fn MyMiddleWare6(){ 
+    if verbose { 
+        println!("{}: Received request for {}", prefix, req.path()); 
+    } 
+    next(req).await 
+} 
@@ -0,0 +1,370 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use akari::Value;
+use starberry_core::app::middleware::{AsyncMiddleware, BoxFuture};
+use starberry_core::cache::{MemoryCache, SharedCache};
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::cookie::Cookie;
+use starberry_lib::ende::signing::constant_time_eq;
+use starberry_lib::secure_token;
+
+use crate::session::session::RenewId;
+use crate::session::{CSessionRW, SessionRW, PRINCIPAL_KEY};
+
+/// The session key holding the id of the logged in user
+const SESSION_KEY: &str = PRINCIPAL_KEY;
+/// The session key holding the series of the "remember me" cookie issued to the session
+pub(crate) const REMEMBER_SERIES_KEY: &str = "auth_remember_series";
+const REMEMBER_COOKIE: &str = "remember_token";
+const REMEMBER_PREFIX: &str = "remember:";
+static DEFAULT_REMEMBER_TTL: u64 = 3600 * 24 * 30; // Default of 30 days
+
+/// A user which can be logged in with `Auth`.
+pub trait AuthUser: Send + Sync + 'static {
+    /// The id stored in the session, used to load the user back with the loader of `Auth`
+    fn auth_id(&self) -> String;
+}
+
+/// The user of the current request, set by `Auth`
+struct CurrentUser<U>(U);
+
+/// A login or logout done by the handler, applied by `Auth` on the response
+enum AuthChange {
+    Login { user_id: String, remember: bool },
+    Logout,
+}
+
+/// The store of the "remember me" tokens of the applications without a cache
+fn local_remember_tokens() -> SharedCache {
+    static CACHE: OnceLock<SharedCache> = OnceLock::new();
+    CACHE.get_or_init(|| Arc::new(MemoryCache::new(10_000))).clone()
+}
+
+/// The "remember me" tokens are kept in the cache of the application, so that they are
+/// shared by its instances and, with a persistent backend, survive a restart
+pub(crate) fn remember_tokens(req: &HttpReqCtx) -> SharedCache {
+    req.cache().unwrap_or_else(local_remember_tokens)
+}
+
+/// Creates a new series for the user and returns it with the cookie value.
+/// The series stays the same for a browser while the token is replaced each time it is used
+async fn issue_remember_token(tokens: &SharedCache, user_id: &str, ttl: u64) -> (String, String) {
+    let series = secure_token(18);
+    let token = secure_token(32);
+    let value = format!("{}:{}", series, token);
+    store_remember_token(tokens, &series, &token, user_id, ttl).await;
+    (series, value)
+}
+
+/// Stores a token of a series as "<token> <user id>", expiring when unused for `ttl`
+async fn store_remember_token(tokens: &SharedCache, series: &str, token: &str, user_id: &str, ttl: u64) {
+    let key = format!("{}{}", REMEMBER_PREFIX, series);
+    let entry = format!("{} {}", token, user_id);
+    if let Err(e) = tokens.set_string(&key, &entry, Some(Duration::from_secs(ttl))).await {
+        eprintln!("Failed to store a remember me token: {}", e);
+    }
+}
+
+/// Checks a cookie value. A valid token is rotated and the user id returned with the new cookie value.
+/// A known series presented with a wrong token means the cookie was stolen, so the series is revoked.
+async fn consume_remember_token(tokens: &SharedCache, value: &str, ttl: u64) -> Option<(String, String)> {
+    let (series, token) = value.split_once(':')?;
+    let entry = tokens.get_string(&format!("{}{}", REMEMBER_PREFIX, series)).await.ok().flatten()?;
+    let (stored, user_id) = entry.split_once(' ')?;
+    if !constant_time_eq(stored.as_bytes(), token.as_bytes()) {
+        revoke_series(tokens, series).await;
+        return None;
+    }
+    let token = secure_token(32);
+    store_remember_token(tokens, series, &token, user_id, ttl).await;
+    Some((user_id.to_string(), format!("{}:{}", series, token)))
+}
+
+async fn revoke_remember_token(tokens: &SharedCache, value: &str) {
+    if let Some((series, _)) = value.split_once(':') {
+        revoke_series(tokens, series).await;
+    }
+}
+
+/// Revokes a series of "remember me" tokens
+pub(crate) async fn revoke_series(tokens: &SharedCache, series: &str) {
+    let _ = tokens.delete(&format!("{}{}", REMEMBER_PREFIX, series)).await;
+}
+
+fn session_value(req: &HttpReqCtx, key: &str) -> Option<String> {
+    if let Some(session) = req.params.get::<SessionRW<'static>>() {
+        return session.get(key).cloned();
+    }
+    match req.params.get::<CSessionRW>().and_then(|session| session.get(key)) {
+        Some(Value::Str(value)) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn set_session_value(req: &mut HttpReqCtx, key: &str, value: Option<String>) {
+    if let Some(session) = req.params.get_mut::<SessionRW<'static>>() {
+        match value {
+            Some(value) => session.set(key, value),
+            None => {
+                session.remove(key);
+            }
+        }
+    } else if let Some(session) = req.params.get_mut::<CSessionRW>() {
+        match value {
+            Some(value) => session.insert(key.to_string(), Value::Str(value)),
+            None => {
+                session.remove(key);
+            }
+        }
+    }
+}
+
+fn session_user_id(req: &HttpReqCtx) -> Option<String> {
+    session_value(req, SESSION_KEY)
+}
+
+fn set_session_user_id(req: &mut HttpReqCtx, user_id: Option<String>) {
+    set_session_value(req, SESSION_KEY, user_id);
+}
+
+/// Login and logout helpers for the handlers behind `Auth`
+pub trait AuthExt {
+    /// Logs the user in for the rest of the session
+    fn login<U: AuthUser>(&mut self, user: U);
+
+    /// Logs the user in and sets a "remember me" cookie which logs them back in after the session expired
+    fn login_remembered<U: AuthUser>(&mut self, user: U);
+
+    /// Logs the user out and revokes their "remember me" cookie
+    fn logout(&mut self);
+
+    /// The logged in user, if any
+    fn user<U: AuthUser>(&self) -> Option<&U>;
+
+    /// The id of the logged in user, read from the session without loading the user
+    fn user_id(&self) -> Option<String>;
+
+    fn is_authenticated(&self) -> bool;
+}
+
+fn login<U: AuthUser>(req: &mut HttpReqCtx, user: U, remember: bool) {
+    let user_id = user.auth_id();
+    set_session_user_id(req, Some(user_id.clone()));
+    req.params.set(RenewId);
+    req.params.set(CurrentUser(user));
+    req.params.set(AuthChange::Login { user_id, remember });
+}
+
+impl AuthExt for HttpReqCtx {
+    fn login<U: AuthUser>(&mut self, user: U) {
+        login(self, user, false);
+    }
+
+    fn login_remembered<U: AuthUser>(&mut self, user: U) {
+        login(self, user, true);
+    }
+
+    fn logout(&mut self) {
+        set_session_user_id(self, None);
+        self.params.set(AuthChange::Logout);
+    }
+
+    fn user<U: AuthUser>(&self) -> Option<&U> {
+        if let Some(AuthChange::Logout) = self.params.get::<AuthChange>() {
+            return None;
+        }
+        self.params.get::<CurrentUser<U>>().map(|user| &user.0)
+    }
+
+    fn user_id(&self) -> Option<String> {
+        session_user_id(self)
+    }
+
+    fn is_authenticated(&self) -> bool {
+        session_user_id(self).is_some()
+    }
+}
+
+type Loader<U> = Arc<dyn Fn(String) -> BoxFuture<Option<U>> + Send + Sync>;
+
+/// Authenticates the requests from the identity persisted in the session.
+///
+/// Must be placed after `Session` or `CookieSession`. The loader turns the user id stored
+/// in the session back into the user, which the handlers get with `req.user::<U>()`.
+/// When the session has no user, a valid "remember me" cookie logs the user back in,
+/// its token being replaced on each use.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// struct User { id: u64, name: String }
+///
+/// impl AuthUser for User {
+///     fn auth_id(&self) -> String { self.id.to_string() }
+/// }
+///
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .append_middleware::<Session>()
+///     .add_middleware(Auth::new(|id: String| async move { find_user(&id).await }));
+///
+/// // In the login handler
+/// req.login_remembered(user);
+///
+/// // In any handler
+/// if let Some(user) = req.user::<User>() { ... }
+/// ```
+pub struct Auth<U: AuthUser> {
+    loader: Loader<U>,
+    remember_ttl: u64,
+}
+
+impl<U: AuthUser> Auth<U> {
+    pub fn new<F, Fut>(loader: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<U>> + Send + 'static,
+    {
+        Self {
+            loader: Arc::new(move |id| Box::pin(loader(id))),
+            remember_ttl: DEFAULT_REMEMBER_TTL,
+        }
+    }
+
+    /// Sets how long a "remember me" cookie stays valid without being used, 30 days by default
+    pub fn remember_for(mut self, ttl: Duration) -> Self {
+        self.remember_ttl = ttl.as_secs();
+        self
+    }
+}
+
+impl<U: AuthUser> AsyncMiddleware<HttpReqCtx> for Auth<U> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Without a loader nobody is authenticated, use `Auth::new` instead
+    fn return_self() -> Self {
+        Self::new(|_| async { None })
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let loader = self.loader.clone();
+        let ttl = self.remember_ttl;
+        Box::pin(async move {
+            let tokens = remember_tokens(&req);
+            let remember_cookie = req.get_cookie(REMEMBER_COOKIE).map(|c| c.get_value().to_string());
+            // The new value of the "remember me" cookie, an empty one clears it
+            let mut set_cookie: Option<String> = None;
+
+            let mut user_id = session_user_id(&req);
+            if user_id.is_none()
+                && let Some(value) = &remember_cookie
+            {
+                match consume_remember_token(&tokens, value, ttl).await {
+                    Some((id, rotated)) => {
+                        set_session_user_id(&mut req, Some(id.clone()));
+                        let series = rotated.split_once(':').map(|(series, _)| series.to_string());
+                        set_session_value(&mut req, REMEMBER_SERIES_KEY, series);
+                        req.params.set(RenewId);
+                        user_id = Some(id);
+                        set_cookie = Some(rotated);
+                    }
+                    None => set_cookie = Some(String::new()),
+                }
+            }
+            if let Some(id) = user_id {
+                match loader(id).await {
+                    Some(user) => req.params.set(CurrentUser(user)),
+                    // The user no longer exists
+                    None => set_session_user_id(&mut req, None),
+                }
+            }
+
+            let mut req = next(req).await;
+
+            match req.params.take::<AuthChange>() {
+                Some(AuthChange::Login { user_id, remember }) => {
+                    if let Some(value) = set_cookie.as_ref().or(remember_cookie.as_ref()) {
+                        revoke_remember_token(&tokens, value).await;
+                    }
+                    set_cookie = if remember {
+                        let (series, value) = issue_remember_token(&tokens, &user_id, ttl).await;
+                        set_session_value(&mut req, REMEMBER_SERIES_KEY, Some(series));
+                        Some(value)
+                    } else {
+                        set_session_value(&mut req, REMEMBER_SERIES_KEY, None);
+                        remember_cookie.as_ref().map(|_| String::new())
+                    };
+                }
+                Some(AuthChange::Logout) => {
+                    if let Some(value) = set_cookie.as_ref().or(remember_cookie.as_ref()) {
+                        revoke_remember_token(&tokens, value).await;
+                    }
+                    set_session_value(&mut req, REMEMBER_SERIES_KEY, None);
+                    if remember_cookie.is_some() {
+                        set_cookie = Some(String::new());
+                    }
+                }
+                None => {}
+            }
+
+            if let Some(value) = set_cookie {
+                let max_age = if value.is_empty() { 0 } else { ttl };
+                req.response = req.response.add_cookie(
+                    REMEMBER_COOKIE,
+                    Cookie::new(value).path("/").secure(true).http_only(true).max_age(max_age),
+                );
+            }
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn remember_tokens_rotate() {
+        let tokens = local_remember_tokens();
+        let (_, first) = issue_remember_token(&tokens, "42", 60).await;
+        let (user_id, second) = consume_remember_token(&tokens, &first, 60).await.unwrap();
+        assert_eq!(user_id, "42");
+        assert_ne!(first, second);
+        // The old token no longer works, and presenting it revokes the series
+        assert!(consume_remember_token(&tokens, &first, 60).await.is_none());
+        assert!(consume_remember_token(&tokens, &second, 60).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn revoked_tokens_are_rejected() {
+        let tokens = local_remember_tokens();
+        let (series, value) = issue_remember_token(&tokens, "7", 60).await;
+        revoke_remember_token(&tokens, &value).await;
+        assert!(consume_remember_token(&tokens, &value, 60).await.is_none());
+        assert!(consume_remember_token(&tokens, "garbage", 60).await.is_none());
+
+        let (series_2, value) = issue_remember_token(&tokens, "7", 60).await;
+        assert_ne!(series, series_2);
+        revoke_series(&tokens, &series_2).await;
+        assert!(consume_remember_token(&tokens, &value, 60).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn remember_tokens_are_shared_through_the_cache() {
+        let cache: SharedCache = Arc::new(MemoryCache::new(16));
+        let (_, value) = issue_remember_token(&cache, "user 1", 60).await;
+        // Another instance, or this one after a restart, reads the same cache
+        let (user_id, _) = consume_remember_token(&cache, &value, 60).await.unwrap();
+        assert_eq!(user_id, "user 1");
+        assert!(consume_remember_token(&local_remember_tokens(), &value, 60).await.is_none());
+    }
+}
@@ -0,0 +1,110 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use starberry_core::app::middleware::{AsyncMiddleware, BoxFuture};
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{Authorization, HttpContentType, StatusCode};
+use starberry_core::http::response::{response_templates, HttpResponse};
+
+type Verifier<P> = Arc<dyn Fn(String, String) -> BoxFuture<Option<P>> + Send + Sync>;
+
+/// Protects the routes behind it with HTTP Basic authentication.
+///
+/// The verifier receives the user id and password of the request and returns the
+/// authenticated principal, which is stored in the request params. Requests without
+/// valid credentials are answered with `401 Unauthorized` and a `WWW-Authenticate` challenge.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// #[derive(Clone)]
+/// struct User(String);
+///
+/// let app = App::new()
+///     .single_protocol(ProtocolBuilder::<HttpReqCtx>::new()
+///         .add_middleware(BasicAuth::new("admin", |user: String, pass: String| async move {
+///             (user == "admin" && pass == "secret").then(|| User(user))
+///         })))
+///     .build();
+///
+/// // In a handler
+/// let user = req.params.get::<User>().unwrap();
+/// ```
+pub struct BasicAuth<P: Send + Sync + 'static = String> {
+    realm: String,
+    verifier: Verifier<P>,
+}
+
+impl<P: Send + Sync + 'static> BasicAuth<P> {
+    pub fn new<F, Fut>(realm: impl Into<String>, verifier: F) -> Self
+    where
+        F: Fn(String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<P>> + Send + 'static,
+    {
+        Self {
+            realm: realm.into(),
+            verifier: Arc::new(move |user, pass| Box::pin(verifier(user, pass))),
+        }
+    }
+
+    /// The value of the `WWW-Authenticate` header sent with the 401 response
+    pub fn challenge(&self) -> String {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm)
+    }
+
+    fn unauthorized(&self) -> HttpResponse {
+        response_templates::normal_response(StatusCode::UNAUTHORIZED, "Unauthorized")
+            .content_type(HttpContentType::TextPlain())
+            .add_header("www-authenticate", self.challenge())
+    }
+}
+
+impl<P: Send + Sync + 'static> AsyncMiddleware<HttpReqCtx> for BasicAuth<P> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Without a verifier every request is rejected, use `BasicAuth::new` instead
+    fn return_self() -> Self {
+        Self::new("Restricted", |_, _| async { None })
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let verifier = self.verifier.clone();
+        let unauthorized = self.unauthorized();
+        Box::pin(async move {
+            let principal = match req.get_authorization() {
+                Some(Authorization::Basic { user, pass }) => verifier(user, pass).await,
+                _ => None,
+            };
+            match principal {
+                Some(principal) => {
+                    req.params.set(principal);
+                    next(req).await
+                }
+                None => {
+                    req.response = unauthorized;
+                    req
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_quotes_the_realm() {
+        let auth = BasicAuth::<String>::new("my \"admin\" area", |user, _| async move { Some(user) });
+        assert_eq!(auth.challenge(), "Basic realm=\"my \\\"admin\\\" area\", charset=\"UTF-8\"");
+    }
+}
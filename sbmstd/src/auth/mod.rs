@@ -0,0 +1,8 @@
+#[allow(clippy::module_inception)]
+pub mod auth; 
+pub mod basic_auth; 
+pub mod token_guard; 
+
+pub use self::auth::{Auth, AuthExt, AuthUser}; 
+pub use self::basic_auth::BasicAuth; 
+pub use self::token_guard::{StaticTokens, TokenGuard, TokenStore}; 
@@ -0,0 +1,204 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use starberry_core::app::middleware::{AsyncMiddleware, BoxFuture};
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{Authorization, HttpContentType, StatusCode};
+use starberry_core::http::response::response_templates;
+
+/// Looks up the principal owning an API key or bearer token.
+///
+/// Implemented for `StaticTokens` and for any async closure `Fn(String) -> Option<P>`,
+/// which is the way to check the tokens against a database.
+pub trait TokenStore<P>: Send + Sync + 'static {
+    fn lookup(&self, token: String) -> BoxFuture<Option<P>>;
+}
+
+impl<P, F, Fut> TokenStore<P> for F
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<P>> + Send + 'static,
+{
+    fn lookup(&self, token: String) -> BoxFuture<Option<P>> {
+        Box::pin(self(token))
+    }
+}
+
+/// A fixed set of tokens, each mapped to its principal
+#[derive(Debug, Clone, Default)]
+pub struct StaticTokens<P = String> {
+    tokens: HashMap<String, P>,
+}
+
+impl<P: Clone + Send + Sync + 'static> StaticTokens<P> {
+    pub fn new() -> Self {
+        Self { tokens: HashMap::new() }
+    }
+
+    /// Accepts the token, authenticating the request as `principal`
+    pub fn token(mut self, token: impl Into<String>, principal: P) -> Self {
+        self.tokens.insert(token.into(), principal);
+        self
+    }
+}
+
+impl StaticTokens<String> {
+    /// Accepts each token of the list, the token itself being the principal
+    pub fn from_list<I: IntoIterator<Item = T>, T: Into<String>>(tokens: I) -> Self {
+        Self {
+            tokens: tokens
+                .into_iter()
+                .map(|token| {
+                    let token = token.into();
+                    (token.clone(), token)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<P: Clone + Send + Sync + 'static> TokenStore<P> for StaticTokens<P> {
+    fn lookup(&self, token: String) -> BoxFuture<Option<P>> {
+        let principal = self.tokens.get(&token).cloned();
+        Box::pin(async move { principal })
+    }
+}
+
+/// Guards routes with an API key or a bearer token, checked against a `TokenStore`.
+///
+/// The token is read from the `Authorization: Bearer` header, or from the API key
+/// header if one is configured. The principal returned by the store is saved in the
+/// request params. Only the paths under the protected prefixes are guarded, every
+/// path is when no prefix is given.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let guard = TokenGuard::new(StaticTokens::from_list(["key-1", "key-2"]))
+///     .api_key_header("x-api-key")
+///     .protect("/api");
+///
+/// // Tokens stored in a database
+/// let pool = db_pool.clone();
+/// let guard = TokenGuard::new(move |token: String| {
+///     let pool = pool.clone();
+///     async move {
+///         let row = QueryBuilder::new("SELECT client FROM api_keys WHERE key = $1")
+///             .bind(token)
+///             .fetch_one_pool(&pool)
+///             .await
+///             .ok()?;
+///         row.get("client").cloned()
+///     }
+/// });
+/// ```
+pub struct TokenGuard<P: Send + Sync + 'static = String> {
+    store: Arc<dyn TokenStore<P>>,
+    api_key_header: Option<String>,
+    prefixes: Vec<String>,
+}
+
+impl<P: Send + Sync + 'static> TokenGuard<P> {
+    pub fn new<S: TokenStore<P>>(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+            api_key_header: None,
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Also accepts the token in the given header, e.g. `x-api-key`
+    pub fn api_key_header(mut self, header: impl Into<String>) -> Self {
+        self.api_key_header = Some(header.into().to_lowercase());
+        self
+    }
+
+    /// Guards the paths under the prefix. Can be called several times
+    pub fn protect(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Whether the guard applies to the path
+    pub fn guards(&self, path: &str) -> bool {
+        self.prefixes.is_empty()
+            || self.prefixes.iter().any(|prefix| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+}
+
+impl<P: Send + Sync + 'static> AsyncMiddleware<HttpReqCtx> for TokenGuard<P> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Without a store every request is rejected, use `TokenGuard::new` instead
+    fn return_self() -> Self {
+        Self::new(|_: String| async { None })
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let guarded = self.guards(&req.path());
+        let store = self.store.clone();
+        let api_key_header = self.api_key_header.clone();
+        Box::pin(async move {
+            if !guarded {
+                return next(req).await;
+            }
+            let token = match req.get_authorization() {
+                Some(Authorization::Bearer(token)) => Some(token),
+                _ => api_key_header
+                    .and_then(|header| req.meta().get_header(&header))
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty()),
+            };
+            let principal = match token {
+                Some(token) => store.lookup(token).await,
+                None => None,
+            };
+            match principal {
+                Some(principal) => {
+                    req.params.set(principal);
+                    next(req).await
+                }
+                None => {
+                    req.response = response_templates::normal_response(StatusCode::UNAUTHORIZED, "Unauthorized")
+                        .content_type(HttpContentType::TextPlain())
+                        .add_header("www-authenticate", "Bearer");
+                    req
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_only_the_protected_subtrees() {
+        let guard = TokenGuard::new(StaticTokens::from_list(["key"])).protect("/api/");
+        assert!(guard.guards("/api"));
+        assert!(guard.guards("/api/users"));
+        assert!(!guard.guards("/apis"));
+        assert!(!guard.guards("/"));
+        assert!(TokenGuard::new(StaticTokens::from_list(["key"])).guards("/anything"));
+    }
+
+    #[tokio::test]
+    async fn static_tokens_lookup() {
+        let store = StaticTokens::new().token("key", 7u32);
+        assert_eq!(store.lookup("key".to_string()).await, Some(7));
+        assert_eq!(store.lookup("other".to_string()).await, None);
+    }
+}
@@ -0,0 +1,112 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{HttpContentType, StatusCode};
+use starberry_core::http::response::{response_templates, HttpResponse};
+
+const DEFAULT_LIMIT: usize = 1024 * 1024; // 1 MB
+
+/// Limits the size of the request bodies, with a default limit and larger or smaller
+/// limits for the paths under given prefixes.
+///
+/// A request announcing a larger `Content-Length` is answered with `413 Payload Too Large`
+/// before its body is read. Otherwise the limit is enforced while the handler reads the
+/// body, which stops as soon as the limit is crossed, so chunked bodies cannot get around it.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(BodyLimit::new(64 * 1024).route("/upload", 100 * 1024 * 1024));
+/// ```
+#[derive(Debug, Clone)]
+pub struct BodyLimit {
+    default: usize,
+    routes: Vec<(String, usize)>,
+}
+
+impl BodyLimit {
+    pub fn new(default: usize) -> Self {
+        Self {
+            default,
+            routes: Vec::new(),
+        }
+    }
+
+    /// Sets the limit of the paths under the prefix. The longest matching prefix wins
+    pub fn route(mut self, prefix: impl Into<String>, limit: usize) -> Self {
+        self.routes.push((prefix.into().trim_end_matches('/').to_string(), limit));
+        self
+    }
+
+    /// The limit applying to the path
+    pub fn limit_for(&self, path: &str) -> usize {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, limit)| *limit)
+    }
+}
+
+impl Default for BodyLimit {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIMIT)
+    }
+}
+
+fn too_large() -> HttpResponse {
+    response_templates::normal_response(StatusCode::PAYLOAD_TOO_LARGE, "Payload Too Large")
+        .content_type(HttpContentType::TextPlain())
+}
+
+impl AsyncMiddleware<HttpReqCtx> for BodyLimit {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::default()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let limit = self.limit_for(&req.path());
+        Box::pin(async move {
+            if req.meta().get_content_length().is_some_and(|length| length > limit) {
+                req.response = too_large();
+                return req;
+            }
+            req.limit_body(limit);
+            let mut req = next(req).await;
+            if req.body_error() == Some(StatusCode::PAYLOAD_TOO_LARGE) {
+                req.response = too_large();
+            }
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_wins() {
+        let limit = BodyLimit::new(10).route("/upload", 100).route("/upload/avatar/", 50);
+        assert_eq!(limit.limit_for("/"), 10);
+        assert_eq!(limit.limit_for("/upload"), 100);
+        assert_eq!(limit.limit_for("/upload/video"), 100);
+        assert_eq!(limit.limit_for("/upload/avatar"), 50);
+        assert_eq!(limit.limit_for("/uploads"), 10);
+    }
+}
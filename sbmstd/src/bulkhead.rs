@@ -0,0 +1,184 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{HttpContentType, StatusCode};
+use starberry_core::http::response::{response_templates, HttpResponse};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+struct Compartment {
+    prefix: String,
+    max: usize,
+    permits: Arc<Semaphore>,
+}
+
+/// Limits the number of requests handled at once under given path prefixes, so expensive
+/// endpoints such as report generation or exports cannot starve the rest of the application.
+///
+/// Each prefix is a compartment with its own limit, shared by every path under it. A request
+/// arriving while its compartment is full waits for a slot up to the queue timeout, then it
+/// is answered with `503 Service Unavailable` and a `Retry-After` header. Paths under no
+/// prefix are not limited.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(
+///         Bulkhead::new()
+///             .route("/reports", 4)
+///             .route("/export", 2)
+///             .queue_timeout(Duration::from_millis(500)),
+///     );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bulkhead {
+    compartments: Vec<Compartment>,
+    queue_timeout: Duration,
+    retry_after: u64,
+}
+
+impl Bulkhead {
+    pub fn new() -> Self {
+        Self {
+            compartments: Vec::new(),
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+            retry_after: 1,
+        }
+    }
+
+    /// Allows at most `max` requests at once under the prefix. The longest matching prefix wins
+    pub fn route(mut self, prefix: impl Into<String>, max: usize) -> Self {
+        let max = max.max(1);
+        self.compartments.push(Compartment {
+            prefix: prefix.into().trim_end_matches('/').to_string(),
+            max,
+            permits: Arc::new(Semaphore::new(max)),
+        });
+        self
+    }
+
+    /// Sets how long a request waits for a slot before it is shed, 100 ms by default.
+    /// A zero duration sheds the requests at once
+    pub fn queue_timeout(mut self, duration: Duration) -> Self {
+        self.queue_timeout = duration;
+        self
+    }
+
+    /// Sets the `Retry-After` of the shed requests, in seconds
+    pub fn retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = seconds;
+        self
+    }
+
+    fn compartment_for(&self, path: &str) -> Option<&Compartment> {
+        self.compartments
+            .iter()
+            .filter(|compartment| {
+                path.strip_prefix(compartment.prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|compartment| compartment.prefix.len())
+    }
+
+    /// The limit applying to the path, `None` if it is not limited
+    pub fn limit_for(&self, path: &str) -> Option<usize> {
+        self.compartment_for(path).map(|compartment| compartment.max)
+    }
+
+    /// The number of requests being handled under the compartment of the path
+    pub fn in_flight(&self, path: &str) -> usize {
+        self.compartment_for(path)
+            .map_or(0, |compartment| compartment.max - compartment.permits.available_permits())
+    }
+
+    /// Waits for a slot of the compartment of the path. `Ok(None)` if the path is not limited,
+    /// `Err` if no slot was freed within the queue timeout
+    async fn enter(&self, path: &str) -> Result<Option<OwnedSemaphorePermit>, ()> {
+        let Some(compartment) = self.compartment_for(path) else {
+            return Ok(None);
+        };
+        let permits = compartment.permits.clone();
+        if let Ok(permit) = permits.clone().try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+        match tokio::time::timeout(self.queue_timeout, permits.acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            _ => Err(()),
+        }
+    }
+
+    fn shed(&self) -> HttpResponse {
+        response_templates::normal_response(StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable")
+            .content_type(HttpContentType::TextPlain())
+            .add_header("retry-after", self.retry_after.to_string())
+    }
+}
+
+impl Default for Bulkhead {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Bulkhead {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::default()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let bulkhead = self.clone();
+        Box::pin(async move {
+            let Ok(permit) = bulkhead.enter(&req.path()).await else {
+                req.response = bulkhead.shed();
+                return req;
+            };
+            let req = next(req).await;
+            drop(permit);
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_wins() {
+        let bulkhead = Bulkhead::new().route("/reports", 4).route("/reports/yearly/", 1);
+        assert_eq!(bulkhead.limit_for("/"), None);
+        assert_eq!(bulkhead.limit_for("/reports/daily"), Some(4));
+        assert_eq!(bulkhead.limit_for("/reports/yearly"), Some(1));
+        assert_eq!(bulkhead.limit_for("/reportsx"), None);
+    }
+
+    #[tokio::test]
+    async fn sheds_when_full() {
+        let bulkhead = Bulkhead::new().route("/export", 1).queue_timeout(Duration::from_millis(10));
+        let first = bulkhead.enter("/export/csv").await.unwrap();
+        assert!(first.is_some());
+        assert_eq!(bulkhead.in_flight("/export"), 1);
+        assert!(bulkhead.enter("/export/pdf").await.is_err());
+        assert!(bulkhead.enter("/other").await.unwrap().is_none());
+
+        drop(first);
+        assert_eq!(bulkhead.in_flight("/export"), 0);
+        assert!(bulkhead.enter("/export/pdf").await.unwrap().is_some());
+    }
+}
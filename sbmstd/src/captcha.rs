@@ -0,0 +1,408 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use akari::Value;
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::body::HttpBody;
+use starberry_core::http::context::{HttpReqCtx, HttpResCtx};
+use starberry_core::http::error::IntoResponse;
+use starberry_core::http::http_value::{HttpContentType, HttpMethod, HttpVersion, StatusCode};
+use starberry_core::http::meta::HttpMeta;
+use starberry_core::http::request::HttpRequest;
+use starberry_core::http::response::{response_templates, HttpResponse};
+use starberry_core::http::safety::HttpSafety;
+use starberry_core::http::start_line::HttpStartLine;
+use starberry_lib::url_encoding::encode_url_owned;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A service checking that a form was submitted by a human
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptchaProvider {
+    HCaptcha,
+    /// reCAPTCHA v2, or v3 with `Captcha::min_score`
+    ReCaptcha,
+}
+
+impl CaptchaProvider {
+    /// The host and path of the verification API
+    fn endpoint(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::HCaptcha => ("https://api.hcaptcha.com", "/siteverify"),
+            Self::ReCaptcha => ("https://www.google.com", "/recaptcha/api/siteverify"),
+        }
+    }
+
+    /// The form field in which the widget posts its token
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::HCaptcha => "h-captcha-response",
+            Self::ReCaptcha => "g-recaptcha-response",
+        }
+    }
+
+    fn script(&self) -> &'static str {
+        match self {
+            Self::HCaptcha => "https://js.hcaptcha.com/1/api.js",
+            Self::ReCaptcha => "https://www.google.com/recaptcha/api.js",
+        }
+    }
+
+    fn widget_class(&self) -> &'static str {
+        match self {
+            Self::HCaptcha => "h-captcha",
+            Self::ReCaptcha => "g-recaptcha",
+        }
+    }
+}
+
+/// Why a captcha was not accepted
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptchaError {
+    /// The form has no token, sent as 400 Bad Request
+    Missing,
+    /// The provider rejected the token, with its error codes, sent as 403 Forbidden
+    Rejected(Vec<String>),
+    /// The provider could not be asked, sent as 503 Service Unavailable
+    Unavailable(String),
+}
+
+impl fmt::Display for CaptchaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "Missing captcha"),
+            Self::Rejected(codes) if codes.is_empty() => write!(f, "Captcha rejected"),
+            Self::Rejected(codes) => write!(f, "Captcha rejected: {}", codes.join(", ")),
+            Self::Unavailable(reason) => write!(f, "Captcha unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CaptchaError {}
+
+impl IntoResponse for CaptchaError {
+    fn into_response(self) -> HttpResponse {
+        let (status, message) = match self {
+            Self::Missing => (StatusCode::BAD_REQUEST, "Missing captcha"),
+            Self::Rejected(_) => (StatusCode::FORBIDDEN, "Captcha verification failed"),
+            Self::Unavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, "Captcha verification unavailable"),
+        };
+        response_templates::normal_response(status, message).content_type(HttpContentType::TextPlain())
+    }
+}
+
+/// Verifies the hCaptcha or reCAPTCHA tokens posted with forms against the API of the provider.
+///
+/// As a middleware, it rejects the `POST`, `PUT`, `PATCH` and `DELETE` requests without a
+/// valid token, on every path or only under the prefixes given with `route`. Handlers can
+/// also check a request themselves with `verify_request`, whose error is a response.
+///
+/// `widget` writes the markup of the widget, and `install` makes it available to every
+/// template as `-[ captcha ]-`.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let captcha = Captcha::hcaptcha("10000000-ffff-ffff-ffff-000000000001", std::env::var("HCAPTCHA_SECRET")?)
+///     .route("/signup")
+///     .route("/contact");
+/// captcha.install();
+/// ProtocolBuilder::<HttpReqCtx>::new().add_middleware(captcha);
+///
+/// // In templates/signup.html, inside the form
+/// -[ captcha ]-
+/// ```
+#[derive(Clone)]
+pub struct Captcha {
+    provider: CaptchaProvider,
+    site_key: String,
+    secret: String,
+    /// Without trailing slash. Every path is guarded when empty
+    routes: Vec<String>,
+    /// The host name the token must have been solved on
+    hostname: Option<String>,
+    /// The lowest accepted score of reCAPTCHA v3, from 0.0 (a bot) to 1.0 (a human)
+    min_score: Option<f64>,
+    timeout: Duration,
+    safety: HttpSafety,
+}
+
+impl Captcha {
+    pub fn new(provider: CaptchaProvider, site_key: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            provider,
+            site_key: site_key.into(),
+            secret: secret.into(),
+            routes: Vec::new(),
+            hostname: None,
+            min_score: None,
+            timeout: DEFAULT_TIMEOUT,
+            safety: HttpSafety::new(),
+        }
+    }
+
+    pub fn hcaptcha(site_key: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self::new(CaptchaProvider::HCaptcha, site_key, secret)
+    }
+
+    pub fn recaptcha(site_key: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self::new(CaptchaProvider::ReCaptcha, site_key, secret)
+    }
+
+    /// Only guards the paths under the prefix
+    pub fn route(mut self, prefix: impl Into<String>) -> Self {
+        self.routes.push(prefix.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Rejects the tokens solved on another site, e.g. `example.com`
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// Rejects the tokens scored below the threshold, for reCAPTCHA v3
+    pub fn min_score(mut self, score: f64) -> Self {
+        self.min_score = Some(score);
+        self
+    }
+
+    /// Sets how long the verification may take, 10 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn provider(&self) -> CaptchaProvider {
+        self.provider
+    }
+
+    /// Whether the requests to the path are guarded
+    pub fn covers(&self, path: &str) -> bool {
+        self.routes.is_empty()
+            || self.routes.iter().any(|prefix| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+
+    /// The markup of the widget: the script of the provider and the element it fills
+    pub fn widget(&self) -> String {
+        format!(
+            "<script src=\"{}\" async defer></script><div class=\"{}\" data-sitekey=\"{}\"></div>",
+            self.provider.script(),
+            self.provider.widget_class(),
+            escape_attribute(&self.site_key)
+        )
+    }
+
+    /// Makes the widget available to every template as `captcha`
+    pub fn install(&self) {
+        response_templates::set_template_global("captcha", Value::Str(self.widget()));
+    }
+
+    /// Verifies the token posted in the form of the request, url-encoded or multipart
+    pub async fn verify_request(&self, req: &mut HttpReqCtx) -> Result<(), CaptchaError> {
+        let field = self.provider.field();
+        let mut token = req.form().await.and_then(|form| form.get(field)).cloned();
+        if token.is_none() {
+            token = req.files().await.and_then(|form| form.get_text(field)).cloned();
+        }
+        let token = token.filter(|token| !token.is_empty()).ok_or(CaptchaError::Missing)?;
+        let remote_ip = req.client_ip().map(|ip| ip.to_string());
+        self.verify(&token, remote_ip.as_deref()).await
+    }
+
+    /// Asks the provider whether the token is valid, `remote_ip` being the address of the client
+    pub async fn verify(&self, token: &str, remote_ip: Option<&str>) -> Result<(), CaptchaError> {
+        let (host, path) = self.provider.endpoint();
+        let request = self.request(path, token, remote_ip);
+        let sent = HttpResCtx::send_request(host, request, self.safety.clone());
+        let response = match tokio::time::timeout(self.timeout, sent).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => return Err(CaptchaError::Unavailable(e.to_string())),
+            Err(_) => return Err(CaptchaError::Unavailable("Timed out".to_string())),
+        };
+        let status = response.meta.start_line.status_code().as_u16();
+        if status >= 300 {
+            return Err(CaptchaError::Unavailable(format!("Status {}", status)));
+        }
+        let body = json_body(&response.body).ok_or_else(|| CaptchaError::Unavailable("Answered without JSON".to_string()))?;
+        self.check(&body)
+    }
+
+    fn request(&self, path: &str, token: &str, remote_ip: Option<&str>) -> HttpRequest {
+        let mut params = vec![("secret", self.secret.as_str()), ("response", token)];
+        if let Some(ip) = remote_ip {
+            params.push(("remoteip", ip));
+        }
+        if self.provider == CaptchaProvider::HCaptcha {
+            params.push(("sitekey", self.site_key.as_str()));
+        }
+        let body: Vec<String> = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, encode_url_owned(value)))
+            .collect();
+        let mut meta = HttpMeta::new(
+            HttpStartLine::new_request(HttpVersion::Http11, HttpMethod::POST, path.to_string()),
+            HashMap::new(),
+        );
+        meta.set_content_type(HttpContentType::ApplicationUrlEncodedForm());
+        HttpRequest::new(meta, HttpBody::Binary(body.join("&").into_bytes()))
+    }
+
+    /// Checks the answer of the provider, `{"success": true, "hostname": "..", "score": 0.9}`
+    fn check(&self, body: &Value) -> Result<(), CaptchaError> {
+        let success = member(body, "success").is_some_and(|success| success.into_json() == "true");
+        if !success {
+            let codes = match member(body, "error-codes") {
+                Some(Value::List(codes)) => codes.iter().map(text).collect(),
+                _ => Vec::new(),
+            };
+            return Err(CaptchaError::Rejected(codes));
+        }
+        if let Some(expected) = &self.hostname {
+            let hostname = member(body, "hostname").map(text);
+            if hostname.as_deref() != Some(expected.as_str()) {
+                return Err(CaptchaError::Rejected(vec!["hostname-mismatch".to_string()]));
+            }
+        }
+        if let Some(min_score) = self.min_score {
+            let score = member(body, "score").and_then(|score| score.into_json().parse::<f64>().ok());
+            if score.is_none_or(|score| score < min_score) {
+                return Err(CaptchaError::Rejected(vec!["score-too-low".to_string()]));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Captcha {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Captcha")
+            .field("provider", &self.provider)
+            .field("site_key", &self.site_key)
+            .field("routes", &self.routes)
+            .field("hostname", &self.hostname)
+            .field("min_score", &self.min_score)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Captcha {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Without keys every guarded form is rejected, use `Captcha::new` instead
+    fn return_self() -> Self {
+        Self::hcaptcha("", "")
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let captcha = self.clone();
+        Box::pin(async move {
+            let submits = matches!(req.method(), HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH | HttpMethod::DELETE);
+            if submits
+                && captcha.covers(&req.path())
+                && let Err(e) = captcha.verify_request(&mut req).await
+            {
+                if let CaptchaError::Unavailable(reason) = &e {
+                    eprintln!("Captcha verification failed: {}", reason);
+                }
+                req.response = e.into_response();
+                return req;
+            }
+            next(req).await
+        })
+    }
+}
+
+fn json_body(body: &HttpBody) -> Option<Value> {
+    match body {
+        HttpBody::Json(value) => Some(value.clone()),
+        HttpBody::Text(text) => Value::from_json(text).ok(),
+        HttpBody::Binary(bytes) => Value::from_json(std::str::from_utf8(bytes).ok()?).ok(),
+        _ => None,
+    }
+}
+
+fn member<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Dict(object) => object.get(key),
+        _ => None,
+    }
+}
+
+/// A JSON string without its quotes, or any other value as JSON
+fn text(value: &Value) -> String {
+    match value {
+        Value::Str(text) => text.clone(),
+        other => other.into_json(),
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(json: &str) -> Value {
+        Value::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn checks_the_answer() {
+        let captcha = Captcha::recaptcha("site", "secret").hostname("example.com").min_score(0.5);
+        assert_eq!(captcha.check(&answer(r#"{"success": true, "hostname": "example.com", "score": 0.9}"#)), Ok(()));
+        assert_eq!(
+            captcha.check(&answer(r#"{"success": false, "error-codes": ["invalid-input-response"]}"#)),
+            Err(CaptchaError::Rejected(vec!["invalid-input-response".to_string()]))
+        );
+        assert_eq!(
+            captcha.check(&answer(r#"{"success": true, "hostname": "evil.com", "score": 0.9}"#)),
+            Err(CaptchaError::Rejected(vec!["hostname-mismatch".to_string()]))
+        );
+        assert_eq!(
+            captcha.check(&answer(r#"{"success": true, "hostname": "example.com", "score": 0.1}"#)),
+            Err(CaptchaError::Rejected(vec!["score-too-low".to_string()]))
+        );
+        assert!(Captcha::hcaptcha("site", "secret").check(&answer(r#"{"hostname": "example.com"}"#)).is_err());
+    }
+
+    #[test]
+    fn posts_the_token() {
+        let captcha = Captcha::hcaptcha("site", "s3cr&t");
+        let request = captcha.request("/siteverify", "to ken", Some("203.0.113.7"));
+        let HttpBody::Binary(body) = &request.body else { panic!("not a binary body") };
+        assert_eq!(
+            String::from_utf8_lossy(body),
+            "secret=s3cr%26t&response=to%20ken&remoteip=203.0.113.7&sitekey=site"
+        );
+    }
+
+    #[test]
+    fn writes_the_widget() {
+        assert_eq!(
+            Captcha::recaptcha("k\"ey", "secret").widget(),
+            "<script src=\"https://www.google.com/recaptcha/api.js\" async defer></script>\
+             <div class=\"g-recaptcha\" data-sitekey=\"k&quot;ey\"></div>"
+        );
+        let captcha = Captcha::hcaptcha("site", "secret").route("/signup/");
+        assert!(captcha.covers("/signup"));
+        assert!(!captcha.covers("/signups"));
+        assert_eq!(CaptchaError::Missing.into_response().meta.start_line.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
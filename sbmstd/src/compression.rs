@@ -0,0 +1,72 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::compression::CompressionConfig;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::HttpMethod;
+
+/// Compresses the responses with the coding the client prefers among the ones it accepts.
+///
+/// The settings, levels, smallest body, content types and routes using other levels or no
+/// compression, are the `CompressionConfig` given to `AppBuilder::compression`, or the
+/// defaults without one. `with_config` gives this middleware its own settings instead.
+/// Bodies which are streamed, already encoded or marked `no-transform` are sent as they are.
+///
+/// Add it before `ETag`, so the tags are computed on the uncompressed bodies.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// App::new().compression(CompressionConfig::new().min_size(512).skip_route("/events")).build();
+/// ProtocolBuilder::<HttpReqCtx>::new().add_middleware(Compression::new());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Compression {
+    config: Option<CompressionConfig>,
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses these settings rather than the ones of the application
+    pub fn with_config(config: CompressionConfig) -> Self {
+        Self { config: Some(config) }
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Compression {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::default()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let config = self.config.clone();
+        Box::pin(async move {
+            if req.method() == HttpMethod::HEAD {
+                return next(req).await;
+            }
+            let config = config
+                .or_else(|| req.app.config().get::<CompressionConfig>().cloned())
+                .unwrap_or_default();
+            let Some(levels) = config.levels_for(&req.path()) else {
+                return next(req).await;
+            };
+            let coding = req.get_preferred_encoding(config.get_codings());
+            let mut req = next(req).await;
+            config.apply(&mut req.response, coding.as_ref(), &levels);
+            req
+        })
+    }
+}
@@ -0,0 +1,129 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+
+/// Values of the consent cookie meaning the client refused
+const REFUSALS: [&str; 5] = ["0", "false", "no", "denied", "rejected"];
+
+/// Whether the client of the current request consented to the non-essential cookies,
+/// stored in `req.params` by `CookieConsent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Consent(pub bool);
+
+/// Drops the cookies marked with `Cookie::non_essential` from the responses until the client
+/// consented, so analytics or advertising cookies can be set freely by the handlers.
+///
+/// The client consented when the consent cookie, `cookie_consent` by default, exists with a
+/// value other than an empty one, `0`, `false`, `no`, `denied` or `rejected`. The cookie can be
+/// set by the banner in the browser, or by a handler, in which case the non-essential cookies
+/// of the same response are kept. Handlers can check the consent with `req.has_consent()`.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new().add_middleware(CookieConsent::new());
+///
+/// // In a handler
+/// text_response("Hello").add_cookie("visitor", Cookie::new(id).path("/").non_essential())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CookieConsent {
+    cookie: Arc<String>,
+}
+
+impl CookieConsent {
+    pub fn new() -> Self {
+        Self {
+            cookie: Arc::new("cookie_consent".to_string()),
+        }
+    }
+
+    /// Sets the name of the consent cookie, `cookie_consent` by default
+    pub fn cookie(mut self, name: impl Into<String>) -> Self {
+        self.cookie = Arc::new(name.into());
+        self
+    }
+
+    /// Whether a value of the consent cookie means the client consented
+    pub fn consents(value: &str) -> bool {
+        let value = value.trim();
+        !value.is_empty() && !REFUSALS.iter().any(|refusal| value.eq_ignore_ascii_case(refusal))
+    }
+}
+
+impl Default for CookieConsent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for CookieConsent {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let name = self.cookie.clone();
+        Box::pin(async move {
+            let consented = req
+                .get_cookie(name.as_str())
+                .is_some_and(|cookie| Self::consents(cookie.get_value()));
+            req.params.set(Consent(consented));
+
+            let mut req = next(req).await;
+
+            if consented {
+                return req;
+            }
+            let mut cookies = req.response.meta.get_cookies().clone();
+            // Consent given by this very response, e.g. the banner posting the choice
+            if cookies.get(name.as_str()).is_some_and(|cookie| Self::consents(cookie.get_value())) {
+                return req;
+            }
+            if cookies.0.values().any(|cookie| !cookie.is_essential()) {
+                cookies.0.retain(|_, cookie| cookie.is_essential());
+                req.response.meta.set_cookies(cookies);
+            }
+            req
+        })
+    }
+}
+
+/// Access to the consent found by `CookieConsent`
+pub trait ConsentExt {
+    /// Whether the client consented to the non-essential cookies, `false` if `CookieConsent`
+    /// is not registered
+    fn has_consent(&self) -> bool;
+}
+
+impl ConsentExt for HttpReqCtx {
+    fn has_consent(&self) -> bool {
+        self.params.get::<Consent>().is_some_and(|consent| consent.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_consent() {
+        assert!(CookieConsent::consents("1"));
+        assert!(CookieConsent::consents("analytics,ads"));
+        assert!(!CookieConsent::consents(""));
+        assert!(!CookieConsent::consents("Rejected"));
+        assert!(!CookieConsent::consents(" 0 "));
+    }
+}
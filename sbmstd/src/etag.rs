@@ -0,0 +1,135 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::body::HttpBody;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{EntityTag, HttpMethod, StatusCode};
+use starberry_core::http::response::HttpResponse;
+use starberry_lib::ende::digest::sha256_hex;
+
+const DEFAULT_MAX_SIZE: usize = 4 * 1024 * 1024; // 4 MB
+
+/// The number of hex digits of the digest kept in the tag
+const TAG_LENGTH: usize = 32;
+
+/// Sets an `ETag` derived from the body of the successful responses to `GET` and `HEAD`
+/// requests, and answers `304 Not Modified` without body when the `If-None-Match` of the
+/// request matches it, so clients polling an API only download what changed.
+///
+/// The handler still runs, only the bandwidth is saved. Responses which already have an
+/// `ETag`, are marked `no-store` or are larger than the maximum size are left alone.
+/// The tags are weak, as a later middleware such as compression may change the bytes.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new().add_middleware(ETag::new());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ETag {
+    max_size: usize,
+}
+
+impl ETag {
+    pub fn new() -> Self {
+        Self { max_size: DEFAULT_MAX_SIZE }
+    }
+
+    /// Sets the size of the largest body hashed, 4 MB by default
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// The tag of a response, `None` if it should not get one
+    pub fn tag_for(&self, response: &mut HttpResponse) -> Option<EntityTag> {
+        if response.meta.start_line.status_code() != StatusCode::OK || response.meta.get_header("etag").is_some() {
+            return None;
+        }
+        if response.meta.get_cache_control().is_some_and(|cache_control| cache_control.no_store) {
+            return None;
+        }
+        let digest = match &response.body {
+            HttpBody::Binary(bytes) if bytes.len() <= self.max_size => sha256_hex(bytes),
+            HttpBody::Text(text) if text.len() <= self.max_size => sha256_hex(text.as_bytes()),
+            HttpBody::Json(json) => {
+                let json = json.into_json();
+                if json.len() > self.max_size {
+                    return None;
+                }
+                sha256_hex(json.as_bytes())
+            }
+            _ => return None,
+        };
+        Some(EntityTag::weak(&digest[..TAG_LENGTH]))
+    }
+}
+
+impl Default for ETag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for ETag {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::default()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let etag = self.clone();
+        Box::pin(async move {
+            if !matches!(req.method(), HttpMethod::GET | HttpMethod::HEAD) {
+                return next(req).await;
+            }
+            let mut req = next(req).await;
+            if let Some(tag) = etag.tag_for(&mut req.response) {
+                let response = std::mem::take(&mut req.response).add_header("etag", tag.to_string());
+                req.response = response.revalidate(&mut req.request.meta);
+            }
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use starberry_core::http::http_value::CacheControl;
+    use starberry_core::http::meta::{HeaderValue, HttpMeta};
+    use starberry_core::http::response::response_templates::{normal_response, text_response};
+
+    use super::*;
+
+    #[test]
+    fn tags_successful_bodies() {
+        let etag = ETag::new();
+        let tag = etag.tag_for(&mut text_response("hello")).unwrap();
+        assert!(tag.weak);
+        assert_eq!(etag.tag_for(&mut text_response("hello")), Some(tag.clone()));
+        assert_ne!(etag.tag_for(&mut text_response("hello!")), Some(tag.clone()));
+
+        assert!(etag.tag_for(&mut normal_response(StatusCode::NOT_FOUND, "missing")).is_none());
+        assert!(etag.tag_for(&mut text_response("hello").add_header("etag", "\"v1\"")).is_none());
+        let mut private = text_response("hello").cache_control(CacheControl::new().with_no_store());
+        assert!(etag.tag_for(&mut private).is_none());
+        assert!(ETag::new().max_size(3).tag_for(&mut text_response("hello")).is_none());
+
+        let mut headers = HashMap::new();
+        headers.insert("if-none-match".to_string(), HeaderValue::new(tag.to_string()));
+        let mut request = HttpMeta::new(Default::default(), headers);
+        let response = text_response("hello").add_header("etag", tag.to_string()).revalidate(&mut request);
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::NOT_MODIFIED);
+    }
+}
@@ -0,0 +1,184 @@
+use std::any::Any;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+
+use super::mmdb::{MaxMindDb, MmdbValue};
+
+/// Where the client of the current request is, found by `GeoIp` and stored in `req.params`.
+/// Every field is `None` when the database does not know it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoLocation {
+    /// The ISO 3166-1 code of the country, e.g. `FR`
+    pub country: Option<String>,
+    /// The name of the country in the language of `GeoIp`
+    pub country_name: Option<String>,
+    /// The code of the continent, e.g. `EU`
+    pub continent: Option<String>,
+    /// The ISO 3166-2 code of the largest subdivision without the country, e.g. `IDF`
+    pub region: Option<String>,
+    pub region_name: Option<String>,
+    pub city: Option<String>,
+    /// The IANA time zone, e.g. `Europe/Paris`
+    pub time_zone: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl GeoLocation {
+    /// Reads a record of a GeoIP2 or GeoLite2 Country or City database
+    pub fn from_record(record: &MmdbValue, language: &str) -> Self {
+        let text = |path: &[&str]| record.path(path).and_then(MmdbValue::as_str).map(str::to_string);
+        let country = text(&["country", "iso_code"]).or_else(|| text(&["registered_country", "iso_code"]));
+        let country_name = text(&["country", "names", language])
+            .or_else(|| text(&["registered_country", "names", language]));
+        Self {
+            country,
+            country_name,
+            continent: text(&["continent", "code"]),
+            region: text(&["subdivisions", "0", "iso_code"]),
+            region_name: text(&["subdivisions", "0", "names", language]),
+            city: text(&["city", "names", language]),
+            time_zone: text(&["location", "time_zone"]),
+            latitude: record.path(&["location", "latitude"]).and_then(MmdbValue::as_f64),
+            longitude: record.path(&["location", "longitude"]).and_then(MmdbValue::as_f64),
+        }
+    }
+}
+
+/// Finds where the client is in a MaxMind database, such as GeoLite2 City, and stores it in
+/// `req.params` as `GeoLocation`, read it with `req.geo()`. Handlers and the middlewares
+/// after this one can then route, block or pick a default locale by country or region.
+///
+/// The client is the address given by `HttpReqCtx::client_ip`, so the forwarding headers of
+/// the proxies given to `AppBuilder::trusted_proxies` are honored. Nothing is stored when the
+/// address is unknown or not in the database, e.g. for private networks.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(GeoIp::open("data/GeoLite2-City.mmdb")?.language("fr"));
+///
+/// #[url(reg![&APP, LitUrl("shipping")])]
+/// async fn shipping() -> HttpResponse {
+///     match req.country() {
+///         Some("FR") | Some("BE") => text_response("Livraison offerte"),
+///         _ => text_response("Free shipping in France and Belgium"),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct GeoIp {
+    database: Option<Arc<MaxMindDb>>,
+    language: String,
+}
+
+impl GeoIp {
+    pub fn new(database: MaxMindDb) -> Self {
+        Self {
+            database: Some(Arc::new(database)),
+            language: "en".to_string(),
+        }
+    }
+
+    /// Reads the database file, `.mmdb`
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        MaxMindDb::open(path).map(Self::new)
+    }
+
+    /// Sets the language of the names, `en` by default. GeoIP2 databases have `de`, `en`,
+    /// `es`, `fr`, `ja`, `pt-BR`, `ru` and `zh-CN`
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Where an address is, `None` if it is not in the database
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoLocation> {
+        let record = self.database.as_ref()?.lookup(ip)?;
+        Some(GeoLocation::from_record(&record, &self.language))
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for GeoIp {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Without database, nothing is ever found
+    fn return_self() -> Self {
+        Self {
+            database: None,
+            language: "en".to_string(),
+        }
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let geoip = self.clone();
+        Box::pin(async move {
+            if let Some(location) = req.client_ip().and_then(|ip| geoip.lookup(ip)) {
+                req.params.set(location);
+            }
+            next(req).await
+        })
+    }
+}
+
+/// Access to the location found by `GeoIp`
+pub trait GeoExt {
+    /// Where the client is, `None` if `GeoIp` is not registered or found nothing
+    fn geo(&self) -> Option<&GeoLocation>;
+
+    /// The ISO code of the country of the client, e.g. `FR`
+    fn country(&self) -> Option<&str>;
+}
+
+impl GeoExt for HttpReqCtx {
+    fn geo(&self) -> Option<&GeoLocation> {
+        self.params.get::<GeoLocation>()
+    }
+
+    fn country(&self) -> Option<&str> {
+        self.geo().and_then(|location| location.country.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geoip::mmdb::tests::{array, database, double, map, string};
+
+    #[test]
+    fn reads_city_records() {
+        let data = map(&[
+            ("continent", map(&[("code", string("EU"))])),
+            ("country", map(&[("iso_code", string("FR")), ("names", map(&[("en", string("France"))]))])),
+            ("subdivisions", array(&[map(&[("iso_code", string("IDF")), ("names", map(&[("fr", string("Île-de-France"))]))])])),
+            ("city", map(&[("names", map(&[("fr", string("Paris"))]))])),
+            ("location", map(&[("time_zone", string("Europe/Paris")), ("longitude", double(2.35))])),
+        ]);
+        let geoip = GeoIp::new(database(data, 0)).language("fr");
+        let location = geoip.lookup("81.2.69.160".parse().unwrap()).unwrap();
+        assert_eq!(location.country.as_deref(), Some("FR"));
+        assert_eq!(location.country_name, None);
+        assert_eq!(location.continent.as_deref(), Some("EU"));
+        assert_eq!(location.region.as_deref(), Some("IDF"));
+        assert_eq!(location.region_name.as_deref(), Some("Île-de-France"));
+        assert_eq!(location.city.as_deref(), Some("Paris"));
+        assert_eq!(location.time_zone.as_deref(), Some("Europe/Paris"));
+        assert_eq!(location.longitude, Some(2.35));
+        assert_eq!(geoip.lookup("203.0.113.9".parse().unwrap()), None);
+        assert_eq!(GeoIp::return_self().lookup("81.2.69.160".parse().unwrap()), None);
+    }
+}
@@ -0,0 +1,384 @@
+//! A reader of the MaxMind DB format, used by the GeoLite2 and GeoIP2 databases.
+//!
+//! The whole file is kept in memory. It is a binary search tree over the bits of the
+//! addresses, whose leaves point into a data section of typed values, followed by the
+//! metadata of the database. See <https://maxmind.github.io/MaxMind-DB/>.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Marks the start of the metadata, at the end of the file
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+/// The zeroes between the search tree and the data section
+const DATA_SECTION_SEPARATOR: usize = 16;
+/// Maps and arrays nested deeper than this are considered corrupt, pointers could loop
+const MAX_DEPTH: usize = 64;
+
+/// A value of the data section
+#[derive(Debug, Clone, PartialEq)]
+pub enum MmdbValue {
+    String(String),
+    Double(f64),
+    Float(f32),
+    Bytes(Vec<u8>),
+    Unsigned(u128),
+    Signed(i32),
+    Boolean(bool),
+    Map(HashMap<String, MmdbValue>),
+    Array(Vec<MmdbValue>),
+}
+
+impl MmdbValue {
+    /// The member of a map
+    pub fn get(&self, key: &str) -> Option<&MmdbValue> {
+        match self {
+            MmdbValue::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Follows map keys and array indexes, e.g. `["subdivisions", "0", "iso_code"]`
+    pub fn path(&self, path: &[&str]) -> Option<&MmdbValue> {
+        path.iter().try_fold(self, |value, step| match value {
+            MmdbValue::Array(items) => items.get(step.parse::<usize>().ok()?),
+            _ => value.get(step),
+        })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MmdbValue::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            MmdbValue::Unsigned(number) => u64::try_from(*number).ok(),
+            MmdbValue::Signed(number) => u64::try_from(*number).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MmdbValue::Double(number) => Some(*number),
+            MmdbValue::Float(number) => Some(*number as f64),
+            MmdbValue::Unsigned(number) => Some(*number as f64),
+            MmdbValue::Signed(number) => Some(*number as f64),
+            _ => None,
+        }
+    }
+}
+
+/// A MaxMind DB loaded in memory
+#[derive(Debug, Clone)]
+pub struct MaxMindDb {
+    bytes: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u16,
+    database_type: String,
+    /// The offset of the data section
+    data_start: usize,
+    /// The node reached after the 96 zero bits of an IPv4 address in an IPv6 tree
+    ipv4_start: usize,
+}
+
+impl MaxMindDb {
+    /// Reads a database file, such as `GeoLite2-City.mmdb`
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    /// Reads a database from its bytes
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        let marker = bytes
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or_else(|| invalid("No MaxMind DB metadata found"))?;
+        let metadata_start = marker + METADATA_MARKER.len();
+        let metadata = Decoder { bytes: &bytes, base: metadata_start }
+            .decode(metadata_start, 0)
+            .map(|(value, _)| value)
+            .ok_or_else(|| invalid("Invalid MaxMind DB metadata"))?;
+        let number = |key: &str| metadata.get(key).and_then(MmdbValue::as_u64);
+        let node_count = number("node_count").ok_or_else(|| invalid("No node count in the metadata"))? as usize;
+        let record_size = number("record_size").ok_or_else(|| invalid("No record size in the metadata"))? as usize;
+        if !matches!(record_size, 24 | 28 | 32) {
+            return Err(invalid(&format!("Unsupported record size {}", record_size)));
+        }
+        let ip_version = number("ip_version").unwrap_or(6) as u16;
+        let database_type = metadata.get("database_type").and_then(MmdbValue::as_str).unwrap_or_default().to_string();
+        let tree_size = node_count * record_size * 2 / 8;
+        if tree_size + DATA_SECTION_SEPARATOR > marker {
+            return Err(invalid("The search tree is larger than the file"));
+        }
+        let mut db = Self {
+            bytes,
+            node_count,
+            record_size,
+            ip_version,
+            database_type,
+            data_start: tree_size + DATA_SECTION_SEPARATOR,
+            ipv4_start: 0,
+        };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = db.record(node, 0);
+            }
+            db.ipv4_start = node;
+        }
+        Ok(db)
+    }
+
+    /// The type of the database, e.g. `GeoLite2-City`
+    pub fn database_type(&self) -> &str {
+        &self.database_type
+    }
+
+    /// The data of the network containing the address, `None` if it is not in the database
+    pub fn lookup(&self, ip: IpAddr) -> Option<MmdbValue> {
+        let (bits, bit_count, start) = match (ip.to_canonical(), self.ip_version) {
+            (IpAddr::V4(ip), 4) => (u32::from(ip) as u128, 32, 0),
+            (IpAddr::V4(ip), _) => (u32::from(ip) as u128, 32, self.ipv4_start),
+            (IpAddr::V6(_), 4) => return None,
+            (IpAddr::V6(ip), _) => (u128::from(ip), 128, 0),
+        };
+        let mut node = start;
+        for i in 0..bit_count {
+            if node >= self.node_count {
+                break;
+            }
+            let bit = (bits >> (bit_count - 1 - i)) & 1;
+            node = self.record(node, bit as usize);
+        }
+        if node <= self.node_count {
+            return None;
+        }
+        // Records past the tree point into the data section, after the separator
+        let offset = self.data_start + node - self.node_count - DATA_SECTION_SEPARATOR;
+        Decoder { bytes: &self.bytes, base: self.data_start }
+            .decode(offset, 0)
+            .map(|(value, _)| value)
+    }
+
+    /// The left (0) or right (1) record of a node
+    fn record(&self, node: usize, side: usize) -> usize {
+        let size = self.record_size * 2 / 8;
+        let Some(bytes) = self.bytes.get(node * size..(node + 1) * size) else {
+            return self.node_count;
+        };
+        let read = |slice: &[u8]| slice.iter().fold(0usize, |n, byte| (n << 8) | *byte as usize);
+        match (self.record_size, side) {
+            (24, 0) => read(&bytes[..3]),
+            (24, _) => read(&bytes[3..]),
+            (28, 0) => ((bytes[3] as usize & 0xF0) << 20) | read(&bytes[..3]),
+            (28, _) => ((bytes[3] as usize & 0x0F) << 24) | read(&bytes[4..]),
+            (_, 0) => read(&bytes[..4]),
+            (_, _) => read(&bytes[4..]),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    /// Pointers are relative to the start of the section
+    base: usize,
+}
+
+impl Decoder<'_> {
+    fn slice(&self, offset: usize, len: usize) -> Option<&[u8]> {
+        self.bytes.get(offset..offset.checked_add(len)?)
+    }
+
+    fn number(&self, offset: usize, len: usize) -> Option<u128> {
+        Some(self.slice(offset, len)?.iter().fold(0u128, |n, byte| (n << 8) | *byte as u128))
+    }
+
+    /// Decodes the value at the offset, returning it with the offset following it
+    fn decode(&self, offset: usize, depth: usize) -> Option<(MmdbValue, usize)> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let control = *self.bytes.get(offset)?;
+        let mut offset = offset + 1;
+        let mut kind = control >> 5;
+        if kind == 1 {
+            let size = (control >> 3) & 0x3;
+            let high = (control & 0x7) as usize;
+            let len = size as usize + 1;
+            let low = self.number(offset, len)? as usize;
+            let pointer = match size {
+                0 => (high << 8) | low,
+                1 => ((high << 16) | low) + 2048,
+                2 => ((high << 24) | low) + 526336,
+                _ => low,
+            };
+            let (value, _) = self.decode(self.base + pointer, depth + 1)?;
+            return Some((value, offset + len));
+        }
+        if kind == 0 {
+            kind = 7 + *self.bytes.get(offset)?;
+            offset += 1;
+        }
+        let size = match control & 0x1F {
+            29 => 29 + self.number(offset, 1)? as usize,
+            30 => 285 + self.number(offset, 2)? as usize,
+            31 => 65821 + self.number(offset, 3)? as usize,
+            size => size as usize,
+        };
+        offset += match control & 0x1F {
+            29 => 1,
+            30 => 2,
+            31 => 3,
+            _ => 0,
+        };
+        match kind {
+            2 => {
+                let text = std::str::from_utf8(self.slice(offset, size)?).ok()?;
+                Some((MmdbValue::String(text.to_string()), offset + size))
+            }
+            3 => {
+                let bytes: [u8; 8] = self.slice(offset, 8)?.try_into().ok()?;
+                Some((MmdbValue::Double(f64::from_be_bytes(bytes)), offset + 8))
+            }
+            4 => Some((MmdbValue::Bytes(self.slice(offset, size)?.to_vec()), offset + size)),
+            5 | 6 | 9 | 10 => Some((MmdbValue::Unsigned(self.number(offset, size.min(16))?), offset + size)),
+            8 => {
+                // Shorter encodings are sign extended from their size
+                let number = self.number(offset, size.min(4))? as u32;
+                let shift = 32 - 8 * size.min(4) as u32;
+                let number = ((number.checked_shl(shift).unwrap_or(0)) as i32).checked_shr(shift).unwrap_or(0);
+                Some((MmdbValue::Signed(number), offset + size))
+            }
+            7 => {
+                let mut map = HashMap::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (key, next) = self.decode(offset, depth + 1)?;
+                    let MmdbValue::String(key) = key else {
+                        return None;
+                    };
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    map.insert(key, value);
+                    offset = next;
+                }
+                Some((MmdbValue::Map(map), offset))
+            }
+            11 => {
+                let mut items = Vec::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (value, next) = self.decode(offset, depth + 1)?;
+                    items.push(value);
+                    offset = next;
+                }
+                Some((MmdbValue::Array(items), offset))
+            }
+            14 => Some((MmdbValue::Boolean(size != 0), offset)),
+            15 => {
+                let bytes: [u8; 4] = self.slice(offset, 4)?.try_into().ok()?;
+                Some((MmdbValue::Float(f32::from_be_bytes(bytes)), offset + 4))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn control(kind: u8, size: usize) -> Vec<u8> {
+        assert!(size < 29);
+        if kind <= 7 {
+            vec![(kind << 5) | size as u8]
+        } else {
+            vec![size as u8, kind - 7]
+        }
+    }
+
+    pub(crate) fn string(text: &str) -> Vec<u8> {
+        [control(2, text.len()), text.as_bytes().to_vec()].concat()
+    }
+
+    pub(crate) fn unsigned(kind: u8, number: u32) -> Vec<u8> {
+        let bytes: Vec<u8> = number.to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect();
+        [control(kind, bytes.len()), bytes].concat()
+    }
+
+    pub(crate) fn double(number: f64) -> Vec<u8> {
+        [control(3, 8), number.to_be_bytes().to_vec()].concat()
+    }
+
+    pub(crate) fn pointer(offset: usize) -> Vec<u8> {
+        vec![(1 << 5) | ((offset >> 8) as u8 & 0x7), offset as u8]
+    }
+
+    pub(crate) fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = control(7, entries.len());
+        for (key, value) in entries {
+            bytes.extend(string(key));
+            bytes.extend(value);
+        }
+        bytes
+    }
+
+    pub(crate) fn array(items: &[Vec<u8>]) -> Vec<u8> {
+        [control(11, items.len()), items.concat()].concat()
+    }
+
+    /// An IPv4 database of one node: the addresses starting with a zero bit, 0.0.0.0/1,
+    /// have the record at `record` in the data section, the others are not found
+    pub(crate) fn database(data: Vec<u8>, record: usize) -> MaxMindDb {
+        let leaf = 1 + DATA_SECTION_SEPARATOR + record;
+        let mut bytes = vec![(leaf >> 16) as u8, (leaf >> 8) as u8, leaf as u8, 0, 0, 1];
+        bytes.extend([0; DATA_SECTION_SEPARATOR]);
+        bytes.extend(data);
+        bytes.extend(METADATA_MARKER);
+        bytes.extend(map(&[
+            ("node_count", unsigned(6, 1)),
+            ("record_size", unsigned(5, 24)),
+            ("ip_version", unsigned(5, 4)),
+            ("database_type", string("Test-City")),
+        ]));
+        MaxMindDb::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn looks_up_the_records() {
+        let mut data = string("France");
+        let record = data.len();
+        data.extend(map(&[
+            ("country", map(&[("iso_code", string("FR")), ("names", map(&[("en", pointer(0))]))])),
+            ("subdivisions", array(&[map(&[("iso_code", string("IDF"))])])),
+            ("location", map(&[("latitude", double(48.85)), ("accuracy_radius", unsigned(5, 500))])),
+        ]));
+        let db = database(data, record);
+        assert_eq!(db.database_type(), "Test-City");
+
+        let found = db.lookup("81.2.69.160".parse().unwrap()).unwrap();
+        assert_eq!(found.path(&["country", "iso_code"]).and_then(MmdbValue::as_str), Some("FR"));
+        assert_eq!(found.path(&["country", "names", "en"]).and_then(MmdbValue::as_str), Some("France"));
+        assert_eq!(found.path(&["subdivisions", "0", "iso_code"]).and_then(MmdbValue::as_str), Some("IDF"));
+        assert_eq!(found.path(&["location", "latitude"]).and_then(MmdbValue::as_f64), Some(48.85));
+        assert_eq!(found.path(&["location", "accuracy_radius"]).and_then(MmdbValue::as_u64), Some(500));
+        assert!(db.lookup("::ffff:81.2.69.160".parse().unwrap()).is_some());
+        assert!(db.lookup("203.0.113.9".parse().unwrap()).is_none());
+        assert!(db.lookup("2001:db8::1".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(MaxMindDb::from_bytes(b"not a database".to_vec()).is_err());
+    }
+}
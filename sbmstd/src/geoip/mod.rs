@@ -0,0 +1,5 @@
+pub mod location; 
+pub mod mmdb; 
+
+pub use self::location::{GeoExt, GeoIp, GeoLocation}; 
+pub use self::mmdb::{MaxMindDb, MmdbValue}; 
@@ -0,0 +1,212 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::cookie::Cookie;
+use starberry_core::http::http_value::AcceptLang;
+
+static LOCALE_COOKIE_MAX_AGE: u64 = 3600 * 24 * 365; // One year
+
+/// The locale resolved by `I18n` for the current request, stored in `req.params`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale(pub String);
+
+/// Resolves the locale of each request among the supported ones, from the `lang` query
+/// parameter, then the `lang` cookie, then the `Accept-Language` header, falling back to
+/// the default locale. The locale is stored in `req.params` as `Locale`, read it with
+/// `req.locale()`, and sent back in `Content-Language` unless the handler set one.
+///
+/// A tag matches a supported locale exactly, or by its primary language: `en-US` selects
+/// `en`, and `en` selects `en-GB` if `en` itself is not supported.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(I18n::new(["en", "fr", "zh-CN"]).remember());
+///
+/// #[url(reg![&APP, LitUrl("hello")])]
+/// async fn hello() -> HttpResponse {
+///     match req.locale().as_deref() {
+///         Some("fr") => text_response("Bonjour"),
+///         _ => text_response("Hello"),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct I18n {
+    supported: Arc<Vec<String>>,
+    default: String,
+    query_param: String,
+    cookie: String,
+    remember: bool,
+}
+
+impl I18n {
+    /// The first supported locale is the default one
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(supported: I) -> Self {
+        let supported: Vec<String> = supported.into_iter().map(Into::into).collect();
+        let default = supported.first().cloned().unwrap_or_else(|| "en".to_string());
+        Self {
+            supported: Arc::new(supported),
+            default,
+            query_param: "lang".to_string(),
+            cookie: "lang".to_string(),
+            remember: false,
+        }
+    }
+
+    /// Sets the locale used when nothing matches
+    pub fn default_locale(mut self, locale: impl Into<String>) -> Self {
+        self.default = locale.into();
+        self
+    }
+
+    /// Sets the query parameter selecting the locale, `lang` by default
+    pub fn query_param(mut self, name: impl Into<String>) -> Self {
+        self.query_param = name.into();
+        self
+    }
+
+    /// Sets the cookie holding the locale, `lang` by default
+    pub fn cookie(mut self, name: impl Into<String>) -> Self {
+        self.cookie = name.into();
+        self
+    }
+
+    /// Stores a locale chosen with the query parameter in the cookie, so it sticks
+    /// for the following requests
+    pub fn remember(mut self) -> Self {
+        self.remember = true;
+        self
+    }
+
+    /// The supported locale matching a language tag
+    pub fn matching(&self, tag: &str) -> Option<String> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return None;
+        }
+        if let Some(exact) = self.supported.iter().find(|l| l.eq_ignore_ascii_case(tag)) {
+            return Some(exact.clone());
+        }
+        let primary = primary_language(tag);
+        self.supported
+            .iter()
+            .find(|l| l.eq_ignore_ascii_case(primary))
+            .or_else(|| self.supported.iter().find(|l| primary_language(l).eq_ignore_ascii_case(primary)))
+            .cloned()
+    }
+
+    /// Resolves the locale from the query parameter, the cookie and the `Accept-Language`
+    /// header, in this order
+    pub fn resolve(&self, query: Option<&str>, cookie: Option<&str>, accept: Option<&AcceptLang>) -> String {
+        if let Some(locale) = query.and_then(|q| self.matching(q)) {
+            return locale;
+        }
+        if let Some(locale) = cookie.and_then(|c| self.matching(c)) {
+            return locale;
+        }
+        if let Some(accept) = accept {
+            let mut languages: Vec<(String, f32)> = accept
+                .all_languages()
+                .into_iter()
+                .map(|lang| {
+                    let weight = accept.get_weight(&lang);
+                    (lang, weight)
+                })
+                .filter(|(lang, weight)| *weight > 0.0 && lang != "*")
+                .collect();
+            // Stable, so equal weights keep the order of the header
+            languages.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            if let Some(locale) = languages.iter().find_map(|(lang, _)| self.matching(lang)) {
+                return locale;
+            }
+        }
+        self.default.clone()
+    }
+}
+
+fn primary_language(tag: &str) -> &str {
+    tag.split(['-', '_']).next().unwrap_or(tag)
+}
+
+impl AsyncMiddleware<HttpReqCtx> for I18n {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new(["en"])
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let i18n = self.clone();
+        Box::pin(async move {
+            let query = req.query_map().get(&i18n.query_param).map(str::to_string);
+            let chosen = query.as_deref().and_then(|q| i18n.matching(q));
+            let cookie = req.get_cookie(&i18n.cookie).map(|c| c.get_value().to_string());
+            let accept = req.request.meta.get_lang();
+            let locale = i18n.resolve(query.as_deref(), cookie.as_deref(), accept.as_ref());
+            req.params.set(Locale(locale.clone()));
+
+            let mut req = next(req).await;
+
+            if req.response.meta.get_header("content-language").is_none() {
+                req.response.meta.set_attribute("content-language", locale);
+            }
+            if let Some(chosen) = chosen.filter(|c| i18n.remember && cookie.as_deref() != Some(c.as_str())) {
+                req.response = req.response.add_cookie(
+                    i18n.cookie.clone(),
+                    Cookie::new(chosen).path("/").max_age(LOCALE_COOKIE_MAX_AGE),
+                );
+            }
+            req
+        })
+    }
+}
+
+/// Access to the locale resolved by `I18n`
+pub trait LocaleExt {
+    /// The locale of the request, `None` if `I18n` is not registered
+    fn locale(&self) -> Option<String>;
+}
+
+impl LocaleExt for HttpReqCtx {
+    fn locale(&self) -> Option<String> {
+        self.params.get::<Locale>().map(|locale| locale.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_by_primary_language() {
+        let i18n = I18n::new(["en", "fr", "zh-CN"]);
+        assert_eq!(i18n.matching("FR").as_deref(), Some("fr"));
+        assert_eq!(i18n.matching("en-US").as_deref(), Some("en"));
+        assert_eq!(i18n.matching("zh").as_deref(), Some("zh-CN"));
+        assert_eq!(i18n.matching("de"), None);
+    }
+
+    #[test]
+    fn resolution_order() {
+        let i18n = I18n::new(["en", "fr", "de"]);
+        let accept = AcceptLang::from_str("it, de;q=0.8, fr;q=0.9");
+        assert_eq!(i18n.resolve(Some("de"), Some("fr"), Some(&accept)), "de");
+        assert_eq!(i18n.resolve(Some("xx"), Some("fr"), Some(&accept)), "fr");
+        assert_eq!(i18n.resolve(None, None, Some(&accept)), "fr");
+        assert_eq!(i18n.resolve(None, None, None), "en");
+        let refused = AcceptLang::from_str("fr;q=0, it");
+        assert_eq!(i18n.resolve(None, None, Some(&refused)), "en");
+    }
+}
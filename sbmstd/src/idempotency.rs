@@ -0,0 +1,401 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use akari::Value;
+use starberry_core::app::middleware::{AsyncMiddleware, BoxFuture};
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{HttpMethod, StatusCode};
+use starberry_core::http::meta::HttpMeta;
+use starberry_core::http::response::HttpResponse;
+use starberry_core::http::response::response_templates::{normal_response, return_status};
+use starberry_lib::ende::digest::sha256_hex;
+
+use crate::session::{CSessionRW, SessionRW, PRINCIPAL_KEY};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// The longest key accepted
+const MAX_KEY_LENGTH: usize = 255;
+
+/// What a store knows about a key when a request claims it
+#[derive(Debug, Clone)]
+pub enum Claim {
+    /// The key is new, the request runs and its response is saved with `complete`
+    New,
+    /// A request with the key is still being handled
+    InProgress,
+    /// A request with the key was handled, with the fingerprint of that request
+    Completed { fingerprint: String, response: Box<HttpResponse> },
+}
+
+/// Where the first response of each key is kept, until its ttl runs out
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Claims the key for a request, unless it is known already
+    fn claim(&self, key: String, fingerprint: String, ttl: Duration) -> BoxFuture<Claim>;
+    /// Saves the response of the request which claimed the key
+    fn complete(&self, key: String, response: HttpResponse, ttl: Duration) -> BoxFuture<()>;
+    /// Forgets the key, so the request can be tried again
+    fn release(&self, key: String) -> BoxFuture<()>;
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Pending { fingerprint: String },
+    Done { fingerprint: String, response: Box<HttpResponse> },
+}
+
+/// Keeps the responses in memory, shared by the clones of the store
+#[derive(Debug, Clone, Default)]
+pub struct MemoryIdempotencyStore {
+    entries: Arc<Mutex<HashMap<String, (Entry, Instant)>>>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of keys known
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    fn claim(&self, key: String, fingerprint: String, ttl: Duration) -> BoxFuture<Claim> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (_, expires)| *expires > now);
+        let claim = match entries.get(&key) {
+            Some((Entry::Pending { .. }, _)) => Claim::InProgress,
+            Some((Entry::Done { fingerprint, response }, _)) => Claim::Completed {
+                fingerprint: fingerprint.clone(),
+                response: response.clone(),
+            },
+            None => {
+                entries.insert(key, (Entry::Pending { fingerprint }, now + ttl));
+                Claim::New
+            }
+        };
+        Box::pin(async move { claim })
+    }
+
+    fn complete(&self, key: String, response: HttpResponse, ttl: Duration) -> BoxFuture<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((Entry::Pending { fingerprint }, _)) = entries.get(&key) {
+            let fingerprint = fingerprint.clone();
+            entries.insert(key, (Entry::Done { fingerprint, response: Box::new(response) }, Instant::now() + ttl));
+        }
+        Box::pin(async {})
+    }
+
+    fn release(&self, key: String) -> BoxFuture<()> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+        Box::pin(async {})
+    }
+}
+
+/// Honors the `Idempotency-Key` header of `POST` and `PATCH` requests, so a client can retry
+/// a request, e.g. a payment, without the risk of running it twice.
+///
+/// The first response of each key is kept for the ttl, 24 hours by default, and sent again
+/// with `Idempotent-Replayed: true` for the later requests with the same key. While the first
+/// request is still being handled, the others are answered with `409 Conflict`, and a key
+/// reused for another method, path or body is answered with `422 Unprocessable Entity`.
+/// Responses with a 5xx status are not kept, and neither is the key of a request whose handler
+/// panicked or was cancelled, so the request can be retried.
+///
+/// Keys are scoped by the `Authorization` header, or else by the principal logged in with
+/// `Auth` or the session, so clients cannot read the responses of each other by guessing keys.
+/// It must therefore be placed after `Session` or `CookieSession` when the clients are
+/// authenticated by a cookie.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .append_middleware::<Session>()
+///     .add_middleware(Idempotency::new().route("/payments").require_key());
+///
+/// // With a store shared by several instances, implementing `IdempotencyStore`
+/// Idempotency::new().store(MyRedisIdempotencyStore::new(pool)).ttl(Duration::from_secs(3600));
+/// ```
+#[derive(Clone)]
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+    ttl: Duration,
+    prefixes: Vec<String>,
+    require_key: bool,
+    header: String,
+}
+
+impl Idempotency {
+    pub fn new() -> Self {
+        Self {
+            store: Arc::new(MemoryIdempotencyStore::new()),
+            ttl: DEFAULT_TTL,
+            prefixes: Vec::new(),
+            require_key: false,
+            header: "idempotency-key".to_string(),
+        }
+    }
+
+    /// Keeps the responses in the given store instead of memory
+    pub fn store<S: IdempotencyStore>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Sets how long a response is kept, 24 hours by default
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Only applies to the paths under the prefix. Can be called several times, every path
+    /// is covered when no prefix is given
+    pub fn route(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Rejects the requests without a key with `400 Bad Request`
+    pub fn require_key(mut self) -> Self {
+        self.require_key = true;
+        self
+    }
+
+    /// Reads the key from another header
+    pub fn header(mut self, header: impl Into<String>) -> Self {
+        self.header = header.into().to_lowercase();
+        self
+    }
+
+    /// Whether the requests to the path are covered
+    pub fn covers(&self, path: &str) -> bool {
+        self.prefixes.is_empty()
+            || self.prefixes.iter().any(|prefix| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+}
+
+impl Default for Idempotency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether a key can be used: not empty, at most 255 visible ASCII characters
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// What a request must match to reuse a key: its method, path, content type and body
+fn fingerprint(meta: &HttpMeta, body: &[u8]) -> String {
+    let parts = [
+        meta.method().to_string(),
+        meta.start_line.path(),
+        meta.get_header("content-type").unwrap_or_default(),
+        sha256_hex(body),
+    ];
+    sha256_hex(parts.join("\n").as_bytes())
+}
+
+/// Who the keys of a request belong to: the bearer of its `Authorization` header, else the
+/// principal of its session, else the session itself. Empty for an anonymous client
+fn scope(req: &HttpReqCtx) -> String {
+    if let Some(authorization) = req.request.meta.get_header("authorization") {
+        return format!("authorization:{}", authorization);
+    }
+    if let Some(session) = req.params.get::<SessionRW<'static>>() {
+        return match session.get(PRINCIPAL_KEY) {
+            Some(principal) => format!("principal:{}", principal),
+            None => format!("session:{}", session.session_id),
+        };
+    }
+    match req.params.get::<CSessionRW>().and_then(|session| session.get(PRINCIPAL_KEY)) {
+        Some(Value::Str(principal)) => format!("principal:{}", principal),
+        _ => String::new(),
+    }
+}
+
+/// Releases a claimed key unless the response was saved, so a handler which panicked or
+/// was cancelled does not leave its key in progress until the ttl runs out
+struct ClaimGuard {
+    store: Arc<dyn IdempotencyStore>,
+    key: Option<String>,
+}
+
+impl ClaimGuard {
+    /// The key, which the guard no longer releases
+    fn disarm(mut self) -> String {
+        self.key.take().unwrap_or_default()
+    }
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            runtime.spawn(self.store.release(key));
+        }
+    }
+}
+
+fn rejection(status: StatusCode, message: &str) -> HttpResponse {
+    normal_response(status, message.to_string())
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Idempotency {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::default()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let idempotency = self.clone();
+        Box::pin(async move {
+            if !matches!(req.method(), HttpMethod::POST | HttpMethod::PATCH) || !idempotency.covers(&req.path()) {
+                return next(req).await;
+            }
+            let Some(key) = req.request.meta.get_header(idempotency.header.as_str()) else {
+                if idempotency.require_key {
+                    req.response = rejection(StatusCode::BAD_REQUEST, "Missing Idempotency-Key header");
+                    return req;
+                }
+                return next(req).await;
+            };
+            let key = key.trim().to_string();
+            if !valid_key(&key) {
+                req.response = rejection(StatusCode::BAD_REQUEST, "Invalid Idempotency-Key header");
+                return req;
+            }
+            let key = format!("{}:{}", &sha256_hex(scope(&req).as_bytes())[..16], key);
+            let fingerprint = match req.raw_body().await {
+                Ok(body) => fingerprint(&req.request.meta, &body),
+                Err(status) => {
+                    req.response = return_status(status);
+                    return req;
+                }
+            };
+
+            match idempotency.store.claim(key.clone(), fingerprint.clone(), idempotency.ttl).await {
+                Claim::New => {}
+                Claim::InProgress => {
+                    req.response = rejection(StatusCode::CONFLICT, "A request with this Idempotency-Key is in progress")
+                        .add_header("retry-after", "1");
+                    return req;
+                }
+                Claim::Completed { fingerprint: first, .. } if first != fingerprint => {
+                    req.response = rejection(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "Idempotency-Key already used for another request",
+                    );
+                    return req;
+                }
+                Claim::Completed { response, .. } => {
+                    req.response = response.add_header("idempotent-replayed", "true");
+                    return req;
+                }
+            }
+
+            let guard = ClaimGuard { store: idempotency.store.clone(), key: Some(key) };
+            let req = next(req).await;
+            let key = guard.disarm();
+            if req.response.meta.start_line.status_code().as_u16() >= 500 {
+                idempotency.store.release(key).await;
+            } else {
+                idempotency.store.complete(key, req.response.clone(), idempotency.ttl).await;
+            }
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starberry_core::http::response::response_templates::text_response;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_completed_keys() {
+        let store = MemoryIdempotencyStore::new();
+        let ttl = Duration::from_secs(60);
+        assert!(matches!(store.claim("k".into(), "a".into(), ttl).await, Claim::New));
+        assert!(matches!(store.claim("k".into(), "a".into(), ttl).await, Claim::InProgress));
+
+        store.complete("k".into(), text_response("charged"), ttl).await;
+        match store.claim("k".into(), "a".into(), ttl).await {
+            Claim::Completed { fingerprint, response } => {
+                assert_eq!(fingerprint, "a");
+                assert_eq!(response.meta.start_line.status_code(), StatusCode::OK);
+            }
+            claim => panic!("unexpected {:?}", claim),
+        }
+
+        store.release("k".into()).await;
+        assert!(matches!(store.claim("k".into(), "b".into(), ttl).await, Claim::New));
+        assert!(matches!(store.claim("x".into(), "a".into(), Duration::ZERO).await, Claim::New));
+        assert!(matches!(store.claim("x".into(), "a".into(), ttl).await, Claim::New));
+    }
+
+    #[tokio::test]
+    async fn panicked_handlers_release_their_key() {
+        let store = MemoryIdempotencyStore::new();
+        let ttl = Duration::from_secs(60);
+        assert!(matches!(store.claim("k".into(), "a".into(), ttl).await, Claim::New));
+        let guard = ClaimGuard { store: Arc::new(store.clone()), key: Some("k".into()) };
+        let handler = tokio::spawn(async move {
+            let _guard = guard;
+            panic!("handler failed");
+        });
+        assert!(handler.await.is_err());
+        tokio::task::yield_now().await;
+        assert!(matches!(store.claim("k".into(), "a".into(), ttl).await, Claim::New));
+
+        let guard = ClaimGuard { store: Arc::new(store.clone()), key: Some("k".into()) };
+        assert_eq!(guard.disarm(), "k");
+        tokio::task::yield_now().await;
+        assert!(matches!(store.claim("k".into(), "a".into(), ttl).await, Claim::InProgress));
+    }
+
+    #[test]
+    fn fingerprints_cover_the_body() {
+        let meta = HttpMeta::new(Default::default(), Default::default());
+        assert_eq!(fingerprint(&meta, b"amount=10"), fingerprint(&meta, b"amount=10"));
+        assert_ne!(fingerprint(&meta, b"amount=10"), fingerprint(&meta, b"amount=99"));
+    }
+
+    #[test]
+    fn checks_keys_and_routes() {
+        assert!(valid_key("0c0b4b1e-6f6b-4b8a-9f3e-2f0e8c1d9a7b"));
+        assert!(!valid_key(""));
+        assert!(!valid_key("has space"));
+        assert!(!valid_key(&"k".repeat(256)));
+
+        let idempotency = Idempotency::new().route("/payments/");
+        assert!(idempotency.covers("/payments"));
+        assert!(idempotency.covers("/payments/42/refund"));
+        assert!(!idempotency.covers("/paymentsx"));
+        assert!(Idempotency::new().covers("/anything"));
+    }
+}
@@ -0,0 +1,296 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use akari::Value;
+use starberry_core::app::application::RunMode;
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::error::ErrorContext;
+use starberry_core::http::http_value::HttpMethod;
+use starberry_core::http::response::response_templates::{json_response, template_global_names};
+use starberry_lib::date::format_rfc3339;
+
+use crate::recorder::Recorder;
+
+/// The path of the introspection report
+pub const INTROSPECTION_PATH: &str = "/._starberry";
+
+/// Config values whose key contains one of these are never shown
+const SECRET_HINTS: [&str; 6] = ["secret", "password", "token", "key", "credential", "auth"];
+
+const REDACTED: &str = "[redacted]";
+
+/// A request answered with a 5xx status, as recorded by `Introspection`
+#[derive(Debug, Clone)]
+pub struct RecordedError {
+    pub method: HttpMethod,
+    pub path: String,
+    /// The pattern of the matched route, e.g. `/user/{id}`
+    pub route: String,
+    pub request_id: String,
+    pub status: u16,
+    pub at: SystemTime,
+    /// The params, locals and timing of the request, with secrets redacted
+    pub context: ErrorContext,
+}
+
+/// Serves a JSON report of the application at `/._starberry`, to speed up debugging:
+/// the registered routes with their middleware chains, the template globals, the
+/// settings of the app and the config values given with `config` (values whose key
+/// looks secret are redacted, statics only show their keys), and the recent requests
+/// which ended with a 5xx status. Given a `Recorder`, it also shows the recorded exchanges.
+///
+/// The report is only served in `RunMode::Development` unless `in_every_mode` is used.
+/// Errors are recorded in every mode.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(
+///         Introspection::new()
+///             .config("database_url", "postgres://localhost/app")
+///             .config("session_secret", SECRET),
+///     );
+/// ```
+#[derive(Clone)]
+pub struct Introspection {
+    path: String,
+    every_mode: bool,
+    capacity: usize,
+    config: Arc<Vec<(String, String)>>,
+    errors: Arc<Mutex<VecDeque<RecordedError>>>,
+    recorder: Option<Recorder>,
+}
+
+impl Introspection {
+    /// The number of errors kept by default
+    pub const DEFAULT_CAPACITY: usize = 50;
+
+    pub fn new() -> Self {
+        Self {
+            path: INTROSPECTION_PATH.to_string(),
+            every_mode: false,
+            capacity: Self::DEFAULT_CAPACITY,
+            config: Arc::new(Vec::new()),
+            errors: Arc::new(Mutex::new(VecDeque::new())),
+            recorder: None,
+        }
+    }
+
+    /// Serves the report at another path
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Serves the report in production and beta too. Protect it, e.g. with `Auth`
+    pub fn in_every_mode(mut self) -> Self {
+        self.every_mode = true;
+        self
+    }
+
+    /// Sets the number of recent errors kept
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Adds a config value to the report. It is redacted if its key looks secret
+    pub fn config(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        Arc::make_mut(&mut self.config).push((key.into(), value.to_string()));
+        self
+    }
+
+    /// Shows the exchanges recorded by the recorder in the report
+    pub fn recorder(mut self, recorder: &Recorder) -> Self {
+        self.recorder = Some(recorder.clone());
+        self
+    }
+
+    /// The recent errors, oldest first
+    pub fn recent_errors(&self) -> Vec<RecordedError> {
+        self.errors.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    fn record(&self, error: RecordedError) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        if errors.len() >= self.capacity {
+            errors.pop_front();
+        }
+        errors.push_back(error);
+    }
+
+    fn report(&self, req: &HttpReqCtx) -> Value {
+        let app = &req.app;
+        let mut report = HashMap::new();
+        report.insert("mode".to_string(), Value::Str(format!("{:?}", app.get_mode())));
+
+        let mut settings = HashMap::new();
+        settings.insert("binding_address".to_string(), Value::Str(app.binding_address.clone()));
+        settings.insert("workers".to_string(), literal(app.worker));
+        settings.insert("max_connection_time".to_string(), literal(app.max_connection_time));
+        settings.insert("https_port".to_string(), literal(app.https_port));
+        if let Some(redirect) = &app.http_redirect {
+            settings.insert("http_redirect".to_string(), Value::Str(redirect.clone()));
+        }
+        report.insert("app".to_string(), Value::Dict(settings));
+
+        let mut config = HashMap::new();
+        for key in app.statics.keys() {
+            config.insert(key.to_string(), Value::Str(REDACTED.to_string()));
+        }
+        for (key, value) in self.config.iter() {
+            let value = if is_secret(key) { REDACTED.to_string() } else { value.clone() };
+            config.insert(key.clone(), Value::Str(value));
+        }
+        report.insert("config".to_string(), Value::Dict(config));
+
+        let chain = app.handler.middlewares::<HttpReqCtx>().unwrap_or_default();
+        report.insert("middlewares".to_string(), strings(chain.iter().map(|m| m.name())));
+        let routes = app
+            .handler
+            .url::<HttpReqCtx>()
+            .map(|root| root.routes())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|route| {
+                let mut info = HashMap::new();
+                info.insert("pattern".to_string(), Value::Str(route.pattern));
+                if let Some(name) = route.name {
+                    info.insert("name".to_string(), Value::Str(name));
+                }
+                info.insert("middlewares".to_string(), strings(route.middlewares.into_iter()));
+                Value::Dict(info)
+            })
+            .collect();
+        report.insert("routes".to_string(), Value::List(routes));
+
+        // Templates are read from disk on every render, nothing is cached
+        let mut templates = HashMap::new();
+        templates.insert("directory".to_string(), Value::Str("templates".to_string()));
+        templates.insert("cached".to_string(), literal(false));
+        templates.insert("globals".to_string(), strings(template_global_names().into_iter()));
+        report.insert("templates".to_string(), Value::Dict(templates));
+
+        let errors = self
+            .recent_errors()
+            .into_iter()
+            .rev()
+            .map(|error| {
+                let mut info = HashMap::new();
+                info.insert("method".to_string(), Value::Str(error.method.to_string()));
+                info.insert("path".to_string(), Value::Str(error.path));
+                info.insert("route".to_string(), Value::Str(error.route));
+                info.insert("request_id".to_string(), Value::Str(error.request_id));
+                info.insert("status".to_string(), literal(error.status));
+                info.insert("at".to_string(), Value::Str(format_rfc3339(error.at)));
+                info.insert("context".to_string(), error.context.to_value());
+                Value::Dict(info)
+            })
+            .collect();
+        report.insert("recent_errors".to_string(), Value::List(errors));
+        if let Some(recorder) = &self.recorder {
+            report.insert("exchanges".to_string(), recorder.to_value());
+        }
+        Value::Dict(report)
+    }
+}
+
+impl Default for Introspection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SECRET_HINTS.iter().any(|hint| key.contains(hint))
+}
+
+/// A number or a bool, read back from its JSON text
+pub(crate) fn literal<N: ToString>(n: N) -> Value {
+    Value::from_json(&n.to_string()).unwrap_or(Value::new(""))
+}
+
+fn strings<S: ToString, I: Iterator<Item = S>>(items: I) -> Value {
+    Value::List(items.map(|s| Value::Str(s.to_string())).collect())
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Introspection {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let introspection = self.clone();
+        Box::pin(async move {
+            let path = req.path();
+            if path == introspection.path && (introspection.every_mode || req.app.get_mode() == RunMode::Development) {
+                req.response = json_response(introspection.report(&req));
+                return req;
+            }
+            let method = req.method();
+            let route = req.endpoint.route_pattern();
+            let req = next(req).await;
+            let status: u16 = req.response.meta.start_line.status_code().into();
+            if status >= 500 {
+                introspection.record(RecordedError {
+                    method,
+                    path,
+                    route,
+                    request_id: req.request_id().to_string(),
+                    status,
+                    at: SystemTime::now(),
+                    context: req.error_context(),
+                });
+            }
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_detected() {
+        assert!(is_secret("SESSION_SECRET"));
+        assert!(is_secret("api_key"));
+        assert!(!is_secret("database_host"));
+    }
+
+    #[test]
+    fn keeps_the_latest_errors() {
+        let introspection = Introspection::new().capacity(2);
+        for status in [500, 502, 503] {
+            introspection.record(RecordedError {
+                method: HttpMethod::GET,
+                path: "/".to_string(),
+                route: "/".to_string(),
+                request_id: String::new(),
+                status,
+                at: SystemTime::now(),
+                context: ErrorContext::default(),
+            });
+        }
+        let statuses: Vec<u16> = introspection.recent_errors().iter().map(|e| e.status).collect();
+        assert_eq!(statuses, vec![502, 503]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::any::Any;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{HttpContentType, StatusCode};
+use starberry_core::http::proxy::Cidr;
+use starberry_core::http::response::{response_templates, HttpResponse};
+
+/// Answers `403 Forbidden` to the clients outside of the allowed ranges, or inside of the
+/// denied ones, e.g. to keep the admin pages or the metrics on the private network.
+///
+/// The client is the address given by `HttpReqCtx::client_ip`, read from the forwarding
+/// headers of the proxies given to `AppBuilder::trusted_proxies`. A denied range wins over an
+/// allowed one, and without allowed range every client not denied passes. A client whose
+/// address is unknown is rejected as soon as ranges are allowed.
+///
+/// The filter applies to every path, or only under the prefixes given with `route`.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(
+///         IpFilter::new()
+///             .allow("10.0.0.0/8")
+///             .allow("fd00::/8")
+///             .deny("10.0.66.0/24")
+///             .route("/admin")
+///             .route("/metrics"),
+///     );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allowed: Vec<Cidr>,
+    denied: Vec<Cidr>,
+    /// Without trailing slash. Every path is filtered when empty
+    routes: Vec<String>,
+}
+
+impl IpFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows a range such as `192.168.0.0/16` or `2001:db8::/32`, or a single address.
+    /// Panics if the range is invalid, so a mistyped list does not silently let everyone in
+    pub fn allow(self, range: &str) -> Self {
+        let range = Cidr::parse(range).unwrap_or_else(|e| panic!("Invalid allowed range: {}", e));
+        self.allow_range(range)
+    }
+
+    /// Denies a range or a single address. Panics if the range is invalid
+    pub fn deny(self, range: &str) -> Self {
+        let range = Cidr::parse(range).unwrap_or_else(|e| panic!("Invalid denied range: {}", e));
+        self.deny_range(range)
+    }
+
+    pub fn allow_range(mut self, range: Cidr) -> Self {
+        self.allowed.push(range);
+        self
+    }
+
+    pub fn deny_range(mut self, range: Cidr) -> Self {
+        self.denied.push(range);
+        self
+    }
+
+    /// Only filters the paths under the prefix
+    pub fn route(mut self, prefix: impl Into<String>) -> Self {
+        self.routes.push(prefix.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Whether the requests to the path are filtered
+    pub fn covers(&self, path: &str) -> bool {
+        self.routes.is_empty()
+            || self.routes.iter().any(|prefix| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+
+    /// Whether a client of this address passes the filter, `None` if it is unknown
+    pub fn permits(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip else {
+            return self.allowed.is_empty();
+        };
+        if self.denied.iter().any(|range| range.contains(ip)) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|range| range.contains(ip))
+    }
+
+    fn reject(&self) -> HttpResponse {
+        response_templates::normal_response(StatusCode::FORBIDDEN, "Forbidden").content_type(HttpContentType::TextPlain())
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for IpFilter {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::default()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let filter = self.clone();
+        Box::pin(async move {
+            if filter.covers(&req.path()) && !filter.permits(req.client_ip()) {
+                req.response = filter.reject();
+                return req;
+            }
+            next(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> Option<IpAddr> {
+        Some(text.parse().unwrap())
+    }
+
+    #[test]
+    fn denied_ranges_win() {
+        let filter = IpFilter::new().allow("10.0.0.0/8").allow("fd00::/8").deny("10.0.66.0/24");
+        assert!(filter.permits(ip("10.1.2.3")));
+        assert!(filter.permits(ip("fd12::1")));
+        assert!(!filter.permits(ip("10.0.66.7")));
+        assert!(!filter.permits(ip("203.0.113.9")));
+        assert!(!filter.permits(None));
+
+        let blocklist = IpFilter::new().deny("198.51.100.0/24");
+        assert!(blocklist.permits(ip("203.0.113.9")));
+        assert!(!blocklist.permits(ip("::ffff:198.51.100.4")));
+        assert!(blocklist.permits(None));
+    }
+
+    #[test]
+    fn filters_the_routes() {
+        let filter = IpFilter::new().allow("127.0.0.1").route("/admin/").route("/metrics");
+        assert!(filter.covers("/admin"));
+        assert!(filter.covers("/metrics/process"));
+        assert!(!filter.covers("/administrators"));
+        assert!(IpFilter::new().covers("/"));
+    }
+}
@@ -1,9 +1,54 @@
 pub mod session; 
 pub mod cors; 
+pub mod auth; 
+pub mod maintenance; 
+pub mod body_limit; 
+pub mod bulkhead; 
+pub mod ip_filter; 
+pub mod slow_requests; 
+pub mod response_time; 
+pub mod etag; 
+pub mod compression; 
+pub mod idempotency; 
+pub mod i18n; 
+pub mod cookie_consent; 
+pub mod introspection; 
+pub mod recorder; 
+pub mod problem_details; 
+pub mod captcha; 
+pub mod spam_guard; 
+#[cfg(feature = "tus")] 
+pub mod tus; 
+#[cfg(feature = "geoip")] 
+pub mod geoip; 
 
 pub use starberry_core::app::middleware::LoggingMiddleware as PrintLog; 
 pub use session::Session; 
 pub use session::CookieSession; 
+pub use session::{LimitPolicy, SessionLimit}; 
 
 pub use cors::cors::Cors; 
 pub use cors::cors_settings; 
+
+pub use auth::{Auth, AuthExt, AuthUser, BasicAuth, StaticTokens, TokenGuard, TokenStore}; 
+
+pub use maintenance::{Maintenance, MaintenanceSwitch}; 
+pub use body_limit::BodyLimit; 
+pub use bulkhead::Bulkhead; 
+pub use ip_filter::IpFilter; 
+pub use slow_requests::{SlowRequest, SlowRequests}; 
+pub use response_time::ResponseTime; 
+pub use etag::ETag; 
+pub use compression::Compression; 
+pub use idempotency::{Claim, Idempotency, IdempotencyStore, MemoryIdempotencyStore}; 
+pub use i18n::{I18n, Locale, LocaleExt}; 
+pub use cookie_consent::{Consent, ConsentExt, CookieConsent}; 
+pub use introspection::{Introspection, RecordedError}; 
+pub use recorder::{Exchange, RecordedBody, Recorder}; 
+pub use problem_details::ProblemDetails; 
+pub use captcha::{Captcha, CaptchaError, CaptchaProvider}; 
+pub use spam_guard::{FormRules, SpamError, SpamGuard}; 
+#[cfg(feature = "tus")] 
+pub use tus::{DirUploadStore, MemoryUploadStore, Tus, Upload, UploadStore}; 
+#[cfg(feature = "geoip")] 
+pub use geoip::{GeoExt, GeoIp, GeoLocation, MaxMindDb}; 
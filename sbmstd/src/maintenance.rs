@@ -0,0 +1,220 @@
+use std::any::Any;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{HttpContentType, StatusCode};
+use starberry_core::http::response::response_templates;
+
+const DEFAULT_PAGE: &str = "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Maintenance</title></head>\
+<body><h1>Down for maintenance</h1><p>We will be back shortly.</p></body></html>";
+
+/// Turns the maintenance mode on and off at runtime. Clones control the same middleware
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceSwitch(Arc<AtomicBool>);
+
+impl MaintenanceSwitch {
+    pub fn enable(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn disable(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Serves a 503 maintenance page instead of running the handlers while the maintenance
+/// mode is on.
+///
+/// The mode is on while the switch is enabled or while the sentinel file exists, so a
+/// deploy script can take the application down with a `touch`. Requests from the allowed
+/// IPs or under the allowed paths, e.g. a health check, are served normally.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let maintenance = Maintenance::new()
+///     .sentinel("/var/run/myapp/maintenance")
+///     .allow_ip("10.0.0.1".parse().unwrap())
+///     .allow_path("/health")
+///     .retry_after(300);
+/// let switch = maintenance.switch();
+///
+/// let app = App::new()
+///     .single_protocol(ProtocolBuilder::<HttpReqCtx>::new().add_middleware(maintenance))
+///     .manage(switch)
+///     .build();
+///
+/// // Later, e.g. in an admin handler
+/// req.state::<MaintenanceSwitch>().unwrap().enable();
+/// ```
+pub struct Maintenance {
+    switch: MaintenanceSwitch,
+    sentinel: Option<PathBuf>,
+    allowed_ips: Vec<IpAddr>,
+    allowed_paths: Vec<String>,
+    page: String,
+    retry_after: Option<u64>,
+}
+
+impl Maintenance {
+    pub fn new() -> Self {
+        Self {
+            switch: MaintenanceSwitch::default(),
+            sentinel: None,
+            allowed_ips: Vec::new(),
+            allowed_paths: Vec::new(),
+            page: DEFAULT_PAGE.to_string(),
+            retry_after: None,
+        }
+    }
+
+    /// The switch toggling the maintenance mode of this middleware
+    pub fn switch(&self) -> MaintenanceSwitch {
+        self.switch.clone()
+    }
+
+    /// Starts in maintenance mode
+    pub fn enabled(self) -> Self {
+        self.switch.enable();
+        self
+    }
+
+    /// Turns the maintenance mode on while the file exists
+    pub fn sentinel(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sentinel = Some(path.into());
+        self
+    }
+
+    /// Lets the requests of the IP through
+    pub fn allow_ip(mut self, ip: IpAddr) -> Self {
+        self.allowed_ips.push(ip);
+        self
+    }
+
+    /// Lets the requests under the path through
+    pub fn allow_path(mut self, prefix: impl Into<String>) -> Self {
+        self.allowed_paths.push(prefix.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Sets the HTML page served during the maintenance
+    pub fn page(mut self, html: impl Into<String>) -> Self {
+        self.page = html.into();
+        self
+    }
+
+    /// Tells the clients to retry after the given number of seconds
+    pub fn retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    /// Whether the request bypasses the maintenance mode
+    pub fn allows(&self, ip: Option<IpAddr>, path: &str) -> bool {
+        ip.is_some_and(|ip| self.allowed_ips.contains(&ip))
+            || self.allowed_paths.iter().any(|prefix| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Maintenance {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let switched_on = self.switch.is_enabled();
+        let sentinel = self.sentinel.clone();
+        let allowed = self.allows(req.client_ip(), &req.path());
+        let page = self.page.clone();
+        let retry_after = self.retry_after;
+        Box::pin(async move {
+            if allowed {
+                return next(req).await;
+            }
+            let on = switched_on
+                || match sentinel {
+                    Some(path) => tokio::fs::try_exists(path).await.unwrap_or(false),
+                    None => false,
+                };
+            if !on {
+                return next(req).await;
+            }
+            let mut response = response_templates::normal_response(StatusCode::SERVICE_UNAVAILABLE, page)
+                .content_type(HttpContentType::TextHtml());
+            if let Some(seconds) = retry_after {
+                response = response.add_header("retry-after", seconds.to_string());
+            }
+            req.response = response;
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starberry_core::http::meta::HttpMeta;
+    use starberry_core::http::proxy::TrustedProxies;
+    use std::collections::HashMap;
+
+    #[test]
+    fn switch_is_shared() {
+        let maintenance = Maintenance::new();
+        let switch = maintenance.switch();
+        assert!(!maintenance.switch.is_enabled());
+        switch.enable();
+        assert!(maintenance.switch.is_enabled());
+        switch.disable();
+        assert!(!maintenance.switch.is_enabled());
+    }
+
+    #[test]
+    fn allowlist() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let maintenance = Maintenance::new().allow_ip(ip).allow_path("/health/");
+        assert!(maintenance.allows(Some(ip), "/"));
+        assert!(maintenance.allows(None, "/health"));
+        assert!(maintenance.allows(None, "/health/db"));
+        assert!(!maintenance.allows(None, "/healthy"));
+        assert!(!maintenance.allows(Some("10.0.0.2".parse().unwrap()), "/"));
+    }
+
+    #[test]
+    fn allows_the_client_behind_a_trusted_proxy() {
+        let client: IpAddr = "203.0.113.5".parse().unwrap();
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let maintenance = Maintenance::new().allow_ip(client);
+        let mut meta = HttpMeta::new(Default::default(), HashMap::new());
+        meta.set_attribute("x-forwarded-for", "203.0.113.5");
+        let resolved = TrustedProxies::new().trust("10.0.0.0/8").resolve(proxy, &meta);
+        assert!(maintenance.allows(Some(resolved), "/"));
+        assert!(!maintenance.allows(Some(proxy), "/"));
+    }
+}
@@ -0,0 +1,216 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::body::HttpBody;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::error::ErrorContext;
+use starberry_core::http::http_value::HttpContentType;
+use starberry_core::http::problem::{PROBLEM_JSON, Problem, prefers_html};
+use starberry_core::http::response::HttpResponse;
+
+type PageRenderer = Arc<dyn Fn(&Problem, &ErrorContext) -> String + Send + Sync>;
+
+/// Negotiates the error responses between HTML error pages and RFC 7807 problem
+/// documents, following the `Accept` header of the request.
+///
+/// Problems returned by the handlers are sent as `application/problem+json`, or as an
+/// HTML page to clients preferring HTML such as browsers. Error responses with an empty
+/// or plain text body, e.g. from `return_status` or `HttpError`, are turned into problems
+/// whose detail is the text. Other error responses, such as HTML pages or JSON bodies
+/// built by the handlers, are left untouched.
+///
+/// Pages rendered with `html_with_context` also get the `ErrorContext` of the request: its
+/// id, route, params, locals with secrets redacted, and the time spent on it.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(ProblemDetails::new().html(|problem| {
+///         format!("<h1>{}</h1><a href=\"/\">Back home</a>", problem.title)
+///     }));
+///
+/// ProblemDetails::new().html_with_context(|problem, context| {
+///     format!("<h1>{}</h1><p>Request {} on {}</p>", problem.title, context.request_id, context.route)
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct ProblemDetails {
+    renderer: Option<PageRenderer>,
+}
+
+impl ProblemDetails {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders the HTML error pages instead of the minimal default page
+    pub fn html<F: Fn(&Problem) -> String + Send + Sync + 'static>(self, renderer: F) -> Self {
+        self.html_with_context(move |problem, _| renderer(problem))
+    }
+
+    /// Renders the HTML error pages with the context of the failed request
+    pub fn html_with_context<F: Fn(&Problem, &ErrorContext) -> String + Send + Sync + 'static>(
+        mut self,
+        renderer: F,
+    ) -> Self {
+        self.renderer = Some(Arc::new(renderer));
+        self
+    }
+
+    /// Rewrites an error response for a client, keeping its status and headers
+    pub fn negotiate(&self, response: &mut HttpResponse, accept: Option<&str>, path: &str) {
+        let context = ErrorContext {
+            path: path.to_string(),
+            status: response.meta.start_line.status_code().as_u16(),
+            ..Default::default()
+        };
+        self.negotiate_with(response, accept, &context);
+    }
+
+    /// Rewrites an error response for a client, rendering the pages with the context
+    pub fn negotiate_with(&self, response: &mut HttpResponse, accept: Option<&str>, context: &ErrorContext) {
+        let Some(problem) = problem_of(response, &context.path) else {
+            return;
+        };
+        response.meta.delete_content_length();
+        if prefers_html(accept) {
+            let page = match &self.renderer {
+                Some(renderer) => renderer(&problem, context),
+                None => {
+                    let HttpBody::Binary(page) = problem.to_html_response().body else {
+                        return;
+                    };
+                    String::from_utf8_lossy(&page).into_owned()
+                }
+            };
+            response.body = HttpBody::Binary(page.into_bytes());
+            response.meta.set_content_type(HttpContentType::TextHtml());
+        } else {
+            response.body = HttpBody::Json(problem.to_value());
+            response.meta.set_content_type(HttpContentType::from_str(PROBLEM_JSON));
+        }
+    }
+}
+
+/// The problem described by an error response, if it can be negotiated
+fn problem_of(response: &mut HttpResponse, path: &str) -> Option<Problem> {
+    let status = response.meta.start_line.status_code();
+    if !status.is_error() {
+        return None;
+    }
+    let content_type = response.meta.get_content_type().map(|content_type| content_type.to_string());
+    match (content_type.as_deref(), &response.body) {
+        (Some(PROBLEM_JSON), HttpBody::Json(value)) => Problem::from_value(value),
+        (Some(PROBLEM_JSON), HttpBody::Binary(json)) => {
+            akari::Value::from_json(&String::from_utf8_lossy(json)).ok().and_then(|value| Problem::from_value(&value))
+        }
+        (None | Some("text/plain"), HttpBody::Empty | HttpBody::Unparsed) => Some(Problem::new(status).with_instance(path)),
+        (None | Some("text/plain"), HttpBody::Binary(text)) => Some(with_detail(Problem::new(status), &String::from_utf8_lossy(text), path)),
+        (None | Some("text/plain"), HttpBody::Text(text)) => Some(with_detail(Problem::new(status), text, path)),
+        _ => None,
+    }
+}
+
+fn with_detail(problem: Problem, detail: &str, path: &str) -> Problem {
+    let problem = problem.with_instance(path);
+    if detail.trim().is_empty() { problem } else { problem.with_detail(detail) }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for ProblemDetails {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new()
+    }
+
+    fn handle<'a>(
+        &self,
+        req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let problems = self.clone();
+        Box::pin(async move {
+            let mut req = next(req).await;
+            if !req.response.meta.start_line.status_code().is_error() {
+                return req;
+            }
+            let accept = req.request.meta.get_header("accept");
+            let context = req.error_context();
+            problems.negotiate_with(&mut req.response, accept.as_deref(), &context);
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starberry_core::http::error::{HttpError, IntoResponse};
+    use starberry_core::http::http_value::StatusCode;
+    use starberry_core::http::response::response_templates::{html_response, return_status};
+
+    use super::*;
+
+    fn content_type(response: &mut HttpResponse) -> String {
+        response.meta.get_content_type().unwrap().to_string()
+    }
+
+    #[test]
+    fn plain_errors_become_problems() {
+        let problems = ProblemDetails::new();
+        let mut response = HttpError::not_found("No such user").into_response();
+        problems.negotiate(&mut response, Some("application/json"), "/user/7");
+        assert_eq!(content_type(&mut response), PROBLEM_JSON);
+        let HttpBody::Json(document) = &response.body else { panic!("not a problem document") };
+        let problem = Problem::from_value(document).unwrap();
+        assert_eq!(problem.detail.as_deref(), Some("No such user"));
+        assert_eq!(problem.instance.as_deref(), Some("/user/7"));
+
+        let mut response = return_status(StatusCode::FORBIDDEN);
+        problems.negotiate(&mut response, Some("text/html"), "/admin");
+        assert_eq!(content_type(&mut response), "text/html");
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn problems_follow_accept() {
+        let problems = ProblemDetails::new().html(|problem| format!("<h1>{}</h1>", problem.title));
+        let mut response = Problem::new(StatusCode::CONFLICT).into_response();
+        problems.negotiate(&mut response, Some("text/html,*/*;q=0.8"), "/");
+        let HttpBody::Binary(page) = &response.body else { panic!("not a page") };
+        assert_eq!(page.as_slice(), b"<h1>Conflict</h1>");
+    }
+
+    #[test]
+    fn pages_get_the_context() {
+        let problems = ProblemDetails::new()
+            .html_with_context(|problem, context| format!("{} on {} ({})", problem.title, context.route, context.request_id));
+        let context = ErrorContext {
+            request_id: "req-1".to_string(),
+            path: "/user/7".to_string(),
+            route: "/user/{id}".to_string(),
+            status: 404,
+            ..Default::default()
+        };
+        let mut response = return_status(StatusCode::NOT_FOUND);
+        problems.negotiate_with(&mut response, Some("text/html"), &context);
+        let HttpBody::Binary(page) = &response.body else { panic!("not a page") };
+        assert_eq!(page.as_slice(), b"Not Found on /user/{id} (req-1)");
+    }
+
+    #[test]
+    fn other_responses_are_kept() {
+        let problems = ProblemDetails::new();
+        let mut response = html_response("<h1>Oops</h1>").status(StatusCode::INTERNAL_SERVER_ERROR);
+        problems.negotiate(&mut response, None, "/");
+        assert_eq!(content_type(&mut response), "text/html");
+        let mut response = return_status(StatusCode::OK);
+        problems.negotiate(&mut response, None, "/");
+        assert!(matches!(response.body, HttpBody::Binary(ref body) if body.is_empty()));
+    }
+}
@@ -0,0 +1,503 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use akari::Value;
+use starberry_core::app::application::RunMode;
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::body::HttpBody;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::form::MultiFormField;
+use starberry_core::http::http_value::HttpMethod;
+use starberry_core::http::meta::HttpMeta;
+use starberry_lib::date::format_rfc3339;
+
+use crate::introspection::literal;
+
+const REDACTED: &str = "[redacted]";
+
+/// Headers whose value is never recorded
+const DEFAULT_REDACTED_HEADERS: [&str; 5] = ["authorization", "proxy-authorization", "cookie", "set-cookie", "x-api-key"];
+
+/// JSON and form fields whose value is never recorded
+const DEFAULT_REDACTED_FIELDS: [&str; 7] = [
+    "password",
+    "secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "api_key",
+];
+
+/// A body as recorded, cut after the size limit of the `Recorder`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordedBody {
+    pub text: String,
+    /// The size of the whole body in bytes
+    pub size: usize,
+    pub truncated: bool,
+}
+
+impl RecordedBody {
+    fn new(text: String, limit: usize) -> Self {
+        let size = text.len();
+        if size <= limit {
+            return Self { text, size, truncated: false };
+        }
+        let mut end = limit;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Self {
+            text: text[..end].to_string(),
+            size,
+            truncated: true,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut map = HashMap::new();
+        map.insert("text".to_string(), Value::Str(self.text.clone()));
+        map.insert("size".to_string(), literal(self.size));
+        map.insert("truncated".to_string(), literal(self.truncated));
+        Value::Dict(map)
+    }
+}
+
+/// A request and its response, as recorded by `Recorder`
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    /// Increasing number of the exchange, starting at 1
+    pub id: u64,
+    pub at: SystemTime,
+    pub request_id: String,
+    pub method: HttpMethod,
+    /// The path with the query string
+    pub url: String,
+    /// The pattern of the matched route, e.g. `/user/{id}`
+    pub route: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: RecordedBody,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: RecordedBody,
+    /// The time spent in the middlewares after the recorder and the handler
+    pub duration: Duration,
+}
+
+impl Exchange {
+    /// A curl command replaying the request. Redacted headers are left out and
+    /// truncated bodies are sent as recorded
+    pub fn to_curl(&self) -> String {
+        let host = self
+            .request_headers
+            .iter()
+            .find(|(name, _)| name == "host")
+            .map_or("localhost", |(_, value)| value.as_str());
+        let mut command = format!("curl -X {} {}", self.method, shell_quote(&format!("http://{}{}", host, self.url)));
+        for (name, value) in &self.request_headers {
+            if value == REDACTED || name == "host" || name == "content-length" {
+                continue;
+            }
+            command.push_str(&format!(" -H {}", shell_quote(&format!("{}: {}", name, value))));
+        }
+        if !self.request_body.text.is_empty() {
+            command.push_str(&format!(" --data-raw {}", shell_quote(&self.request_body.text)));
+        }
+        command
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut map = HashMap::new();
+        map.insert("id".to_string(), literal(self.id));
+        map.insert("at".to_string(), Value::Str(format_rfc3339(self.at)));
+        map.insert("request_id".to_string(), Value::Str(self.request_id.clone()));
+        map.insert("method".to_string(), Value::Str(self.method.to_string()));
+        map.insert("url".to_string(), Value::Str(self.url.clone()));
+        map.insert("route".to_string(), Value::Str(self.route.clone()));
+        map.insert("request_headers".to_string(), headers_value(&self.request_headers));
+        map.insert("request_body".to_string(), self.request_body.to_value());
+        map.insert("status".to_string(), literal(self.status));
+        map.insert("response_headers".to_string(), headers_value(&self.response_headers));
+        map.insert("response_body".to_string(), self.response_body.to_value());
+        map.insert("duration_ms".to_string(), literal(self.duration.as_millis()));
+        map.insert("curl".to_string(), Value::Str(self.to_curl()));
+        Value::Dict(map)
+    }
+}
+
+/// Records the requests and responses going through it, to inspect them or replay them.
+///
+/// The latest exchanges are kept in memory, in a ring buffer holding `capacity` of them.
+/// They are shown in the report of `Introspection` when it is given the recorder, and
+/// each exchange can be written as a JSON file to a directory with `dump_to`, or all of
+/// them at once with `dump`. A dumped exchange carries a curl command replaying it.
+///
+/// Bodies are cut after `body_limit` bytes. The values of sensitive headers, such as
+/// `Authorization` and `Cookie`, and of sensitive JSON and form fields, such as
+/// `password`, are replaced by `[redacted]`; `redact_header` and `redact_field` add more.
+///
+/// Requests are only recorded in `RunMode::Development` unless `in_every_mode` is used.
+/// The request body is read before the handler runs, so place `BodyLimit` before the
+/// recorder, or use `skip_request_bodies`.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let recorder = Recorder::new().redact_field("card_number").dump_to("recordings");
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(Introspection::new().recorder(&recorder))
+///     .add_middleware(recorder);
+/// ```
+#[derive(Clone)]
+pub struct Recorder {
+    capacity: usize,
+    body_limit: usize,
+    every_mode: bool,
+    read_request_bodies: bool,
+    redacted_headers: Arc<Vec<String>>,
+    redacted_fields: Arc<Vec<String>>,
+    dump_dir: Option<PathBuf>,
+    exchanges: Arc<Mutex<VecDeque<Exchange>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Recorder {
+    /// The number of exchanges kept by default
+    pub const DEFAULT_CAPACITY: usize = 100;
+
+    /// The number of bytes of each body kept by default
+    pub const DEFAULT_BODY_LIMIT: usize = 16 * 1024;
+
+    pub fn new() -> Self {
+        Self {
+            capacity: Self::DEFAULT_CAPACITY,
+            body_limit: Self::DEFAULT_BODY_LIMIT,
+            every_mode: false,
+            read_request_bodies: true,
+            redacted_headers: Arc::new(DEFAULT_REDACTED_HEADERS.iter().map(|h| h.to_string()).collect()),
+            redacted_fields: Arc::new(DEFAULT_REDACTED_FIELDS.iter().map(|f| f.to_string()).collect()),
+            dump_dir: None,
+            exchanges: Arc::new(Mutex::new(VecDeque::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Sets the number of exchanges kept in memory
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the number of bytes of each body which are recorded
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// Records in production and beta too
+    pub fn in_every_mode(mut self) -> Self {
+        self.every_mode = true;
+        self
+    }
+
+    /// Leaves the request bodies unread, only bodies read by the handler are recorded
+    pub fn skip_request_bodies(mut self) -> Self {
+        self.read_request_bodies = false;
+        self
+    }
+
+    /// Redacts the value of a header, matched case-insensitively
+    pub fn redact_header(mut self, name: impl AsRef<str>) -> Self {
+        Arc::make_mut(&mut self.redacted_headers).push(name.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Redacts the value of a JSON or form field, matched case-insensitively at any depth
+    pub fn redact_field(mut self, name: impl AsRef<str>) -> Self {
+        Arc::make_mut(&mut self.redacted_fields).push(name.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Writes each exchange to the directory as it is recorded
+    pub fn dump_to(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dump_dir = Some(dir.into());
+        self
+    }
+
+    /// The recorded exchanges, oldest first
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    /// Forgets the recorded exchanges
+    pub fn clear(&self) {
+        self.exchanges.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// The recorded exchanges, newest first
+    pub fn to_value(&self) -> Value {
+        Value::List(self.exchanges().iter().rev().map(Exchange::to_value).collect())
+    }
+
+    /// Writes the recorded exchanges to the directory, returning how many were written
+    pub fn dump<P: AsRef<Path>>(&self, dir: P) -> io::Result<usize> {
+        let exchanges = self.exchanges();
+        for exchange in &exchanges {
+            write_exchange(dir.as_ref(), exchange)?;
+        }
+        Ok(exchanges.len())
+    }
+
+    fn record(&self, exchange: Exchange) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut exchanges = self.exchanges.lock().unwrap_or_else(|e| e.into_inner());
+        if exchanges.len() >= self.capacity {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+
+    fn headers(&self, meta: &HttpMeta) -> Vec<(String, String)> {
+        let represented = meta.represent();
+        represented
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| {
+                let name = name.trim().to_ascii_lowercase();
+                let value = if self.redacted_headers.contains(&name) {
+                    REDACTED.to_string()
+                } else {
+                    value.trim().to_string()
+                };
+                (name, value)
+            })
+            .collect()
+    }
+
+    fn body(&self, body: &HttpBody) -> RecordedBody {
+        let text = match body {
+            HttpBody::Text(text) => self.redact_text(text),
+            HttpBody::Binary(bytes) => match std::str::from_utf8(bytes) {
+                Ok(text) => self.redact_text(text),
+                Err(_) => format!("[{} bytes of binary data]", bytes.len()),
+            },
+            HttpBody::Json(value) => {
+                let mut value = value.clone();
+                self.redact_value(&mut value);
+                value.into_json()
+            }
+            HttpBody::Form(form) => {
+                let fields = form
+                    .get_all()
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Value::Str(self.redact_field_value(key, value))))
+                    .collect();
+                Value::Dict(fields).into_json()
+            }
+            HttpBody::Files(form) => {
+                let fields = form
+                    .get_all()
+                    .iter()
+                    .map(|(key, field)| {
+                        let value = match field {
+                            MultiFormField::Text(text) => self.redact_field_value(key, text),
+                            MultiFormField::File(files) => files
+                                .iter()
+                                .map(|file| {
+                                    format!("[file {}, {} bytes]", file.filename().unwrap_or_default(), file.data().len())
+                                })
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        };
+                        (key.clone(), Value::Str(value))
+                    })
+                    .collect();
+                Value::Dict(fields).into_json()
+            }
+            HttpBody::Empty => String::new(),
+            HttpBody::Unparsed => "[not read]".to_string(),
+        };
+        RecordedBody::new(text, self.body_limit)
+    }
+
+    /// Redacts the fields of a text holding a JSON object or array, other texts are kept
+    fn redact_text(&self, text: &str) -> String {
+        let trimmed = text.trim_start();
+        if !trimmed.starts_with('{') && !trimmed.starts_with('[') {
+            return text.to_string();
+        }
+        match Value::from_json(text) {
+            Ok(mut value) => {
+                self.redact_value(&mut value);
+                value.into_json()
+            }
+            Err(_) => text.to_string(),
+        }
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::Dict(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.is_redacted_field(key) {
+                        *value = Value::Str(REDACTED.to_string());
+                    } else {
+                        self.redact_value(value);
+                    }
+                }
+            }
+            Value::List(list) => list.iter_mut().for_each(|value| self.redact_value(value)),
+            _ => {}
+        }
+    }
+
+    fn redact_field_value(&self, key: &str, value: &str) -> String {
+        if self.is_redacted_field(key) { REDACTED.to_string() } else { value.to_string() }
+    }
+
+    fn is_redacted_field(&self, key: &str) -> bool {
+        self.redacted_fields.contains(&key.to_ascii_lowercase())
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn headers_value(headers: &[(String, String)]) -> Value {
+    Value::List(headers.iter().map(|(name, value)| Value::Str(format!("{}: {}", name, value))).collect())
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Writes an exchange to `<dir>/<unix millis>-<id>.json`
+fn write_exchange(dir: &Path, exchange: &Exchange) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let millis = exchange.at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let file = dir.join(format!("{}-{:06}.json", millis, exchange.id));
+    std::fs::write(file, exchange.to_value().into_json())
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Recorder {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let recorder = self.clone();
+        Box::pin(async move {
+            if !recorder.every_mode && req.app.get_mode() != RunMode::Development {
+                return next(req).await;
+            }
+            if recorder.read_request_bodies {
+                req.parse_body().await;
+            }
+            let at = SystemTime::now();
+            let started = Instant::now();
+            let mut req = next(req).await;
+            let duration = started.elapsed();
+
+            // The request is read after the handler, which may have read a body left by the recorder
+            let exchange = Exchange {
+                id: recorder.next_id.fetch_add(1, Ordering::Relaxed),
+                at,
+                request_id: req.request_id().to_string(),
+                method: req.method(),
+                url: req.request.meta.url(),
+                route: req.endpoint.route_pattern(),
+                request_headers: recorder.headers(&req.request.meta),
+                request_body: recorder.body(&req.request.body),
+                status: req.response.meta.start_line.status_code().into(),
+                response_headers: recorder.headers(&req.response.meta),
+                response_body: recorder.body(&req.response.body),
+                duration,
+            };
+            if let Some(dir) = recorder.dump_dir.clone() {
+                let exchange = exchange.clone();
+                let written = tokio::task::spawn_blocking(move || write_exchange(&dir, &exchange)).await;
+                if let Ok(Err(e)) = written {
+                    eprintln!("[Recorder] Failed to dump the exchange of request {}: {}", req.request_id(), e);
+                }
+            }
+            recorder.record(exchange);
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_are_cut_on_char_boundaries() {
+        let body = RecordedBody::new("héllo".to_string(), 2);
+        assert_eq!(body.text, "h");
+        assert_eq!(body.size, 6);
+        assert!(body.truncated);
+        assert!(!RecordedBody::new("hello".to_string(), 5).truncated);
+    }
+
+    #[test]
+    fn redacts_fields() {
+        let recorder = Recorder::new().redact_field("Card");
+        let recorded = recorder.body(&HttpBody::Text(r#"{"user":{"password":"hunter2","card":"4242"},"name":"ada"}"#.to_string()));
+        assert!(!recorded.text.contains("hunter2"));
+        assert!(!recorded.text.contains("4242"));
+        assert!(recorded.text.contains("ada"));
+        assert_eq!(recorder.body(&HttpBody::Text("password".to_string())).text, "password");
+    }
+
+    #[test]
+    fn keeps_the_latest_exchanges() {
+        let recorder = Recorder::new().capacity(2);
+        for id in 1..=3 {
+            recorder.record(Exchange {
+                id,
+                at: SystemTime::now(),
+                request_id: String::new(),
+                method: HttpMethod::POST,
+                url: "/login?next=/".to_string(),
+                route: "/login".to_string(),
+                request_headers: vec![
+                    ("host".to_string(), "example.com".to_string()),
+                    ("authorization".to_string(), REDACTED.to_string()),
+                ],
+                request_body: RecordedBody::new("it's".to_string(), 16),
+                status: 200,
+                response_headers: vec![],
+                response_body: RecordedBody::default(),
+                duration: Duration::ZERO,
+            });
+        }
+        let exchanges = recorder.exchanges();
+        assert_eq!(exchanges.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(
+            exchanges[0].to_curl(),
+            r#"curl -X POST 'http://example.com/login?next=/' --data-raw 'it'\''s'"#
+        );
+    }
+}
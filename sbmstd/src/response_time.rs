@@ -0,0 +1,104 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+
+/// Stamps each response with `X-Response-Time`, the time spent in the middleware chain after
+/// this middleware, and with a `Server` header.
+///
+/// Register it first so that the time covers every other middleware.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(ResponseTime::new().server("myapp/1.2"))
+///     .append_middleware::<Cors>();
+/// // X-Response-Time: 3.214ms
+/// // Server: myapp/1.2
+/// ```
+#[derive(Debug, Clone)]
+pub struct ResponseTime {
+    header: String,
+    server: Option<String>,
+}
+
+impl ResponseTime {
+    pub fn new() -> Self {
+        Self {
+            header: "x-response-time".to_string(),
+            server: Some("starberry".to_string()),
+        }
+    }
+
+    /// Sets the value of the `Server` header, `starberry` by default
+    pub fn server(mut self, server: impl Into<String>) -> Self {
+        self.server = Some(server.into());
+        self
+    }
+
+    /// Does not send the `Server` header
+    pub fn hide_server(mut self) -> Self {
+        self.server = None;
+        self
+    }
+
+    /// Sets the name of the header holding the time, `X-Response-Time` by default
+    pub fn header(mut self, header: impl Into<String>) -> Self {
+        self.header = header.into().to_lowercase();
+        self
+    }
+}
+
+impl Default for ResponseTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formats a duration as milliseconds with microsecond precision, e.g. `3.214ms`
+fn format_millis(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl AsyncMiddleware<HttpReqCtx> for ResponseTime {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new()
+    }
+
+    fn handle<'a>(
+        &self,
+        req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let header = self.header.clone();
+        let server = self.server.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let mut req = next(req).await;
+            req.response.meta.set_attribute(header, format_millis(start.elapsed()));
+            if let Some(server) = server {
+                req.response.meta.set_attribute("server", server);
+            }
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_milliseconds() {
+        assert_eq!(format_millis(Duration::from_micros(3214)), "3.214ms");
+        assert_eq!(format_millis(Duration::from_secs(2)), "2000.000ms");
+    }
+}
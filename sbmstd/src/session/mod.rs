@@ -1,6 +1,7 @@
 pub mod session; 
 pub mod cookie_session; 
 pub mod session_counter; 
+pub mod session_limit; 
 
 pub use self::cookie_session::CookieSession; 
 pub use self::cookie_session::CSessionRW; 
@@ -8,4 +9,6 @@ pub use self::cookie_session::CSessionRW;
 pub use self::session::Session; 
 pub use self::session::SessionCont; 
 pub use self::session::SessionRW; 
-pub use self::session::init_session_system; 
+pub use self::session::init_session_system;
+pub use self::session::PRINCIPAL_KEY; 
+pub use self::session_limit::{LimitPolicy, SessionLimit};  
@@ -1,6 +1,8 @@
+use akari::Value;
 use dashmap::DashMap;
+use starberry_core::cache::SharedCache;
 use starberry_core::http::cookie::Cookie;
-use std::sync::atomic::{AtomicU64, Ordering};
+use starberry_lib::uuid::Uuid;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::collections::HashMap;
 use lazy_static::lazy_static;
@@ -10,6 +12,16 @@ use starberry_macro::middleware;
 use starberry_core::app::middleware::AsyncMiddleware; 
 use starberry_core::http::context::HttpReqCtx;  
 
+use crate::auth::auth::{remember_tokens, revoke_series, REMEMBER_SERIES_KEY};
+use crate::session::session_limit::{self, SessionLimit};
+
+/// The session key holding the principal, the id of the user logged in with `Auth`
+pub const PRINCIPAL_KEY: &str = "auth_user_id";
+
+/// Set by `Auth` when a user logs in, for `Session` to move the session to a new id after
+/// the request. An id planted in the browser before the login is then of no use
+pub(crate) struct RenewId;
+
 #[derive(Debug, Clone)]
 pub struct SessionCont {
     pub expiry_time: u64,
@@ -22,15 +34,11 @@ lazy_static! {
 
 static DEFAULT_TTL: u64 = 3600 * 24 * 7; // Default TTL of 7 days  
 
-static SESSION_COUNTER: AtomicU64 = AtomicU64::new(0);
-
+/// A random id, so that the instances sharing a cache do not give out the same ids.
+/// The id 0 is reserved for the default session
 fn generate_session_id() -> u64 {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("time error")
-        .as_millis() as u64;
-    let counter = SESSION_COUNTER.fetch_add(1, Ordering::Relaxed) & 0xFFFF;
-    (timestamp << 16) | counter
+    let bytes = Uuid::new_v4().into_bytes();
+    u64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]).max(1)
 }
 
 pub fn new_session(initial_data: HashMap<String, String>, ttl_secs: u64) -> u64 {
@@ -90,6 +98,10 @@ impl<'a> SessionRW<'a> {
         self.guard.data.insert(key.into(), value.into()); 
     }
 
+    pub fn remove<T: AsRef<str>>(&mut self, key: T) -> Option<String> {
+        self.guard.data.remove(key.as_ref())
+    }
+
     pub fn set_all(&mut self, data: HashMap<String, String>) {
         for (k, v) in data {
             self.guard.data.insert(k, v);
@@ -113,22 +125,158 @@ pub fn get_mut<'a>(id: u64) -> Result<SessionRW<'a>, &'static str> {
     }
 } 
 
+fn cache_key(id: u64) -> String {
+    format!("session:{}", id)
+}
+
+/// Loads a session from the cache of the application into the local store, e.g. one
+/// created by another instance or before a restart. Returns `false` if it is not cached
+async fn load_cached(cache: &SharedCache, id: u64, ttl_secs: u64) -> bool {
+    let Ok(Some(json)) = cache.get_string(&cache_key(id)).await else {
+        return false;
+    };
+    let Ok(Value::Dict(values)) = Value::from_json(&json) else {
+        return false;
+    };
+    let data = values.into_iter().map(|(key, value)| (key, value.string())).collect();
+    let expiry_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + ttl_secs;
+    SESSIONS.entry(id).or_insert(SessionCont { expiry_time, data });
+    true
+}
+
+/// Writes the data of a session to the cache of the application, expiring with the session
+async fn save_cached(cache: &SharedCache, id: u64, data: &HashMap<String, String>, ttl_secs: u64) {
+    let values = data.iter().map(|(key, value)| (key.clone(), Value::Str(value.clone()))).collect();
+    let json = Value::Dict(values).into_json();
+    if let Err(e) = cache.set_string(&cache_key(id), &json, Some(Duration::from_secs(ttl_secs))).await {
+        eprintln!("Failed to cache session {}: {}", id, e);
+    }
+}
+
+/// Moves the session to a new id, keeping its data and its seat under the `SessionLimit`.
+/// Must be called without holding the session
+fn renew_id(id: u64) -> u64 {
+    let Some((_, session)) = SESSIONS.remove(&id) else {
+        return id;
+    };
+    let renewed = generate_session_id();
+    SESSIONS.insert(renewed, session);
+    session_limit::rebind(id, renewed);
+    renewed
+}
+
+/// Whether another session is still logged in as the principal. A session in use by a
+/// request cannot be looked at without waiting for it, and is considered logged in
+fn bound_to(id: u64, principal: &str) -> bool {
+    match SESSIONS.try_get(&id) {
+        dashmap::try_result::TryResult::Present(session) => {
+            session.data.get(PRINCIPAL_KEY).is_some_and(|bound| bound == principal)
+        }
+        dashmap::try_result::TryResult::Absent => false,
+        dashmap::try_result::TryResult::Locked => true,
+    }
+}
+
+/// Applies the `SessionLimit` of the application once the request changed the principal
+/// of its session. Returns the sessions evicted for it. Must be called without holding the session
+fn enforce_limit(req: &mut HttpReqCtx, limit: &SessionLimit, session_id: u64, previous: Option<String>) -> Vec<u64> {
+    let current = get_mut(session_id).ok().and_then(|session| session.get(PRINCIPAL_KEY).cloned());
+    if current == previous {
+        return Vec::new();
+    }
+    if let Some(previous) = previous {
+        limit.release(&previous, session_id);
+    }
+    let Some(current) = current else {
+        return Vec::new();
+    };
+    match limit.admit(&current, session_id, |id| bound_to(id, &current)) {
+        Some(evicted) => evicted,
+        None => {
+            if let Ok(mut session) = get_mut(session_id) {
+                session.remove(PRINCIPAL_KEY);
+            }
+            req.response = limit.reject();
+            Vec::new()
+        }
+    }
+}
+
+/// Revokes the "remember me" tokens issued to an evicted session, which would otherwise log
+/// its browser straight back in
+async fn forget_remembered(tokens: &SharedCache, session: &SessionCont) {
+    if let Some(series) = session.data.get(REMEMBER_SERIES_KEY) {
+        revoke_series(tokens, series).await;
+    }
+}
+
+/// Keeps the sessions in a process-wide store. When the application has a cache, set with
+/// `AppBuilder::cache`, each session is also written to it after the request and read back
+/// by the instances which do not hold it, so that they share their sessions and sessions
+/// survive a restart. A session is given a new id when a user logs in with `Auth`
 #[middleware(HttpReqCtx)] 
 pub async fn Session(){ 
     let ttl = req.app.config().get::<u64>().unwrap_or(&DEFAULT_TTL).clone(); 
+    let cache = req.cache(); 
     let mut session_id: u64 = req.get_cookie_or_default("session_id")
         .get_value()
         .parse()
         .unwrap_or_else(|_| {
             new_session(HashMap::new(), ttl) 
         }); 
+    if session_limit::take_evicted(session_id) {
+        // Ended for a newer session of its principal 
+        if let Some((_, evicted)) = SESSIONS.remove(&session_id) {
+            forget_remembered(&remember_tokens(&req), &evicted).await;
+        }
+        if let Some(cache) = &cache {
+            let _ = cache.delete(&cache_key(session_id)).await;
+        }
+    } else if let Some(cache) = &cache
+        && session_id != 0
+        && !SESSIONS.contains_key(&session_id)
+    {
+        load_cached(cache, session_id, ttl).await;
+    }
     let mut session = get_mut(session_id).unwrap_or_else(|_| { 
         session_id = new_session(HashMap::new(), ttl); 
         get_mut(session_id).unwrap() 
     }); 
     session.touch(ttl); // Refresh session expiration 
+    let principal = session.get(PRINCIPAL_KEY).cloned(); 
     req.params.set(session); 
     let mut req = next(req).await; // Continue middleware chain 
+    if let Some(limit) = req.app.config().get::<SessionLimit>().cloned() {
+        drop(req.params.take::<SessionRW<'static>>()); // Release the session before looking at the others 
+        let tokens = remember_tokens(&req); 
+        for id in enforce_limit(&mut req, &limit, session_id, principal) {
+            // A session in use is dealt with on its next request 
+            let evicted = match SESSIONS.try_get(&id) {
+                dashmap::try_result::TryResult::Present(session) => Some(session.clone()),
+                _ => None,
+            };
+            if let Some(evicted) = evicted {
+                forget_remembered(&tokens, &evicted).await;
+            }
+        }
+    } 
+    if req.params.take::<RenewId>().is_some() {
+        drop(req.params.take::<SessionRW<'static>>()); 
+        let renewed = renew_id(session_id); 
+        if let Some(cache) = &cache
+            && renewed != session_id
+        {
+            let _ = cache.delete(&cache_key(session_id)).await; 
+        }
+        session_id = renewed; 
+    } 
+    if let Some(cache) = &cache {
+        drop(req.params.take::<SessionRW<'static>>()); 
+        let data = get_mut(session_id).ok().map(|session| session.data.clone()); 
+        if let Some(data) = data {
+            save_cached(cache, session_id, &data, ttl).await; 
+        }
+    } 
     req.response = req.response.add_cookie(
         "session_id", 
         Cookie::new(session_id.to_string()) 
@@ -153,3 +301,47 @@ async fn session_cleanup_task(interval_secs: u64) {
 pub fn init_session_system() {
     tokio::spawn(session_cleanup_task(3600));
 } 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use starberry_core::cache::MemoryCache;
+
+    #[tokio::test]
+    async fn sessions_are_shared_through_the_cache() {
+        let cache: SharedCache = Arc::new(MemoryCache::new(16));
+        let id = new_session(HashMap::from([(PRINCIPAL_KEY.to_string(), "42".to_string())]), 60);
+        let data = get_mut(id).unwrap().data.clone();
+        save_cached(&cache, id, &data, 60).await;
+
+        // Another instance, or this one after a restart, does not hold the session
+        SESSIONS.remove(&id);
+        assert!(load_cached(&cache, id, 60).await);
+        assert_eq!(get_mut(id).unwrap().get(PRINCIPAL_KEY).map(String::as_str), Some("42"));
+        assert!(!load_cached(&cache, generate_session_id(), 60).await);
+        SESSIONS.remove(&id);
+    }
+
+    #[test]
+    fn renewed_sessions_keep_their_data() {
+        let id = new_session(HashMap::from([(PRINCIPAL_KEY.to_string(), "7".to_string())]), 60);
+        let renewed = renew_id(id);
+        assert_ne!(renewed, id);
+        assert!(get_mut(id).is_err());
+        assert_eq!(get_mut(renewed).unwrap().get(PRINCIPAL_KEY).map(String::as_str), Some("7"));
+        SESSIONS.remove(&renewed);
+    }
+
+    #[tokio::test]
+    async fn evicted_sessions_lose_their_remember_tokens() {
+        let tokens: SharedCache = Arc::new(MemoryCache::new(16));
+        tokens.set_string("remember:series", "token 42", None).await.unwrap();
+        let session = SessionCont {
+            expiry_time: 0,
+            data: HashMap::from([(REMEMBER_SERIES_KEY.to_string(), "series".to_string())]),
+        };
+        forget_remembered(&tokens, &session).await;
+        assert!(tokens.get_string("remember:series").await.unwrap().is_none());
+    }
+}
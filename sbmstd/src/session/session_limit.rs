@@ -0,0 +1,167 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dashmap::{DashMap, DashSet};
+use lazy_static::lazy_static;
+use starberry_core::http::http_value::{HttpContentType, StatusCode};
+use starberry_core::http::response::{response_templates, HttpResponse};
+
+lazy_static! {
+    /// The sessions bound to each principal, with the instant they were bound in milliseconds.
+    /// Entries of expired or logged out sessions are pruned when the principal logs in again
+    static ref BINDINGS: DashMap<String, Vec<(u64, u64)>> = DashMap::new();
+    /// The sessions evicted for a newer one, dropped by `Session` on their next request
+    static ref EVICTED: DashSet<u64> = DashSet::new();
+}
+
+/// What happens when a principal at the limit logs in once more
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// The sessions logged in the longest ago are ended to make room for the new one, and
+    /// their "remember me" cookies revoked
+    EvictOldest,
+    /// The login is undone and answered with `409 Conflict`
+    RejectNew,
+}
+
+/// Caps the sessions a principal, the user logged in with `Auth`, may have at once, e.g. the
+/// seats of a license. Set it with `AppBuilder::set_config`, it is applied by `Session` once a
+/// request changed the principal of its session. `CookieSession` cannot be limited, as the
+/// sessions held by the clients cannot be ended by the server.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// App::new().set_config(SessionLimit::new(3).reject_new()).build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SessionLimit {
+    max_sessions: usize,
+    policy: LimitPolicy,
+}
+
+impl SessionLimit {
+    /// Allows `max_sessions` sessions per principal, evicting the oldest ones beyond
+    pub fn new(max_sessions: usize) -> Self {
+        Self {
+            max_sessions: max_sessions.max(1),
+            policy: LimitPolicy::EvictOldest,
+        }
+    }
+
+    /// Ends the oldest sessions of the principal to make room for a new one, the default
+    pub fn evict_oldest(mut self) -> Self {
+        self.policy = LimitPolicy::EvictOldest;
+        self
+    }
+
+    /// Refuses new logins while the principal has all its sessions
+    pub fn reject_new(mut self) -> Self {
+        self.policy = LimitPolicy::RejectNew;
+        self
+    }
+
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    pub fn policy(&self) -> LimitPolicy {
+        self.policy
+    }
+
+    /// Binds the session to the principal if the policy allows it. `alive` tells whether
+    /// another session is still logged in as the principal. Returns the sessions evicted to
+    /// make room for it, or `None` if rejected
+    pub(crate) fn admit(&self, principal: &str, session_id: u64, alive: impl Fn(u64) -> bool) -> Option<Vec<u64>> {
+        let mut bound = BINDINGS.entry(principal.to_string()).or_default();
+        bound.retain(|(id, _)| *id != session_id && alive(*id));
+        let mut evicted = Vec::new();
+        if bound.len() >= self.max_sessions {
+            if self.policy == LimitPolicy::RejectNew {
+                return None;
+            }
+            bound.sort_by_key(|(_, since)| *since);
+            let excess = bound.len() + 1 - self.max_sessions;
+            for (id, _) in bound.drain(..excess) {
+                EVICTED.insert(id);
+                evicted.push(id);
+            }
+        }
+        bound.push((session_id, now_millis()));
+        Some(evicted)
+    }
+
+    /// Unbinds the session from the principal, after a logout
+    pub(crate) fn release(&self, principal: &str, session_id: u64) {
+        if let Some(mut bound) = BINDINGS.get_mut(principal) {
+            bound.retain(|(id, _)| *id != session_id);
+        }
+        BINDINGS.remove_if(principal, |_, bound| bound.is_empty());
+    }
+
+    /// The response to a rejected login
+    pub(crate) fn reject(&self) -> HttpResponse {
+        response_templates::normal_response(StatusCode::CONFLICT, "Too many sessions")
+            .content_type(HttpContentType::TextPlain())
+    }
+}
+
+/// Moves the seat of a session to the new id it was given on login
+pub(crate) fn rebind(old_id: u64, new_id: u64) {
+    for mut bound in BINDINGS.iter_mut() {
+        for (id, _) in bound.iter_mut().filter(|(id, _)| *id == old_id) {
+            *id = new_id;
+        }
+    }
+}
+
+/// Whether the session was evicted, forgetting it
+pub(crate) fn take_evicted(session_id: u64) -> bool {
+    EVICTED.remove(&session_id).is_some()
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time error")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_oldest_sessions() {
+        let limit = SessionLimit::new(2);
+        assert_eq!(limit.admit("evict-alice", 1, |_| true), Some(vec![]));
+        assert_eq!(limit.admit("evict-alice", 2, |_| true), Some(vec![]));
+        assert_eq!(limit.admit("evict-alice", 3, |_| true), Some(vec![1]));
+        assert!(take_evicted(1));
+        assert!(!take_evicted(2));
+        // A session logging in again is not counted twice
+        assert_eq!(limit.admit("evict-alice", 3, |_| true), Some(vec![]));
+        assert!(!take_evicted(2));
+    }
+
+    #[test]
+    fn rejects_new_sessions() {
+        let limit = SessionLimit::new(1).reject_new();
+        assert!(limit.admit("reject-bob", 10, |_| true).is_some());
+        assert!(limit.admit("reject-bob", 11, |_| true).is_none());
+        assert!(!take_evicted(10));
+        // Ended sessions free their seat
+        assert!(limit.admit("reject-bob", 11, |_| false).is_some());
+        limit.release("reject-bob", 11);
+        assert!(limit.admit("reject-bob", 12, |_| true).is_some());
+    }
+
+    #[test]
+    fn renewed_sessions_keep_their_seat() {
+        let limit = SessionLimit::new(1).reject_new();
+        assert!(limit.admit("renew-carol", 20, |_| true).is_some());
+        rebind(20, 21);
+        assert!(limit.admit("renew-carol", 22, |id| id == 21).is_none());
+        limit.release("renew-carol", 21);
+        assert!(limit.admit("renew-carol", 22, |_| true).is_some());
+    }
+}
@@ -0,0 +1,113 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::{HttpReqCtx, RequestTiming};
+use starberry_core::http::http_value::HttpMethod;
+
+/// A request which took longer than the threshold of `SlowRequests`
+#[derive(Debug, Clone)]
+pub struct SlowRequest {
+    pub method: HttpMethod,
+    pub path: String,
+    /// The pattern of the matched route, e.g. `/user/{id}`
+    pub route: String,
+    pub request_id: String,
+    pub status: u16,
+    pub timing: RequestTiming,
+}
+
+impl std::fmt::Display for SlowRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[Slow Request] {} {} (route {}, id {}) -> {} in {:?} (read {:?}, handle {:?}, write {:?})",
+            self.method,
+            self.path,
+            self.route,
+            self.request_id,
+            self.status,
+            self.timing.total(),
+            self.timing.read,
+            self.timing.handle,
+            self.timing.write,
+        )
+    }
+}
+
+type SlowHook = Arc<dyn Fn(&SlowRequest) + Send + Sync>;
+
+/// Logs the requests taking longer than a threshold, from the start of the read of the
+/// request to the end of the write of the response.
+///
+/// By default the requests are printed to stderr, `on_slow` replaces it to emit metrics or use another logger.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// ProtocolBuilder::<HttpReqCtx>::new()
+///     .add_middleware(SlowRequests::new(Duration::from_millis(500)).on_slow(|slow| {
+///         metrics.slow_requests(&slow.route).increment();
+///         eprintln!("{}", slow);
+///     }));
+/// ```
+pub struct SlowRequests {
+    threshold: Duration,
+    hook: SlowHook,
+}
+
+impl SlowRequests {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            hook: Arc::new(|slow| eprintln!("{}", slow)),
+        }
+    }
+
+    /// Calls the hook with each slow request instead of printing it
+    pub fn on_slow<F: Fn(&SlowRequest) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.hook = Arc::new(hook);
+        self
+    }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for SlowRequests {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Reports the requests slower than one second
+    fn return_self() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let threshold = self.threshold;
+        let hook = self.hook.clone();
+        let method = req.method();
+        let path = req.path();
+        let route = req.endpoint.route_pattern();
+        let request_id = req.request_id().to_string();
+        req.on_sent(move |response, timing| {
+            if timing.total() < threshold {
+                return;
+            }
+            hook(&SlowRequest {
+                method,
+                path,
+                route,
+                request_id,
+                status: response.meta.start_line.status_code().into(),
+                timing: *timing,
+            });
+        });
+        Box::pin(async move { next(req).await })
+    }
+}
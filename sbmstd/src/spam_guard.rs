@@ -0,0 +1,407 @@
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::error::IntoResponse;
+use starberry_core::http::http_value::{HttpContentType, HttpMethod, StatusCode};
+use starberry_core::http::response::{response_templates, HttpResponse};
+use starberry_lib::encoding::hex_encode;
+use starberry_lib::ende::signing::{constant_time_eq, sign_hmac_sha256};
+
+/// The hidden field holding the signed time at which the form was rendered
+pub const FORM_TIME_FIELD: &str = "_form_time";
+
+/// Above this number of tracked clients, those without recent submission are forgotten
+const MAX_TRACKED: usize = 10_000;
+
+/// The defenses of the forms under a route
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormRules {
+    /// A field hidden from humans, which bots fill in
+    honeypot: Option<String>,
+    /// How long a human needs at least to fill the form in
+    min_time: Option<Duration>,
+    /// How long a rendered form may be submitted
+    max_age: Option<Duration>,
+    /// The number of submissions allowed per client in a window
+    throttle: Option<(usize, Duration)>,
+}
+
+impl FormRules {
+    /// A honeypot named `website`, a minimum time of 3 seconds and forms valid for a day
+    pub fn new() -> Self {
+        Self {
+            honeypot: Some("website".to_string()),
+            min_time: Some(Duration::from_secs(3)),
+            max_age: Some(Duration::from_secs(24 * 60 * 60)),
+            throttle: None,
+        }
+    }
+
+    /// No defense, to be enabled one by one
+    pub fn none() -> Self {
+        Self { honeypot: None, min_time: None, max_age: None, throttle: None }
+    }
+
+    /// Sets the name of the honeypot field. A name a bot wants to fill in works best
+    pub fn honeypot(mut self, field: impl Into<String>) -> Self {
+        self.honeypot = Some(field.into());
+        self
+    }
+
+    pub fn no_honeypot(mut self) -> Self {
+        self.honeypot = None;
+        self
+    }
+
+    /// Rejects the forms submitted sooner after being rendered. The signed time of rendering
+    /// is then required, so the form must contain `SpamGuard::fields`
+    pub fn min_time(mut self, min_time: Duration) -> Self {
+        self.min_time = Some(min_time);
+        self
+    }
+
+    /// Rejects the forms rendered longer ago, so a rendered form cannot be replayed forever
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Allows a client `max` submissions per `window`, whether they were accepted or not
+    pub fn throttle(mut self, max: usize, window: Duration) -> Self {
+        self.throttle = Some((max, window));
+        self
+    }
+
+    /// Whether the form must carry the time it was rendered at
+    fn is_timed(&self) -> bool {
+        self.min_time.is_some() || self.max_age.is_some()
+    }
+}
+
+impl Default for FormRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why a submission was taken for spam
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpamError {
+    /// The honeypot field was filled in
+    Honeypot,
+    /// The time of rendering is missing or was tampered with
+    InvalidTime,
+    /// The form was submitted faster than a human could
+    TooFast,
+    /// The form was rendered too long ago
+    Expired,
+    /// The client submitted too many forms, it may try again after the duration
+    Throttled(Duration),
+}
+
+impl fmt::Display for SpamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Honeypot => write!(f, "Honeypot filled in"),
+            Self::InvalidTime => write!(f, "Missing or invalid form time"),
+            Self::TooFast => write!(f, "Form submitted too fast"),
+            Self::Expired => write!(f, "Form expired"),
+            Self::Throttled(retry) => write!(f, "Too many submissions, retry in {}s", retry.as_secs()),
+        }
+    }
+}
+
+impl std::error::Error for SpamError {}
+
+/// A throttled client gets `429 Too Many Requests`, an expired form `400 Bad Request` so the
+/// page can be reloaded, and any other spam `403 Forbidden`
+impl IntoResponse for SpamError {
+    fn into_response(self) -> HttpResponse {
+        let response = match &self {
+            Self::Throttled(_) => response_templates::normal_response(StatusCode::TOO_MANY_REQUESTS, "Too Many Requests"),
+            Self::Expired => response_templates::normal_response(StatusCode::BAD_REQUEST, "Form expired, please reload the page"),
+            _ => response_templates::normal_response(StatusCode::FORBIDDEN, "Forbidden"),
+        };
+        let response = response.content_type(HttpContentType::TextPlain());
+        match self {
+            Self::Throttled(retry) => response.add_header("retry-after", retry.as_secs().max(1).to_string()),
+            _ => response,
+        }
+    }
+}
+
+/// Protects forms from spam without a third-party captcha: a honeypot field, a minimum time
+/// to fill the form in and a limit of submissions per client, set for each form route.
+///
+/// Only the `POST`, `PUT`, `PATCH` and `DELETE` requests under the routes are checked, with
+/// the rules of the longest matching prefix. The forms must contain the hidden fields written
+/// by `fields`, which carry the time of rendering signed with the secret of the guard, so a
+/// bot cannot make it up. The clients are told apart by `HttpReqCtx::client_ip`, and those
+/// whose address is unknown are not throttled.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// static SPAM_GUARD: Lazy<SpamGuard> = Lazy::new(|| {
+///     SpamGuard::new(std::env::var("FORM_SECRET").unwrap())
+///         .route("/contact", FormRules::new().throttle(5, Duration::from_secs(3600)))
+///         .route("/comments", FormRules::none().honeypot("url").min_time(Duration::from_secs(5)))
+/// });
+/// ProtocolBuilder::<HttpReqCtx>::new().add_middleware(SPAM_GUARD.clone());
+///
+/// // In the handler rendering the form, written with -[ spam_fields ]- inside the form
+/// akari_render!("contact.html", spam_fields = SPAM_GUARD.fields("/contact"))
+/// ```
+#[derive(Clone)]
+pub struct SpamGuard {
+    secret: Arc<Vec<u8>>,
+    /// Without trailing slash
+    routes: Vec<(String, FormRules)>,
+    /// The times of the recent submissions of each client to each route
+    submissions: Arc<DashMap<(String, IpAddr), Vec<Instant>>>,
+}
+
+impl SpamGuard {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: Arc::new(secret.as_ref().to_vec()),
+            routes: Vec::new(),
+            submissions: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Guards the forms under the prefix with the rules. The longest matching prefix wins
+    pub fn route(mut self, prefix: impl Into<String>, rules: FormRules) -> Self {
+        self.routes.push((prefix.into().trim_end_matches('/').to_string(), rules));
+        self
+    }
+
+    /// The prefix and rules applying to the path, `None` if it is not guarded
+    pub fn rules_for(&self, path: &str) -> Option<(&str, &FormRules)> {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, rules)| (prefix.as_str(), rules))
+    }
+
+    /// The hidden fields to put in the form posted to `path`: the honeypot, positioned out of
+    /// sight rather than hidden as some bots skip hidden fields, and the signed time of rendering
+    pub fn fields(&self, path: &str) -> String {
+        let Some((_, rules)) = self.rules_for(path) else {
+            return String::new();
+        };
+        let mut html = String::new();
+        if let Some(honeypot) = &rules.honeypot {
+            html.push_str(&format!(
+                "<div style=\"position:absolute;left:-10000px\" aria-hidden=\"true\">\
+                 <input type=\"text\" name=\"{}\" tabindex=\"-1\" autocomplete=\"off\"></div>",
+                honeypot.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;")
+            ));
+        }
+        if rules.is_timed() {
+            html.push_str(&format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                FORM_TIME_FIELD,
+                self.stamp(SystemTime::now())
+            ));
+        }
+        html
+    }
+
+    /// The time of rendering with its signature, `<unix seconds>.<hex hmac>`
+    fn stamp(&self, now: SystemTime) -> String {
+        let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().to_string();
+        format!("{}.{}", seconds, hex_encode(sign_hmac_sha256(&self.secret, seconds.as_bytes())))
+    }
+
+    /// The time of a stamp whose signature is valid
+    fn stamped_time(&self, stamp: &str) -> Option<SystemTime> {
+        let (seconds, signature) = stamp.split_once('.')?;
+        let expected = hex_encode(sign_hmac_sha256(&self.secret, seconds.as_bytes()));
+        if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+            return None;
+        }
+        Some(UNIX_EPOCH + Duration::from_secs(seconds.parse().ok()?))
+    }
+
+    /// Checks the fields of a submitted form, `field` giving the value of a field
+    pub fn check_fields<'a, F>(&self, rules: &FormRules, field: F, now: SystemTime) -> Result<(), SpamError>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        if let Some(honeypot) = &rules.honeypot
+            && field(honeypot).is_some_and(|value| !value.trim().is_empty())
+        {
+            return Err(SpamError::Honeypot);
+        }
+        if !rules.is_timed() {
+            return Ok(());
+        }
+        let rendered = field(FORM_TIME_FIELD).and_then(|stamp| self.stamped_time(stamp)).ok_or(SpamError::InvalidTime)?;
+        // A time in the future has a zero age, and is too fast if a minimum time is set
+        let age = now.duration_since(rendered).unwrap_or_default();
+        if rules.min_time.is_some_and(|min_time| age < min_time) {
+            return Err(SpamError::TooFast);
+        }
+        if rules.max_age.is_some_and(|max_age| age > max_age) {
+            return Err(SpamError::Expired);
+        }
+        Ok(())
+    }
+
+    /// Records a submission of the client to the route, failing if it made too many
+    pub fn record_submission(&self, route: &str, ip: IpAddr, rules: &FormRules, now: Instant) -> Result<(), SpamError> {
+        let Some((max, window)) = rules.throttle else {
+            return Ok(());
+        };
+        if self.submissions.len() > MAX_TRACKED {
+            self.submissions.retain(|_, times| times.last().is_some_and(|last| now.duration_since(*last) < window));
+        }
+        let mut times = self.submissions.entry((route.to_string(), ip)).or_default();
+        times.retain(|time| now.duration_since(*time) < window);
+        if times.len() >= max {
+            let retry = times.first().map_or(window, |oldest| window.saturating_sub(now.duration_since(*oldest)));
+            return Err(SpamError::Throttled(retry));
+        }
+        times.push(now);
+        Ok(())
+    }
+
+    /// Checks a request submitting a form to a guarded route, url-encoded or multipart
+    pub async fn check_request(&self, req: &mut HttpReqCtx) -> Result<(), SpamError> {
+        let path = req.path();
+        let Some((route, rules)) = self.rules_for(&path) else {
+            return Ok(());
+        };
+        if let Some(ip) = req.client_ip() {
+            self.record_submission(route, ip, rules, Instant::now())?;
+        }
+        let mut names: Vec<&str> = rules.honeypot.iter().map(String::as_str).collect();
+        if rules.is_timed() {
+            names.push(FORM_TIME_FIELD);
+        }
+        let mut values = Vec::new();
+        for name in names {
+            values.push((name, form_field(req, name).await));
+        }
+        let field = |name: &str| values.iter().find(|(key, _)| *key == name).and_then(|(_, value)| value.as_deref());
+        self.check_fields(rules, field, SystemTime::now())
+    }
+}
+
+impl fmt::Debug for SpamGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpamGuard")
+            .field("routes", &self.routes)
+            .field("tracked_clients", &self.submissions.len())
+            .finish()
+    }
+}
+
+/// A text field of the url-encoded or multipart body
+async fn form_field(req: &mut HttpReqCtx, name: &str) -> Option<String> {
+    if let Some(value) = req.form().await.and_then(|form| form.get(name)) {
+        return Some(value.clone());
+    }
+    req.files().await.and_then(|form| form.get_text(name)).cloned()
+}
+
+impl AsyncMiddleware<HttpReqCtx> for SpamGuard {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Without routes nothing is guarded, use `SpamGuard::new` instead
+    fn return_self() -> Self {
+        Self::new(starberry_lib::random_alphanumeric_string(32))
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let guard = self.clone();
+        Box::pin(async move {
+            let submits = matches!(req.method(), HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH | HttpMethod::DELETE);
+            if submits
+                && let Err(e) = guard.check_request(&mut req).await
+            {
+                req.response = e.into_response();
+                return req;
+            }
+            next(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<&'a str> {
+        move |name| pairs.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+    }
+
+    #[test]
+    fn checks_the_honeypot_and_the_time() {
+        let guard = SpamGuard::new("secret").route("/contact", FormRules::new());
+        let (_, rules) = guard.rules_for("/contact/send").unwrap();
+        let rendered = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let stamp = guard.stamp(rendered);
+        let later = rendered + Duration::from_secs(10);
+        assert_eq!(guard.check_fields(rules, form(&[("website", ""), (FORM_TIME_FIELD, stamp.as_str())]), later), Ok(()));
+        assert_eq!(
+            guard.check_fields(rules, form(&[("website", "http://spam"), (FORM_TIME_FIELD, stamp.as_str())]), later),
+            Err(SpamError::Honeypot)
+        );
+        assert_eq!(guard.check_fields(rules, form(&[(FORM_TIME_FIELD, stamp.as_str())]), rendered + Duration::from_secs(1)), Err(SpamError::TooFast));
+        assert_eq!(guard.check_fields(rules, form(&[(FORM_TIME_FIELD, stamp.as_str())]), rendered + Duration::from_secs(2 * 86400)), Err(SpamError::Expired));
+        assert_eq!(guard.check_fields(rules, form(&[]), later), Err(SpamError::InvalidTime));
+        let forged = stamp.replace("1700000000", "1600000000");
+        assert_eq!(guard.check_fields(rules, form(&[(FORM_TIME_FIELD, forged.as_str())]), later), Err(SpamError::InvalidTime));
+        assert_eq!(SpamGuard::new("other").check_fields(rules, form(&[(FORM_TIME_FIELD, stamp.as_str())]), later), Err(SpamError::InvalidTime));
+    }
+
+    #[test]
+    fn throttles_each_client() {
+        let rules = FormRules::none().throttle(2, Duration::from_secs(60));
+        let guard = SpamGuard::new("secret").route("/comments", rules.clone());
+        let (a, b): (IpAddr, IpAddr) = ("203.0.113.1".parse().unwrap(), "203.0.113.2".parse().unwrap());
+        let now = Instant::now();
+        assert!(guard.record_submission("/comments", a, &rules, now).is_ok());
+        assert!(guard.record_submission("/comments", a, &rules, now + Duration::from_secs(10)).is_ok());
+        assert_eq!(
+            guard.record_submission("/comments", a, &rules, now + Duration::from_secs(20)),
+            Err(SpamError::Throttled(Duration::from_secs(40)))
+        );
+        assert!(guard.record_submission("/comments", b, &rules, now + Duration::from_secs(20)).is_ok());
+        assert!(guard.record_submission("/comments", a, &rules, now + Duration::from_secs(61)).is_ok());
+    }
+
+    #[test]
+    fn writes_the_fields_of_guarded_routes() {
+        let guard = SpamGuard::new("secret")
+            .route("/contact", FormRules::new().honeypot("url"))
+            .route("/contact/quick", FormRules::none());
+        let fields = guard.fields("/contact");
+        assert!(fields.contains("name=\"url\" tabindex=\"-1\""));
+        assert!(fields.contains(&format!("name=\"{}\"", FORM_TIME_FIELD)));
+        assert_eq!(guard.fields("/contact/quick"), "");
+        assert_eq!(guard.fields("/about"), "");
+        let response = SpamError::Throttled(Duration::from_secs(30)).into_response();
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,707 @@
+//! Resumable uploads with the tus protocol 1.0.0, <https://tus.io/protocols/resumable-upload>.
+//!
+//! A client creates an upload with a `POST` to the collection, e.g. `/files`, then sends
+//! the bytes with `PATCH` requests to the upload, e.g. `/files/<id>`, each starting at the
+//! `Upload-Offset` returned by a `HEAD` request. An interrupted transfer is resumed from the
+//! last byte received. The `creation`, `creation-with-upload`, `expiration` and
+//! `termination` extensions are supported.
+//!
+//! The uploads are kept in an `UploadStore`: `MemoryUploadStore` for tests, `DirUploadStore`
+//! for files on the disk, or any other storage implementing the trait. With `Tus::storage`,
+//! complete uploads are moved to a `Storage` such as S3, the upload store only holding them
+//! while they are received.
+//!
+//! Browsers only let scripts read the `Location`, `Upload-Offset`, `Upload-Length` and
+//! `Tus-*` headers if they are exposed by the CORS settings.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use akari::Value;
+use starberry_core::app::middleware::{AsyncMiddleware, BoxFuture};
+use starberry_core::app::storage::SharedStorage;
+use starberry_core::http::body::HttpBody;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{HttpMethod, StatusCode};
+use starberry_core::http::response::HttpResponse;
+use starberry_core::http::response::response_templates::normal_response;
+use starberry_core::http::safety::HttpSafety;
+use starberry_lib::date::{format_http_date, format_rfc3339, parse_rfc3339};
+use starberry_lib::encoding::{base64_decode, base64_encode};
+use tokio::io::AsyncReadExt;
+
+/// The version of the protocol implemented
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// The content type of the bodies of `PATCH` requests
+pub const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+const EXTENSIONS: &str = "creation,creation-with-upload,expiration,termination";
+
+/// The bytes of a `PATCH` are handed to the store in chunks of this size
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// An upload, complete once `offset` reaches `length`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upload {
+    pub id: String,
+    /// The size of the whole upload in bytes
+    pub length: u64,
+    /// The number of bytes received
+    pub offset: u64,
+    /// The `Upload-Metadata` sent at creation, e.g. the name of the file
+    pub metadata: HashMap<String, String>,
+    /// The time after which an unfinished upload is discarded
+    pub expires: Option<SystemTime>,
+}
+
+impl Upload {
+    pub fn is_complete(&self) -> bool {
+        self.offset >= self.length
+    }
+
+    fn is_expired(&self) -> bool {
+        !self.is_complete() && self.expires.is_some_and(|expires| expires <= SystemTime::now())
+    }
+}
+
+/// Keeps the uploads and their bytes.
+///
+/// `append` writes `data` at `offset`, which is always the current offset of the upload,
+/// and returns the new offset.
+pub trait UploadStore: Send + Sync + 'static {
+    fn create(&self, upload: Upload) -> BoxFuture<io::Result<()>>;
+    fn get(&self, id: String) -> BoxFuture<io::Result<Option<Upload>>>;
+    fn append(&self, id: String, offset: u64, data: Vec<u8>) -> BoxFuture<io::Result<u64>>;
+    fn delete(&self, id: String) -> BoxFuture<io::Result<()>>;
+
+    /// Copies the bytes of an upload to `key` of a `Storage`, used by `Tus::storage`
+    fn copy_to(&self, _id: String, _storage: SharedStorage, _key: String) -> BoxFuture<io::Result<()>> {
+        Box::pin(async { Err(io::Error::new(io::ErrorKind::Unsupported, "The upload store cannot copy uploads")) })
+    }
+}
+
+/// The uploads of a `MemoryUploadStore` with the bytes received, by id
+type MemoryUploads = HashMap<String, (Upload, Vec<u8>)>;
+
+/// Keeps the uploads in memory, mostly for tests
+#[derive(Clone, Default)]
+pub struct MemoryUploadStore {
+    uploads: Arc<Mutex<MemoryUploads>>,
+}
+
+impl MemoryUploadStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bytes received for an upload
+    pub fn data(&self, id: &str) -> Option<Vec<u8>> {
+        self.uploads.lock().unwrap_or_else(|e| e.into_inner()).get(id).map(|(_, data)| data.clone())
+    }
+}
+
+impl UploadStore for MemoryUploadStore {
+    fn create(&self, upload: Upload) -> BoxFuture<io::Result<()>> {
+        self.uploads.lock().unwrap_or_else(|e| e.into_inner()).insert(upload.id.clone(), (upload, Vec::new()));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn get(&self, id: String) -> BoxFuture<io::Result<Option<Upload>>> {
+        let upload = self.uploads.lock().unwrap_or_else(|e| e.into_inner()).get(&id).map(|(upload, _)| upload.clone());
+        Box::pin(async move { Ok(upload) })
+    }
+
+    fn append(&self, id: String, offset: u64, data: Vec<u8>) -> BoxFuture<io::Result<u64>> {
+        let result = match self.uploads.lock().unwrap_or_else(|e| e.into_inner()).get_mut(&id) {
+            Some((upload, bytes)) if upload.offset == offset => {
+                bytes.extend_from_slice(&data);
+                upload.offset += data.len() as u64;
+                Ok(upload.offset)
+            }
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "Offset mismatch")),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "Unknown upload")),
+        };
+        Box::pin(async move { result })
+    }
+
+    fn delete(&self, id: String) -> BoxFuture<io::Result<()>> {
+        self.uploads.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn copy_to(&self, id: String, storage: SharedStorage, key: String) -> BoxFuture<io::Result<()>> {
+        let data = self.data(&id);
+        Box::pin(async move {
+            let data = data.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Unknown upload"))?;
+            storage.put(key, data).await
+        })
+    }
+}
+
+/// Keeps each upload in a directory: the bytes in `<id>.bin` and the details in `<id>.json`.
+/// The offset is the size of the `.bin` file, so bytes written before a crash are kept
+#[derive(Debug, Clone)]
+pub struct DirUploadStore {
+    dir: PathBuf,
+}
+
+impl DirUploadStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The file holding the bytes of an upload, to be read or moved once it is complete
+    pub fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", id))
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Deletes the unfinished uploads which have expired, returning their number.
+    /// Meant to be scheduled, e.g. every hour with `APP.schedule`
+    pub async fn purge_expired(&self) -> io::Result<usize> {
+        let mut purged = 0;
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+                continue;
+            };
+            if let Some(upload) = self.read(&id).await?
+                && upload.is_expired()
+            {
+                self.remove(&id).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    async fn read(&self, id: &str) -> io::Result<Option<Upload>> {
+        let info = match tokio::fs::read_to_string(self.info_path(id)).await {
+            Ok(info) => info,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid upload info");
+        let Ok(Value::Dict(info)) = Value::from_json(&info) else {
+            return Err(invalid());
+        };
+        let length = match info.get("length") {
+            Some(Value::Str(length)) => length.parse().map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+        let expires = match info.get("expires") {
+            Some(Value::Str(expires)) => Some(parse_rfc3339(expires).ok_or_else(invalid)?),
+            _ => None,
+        };
+        let metadata = match info.get("metadata") {
+            Some(Value::Dict(metadata)) => metadata
+                .iter()
+                .filter_map(|(key, value)| match value {
+                    Value::Str(value) => Some((key.clone(), value.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+        let offset = tokio::fs::metadata(self.path(id)).await.map(|m| m.len()).unwrap_or(0);
+        Ok(Some(Upload { id: id.to_string(), length, offset, metadata, expires }))
+    }
+
+    async fn remove(&self, id: &str) -> io::Result<()> {
+        for path in [self.path(id), self.info_path(id)] {
+            match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UploadStore for DirUploadStore {
+    fn create(&self, upload: Upload) -> BoxFuture<io::Result<()>> {
+        let store = self.clone();
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&store.dir).await?;
+            let mut info = HashMap::new();
+            info.insert("length".to_string(), Value::Str(upload.length.to_string()));
+            if let Some(expires) = upload.expires {
+                info.insert("expires".to_string(), Value::Str(format_rfc3339(expires)));
+            }
+            let metadata = upload.metadata.iter().map(|(k, v)| (k.clone(), Value::Str(v.clone()))).collect();
+            info.insert("metadata".to_string(), Value::Dict(metadata));
+            tokio::fs::write(store.path(&upload.id), b"").await?;
+            tokio::fs::write(store.info_path(&upload.id), Value::Dict(info).into_json()).await
+        })
+    }
+
+    fn get(&self, id: String) -> BoxFuture<io::Result<Option<Upload>>> {
+        let store = self.clone();
+        Box::pin(async move { store.read(&id).await })
+    }
+
+    fn append(&self, id: String, offset: u64, data: Vec<u8>) -> BoxFuture<io::Result<u64>> {
+        let store = self.clone();
+        Box::pin(async move {
+            use tokio::io::AsyncWriteExt;
+            let mut file = tokio::fs::OpenOptions::new().append(true).open(store.path(&id)).await?;
+            if file.metadata().await?.len() != offset {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Offset mismatch"));
+            }
+            file.write_all(&data).await?;
+            file.flush().await?;
+            Ok(offset + data.len() as u64)
+        })
+    }
+
+    fn delete(&self, id: String) -> BoxFuture<io::Result<()>> {
+        let store = self.clone();
+        Box::pin(async move { store.remove(&id).await })
+    }
+
+    /// Hands the file to the storage, which copies it without reading it in memory if it can
+    fn copy_to(&self, id: String, storage: SharedStorage, key: String) -> BoxFuture<io::Result<()>> {
+        storage.put_file(key, self.path(&id))
+    }
+}
+
+/// Parses `Upload-Metadata`: comma separated pairs of a key and an optional base64 value
+pub fn parse_metadata(header: &str) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    for pair in header.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let mut parts = pair.split(' ');
+        let key = parts.next()?;
+        let value = match parts.next() {
+            Some(value) => String::from_utf8(base64_decode(value).ok()?).ok()?,
+            None => String::new(),
+        };
+        if parts.next().is_some() || metadata.insert(key.to_string(), value).is_some() {
+            return None;
+        }
+    }
+    Some(metadata)
+}
+
+/// Writes `Upload-Metadata`, sorted by key
+pub fn format_metadata(metadata: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<String> = metadata
+        .iter()
+        .map(|(key, value)| if value.is_empty() { key.clone() } else { format!("{} {}", key, base64_encode(value)) })
+        .collect();
+    pairs.sort();
+    pairs.join(",")
+}
+
+type CompleteHook = Arc<dyn Fn(Upload) -> BoxFuture<()> + Send + Sync>;
+
+/// Serves resumable uploads under a path, `/files` by default, see the module documentation.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let store = DirUploadStore::new("uploads");
+/// ProtocolBuilder::<HttpReqCtx>::new().add_middleware(
+///     Tus::new()
+///         .path("/uploads")
+///         .store(store.clone())
+///         .max_size(4 << 30)
+///         .on_complete(move |upload| {
+///             let store = store.clone();
+///             async move { process(upload.metadata.get("filename"), store.path(&upload.id)).await }
+///         }),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct Tus {
+    path: String,
+    store: Arc<dyn UploadStore>,
+    max_size: Option<u64>,
+    expire_after: Option<Duration>,
+    on_complete: Option<CompleteHook>,
+    /// The storage complete uploads are moved to, with the prefix of their keys
+    storage: Option<(SharedStorage, String)>,
+}
+
+impl Tus {
+    /// Serves uploads at `/files`, kept in memory and expiring after a day
+    pub fn new() -> Self {
+        Self {
+            path: "/files".to_string(),
+            store: Arc::new(MemoryUploadStore::new()),
+            max_size: None,
+            expire_after: Some(Duration::from_secs(24 * 3600)),
+            on_complete: None,
+            storage: None,
+        }
+    }
+
+    /// Sets the path of the collection, the uploads are served below it
+    pub fn path<T: Into<String>>(mut self, path: T) -> Self {
+        self.path = format!("/{}", path.into().trim_matches('/'));
+        self
+    }
+
+    pub fn store<S: UploadStore>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Refuses uploads larger than `bytes`
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Discards unfinished uploads after `duration`
+    pub fn expire_after(mut self, duration: Duration) -> Self {
+        self.expire_after = Some(duration);
+        self
+    }
+
+    /// Keeps unfinished uploads forever
+    pub fn never_expire(mut self) -> Self {
+        self.expire_after = None;
+        self
+    }
+
+    /// Moves each complete upload to `storage` under `<prefix><id>`, e.g. `uploads/<id>` with
+    /// the prefix `uploads/`, and deletes it from the upload store. The `on_complete` hook runs
+    /// once the upload is moved, and not if moving it failed
+    pub fn storage<T: Into<String>>(mut self, storage: SharedStorage, prefix: T) -> Self {
+        self.storage = Some((storage, prefix.into()));
+        self
+    }
+
+    /// Runs `hook` once the last byte of an upload is received
+    pub fn on_complete<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(Upload) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_complete = Some(Arc::new(move |upload| -> BoxFuture<()> { Box::pin(hook(upload)) }));
+        self
+    }
+
+    /// `Some(None)` for the collection, `Some(Some(id))` for an upload, `None` for other paths
+    fn target<'a>(&self, path: &'a str) -> Option<Option<&'a str>> {
+        let rest = path.strip_prefix(self.path.as_str())?;
+        match rest.trim_start_matches('/') {
+            "" => Some(None),
+            // Ids are base64url tokens, anything else could escape the directory of a store
+            id if rest.starts_with('/') && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') => {
+                Some(Some(id))
+            }
+            _ => None,
+        }
+    }
+
+    async fn serve(&self, req: &mut HttpReqCtx, id: Option<String>) -> HttpResponse {
+        let method = match req.meta().get_header("x-http-method-override") {
+            Some(method) => HttpMethod::from_string(method.trim()),
+            None => req.method(),
+        };
+        if method == HttpMethod::OPTIONS {
+            let mut response = empty(StatusCode::NO_CONTENT)
+                .add_header("tus-version", TUS_VERSION)
+                .add_header("tus-extension", EXTENSIONS);
+            if let Some(max_size) = self.max_size {
+                response = response.add_header("tus-max-size", max_size.to_string());
+            }
+            return response;
+        }
+        if req.meta().get_header("tus-resumable").as_deref().map(str::trim) != Some(TUS_VERSION) {
+            return empty(StatusCode::PRECONDITION_FAILED).add_header("tus-version", TUS_VERSION);
+        }
+        let result = match (id, method) {
+            (None, HttpMethod::POST) => self.create(req).await,
+            (Some(id), HttpMethod::HEAD) => self.head(id).await,
+            (Some(id), HttpMethod::PATCH) => self.patch(req, id).await,
+            (Some(id), HttpMethod::DELETE) => self.terminate(id).await,
+            _ => Err(StatusCode::METHOD_NOT_ALLOWED),
+        };
+        result.unwrap_or_else(empty)
+    }
+
+    async fn create(&self, req: &mut HttpReqCtx) -> Result<HttpResponse, StatusCode> {
+        let length: u64 = req
+            .meta()
+            .get_header("upload-length")
+            .and_then(|length| length.trim().parse().ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        if self.max_size.is_some_and(|max| length > max) {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        let metadata = match req.meta().get_header("upload-metadata") {
+            Some(header) => parse_metadata(&header).ok_or(StatusCode::BAD_REQUEST)?,
+            None => HashMap::new(),
+        };
+        let upload = Upload {
+            id: starberry_lib::secure_token(18),
+            length,
+            offset: 0,
+            metadata,
+            expires: self.expire_after.map(|after| SystemTime::now() + after),
+        };
+        self.store.create(upload.clone()).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let mut response = empty(StatusCode::CREATED).add_header("location", format!("{}/{}", self.path, upload.id));
+        if let Some(expires) = upload.expires {
+            response = response.add_header("upload-expires", format_http_date(expires));
+        }
+        if has_offset_body(req) {
+            let offset = self.receive(req, upload.clone()).await?;
+            response = response.add_header("upload-offset", offset.to_string());
+        } else if upload.is_complete() {
+            self.complete(upload).await;
+        }
+        Ok(response)
+    }
+
+    /// The upload, unless it is unknown or has expired
+    async fn load(&self, id: String) -> Result<Upload, StatusCode> {
+        let upload = self.store.get(id.clone()).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        match upload {
+            Some(upload) if upload.is_expired() => {
+                let _ = self.store.delete(id).await;
+                Err(StatusCode::GONE)
+            }
+            Some(upload) => Ok(upload),
+            None => Err(StatusCode::NOT_FOUND),
+        }
+    }
+
+    async fn head(&self, id: String) -> Result<HttpResponse, StatusCode> {
+        let upload = self.load(id).await?;
+        let mut response = empty(StatusCode::OK)
+            .add_header("upload-offset", upload.offset.to_string())
+            .add_header("upload-length", upload.length.to_string())
+            .add_header("cache-control", "no-store");
+        if !upload.metadata.is_empty() {
+            response = response.add_header("upload-metadata", format_metadata(&upload.metadata));
+        }
+        if let Some(expires) = upload.expires.filter(|_| !upload.is_complete()) {
+            response = response.add_header("upload-expires", format_http_date(expires));
+        }
+        Ok(response)
+    }
+
+    async fn patch(&self, req: &mut HttpReqCtx, id: String) -> Result<HttpResponse, StatusCode> {
+        if !has_offset_body(req) {
+            return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        let upload = self.load(id).await?;
+        let offset: u64 = req
+            .meta()
+            .get_header("upload-offset")
+            .and_then(|offset| offset.trim().parse().ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        if offset != upload.offset {
+            return Err(StatusCode::CONFLICT);
+        }
+        let expires = upload.expires;
+        let offset = self.receive(req, upload).await?;
+        let mut response = empty(StatusCode::NO_CONTENT).add_header("upload-offset", offset.to_string());
+        if let Some(expires) = expires {
+            response = response.add_header("upload-expires", format_http_date(expires));
+        }
+        Ok(response)
+    }
+
+    async fn terminate(&self, id: String) -> Result<HttpResponse, StatusCode> {
+        self.load(id.clone()).await?;
+        self.store.delete(id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(empty(StatusCode::NO_CONTENT))
+    }
+
+    /// Hands the body of the request to the store in chunks and returns the new offset.
+    /// If the transfer breaks off, the chunks already stored are kept for the client to resume
+    async fn receive(&self, req: &mut HttpReqCtx, mut upload: Upload) -> Result<u64, StatusCode> {
+        let remaining = upload.length - upload.offset;
+        let chunked = req
+            .meta()
+            .get_header("transfer-encoding")
+            .is_some_and(|encoding| encoding.to_ascii_lowercase().contains("chunked"));
+        if chunked {
+            // Chunked bodies are rare for uploads, they are read at once within the remaining length
+            let mut safety = HttpSafety::new();
+            safety.set_max_body_size(Some(remaining as usize));
+            let mut data = Vec::new();
+            let result = HttpBody::stream_to(&mut req.reader, &mut req.request.meta, &safety, &mut data).await;
+            req.request.body = HttpBody::Empty;
+            result.map_err(|e| too_large_or(e, StatusCode::BAD_REQUEST))?;
+            upload.offset = self.store_chunk(&upload, data).await?;
+        } else {
+            let length = req.meta().get_content_length().unwrap_or(0) as u64;
+            if length > remaining {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            }
+            req.request.body = HttpBody::Empty;
+            let mut body = (&mut req.reader).take(length);
+            loop {
+                let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                let read = (&mut body).take(CHUNK_SIZE as u64).read_to_end(&mut chunk).await;
+                if !chunk.is_empty() {
+                    upload.offset = self.store_chunk(&upload, chunk).await?;
+                }
+                match read {
+                    Ok(n) if n == CHUNK_SIZE => continue,
+                    _ => break,
+                }
+            }
+        }
+        if upload.is_complete() {
+            self.complete(upload.clone()).await;
+        }
+        Ok(upload.offset)
+    }
+
+    async fn store_chunk(&self, upload: &Upload, chunk: Vec<u8>) -> Result<u64, StatusCode> {
+        self.store
+            .append(upload.id.clone(), upload.offset, chunk)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    async fn complete(&self, upload: Upload) {
+        if let Some((storage, prefix)) = &self.storage {
+            let key = format!("{}{}", prefix, upload.id);
+            if let Err(e) = self.store.copy_to(upload.id.clone(), storage.clone(), key).await {
+                eprintln!("Failed to move upload {} to the storage: {}", upload.id, e);
+                return;
+            }
+            if let Err(e) = self.store.delete(upload.id.clone()).await {
+                eprintln!("Failed to delete moved upload {}: {}", upload.id, e);
+            }
+        }
+        if let Some(hook) = &self.on_complete {
+            hook(upload).await;
+        }
+    }
+}
+
+impl Default for Tus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn empty(status: StatusCode) -> HttpResponse {
+    normal_response(status, Vec::new())
+}
+
+fn has_offset_body(req: &mut HttpReqCtx) -> bool {
+    req.meta()
+        .get_header("content-type")
+        .is_some_and(|content_type| content_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case(OFFSET_OCTET_STREAM))
+}
+
+fn too_large_or(e: io::Error, status: StatusCode) -> StatusCode {
+    if e.kind() == io::ErrorKind::FileTooLarge { StatusCode::PAYLOAD_TOO_LARGE } else { status }
+}
+
+impl AsyncMiddleware<HttpReqCtx> for Tus {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn return_self() -> Self {
+        Self::new()
+    }
+
+    fn handle<'a>(
+        &self,
+        mut req: HttpReqCtx,
+        next: Box<dyn Fn(HttpReqCtx) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send>> + Send + Sync + 'static>,
+    ) -> Pin<Box<dyn Future<Output = HttpReqCtx> + Send + 'static>> {
+        let tus = self.clone();
+        Box::pin(async move {
+            let path = req.path();
+            let Some(target) = tus.target(&path) else {
+                return next(req).await;
+            };
+            let id = target.map(str::to_string);
+            let response = tus.serve(&mut req, id).await;
+            req.response = response.add_header("tus-resumable", TUS_VERSION);
+            req
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_round_trips() {
+        let metadata = parse_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential").unwrap();
+        assert_eq!(metadata["filename"], "world_domination_plan.pdf");
+        assert_eq!(metadata["is_confidential"], "");
+        assert_eq!(parse_metadata(&format_metadata(&metadata)).unwrap(), metadata);
+        assert!(parse_metadata("a YQ==,a YQ==").is_none());
+        assert!(parse_metadata("a not-base64!").is_none());
+    }
+
+    #[test]
+    fn matches_the_collection_and_uploads() {
+        let tus = Tus::new().path("uploads/");
+        assert_eq!(tus.target("/uploads"), Some(None));
+        assert_eq!(tus.target("/uploads/"), Some(None));
+        assert_eq!(tus.target("/uploads/abc"), Some(Some("abc")));
+        assert_eq!(tus.target("/uploads/abc/def"), None);
+        assert_eq!(tus.target("/uploads/.."), None);
+        assert_eq!(tus.target("/uploadsabc"), None);
+        assert_eq!(tus.target("/other"), None);
+    }
+
+    #[tokio::test]
+    async fn stores_resume_at_their_offset() {
+        let dir = std::env::temp_dir().join(format!("starberry-tus-{}", starberry_lib::uuid::uuid_v4()));
+        let stores: Vec<Arc<dyn UploadStore>> = vec![Arc::new(MemoryUploadStore::new()), Arc::new(DirUploadStore::new(&dir))];
+        for store in stores {
+            let mut metadata = HashMap::new();
+            metadata.insert("filename".to_string(), "a.txt".to_string());
+            let upload = Upload { id: "up1".to_string(), length: 5, offset: 0, metadata, expires: None };
+            store.create(upload.clone()).await.unwrap();
+            assert_eq!(store.append("up1".to_string(), 0, b"hel".to_vec()).await.unwrap(), 3);
+            assert!(store.append("up1".to_string(), 0, b"lo".to_vec()).await.is_err());
+            assert_eq!(store.append("up1".to_string(), 3, b"lo".to_vec()).await.unwrap(), 5);
+            let stored = store.get("up1".to_string()).await.unwrap().unwrap();
+            assert_eq!(stored, Upload { offset: 5, ..upload });
+            assert!(stored.is_complete());
+            store.delete("up1".to_string()).await.unwrap();
+            assert!(store.get("up1".to_string()).await.unwrap().is_none());
+        }
+        assert_eq!(std::fs::read(DirUploadStore::new(&dir).path("up1")).ok(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn moves_complete_uploads_to_the_storage() {
+        use starberry_core::app::storage::LocalStorage;
+        let dir = std::env::temp_dir().join(format!("starberry-tus-storage-{}", starberry_lib::uuid::uuid_v4()));
+        let store = MemoryUploadStore::new();
+        let storage: SharedStorage = Arc::new(LocalStorage::new(&dir));
+        let tus = Tus::new().store(store.clone()).storage(storage.clone(), "uploads/");
+        let upload = Upload { id: "up1".to_string(), length: 5, offset: 0, metadata: HashMap::new(), expires: None };
+        store.create(upload.clone()).await.unwrap();
+        store.append("up1".to_string(), 0, b"hello".to_vec()).await.unwrap();
+        tus.complete(Upload { offset: 5, ..upload }).await;
+        assert_eq!(storage.get("uploads/up1".to_string()).await.unwrap(), Some(b"hello".to_vec()));
+        assert!(store.data("up1").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
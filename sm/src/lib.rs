@@ -62,7 +62,8 @@ use proc_macro2::{Span, TokenStream as TokenStream2};
 struct UrlMethodArgs {
     pub url_expr: Expr,
     pub config: Option<Vec<Expr>>,
-    pub middlewares: Option<Vec<Expr>> 
+    pub middlewares: Option<Vec<Expr>>, 
+    pub name: Option<Expr> 
 } 
 
 impl Parse for UrlMethodArgs {
@@ -73,6 +74,7 @@ impl Parse for UrlMethodArgs {
         // Initialize optional parameters
         let mut config: Option<Vec<Expr>> = None;
         let mut middlewares: Option<Vec<Expr>> = None;
+        let mut name: Option<Expr> = None;
         
         // If there are more tokens, process named parameters
         while !input.is_empty() {
@@ -103,6 +105,9 @@ impl Parse for UrlMethodArgs {
                         let list = Punctuated::<Expr, Comma>::parse_terminated(input)?;
                         middlewares = Some(list.into_iter().collect());
                     },
+                    "name" => {
+                        name = Some(input.parse()?);
+                    },
                     _ => return Err(input.error(format!("unknown parameter: {}", param_name_str))),
                 }
             } else {
@@ -113,7 +118,8 @@ impl Parse for UrlMethodArgs {
         Ok(UrlMethodArgs {
             url_expr,
             config, 
-            middlewares  
+            middlewares, 
+            name 
         })
     }
 } 
@@ -151,6 +157,12 @@ pub fn url(attr: TokenStream, function: TokenStream) -> TokenStream {
         }
     }; 
 
+    // Named routes can be reversed with `url_for`
+    let name_setup = match args.name {
+        Some(name) => quote! { child_url.set_name(#name); },
+        None => quote! {},
+    };
+
     // Check if the function has a parameter
     let has_param = !func.sig.inputs.is_empty();
     
@@ -159,8 +171,10 @@ pub fn url(attr: TokenStream, function: TokenStream) -> TokenStream {
         // Check if return type is HttpResponse
         match ret_type.as_ref() {
             syn::Type::Path(type_path) => {
+                // Handlers returning `Result<HttpResponse, E>` are wrapped the same way, 
+                // the `Err` branch is turned into a response through `IntoResponse` 
                 let last_segment = type_path.path.segments.last().unwrap();
-                last_segment.ident.to_string() == "HttpResponse"
+                last_segment.ident == "HttpResponse" || last_segment.ident == "Result"
             }
             _ => false,
         }
@@ -209,7 +223,7 @@ pub fn url(attr: TokenStream, function: TokenStream) -> TokenStream {
                 (quote! {
                     async fn #wrapper_func_ident(mut rc: HttpReqCtx) -> HttpReqCtx {
                         let response = #func_ident(&mut rc).await;
-                        rc.response = response;
+                        rc.response = starberry::starberry_core::http::error::IntoResponse::into_response(response);
                         rc
                     }
                 }, param_name)
@@ -225,7 +239,7 @@ pub fn url(attr: TokenStream, function: TokenStream) -> TokenStream {
                 (quote! {
                     async fn #wrapper_func_ident(mut rc: HttpReqCtx) -> HttpReqCtx {
                         let response = #func_ident(&mut rc).await;
-                        rc.response = response;
+                        rc.response = starberry::starberry_core::http::error::IntoResponse::into_response(response);
                         rc
                     }
                 }, param_name)
@@ -303,7 +317,7 @@ pub fn url(attr: TokenStream, function: TokenStream) -> TokenStream {
             (quote! {
                 async fn #wrapper_func_ident(mut rc: HttpReqCtx) -> HttpReqCtx {
                     let response = #func_ident(&mut rc).await;
-                    rc.response = response;
+                    rc.response = starberry::starberry_core::http::error::IntoResponse::into_response(response);
                     rc
                 }
             }, param_name)
@@ -323,26 +337,119 @@ pub fn url(attr: TokenStream, function: TokenStream) -> TokenStream {
         quote! { #func_ident }
     };
 
+    // With the `ctor` feature the registration function is executed at startup, 
+    // otherwise it has to be called through `collect_routes!` 
+    let ctor_setup = if cfg!(feature = "ctor") { 
+        let ctor_fn_ident = syn::Ident::new(&format!("__ctor{}", register_fn_name), func_ident.span()); 
+        quote! { 
+            #[ctor::ctor]
+            #[allow(non_snake_case)]
+            fn #ctor_fn_ident() { 
+                if let Err(e) = #register_fn_ident() { 
+                    eprintln!("{}", e); 
+                } 
+            } 
+        } 
+    } else { 
+        quote! {} 
+    }; 
+
     // Generate the final code
     let expanded = quote! {
         #func
 
         #wrapper_code
 
-        // This function will be executed at startup (using the ctor crate).
-        #[ctor::ctor]
-        fn #register_fn_ident() {
+        /// Registers the function to its url. Returns an error if the url could not be created. 
+        #[doc(hidden)]
+        #[allow(unused_mut)]
+        pub fn #register_fn_ident() -> Result<(), String> {
             let mut child_url = #url_expr;  
+            if child_url.is_dangling() { 
+                return Err(format!("Failed to register url for `{}`", stringify!(#func_ident))); 
+            } 
             #config_setup 
             #middleware_setup 
+            #name_setup 
             child_url.set_method(Arc::new(#register_function)); 
             // child_url.set_middlewares(child_url.middlewares.read().unwrap().get_middlewares()); 
+            Ok(())
         }
+
+        #ctor_setup
     };
 
     expanded.into()
 } 
 
+/// A config field of `#[middleware(config(name: Type = default))]`
+struct MiddlewareConfigField {
+    ident: Ident,
+    ty: Type,
+    default: Option<Expr>,
+}
+
+impl Parse for MiddlewareConfigField {
+    fn parse(input: ParseStream) -> SynResult<Self> {
+        let ident: Ident = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty: Type = input.parse()?;
+        let default = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(MiddlewareConfigField { ident, ty, default })
+    }
+}
+
+/// Arguments of `#[middleware]`: an optional context type and an optional `config(...)` list
+struct MiddlewareArgs {
+    ty: Option<Type>,
+    config: Vec<MiddlewareConfigField>,
+}
+
+impl Parse for MiddlewareArgs {
+    fn parse(input: ParseStream) -> SynResult<Self> {
+        let mut ty = None;
+        let mut config = Vec::new();
+        while !input.is_empty() {
+            if input.peek(Ident) && input.peek2(syn::token::Paren) && input.fork().parse::<Ident>()? == "config" {
+                input.parse::<Ident>()?;
+                let content;
+                syn::parenthesized!(content in input);
+                let fields = Punctuated::<MiddlewareConfigField, Comma>::parse_terminated(&content)?;
+                config.extend(fields);
+            } else if ty.is_none() {
+                ty = Some(input.parse()?);
+            } else {
+                return Err(input.error("expected `config(...)`"));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(MiddlewareArgs { ty, config })
+    }
+}
+
+/// Turns an async fn into a middleware struct implementing `AsyncMiddleware`. 
+/// The context is available as `req` (or the name of the first argument) and `next` runs the rest of the chain. 
+/// 
+/// Per-instance settings are declared with `config(...)`. Each field becomes a struct field, a parameter 
+/// of the generated `new` constructor and a local in the body. Fields without a default use `Default::default()` 
+/// when the middleware is created by `append_middleware`. Field types must implement `Clone`. 
+/// # Example 
+/// ```ignore 
+/// #[middleware(config(max_requests: u32 = 100, name: String))]
+/// pub async fn RateLimit() { 
+///     println!("{}: at most {} requests", name, max_requests); 
+///     next(req).await 
+/// } 
+/// 
+/// ProtocolBuilder::<HttpReqCtx>::new().add_middleware(RateLimit::new(10, "api".to_string())); 
+/// ``` 
 #[proc_macro_attribute]
 pub fn middleware(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Parse the async fn we're given
@@ -359,12 +466,11 @@ pub fn middleware(attr: TokenStream, item: TokenStream) -> TokenStream {
         .into();
     } 
 
-    // parse the type parameter R from the attribute (or default to HttpReqCtx)
-    let ty_tokens = if attr.is_empty() {
-        quote! { HttpReqCtx }
-    } else {
-        let ty: Type = syn::parse(attr).expect("Expected a single type in #[middleware<…>]");
-        quote! { #ty }
+    // parse the type parameter R and the config fields from the attribute (R defaults to HttpReqCtx)
+    let args = parse_macro_input!(attr as MiddlewareArgs);
+    let ty_tokens = match args.ty {
+        Some(ty) => quote! { #ty },
+        None => quote! { HttpReqCtx },
     };
 
     // Extract first argument's name and type
@@ -391,12 +497,39 @@ pub fn middleware(attr: TokenStream, item: TokenStream) -> TokenStream {
     // The original function body (a Block)
     let fn_body = &input_fn.block;
 
+    // Without config the middleware is a unit struct, otherwise every config field becomes 
+    // a struct field, a `new` parameter and a local (cloned out of self) inside the body 
+    let field_idents: Vec<&Ident> = args.config.iter().map(|f| &f.ident).collect();
+    let field_types: Vec<&Type> = args.config.iter().map(|f| &f.ty).collect();
+    let field_defaults = args.config.iter().map(|f| match &f.default {
+        Some(expr) => quote! { #expr },
+        None => quote! { Default::default() },
+    });
+    let (struct_def, self_value) = if args.config.is_empty() {
+        (quote! { pub struct #fn_name; }, quote! { #fn_name })
+    } else {
+        (
+            quote! {
+                pub struct #fn_name {
+                    #(pub #field_idents: #field_types,)*
+                }
+
+                impl #fn_name {
+                    pub fn new(#(#field_idents: #field_types),*) -> Self {
+                        Self { #(#field_idents),* }
+                    }
+                }
+            },
+            quote! { #fn_name { #(#field_idents: #field_defaults),* } },
+        )
+    };
+
     // Generate:
     //  pub struct Foo;
     //  impl AsyncMiddleware<ParamType> for Foo { ... }
     let expanded = quote! {
         // drop the original free function; we only emit the struct+impl
-        pub struct #fn_name;
+        #struct_def
 
         impl AsyncMiddleware<#ty_tokens> for #fn_name {
             fn as_any(&self) -> &dyn std::any::Any {
@@ -407,7 +540,7 @@ pub fn middleware(attr: TokenStream, item: TokenStream) -> TokenStream {
             where
                 Self: Sized,
             {
-                #fn_name
+                #self_value
             }
 
             fn handle<'a>(
@@ -420,6 +553,7 @@ pub fn middleware(attr: TokenStream, item: TokenStream) -> TokenStream {
                         + 'static,
                 >,
             ) -> std::pin::Pin<Box<dyn std::future::Future<Output = #ty_tokens> + Send + 'static>> {
+                #(let #field_idents = self.#field_idents.clone();)*
                 Box::pin(async move {
                     #param_binding
                     // original user code:
@@ -441,19 +575,71 @@ pub fn object(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
-/// A macro that returns a JSON response containing the provided object
+/// A macro that returns a JSON response containing the provided object. 
+/// Besides the `object!` syntax it accepts an expression, which is either converted 
+/// into a `Value` or, failing that, serialized with serde (`serde` feature). 
+/// # Example 
+/// ```ignore 
+/// akari_json!({ name: "Ada", roles: ["admin"] }); 
+/// akari_json!(user); // `user` implements `Serialize` 
+/// ``` 
 #[proc_macro]
 pub fn akari_json(input: TokenStream) -> TokenStream {
     let expr = parse_macro_input!(input as ValueExpr);
-    let object_code = generate_code(&expr);
     
-    let expanded = quote! {
-        json_response(#object_code)
+    let expanded = match &expr {
+        // Autoref picks `JsonFromValue` when the value converts into `Value`, `JsonFromSerialize` otherwise 
+        ValueExpr::Other(value) if !matches!(value, syn::Expr::Lit(_)) => quote! {{
+            #[allow(unused_imports)]
+            use starberry::starberry_core::http::json::bridge::*;
+            (&&starberry::starberry_core::http::json::bridge::JsonBody(&(#value))).to_json_response()
+        }},
+        _ => {
+            let object_code = generate_code(&expr);
+            quote! {
+                json_response(#object_code)
+            }
+        }
     };
     
     TokenStream::from(expanded)
 } 
 
+/// Registers the given `#[url]` functions explicitly, as an alternative to the `ctor` feature. 
+/// Useful on platforms and test harnesses where code cannot run before main. 
+/// Evaluates to `Result<(), String>`, the error lists every url that failed to register. 
+/// A misspelled function is a compile error. 
+/// # Example 
+/// ```ignore 
+/// collect_routes![home, about, blog::post].expect("Failed to register routes"); 
+/// ``` 
+#[proc_macro]
+pub fn collect_routes(input: TokenStream) -> TokenStream { 
+    let paths = parse_macro_input!(input with Punctuated::<syn::Path, Token![,]>::parse_terminated); 
+    let calls = paths.into_iter().map(|mut path| { 
+        if let Some(last) = path.segments.last_mut() { 
+            last.ident = syn::Ident::new(&format!("__register_{}", last.ident), last.ident.span()); 
+        } 
+        quote! { 
+            if let Err(e) = #path() { 
+                errors.push(e); 
+            } 
+        } 
+    }); 
+    let expanded = quote! { 
+        { 
+            let mut errors: Vec<String> = Vec::new(); 
+            #(#calls)* 
+            if errors.is_empty() { 
+                Ok::<(), String>(()) 
+            } else { 
+                Err(errors.join("\n")) 
+            } 
+        } 
+    }; 
+    TokenStream::from(expanded) 
+} 
+
 #[proc_macro]
 pub fn reg(input: TokenStream) -> TokenStream {
     // Parse the comma-separated items inside reg![ ... ]
@@ -481,41 +667,15 @@ pub fn reg(input: TokenStream) -> TokenStream {
         path_segments.push(convert_expr_to_pathpattern(expr));
     }
 
-    // Decide expansion depending on the first argument type.
-    // In a full-blown macro you'd likely do advanced type-checking or pattern matching with `syn`,
-    // but for illustration, we produce code that calls either .reg_from(...) or .register(...)
-    // based on whether it "looks" like App or Url. In practice, you'd do more robust matching.
-
-    // Simplistic check: if the first token string contains "Url", call .register(...),
-    // otherwise call .reg_from(...). This is purely demonstrative.
-    let first_str = quote! { #first }.to_string();
-    let expansion = if first_str.contains("Url") {
-        // Url path
-        quote! {
-            {
-                let ancestor = #first;
-                // Suppose function=None, middleware = ancestor.get_middlewares(), path=our segments:
-                let _segments: Vec<PathPattern> = vec![#(#path_segments),*];
-                // Call the .register(...) method
-                ancestor
-                    .register(
-                        _segments,
-                        None, 
-                        ancestor.get_middlewares(), 
-                        Params::default()
-                    )
-                    .map_err(|e| e.to_string())
-            }
-        }
-    } else {
-        // App path
-        quote! {
-            {
-                let ancestor = #first;
-                let _segments: Vec<PathPattern> = vec![#(#path_segments),*];
-                // Call .reg_from() if the type is an App-like
-                ancestor.reg_from::<HttpReqCtx>(&_segments)
-            }
+    // The ancestor is resolved through the `RegAncestor` trait, so `App`, `Url`, their `Arc`s 
+    // and anything dereferencing to them (e.g. `Lazy` statics) register correctly. 
+    let expansion = quote! {
+        {
+            use starberry::starberry_core::app::urls::RegAncestor as _; 
+            let ancestor = #first;
+            let _segments: Vec<PathPattern> = vec![#(#path_segments),*];
+            let url: Arc<Url<HttpReqCtx>> = ancestor.reg_segments(&_segments); 
+            url 
         }
     };
 
@@ -536,7 +696,7 @@ fn convert_expr_to_pathpattern(expr: &Expr) -> proc_macro2::TokenStream {
         Expr::Lit(expr_lit) => {
             // We'll wrap with LitUrl(...) for string-literal or numeric-literal (simplified approach)
             quote! {
-                PathPattern::LitUrl(#expr_lit.to_string())
+                PathPattern::literal_path(#expr_lit.to_string())
             }
         }
         Expr::Path(_) | Expr::Call(_) | Expr::Reference(_) => {
@@ -790,3 +950,745 @@ fn generate_render_code(args: RenderArgs) -> TokenStream2 {
         template_response(#template_path, context)
     }}
 } 
+
+/// Derives `FromQuery` for a struct with named fields, filling it from a query string. 
+/// `Option<T>` fields may be missing, `Vec<T>` fields collect repeated keys and the other 
+/// fields are required. Fields accept `#[query(rename = "key")]`, `#[query(default)]`, 
+/// `#[query(default = expr)]` and `#[query(with = path::to::parser)]`. 
+/// # Example 
+/// ```ignore 
+/// #[derive(FromQuery)] 
+/// struct Search { 
+///     q: String, 
+///     #[query(default = 1)] 
+///     page: u32, 
+///     tag: Vec<String>, 
+/// } 
+/// ``` 
+#[proc_macro_derive(FromQuery, attributes(query))]
+pub fn derive_from_query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    match generate_from_query(&input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => TokenStream::from(e.to_compile_error()),
+    }
+}
+
+/// The `#[query(...)]` attributes of a field
+struct QueryFieldAttrs {
+    rename: Option<String>,
+    /// `Some(None)` for `#[query(default)]`, `Some(Some(expr))` for `#[query(default = expr)]`
+    default: Option<Option<Expr>>,
+    with: Option<syn::Path>,
+}
+
+fn query_field_attrs(field: &syn::Field) -> SynResult<QueryFieldAttrs> {
+    let mut attrs = QueryFieldAttrs { rename: None, default: None, with: None };
+    for attr in &field.attrs {
+        if !attr.path().is_ident("query") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let key: LitStr = meta.value()?.parse()?;
+                attrs.rename = Some(key.value());
+            } else if meta.path.is_ident("default") {
+                if meta.input.peek(Token![=]) {
+                    attrs.default = Some(Some(meta.value()?.parse()?));
+                } else {
+                    attrs.default = Some(None);
+                }
+            } else if meta.path.is_ident("with") {
+                attrs.with = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unknown query attribute, expected `rename`, `default` or `with`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+/// The `T` of a field typed `Option<T>` or `Vec<T>`
+fn wrapped_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else { return None };
+    match args.args.first() {
+        Some(syn::GenericArgument::Type(inner)) => Some(inner),
+        _ => None,
+    }
+}
+
+fn generate_from_query(input: &syn::DeriveInput) -> SynResult<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(fields), .. }) => &fields.named,
+        _ => return Err(syn::Error::new(input.ident.span(), "FromQuery can only be derived for structs with named fields")),
+    };
+
+    let mut extractions = Vec::new();
+    let mut assignments = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let attrs = query_field_attrs(field)?;
+        let key = attrs
+            .rename
+            .unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string());
+        let var = Ident::new(&format!("__field_{}", index), Span::call_site());
+
+        let option_inner = wrapped_type(ty, "Option");
+        let vec_inner = wrapped_type(ty, "Vec");
+        let inner = option_inner.or(vec_inner).unwrap_or(ty);
+        let parse = match &attrs.with {
+            Some(with) => quote! { #with(value).map_err(|e| e.to_string()) },
+            None => quote! {
+                <#inner as starberry::starberry_core::http::query::FromQueryValue>::from_query_value(value)
+            },
+        };
+        let default = match &attrs.default {
+            Some(Some(expr)) => Some(quote! { #expr }),
+            Some(None) => Some(quote! { ::std::default::Default::default() }),
+            None => None,
+        };
+
+        let extraction = if option_inner.is_some() {
+            let missing = default.unwrap_or_else(|| quote! { None });
+            quote! {
+                let #var: Option<#ty> = match __query.get(#key) {
+                    Some(value) => match #parse {
+                        Ok(parsed) => Some(Some(parsed)),
+                        Err(e) => { __errors.push(#key, e); None }
+                    },
+                    None => Some(#missing),
+                };
+            }
+        } else if vec_inner.is_some() {
+            let missing = default.unwrap_or_else(|| quote! { Vec::new() });
+            quote! {
+                let #var: Option<#ty> = if __query.contains(#key) {
+                    let mut values = Vec::new();
+                    let mut valid = true;
+                    for value in __query.get_all(#key) {
+                        match #parse {
+                            Ok(parsed) => values.push(parsed),
+                            Err(e) => { __errors.push(#key, e); valid = false; }
+                        }
+                    }
+                    if valid { Some(values) } else { None }
+                } else {
+                    Some(#missing)
+                };
+            }
+        } else {
+            let missing = match default {
+                Some(default) => quote! { Some(#default) },
+                None => quote! { { __errors.push(#key, "missing field"); None } },
+            };
+            quote! {
+                let #var: Option<#ty> = match __query.get(#key) {
+                    Some(value) => match #parse {
+                        Ok(parsed) => Some(parsed),
+                        Err(e) => { __errors.push(#key, e); None }
+                    },
+                    None => #missing,
+                };
+            }
+        };
+        extractions.push(extraction);
+        assignments.push(quote! { #ident: #var.expect("checked by the error list") });
+    }
+
+    Ok(quote! {
+        impl #impl_generics starberry::starberry_core::http::query::FromQuery for #name #ty_generics #where_clause {
+            fn from_query(
+                __query: &starberry::starberry_core::http::query::QueryMap,
+            ) -> ::std::result::Result<Self, starberry::starberry_core::http::query::QueryErrors> {
+                let mut __errors = starberry::starberry_core::http::query::QueryErrors::new();
+                #(#extractions)*
+                if !__errors.is_empty() {
+                    return Err(__errors);
+                }
+                Ok(Self { #(#assignments),* })
+            }
+        }
+    })
+}
+
+/// Derives `Validate` for a struct with named fields from its `#[validate(...)]` rules: 
+/// `length(min = .., max = ..)`, `range(min = .., max = ..)`, `email`, `regex = ".."` 
+/// and `custom = path::to::check`. The rules of an `Option` field apply to its content. 
+/// # Example 
+/// ```ignore 
+/// #[derive(Validate)] 
+/// struct Signup { 
+///     #[validate(length(min = 3, max = 20))] 
+///     username: String, 
+///     #[validate(email)] 
+///     email: String, 
+/// } 
+/// ``` 
+#[proc_macro_derive(Validate, attributes(validate))]
+pub fn derive_validate(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    match generate_validate(&input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => TokenStream::from(e.to_compile_error()),
+    }
+}
+
+/// Parses the `min = ..` and `max = ..` arguments of `length(..)` and `range(..)`
+fn validate_bounds(meta: &syn::meta::ParseNestedMeta) -> SynResult<(Option<Expr>, Option<Expr>)> {
+    let mut min = None;
+    let mut max = None;
+    meta.parse_nested_meta(|bound| {
+        if bound.path.is_ident("min") {
+            min = Some(bound.value()?.parse()?);
+        } else if bound.path.is_ident("max") {
+            max = Some(bound.value()?.parse()?);
+        } else {
+            return Err(bound.error("expected `min` or `max`"));
+        }
+        Ok(())
+    })?;
+    Ok((min, max))
+}
+
+fn generate_validate(input: &syn::DeriveInput) -> SynResult<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(fields), .. }) => &fields.named,
+        _ => return Err(syn::Error::new(input.ident.span(), "Validate can only be derived for structs with named fields")),
+    };
+    let rules_path = quote! { starberry::starberry_core::http::validate::rules };
+    let option = |bound: Option<Expr>| match bound {
+        Some(bound) => quote! { Some(#bound) },
+        None => quote! { None },
+    };
+
+    let mut checks = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let field_name = ident.to_string().trim_start_matches("r#").to_string();
+        let mut rules = Vec::new();
+        for attr in &field.attrs {
+            if !attr.path().is_ident("validate") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                let (rule, call) = if meta.path.is_ident("length") {
+                    let (min, max) = validate_bounds(&meta)?;
+                    let (min, max) = (option(min), option(max));
+                    ("length", quote! { #rules_path::length(value, #min, #max) })
+                } else if meta.path.is_ident("range") {
+                    let (min, max) = validate_bounds(&meta)?;
+                    let (min, max) = (option(min), option(max));
+                    ("range", quote! { #rules_path::range(value, #min, #max) })
+                } else if meta.path.is_ident("email") {
+                    ("email", quote! { #rules_path::email(value) })
+                } else if meta.path.is_ident("regex") {
+                    let pattern: LitStr = meta.value()?.parse()?;
+                    ("regex", quote! { #rules_path::regex(value, #pattern) })
+                } else if meta.path.is_ident("custom") {
+                    let function: syn::Path = meta.value()?.parse()?;
+                    ("custom", quote! { #function(value) })
+                } else {
+                    return Err(meta.error("unknown rule, expected `length`, `range`, `email`, `regex` or `custom`"));
+                };
+                rules.push(quote! {
+                    if let Err(message) = #call {
+                        __errors.push(#field_name, #rule, message);
+                    }
+                });
+                Ok(())
+            })?;
+        }
+        if rules.is_empty() {
+            continue;
+        }
+        if wrapped_type(&field.ty, "Option").is_some() {
+            checks.push(quote! {
+                if let Some(value) = &self.#ident {
+                    #(#rules)*
+                }
+            });
+        } else {
+            checks.push(quote! {
+                {
+                    let value = &self.#ident;
+                    #(#rules)*
+                }
+            });
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics starberry::starberry_core::http::validate::Validate for #name #ty_generics #where_clause {
+            fn validate(&self) -> ::std::result::Result<(), starberry::starberry_core::http::validate::ValidationErrors> {
+                let mut __errors = starberry::starberry_core::http::validate::ValidationErrors::new();
+                #(#checks)*
+                __errors.into_result()
+            }
+        }
+    })
+}
+
+/// Derives `Form` for a struct with named fields, listing the inputs of its fields. 
+/// `#[form(label = "..")]` and `#[form(kind = "..")]` replace the label and input type 
+/// guessed from the field, `#[form(skip)]` leaves a field out, and the `rename` and 
+/// `default` of `#[query(...)]` are followed to match `FromQuery`. 
+/// # Example 
+/// ```ignore 
+/// #[derive(FromQuery, Validate, Form)] 
+/// struct Comment { 
+///     #[form(label = "Your name")] 
+///     author: String, 
+///     #[form(kind = "textarea")] 
+///     #[validate(length(min = 1, max = 2000))] 
+///     body: String, 
+/// } 
+/// ``` 
+#[proc_macro_derive(Form, attributes(form))]
+pub fn derive_form(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    match generate_form(&input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => TokenStream::from(e.to_compile_error()),
+    }
+}
+
+/// The `#[form(...)]` attributes of a field
+struct FormFieldAttrs {
+    label: Option<String>,
+    kind: Option<String>,
+    skip: bool,
+}
+
+fn form_field_attrs(field: &syn::Field) -> SynResult<FormFieldAttrs> {
+    let mut attrs = FormFieldAttrs { label: None, kind: None, skip: false };
+    for attr in &field.attrs {
+        if !attr.path().is_ident("form") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("label") {
+                let label: LitStr = meta.value()?.parse()?;
+                attrs.label = Some(label.value());
+            } else if meta.path.is_ident("kind") {
+                let kind: LitStr = meta.value()?.parse()?;
+                attrs.kind = Some(kind.value());
+            } else if meta.path.is_ident("skip") {
+                attrs.skip = true;
+            } else {
+                return Err(meta.error("unknown form attribute, expected `label`, `kind` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+/// The last segment of a type path, e.g. `u32` or `String`
+fn type_name(ty: &Type) -> Option<String> {
+    let Type::Path(path) = ty else { return None };
+    path.path.segments.last().map(|segment| segment.ident.to_string())
+}
+
+fn generate_form(input: &syn::DeriveInput) -> SynResult<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(fields), .. }) => &fields.named,
+        _ => return Err(syn::Error::new(input.ident.span(), "Form can only be derived for structs with named fields")),
+    };
+    let form_path = quote! { starberry::starberry_core::http::bound_form };
+
+    let mut descriptions = Vec::new();
+    let mut values = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let field_name = ident.to_string().trim_start_matches("r#").to_string();
+        let attrs = form_field_attrs(field)?;
+        let query = query_field_attrs(field)?;
+        let key = query.rename.unwrap_or_else(|| field_name.clone());
+        values.push(quote! {
+            for value in #form_path::FormValue::form_values(&self.#ident) {
+                __values.push(#key, value);
+            }
+        });
+        if attrs.skip {
+            continue;
+        }
+
+        let option_inner = wrapped_type(&field.ty, "Option");
+        let vec_inner = wrapped_type(&field.ty, "Vec");
+        let inner = option_inner.or(vec_inner).unwrap_or(&field.ty);
+        let inner_name = type_name(inner).unwrap_or_default();
+        let is_bool = inner_name == "bool";
+        let kind = attrs.kind.unwrap_or_else(|| {
+            let numbers = ["u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64"];
+            if is_bool {
+                "checkbox".to_string()
+            } else if numbers.contains(&inner_name.as_str()) {
+                "number".to_string()
+            } else if field_name.contains("password") {
+                "password".to_string()
+            } else if field_name.contains("email") {
+                "email".to_string()
+            } else {
+                "text".to_string()
+            }
+        });
+        let label = attrs.label.unwrap_or_else(|| {
+            let words = field_name.replace('_', " ");
+            let mut chars = words.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => words,
+            }
+        });
+        let required = option_inner.is_none() && vec_inner.is_none() && !is_bool && query.default.is_none();
+        descriptions.push(quote! {
+            #form_path::FormField::new(#key, #label, #kind).required(#required)
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics #form_path::Form for #name #ty_generics #where_clause {
+            fn form_fields() -> ::std::vec::Vec<#form_path::FormField> {
+                vec![#(#descriptions),*]
+            }
+
+            fn form_values(&self) -> starberry::starberry_core::http::query::QueryMap {
+                let mut __values = starberry::starberry_core::http::query::QueryMap::default();
+                #(#values)*
+                __values
+            }
+        }
+    })
+}
+
+/// Builds a `starberry_sql::SqlQuery` from SQL with its parameters in braces, checked when 
+/// compiling. `{expr}` binds an expression and `{}` the next argument after the SQL, like 
+/// `format!`; they are sent as `$1`, `$2`, ... (`?` to MySQL) and never spliced into the SQL. 
+/// `{{` and `}}` are literal braces, and braces inside quoted strings or comments are kept as 
+/// written. 
+/// 
+/// A `SELECT` or `RETURNING` of named columns gives a `TypedQuery` of an anonymous struct with 
+/// a `String` field per column, while `Type => "..."` maps the rows into a `FromRow` type. 
+/// The number of arguments, the aliases of computed columns and duplicated columns are checked. 
+/// # Example 
+/// ```ignore 
+/// let user = sql!("SELECT id, name FROM users WHERE id = {id}").fetch_one(&mut conn).await?; 
+/// println!("{}", user.name); 
+/// let adults: Vec<User> = sql!(User => "SELECT * FROM users WHERE age >= {}", 18) 
+///     .fetch_all_pool(&pool).await?; 
+/// ``` 
+#[proc_macro]
+pub fn sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as SqlInput);
+    match generate_sql(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => TokenStream::from(e.to_compile_error()),
+    }
+}
+
+struct SqlInput {
+    row_type: Option<Type>,
+    template: LitStr,
+    args: Vec<Expr>,
+}
+
+impl Parse for SqlInput {
+    fn parse(input: ParseStream) -> SynResult<Self> {
+        let row_type = if input.peek(LitStr) {
+            None
+        } else {
+            let ty: Type = input.parse()?;
+            input.parse::<Token![=>]>()?;
+            Some(ty)
+        };
+        let template: LitStr = input.parse()?;
+        let mut args = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            args.push(input.parse()?);
+        }
+        Ok(Self { row_type, template, args })
+    }
+}
+
+/// A parameter of the SQL: the next argument, or the expression written in its braces
+enum SqlParam {
+    Next,
+    Inline(String),
+}
+
+/// Replaces the parameters of the SQL by `$1`, `$2`, ..., leaving the quotes and comments as they are
+fn sql_placeholders(template: &str) -> Result<(String, Vec<SqlParam>), String> {
+    let mut sql = String::with_capacity(template.len());
+    let mut params = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            sql.push(c);
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => {
+                quote = Some(c);
+                sql.push(c);
+            }
+            // A comment, which may contain quotes
+            '-' if chars.peek() == Some(&'-') => {
+                sql.push(c);
+                for c in chars.by_ref() {
+                    sql.push(c);
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                sql.push(c);
+                sql.push(chars.next().expect("peeked"));
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some(c) => {
+                            sql.push(c);
+                            if last == '*' && c == '/' {
+                                break;
+                            }
+                            last = c;
+                        }
+                        None => return Err("unclosed `/*` comment in the SQL".to_string()),
+                    }
+                }
+            }
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                sql.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                sql.push('}');
+            }
+            '{' => {
+                let mut expr = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => expr.push(c),
+                        None => return Err("unclosed `{` in the SQL, write `{{` for a literal brace".to_string()),
+                    }
+                }
+                let expr = expr.trim();
+                params.push(if expr.is_empty() { SqlParam::Next } else { SqlParam::Inline(expr.to_string()) });
+                sql.push_str(&format!("${}", params.len()));
+            }
+            '}' => return Err("unmatched `}` in the SQL, write `}}` for a literal brace".to_string()),
+            '$' if chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                return Err("write the parameters as `{}` or `{expr}` instead of `$n`".to_string());
+            }
+            c => sql.push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unclosed quote in the SQL".to_string());
+    }
+    Ok((sql, params))
+}
+
+/// The SQL in uppercase, with the content of the quotes and of the parentheses blanked, so 
+/// the keywords and commas found are those of the statement itself. Byte offsets are kept 
+fn sql_mask(sql: &str) -> Vec<u8> {
+    let mut mask = sql.as_bytes().to_ascii_uppercase();
+    let mut quote = None;
+    let mut depth = 0usize;
+    for (i, &b) in sql.as_bytes().iter().enumerate() {
+        if let Some(q) = quote {
+            if b == q {
+                quote = None;
+            } else {
+                mask[i] = b' ';
+            }
+            continue;
+        }
+        match b {
+            b'\'' | b'"' | b'`' => quote = Some(b),
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => mask[i] = b' ',
+            _ => {}
+        }
+    }
+    mask
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$'
+}
+
+/// The offset of the first keyword at or after `from`
+fn find_keyword(mask: &[u8], keyword: &str, from: usize) -> Option<usize> {
+    let keyword = keyword.as_bytes();
+    (from..mask.len()).find(|&i| {
+        mask[i..].starts_with(keyword)
+            && (i == 0 || !is_word_byte(mask[i - 1]))
+            && mask.get(i + keyword.len()).is_none_or(|&b| !is_word_byte(b))
+    })
+}
+
+fn is_sql_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_quoted(name: &str) -> bool {
+    name.len() >= 2
+        && ((name.starts_with('"') && name.ends_with('"')) || (name.starts_with('`') && name.ends_with('`')))
+}
+
+/// The name of each column selected or returned by the SQL, `None` if the statement has no 
+/// such list or selects `*` 
+fn sql_columns(sql: &str) -> Result<Option<Vec<String>>, String> {
+    let mask = sql_mask(sql);
+    let first = mask.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(mask.len());
+    let mut start = if mask[first..].starts_with(b"SELECT") || mask[first..].starts_with(b"WITH") {
+        match find_keyword(&mask, "SELECT", first) {
+            Some(i) => i + "SELECT".len(),
+            None => return Ok(None),
+        }
+    } else {
+        match find_keyword(&mask, "RETURNING", first) {
+            Some(i) => i + "RETURNING".len(),
+            None => return Ok(None),
+        }
+    };
+    for modifier in ["DISTINCT", "ALL"] {
+        let next = start + mask[start..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+        if find_keyword(&mask, modifier, next) == Some(next) {
+            start = next + modifier.len();
+        }
+    }
+    let next = start + mask[start..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+    if find_keyword(&mask, "ON", next) == Some(next) {
+        // DISTINCT ON (...)
+        start = mask[next..].iter().position(|&b| b == b')').map_or(mask.len(), |i| next + i + 1);
+    }
+    let end = ["FROM", "INTO", "WHERE", "GROUP", "HAVING", "WINDOW", "ORDER", "LIMIT", "OFFSET", "UNION", "INTERSECT", "EXCEPT", "FOR"]
+        .iter()
+        .filter_map(|keyword| find_keyword(&mask, keyword, start))
+        .min()
+        .unwrap_or(mask.len());
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut item_start = start;
+    for i in start..=end {
+        if i < end && mask[i] != b',' {
+            continue;
+        }
+        let item = &sql[item_start..i];
+        let item_mask = &mask[item_start..i];
+        let text = item.trim().trim_end_matches(';').trim();
+        item_start = i + 1;
+        if text.is_empty() {
+            return Err("empty column in the SQL".to_string());
+        }
+        if text == "*" || text.ends_with(".*") {
+            return Ok(None);
+        }
+        let name = match (0..item_mask.len()).rev().find(|&j| find_keyword(item_mask, "AS", j) == Some(j)) {
+            Some(j) => item[j + "AS".len()..].trim().trim_end_matches(';').trim(),
+            None => {
+                let last = text.rsplit('.').next().unwrap_or(text);
+                let qualified = text.split('.').all(|part| is_sql_identifier(part) || is_quoted(part));
+                if !qualified {
+                    return Err(format!("name the column `{}` with AS to map it", text));
+                }
+                last
+            }
+        };
+        let name = if is_quoted(name) { &name[1..name.len() - 1] } else { name };
+        if !is_sql_identifier(name) {
+            return Err(format!("the column `{}` is not a valid field name, rename it with AS", name));
+        }
+        if columns.iter().any(|column| column == name) {
+            return Err(format!("the column `{}` is selected twice", name));
+        }
+        columns.push(name.to_string());
+    }
+    Ok(Some(columns))
+}
+
+fn generate_sql(input: SqlInput) -> SynResult<TokenStream2> {
+    let span = input.template.span();
+    let (sql, params) = sql_placeholders(&input.template.value()).map_err(|e| syn::Error::new(span, e))?;
+    let expected = params.iter().filter(|param| matches!(param, SqlParam::Next)).count();
+    if expected != input.args.len() {
+        return Err(syn::Error::new(
+            span,
+            format!("the SQL has {} `{{}}` parameters but {} arguments are given", expected, input.args.len()),
+        ));
+    }
+    let mut args = input.args.iter();
+    let mut binds = Vec::new();
+    for param in &params {
+        let expr = match param {
+            SqlParam::Next => args.next().expect("checked above").to_token_stream(),
+            SqlParam::Inline(text) => syn::parse_str::<Expr>(text)
+                .map_err(|e| syn::Error::new(span, format!("invalid parameter `{{{}}}`: {}", text, e)))?
+                .to_token_stream(),
+        };
+        binds.push(quote! { .bind(&(#expr)) });
+    }
+    let query = quote! { starberry_sql::SqlQuery::new(#sql).numbered() #(#binds)* };
+
+    if let Some(ty) = &input.row_type {
+        return Ok(quote! { #query.typed::<#ty>() });
+    }
+    let Some(columns) = sql_columns(&sql).map_err(|e| syn::Error::new(span, e))? else {
+        return Ok(query);
+    };
+    let mut fields = Vec::new();
+    for column in &columns {
+        let ident = match syn::parse_str::<Ident>(column) {
+            Ok(ident) => ident,
+            Err(_) if !matches!(column.as_str(), "self" | "Self" | "super" | "crate" | "_") => Ident::new_raw(column, Span::call_site()),
+            Err(_) => return Err(syn::Error::new(span, format!("the column `{}` is not a valid field name, rename it with AS", column))),
+        };
+        fields.push(ident);
+    }
+    Ok(quote! {
+        {
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            #[allow(non_snake_case)]
+            struct SqlRow {
+                #(pub #fields: String,)*
+            }
+            impl starberry_sql::FromRow for SqlRow {
+                fn from_row(
+                    row: &::std::collections::HashMap<String, String>,
+                ) -> ::std::result::Result<Self, starberry_sql::DbError> {
+                    Ok(Self { #(#fields: starberry_sql::column_value(row, #columns)?,)* })
+                }
+            }
+            #query.typed::<SqlRow>()
+        }
+    })
+}
@@ -12,12 +12,22 @@ pub use starberry_core::app::urls::path_pattern_creator::{
     any_path as AnyPath, 
 }; 
 
-pub use starberry_core::app::middleware::AsyncMiddleware; 
+pub use starberry_core::app::middleware::{AsyncMiddleware, SkipMiddleware}; 
 pub use starberry_core::app::protocol::{ProtocolHandlerBuilder, ProtocolRegistryKind, ProtocolRegistryBuilder}; 
+pub use starberry_core::app::task::{TaskTracker, current_request_id}; 
+pub use starberry_core::app::events::{EventBus, Subscriber}; 
+pub use starberry_core::app::hub::{Hub, Membership, MemberId, MemberInfo}; 
+pub use starberry_core::app::schedule::{Job, Schedule, CronExpr, every, cron}; 
+pub use starberry_core::app::webhook_queue::{DirWebhookStore, MemoryWebhookStore, OutgoingWebhook, RetryPolicy, WebhookQueue, WebhookStore}; 
+pub use starberry_core::app::reporter::{ErrorKind, ErrorReport, ErrorReporter, SharedErrorReporter}; 
+#[cfg(feature = "sentry")] 
+pub use starberry_core::app::sentry::SentryReporter; 
+pub use starberry_core::app::assets::AssetManifest; 
 
 pub use starberry_core::Value; 
 pub use starberry_core::TemplateManager; 
 pub use starberry_core::object; 
+pub use starberry_core::serve_embedded; 
 
 pub use starberry_core::connection::{Rx, Tx};  
 pub use starberry_core::connection::{Connection, ConnectionBuilder}; 
@@ -34,10 +44,43 @@ pub use starberry_core::http::http_value::*;
 pub use starberry_core::http::cookie::*; 
 pub use starberry_core::http::body::*; 
 pub use starberry_core::http::form::*; 
+pub use starberry_core::http::query::{FromQuery, FromQueryValue, QueryErrors, QueryError, QueryMap}; 
+pub use starberry_core::http::validate::{Validate, ValidationErrors, Violation, ExtractError}; 
+pub use starberry_core::http::bound_form::{BoundForm, Form, FormField, FormValue}; 
+pub use starberry_core::http::pagination::{Pagination, Page, PageLinks}; 
+pub use starberry_core::http::long_poll::{LongPoll, PollOutcome}; 
 pub use starberry_core::http::encoding::*; 
+pub use starberry_core::http::compression::{CompressionConfig, CompressionLevels}; 
 pub use starberry_core::http::safety::HttpSafety;
+pub use starberry_core::http::proxy::{Cidr, ForwardedHeader, InvalidCidr, TrustedProxies}; 
+pub use starberry_core::http::error::{ErrorContext, HttpError, IntoResponse}; 
+pub use starberry_core::http::problem::Problem; 
+pub use starberry_core::http::xml::XmlError; 
+pub use starberry_core::http::feed::{Feed, FeedItem}; 
+pub use starberry_core::http::sanitize::{HtmlSanitizer, sanitize_html}; 
+pub use starberry_core::http::markdown::{MarkdownRenderer, markdown}; 
+pub use starberry_core::http::temp_file::TempFile; 
+pub use starberry_core::http::circuit_breaker::{BreakerError, BreakerSettings, CircuitBreaker, CircuitState}; 
+pub use starberry_core::http::webhook::{ReplayCache, SignatureScheme, WebhookError, WebhookRoute, WebhookVerifier}; 
+pub use starberry_core::app::sitemap::{ChangeFreq, Sitemap, SitemapEntry}; 
+pub use starberry_core::app::well_known::{RobotsTxt, SecurityTxt, WellKnown}; 
+pub use starberry_core::app::upgrade::GracefulUpgrade; 
+pub use starberry_core::app::reload::{ConfigChange, RuntimeConfig}; 
+pub use starberry_core::app::timeouts::RouteTimeouts; 
+pub use starberry_core::app::secrets::{ChainedSecrets, EnvSecrets, FileSecrets, Secret, SecretError, SecretsProvider, SharedSecrets}; 
+#[cfg(feature = "vault")] 
+pub use starberry_core::app::vault::VaultSecrets; 
+#[cfg(feature = "kms")] 
+pub use starberry_core::app::kms::KmsSecrets; 
+pub use starberry_core::app::acme::{AcmeChallenges, CertStore, DirCertStore, StoredCertificate}; 
+#[cfg(feature = "acme")] 
+pub use starberry_core::app::acme::{AcmeClient, AcmeError, ChallengeType, LETS_ENCRYPT, LETS_ENCRYPT_STAGING}; 
+pub use starberry_core::app::tls::TlsCertificates; 
+pub use starberry_core::app::health::{HealthChecks, HealthEndpoints, HealthStatus, Readiness, cache_check, pool_check, upstream_check}; 
+pub use starberry_core::app::storage::{ByteStream, LocalStorage, S3Storage, SharedStorage, Storage}; 
 
 pub use starberry_core::extensions::*; 
+pub use starberry_core::cache::{Cache, CacheExt, CacheError, MemoryCache, SharedCache}; 
 
 pub use starberry_core; 
 pub use akari; 
@@ -48,6 +91,10 @@ pub use starberry_macro as sm;
 pub use sm::url; 
 pub use sm::middleware; 
 pub use sm::reg; 
+pub use sm::collect_routes; 
+pub use sm::FromQuery; 
+pub use sm::Validate; 
+pub use sm::Form; 
 
 pub use starberry_lib; 
 
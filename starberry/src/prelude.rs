@@ -1,6 +1,7 @@
 pub use once_cell::sync::Lazy; 
 pub use crate::Value;  
 pub use crate::object;  
+pub use crate::serve_embedded; 
 pub use crate::{App, RunMode}; 
 pub use crate::{LitUrl, RegUrl, PatUrl, AnyUrl, ArgUrl, AnyPath, TrailingSlash}; 
 pub use crate::urls::*; 
@@ -15,12 +16,18 @@ pub use crate::sm::akari_json;
 pub use crate::url; 
 pub use crate::middleware; 
 pub use crate::reg; 
+pub use crate::collect_routes; 
 pub use crate::HttpMethod::*; 
 pub use crate::HttpSafety; 
 pub use crate::{Cookie, CookieMap}; 
 pub use crate::StatusCode; 
+pub use crate::{HttpError, IntoResponse, Problem}; 
+pub use crate::{FromQuery, QueryErrors, Validate, ValidationErrors}; 
+pub use crate::{BoundForm, Form}; 
+pub use crate::{Pagination, Page}; 
 pub use crate::{MultiFormField, MultiFormFieldFile, ContentDisposition}; 
-pub use crate::AsyncMiddleware; 
+pub use crate::{AsyncMiddleware, SkipMiddleware}; 
+pub use crate::{every, cron}; 
 pub use crate::{Params, ParamsClone, Locals, LocalsClone}; // Always keep this in prelude 
 
 pub use std::sync::Arc; 
@@ -3,3 +3,27 @@ pub mod application;
 pub mod middleware; 
 pub mod config; 
 pub mod protocol; 
+pub mod task; 
+pub mod upgrade; 
+pub mod trace; 
+pub mod schedule; 
+pub mod events; 
+pub mod health; 
+pub mod reload; 
+pub mod secrets; 
+pub mod timeouts; 
+#[cfg(feature = "vault")] 
+pub mod vault; 
+#[cfg(feature = "kms")] 
+pub mod kms; 
+pub mod webhook_queue; 
+pub mod reporter; 
+#[cfg(feature = "sentry")] 
+pub mod sentry; 
+pub mod hub; 
+pub mod acme; 
+pub mod tls; 
+pub mod assets; 
+pub mod sitemap; 
+pub mod well_known; 
+pub mod storage; 
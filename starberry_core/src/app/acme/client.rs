@@ -0,0 +1,783 @@
+//! The ACME client (RFC 8555) ordering certificates from a CA such as Let's Encrypt.
+//!
+//! Requests are signed as JWS with an ES256 account key, kept in the `CertStore` so that
+//! the account is reused across restarts. The domains of an order are validated with
+//! HTTP-01, answered by `AcmeChallenges` on the plain HTTP listener, or with TLS-ALPN-01
+//! (RFC 8737), answered by the `TlsCertificates` of the TLS listener.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use akari::Value;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{
+    ECDSA_P256_SHA256_ASN1_SIGNING, ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, EcdsaSigningAlgorithm, KeyPair,
+};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use starberry_lib::encoding::{base64_encode, base64_url_encode};
+use starberry_lib::ende::digest::sha256;
+
+use super::der;
+use super::{AcmeChallenges, CertStore, RENEW_BEFORE, StoredCertificate};
+use crate::app::secrets::{json_body, member};
+use crate::app::task::TaskTracker;
+use crate::app::tls::TlsCertificates;
+use crate::http::body::HttpBody;
+use crate::http::context::HttpResCtx;
+use crate::http::http_value::{HttpContentType, HttpMethod, HttpVersion};
+use crate::http::meta::HttpMeta;
+use crate::http::request::HttpRequest;
+use crate::http::response::HttpResponse;
+use crate::http::safety::HttpSafety;
+use crate::http::start_line::HttpStartLine;
+use crate::object;
+
+/// The production directory of Let's Encrypt
+pub const LETS_ENCRYPT: &str = "https://acme-v02.api.letsencrypt.org/directory";
+/// The staging directory of Let's Encrypt, with higher rate limits and untrusted certificates
+pub const LETS_ENCRYPT_STAGING: &str = "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 30;
+/// A request failing with `badNonce` is sent again with the fresh nonce of the error
+const NONCE_RETRIES: usize = 3;
+
+/// How the CA validates that the domains of an order are ours
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChallengeType {
+    /// A token served under `/.well-known/acme-challenge/` on port 80
+    #[default]
+    Http01,
+    /// A validation certificate served for the `acme-tls/1` protocol on port 443
+    TlsAlpn01,
+}
+
+impl ChallengeType {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Http01 => "http-01",
+            Self::TlsAlpn01 => "tls-alpn-01",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AcmeError {
+    /// The CA could not be reached or gave an unexpected answer
+    Unavailable(String),
+    /// The CA refused a request, with the type and detail of its problem document
+    Problem { status: u16, kind: String, detail: String },
+    /// The CA found an authorization or the order invalid
+    Invalid(String),
+    /// The certificate store or a key failed
+    Io(io::Error),
+}
+
+impl fmt::Display for AcmeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable(msg) => write!(f, "ACME server unavailable: {}", msg),
+            Self::Problem { status, kind, detail } => write!(f, "ACME error {} ({}): {}", status, kind, detail),
+            Self::Invalid(msg) => write!(f, "ACME validation failed: {}", msg),
+            Self::Io(e) => write!(f, "ACME I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AcmeError {}
+
+impl From<io::Error> for AcmeError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Provisions the certificate of a set of domains and renews it before it expires
+///
+/// # Example
+/// ```rust,ignore
+/// let challenges = AcmeChallenges::new();
+/// let certs = TlsCertificates::new();
+/// let acme = AcmeClient::new(LETS_ENCRYPT, DirCertStore::new("certs"))
+///     .domain("example.com")
+///     .domain("www.example.com")
+///     .contact("mailto:admin@example.com")
+///     .challenges(challenges.clone())
+///     .tls(certs.clone());
+/// let app = App::new()
+///     .binding("0.0.0.0:443")
+///     .tls(certs)
+///     .redirect_http("0.0.0.0:80")
+///     .acme_challenges(challenges)
+///     .build();
+/// acme.start(app.tasks());
+/// app.run().await;
+/// ```
+#[derive(Clone)]
+pub struct AcmeClient {
+    directory: String,
+    domains: Vec<String>,
+    contact: Vec<String>,
+    challenge: ChallengeType,
+    store: Arc<dyn CertStore>,
+    challenges: AcmeChallenges,
+    tls: Option<TlsCertificates>,
+    nonce: Arc<Mutex<Option<String>>>,
+    timeout: Duration,
+    check_every: Duration,
+    retry_after: Duration,
+    safety: HttpSafety,
+}
+
+/// The endpoints listed by the directory of a CA
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+/// The ES256 key of an account, with its URL once the account is known to the CA
+struct Account {
+    key: EcdsaKeyPair,
+    kid: Option<String>,
+}
+
+impl Account {
+    /// The public key as a JWK, with its members in the order required by the thumbprint
+    fn jwk(&self) -> String {
+        // An uncompressed point, 0x04 followed by x and y
+        let point = self.key.public_key().as_ref();
+        format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            base64_url_encode(&point[1..33]),
+            base64_url_encode(&point[33..65])
+        )
+    }
+
+    /// The JWK thumbprint of RFC 7638
+    fn thumbprint(&self) -> String {
+        base64_url_encode(sha256(self.jwk().as_bytes()))
+    }
+
+    /// The flattened JWS of `payload`, an empty payload for a POST-as-GET
+    fn sign(&self, url: &str, nonce: &str, payload: &str) -> Result<String, AcmeError> {
+        let key = match &self.kid {
+            Some(kid) => format!(r#""kid":{}"#, Value::Str(kid.clone()).into_json()),
+            None => format!(r#""jwk":{}"#, self.jwk()),
+        };
+        let protected = format!(
+            r#"{{"alg":"ES256",{},"nonce":{},"url":{}}}"#,
+            key,
+            Value::Str(nonce.to_string()).into_json(),
+            Value::Str(url.to_string()).into_json()
+        );
+        let protected = base64_url_encode(protected);
+        let payload = base64_url_encode(payload);
+        let signature = self
+            .key
+            .sign(&SystemRandom::new(), format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| AcmeError::Io(io::Error::other("signing failed")))?;
+        let mut jws = object!({});
+        jws.set("protected", protected);
+        jws.set("payload", payload);
+        jws.set("signature", base64_url_encode(signature.as_ref()));
+        Ok(jws.into_json())
+    }
+}
+
+impl AcmeClient {
+    /// Creates a client of the CA at `directory`, keeping the account key and the issued
+    /// certificates in `store`
+    pub fn new<T: Into<String>, S: CertStore + 'static>(directory: T, store: S) -> Self {
+        Self {
+            directory: directory.into(),
+            domains: Vec::new(),
+            contact: Vec::new(),
+            challenge: ChallengeType::default(),
+            store: Arc::new(store),
+            challenges: AcmeChallenges::new(),
+            tls: None,
+            nonce: Arc::new(Mutex::new(None)),
+            timeout: DEFAULT_TIMEOUT,
+            check_every: Duration::from_secs(12 * 3600),
+            retry_after: Duration::from_secs(3600),
+            safety: HttpSafety::new(),
+        }
+    }
+
+    /// Adds a domain to the certificate. The first one names the certificate in the store
+    pub fn domain<T: Into<String>>(mut self, domain: T) -> Self {
+        self.domains.push(domain.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Adds a contact of the account, e.g. `mailto:admin@example.com`
+    pub fn contact<T: Into<String>>(mut self, contact: T) -> Self {
+        self.contact.push(contact.into());
+        self
+    }
+
+    /// Sets how the domains are validated, HTTP-01 by default
+    pub fn challenge(mut self, challenge: ChallengeType) -> Self {
+        self.challenge = challenge;
+        self
+    }
+
+    /// Publishes the HTTP-01 challenges in `challenges`, which must be the ones given to
+    /// `AppBuilder::acme_challenges`
+    pub fn challenges(mut self, challenges: AcmeChallenges) -> Self {
+        self.challenges = challenges;
+        self
+    }
+
+    /// Installs the certificates in the TLS listener using `certs`, which also answers the
+    /// TLS-ALPN-01 challenges
+    pub fn tls(mut self, certs: TlsCertificates) -> Self {
+        self.tls = Some(certs);
+        self
+    }
+
+    /// Sets how long each request to the CA may take, 30 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how often the expiry of the certificate is checked, every 12 hours by default.
+    /// A failed order is retried after an hour
+    pub fn check_every(mut self, interval: Duration) -> Self {
+        self.check_every = interval;
+        self
+    }
+
+    /// Loads the certificate from the store, orders a new one if there is none or if it
+    /// expires within `RENEW_BEFORE`, then installs it in the TLS listener
+    pub async fn provision(&self) -> Result<StoredCertificate, AcmeError> {
+        let Some(primary) = self.domains.first() else {
+            return Err(AcmeError::Invalid("no domain to certify".to_string()));
+        };
+        let cert = match self.store.load(primary).await? {
+            Some(cert) if !cert.needs_renewal(RENEW_BEFORE) => cert,
+            _ => {
+                let cert = self.issue().await?;
+                self.store.save(&cert).await?;
+                cert
+            }
+        };
+        if let Some(tls) = &self.tls {
+            for domain in &self.domains {
+                tls.insert_pem(domain.clone(), &cert.cert_chain_pem, &cert.private_key_pem)?;
+            }
+        }
+        Ok(cert)
+    }
+
+    /// Provisions the certificate now, then checks it periodically as a background task
+    /// until the tracker shuts down
+    pub fn start(&self, tasks: &TaskTracker) {
+        let client = self.clone();
+        let shutdown = tasks.shutdown_token();
+        tasks.spawn(async move {
+            loop {
+                let provisioned = tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    provisioned = client.provision() => provisioned,
+                };
+                let wait = match provisioned {
+                    Ok(_) => client.check_every,
+                    Err(e) => {
+                        eprintln!("ACME: {}", e);
+                        client.retry_after
+                    }
+                };
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+        });
+    }
+
+    /// Orders a new certificate for the domains, without saving nor installing it
+    pub async fn issue(&self) -> Result<StoredCertificate, AcmeError> {
+        let Some(primary) = self.domains.first().cloned() else {
+            return Err(AcmeError::Invalid("no domain to certify".to_string()));
+        };
+        let directory = self.directory().await?;
+        let mut account = Account {
+            key: self.account_key().await?,
+            kid: None,
+        };
+
+        let mut registration = object!({});
+        registration.set("termsOfServiceAgreed", true);
+        if !self.contact.is_empty() {
+            registration.set("contact", Value::List(self.contact.iter().cloned().map(Value::Str).collect()));
+        }
+        let response = self.post(&directory, &account, &directory.new_account, &registration.into_json()).await?;
+        account.kid = Some(location(&response)?);
+
+        let identifiers = self
+            .domains
+            .iter()
+            .map(|domain| {
+                let mut identifier = object!({});
+                identifier.set("type", "dns");
+                identifier.set("value", domain.clone());
+                identifier
+            })
+            .collect();
+        let mut order = object!({});
+        order.set("identifiers", Value::List(identifiers));
+        let response = self.post(&directory, &account, &directory.new_order, &order.into_json()).await?;
+        let order_url = location(&response)?;
+        let order = body(&response)?;
+        let Some(Value::List(authorizations)) = member(&order, "authorizations") else {
+            return Err(AcmeError::Unavailable("the order has no authorizations".to_string()));
+        };
+        for authorization in authorizations {
+            self.authorize(&directory, &account, &authorization.string()).await?;
+        }
+
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .map_err(|_| io::Error::other("key generation failed"))?;
+        let cert_key = key_pair(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())?;
+        let csr = der::csr(&self.domains, cert_key.public_key().as_ref(), |info| {
+            cert_key.sign(&rng, info).map(|s| s.as_ref().to_vec()).unwrap_or_default()
+        });
+        let finalize = text(&order, "finalize")?;
+        let mut request = object!({});
+        request.set("csr", base64_url_encode(&csr));
+        self.post(&directory, &account, &finalize, &request.into_json()).await?;
+        let order = self.poll(&directory, &account, &order_url).await?;
+
+        let certificate = text(&order, "certificate")?;
+        let response = self.post(&directory, &account, &certificate, "").await?;
+        let cert_chain_pem = match &response.body {
+            HttpBody::Text(text) => text.clone(),
+            HttpBody::Binary(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            _ => return Err(AcmeError::Unavailable("the certificate was not returned".to_string())),
+        };
+        let not_after = CertificateDer::pem_slice_iter(cert_chain_pem.as_bytes())
+            .next()
+            .and_then(Result::ok)
+            .and_then(|leaf| der::not_after(&leaf))
+            .ok_or_else(|| AcmeError::Unavailable("the certificate cannot be read".to_string()))?;
+        Ok(StoredCertificate {
+            domain: primary,
+            cert_chain_pem,
+            private_key_pem: pem("PRIVATE KEY", pkcs8.as_ref()),
+            not_after,
+        })
+    }
+
+    /// Completes the challenge of an authorization and waits until the CA validated it
+    async fn authorize(&self, directory: &Directory, account: &Account, url: &str) -> Result<(), AcmeError> {
+        let authorization = body(&self.post(directory, account, url, "").await?)?;
+        if text(&authorization, "status")? == "valid" {
+            return Ok(());
+        }
+        let domain = authorization.get("identifier").get("value").string();
+        let Some(Value::List(challenges)) = member(&authorization, "challenges") else {
+            return Err(AcmeError::Unavailable(format!("no challenge to authorize {}", domain)));
+        };
+        let wanted = self.challenge.name();
+        let Some(challenge) = challenges.iter().find(|c| c.get("type").string() == wanted) else {
+            return Err(AcmeError::Invalid(format!("the CA offers no {} challenge for {}", wanted, domain)));
+        };
+        let token = text(challenge, "token")?;
+        let key_authorization = format!("{}.{}", token, account.thumbprint());
+
+        match self.challenge {
+            ChallengeType::Http01 => self.challenges.insert(token.clone(), key_authorization),
+            ChallengeType::TlsAlpn01 => {
+                let Some(tls) = &self.tls else {
+                    return Err(AcmeError::Invalid("TLS-ALPN-01 needs the TlsCertificates of the listener".to_string()));
+                };
+                let (cert, key) = alpn_certificate(&domain, &key_authorization)?;
+                tls.insert_challenge(domain.clone(), cert, key)?;
+            }
+        }
+        let validated = async {
+            // An empty object asks the CA to validate the challenge
+            self.post(directory, account, &text(challenge, "url")?, "{}").await?;
+            self.poll(directory, account, url).await
+        }
+        .await;
+        match self.challenge {
+            ChallengeType::Http01 => {
+                self.challenges.remove(&token);
+            }
+            ChallengeType::TlsAlpn01 => {
+                if let Some(tls) = &self.tls {
+                    tls.remove_challenge(&domain);
+                }
+            }
+        }
+        validated.map(|_| ())
+    }
+
+    /// Fetches an authorization or an order until it is valid
+    async fn poll(&self, directory: &Directory, account: &Account, url: &str) -> Result<Value, AcmeError> {
+        for _ in 0..POLL_ATTEMPTS {
+            let resource = body(&self.post(directory, account, url, "").await?)?;
+            match text(&resource, "status")?.as_str() {
+                "valid" => return Ok(resource),
+                "invalid" => return Err(AcmeError::Invalid(invalid_reason(url, &resource))),
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+        Err(AcmeError::Unavailable(format!("{} is still not valid", url)))
+    }
+
+    /// Loads the account key from the store, or creates and saves one
+    async fn account_key(&self) -> Result<EcdsaKeyPair, AcmeError> {
+        if let Some(pem) = self.store.load_account_key().await? {
+            let der = PrivatePkcs8KeyDer::from_pem_slice(pem.as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+            return Ok(key_pair(&ECDSA_P256_SHA256_FIXED_SIGNING, der.secret_pkcs8_der())?);
+        }
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+            .map_err(|_| io::Error::other("key generation failed"))?;
+        self.store.save_account_key(&pem("PRIVATE KEY", pkcs8.as_ref())).await?;
+        Ok(key_pair(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref())?)
+    }
+
+    async fn directory(&self) -> Result<Directory, AcmeError> {
+        let response = self.send(HttpMethod::GET, &self.directory, None).await?;
+        let directory = body(&response)?;
+        Ok(Directory {
+            new_nonce: text(&directory, "newNonce")?,
+            new_account: text(&directory, "newAccount")?,
+            new_order: text(&directory, "newOrder")?,
+        })
+    }
+
+    async fn nonce(&self, directory: &Directory) -> Result<String, AcmeError> {
+        if let Some(nonce) = self.nonce.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            return Ok(nonce);
+        }
+        self.send(HttpMethod::GET, &directory.new_nonce, None).await?;
+        self.nonce
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .ok_or_else(|| AcmeError::Unavailable("no nonce was given".to_string()))
+    }
+
+    /// Sends a signed request, `payload` is empty for a POST-as-GET
+    async fn post(&self, directory: &Directory, account: &Account, url: &str, payload: &str) -> Result<HttpResponse, AcmeError> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let nonce = self.nonce(directory).await?;
+            let jws = account.sign(url, &nonce, payload)?;
+            let response = self.send(HttpMethod::POST, url, Some(jws)).await?;
+            let status = response.meta.start_line.status_code().as_u16();
+            if status < 400 {
+                return Ok(response);
+            }
+            let problem = json_body(&response.body).unwrap_or(Value::None);
+            let kind = problem.get("type").string();
+            if kind == "urn:ietf:params:acme:error:badNonce" && attempt < NONCE_RETRIES {
+                continue;
+            }
+            return Err(AcmeError::Problem {
+                status,
+                kind,
+                detail: problem.get("detail").string(),
+            });
+        }
+    }
+
+    async fn send(&self, method: HttpMethod, url: &str, jws: Option<String>) -> Result<HttpResponse, AcmeError> {
+        let (host, path) = split_url(url)?;
+        let mut meta = HttpMeta::new(
+            HttpStartLine::new_request(HttpVersion::Http11, method, path),
+            HashMap::new(),
+        );
+        let body = match jws {
+            Some(jws) => {
+                meta.set_content_type(HttpContentType::from_str("application/jose+json"));
+                meta.set_attribute("accept", "application/pem-certificate-chain, application/json");
+                HttpBody::Binary(jws.into_bytes())
+            }
+            None => HttpBody::Empty,
+        };
+        let sent = HttpResCtx::send_request(host.clone(), HttpRequest::new(meta, body), self.safety.clone());
+        let response = match tokio::time::timeout(self.timeout, sent).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => return Err(AcmeError::Unavailable(format!("{}: {}", host, e))),
+            Err(_) => return Err(AcmeError::Unavailable(format!("{} timed out", host))),
+        };
+        if let Some(nonce) = response.meta.get_header("replay-nonce") {
+            *self.nonce.lock().unwrap_or_else(|e| e.into_inner()) = Some(nonce);
+        }
+        Ok(response)
+    }
+}
+
+/// The validation certificate of TLS-ALPN-01 with its PKCS#8 key, both DER encoded
+fn alpn_certificate(domain: &str, key_authorization: &str) -> Result<(Vec<u8>, Vec<u8>), AcmeError> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+        .map_err(|_| io::Error::other("key generation failed"))?;
+    let key = key_pair(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())?;
+    let mut serial = [0u8; 16];
+    rng.fill(&mut serial).map_err(|_| io::Error::other("no randomness"))?;
+    let cert = der::alpn_certificate(
+        domain,
+        &sha256(key_authorization.as_bytes()),
+        &serial,
+        key.public_key().as_ref(),
+        |info| key.sign(&rng, info).map(|s| s.as_ref().to_vec()).unwrap_or_default(),
+    );
+    Ok((cert, pkcs8.as_ref().to_vec()))
+}
+
+fn key_pair(algorithm: &'static EcdsaSigningAlgorithm, pkcs8: &[u8]) -> io::Result<EcdsaKeyPair> {
+    EcdsaKeyPair::from_pkcs8(algorithm, pkcs8, &SystemRandom::new())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// PEM encodes a DER document
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64_encode(der);
+    let mut out = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+/// Splits an absolute URL into the scheme and authority, and the path
+fn split_url(url: &str) -> Result<(String, String), AcmeError> {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Err(AcmeError::Unavailable(format!("{} is not an absolute URL", url)));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    Ok((format!("{}://{}", scheme, authority), path.to_string()))
+}
+
+fn location(response: &HttpResponse) -> Result<String, AcmeError> {
+    response
+        .meta
+        .get_header("location")
+        .ok_or_else(|| AcmeError::Unavailable("the CA gave no location".to_string()))
+}
+
+fn body(response: &HttpResponse) -> Result<Value, AcmeError> {
+    json_body(&response.body).ok_or_else(|| AcmeError::Unavailable("the CA answered without JSON".to_string()))
+}
+
+fn text(value: &Value, key: &str) -> Result<String, AcmeError> {
+    match member(value, key) {
+        Some(Value::Str(text)) => Ok(text.clone()),
+        _ => Err(AcmeError::Unavailable(format!("the CA gave no {}", key))),
+    }
+}
+
+/// The detail of the error which made an authorization or an order invalid
+fn invalid_reason(url: &str, resource: &Value) -> String {
+    let errors = std::iter::once(resource.get("error"))
+        .chain(match member(resource, "challenges") {
+            Some(Value::List(challenges)) => challenges.iter().map(|c| c.get("error")).collect(),
+            _ => Vec::new(),
+        })
+        .map(|error| error.get("detail").string())
+        .filter(|detail| !detail.is_empty())
+        .collect::<Vec<_>>();
+    match errors.is_empty() {
+        true => format!("{} is invalid", url),
+        false => format!("{} is invalid: {}", url, errors.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tls::ACME_TLS_ALPN;
+    use ring::signature::{ECDSA_P256_SHA256_FIXED, UnparsedPublicKey};
+    use starberry_lib::encoding::base64_url_decode;
+
+    fn account() -> Account {
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+        Account {
+            key: key_pair(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap(),
+            kid: None,
+        }
+    }
+
+    #[test]
+    fn jws_is_signed_with_the_account_key() {
+        let mut account = account();
+        let jws = Value::from_json(&account.sign("https://ca.test/new-account", "n0nce", "{}").unwrap()).unwrap();
+        let protected = jws.get("protected").string();
+        let header = Value::from_json(&String::from_utf8(base64_url_decode(&protected).unwrap()).unwrap()).unwrap();
+        assert_eq!(header.get("alg").string(), "ES256");
+        assert_eq!(header.get("nonce").string(), "n0nce");
+        assert_eq!(header.get("url").string(), "https://ca.test/new-account");
+        assert_eq!(header.get("jwk").get("kty").string(), "EC");
+
+        let signed = format!("{}.{}", protected, jws.get("payload").string());
+        let signature = base64_url_decode(jws.get("signature").string()).unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, account.key.public_key().as_ref())
+            .verify(signed.as_bytes(), &signature)
+            .unwrap();
+
+        account.kid = Some("https://ca.test/acct/1".to_string());
+        let jws = Value::from_json(&account.sign("https://ca.test/order", "n1", "").unwrap()).unwrap();
+        let protected = base64_url_decode(jws.get("protected").string()).unwrap();
+        let header = Value::from_json(&String::from_utf8(protected).unwrap()).unwrap();
+        assert_eq!(header.get("kid").string(), "https://ca.test/acct/1");
+        assert_eq!(header.get("jwk"), &Value::None);
+        assert_eq!(jws.get("payload").string(), "");
+    }
+
+    #[test]
+    fn thumbprint_hashes_the_canonical_jwk() {
+        let account = account();
+        let jwk = account.jwk();
+        assert!(jwk.starts_with(r#"{"crv":"P-256","kty":"EC","x":""#));
+        assert_eq!(account.thumbprint(), base64_url_encode(sha256(jwk.as_bytes())));
+        assert_eq!(account.thumbprint().len(), 43);
+    }
+
+    #[test]
+    fn alpn_certificate_is_served_for_the_challenge() {
+        let (cert, key) = alpn_certificate("example.com", "token.thumbprint").unwrap();
+        let digest = sha256(b"token.thumbprint");
+        assert!(cert.windows(32).any(|w| w == digest));
+        assert!(der::not_after(&cert).is_some_and(|end| end > std::time::SystemTime::now()));
+
+        let certs = TlsCertificates::new();
+        certs.insert_challenge("Example.com", cert, key).unwrap();
+        certs.remove_challenge("example.com");
+    }
+
+    #[test]
+    fn installed_certificates_are_replaced() {
+        let (cert, key) = alpn_certificate("example.com", "a.b").unwrap();
+        let certs = TlsCertificates::new();
+        certs
+            .insert_pem("example.com", &pem("CERTIFICATE", &cert), &pem("PRIVATE KEY", &key))
+            .unwrap();
+        assert!(certs.contains("EXAMPLE.com"));
+        let (cert, key) = alpn_certificate("example.com", "c.d").unwrap();
+        certs
+            .insert_pem("example.com", &pem("CERTIFICATE", &cert), &pem("PRIVATE KEY", &key))
+            .unwrap();
+        assert!(certs.contains("example.com"));
+        assert!(certs.insert_pem("example.org", "", &pem("PRIVATE KEY", &key)).is_err());
+        assert!(certs.remove("example.com"));
+        assert!(!certs.contains("example.com"));
+    }
+
+    /// Accepts any certificate, the tests compare the one served with the expected one
+    #[derive(Debug)]
+    struct AnyCertificate;
+
+    impl rustls::client::danger::ServerCertVerifier for AnyCertificate {
+        fn verify_server_cert(
+            &self,
+            _: &CertificateDer<'_>,
+            _: &[CertificateDer<'_>],
+            _: &rustls::pki_types::ServerName<'_>,
+            _: &[u8],
+            _: rustls::pki_types::UnixTime,
+        ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+            Ok(rustls::client::danger::ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _: &[u8],
+            _: &CertificateDer<'_>,
+            _: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _: &[u8],
+            _: &CertificateDer<'_>,
+            _: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+            rustls::crypto::ring::default_provider()
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+
+    /// Connects to a server accepting with `certs` and returns the certificate it served,
+    /// and whether the server kept the connection
+    async fn served_certificate(certs: &TlsCertificates, alpn: &[u8]) -> (Vec<u8>, bool) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let certs = certs.clone();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            certs.accept(stream).await.unwrap().is_some()
+        });
+        let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AnyCertificate))
+            .with_no_client_auth();
+        config.alpn_protocols = vec![alpn.to_vec()];
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let name = rustls::pki_types::ServerName::try_from("example.com").unwrap();
+        let stream = tokio_rustls::TlsConnector::from(Arc::new(config)).connect(name, tcp).await.unwrap();
+        let served = stream.get_ref().1.peer_certificates().unwrap()[0].to_vec();
+        (served, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn handshakes_use_the_latest_certificate() {
+        let certs = TlsCertificates::new();
+        let (first, key) = alpn_certificate("example.com", "a.b").unwrap();
+        certs
+            .insert_pem("example.com", &pem("CERTIFICATE", &first), &pem("PRIVATE KEY", &key))
+            .unwrap();
+        assert_eq!(served_certificate(&certs, b"http/1.1").await, (first, true));
+
+        let (second, key) = alpn_certificate("example.com", "c.d").unwrap();
+        certs
+            .insert_pem("example.com", &pem("CERTIFICATE", &second), &pem("PRIVATE KEY", &key))
+            .unwrap();
+        assert_eq!(served_certificate(&certs, b"http/1.1").await, (second.clone(), true));
+
+        // A TLS-ALPN-01 validation gets the validation certificate and is closed
+        let (validation, key) = alpn_certificate("example.com", "token.thumbprint").unwrap();
+        certs.insert_challenge("example.com", validation.clone(), key).unwrap();
+        assert_eq!(served_certificate(&certs, ACME_TLS_ALPN).await, (validation, false));
+        certs.remove_challenge("example.com");
+        assert_eq!(served_certificate(&certs, b"http/1.1").await, (second, true));
+    }
+
+    #[test]
+    fn splits_urls() {
+        let (host, path) = split_url("https://acme.test:14000/order/1?x=y").unwrap();
+        assert_eq!(host, "https://acme.test:14000");
+        assert_eq!(path, "/order/1?x=y");
+        assert_eq!(split_url("https://acme.test").unwrap().1, "/");
+        assert!(split_url("acme.test/order").is_err());
+    }
+}
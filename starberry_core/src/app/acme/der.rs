@@ -0,0 +1,275 @@
+//! The few DER structures an ACME client needs: the certificate signing request of an
+//! order (RFC 2986), the validation certificate of TLS-ALPN-01 (RFC 8737) and the end of
+//! the validity of an issued certificate.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use starberry_lib::date::{civil_from_days, days_from_civil};
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const BOOLEAN: u8 = 0x01;
+const OID: u8 = 0x06;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+/// `[2] IMPLICIT IA5String`, the dNSName of a GeneralName
+const DNS_NAME: u8 = 0x82;
+
+const EC_PUBLIC_KEY: &[u64] = &[1, 2, 840, 10045, 2, 1];
+const PRIME256V1: &[u64] = &[1, 2, 840, 10045, 3, 1, 7];
+const ECDSA_WITH_SHA256: &[u64] = &[1, 2, 840, 10045, 4, 3, 2];
+const SUBJECT_ALT_NAME: &[u64] = &[2, 5, 29, 17];
+const EXTENSION_REQUEST: &[u64] = &[1, 2, 840, 113549, 1, 9, 14];
+const ACME_IDENTIFIER: &[u64] = &[1, 3, 6, 1, 5, 5, 7, 1, 31];
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn constructed(tag: u8, parts: &[Vec<u8>]) -> Vec<u8> {
+    tlv(tag, &parts.concat())
+}
+
+fn oid(arcs: &[u64]) -> Vec<u8> {
+    let mut content = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for &arc in &arcs[2..] {
+        let mut groups = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(groups.into_iter().rev());
+    }
+    tlv(OID, &content)
+}
+
+/// A positive INTEGER from big endian bytes
+fn unsigned(bytes: &[u8]) -> Vec<u8> {
+    let bytes: Vec<u8> = bytes.iter().copied().skip_while(|b| *b == 0).collect();
+    match bytes.first() {
+        None => tlv(INTEGER, &[0]),
+        Some(first) if first & 0x80 != 0 => tlv(INTEGER, &[&[0], bytes.as_slice()].concat()),
+        Some(_) => tlv(INTEGER, &bytes),
+    }
+}
+
+fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    tlv(BIT_STRING, &[&[0], bytes].concat())
+}
+
+fn utc_time(time: SystemTime) -> Vec<u8> {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    let text = format!(
+        "{:02}{:02}{:02}{:02}{:02}{:02}Z",
+        year % 100,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    );
+    tlv(UTC_TIME, text.as_bytes())
+}
+
+fn ecdsa_with_sha256() -> Vec<u8> {
+    constructed(SEQUENCE, &[oid(ECDSA_WITH_SHA256)])
+}
+
+/// The SubjectPublicKeyInfo of an uncompressed P-256 point
+fn p256_public_key(point: &[u8]) -> Vec<u8> {
+    constructed(
+        SEQUENCE,
+        &[constructed(SEQUENCE, &[oid(EC_PUBLIC_KEY), oid(PRIME256V1)]), bit_string(point)],
+    )
+}
+
+fn subject_alt_name(domains: &[String]) -> Vec<u8> {
+    let names: Vec<Vec<u8>> = domains.iter().map(|domain| tlv(DNS_NAME, domain.as_bytes())).collect();
+    constructed(
+        SEQUENCE,
+        &[oid(SUBJECT_ALT_NAME), tlv(OCTET_STRING, &constructed(SEQUENCE, &names))],
+    )
+}
+
+/// Signs `info` and wraps it with the algorithm as a certificate or a request
+fn signed<F: FnOnce(&[u8]) -> Vec<u8>>(info: Vec<u8>, sign: F) -> Vec<u8> {
+    let signature = sign(&info);
+    constructed(SEQUENCE, &[info, ecdsa_with_sha256(), bit_string(&signature)])
+}
+
+/// A certificate signing request for `domains`, with an empty subject as allowed by
+/// RFC 8555 7.4. `sign` gives the ASN.1 ECDSA P-256 signature of its input
+pub(crate) fn csr<F: FnOnce(&[u8]) -> Vec<u8>>(domains: &[String], public_key: &[u8], sign: F) -> Vec<u8> {
+    let extensions = constructed(SEQUENCE, &[subject_alt_name(domains)]);
+    let attribute = constructed(
+        SEQUENCE,
+        &[oid(EXTENSION_REQUEST), constructed(SET, &[extensions])],
+    );
+    let info = constructed(
+        SEQUENCE,
+        &[
+            unsigned(&[0]),
+            constructed(SEQUENCE, &[]),
+            p256_public_key(public_key),
+            constructed(0xa0, &[attribute]),
+        ],
+    );
+    signed(info, sign)
+}
+
+/// The self signed certificate answering the TLS-ALPN-01 challenge of `domain`, which
+/// carries the SHA-256 digest of the key authorization in a critical acmeIdentifier
+/// extension. `sign` gives the ASN.1 ECDSA P-256 signature of its input
+pub(crate) fn alpn_certificate<F: FnOnce(&[u8]) -> Vec<u8>>(
+    domain: &str,
+    digest: &[u8; 32],
+    serial: &[u8],
+    public_key: &[u8],
+    sign: F,
+) -> Vec<u8> {
+    let now = SystemTime::now();
+    let identifier = constructed(
+        SEQUENCE,
+        &[oid(ACME_IDENTIFIER), tlv(BOOLEAN, &[0xff]), tlv(OCTET_STRING, &tlv(OCTET_STRING, digest))],
+    );
+    let extensions = constructed(SEQUENCE, &[subject_alt_name(&[domain.to_string()]), identifier]);
+    let info = constructed(
+        SEQUENCE,
+        &[
+            // Version 3
+            constructed(0xa0, &[unsigned(&[2])]),
+            unsigned(serial),
+            ecdsa_with_sha256(),
+            constructed(SEQUENCE, &[]),
+            constructed(
+                SEQUENCE,
+                &[utc_time(now - Duration::from_secs(86400)), utc_time(now + Duration::from_secs(7 * 86400))],
+            ),
+            constructed(SEQUENCE, &[]),
+            p256_public_key(public_key),
+            constructed(0xa3, &[extensions]),
+        ],
+    );
+    signed(info, sign)
+}
+
+/// Splits the first element of `input` into its tag and content, and the rest of the input
+fn read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// The end of the validity of a DER certificate
+pub(crate) fn not_after(cert: &[u8]) -> Option<SystemTime> {
+    let (SEQUENCE, cert, _) = read(cert)? else { return None };
+    let (SEQUENCE, tbs, _) = read(cert)? else { return None };
+    let (tag, _, mut rest) = read(tbs)?;
+    // The version is optional, the serial number follows it
+    if tag == 0xa0 {
+        (_, _, rest) = read(rest)?;
+    }
+    // Signature algorithm and issuer
+    (_, _, rest) = read(rest)?;
+    (_, _, rest) = read(rest)?;
+    let (SEQUENCE, validity, _) = read(rest)? else { return None };
+    let (_, _, validity) = read(validity)?;
+    let (tag, time, _) = read(validity)?;
+    let time = std::str::from_utf8(time).ok()?;
+    let (year, rest) = match tag {
+        UTC_TIME => {
+            let year: i64 = time.get(..2)?.parse().ok()?;
+            (if year < 50 { 2000 + year } else { 1900 + year }, time.get(2..)?)
+        }
+        GENERALIZED_TIME => (time.get(..4)?.parse().ok()?, time.get(4..)?),
+        _ => return None,
+    };
+    if rest.len() != 11 || !rest.ends_with('Z') || !rest[..10].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| rest[range].parse::<u32>().unwrap_or(0);
+    let (month, day) = (field(0..2), field(2..4));
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + field(4..6) as i64 * 3600 + field(6..8) as i64 * 60 + field(8..10) as i64;
+    UNIX_EPOCH.checked_add(Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_oids_and_lengths() {
+        assert_eq!(oid(PRIME256V1), [0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07]);
+        assert_eq!(oid(ACME_IDENTIFIER), [0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x1f]);
+        assert_eq!(&tlv(OCTET_STRING, &[0; 200])[..3], [0x04, 0x81, 200]);
+        assert_eq!(&tlv(OCTET_STRING, &[0; 300])[..4], [0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(unsigned(&[0x80]), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(unsigned(&[0, 0, 5]), [0x02, 0x01, 0x05]);
+    }
+
+    #[test]
+    fn reads_the_end_of_validity() {
+        let not_before = tlv(UTC_TIME, b"240101000000Z");
+        let end = tlv(GENERALIZED_TIME, b"20270315123456Z");
+        let tbs = constructed(
+            SEQUENCE,
+            &[
+                constructed(0xa0, &[unsigned(&[2])]),
+                unsigned(&[7]),
+                ecdsa_with_sha256(),
+                constructed(SEQUENCE, &[]),
+                constructed(SEQUENCE, &[not_before, end]),
+            ],
+        );
+        let cert = constructed(SEQUENCE, &[tbs]);
+        let expected = starberry_lib::date::parse_rfc3339("2027-03-15T12:34:56Z");
+        assert_eq!(not_after(&cert), expected);
+        assert_eq!(not_after(&cert[..cert.len() - 1]), None);
+    }
+
+    #[test]
+    fn csr_lists_the_domains() {
+        let domains = vec!["example.com".to_string(), "www.example.com".to_string()];
+        let csr = csr(&domains, &[4; 65], |_| vec![0x30, 0x00]);
+        let (SEQUENCE, content, rest) = read(&csr).unwrap() else { panic!("not a sequence") };
+        assert!(rest.is_empty());
+        let (SEQUENCE, info, signature) = read(content).unwrap() else { panic!("no request info") };
+        assert!(info.windows(11).any(|w| w == b"example.com"));
+        assert!(info.windows(15).any(|w| w == b"www.example.com"));
+        let (SEQUENCE, _, signature) = read(signature).unwrap() else { panic!("no algorithm") };
+        assert_eq!(read(signature).unwrap().1, [0, 0x30, 0x00]);
+    }
+}
@@ -1,21 +1,42 @@
 use core::panic;
 // use std::collections::HashMap; 
+use tokio::io::{BufReader, BufWriter};
 use tokio::net::{TcpListener, TcpStream};
 
 // use starberry_lib::random_string;
 // use std::future::Future;
 // use std::pin::Pin; 
-use std::sync::Arc;
-use std::time::Duration;
-// use tokio::runtime::Runtime;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, Runtime};
 
+use crate::app::acme::AcmeChallenges;
 use crate::app::protocol::{ProtocolHandlerBuilder, ProtocolRegistryBuilder};
+use crate::app::events::EventBus;
+use crate::app::health::{HealthChecks, HealthEndpoints, HealthStatus};
+use crate::app::reload::RuntimeConfig;
+use crate::app::reporter::{ErrorReport, ErrorReporter, SharedErrorReporter};
+use crate::app::schedule::{Job, Schedule};
+use crate::app::secrets::{SecretsProvider, SharedSecrets};
+use crate::app::storage::{SharedStorage, Storage};
+use crate::app::task::TaskTracker;
+use crate::app::timeouts::RouteTimeouts;
+use crate::app::tls::TlsCertificates;
+use crate::app::upgrade::{self, GracefulUpgrade, UpgradeSignal};
+use crate::app::well_known::WellKnown;
 use crate::app::urls;
-use crate::connection::Connection;
+use crate::cache::{Cache, SharedCache};
+use crate::connection::{CancelReason, CancellationToken, Connection};
 use crate::connection::Rx;
 
 use crate::extensions::{Params, Locals}; 
+use crate::http::compression::CompressionConfig;
 use crate::http::context::HttpReqCtx;
+use crate::http::http_value::{HttpContentType, StatusCode, StrictTransportSecurity};
+use crate::http::meta::HttpMeta;
+use crate::http::proxy::TrustedProxies;
+use crate::http::response::response_templates;
+use crate::http::safety::HttpSafety;
 
 // use super::middleware::AsyncMiddleware;
 use super::protocol::ProtocolRegistryKind;
@@ -41,10 +62,21 @@ pub struct App {
     pub binding_address: String,
     pub handler: ProtocolRegistryKind, // Changed from listener to binding_address
     pub mode: RunMode,
-    pub worker: usize, // Did not implemented
+    pub worker: usize, 
+    pub blocking_threads: Option<usize>, 
+    pub thread_name: String, 
+    pub runtime: Option<Handle>, 
     pub max_connection_time: usize, 
+    pub http_redirect: Option<String>, 
+    pub https_port: u16, 
     pub config: Params,
     pub statics: Locals,
+    state: RwLock<Params>,
+    tasks: TaskTracker,
+    connections: TaskTracker,
+    jobs: Mutex<Option<Vec<Job>>>,
+    events: EventBus,
+    health: HealthChecks,
 }
 
 /// Builder for App
@@ -53,9 +85,15 @@ pub struct AppBuilder {
     handler: Option<ProtocolRegistryKind>,
     mode: Option<RunMode>,
     worker: Option<usize>,
+    blocking_threads: Option<usize>, 
+    thread_name: Option<String>, 
+    runtime: Option<Handle>, 
     max_connection_time: Option<usize>, 
+    http_redirect: Option<String>, 
+    https_port: Option<u16>, 
     config: Params, 
     statics: Locals, 
+    state: Params, 
 }
 
 impl AppBuilder {
@@ -65,9 +103,15 @@ impl AppBuilder {
             handler: None,
             mode: None,
             worker: None,
+            blocking_threads: None, 
+            thread_name: None, 
+            runtime: None, 
             max_connection_time: None, 
+            http_redirect: None, 
+            https_port: None, 
             config: Params::new(),  
             statics: Locals::new(), 
+            state: Params::new(), 
         }
     }
 
@@ -106,18 +150,123 @@ impl AppBuilder {
         self
     }
 
-    /// This function is currently useless 
+    /// Set the number of worker threads of the runtime built by `App::start`. Defaults to the CPU count 
     pub fn worker(mut self, threads: usize) -> Self {
         self.worker = Some(threads);
         self
     }
 
+    /// Set the maximum number of threads in the blocking pool of the runtime built by `App::start`. 
+    /// Uses the tokio default when not set 
+    pub fn blocking_threads(mut self, threads: usize) -> Self {
+        self.blocking_threads = Some(threads);
+        self
+    }
+
+    /// Set the name of the threads of the runtime built by `App::start`. Defaults to "starberry-worker" 
+    pub fn thread_name<T: Into<String>>(mut self, name: T) -> Self {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Run the application on an existing runtime instead of building one in `App::start`. 
+    /// The worker, blocking pool and thread name settings are ignored in this case 
+    pub fn runtime(mut self, handle: Handle) -> Self {
+        self.runtime = Some(handle);
+        self
+    }
+
     /// Set the maximum connection time for the application 
     pub fn max_connection_time(mut self, max_connection_time: usize) -> Self {
         self.max_connection_time = Some(max_connection_time);
         self
     } 
 
+    /// Run a companion plain HTTP listener on `binding`, e.g. "0.0.0.0:80", which answers every 
+    /// request with a 301 redirect to the same host and path over HTTPS 
+    pub fn redirect_http<T: Into<String>>(mut self, binding: T) -> Self {
+        self.http_redirect = Some(binding.into());
+        self
+    } 
+
+    /// Set the port HTTP requests are redirected to by `redirect_http`. Defaults to 443 
+    pub fn https_port(mut self, port: u16) -> Self {
+        self.https_port = Some(port);
+        self
+    } 
+
+    /// Send a `Strict-Transport-Security` header with every response which does not set its own. 
+    /// Browsers ignore it unless the response arrives over HTTPS 
+    /// # Example 
+    /// ```rust,ignore 
+    /// App::new().redirect_http("0.0.0.0:80").hsts(StrictTransportSecurity::preload()) 
+    /// ``` 
+    pub fn hsts(mut self, hsts: StrictTransportSecurity) -> Self {
+        self.config.set(hsts);
+        self
+    } 
+
+    /// Answer the ACME HTTP-01 challenges published in `challenges` on the listener of 
+    /// `redirect_http`, so that certificates can be issued for the domains of the application 
+    pub fn acme_challenges(mut self, challenges: AcmeChallenges) -> Self {
+        self.config.set(challenges);
+        self
+    } 
+
+    /// Accept TLS connections on the binding address, with the certificates of `certs`. 
+    /// They can be replaced while the application runs, e.g. by `AcmeClient` 
+    pub fn tls(mut self, certs: TlsCertificates) -> Self {
+        self.config.set(certs);
+        self
+    } 
+
+    /// Serve `/robots.txt` and the documents under `/.well-known/`. They are kept in the 
+    /// config and their routes are registered by `build` 
+    pub fn well_known(mut self, well_known: WellKnown) -> Self {
+        self.config.set(well_known);
+        self
+    } 
+
+    /// Hand the listening socket over to a new process of the executable on `SIGUSR2`, then drain 
+    /// the open connections and exit, so that a deployed binary is started without dropping any 
+    /// connection. Unix only 
+    /// # Example 
+    /// ```rust,ignore 
+    /// App::new().graceful_upgrade(GracefulUpgrade::new().ready_timeout(Duration::from_secs(10))) 
+    /// ``` 
+    pub fn graceful_upgrade(mut self, upgrade: GracefulUpgrade) -> Self {
+        self.config.set(upgrade);
+        self
+    } 
+
+    /// Serve the liveness on `/healthz` and the readiness on `/readyz`, which runs the checks 
+    /// registered with `App::health_check`. Their routes are registered by `build` 
+    pub fn health_endpoints(mut self, endpoints: HealthEndpoints) -> Self {
+        self.config.set(endpoints);
+        self
+    } 
+
+    /// Set how responses are compressed by the `Compression` middleware: levels, smallest body, 
+    /// content types and per route overrides 
+    pub fn compression(mut self, compression: CompressionConfig) -> Self {
+        self.config.set(compression);
+        self
+    } 
+
+    /// Set time limits per route, which may be longer or shorter than the max connection time. 
+    /// The rules can be read from a `RuntimeConfig`, see `RouteTimeouts::from_config` 
+    pub fn route_timeouts(mut self, timeouts: RouteTimeouts) -> Self {
+        self.config.set(timeouts);
+        self
+    } 
+
+    /// Believe the forwarding header of these proxies, `X-Forwarded-For` unless set otherwise, so that 
+    /// `HttpReqCtx::client_ip` gives the address of the client rather than the one of the proxy 
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.config.set(proxies);
+        self
+    } 
+
     /// Set the FULL LOCAL HASHMAP for the application 
     pub fn statics(mut self, statics: Locals) -> Self {
         self.statics = statics; 
@@ -142,6 +291,40 @@ impl AppBuilder {
         self 
     }
 
+    /// Add a shared state to the application, such as a database pool or a template manager. 
+    /// It can be retrieved in handlers with `req.state::<T>()`. A state of the same type is replaced 
+    pub fn manage<T: Send + Sync + 'static>(mut self, state: T) -> Self { 
+        self.state.set(Arc::new(state)); 
+        self 
+    } 
+
+    /// Set the cache of the application, retrieved with `App::cache` or `req.cache()` 
+    pub fn cache<C: Cache + 'static>(self, cache: C) -> Self { 
+        self.manage::<SharedCache>(Arc::new(cache)) 
+    } 
+
+    /// Add settings reloaded while the server runs, retrieved with `req.state::<RuntimeConfig>()`. 
+    /// Their file is watched from the moment the server runs 
+    pub fn runtime_config(self, config: RuntimeConfig) -> Self { 
+        self.manage(config) 
+    } 
+
+    /// Set the provider of the secrets of the application, retrieved with `App::secrets`. 
+    /// Settings written `secret:<name>` are resolved through it with `RuntimeConfig::secret` 
+    pub fn secrets<P: SecretsProvider>(self, provider: P) -> Self { 
+        self.manage::<SharedSecrets>(Arc::new(provider)) 
+    } 
+
+    /// Set the storage of the files of the application, retrieved with `App::storage` or `req.storage()` 
+    pub fn storage<S: Storage>(self, storage: S) -> Self { 
+        self.manage::<SharedStorage>(Arc::new(storage)) 
+    } 
+
+    /// Set the reporter given the panics and 5xx responses of the requests, see `App::report_error` 
+    pub fn error_reporter<R: ErrorReporter>(self, reporter: R) -> Self { 
+        self.manage::<SharedErrorReporter>(Arc::new(reporter)) 
+    } 
+
     /// Build method: create the `App`, storing binding address without creating a TcpListener
     pub fn build(self) -> Arc<App> {
         let handler = match self.handler {
@@ -156,17 +339,38 @@ impl AppBuilder {
             .unwrap_or_else(|| String::from("127.0.0.1:3003"));
         let mode = self.mode.unwrap_or_else(|| RunMode::Development);
         let worker = self.worker.unwrap_or_else(|| num_cpus());
+        let thread_name = self
+            .thread_name
+            .unwrap_or_else(|| String::from("starberry-worker"));
         let max_connection_time = self.max_connection_time.unwrap_or_else(|| 5);  
 
-        Arc::new(App {
+        let app = Arc::new(App {
             handler,
             binding_address,
             mode,
             worker,
+            blocking_threads: self.blocking_threads, 
+            thread_name, 
+            runtime: self.runtime, 
             max_connection_time, 
+            http_redirect: self.http_redirect, 
+            https_port: self.https_port.unwrap_or(443), 
             config: self.config,
             statics: self.statics,
-        })
+            state: RwLock::new(self.state),
+            tasks: TaskTracker::new(),
+            connections: TaskTracker::new(),
+            jobs: Mutex::new(Some(Vec::new())),
+            events: EventBus::new(),
+            health: HealthChecks::new(),
+        });
+        if let Some(well_known) = app.config.get::<WellKnown>().cloned() {
+            well_known.register(&app);
+        }
+        if let Some(endpoints) = app.config.get::<HealthEndpoints>().cloned() {
+            endpoints.register(&app);
+        }
+        app
     }
 }
 
@@ -203,6 +407,150 @@ impl App {
         &self.statics
     } 
 
+    /// Add a shared state to a running application. A state of the same type is replaced, 
+    /// requests that already obtained the old state keep using it 
+    pub fn manage<T: Send + Sync + 'static>(self: &Arc<Self>, state: T) { 
+        self.state.write().unwrap_or_else(|e| e.into_inner()).set(Arc::new(state)); 
+    } 
+
+    /// Get a shared state added with `manage` 
+    pub fn state<T: Send + Sync + 'static>(self: &Arc<Self>) -> Option<Arc<T>> { 
+        self.state.read().unwrap_or_else(|e| e.into_inner()).get::<Arc<T>>().cloned() 
+    } 
+
+    /// Spawn a background task. The server waits for it, up to the max connection time, before shutting down. 
+    /// Watch `shutdown_token` in long-running tasks to stop early 
+    pub fn spawn_task<F>(self: &Arc<Self>, fut: F) -> tokio::task::JoinHandle<F::Output> 
+    where 
+        F: std::future::Future + Send + 'static, 
+        F::Output: Send + 'static, 
+    { 
+        self.tasks.spawn(fut) 
+    } 
+
+    /// Run a blocking closure on the blocking pool. The server waits for it before shutting down 
+    pub fn spawn_blocking<F, T>(self: &Arc<Self>, f: F) -> tokio::task::JoinHandle<T> 
+    where 
+        F: FnOnce() -> T + Send + 'static, 
+        T: Send + 'static, 
+    { 
+        self.tasks.spawn_blocking(f) 
+    } 
+
+    /// Get the token cancelled when the server starts shutting down 
+    pub fn shutdown_token(self: &Arc<Self>) -> CancellationToken { 
+        self.tasks.shutdown_token() 
+    } 
+
+    /// Get the cache set with `AppBuilder::cache` 
+    pub fn cache(self: &Arc<Self>) -> Option<SharedCache> { 
+        self.state::<SharedCache>().map(|cache| (*cache).clone()) 
+    } 
+
+    /// Get the secrets provider set with `AppBuilder::secrets` 
+    pub fn secrets(self: &Arc<Self>) -> Option<SharedSecrets> { 
+        self.state::<SharedSecrets>().map(|secrets| (*secrets).clone()) 
+    } 
+
+    /// Get the storage set with `AppBuilder::storage` 
+    pub fn storage(self: &Arc<Self>) -> Option<SharedStorage> { 
+        self.state::<SharedStorage>().map(|storage| (*storage).clone()) 
+    } 
+
+    /// Get the reporter set with `AppBuilder::error_reporter` 
+    pub fn error_reporter(self: &Arc<Self>) -> Option<SharedErrorReporter> { 
+        self.state::<SharedErrorReporter>().map(|reporter| (*reporter).clone()) 
+    } 
+
+    /// Give a report to the error reporter in a background task. Does nothing without reporter 
+    pub fn report_error(self: &Arc<Self>, report: ErrorReport) { 
+        if let Some(reporter) = self.error_reporter() { 
+            self.tasks.spawn(async move { reporter.report(report).await }); 
+        } 
+    } 
+
+    /// Run a job on a schedule, either an interval or a cron expression. 
+    /// Jobs added before the server runs are started with it, later ones start immediately. 
+    /// A run is skipped while the previous one is still in progress unless the schedule allows overlap 
+    /// # Example 
+    /// ```rust,ignore 
+    /// APP.schedule(every(5).minutes().with_jitter(Duration::from_secs(10)), clean_sessions); 
+    /// APP.schedule(cron("0 3 * * *"), || async { println!("Nightly job") }); 
+    /// ``` 
+    pub fn schedule<F, Fut>(self: &Arc<Self>, schedule: Schedule, job: F) 
+    where 
+        F: Fn() -> Fut + Send + Sync + 'static, 
+        Fut: std::future::Future<Output = ()> + Send + 'static, 
+    { 
+        self.add_job(Job::new(std::any::type_name::<F>(), schedule, job)); 
+    } 
+
+    /// Add a job created with `Job::new`, see `schedule` 
+    pub fn add_job(self: &Arc<Self>, job: Job) { 
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner()); 
+        match jobs.as_mut() { 
+            Some(pending) => pending.push(job), 
+            None => job.start(&self.tasks), 
+        } 
+    } 
+
+    /// Get the event bus of the application 
+    /// # Example 
+    /// ```rust,ignore 
+    /// APP.events().publish(UserRegistered { id }); 
+    /// let mut registrations = APP.events().subscribe::<UserRegistered>(); 
+    /// ``` 
+    pub fn events(self: &Arc<Self>) -> &EventBus { 
+        &self.events 
+    } 
+
+    /// Register a check run by the readiness endpoint, see `AppBuilder::health_endpoints`.
+    /// A check of the same name is replaced
+    /// # Example
+    /// ```rust,ignore
+    /// APP.health_check("cache", || cache_check(APP.cache().unwrap()));
+    /// ```
+    pub fn health_check<F, Fut>(self: &Arc<Self>, name: impl Into<String>, check: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = HealthStatus> + Send + 'static,
+    {
+        self.health.add(name, check);
+    }
+
+    /// Get the checks registered with `health_check`
+    pub fn health(self: &Arc<Self>) -> &HealthChecks {
+        &self.health
+    }
+
+    /// Run the handler for every event of type `T` published from now on, until the server shuts down. 
+    /// Must be called from within the tokio runtime 
+    pub fn on<T, F, Fut>(self: &Arc<Self>, handler: F) -> tokio::task::JoinHandle<()> 
+    where 
+        T: Send + Sync + 'static, 
+        F: Fn(Arc<T>) -> Fut + Send + Sync + 'static, 
+        Fut: std::future::Future<Output = ()> + Send + 'static, 
+    { 
+        let mut subscriber = self.events.subscribe::<T>(); 
+        let shutdown = self.tasks.shutdown_token(); 
+        self.tasks.spawn(async move { 
+            loop { 
+                tokio::select! { 
+                    _ = shutdown.cancelled() => return, 
+                    event = subscriber.recv() => match event { 
+                        Some(event) => handler(event).await, 
+                        None => return, 
+                    } 
+                } 
+            } 
+        }) 
+    } 
+
+    /// Get the tracker of the background tasks spawned on the application 
+    pub fn tasks(self: &Arc<Self>) -> &TaskTracker { 
+        &self.tasks 
+    } 
+
     /// This function add a new url to the app. It will be added to the root url
     /// # Arguments
     /// * `url` - The url to add. It should be a string.
@@ -229,44 +577,124 @@ impl App {
         }
     }
 
+    /// Mount a url tree, usually exported by a library crate, under the given prefix. 
+    /// The middlewares and config of the tree are carried along. 
+    /// # Example 
+    /// ```rust,ignore 
+    /// APP.mount("/blog", &blog::urls()); 
+    /// ``` 
+    pub fn mount<R: Rx + 'static>(self: &Arc<Self>, prefix: &str, tree: &Arc<Url<R>>) -> Arc<Url<R>> { 
+        match self.handler.mount::<R>(prefix, tree) { 
+            Ok(url) => url, 
+            Err(e) => { 
+                eprintln!("{}", e); 
+                urls::dangling_url() 
+            } 
+        } 
+    } 
+
     /// Handle a single connection
     pub fn handle_connection(self: Arc<Self>, stream: TcpStream) {
         let duration = Duration::from_secs(self.max_connection_time as u64);
+        // A route may be given more time, its requests are then limited by `HttpReqCtx::run`
+        let limit = match self.config.get::<RouteTimeouts>().and_then(RouteTimeouts::longest) {
+            Some(longest) => longest.max(duration),
+            None => duration,
+        };
         let app = self.clone();
-        // 1) spawn the actual connection job
-        // let handle = tokio::spawn(async move {
-        //     self.handler.run(app, Connection::Tcp(stream)).await;
-        // });
-        // 2) in parallel, sleep then abort
-        tokio::spawn(async move {
+        let cancel = CancellationToken::new();
+        let (stream, watcher) = match split_disconnect_watcher(stream) {
+            Ok(split) => split,
+            Err(e) => {
+                eprintln!("Failed to set up the connection: {e}");
+                return;
+            }
+        };
+        let tls = self.config.get::<TlsCertificates>().cloned();
+        let started = tokio::time::Instant::now();
+        self.connections.clone().spawn(async move {
+            let conn = match tls {
+                Some(tls) => match tokio::time::timeout(duration, tls.accept(stream)).await {
+                    Ok(Ok(Some(stream))) => Connection::ServerTls(stream),
+                    // A TLS-ALPN-01 validation is over once the handshake is done
+                    Ok(Ok(None)) => return,
+                    Ok(Err(e)) => {
+                        if app.get_mode() == RunMode::Build {
+                            eprintln!("TLS handshake failed: {e}");
+                        }
+                        return;
+                    }
+                    Err(_) => return,
+                },
+                None => Connection::Tcp(stream),
+            };
+            let conn_info = conn
+                .info()
+                .with_deadline(Instant::now() + duration)
+                .with_cancel(cancel.clone());
+            // 1) watch for the client going away while the request is processed
+            let watcher = watcher.map(|watcher| tokio::spawn(watch_disconnect(watcher, cancel.clone())));
+            // 2) in parallel, sleep then abort
             tokio::select! { 
-                _ = self.handler.run(app, Connection::Tcp(stream)) => {}, 
-                _ = tokio::time::sleep(duration) => {
+                _ = self.handler.run_with_info(app, conn_info, conn) => {}, 
+                _ = tokio::time::sleep_until(started + limit) => {
                     // Timed out: forcefully close
-                    eprintln!("⚠️ Connection timed out after {:?}", duration);
+                    cancel.cancel(CancelReason::Timeout);
+                    eprintln!("⚠️ Connection timed out after {:?}", limit);
                     // Note: dropping the reader/writer will close the socket
                 } 
             }  
-            // tokio::time::sleep(duration).await;
-            // if !handle.is_finished() {
-            //     handle.abort();
-            //     eprintln!("Connection timed out after {:?}", duration);
-            // }
+            // The watcher holds a duplicate of the socket, the connection is only
+            // closed once it is dropped as well
+            if let Some(watcher) = watcher {
+                watcher.abort();
+            }
         });
     }
 
+    /// Build a multi-threaded tokio runtime from the worker, blocking pool and thread name settings 
+    pub fn build_runtime(&self) -> std::io::Result<Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder
+            .worker_threads(self.worker.max(1))
+            .thread_name(self.thread_name.clone())
+            .enable_all();
+        if let Some(blocking_threads) = self.blocking_threads {
+            builder.max_blocking_threads(blocking_threads.max(1));
+        }
+        builder.build()
+    }
+
+    /// Run the application without a `#[tokio::main]` harness, blocking the current thread until shutdown. 
+    /// Uses the runtime given to `AppBuilder::runtime`, otherwise builds one with `build_runtime`. 
+    /// Must not be called from inside an async context 
+    /// # Example 
+    /// ```rust,ignore 
+    /// fn main() { 
+    ///     APP.clone().start(); 
+    /// } 
+    /// ``` 
+    pub fn start(self: Arc<Self>) {
+        if let Some(handle) = self.runtime.clone() {
+            return handle.block_on(self.run());
+        }
+        let runtime = match self.build_runtime() {
+            Ok(runtime) => runtime,
+            Err(e) => panic!("Failed to build the runtime: {}", e),
+        };
+        runtime.block_on(self.run());
+    }
+
     /// Main loop listening for connections - now creates the TcpListener at runtime
     pub async fn run(self: Arc<Self>) {
-        // let runtime = tokio::runtime::Builder::new_multi_thread()
-        // .worker_threads(self.worker)
-        // .enable_all()
-        // .build()
-        // .unwrap();
-
-        // Create TcpListener only when run() is called, within the tokio runtime
-        let listener = match TcpListener::bind(&self.binding_address).await {
-            Ok(listener) => listener,
-            Err(e) => panic!("Binding failed on {}: {}", self.binding_address, e),
+        // Create TcpListener only when run() is called, within the tokio runtime, 
+        // unless the process replaced by an upgrade handed its listener over 
+        let (listener, upgraded) = match upgrade::inherited_listener() {
+            Some(listener) => (listener, true),
+            None => match TcpListener::bind(&self.binding_address).await {
+                Ok(listener) => (listener, false),
+                Err(e) => panic!("Binding failed on {}: {}", self.binding_address, e),
+            },
         };
 
         println!(
@@ -274,6 +702,29 @@ impl App {
             listener.local_addr().unwrap()
         );
 
+        // Start the jobs scheduled before the server was running
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner()).take();
+        for job in jobs.unwrap_or_default() {
+            job.start(&self.tasks);
+        }
+        if let Some(config) = self.state::<RuntimeConfig>() {
+            config.start(&self.tasks);
+        }
+
+        let redirect = self
+            .http_redirect
+            .clone()
+            .map(|binding| {
+                let challenges = self.config.get::<AcmeChallenges>().cloned();
+                // After an upgrade, the replaced process holds the port until it has drained
+                let retry_for = if upgraded {
+                    Duration::from_secs(self.max_connection_time.max(1) as u64 + 5)
+                } else {
+                    Duration::ZERO
+                };
+                tokio::spawn(run_http_redirect(binding, self.https_port, challenges, retry_for))
+            });
+
         // Create a signal handler for clean shutdown
         let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel::<()>();
 
@@ -285,6 +736,10 @@ impl App {
             }
         });
 
+        let upgrade = self.config.get::<GracefulUpgrade>().cloned();
+        let mut upgrade_signal = UpgradeSignal::new(upgrade.is_some());
+        upgrade::notify_ready().await;
+
         loop {
             tokio::select! {
                 accept_result = listener.accept() => {
@@ -304,14 +759,135 @@ impl App {
                     println!("Shutting down server...");
                     break;
                 }
+                _ = upgrade_signal.recv() => {
+                    let Some(upgrade) = &upgrade else { continue };
+                    println!("Received upgrade signal, starting a new process");
+                    match upgrade::spawn_successor(&listener, upgrade).await {
+                        Ok(pid) => {
+                            println!("Process {} took over, draining connections", pid);
+                            break;
+                        }
+                        Err(e) => eprintln!("⚠️ Upgrade failed, still serving: {}", e),
+                    }
+                }
             }
         }
 
-        tokio::time::sleep(Duration::from_secs(1)).await;
+        if let Some(redirect) = redirect {
+            redirect.abort();
+        }
+
+        // Stop accepting, the new process of an upgrade keeps the socket open
+        drop(listener);
+        let grace = Duration::from_secs(self.max_connection_time.max(1) as u64);
+        if tokio::time::timeout(grace, self.connections.wait()).await.is_err() {
+            eprintln!("⚠️ {} connection(s) still open after {:?}", self.connections.running(), grace);
+        }
+        if !self.tasks.shutdown(grace).await {
+            eprintln!("⚠️ {} background task(s) still running after {:?}", self.tasks.running(), grace);
+        }
         println!("Server shutdown complete");
     }
 }
 
+/// Accepts plain HTTP connections and redirects each of them to HTTPS.
+/// Binding is retried for `retry_for` while the address is in use
+async fn run_http_redirect(binding: String, https_port: u16, challenges: Option<AcmeChallenges>, retry_for: Duration) {
+    let deadline = Instant::now() + retry_for;
+    let listener = loop {
+        match TcpListener::bind(&binding).await {
+            Ok(listener) => break listener,
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && Instant::now() < deadline => {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            Err(e) => {
+                eprintln!("HTTP redirect binding failed on {}: {}", binding, e);
+                return;
+            }
+        }
+    };
+    println!("Redirecting HTTP on {} to HTTPS", binding);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(_) => continue,
+        };
+        let challenges = challenges.clone();
+        tokio::spawn(async move {
+            let redirect = redirect_to_https(stream, https_port, challenges);
+            let _ = tokio::time::timeout(Duration::from_secs(5), redirect).await;
+        });
+    }
+}
+
+/// Reads the request head and answers with a permanent redirect to the HTTPS url,
+/// or with the key authorization of a pending ACME challenge
+async fn redirect_to_https(stream: TcpStream, https_port: u16, challenges: Option<AcmeChallenges>) -> std::io::Result<()> {
+    let (read, write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    let mut writer = BufWriter::new(write);
+    let mut response = match HttpMeta::from_stream(&mut reader, &HttpSafety::default(), false, true).await {
+        Ok(mut meta) => {
+            let key_authorization = challenges.and_then(|challenges| challenges.respond(&meta.path()));
+            match (key_authorization, meta.get_host()) {
+                (Some(key_authorization), _) => response_templates::normal_response(StatusCode::OK, key_authorization)
+                    .content_type(HttpContentType::TextPlain()),
+                (None, Some(host)) => response_templates::redirect_response(&https_url(&host, &meta.url(), https_port))
+                    .status(StatusCode::MOVED_PERMANENTLY),
+                (None, None) => response_templates::return_status(StatusCode::BAD_REQUEST),
+            }
+        }
+        Err(status) => response_templates::return_status(status),
+    };
+    response.send(&mut writer).await
+}
+
+/// The HTTPS url of a request received over HTTP, replacing the port of the host
+fn https_url(host: &str, target: &str, https_port: u16) -> String {
+    // Keep the colons of an IPv6 literal such as [::1]
+    let name = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    let target = if target.starts_with('/') { target } else { "/" };
+    if https_port == 443 {
+        format!("https://{}{}", name, target)
+    } else {
+        format!("https://{}:{}{}", name, https_port, target)
+    }
+}
+
+/// Duplicates the socket so that the peer can be watched for disconnection while
+/// the original stream is owned by the protocol handler.
+/// Returns no watcher if the socket cannot be duplicated.
+fn split_disconnect_watcher(stream: TcpStream) -> std::io::Result<(TcpStream, Option<TcpStream>)> {
+    let std_stream = stream.into_std()?;
+    let watcher = std_stream
+        .try_clone()
+        .ok()
+        .and_then(|watcher| TcpStream::from_std(watcher).ok());
+    Ok((TcpStream::from_std(std_stream)?, watcher))
+}
+
+/// Cancels the token once the peer has closed the connection.
+/// Peeking does not consume any byte, so the request body is left for the handler.
+async fn watch_disconnect(watcher: TcpStream, cancel: CancellationToken) {
+    let mut buf = [0u8; 1];
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            peeked = watcher.peek(&mut buf) => match peeked {
+                Ok(0) | Err(_) => {
+                    cancel.cancel(CancelReason::ClientDisconnected);
+                    return;
+                }
+                // Unread data is pending, check again later
+                Ok(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+}
+
 // Helper function for determining CPU count
 fn num_cpus() -> usize {
     match std::thread::available_parallelism() {
@@ -319,3 +895,16 @@ fn num_cpus() -> usize {
         Err(_) => 1, // Fallback if we can't determine
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::https_url;
+
+    #[test]
+    fn https_url_replaces_the_port() {
+        assert_eq!(https_url("example.com", "/a?b=c", 443), "https://example.com/a?b=c");
+        assert_eq!(https_url("example.com:80", "/", 8443), "https://example.com:8443/");
+        assert_eq!(https_url("[::1]:8080", "/x", 443), "https://[::1]/x");
+        assert_eq!(https_url("[::1]", "*", 443), "https://[::1]/");
+    }
+}
@@ -0,0 +1,267 @@
+//! Fingerprinted static assets.
+//!
+//! `AssetManifest::build` copies the files of a source directory to an output directory
+//! under names containing a digest of their content, e.g. `css/app.css` becomes
+//! `css/app.3f2a9c1d7e4b.css`, and records the names in `manifest.json`. A changed file
+//! gets a new url, so fingerprinted files are served with far-future caching headers.
+//!
+//! `AssetManifest::install` makes the urls available to every template as the `asset`
+//! dict, keyed by the original names, so templates never hard-code fingerprinted paths.
+//!
+//! # Example
+//! ```rust,ignore
+//! // At startup, or once at deployment followed by `AssetManifest::load("dist")`
+//! static ASSETS: Lazy<AssetManifest> = Lazy::new(|| {
+//!     let manifest = AssetManifest::build("assets", "dist").expect("Failed to build the assets");
+//!     manifest.install();
+//!     manifest
+//! });
+//!
+//! #[url(reg![&APP, LitUrl("static"), AnyPath()])]
+//! async fn assets() -> HttpResponse {
+//!     ASSETS.serve(&req.path())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use akari::Value;
+use starberry_lib::ende::digest::sha256_hex;
+
+use crate::http::http_value::{CacheControl, StatusCode};
+use crate::http::response::HttpResponse;
+use crate::http::response::response_templates::{
+    normal_response, return_status, set_template_global, static_content_type,
+};
+
+/// The file of the output directory holding the manifest
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The number of hex digits of the digest kept in fingerprinted names
+pub const FINGERPRINT_LENGTH: usize = 12;
+
+/// Fingerprinted files never change, they are cached for a year
+pub const IMMUTABLE_MAX_AGE: u64 = 365 * 24 * 3600;
+
+/// Inserts the digest of `content` before the extension of `name`:
+/// `css/app.css` becomes `css/app.<digest>.css`
+pub fn fingerprint(name: &str, content: &[u8]) -> String {
+    let digest = sha256_hex(content);
+    let digest = &digest[..FINGERPRINT_LENGTH];
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((dir, file)) => (Some(dir), file),
+        None => (None, name),
+    };
+    let file = match file.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}.{}.{}", stem, digest, extension),
+        _ => format!("{}.{}", file, digest),
+    };
+    match dir {
+        Some(dir) => format!("{}/{}", dir, file),
+        None => file,
+    }
+}
+
+/// The fingerprinted names of the assets and the directory holding them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetManifest {
+    dir: PathBuf,
+    prefix: String,
+    /// Original name to fingerprinted name, both relative to the asset directories
+    entries: HashMap<String, String>,
+}
+
+impl AssetManifest {
+    /// Fingerprints every file of `source` into `output` and writes the manifest there.
+    /// Names use `/` as separator on every platform
+    pub fn build<S: AsRef<Path>, O: Into<PathBuf>>(source: S, output: O) -> io::Result<Self> {
+        let source = source.as_ref();
+        let output = output.into();
+        let mut files = Vec::new();
+        collect_files(source, &output, &mut files)?;
+
+        let mut entries = HashMap::new();
+        for path in files {
+            let name = relative_name(source, &path);
+            let content = std::fs::read(&path)?;
+            let fingerprinted = fingerprint(&name, &content);
+            let destination = output.join(&fingerprinted);
+            if let Some(parent) = destination.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(destination, content)?;
+            entries.insert(name, fingerprinted);
+        }
+
+        let manifest = Self {
+            dir: output,
+            prefix: "/static/".to_string(),
+            entries,
+        };
+        manifest.save()?;
+        Ok(manifest)
+    }
+
+    /// Reads the manifest written by `build` in `dir`
+    pub fn load<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        let dir = dir.into();
+        let json = std::fs::read_to_string(dir.join(MANIFEST_FILE))?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid asset manifest");
+        let entries = match Value::from_json(&json).map_err(|_| invalid())? {
+            Value::Dict(map) => map
+                .into_iter()
+                .map(|(name, value)| match value {
+                    Value::Str(fingerprinted) => Ok((name, fingerprinted)),
+                    _ => Err(invalid()),
+                })
+                .collect::<io::Result<HashMap<_, _>>>()?,
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            dir,
+            prefix: "/static/".to_string(),
+            entries,
+        })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let map = self
+            .entries
+            .iter()
+            .map(|(name, fingerprinted)| (name.clone(), Value::Str(fingerprinted.clone())))
+            .collect();
+        std::fs::write(self.dir.join(MANIFEST_FILE), Value::Dict(map).into_json())
+    }
+
+    /// Sets the url path under which the assets are served, `/static/` by default
+    pub fn with_prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        let prefix = prefix.into();
+        let prefix = prefix.trim_matches('/');
+        self.prefix = if prefix.is_empty() { "/".to_string() } else { format!("/{}/", prefix) };
+        self
+    }
+
+    /// The fingerprinted name of an asset
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.get(name.trim_start_matches('/')).map(String::as_str)
+    }
+
+    /// The url of an asset. Unknown assets keep their name, so a missing file shows up
+    /// as a 404 instead of a broken page
+    pub fn url(&self, name: &str) -> String {
+        let name = name.trim_start_matches('/');
+        format!("{}{}", self.prefix, self.get(name).unwrap_or(name))
+    }
+
+    /// The urls of the assets keyed by their original names
+    pub fn to_value(&self) -> Value {
+        let map = self
+            .entries
+            .keys()
+            .map(|name| (name.clone(), Value::Str(self.url(name))))
+            .collect();
+        Value::Dict(map)
+    }
+
+    /// Makes the urls available to every template as the `asset` dict
+    pub fn install(&self) {
+        set_template_global("asset", self.to_value());
+    }
+
+    /// Answers a request for an asset, `path` being the request path such as
+    /// `/static/css/app.3f2a9c1d7e4b.css`. Fingerprinted files are cached for a year,
+    /// files requested by their original name are revalidated on every use
+    pub fn serve(&self, path: &str) -> HttpResponse {
+        let Some(name) = path.strip_prefix(self.prefix.as_str()) else {
+            return return_status(StatusCode::NOT_FOUND);
+        };
+        if name.split('/').any(|part| part.is_empty() || part.starts_with('.')) || name.contains('\\') {
+            return return_status(StatusCode::NOT_FOUND);
+        }
+        let (file, cache_control) = if self.entries.values().any(|f| f == name) {
+            (name, CacheControl::new().with_public().with_max_age(IMMUTABLE_MAX_AGE).with_immutable())
+        } else {
+            match self.get(name) {
+                Some(fingerprinted) => (fingerprinted, CacheControl::new().with_no_cache()),
+                None => return return_status(StatusCode::NOT_FOUND),
+            }
+        };
+        let file_path = self.dir.join(file);
+        match std::fs::read(&file_path) {
+            Ok(body) => normal_response(StatusCode::OK, body)
+                .content_type(static_content_type(&file_path))
+                .cache_control(cache_control),
+            Err(_) => return_status(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+/// Lists the files of `dir` recursively, leaving out `skip` when the output directory
+/// is inside the source one
+fn collect_files(dir: &Path, skip: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path == skip {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, skip, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn relative_name(source: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(source).unwrap_or(path);
+    let parts: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_before_the_extension() {
+        let digest = &sha256_hex(b"body{}")[..FINGERPRINT_LENGTH];
+        assert_eq!(fingerprint("css/app.css", b"body{}"), format!("css/app.{}.css", digest));
+        assert_eq!(fingerprint("LICENSE", b"body{}"), format!("LICENSE.{}", digest));
+        assert_eq!(fingerprint(".env", b"body{}"), format!(".env.{}", digest));
+        assert_ne!(fingerprint("app.css", b"a"), fingerprint("app.css", b"b"));
+    }
+
+    #[test]
+    fn build_load_and_serve() {
+        let root = std::env::temp_dir().join(format!("starberry-assets-{}", starberry_lib::uuid::uuid_v4()));
+        let source = root.join("assets");
+        std::fs::create_dir_all(source.join("css")).unwrap();
+        std::fs::write(source.join("css").join("app.css"), "body{}").unwrap();
+        std::fs::write(source.join("app.js"), "run()").unwrap();
+
+        let manifest = AssetManifest::build(&source, root.join("dist")).unwrap();
+        let css = manifest.get("css/app.css").unwrap().to_string();
+        assert_eq!(css, fingerprint("css/app.css", b"body{}"));
+        assert_eq!(manifest.url("css/app.css"), format!("/static/{}", css));
+        assert_eq!(manifest.url("missing.png"), "/static/missing.png");
+        assert_eq!(AssetManifest::load(root.join("dist")).unwrap(), manifest);
+
+        let mut response = manifest.serve(&format!("/static/{}", css));
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::OK);
+        assert!(response.meta.get_cache_control().unwrap().immutable);
+        let mut response = manifest.serve("/static/css/app.css");
+        assert!(response.meta.get_cache_control().unwrap().no_cache);
+        let response = manifest.serve("/static/../assets/app.js");
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::NOT_FOUND);
+
+        let prefixed = manifest.with_prefix("assets");
+        assert_eq!(prefixed.url("app.js"), format!("/assets/{}", fingerprint("app.js", b"run()")));
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
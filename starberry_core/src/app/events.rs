@@ -0,0 +1,176 @@
+//! In-process publish/subscribe of typed events.
+//!
+//! Every event type gets its own broadcast channel, created on first use. Publishers
+//! and subscribers only need to agree on the type of the event, so subsystems such as
+//! mailers, metrics or websockets can react to domain events without sharing channels.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use tokio::sync::broadcast;
+
+/// The number of events buffered for each subscriber when none is given
+pub const DEFAULT_EVENT_CAPACITY: usize = 64;
+
+/// A typed broadcast bus.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::app::events::EventBus;
+///
+/// struct UserRegistered(String);
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// runtime.block_on(async {
+///     let bus = EventBus::new();
+///     let mut subscriber = bus.subscribe::<UserRegistered>();
+///
+///     assert_eq!(bus.publish(UserRegistered("alice".to_string())), 1);
+///     assert_eq!(subscriber.recv().await.unwrap().0, "alice");
+/// });
+/// ```
+#[derive(Debug)]
+pub struct EventBus {
+    capacity: usize,
+    channels: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a bus buffering at most `capacity` events per subscriber.
+    /// A subscriber falling further behind misses the oldest events
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            channels: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn sender<T: Send + Sync + 'static>(&self) -> broadcast::Sender<Arc<T>> {
+        let id = TypeId::of::<T>();
+        if let Some(sender) = self
+            .channels
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+            .and_then(|sender| sender.downcast_ref::<broadcast::Sender<Arc<T>>>())
+        {
+            return sender.clone();
+        }
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        channels
+            .entry(id)
+            .or_insert_with(|| Box::new(broadcast::channel::<Arc<T>>(self.capacity).0))
+            .downcast_ref::<broadcast::Sender<Arc<T>>>()
+            .expect("event channel stored under the wrong type")
+            .clone()
+    }
+
+    /// Publishes an event to every current subscriber of its type.
+    /// Returns the number of subscribers which will receive it
+    pub fn publish<T: Send + Sync + 'static>(&self, event: T) -> usize {
+        self.sender::<T>().send(Arc::new(event)).unwrap_or(0)
+    }
+
+    /// Subscribes to the events of type `T` published from now on
+    pub fn subscribe<T: Send + Sync + 'static>(&self) -> Subscriber<T> {
+        Subscriber {
+            receiver: self.sender::<T>().subscribe(),
+        }
+    }
+
+    /// Returns the number of subscribers of the events of type `T`
+    pub fn subscriber_count<T: Send + Sync + 'static>(&self) -> usize {
+        self.sender::<T>().receiver_count()
+    }
+}
+
+/// Receives the events of one type published on an `EventBus`
+#[derive(Debug)]
+pub struct Subscriber<T> {
+    receiver: broadcast::Receiver<Arc<T>>,
+}
+
+impl<T: Send + Sync + 'static> Subscriber<T> {
+    /// Waits for the next event. Events missed because the subscriber fell behind are skipped.
+    /// Returns `None` once the bus has been dropped
+    pub async fn recv(&mut self) -> Option<Arc<T>> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    eprintln!(
+                        "Subscriber of {} missed {} events",
+                        std::any::type_name::<T>(),
+                        missed
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns the next event if one is already waiting
+    pub fn try_recv(&mut self) -> Option<Arc<T>> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Ping(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Pong(u32);
+
+    #[test]
+    fn events_are_routed_by_type() {
+        let bus = EventBus::new();
+        let mut pings = bus.subscribe::<Ping>();
+        let mut pongs = bus.subscribe::<Pong>();
+        assert_eq!(bus.publish(Ping(1)), 1);
+        assert_eq!(pings.try_recv().as_deref(), Some(&Ping(1)));
+        assert_eq!(pongs.try_recv(), None);
+        assert_eq!(bus.publish(Pong(2)), 1);
+        assert_eq!(pongs.try_recv().as_deref(), Some(&Pong(2)));
+    }
+
+    #[test]
+    fn publishing_without_subscriber_is_dropped() {
+        let bus = EventBus::new();
+        assert_eq!(bus.publish(Ping(1)), 0);
+        let mut pings = bus.subscribe::<Ping>();
+        assert_eq!(pings.try_recv(), None);
+    }
+
+    #[test]
+    fn lagging_subscriber_skips_old_events() {
+        let bus = EventBus::with_capacity(2);
+        let mut pings = bus.subscribe::<Ping>();
+        for i in 0..4 {
+            bus.publish(Ping(i));
+        }
+        assert_eq!(pings.try_recv().as_deref(), Some(&Ping(2)));
+        assert_eq!(pings.try_recv().as_deref(), Some(&Ping(3)));
+    }
+}
@@ -0,0 +1,345 @@
+//! Liveness and readiness endpoints for orchestrators and load balancers.
+//!
+//! Checks are registered with `App::health_check` under a name, e.g. one for the database
+//! pool, one for the cache and one for each upstream service. Given to
+//! `AppBuilder::health_endpoints`, `HealthEndpoints` serves:
+//!
+//! - `/healthz`, the liveness: answers `200` as long as the server handles requests
+//! - `/readyz`, the readiness: runs every check concurrently and answers `200` unless one
+//!   of them is unhealthy, `503` otherwise, with the result of each check
+//!
+//! ```json
+//! { "status": "fail", "checks": {
+//!     "db": { "status": "ok", "duration_ms": 3 },
+//!     "billing": { "status": "fail", "message": "Status 502", "duration_ms": 41 } } }
+//! ```
+//!
+//! # Example
+//! ```rust,ignore
+//! pub static APP: SApp = Lazy::new(|| App::new().health_endpoints(HealthEndpoints::new()).build());
+//!
+//! APP.health_check("db", move || {
+//!     let pool = pool.clone();
+//!     async move { pool.ping().await.into() }
+//! });
+//! APP.health_check("billing", || upstream_check("https://billing.internal/healthz"));
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use akari::Value;
+
+use crate::app::application::App;
+use crate::app::middleware::BoxFuture;
+use crate::app::urls::PathPattern;
+use crate::app::webhook_queue::split_url;
+use crate::cache::SharedCache;
+use crate::connection::transmit::Pool;
+use crate::http::context::{HttpReqCtx, HttpResCtx};
+use crate::http::http_value::{HttpMethod, HttpVersion, StatusCode};
+use crate::http::meta::HttpMeta;
+use crate::http::request::HttpRequest;
+use crate::http::response::response_templates::json_response;
+use crate::http::safety::HttpSafety;
+use crate::http::start_line::HttpStartLine;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The result of a health check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    /// Working with reduced service, e.g. a slow dependency. The application stays ready
+    Degraded(String),
+    /// Not working, the application is not ready
+    Unhealthy(String),
+}
+
+impl HealthStatus {
+    /// Whether the application can serve with this status
+    pub fn is_ready(&self) -> bool {
+        !matches!(self, Self::Unhealthy(_))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Healthy => "ok",
+            Self::Degraded(_) => "degraded",
+            Self::Unhealthy(_) => "fail",
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            Self::Healthy => None,
+            Self::Degraded(message) | Self::Unhealthy(message) => Some(message),
+        }
+    }
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "{}: {}", self.as_str(), message),
+            None => write!(f, "{}", self.as_str()),
+        }
+    }
+}
+
+/// An error makes the check unhealthy, with the error as message
+impl<T, E: fmt::Display> From<Result<T, E>> for HealthStatus {
+    fn from(result: Result<T, E>) -> Self {
+        match result {
+            Ok(_) => Self::Healthy,
+            Err(e) => Self::Unhealthy(e.to_string()),
+        }
+    }
+}
+
+type HealthCheck = Arc<dyn Fn() -> BoxFuture<HealthStatus> + Send + Sync>;
+
+/// The result of every check, as served by `/readyz`
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    /// The name, status and duration of each check, in the order they were registered
+    pub checks: Vec<(String, HealthStatus, Duration)>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|(_, status, _)| status.is_ready())
+    }
+
+    /// The overall status: failing if a check fails, degraded if a check is degraded
+    pub fn status(&self) -> &'static str {
+        if !self.is_ready() {
+            "fail"
+        } else if self.checks.iter().any(|(_, status, _)| matches!(status, HealthStatus::Degraded(_))) {
+            "degraded"
+        } else {
+            "ok"
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut checks = HashMap::new();
+        for (name, status, duration) in &self.checks {
+            let mut check = HashMap::new();
+            check.insert("status".to_string(), Value::Str(status.as_str().to_string()));
+            if let Some(message) = status.message() {
+                check.insert("message".to_string(), Value::Str(message.to_string()));
+            }
+            check.insert(
+                "duration_ms".to_string(),
+                Value::from_json(&duration.as_millis().to_string()).unwrap_or(Value::new("")),
+            );
+            checks.insert(name.clone(), Value::Dict(check));
+        }
+        let mut report = HashMap::new();
+        report.insert("status".to_string(), Value::Str(self.status().to_string()));
+        report.insert("checks".to_string(), Value::Dict(checks));
+        Value::Dict(report)
+    }
+}
+
+/// The health checks of an application, registered with `App::health_check`
+#[derive(Default)]
+pub struct HealthChecks {
+    checks: RwLock<Vec<(String, HealthCheck)>>,
+}
+
+impl HealthChecks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a check, replacing the check of the same name
+    pub fn add<F, Fut>(&self, name: impl Into<String>, check: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HealthStatus> + Send + 'static,
+    {
+        let name = name.into();
+        let check: HealthCheck = Arc::new(move || Box::pin(check()));
+        let mut checks = self.checks.write().unwrap_or_else(|e| e.into_inner());
+        match checks.iter_mut().find(|(existing, _)| *existing == name) {
+            Some(entry) => entry.1 = check,
+            None => checks.push((name, check)),
+        }
+    }
+
+    /// The names of the checks, in the order they were registered
+    pub fn names(&self) -> Vec<String> {
+        self.checks.read().unwrap_or_else(|e| e.into_inner()).iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Runs every check concurrently. A check taking longer than the timeout is unhealthy
+    pub async fn run(&self, timeout: Duration) -> Readiness {
+        let checks: Vec<(String, HealthCheck)> = self.checks.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let runs = checks.into_iter().map(|(name, check)| async move {
+            let start = Instant::now();
+            let status = match tokio::time::timeout(timeout, check()).await {
+                Ok(status) => status,
+                Err(_) => HealthStatus::Unhealthy(format!("Timed out after {:?}", timeout)),
+            };
+            (name, status, start.elapsed())
+        });
+        Readiness { checks: futures::future::join_all(runs).await }
+    }
+}
+
+impl fmt::Debug for HealthChecks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthChecks").field("checks", &self.names()).finish()
+    }
+}
+
+/// The `/healthz` and `/readyz` endpoints, enabled by `AppBuilder::health_endpoints`
+#[derive(Debug, Clone)]
+pub struct HealthEndpoints {
+    liveness_path: String,
+    readiness_path: String,
+    timeout: Duration,
+}
+
+impl HealthEndpoints {
+    pub fn new() -> Self {
+        Self {
+            liveness_path: "/healthz".to_string(),
+            readiness_path: "/readyz".to_string(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Serves the liveness on another path, `/healthz` by default
+    pub fn liveness_path(mut self, path: impl Into<String>) -> Self {
+        self.liveness_path = path.into();
+        self
+    }
+
+    /// Serves the readiness on another path, `/readyz` by default
+    pub fn readiness_path(mut self, path: impl Into<String>) -> Self {
+        self.readiness_path = path.into();
+        self
+    }
+
+    /// Sets how long each check may take, 5 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Registers the routes of the endpoints on the application
+    pub fn register(self, app: &Arc<App>) {
+        app.reg_from::<HttpReqCtx>(&segments(&self.liveness_path))
+            .set_method(Arc::new(|mut req: HttpReqCtx| async move {
+                let mut report = HashMap::new();
+                report.insert("status".to_string(), Value::Str("ok".to_string()));
+                req.response = json_response(Value::Dict(report)).add_header("cache-control", "no-store");
+                req
+            }));
+        let timeout = self.timeout;
+        app.reg_from::<HttpReqCtx>(&segments(&self.readiness_path))
+            .set_method(Arc::new(move |mut req: HttpReqCtx| async move {
+                let readiness = req.app.health().run(timeout).await;
+                let status = if readiness.is_ready() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+                req.response = json_response(readiness.to_value())
+                    .status(status)
+                    .add_header("cache-control", "no-store");
+                req
+            }));
+    }
+}
+
+impl Default for HealthEndpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn segments(path: &str) -> Vec<PathPattern> {
+    path.split('/').filter(|s| !s.is_empty()).map(PathPattern::literal_path).collect()
+}
+
+/// Checks that the cache answers, by writing and reading a key
+pub async fn cache_check(cache: SharedCache) -> HealthStatus {
+    const KEY: &str = "starberry:health";
+    if let Err(e) = cache.set(KEY, b"ok".to_vec(), Some(Duration::from_secs(60))).await {
+        return HealthStatus::Unhealthy(e.to_string());
+    }
+    match cache.get(KEY).await {
+        Ok(Some(_)) => HealthStatus::Healthy,
+        Ok(None) => HealthStatus::Degraded("The cache did not keep the value".to_string()),
+        Err(e) => HealthStatus::Unhealthy(e.to_string()),
+    }
+}
+
+/// Checks that a resource, e.g. a connection, can be taken from a pool
+pub async fn pool_check<P>(pool: &P) -> HealthStatus
+where
+    P: Pool + Sync,
+    P::Error: fmt::Display,
+{
+    match pool.get().await {
+        Ok(item) => {
+            pool.release(item).await;
+            HealthStatus::Healthy
+        }
+        Err(e) => HealthStatus::Unhealthy(e.to_string()),
+    }
+}
+
+/// Checks that an upstream service answers a `GET` on the url without a 5xx status
+pub async fn upstream_check(url: &str) -> HealthStatus {
+    let Some((host, path)) = split_url(url) else {
+        return HealthStatus::Unhealthy(format!("Invalid url {}", url));
+    };
+    let meta = HttpMeta::new(HttpStartLine::new_request(HttpVersion::Http11, HttpMethod::GET, path), HashMap::new());
+    let request = HttpRequest::new(meta, Default::default());
+    match HttpResCtx::send_request(host, request, HttpSafety::new()).await {
+        Ok(response) => match response.meta.start_line.status_code().as_u16() {
+            status if status >= 500 => HealthStatus::Unhealthy(format!("Status {}", status)),
+            _ => HealthStatus::Healthy,
+        },
+        Err(e) => HealthStatus::Unhealthy(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCache;
+
+    #[tokio::test]
+    async fn readiness_aggregates_the_checks() {
+        let checks = HealthChecks::new();
+        checks.add("db", || async { HealthStatus::Healthy });
+        checks.add("search", || async { HealthStatus::Degraded("Slow".to_string()) });
+        let readiness = checks.run(Duration::from_secs(1)).await;
+        assert!(readiness.is_ready());
+        assert_eq!(readiness.status(), "degraded");
+
+        checks.add("billing", || async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            HealthStatus::Healthy
+        });
+        checks.add("db", || async { HealthStatus::from(Err::<(), _>("Connection refused")) });
+        let readiness = checks.run(Duration::from_millis(20)).await;
+        assert!(!readiness.is_ready());
+        assert_eq!(readiness.status(), "fail");
+        assert_eq!(checks.names(), vec!["db", "search", "billing"]);
+        assert_eq!(readiness.checks[0].1, HealthStatus::Unhealthy("Connection refused".to_string()));
+        assert!(matches!(&readiness.checks[2].1, HealthStatus::Unhealthy(message) if message.starts_with("Timed out")));
+    }
+
+    #[tokio::test]
+    async fn checks_the_cache() {
+        let cache: SharedCache = Arc::new(MemoryCache::new(16));
+        assert_eq!(cache_check(cache).await, HealthStatus::Healthy);
+        assert!(matches!(upstream_check("ftp://example.com").await, HealthStatus::Unhealthy(_)));
+    }
+}
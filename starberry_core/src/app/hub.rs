@@ -0,0 +1,327 @@
+//! A registry of connected clients grouped in named rooms.
+//!
+//! Each client connecting to the hub gets a `Membership`, which owns the receiving
+//! end of its message queue. The connection handler forwards the received messages
+//! to its socket sink, e.g. a WebSocket, while any part of the application can
+//! broadcast to a room through the shared hub. Dropping the membership removes the
+//! client from every room it joined.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+
+use tokio::sync::mpsc;
+
+/// Identifies a client connected to a `Hub`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MemberId(pub u64);
+
+/// The presence of a client in a room
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberInfo {
+    pub id: MemberId,
+    pub name: String,
+}
+
+#[derive(Debug)]
+struct Member<M> {
+    name: String,
+    sender: mpsc::UnboundedSender<M>,
+    rooms: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+struct HubState<M> {
+    members: HashMap<MemberId, Member<M>>,
+    rooms: BTreeMap<String, BTreeSet<MemberId>>,
+}
+
+/// A hub broadcasting messages of type `M` to the clients of named rooms.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::app::hub::Hub;
+///
+/// let hub = Hub::<String>::new();
+/// let mut alice = hub.connect("alice");
+/// let bob = hub.connect("bob");
+/// alice.join("lobby");
+/// bob.join("lobby");
+///
+/// assert_eq!(hub.broadcast_except("lobby", bob.id(), "hi".to_string()), 1);
+/// assert_eq!(alice.try_recv().as_deref(), Some("hi"));
+///
+/// drop(bob);
+/// assert_eq!(hub.presence("lobby").len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct Hub<M> {
+    state: RwLock<HubState<M>>,
+    next_id: AtomicU64,
+}
+
+impl<M: Clone + Send + 'static> Hub<M> {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: RwLock::new(HubState {
+                members: HashMap::new(),
+                rooms: BTreeMap::new(),
+            }),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Registers a client. It does not belong to any room until it joins one
+    pub fn connect<T: Into<String>>(self: &Arc<Self>, name: T) -> Membership<M> {
+        let id = MemberId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.state
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .members
+            .insert(
+                id,
+                Member {
+                    name: name.into(),
+                    sender,
+                    rooms: BTreeSet::new(),
+                },
+            );
+        Membership {
+            id,
+            hub: Arc::downgrade(self),
+            receiver,
+        }
+    }
+
+    /// Removes a client from every room. Returns `false` if it was not connected
+    pub fn disconnect(&self, id: MemberId) -> bool {
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        let Some(member) = state.members.remove(&id) else {
+            return false;
+        };
+        for room in member.rooms {
+            state.remove_from_room(&room, id);
+        }
+        true
+    }
+
+    /// Adds a client to a room, creating the room if needed.
+    /// Returns `false` if the client is not connected or already in the room
+    pub fn join<T: Into<String>>(&self, id: MemberId, room: T) -> bool {
+        let room = room.into();
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        let Some(member) = state.members.get_mut(&id) else {
+            return false;
+        };
+        if !member.rooms.insert(room.clone()) {
+            return false;
+        }
+        state.rooms.entry(room).or_default().insert(id);
+        true
+    }
+
+    /// Removes a client from a room. An empty room is deleted.
+    /// Returns `false` if the client was not in the room
+    pub fn leave(&self, id: MemberId, room: &str) -> bool {
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        let left = state
+            .members
+            .get_mut(&id)
+            .is_some_and(|member| member.rooms.remove(room));
+        if left {
+            state.remove_from_room(room, id);
+        }
+        left
+    }
+
+    /// Sends a message to a single client. Returns `false` if it is gone
+    pub fn send_to(&self, id: MemberId, message: M) -> bool {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state
+            .members
+            .get(&id)
+            .is_some_and(|member| member.sender.send(message).is_ok())
+    }
+
+    /// Sends a message to every client of a room. Returns the number of clients reached
+    pub fn broadcast(&self, room: &str, message: M) -> usize {
+        self.broadcast_filtered(room, message, |_| true)
+    }
+
+    /// Sends a message to every client of a room but one, usually its author
+    pub fn broadcast_except(&self, room: &str, except: MemberId, message: M) -> usize {
+        self.broadcast_filtered(room, message, |id| id != except)
+    }
+
+    fn broadcast_filtered(&self, room: &str, message: M, filter: impl Fn(MemberId) -> bool) -> usize {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        let Some(ids) = state.rooms.get(room) else {
+            return 0;
+        };
+        ids.iter()
+            .filter(|id| filter(**id))
+            .filter_map(|id| state.members.get(id))
+            .filter(|member| member.sender.send(message.clone()).is_ok())
+            .count()
+    }
+
+    /// Returns the clients currently in a room
+    pub fn presence(&self, room: &str) -> Vec<MemberInfo> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state
+            .rooms
+            .get(room)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| {
+                        state.members.get(id).map(|member| MemberInfo {
+                            id: *id,
+                            name: member.name.clone(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the names of the rooms having at least one client
+    pub fn rooms(&self) -> Vec<String> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state.rooms.keys().cloned().collect()
+    }
+
+    /// Returns the rooms a client has joined
+    pub fn rooms_of(&self, id: MemberId) -> Vec<String> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        state
+            .members
+            .get(&id)
+            .map(|member| member.rooms.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of connected clients
+    pub fn len(&self) -> usize {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<M> HubState<M> {
+    fn remove_from_room(&mut self, room: &str, id: MemberId) {
+        if let Some(ids) = self.rooms.get_mut(room) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.rooms.remove(room);
+            }
+        }
+    }
+}
+
+/// The connection of a client to a `Hub`. Leaves every room when dropped
+#[derive(Debug)]
+pub struct Membership<M: Clone + Send + 'static> {
+    id: MemberId,
+    hub: Weak<Hub<M>>,
+    receiver: mpsc::UnboundedReceiver<M>,
+}
+
+impl<M: Clone + Send + 'static> Membership<M> {
+    pub fn id(&self) -> MemberId {
+        self.id
+    }
+
+    /// Joins a room, see `Hub::join`
+    pub fn join<T: Into<String>>(&self, room: T) -> bool {
+        self.hub.upgrade().is_some_and(|hub| hub.join(self.id, room))
+    }
+
+    /// Leaves a room, see `Hub::leave`
+    pub fn leave(&self, room: &str) -> bool {
+        self.hub.upgrade().is_some_and(|hub| hub.leave(self.id, room))
+    }
+
+    /// Sends a message to the other clients of a room
+    pub fn broadcast(&self, room: &str, message: M) -> usize {
+        self.hub
+            .upgrade()
+            .map(|hub| hub.broadcast_except(room, self.id, message))
+            .unwrap_or(0)
+    }
+
+    /// Waits for the next message sent to this client.
+    /// Returns `None` once the client has been disconnected from the hub
+    pub async fn recv(&mut self) -> Option<M> {
+        self.receiver.recv().await
+    }
+
+    /// Returns the next message if one is already waiting
+    pub fn try_recv(&mut self) -> Option<M> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<M: Clone + Send + 'static> Drop for Membership<M> {
+    fn drop(&mut self) {
+        if let Some(hub) = self.hub.upgrade() {
+            hub.disconnect(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broadcast_reaches_room_members_only() {
+        let hub = Hub::<u32>::new();
+        let mut a = hub.connect("a");
+        let mut b = hub.connect("b");
+        let mut c = hub.connect("c");
+        a.join("red");
+        b.join("red");
+        c.join("blue");
+        assert_eq!(hub.broadcast("red", 1), 2);
+        assert_eq!(a.try_recv(), Some(1));
+        assert_eq!(b.try_recv(), Some(1));
+        assert_eq!(c.try_recv(), None);
+        assert_eq!(a.broadcast("red", 2), 1);
+        assert_eq!(a.try_recv(), None);
+        assert_eq!(b.try_recv(), Some(2));
+    }
+
+    #[test]
+    fn empty_rooms_are_removed() {
+        let hub = Hub::<u32>::new();
+        let a = hub.connect("a");
+        assert!(a.join("red"));
+        assert!(!a.join("red"));
+        assert_eq!(hub.rooms(), vec!["red".to_string()]);
+        assert!(a.leave("red"));
+        assert!(!a.leave("red"));
+        assert!(hub.rooms().is_empty());
+    }
+
+    #[test]
+    fn dropping_membership_disconnects() {
+        let hub = Hub::<u32>::new();
+        let a = hub.connect("a");
+        let b = hub.connect("b");
+        a.join("red");
+        b.join("red");
+        let b_id = b.id();
+        drop(b);
+        assert_eq!(hub.len(), 1);
+        assert_eq!(
+            hub.presence("red"),
+            vec![MemberInfo { id: a.id(), name: "a".to_string() }]
+        );
+        assert!(!hub.send_to(b_id, 1));
+    }
+}
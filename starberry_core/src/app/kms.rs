@@ -0,0 +1,180 @@
+//! A `SecretsProvider` decrypting secrets encrypted with a Google Cloud KMS key.
+//!
+//! The encrypted secrets, base64 as printed by `gcloud kms encrypt`, are read from another
+//! provider, e.g. environment variables, so they can be kept next to the configuration. Each
+//! one is decrypted with the key when it is read. The access token is given, or taken from the
+//! metadata server of the instance and renewed before it expires.
+//!
+//! # Example
+//! ```rust,ignore
+//! let kms = KmsSecrets::new(
+//!     "projects/shop/locations/global/keyRings/prod/cryptoKeys/secrets",
+//!     EnvSecrets::new().prefix("ENCRYPTED_"),
+//! );
+//! let password = kms.require("db_password").await?;
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use akari::Value;
+use starberry_lib::encoding::base64_decode;
+
+use crate::app::middleware::BoxFuture;
+use crate::app::secrets::{Secret, SecretError, SecretsProvider, SharedSecrets, json_body, member};
+use crate::http::body::HttpBody;
+use crate::http::context::HttpResCtx;
+use crate::http::http_value::{HttpContentType, HttpMethod, HttpVersion};
+use crate::http::meta::HttpMeta;
+use crate::http::request::HttpRequest;
+use crate::http::safety::HttpSafety;
+use crate::http::start_line::HttpStartLine;
+
+const KMS_HOST: &str = "https://cloudkms.googleapis.com";
+const METADATA_HOST: &str = "http://metadata.google.internal";
+const METADATA_TOKEN_PATH: &str = "/computeMetadata/v1/instance/service-accounts/default/token";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A token from the metadata server is renewed this long before it expires
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// Decrypts the secrets of another provider with a Cloud KMS key
+#[derive(Clone)]
+pub struct KmsSecrets {
+    /// The resource name of the key, `projects/*/locations/*/keyRings/*/cryptoKeys/*`
+    key: String,
+    source: SharedSecrets,
+    /// The access token given with `access_token`, the metadata server is asked otherwise
+    token: Option<Secret>,
+    cached: Arc<Mutex<Option<(Secret, Instant)>>>,
+    timeout: Duration,
+    safety: HttpSafety,
+}
+
+impl KmsSecrets {
+    pub fn new<P: SecretsProvider>(key: impl Into<String>, source: P) -> Self {
+        Self {
+            key: key.into().trim_matches('/').to_string(),
+            source: Arc::new(source),
+            token: None,
+            cached: Arc::new(Mutex::new(None)),
+            timeout: DEFAULT_TIMEOUT,
+            safety: HttpSafety::new(),
+        }
+    }
+
+    /// Uses this OAuth access token instead of the one of the instance service account
+    pub fn access_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(Secret::new(token));
+        self
+    }
+
+    /// Sets how long each call to Google Cloud may take, 10 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn send(&self, host: &str, request: HttpRequest) -> Result<Value, SecretError> {
+        let sent = HttpResCtx::send_request(host, request, self.safety.clone());
+        let response = match tokio::time::timeout(self.timeout, sent).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => return Err(SecretError::Unavailable(format!("{}: {}", host, e))),
+            Err(_) => return Err(SecretError::Unavailable(format!("{} timed out", host))),
+        };
+        let status = response.meta.start_line.status_code().as_u16();
+        if status >= 300 {
+            return Err(SecretError::Unavailable(format!("{} answered with status {}", host, status)));
+        }
+        json_body(&response.body).ok_or_else(|| SecretError::Unavailable(format!("{} answered without JSON", host)))
+    }
+
+    async fn token(&self) -> Result<Secret, SecretError> {
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+        let cached = self.cached.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some((token, expires)) = cached
+            && Instant::now() < expires
+        {
+            return Ok(token);
+        }
+        let mut meta = HttpMeta::new(
+            HttpStartLine::new_request(HttpVersion::Http11, HttpMethod::GET, METADATA_TOKEN_PATH.to_string()),
+            HashMap::new(),
+        );
+        meta.set_attribute("metadata-flavor", "Google");
+        let body = self.send(METADATA_HOST, HttpRequest::new(meta, HttpBody::Empty)).await?;
+        let Some(Value::Str(token)) = member(&body, "access_token") else {
+            return Err(SecretError::Unavailable("The metadata server gave no access token".to_string()));
+        };
+        let token = Secret::new(token.clone());
+        let lifetime = member(&body, "expires_in")
+            .and_then(|expires| expires.into_json().trim().parse::<f64>().ok())
+            .map(Duration::from_secs_f64)
+            .unwrap_or_default();
+        let expires = Instant::now() + lifetime.saturating_sub(TOKEN_MARGIN);
+        *self.cached.lock().unwrap_or_else(|e| e.into_inner()) = Some((token.clone(), expires));
+        Ok(token)
+    }
+
+    async fn decrypt(&self, ciphertext: &str) -> Result<Secret, SecretError> {
+        let token = self.token().await?;
+        let mut meta = HttpMeta::new(
+            HttpStartLine::new_request(HttpVersion::Http11, HttpMethod::POST, format!("/v1/{}:decrypt", self.key)),
+            HashMap::new(),
+        );
+        meta.set_content_type(HttpContentType::ApplicationJson());
+        meta.set_attribute("authorization", format!("Bearer {}", token.expose()));
+        let body = HttpBody::Binary(decrypt_body(ciphertext).into_bytes());
+        let decrypted = self.send(KMS_HOST, HttpRequest::new(meta, body)).await?;
+        plaintext(&decrypted)
+    }
+}
+
+impl SecretsProvider for KmsSecrets {
+    fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>> {
+        let kms = self.clone();
+        let fetched = self.source.get(name);
+        Box::pin(async move {
+            match fetched.await? {
+                Some(ciphertext) => kms.decrypt(ciphertext.expose()).await.map(Some),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+fn decrypt_body(ciphertext: &str) -> String {
+    let mut body = HashMap::new();
+    body.insert("ciphertext".to_string(), Value::Str(ciphertext.trim().to_string()));
+    Value::Dict(body).into_json()
+}
+
+/// The secret in a decrypt response, `{"plaintext": "<base64>"}`
+fn plaintext(response: &Value) -> Result<Secret, SecretError> {
+    let Some(Value::Str(encoded)) = member(response, "plaintext") else {
+        return Err(SecretError::Unavailable("KMS gave no plaintext".to_string()));
+    };
+    let bytes = base64_decode(encoded).map_err(|_| SecretError::Invalid("KMS gave invalid base64".to_string()))?;
+    String::from_utf8(bytes)
+        .map(Secret::new)
+        .map_err(|_| SecretError::Invalid("The decrypted secret is not UTF-8".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_plaintext() {
+        let response = Value::from_json(r#"{"plaintext": "aHVudGVyMg==", "usedPrimary": true}"#).unwrap();
+        assert_eq!(plaintext(&response).unwrap().expose(), "hunter2");
+        let response = Value::from_json(r#"{"plaintext": "not base64!"}"#).unwrap();
+        assert!(matches!(plaintext(&response), Err(SecretError::Invalid(_))));
+        assert!(plaintext(&Value::from_json("{}").unwrap()).is_err());
+
+        let body = Value::from_json(&decrypt_body(" CiQA\n")).unwrap();
+        assert!(matches!(member(&body, "ciphertext"), Some(Value::Str(text)) if text == "CiQA"));
+    }
+}
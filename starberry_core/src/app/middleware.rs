@@ -4,7 +4,8 @@ use std::sync::Arc;
 use crate::http::context::HttpReqCtx;
 
 use crate::connection::Rx; 
-use std::any::Any; 
+use std::any::{Any, TypeId}; 
+use std::marker::PhantomData; 
 
 /// A boxed future returning `R`.
 pub type BoxFuture<R> = Pin<Box<dyn Future<Output = R> + Send + 'static>>; 
@@ -17,11 +18,55 @@ pub trait AsyncMiddleware<R: Rx>: Send + Sync + 'static {
     /// Used when creating the mddleware 
     fn return_self() -> Self where Self: Sized; 
 
+    /// The name of the middleware shown in diagnostics, its type name by default 
+    fn name(&self) -> &'static str { 
+        std::any::type_name::<Self>() 
+    } 
+
     fn handle<'a>( 
         &self,
         rc: R,
         next: Box<dyn Fn(R) -> Pin<Box<dyn Future<Output = R> + Send>> + Send + Sync + 'static>,
     ) -> Pin<Box<dyn Future<Output = R> + Send + 'static>>; 
+
+    /// The type id of `SkipMiddleware<Self>`, looked up in the params of a url to skip this middleware. 
+    /// Not meant to be overridden 
+    fn skip_marker(&self) -> TypeId { 
+        TypeId::of::<SkipMiddleware<Self>>() 
+    } 
+} 
+
+/// Marks a url as not running the middleware `M`, even if it is applied to the whole application, 
+/// e.g. to keep the session or CSRF middleware away from a webhook endpoint 
+/// # Example 
+/// ```rust,ignore 
+/// #[url(APP.reg_from(&[LitUrl("hooks")]), config=[SkipMiddleware::<Cors>::new(), SkipMiddleware::<CookieSession>::new()])] 
+/// async fn hooks() -> HttpResponse { ... } 
+/// ``` 
+pub struct SkipMiddleware<M: ?Sized>(PhantomData<fn(&M)>); 
+
+impl<M: ?Sized> SkipMiddleware<M> { 
+    pub fn new() -> Self { 
+        Self(PhantomData) 
+    } 
+} 
+
+impl<M: ?Sized> Default for SkipMiddleware<M> { 
+    fn default() -> Self { 
+        Self::new() 
+    } 
+} 
+
+impl<M: ?Sized> Clone for SkipMiddleware<M> { 
+    fn clone(&self) -> Self { 
+        Self::new() 
+    } 
+} 
+
+impl<M: ?Sized> std::fmt::Debug for SkipMiddleware<M> { 
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { 
+        write!(f, "SkipMiddleware<{}>", std::any::type_name::<M>()) 
+    } 
 } 
 
 /// The “final handler” trait that sits at the end of a middleware chain.
@@ -9,7 +9,7 @@ use tokio::io::{
     ReadHalf,
     WriteHalf,
 };
-use crate::{app::{middleware::{AsyncMiddleware, AsyncMiddlewareChain}, urls::{PathPattern, Url}}, connection::{Connection, Rx}, extensions::ParamsClone};
+use crate::{app::{middleware::{AsyncMiddleware, AsyncMiddlewareChain}, urls::{PathPattern, Url}}, connection::{Connection, ConnectionInfo, Rx}, extensions::ParamsClone};
 use super::application::App; 
 
 // type TestFn = fn(&[u8]) -> bool;
@@ -44,12 +44,13 @@ pub trait ProtocolHandlerTrait: Send + Sync {
     /// Returns `true` if the given buffer matches the protocol signature.
     fn test(&self, buf: &[u8]) -> bool; 
 
-    /// A function pointer that, given the `App` and split I/O halves wrapped
+    /// A function pointer that, given the `App`, the connection info and split I/O halves wrapped
     /// in buffered reader/writer, returns a boxed `Future` that drives the
     /// protocol handler to completion.
     fn handle(
         &self,
         app: Arc<App>,
+        conn_info: ConnectionInfo,
         reader: BufReader<ReadHalf<Connection>>,
         writer: BufWriter<WriteHalf<Connection>>,
     ) -> Pin<Box<dyn Future<Output = ()> + Send>>; 
@@ -69,12 +70,13 @@ impl<R: Rx + 'static> ProtocolHandlerTrait for ProtocolHandler<R> {
     fn handle(
         &self,
         app: Arc<App>,
+        conn_info: ConnectionInfo,
         reader: BufReader<ReadHalf<Connection>>,
         writer: BufWriter<WriteHalf<Connection>>,
     ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         let root_handler = self.root_handler.clone();
         Box::pin(async move {
-            R::process(app, root_handler, reader, writer).await;
+            R::process(app, root_handler, conn_info, reader, writer).await;
         })
     } 
 
@@ -118,7 +120,7 @@ impl ProtocolRegistry {
     /// 2. Peek at the initial bytes without consuming them.
     /// 3. Iterate in registration order and run the first matching protocol.
     /// 4. If no match is found, cleanly shutdown the write half.
-    pub async fn run_multi(&self, app: Arc<App>, conn: Connection) {
+    pub async fn run_multi(&self, app: Arc<App>, conn_info: ConnectionInfo, conn: Connection) {
         // 1) split into raw halves
         let (read_half, write_half) = conn.split();
         let mut reader = BufReader::new(read_half);
@@ -132,7 +134,7 @@ impl ProtocolRegistry {
         for handler in &self.handlers {
             if handler.test(&buf[..n]) {
                 // 4) if test passes, dispatch to this protocol's handler
-                handler.handle(app.clone(), reader, writer).await;
+                handler.handle(app.clone(), conn_info, reader, writer).await;
                 return;
             }
         }
@@ -191,6 +193,15 @@ impl<R: Rx> ProtocolHandlerBuilder<R> {
         self
     }
 
+    // Append a middleware instance, e.g. one carrying its own configuration, to the end of the vector.
+    pub fn add_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: AsyncMiddleware<R> + 'static,
+    {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
     // Insert a middleware instance created by T at the beginning of the vector.
     pub fn prepend_middleware<M>(mut self) -> Self
     where
@@ -256,16 +267,23 @@ impl ProtocolRegistryKind {
     /// - `Single` mode directly invokes the stored `handler`.
     /// - `Multi` mode calls `run_multi` on the inner registry.
     pub async fn run(&self, app: Arc<App>, conn: Connection) {
+        let conn_info = conn.info();
+        self.run_with_info(app, conn_info, conn).await
+    } 
+
+    /// Same as `run`, using connection info collected by the caller, e.g. to attach
+    /// a deadline and a cancellation token to the connection.
+    pub async fn run_with_info(&self, app: Arc<App>, conn_info: ConnectionInfo, conn: Connection) {
         match self {
             ProtocolRegistryKind::Single(handler) => {
                 let (read_half, write_half) = conn.split();
                 let reader = BufReader::new(read_half);
                 let writer = BufWriter::new(write_half);
-                handler.handle(app, reader, writer).await;
+                handler.handle(app, conn_info, reader, writer).await;
             } 
             ProtocolRegistryKind::Multi(registry) => {
                 // Use detection logic for multiple protocols.
-                registry.run_multi(app, conn).await;
+                registry.run_multi(app, conn_info, conn).await;
             }
         }
     } 
@@ -338,6 +356,19 @@ impl ProtocolRegistryKind {
         }
     } 
 
+    /// Mount a url tree under the root url of protocol `R` at the given prefix. 
+    /// See `Url::mount` for how middlewares and params are carried along. 
+    pub fn mount<R: Rx + 'static>(
+        &self, 
+        prefix: &str, 
+        tree: &Arc<Url<R>>, 
+    ) -> Result<Arc<Url<R>>, String> { 
+        match self.url::<R>() { 
+            Some(root) => root.mount(prefix, tree), 
+            None => Err("Protocol Not Found".to_string()), 
+        } 
+    } 
+
     pub fn reg_from<R: Rx + 'static>(
         &self,
         segments: &[PathPattern]
@@ -0,0 +1,427 @@
+//! Settings which can change while the server runs, reloaded from a file.
+//!
+//! A `RuntimeConfig` keeps flat `key = value` settings, read from a file with one setting per
+//! line (`#` starts a comment), or from a JSON file whose nested objects give dotted keys such
+//! as `rate_limit.per_minute`. Once started, the file is read again when it is modified and when
+//! the process receives `SIGHUP`. The new settings replace the old ones at once, so a reader
+//! never sees a half applied file, and the subscribers of the changed keys are notified.
+//!
+//! Subsystems either read their setting on each request, e.g. a rate limiter calling
+//! `get_as::<u32>("rate_limit.per_minute")`, or subscribe with `on_change` to apply it, e.g.
+//! the maintenance switch or the log level. A file which cannot be read or parsed is reported
+//! and the current settings are kept.
+//!
+//! A setting written `secret:<name>`, e.g. `db.password = secret:db_password`, only names a
+//! secret, which `secret` reads from a `SecretsProvider`.
+//!
+//! # Example
+//! ```rust,ignore
+//! let config = RuntimeConfig::from_file("config/runtime.json")?.reloadable(["log", "rate_limit", "maintenance", "trusted_proxies"]);
+//! let switch = maintenance.switch();
+//! config.on_change("maintenance", move |change| match change.new.as_deref() {
+//!     Some("true") => switch.enable(),
+//!     _ => switch.disable(),
+//! });
+//! App::new().runtime_config(config).build();
+//!
+//! // In a handler
+//! let proxies = req.state::<RuntimeConfig>().unwrap().get("trusted_proxies");
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use akari::Value;
+
+use crate::app::secrets::{Secret, SecretError, SecretsProvider};
+use crate::app::task::TaskTracker;
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Starts the settings which name a secret instead of holding it
+const SECRET_PREFIX: &str = "secret:";
+
+/// A setting which changed with a reload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub key: String,
+    /// `None` if the setting was added
+    pub old: Option<String>,
+    /// `None` if the setting was removed
+    pub new: Option<String>,
+}
+
+type Subscriber = Arc<dyn Fn(&ConfigChange) + Send + Sync>;
+
+struct Inner {
+    path: Option<PathBuf>,
+    values: RwLock<Arc<HashMap<String, String>>>,
+    version: AtomicU64,
+    modified: Mutex<Option<SystemTime>>,
+    subscribers: Mutex<Vec<(String, Subscriber)>>,
+}
+
+/// Settings swapped at once when their file changes, shared by its clones
+#[derive(Clone)]
+pub struct RuntimeConfig {
+    inner: Arc<Inner>,
+    reloadable: Vec<String>,
+    poll_interval: Duration,
+}
+
+impl RuntimeConfig {
+    /// Settings which only change with `replace`
+    pub fn new(values: HashMap<String, String>) -> Self {
+        Self::build(None, values)
+    }
+
+    /// Reads the settings from a file, parsed as JSON if its extension is `.json`
+    pub fn from_file(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let values = read(&path)?;
+        let config = Self::build(Some(path.clone()), values);
+        *config.inner.modified.lock().unwrap_or_else(|e| e.into_inner()) = modified(&path);
+        Ok(config)
+    }
+
+    fn build(path: Option<PathBuf>, values: HashMap<String, String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                path,
+                values: RwLock::new(Arc::new(values)),
+                version: AtomicU64::new(0),
+                modified: Mutex::new(None),
+                subscribers: Mutex::new(Vec::new()),
+            }),
+            reloadable: Vec::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Only the settings under these prefixes are reloaded, the others keep the value they had
+    /// at startup until a restart. Every setting is reloaded when no prefix is given
+    pub fn reloadable<I: IntoIterator<Item = S>, S: Into<String>>(mut self, prefixes: I) -> Self {
+        self.reloadable.extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets how often the file is checked for modifications, every 2 seconds by default
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.snapshot().get(key).cloned()
+    }
+
+    /// Parses a setting, `None` if it is missing or invalid
+    pub fn get_as<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.trim().parse().ok()
+    }
+
+    /// Resolves a setting holding a secret. A value written `secret:<name>` is read from the
+    /// provider, another value is taken as it is. `None` if the setting is missing
+    pub async fn secret(&self, key: &str, secrets: &dyn SecretsProvider) -> Result<Option<Secret>, SecretError> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        match value.trim().strip_prefix(SECRET_PREFIX) {
+            Some(name) => secrets.require(name.trim()).await.map(Some),
+            None => Ok(Some(Secret::new(value))),
+        }
+    }
+
+    /// Every setting, as of the last reload
+    pub fn snapshot(&self) -> Arc<HashMap<String, String>> {
+        self.inner.values.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The number of reloads which changed a setting
+    pub fn version(&self) -> u64 {
+        self.inner.version.load(Ordering::Acquire)
+    }
+
+    /// Calls `subscriber` for each change of the setting `prefix` or of the settings under it,
+    /// e.g. `rate_limit` covers `rate_limit.per_minute`. An empty prefix covers every setting
+    pub fn on_change<F: Fn(&ConfigChange) + Send + Sync + 'static>(&self, prefix: impl Into<String>, subscriber: F) {
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((prefix.into(), Arc::new(subscriber)));
+    }
+
+    /// Swaps in new settings and notifies the subscribers. Settings which are not reloadable
+    /// keep their value. Returns the changes
+    pub fn replace(&self, mut values: HashMap<String, String>) -> Vec<ConfigChange> {
+        let changes = {
+            let mut current = self.inner.values.write().unwrap_or_else(|e| e.into_inner());
+            let keys: BTreeSet<&String> = current.keys().chain(values.keys()).collect();
+            let mut changes = Vec::new();
+            let mut ignored = Vec::new();
+            for key in keys {
+                let (old, new) = (current.get(key), values.get(key));
+                if old == new {
+                    continue;
+                }
+                if !covered(&self.reloadable, key) {
+                    ignored.push(key.clone());
+                    continue;
+                }
+                changes.push(ConfigChange { key: key.clone(), old: old.cloned(), new: new.cloned() });
+            }
+            for key in ignored {
+                eprintln!("Setting {} changed, it is applied on restart", key);
+                match current.get(&key) {
+                    Some(value) => values.insert(key, value.clone()),
+                    None => values.remove(&key),
+                };
+            }
+            if !changes.is_empty() {
+                *current = Arc::new(values);
+                self.inner.version.fetch_add(1, Ordering::AcqRel);
+            }
+            changes
+        };
+        // Called once the lock is released, so subscribers can read the new settings
+        let subscribers = self.inner.subscribers.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for change in &changes {
+            for (prefix, subscriber) in &subscribers {
+                if prefix.is_empty() || covers(prefix, &change.key) {
+                    subscriber(change);
+                }
+            }
+        }
+        changes
+    }
+
+    /// Reads the file again and swaps in its settings
+    pub async fn reload(&self) -> io::Result<Vec<ConfigChange>> {
+        let Some(path) = self.inner.path.clone() else {
+            return Ok(Vec::new());
+        };
+        *self.inner.modified.lock().unwrap_or_else(|e| e.into_inner()) = modified(&path);
+        let values = tokio::task::spawn_blocking(move || read(&path)).await.map_err(io::Error::other)??;
+        Ok(self.replace(values))
+    }
+
+    /// Reloads the file when it is modified and on `SIGHUP`, until the application shuts down.
+    /// Called by the application for the config given to `AppBuilder::runtime_config`
+    pub fn start(&self, tasks: &TaskTracker) {
+        let Some(path) = self.inner.path.clone() else {
+            return;
+        };
+        let config = self.clone();
+        let shutdown = tasks.shutdown_token();
+        tasks.spawn(async move {
+            let mut hangup = HangupSignal::new();
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = hangup.recv() => {}
+                    _ = tokio::time::sleep(config.poll_interval) => {
+                        let last = *config.inner.modified.lock().unwrap_or_else(|e| e.into_inner());
+                        if modified(&path) == last {
+                            continue;
+                        }
+                    }
+                }
+                match config.reload().await {
+                    Ok(changes) if !changes.is_empty() => {
+                        let keys: Vec<&str> = changes.iter().map(|change| change.key.as_str()).collect();
+                        println!("Reloaded {}: {}", path.display(), keys.join(", "));
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("⚠️ Failed to reload {}, keeping the current settings: {}", path.display(), e),
+                }
+            }
+        });
+    }
+}
+
+impl fmt::Debug for RuntimeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RuntimeConfig")
+            .field("path", &self.inner.path)
+            .field("version", &self.version())
+            .field("reloadable", &self.reloadable)
+            .finish()
+    }
+}
+
+/// Whether the key is the prefix or under it
+fn covers(prefix: &str, key: &str) -> bool {
+    key.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+fn covered(prefixes: &[String], key: &str) -> bool {
+    prefixes.is_empty() || prefixes.iter().any(|prefix| covers(prefix, key))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn read(path: &Path) -> io::Result<HashMap<String, String>> {
+    let text = std::fs::read_to_string(path)?;
+    let parsed = if path.extension().is_some_and(|extension| extension == "json") {
+        parse_json(&text)
+    } else {
+        parse_lines(&text)
+    };
+    parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Reads `key = value` lines. Blank lines and lines starting with `#` are skipped, values may
+/// be quoted
+pub fn parse_lines(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut values = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Line {}: expected key = value", number + 1));
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("Line {}: empty key", number + 1));
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        values.insert(key.to_string(), value.to_string());
+    }
+    Ok(values)
+}
+
+/// Reads a JSON object. Nested objects give dotted keys, other values are kept as JSON
+/// except strings
+pub fn parse_json(text: &str) -> Result<HashMap<String, String>, String> {
+    let Ok(Value::Dict(object)) = Value::from_json(text) else {
+        return Err("Expected a JSON object".to_string());
+    };
+    let mut values = HashMap::new();
+    flatten("", &object, &mut values);
+    Ok(values)
+}
+
+fn flatten(prefix: &str, object: &HashMap<String, Value>, values: &mut HashMap<String, String>) {
+    for (key, value) in object {
+        let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            Value::Dict(nested) => flatten(&key, nested, values),
+            Value::Str(text) => {
+                values.insert(key, text.clone());
+            }
+            other => {
+                values.insert(key, other.into_json());
+            }
+        }
+    }
+}
+
+/// Waits for `SIGHUP` on Unix, forever elsewhere
+struct HangupSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl HangupSignal {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+            Self { signal: signal(SignalKind::hangup()).ok() }
+        }
+        #[cfg(not(unix))]
+        {
+            Self {}
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal
+            && signal.recv().await.is_some()
+        {
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    fn settings(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn parses_settings() {
+        let values = parse_lines("# Runtime settings\nlog.level = debug\n\nmaintenance = \"true\"\n").unwrap();
+        assert_eq!(values, settings(&[("log.level", "debug"), ("maintenance", "true")]));
+        assert!(parse_lines("no separator").is_err());
+
+        let values = parse_json(r#"{"rate_limit": {"per_minute": 60}, "log": {"level": "info"}}"#).unwrap();
+        assert_eq!(values.get("log.level").map(String::as_str), Some("info"));
+        assert_eq!(values.get("rate_limit.per_minute").map(|v| v.trim().parse::<u32>().ok()), Some(Some(60)));
+        assert!(parse_json("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn swaps_reloadable_settings() {
+        let config = RuntimeConfig::new(settings(&[("log.level", "info"), ("port", "3000")])).reloadable(["log", "maintenance"]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        config.on_change("log", move |change| {
+            assert_eq!(change.key, "log.level");
+            assert_eq!(change.new.as_deref(), Some("debug"));
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let changes = config.replace(settings(&[("log.level", "debug"), ("port", "4000"), ("maintenance", "true")]));
+        assert_eq!(changes.len(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(config.version(), 1);
+        assert_eq!(config.get("log.level").as_deref(), Some("debug"));
+        assert_eq!(config.get_as::<u16>("port"), Some(3000));
+        assert_eq!(config.get_as::<bool>("maintenance"), Some(true));
+
+        assert!(config.replace((*config.snapshot()).clone()).is_empty());
+        assert_eq!(config.version(), 1);
+    }
+
+    #[tokio::test]
+    async fn resolves_secrets() {
+        let directory = std::env::temp_dir().join(format!("starberry-config-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("db_password"), "hunter2\n").unwrap();
+        let secrets = crate::app::secrets::FileSecrets::new(&directory);
+        let config = RuntimeConfig::new(settings(&[
+            ("db.password", "secret:db_password"),
+            ("db.user", "shop"),
+            ("api.token", "secret:api_token"),
+        ]));
+
+        assert_eq!(config.secret("db.password", &secrets).await.unwrap().unwrap().expose(), "hunter2");
+        assert_eq!(config.secret("db.user", &secrets).await.unwrap().unwrap().expose(), "shop");
+        assert!(config.secret("db.host", &secrets).await.unwrap().is_none());
+        assert!(matches!(config.secret("api.token", &secrets).await, Err(SecretError::Missing(_))));
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
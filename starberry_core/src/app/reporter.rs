@@ -0,0 +1,128 @@
+//! Reporting of production errors to an external service.
+//!
+//! An `ErrorReporter` set with `AppBuilder::error_reporter` is given an `ErrorReport` for each
+//! request whose handler panicked, each response sent with a 5xx status, and each error reported
+//! by a handler with `req.report_error`. Reports carry the `ErrorContext` of the request, with
+//! the secrets redacted, and are sent by a background task of the application so they never
+//! delay the response.
+//!
+//! With the `sentry` feature, `SentryReporter` sends the reports to Sentry.
+//!
+//! # Example
+//! ```rust,ignore
+//! struct Stderr;
+//!
+//! impl ErrorReporter for Stderr {
+//!     fn report(&self, report: ErrorReport) -> BoxFuture<()> {
+//!         eprintln!("{} on {}: {}", report.kind, report.context.route, report.message);
+//!         Box::pin(async {})
+//!     }
+//! }
+//!
+//! App::new().error_reporter(Stderr).build();
+//! ```
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::app::middleware::BoxFuture;
+use crate::http::error::ErrorContext;
+
+/// A reporter shared by the requests of an application
+pub type SharedErrorReporter = Arc<dyn ErrorReporter>;
+
+/// The longest message kept in a report, longer ones are cut
+const MAX_MESSAGE_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The handler or a middleware panicked, the connection was closed without response
+    Panic,
+    /// A response was sent with a 5xx status, or a handler reported an error
+    Error,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panic => write!(f, "panic"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// An error which happened while a request was handled
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub context: ErrorContext,
+    pub at: SystemTime,
+}
+
+impl ErrorReport {
+    pub fn error(message: impl Into<String>, context: ErrorContext) -> Self {
+        Self {
+            kind: ErrorKind::Error,
+            message: truncate(message.into()),
+            context,
+            at: SystemTime::now(),
+        }
+    }
+
+    /// A report of a panic, with the message given to `panic!` when there is one
+    pub fn panic(payload: &(dyn Any + Send), context: ErrorContext) -> Self {
+        let message = if let Some(message) = payload.downcast_ref::<&'static str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "Panicked without message".to_string()
+        };
+        Self {
+            kind: ErrorKind::Panic,
+            message: truncate(message),
+            context,
+            at: SystemTime::now(),
+        }
+    }
+}
+
+fn truncate(mut message: String) -> String {
+    if message.len() > MAX_MESSAGE_LENGTH {
+        let mut end = MAX_MESSAGE_LENGTH;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+    }
+    message
+}
+
+/// Sends the reports of an application somewhere, e.g. to an error tracking service
+pub trait ErrorReporter: Send + Sync + 'static {
+    fn report(&self, report: ErrorReport) -> BoxFuture<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panics_keep_their_message() {
+        let payload: Box<dyn Any + Send> = Box::new("index out of bounds");
+        let report = ErrorReport::panic(&*payload, ErrorContext::default());
+        assert_eq!(report.kind, ErrorKind::Panic);
+        assert_eq!(report.message, "index out of bounds");
+
+        let payload: Box<dyn Any + Send> = Box::new(format!("user {} missing", 7));
+        assert_eq!(ErrorReport::panic(&*payload, ErrorContext::default()).message, "user 7 missing");
+        let payload: Box<dyn Any + Send> = Box::new(42);
+        assert_eq!(ErrorReport::panic(&*payload, ErrorContext::default()).message, "Panicked without message");
+
+        let report = ErrorReport::error("é".repeat(1000), ErrorContext::default());
+        assert_eq!(report.message.len(), MAX_MESSAGE_LENGTH);
+    }
+}
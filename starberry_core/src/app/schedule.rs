@@ -0,0 +1,412 @@
+//! Recurring background jobs.
+//!
+//! A job is an async function run on a `Schedule`, either at a fixed interval
+//! (`every(5).minutes()`) or following a cron expression (`cron("0 3 * * *")`).
+//! Jobs are started together with the server, run as tracked tasks so that shutdown
+//! waits for a run in progress, and stop being scheduled once the server shuts down.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use starberry_lib::date::civil_from_days;
+
+use super::task::TaskTracker;
+
+/// A boxed job run by the scheduler
+pub type JobFn = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// An interval being built by `every`
+#[derive(Debug, Clone, Copy)]
+pub struct Every(u64);
+
+/// Starts building a fixed interval schedule, e.g. `every(5).minutes()`
+pub fn every(amount: u64) -> Every {
+    Every(amount)
+}
+
+impl Every {
+    pub fn seconds(self) -> Schedule {
+        Schedule::interval(Duration::from_secs(self.0))
+    }
+
+    pub fn minutes(self) -> Schedule {
+        Schedule::interval(Duration::from_secs(self.0 * 60))
+    }
+
+    pub fn hours(self) -> Schedule {
+        Schedule::interval(Duration::from_secs(self.0 * 3600))
+    }
+
+    pub fn days(self) -> Schedule {
+        Schedule::interval(Duration::from_secs(self.0 * 86400))
+    }
+}
+
+/// Parses a cron expression into a schedule. Panics if the expression is invalid,
+/// use `CronExpr::from_str` to handle the error instead
+pub fn cron(expr: &str) -> Schedule {
+    match CronExpr::from_str(expr) {
+        Ok(expr) => Schedule::cron(expr),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Trigger {
+    Interval(Duration),
+    Cron(CronExpr),
+}
+
+/// When a job runs, together with its jitter and overlap settings
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    trigger: Trigger,
+    jitter: Duration,
+    allow_overlap: bool,
+}
+
+impl Schedule {
+    /// Run the job every `period`, the first run happens one period after the server starts
+    pub fn interval(period: Duration) -> Self {
+        Self {
+            trigger: Trigger::Interval(period.max(Duration::from_millis(1))),
+            jitter: Duration::ZERO,
+            allow_overlap: false,
+        }
+    }
+
+    /// Run the job at every minute matching the cron expression, in UTC
+    pub fn cron(expr: CronExpr) -> Self {
+        Self {
+            trigger: Trigger::Cron(expr),
+            jitter: Duration::ZERO,
+            allow_overlap: false,
+        }
+    }
+
+    /// Delay every run by a random duration up to `jitter`, so that several instances
+    /// of the application do not run the job at the same instant
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Start a run even if the previous one is still in progress.
+    /// By default a run is skipped while the previous one has not finished
+    pub fn allow_overlap(mut self) -> Self {
+        self.allow_overlap = true;
+        self
+    }
+
+    /// Returns the time to wait from `now` until the next run, jitter excluded
+    pub fn next_delay(&self, now: SystemTime) -> Option<Duration> {
+        match &self.trigger {
+            Trigger::Interval(period) => Some(*period),
+            Trigger::Cron(expr) => {
+                let next = expr.next_after(now)?;
+                Some(next.duration_since(now).unwrap_or_default())
+            }
+        }
+    }
+
+    fn random_jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        Duration::from_nanos(random % (self.jitter.as_nanos().min(u64::MAX as u128) as u64 + 1))
+    }
+}
+
+/// A job registered on the application, started when the server runs
+pub struct Job {
+    pub name: String,
+    pub schedule: Schedule,
+    job: JobFn,
+}
+
+impl Job {
+    pub fn new<F, Fut>(name: impl Into<String>, schedule: Schedule, job: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            schedule,
+            job: Arc::new(move || -> Pin<Box<dyn Future<Output = ()> + Send>> { Box::pin(job()) }),
+        }
+    }
+
+    /// Runs the job on its schedule until the tracker shuts down
+    pub fn start(self, tasks: &TaskTracker) {
+        let tracker = tasks.clone();
+        let shutdown = tasks.shutdown_token();
+        tasks.spawn(async move {
+            let running = Arc::new(AtomicBool::new(false));
+            loop {
+                let Some(delay) = self.schedule.next_delay(SystemTime::now()) else {
+                    eprintln!("Job {} will never run again, stopping it", self.name);
+                    return;
+                };
+                let delay = delay + self.schedule.random_jitter();
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = tokio::time::sleep(delay) => {}
+                }
+                if self.schedule.allow_overlap {
+                    tracker.spawn((self.job)());
+                } else if !running.swap(true, Ordering::AcqRel) {
+                    let running = running.clone();
+                    let job = (self.job)();
+                    tracker.spawn(async move {
+                        let _reset = ResetOnDrop(running);
+                        job.await;
+                    });
+                }
+            }
+        });
+    }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Job")
+            .field("name", &self.name)
+            .field("schedule", &self.schedule)
+            .finish()
+    }
+}
+
+/// Clears the running flag even if the job panicked
+struct ResetOnDrop(Arc<AtomicBool>);
+
+impl Drop for ResetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Error returned when a cron expression cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError(String);
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid cron expression: {}", self.0)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// A standard five field cron expression: minute, hour, day of month, month and day of week.
+/// Fields accept `*`, numbers, ranges `a-b`, steps `*/n` or `a-b/n` and comma separated lists.
+/// Day of week is 0 to 7, both 0 and 7 being Sunday. Times are in UTC
+///
+/// # Examples
+///
+/// ```
+/// use std::str::FromStr;
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use starberry_core::app::schedule::CronExpr;
+///
+/// // Every day at 03:30
+/// let expr = CronExpr::from_str("30 3 * * *").unwrap();
+/// let next = expr.next_after(UNIX_EPOCH).unwrap();
+/// assert_eq!(next, UNIX_EPOCH + Duration::from_secs(3 * 3600 + 30 * 60));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for CronExpr {
+    type Err = CronError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(CronError(format!("expected 5 fields, found {}", fields.len())));
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, CronError> {
+    let number = |s: &str| -> Result<u64, CronError> {
+        let n = s
+            .parse::<u64>()
+            .map_err(|_| CronError(format!("`{}` is not a number", s)))?;
+        if n < min || n > max {
+            return Err(CronError(format!("{} is out of range {}-{}", n, min, max)));
+        }
+        Ok(n)
+    };
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, number_step(step)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (number(a)?, number(b)?)
+        } else {
+            let n = number(range)?;
+            (n, if step > 1 { max } else { n })
+        };
+        if start > end {
+            return Err(CronError(format!("range `{}` is reversed", range)));
+        }
+        for n in (start..=end).step_by(step as usize) {
+            set |= 1 << n;
+        }
+    }
+    Ok(set)
+}
+
+fn number_step(step: &str) -> Result<u64, CronError> {
+    match step.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(CronError(format!("`{}` is not a valid step", step))),
+    }
+}
+
+impl CronExpr {
+    fn matches_day(&self, day: u64, weekday: u64) -> bool {
+        let day_match = self.days & (1 << day) != 0;
+        let weekday_match = self.weekdays & (1 << weekday) != 0;
+        // As in cron, when both fields are restricted either of them may match
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day_match || weekday_match,
+            _ => day_match && weekday_match,
+        }
+    }
+
+    /// Returns the first matching minute strictly after `time`, or `None` if there is none
+    /// within the next five years (e.g. `0 0 31 2 *`)
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let mut minute = secs / 60 + 1;
+        let limit = minute + 5 * 366 * 24 * 60;
+        while minute < limit {
+            let days = minute / 1440;
+            let (_, month, day) = civil_from_days(days as i64);
+            if self.months & (1 << month) == 0 {
+                // Skip to the first day of the next month
+                minute = (days + days_in_month_left(days)) * 1440;
+                continue;
+            }
+            if !self.matches_day(day as u64, (days + 4) % 7) {
+                minute = (days + 1) * 1440;
+                continue;
+            }
+            let hour = minute % 1440 / 60;
+            if self.hours & (1 << hour) == 0 {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+            if self.minutes & (1 << (minute % 60)) == 0 {
+                minute += 1;
+                continue;
+            }
+            return Some(UNIX_EPOCH + Duration::from_secs(minute * 60));
+        }
+        None
+    }
+}
+
+/// Number of days from `days` (included) to the first day of the next month
+fn days_in_month_left(days: u64) -> u64 {
+    let (year, month, day) = civil_from_days(days as i64);
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let length = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    (length - day + 1) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn parses_fields() {
+        let expr = CronExpr::from_str("*/15 1-3 * * 1,5").unwrap();
+        assert_eq!(expr.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(expr.hours, 1 << 1 | 1 << 2 | 1 << 3);
+        assert_eq!(expr.weekdays, 1 << 1 | 1 << 5);
+        assert!(CronExpr::from_str("60 * * * *").is_err());
+        assert!(CronExpr::from_str("* * * *").is_err());
+        assert!(CronExpr::from_str("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn finds_next_minute() {
+        let expr = CronExpr::from_str("*/5 * * * *").unwrap();
+        // 00:00 is excluded, the next match is 00:05
+        assert_eq!(expr.next_after(at(0)), Some(at(300)));
+        assert_eq!(expr.next_after(at(301)), Some(at(600)));
+    }
+
+    #[test]
+    fn finds_next_month_and_weekday() {
+        // 1970-03-01 00:00
+        let expr = CronExpr::from_str("0 0 1 3 *").unwrap();
+        assert_eq!(expr.next_after(at(0)), Some(at(59 * 86400)));
+        // 1970-01-01 was a Thursday, the next Monday is the 5th
+        let expr = CronExpr::from_str("0 12 * * 1").unwrap();
+        assert_eq!(expr.next_after(at(0)), Some(at(4 * 86400 + 12 * 3600)));
+        // Sunday written as 7
+        let expr = CronExpr::from_str("0 0 * * 7").unwrap();
+        assert_eq!(expr.next_after(at(0)), Some(at(3 * 86400)));
+    }
+
+    #[test]
+    fn impossible_date_never_runs() {
+        let expr = CronExpr::from_str("0 0 31 2 *").unwrap();
+        assert_eq!(expr.next_after(at(0)), None);
+    }
+
+    #[test]
+    fn jitter_is_bounded() {
+        let schedule = every(1).minutes().with_jitter(Duration::from_secs(2));
+        for _ in 0..100 {
+            assert!(schedule.random_jitter() <= Duration::from_secs(2));
+        }
+        assert_eq!(every(1).minutes().random_jitter(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,283 @@
+//! Secrets such as private keys and database passwords, read from where they are kept instead
+//! of the configuration.
+//!
+//! A `SecretsProvider` gives a secret by name. `EnvSecrets` reads environment variables,
+//! `FileSecrets` reads one file per secret such as the `/run/secrets` of Docker and Kubernetes,
+//! and `ChainedSecrets` asks several providers in turn. With the `vault` feature,
+//! `VaultSecrets` reads the KV store of HashiCorp Vault, and with the `kms` feature, `KmsSecrets`
+//! decrypts secrets encrypted with Google Cloud KMS.
+//!
+//! The provider given to `AppBuilder::secrets` is retrieved with `App::secrets`. A setting of a
+//! `RuntimeConfig` written `secret:<name>` is resolved through a provider by
+//! `RuntimeConfig::secret`, so the configuration file only names the secret.
+//!
+//! # Example
+//! ```rust,ignore
+//! let secrets = ChainedSecrets::new()
+//!     .with(EnvSecrets::new().prefix("APP_"))
+//!     .with(FileSecrets::new("/run/secrets"));
+//! let password = secrets.require("db_password").await?;
+//! let db = DbConnectionBuilder::new("localhost", 5432).password(password.expose());
+//! App::new().secrets(secrets).build();
+//! ```
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::app::middleware::BoxFuture;
+#[cfg(any(feature = "vault", feature = "kms", feature = "acme"))]
+use crate::http::body::HttpBody;
+
+/// A provider shared by the requests of an application
+pub type SharedSecrets = Arc<dyn SecretsProvider>;
+
+/// A secret value, which is never printed by `Debug`
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// The value, to be given where it is used and nowhere else
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretError {
+    /// No provider has the secret of this name
+    Missing(String),
+    /// The provider could not be reached or refused to give the secret
+    Unavailable(String),
+    /// The name or the value of the secret cannot be used
+    Invalid(String),
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(name) => write!(f, "Missing secret {}", name),
+            Self::Unavailable(msg) => write!(f, "Secrets unavailable: {}", msg),
+            Self::Invalid(msg) => write!(f, "Invalid secret: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Gives secrets by name
+pub trait SecretsProvider: Send + Sync + 'static {
+    /// The secret called `name`, `None` if the provider does not have it
+    fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>>;
+
+    /// The secret called `name`, an error if the provider does not have it
+    fn require(&self, name: &str) -> BoxFuture<Result<Secret, SecretError>> {
+        let fetched = self.get(name);
+        let name = name.to_string();
+        Box::pin(async move { fetched.await?.ok_or(SecretError::Missing(name)) })
+    }
+}
+
+impl<P: SecretsProvider + ?Sized> SecretsProvider for Arc<P> {
+    fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>> {
+        (**self).get(name)
+    }
+}
+
+/// Reads secrets from environment variables. The secret `db.password` is the variable
+/// `DB_PASSWORD`, after the prefix if one is set. When the variable is missing but
+/// `DB_PASSWORD_FILE` is set, the secret is read from the file it names
+#[derive(Debug, Clone, Default)]
+pub struct EnvSecrets {
+    prefix: String,
+}
+
+impl EnvSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the prefix of the variables, e.g. `APP_`
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The variable holding the secret `name`
+    pub fn variable(&self, name: &str) -> String {
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+impl SecretsProvider for EnvSecrets {
+    fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>> {
+        let variable = self.variable(name);
+        Box::pin(async move {
+            if let Ok(value) = std::env::var(&variable) {
+                return Ok(Some(Secret(value)));
+            }
+            match std::env::var(format!("{}_FILE", variable)) {
+                Ok(path) => read_secret(PathBuf::from(path)).await,
+                Err(_) => Ok(None),
+            }
+        })
+    }
+}
+
+/// Reads each secret from the file of its name in a directory, such as `/run/secrets`.
+/// The line break ending the file is not part of the secret
+#[derive(Debug, Clone)]
+pub struct FileSecrets {
+    directory: PathBuf,
+}
+
+impl FileSecrets {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: directory.into() }
+    }
+}
+
+impl SecretsProvider for FileSecrets {
+    fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>> {
+        // Names are file names, they cannot lead out of the directory
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            let name = name.to_string();
+            return Box::pin(async move { Err(SecretError::Invalid(format!("{} is not a file name", name))) });
+        }
+        Box::pin(read_secret(self.directory.join(name)))
+    }
+}
+
+async fn read_secret(path: PathBuf) -> Result<Option<Secret>, SecretError> {
+    match tokio::fs::read_to_string(&path).await {
+        Ok(value) => {
+            let value = value.strip_suffix('\n').unwrap_or(&value);
+            let value = value.strip_suffix('\r').unwrap_or(value);
+            Ok(Some(Secret::new(value)))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(SecretError::Unavailable(format!("{}: {}", path.display(), e))),
+    }
+}
+
+/// Asks providers in the order they were added, the first one having the secret gives it.
+/// An unavailable provider fails the lookup rather than letting a later one answer
+#[derive(Clone, Default)]
+pub struct ChainedSecrets {
+    providers: Vec<SharedSecrets>,
+}
+
+impl ChainedSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<P: SecretsProvider>(mut self, provider: P) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+}
+
+impl SecretsProvider for ChainedSecrets {
+    fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>> {
+        let providers = self.providers.clone();
+        let name = name.to_string();
+        Box::pin(async move {
+            for provider in providers {
+                if let Some(secret) = provider.get(&name).await? {
+                    return Ok(Some(secret));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+/// The JSON body of a response of a secrets service
+#[cfg(any(feature = "vault", feature = "kms", feature = "acme"))]
+pub(crate) fn json_body(body: &HttpBody) -> Option<akari::Value> {
+    match body {
+        HttpBody::Json(value) => Some(value.clone()),
+        HttpBody::Text(text) => akari::Value::from_json(text).ok(),
+        HttpBody::Binary(bytes) => akari::Value::from_json(std::str::from_utf8(bytes).ok()?).ok(),
+        _ => None,
+    }
+}
+
+/// The member of a JSON object
+#[cfg(any(feature = "vault", feature = "kms", feature = "acme"))]
+pub(crate) fn member<'a>(value: &'a akari::Value, key: &str) -> Option<&'a akari::Value> {
+    match value {
+        akari::Value::Dict(object) => object.get(key),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    struct Fixed(HashMap<&'static str, &'static str>);
+
+    impl SecretsProvider for Fixed {
+        fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>> {
+            let secret = self.0.get(name).map(|value| Secret::new(*value));
+            Box::pin(async move { Ok(secret) })
+        }
+    }
+
+    #[test]
+    fn secrets_are_not_printed() {
+        assert_eq!(format!("{:?}", Secret::new("hunter2")), "Secret(***)");
+        assert_eq!(EnvSecrets::new().prefix("APP_").variable("db.password"), "APP_DB_PASSWORD");
+    }
+
+    #[tokio::test]
+    async fn reads_files() {
+        let directory = std::env::temp_dir().join(format!("starberry-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("db_password"), "hunter2\n").unwrap();
+        let secrets = FileSecrets::new(&directory);
+
+        assert_eq!(secrets.require("db_password").await.unwrap().expose(), "hunter2");
+        assert!(secrets.get("missing").await.unwrap().is_none());
+        assert!(matches!(secrets.require("missing").await, Err(SecretError::Missing(_))));
+        assert!(matches!(secrets.get("../etc/passwd").await, Err(SecretError::Invalid(_))));
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn chains_providers() {
+        let secrets = ChainedSecrets::new()
+            .with(Fixed(HashMap::from([("jwt_key", "first")])))
+            .with(Fixed(HashMap::from([("jwt_key", "second"), ("db_password", "hunter2")])));
+        assert_eq!(secrets.require("jwt_key").await.unwrap().expose(), "first");
+        assert_eq!(secrets.require("db_password").await.unwrap().expose(), "hunter2");
+        assert!(secrets.get("api_token").await.unwrap().is_none());
+    }
+}
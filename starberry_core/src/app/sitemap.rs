@@ -0,0 +1,322 @@
+//! Sitemaps, as described at <https://www.sitemaps.org/protocol.html>.
+//!
+//! A `Sitemap` lists the named routes without arguments, given entries, and entries
+//! produced on every request by sources, e.g. the pages of a blog read from a database.
+//! `register` serves it at `/sitemap.xml`. Above 50,000 urls the sitemap is split into
+//! pages served at `/sitemap-1.xml`, `/sitemap-2.xml`, ... and `/sitemap.xml` becomes
+//! the index of the pages.
+//!
+//! # Example
+//! ```rust,ignore
+//! Sitemap::new("https://example.com")
+//!     .named_routes()
+//!     .exclude("login")
+//!     .source(|| {
+//!         posts().iter().map(|post| {
+//!             SitemapEntry::new(format!("/posts/{}", post.slug))
+//!                 .lastmod(post.updated_at)
+//!                 .changefreq(ChangeFreq::Weekly)
+//!         }).collect()
+//!     })
+//!     .register(&APP);
+//! ```
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use starberry_lib::date::format_rfc3339;
+
+use super::application::App;
+use super::urls::{PathPattern, route_names};
+use crate::http::context::HttpReqCtx;
+use crate::http::http_value::{HttpContentType, StatusCode};
+use crate::http::response::HttpResponse;
+use crate::http::response::response_templates::{normal_response, return_status};
+use crate::http::xml::{XML_DECLARATION, escape_xml};
+
+/// The path of the sitemap, or of the sitemap index when it is split
+pub const SITEMAP_PATH: &str = "/sitemap.xml";
+
+/// The largest number of urls a sitemap file may hold
+pub const MAX_URLS: usize = 50_000;
+
+const SITEMAP_NAMESPACE: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+/// How often a page is likely to change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl ChangeFreq {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Hourly => "hourly",
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+            Self::Yearly => "yearly",
+            Self::Never => "never",
+        }
+    }
+}
+
+/// A url of the sitemap
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapEntry {
+    /// A path of the site, e.g. `/about`, or an absolute url
+    pub loc: String,
+    pub lastmod: Option<SystemTime>,
+    pub changefreq: Option<ChangeFreq>,
+    /// The priority relative to the other urls of the site, from 0.0 to 1.0
+    pub priority: Option<f32>,
+}
+
+impl SitemapEntry {
+    pub fn new<T: Into<String>>(loc: T) -> Self {
+        Self {
+            loc: loc.into(),
+            lastmod: None,
+            changefreq: None,
+            priority: None,
+        }
+    }
+
+    pub fn lastmod(mut self, time: SystemTime) -> Self {
+        self.lastmod = Some(time);
+        self
+    }
+
+    pub fn changefreq(mut self, changefreq: ChangeFreq) -> Self {
+        self.changefreq = Some(changefreq);
+        self
+    }
+
+    /// Sets the priority, clamped between 0.0 and 1.0
+    pub fn priority(mut self, priority: f32) -> Self {
+        self.priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+}
+
+type EntrySource = Arc<dyn Fn() -> Vec<SitemapEntry> + Send + Sync>;
+
+/// Builds and serves the sitemap of the site
+#[derive(Clone)]
+pub struct Sitemap {
+    base_url: String,
+    named_routes: bool,
+    excluded: Vec<String>,
+    entries: Vec<SitemapEntry>,
+    sources: Vec<EntrySource>,
+    max_urls: usize,
+}
+
+impl Sitemap {
+    /// A sitemap of the site at `base_url`, e.g. `https://example.com`
+    pub fn new<T: Into<String>>(base_url: T) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            named_routes: false,
+            excluded: Vec::new(),
+            entries: Vec::new(),
+            sources: Vec::new(),
+            max_urls: MAX_URLS,
+        }
+    }
+
+    /// Lists the named routes without arguments, see `Url::set_name`
+    pub fn named_routes(mut self) -> Self {
+        self.named_routes = true;
+        self
+    }
+
+    /// Leaves a named route out of the sitemap
+    pub fn exclude<T: Into<String>>(mut self, name: T) -> Self {
+        self.excluded.push(name.into());
+        self
+    }
+
+    pub fn entry(mut self, entry: SitemapEntry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    pub fn entries<I: IntoIterator<Item = SitemapEntry>>(mut self, entries: I) -> Self {
+        self.entries.extend(entries);
+        self
+    }
+
+    /// Adds the entries returned by the source, called every time the sitemap is served
+    pub fn source<F: Fn() -> Vec<SitemapEntry> + Send + Sync + 'static>(mut self, source: F) -> Self {
+        self.sources.push(Arc::new(source));
+        self
+    }
+
+    /// Sets the number of urls per page, at most and by default 50,000
+    pub fn max_urls(mut self, max: usize) -> Self {
+        self.max_urls = max.clamp(1, MAX_URLS);
+        self
+    }
+
+    /// The entries of the sitemap with absolute locations, without duplicates
+    pub fn collect(&self) -> Vec<SitemapEntry> {
+        let mut entries = Vec::new();
+        if self.named_routes {
+            for (name, pattern) in route_names() {
+                if self.excluded.contains(&name) || pattern.contains(['{', '<', '*']) {
+                    continue;
+                }
+                entries.push(SitemapEntry::new(pattern));
+            }
+        }
+        entries.extend(self.entries.iter().cloned());
+        for source in &self.sources {
+            entries.extend(source());
+        }
+
+        let mut seen = HashSet::new();
+        entries
+            .into_iter()
+            .map(|mut entry| {
+                entry.loc = self.absolute(&entry.loc);
+                entry
+            })
+            .filter(|entry| seen.insert(entry.loc.clone()))
+            .collect()
+    }
+
+    fn absolute(&self, loc: &str) -> String {
+        if loc.starts_with("http://") || loc.starts_with("https://") {
+            loc.to_string()
+        } else {
+            format!("{}/{}", self.base_url, loc.trim_start_matches('/'))
+        }
+    }
+
+    /// The document served at `path`, the sitemap, its index or one of its pages
+    pub fn render(&self, path: &str) -> Option<String> {
+        let entries = self.collect();
+        let pages = entries.len().div_ceil(self.max_urls).max(1);
+        if path == SITEMAP_PATH {
+            return Some(if pages == 1 { urlset(&entries) } else { self.index(pages) });
+        }
+        let page: usize = path.strip_prefix("/sitemap-")?.strip_suffix(".xml")?.parse().ok()?;
+        if page == 0 || page > pages {
+            return None;
+        }
+        let start = (page - 1) * self.max_urls;
+        let end = (start + self.max_urls).min(entries.len());
+        Some(urlset(&entries[start..end]))
+    }
+
+    fn index(&self, pages: usize) -> String {
+        let mut out = format!("{}<sitemapindex xmlns=\"{}\">", XML_DECLARATION, SITEMAP_NAMESPACE);
+        for page in 1..=pages {
+            out.push_str(&format!(
+                "<sitemap><loc>{}</loc></sitemap>",
+                escape_xml(&format!("{}/sitemap-{}.xml", self.base_url, page))
+            ));
+        }
+        out.push_str("</sitemapindex>");
+        out
+    }
+
+    /// Answers a request for the sitemap, its index or one of its pages
+    pub fn respond(&self, path: &str) -> HttpResponse {
+        match self.render(path) {
+            Some(xml) => normal_response(StatusCode::OK, xml).content_type(HttpContentType::ApplicationXml()),
+            None => return_status(StatusCode::NOT_FOUND),
+        }
+    }
+
+    /// Serves the sitemap at `/sitemap.xml` and its pages at `/sitemap-<n>.xml`
+    pub fn register(self, app: &Arc<App>) {
+        let sitemap = Arc::new(self);
+        let segments = [
+            PathPattern::literal_path(SITEMAP_PATH.trim_start_matches('/')),
+            PathPattern::regex_path(r"^sitemap-\d+\.xml$"),
+        ];
+        for segment in segments {
+            let sitemap = sitemap.clone();
+            app.reg_from::<HttpReqCtx>(&[segment]).set_method(Arc::new(move |mut req: HttpReqCtx| {
+                let sitemap = sitemap.clone();
+                async move {
+                    req.response = sitemap.respond(&req.path());
+                    req
+                }
+            }));
+        }
+    }
+}
+
+fn urlset(entries: &[SitemapEntry]) -> String {
+    let mut out = format!("{}<urlset xmlns=\"{}\">", XML_DECLARATION, SITEMAP_NAMESPACE);
+    for entry in entries {
+        out.push_str(&format!("<url><loc>{}</loc>", escape_xml(&entry.loc)));
+        if let Some(lastmod) = entry.lastmod {
+            out.push_str(&format!("<lastmod>{}</lastmod>", format_rfc3339(lastmod)));
+        }
+        if let Some(changefreq) = entry.changefreq {
+            out.push_str(&format!("<changefreq>{}</changefreq>", changefreq.as_str()));
+        }
+        if let Some(priority) = entry.priority {
+            out.push_str(&format!("<priority>{:.1}</priority>", priority));
+        }
+        out.push_str("</url>");
+    }
+    out.push_str("</urlset>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::app::urls::Url;
+
+    #[test]
+    fn lists_named_routes_and_entries() {
+        let root = Url::<HttpReqCtx>::root();
+        root.reg_from(&[PathPattern::literal_path("sitemap_about")]).set_name("test_sitemap_about");
+        root.reg_from(&[PathPattern::literal_path("sitemap_user"), PathPattern::argument("id")])
+            .set_name("test_sitemap_user");
+        root.reg_from(&[PathPattern::literal_path("sitemap_login")]).set_name("test_sitemap_login");
+
+        let sitemap = Sitemap::new("https://example.com/")
+            .named_routes()
+            .exclude("test_sitemap_login")
+            .entry(SitemapEntry::new("/posts/1?a=1&b=2").lastmod(UNIX_EPOCH + Duration::from_secs(784111777)))
+            .source(|| vec![SitemapEntry::new("/posts/2").changefreq(ChangeFreq::Weekly).priority(2.0)]);
+        let locs: Vec<String> = sitemap.collect().into_iter().map(|entry| entry.loc).collect();
+        assert!(locs.contains(&"https://example.com/sitemap_about".to_string()));
+        assert!(!locs.iter().any(|loc| loc.contains("sitemap_user") || loc.contains("sitemap_login")));
+
+        let xml = sitemap.render(SITEMAP_PATH).unwrap();
+        assert!(xml.contains("<loc>https://example.com/posts/1?a=1&amp;b=2</loc><lastmod>1994-11-06T08:49:37Z</lastmod>"));
+        assert!(xml.contains("<changefreq>weekly</changefreq><priority>1.0</priority>"));
+    }
+
+    #[test]
+    fn splits_into_an_index() {
+        let sitemap = Sitemap::new("https://example.com")
+            .max_urls(2)
+            .entries((1..=5).map(|i| SitemapEntry::new(format!("/page/{}", i))));
+        let index = sitemap.render(SITEMAP_PATH).unwrap();
+        assert!(index.contains("<sitemapindex"));
+        assert!(index.contains("<loc>https://example.com/sitemap-3.xml</loc>"));
+        let last = sitemap.render("/sitemap-3.xml").unwrap();
+        assert!(last.contains("/page/5") && !last.contains("/page/4"));
+        assert!(sitemap.render("/sitemap-4.xml").is_none());
+        assert!(sitemap.render("/sitemap-0.xml").is_none());
+    }
+}
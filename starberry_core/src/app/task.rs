@@ -0,0 +1,204 @@
+//! Background work tied to the lifetime of the application.
+//!
+//! Tasks spawned through a `TaskTracker` are counted so that the application can
+//! wait for them before it shuts down, and they can observe the shutdown through a
+//! `CancellationToken`. The id of the request that spawned a task is carried along,
+//! so logs written from the task can still be matched with the request.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::app::trace;
+use crate::connection::{CancelReason, CancellationToken};
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Returns the id of the request being processed by the current task, if any.
+/// Tasks spawned with `TaskTracker` inherit the id of the request they were spawned from.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Runs a future with the given request id, making it available through `current_request_id`
+pub async fn with_request_id<F: Future>(id: String, fut: F) -> F::Output {
+    REQUEST_ID.scope(id, fut).await
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    running: AtomicUsize,
+    idle: Notify,
+    shutdown: CancellationToken,
+}
+
+/// Decrements the number of running tasks when dropped, so that a task which
+/// panics or is aborted is still accounted for
+struct TaskGuard {
+    state: Arc<TrackerState>,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if self.state.running.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.state.idle.notify_waiters();
+        }
+    }
+}
+
+/// Keeps count of the background tasks of an application.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use starberry_core::app::task::TaskTracker;
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+/// runtime.block_on(async {
+///     let tracker = TaskTracker::new();
+///     let shutdown = tracker.shutdown_token();
+///     tracker.spawn(async move { shutdown.cancelled().await });
+///     assert_eq!(tracker.running(), 1);
+///
+///     assert!(tracker.shutdown(Duration::from_secs(1)).await);
+///     assert_eq!(tracker.running(), 0);
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TaskTracker {
+    state: Arc<TrackerState>,
+}
+
+impl TaskTracker {
+    /// Creates a tracker with no running task
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn guard(&self) -> TaskGuard {
+        self.state.running.fetch_add(1, Ordering::AcqRel);
+        TaskGuard {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Spawns a future on the runtime. It inherits the current request id and trace context
+    pub fn spawn<F>(&self, fut: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let guard = self.guard();
+        let request_id = current_request_id();
+        let trace = trace::current_trace();
+        tokio::spawn(async move {
+            let _guard = guard;
+            let fut = trace::scoped(trace, fut);
+            match request_id {
+                Some(id) => REQUEST_ID.scope(id, fut).await,
+                None => fut.await,
+            }
+        })
+    }
+
+    /// Runs a blocking closure on the blocking pool. It inherits the current request id and trace context
+    pub fn spawn_blocking<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let guard = self.guard();
+        let request_id = current_request_id();
+        let trace = trace::current_trace();
+        tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            let f = move || trace::sync_scoped(trace, f);
+            match request_id {
+                Some(id) => REQUEST_ID.sync_scope(id, f),
+                None => f(),
+            }
+        })
+    }
+
+    /// Returns the number of tasks which have not finished yet
+    pub fn running(&self) -> usize {
+        self.state.running.load(Ordering::Acquire)
+    }
+
+    /// Returns the token cancelled when the application shuts down.
+    /// Long-running tasks should watch it and stop early
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.state.shutdown.clone()
+    }
+
+    /// Waits until every task has finished
+    pub async fn wait(&self) {
+        loop {
+            let notified = self.state.idle.notified();
+            if self.running() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Cancels the shutdown token and waits at most `grace` for the tasks to finish.
+    /// Returns `false` if some tasks were still running when the grace period elapsed
+    pub async fn shutdown(&self, grace: Duration) -> bool {
+        self.state.shutdown.cancel(CancelReason::Manual);
+        tokio::time::timeout(grace, self.wait()).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spawned_tasks_inherit_the_request_id() {
+        let tracker = TaskTracker::new();
+        let (task, blocking) = with_request_id("abc".to_string(), async {
+            (
+                tracker.spawn(async { current_request_id() }),
+                tracker.spawn_blocking(current_request_id),
+            )
+        })
+        .await;
+        assert_eq!(task.await.unwrap().as_deref(), Some("abc"));
+        assert_eq!(blocking.await.unwrap().as_deref(), Some("abc"));
+        assert_eq!(current_request_id(), None);
+    }
+
+    #[tokio::test]
+    async fn spawned_tasks_inherit_the_trace() {
+        let tracker = TaskTracker::new();
+        let root = trace::TraceContext::new_root();
+        let (task, blocking) = trace::with_trace(root.clone(), async {
+            (
+                tracker.spawn(async { trace::current_trace() }),
+                tracker.spawn_blocking(trace::current_trace),
+            )
+        })
+        .await;
+        assert_eq!(task.await.unwrap(), Some(root.clone()));
+        assert_eq!(blocking.await.unwrap(), Some(root));
+    }
+
+    #[tokio::test]
+    async fn shutdown_times_out_on_stuck_tasks() {
+        let tracker = TaskTracker::new();
+        let handle = tracker.spawn(std::future::pending::<()>());
+        assert!(!tracker.shutdown(Duration::from_millis(10)).await);
+        assert_eq!(tracker.running(), 1);
+        handle.abort();
+        let _ = handle.await;
+        assert_eq!(tracker.running(), 0);
+    }
+}
@@ -0,0 +1,249 @@
+//! Time limits of the requests per route, set in code or in the settings of a `RuntimeConfig`.
+//!
+//! Without rule, a request is limited by the max connection time of the application. A rule
+//! gives a limit to the routes of a pattern and the routes under it, e.g. `/export` covers
+//! `/export/{format}`, optionally for one method only. The longest matching pattern wins, and a
+//! rule for the method wins over a rule for every method. A request which exceeds its limit is
+//! cancelled and its connection closed, as when the max connection time elapses.
+//!
+//! In a `RuntimeConfig`, the rules are the settings under `timeouts`, the key being an optional
+//! method and the pattern, the value a duration such as `500ms`, `30s`, `2m` or a number of
+//! seconds. `timeouts.default` replaces the max connection time for the routes without rule.
+//! The rules of the configuration win over the ones set in code, and follow the reloads of the
+//! file when `timeouts` is reloadable.
+//!
+//! # Example
+//! ```rust,ignore
+//! // config/runtime.json
+//! // { "timeouts": { "default": "10s", "/export": "2m", "POST /import": "5m" } }
+//! let config = RuntimeConfig::from_file("config/runtime.json")?.reloadable(["timeouts"]);
+//! let timeouts = RouteTimeouts::new().route("/reports", Duration::from_secs(60)).from_config(&config);
+//! App::new().route_timeouts(timeouts).runtime_config(config).build();
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::app::reload::RuntimeConfig;
+use crate::http::http_value::HttpMethod;
+
+/// The settings of a `RuntimeConfig` holding the rules
+const CONFIG_PREFIX: &str = "timeouts";
+
+#[derive(Debug, Clone, PartialEq)]
+struct TimeoutRule {
+    /// `None` for every method
+    method: Option<HttpMethod>,
+    /// Without trailing slash, empty for the root
+    pattern: String,
+    timeout: Duration,
+}
+
+impl TimeoutRule {
+    fn new(method: Option<HttpMethod>, pattern: &str, timeout: Duration) -> Self {
+        Self {
+            method,
+            pattern: pattern.trim().trim_end_matches('/').to_string(),
+            timeout,
+        }
+    }
+
+    /// How specific the rule is for the route, `None` if it does not cover it
+    fn rank(&self, method: &HttpMethod, route: &str) -> Option<(usize, bool)> {
+        if self.method.as_ref().is_some_and(|own| own != method) {
+            return None;
+        }
+        let rest = route.strip_prefix(self.pattern.as_str())?;
+        (rest.is_empty() || rest.starts_with('/')).then_some((self.pattern.len(), self.method.is_some()))
+    }
+}
+
+fn best(rules: &[TimeoutRule], method: &HttpMethod, route: &str) -> Option<Duration> {
+    rules
+        .iter()
+        .filter_map(|rule| rule.rank(method, route).map(|rank| (rank, rule.timeout)))
+        .max_by_key(|(rank, _)| *rank)
+        .map(|(_, timeout)| timeout)
+}
+
+/// The rules read from a `RuntimeConfig`
+#[derive(Debug, Default)]
+struct Configured {
+    /// The settings under `timeouts`, as written
+    entries: HashMap<String, String>,
+    default: Option<Duration>,
+    rules: Vec<TimeoutRule>,
+}
+
+impl Configured {
+    fn rebuild(&mut self) {
+        self.default = None;
+        self.rules.clear();
+        for (key, value) in &self.entries {
+            let Some(rule) = key.strip_prefix(CONFIG_PREFIX).and_then(|rest| rest.strip_prefix('.')) else {
+                continue;
+            };
+            let Some(timeout) = parse_duration(value) else {
+                eprintln!("⚠️ Ignoring setting {}: {} is not a duration", key, value);
+                continue;
+            };
+            if rule == "default" {
+                self.default = Some(timeout);
+                continue;
+            }
+            let (method, pattern) = match rule.split_once(' ') {
+                Some((method, pattern)) => (Some(HttpMethod::from_string(&method.to_uppercase())), pattern.trim()),
+                None => (None, rule),
+            };
+            if method == Some(HttpMethod::UNKNOWN) || !pattern.starts_with('/') {
+                eprintln!("⚠️ Ignoring setting {}: expected an optional method and a route pattern", key);
+                continue;
+            }
+            self.rules.push(TimeoutRule::new(method, pattern, timeout));
+        }
+    }
+}
+
+/// The time limits of the requests per route, given to `AppBuilder::route_timeouts`
+#[derive(Debug, Clone, Default)]
+pub struct RouteTimeouts {
+    default: Option<Duration>,
+    rules: Vec<TimeoutRule>,
+    configured: Arc<RwLock<Configured>>,
+}
+
+impl RouteTimeouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the limit of the routes without rule, the max connection time of the application
+    /// otherwise
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default = Some(timeout);
+        self
+    }
+
+    /// Limits the requests to the routes of the pattern, e.g. `/user/{id}`, and under it
+    pub fn route(mut self, pattern: &str, timeout: Duration) -> Self {
+        self.rules.push(TimeoutRule::new(None, pattern, timeout));
+        self
+    }
+
+    /// Limits the requests of one method to the routes of the pattern and under it
+    pub fn route_method(mut self, method: HttpMethod, pattern: &str, timeout: Duration) -> Self {
+        self.rules.push(TimeoutRule::new(Some(method), pattern, timeout));
+        self
+    }
+
+    /// Reads the rules from the settings under `timeouts`, and applies their reloads
+    pub fn from_config(self, config: &RuntimeConfig) -> Self {
+        {
+            let mut configured = self.configured.write().unwrap_or_else(|e| e.into_inner());
+            configured.entries = config
+                .snapshot()
+                .iter()
+                .filter(|(key, _)| key.starts_with(CONFIG_PREFIX))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            configured.rebuild();
+        }
+        let configured = self.configured.clone();
+        config.on_change(CONFIG_PREFIX, move |change| {
+            let mut configured = configured.write().unwrap_or_else(|e| e.into_inner());
+            match &change.new {
+                Some(value) => configured.entries.insert(change.key.clone(), value.clone()),
+                None => configured.entries.remove(&change.key),
+            };
+            configured.rebuild();
+        });
+        self
+    }
+
+    /// The limit of a request to the route, given as its pattern. `None` if no rule applies
+    pub fn timeout_for(&self, method: &HttpMethod, route: &str) -> Option<Duration> {
+        let configured = self.configured.read().unwrap_or_else(|e| e.into_inner());
+        best(&configured.rules, method, route)
+            .or_else(|| best(&self.rules, method, route))
+            .or(configured.default)
+            .or(self.default)
+    }
+
+    /// The longest limit, the connections are kept open at least this long
+    pub fn longest(&self) -> Option<Duration> {
+        let configured = self.configured.read().unwrap_or_else(|e| e.into_inner());
+        configured
+            .rules
+            .iter()
+            .chain(&self.rules)
+            .map(|rule| rule.timeout)
+            .chain(configured.default)
+            .chain(self.default)
+            .max()
+    }
+}
+
+/// Reads a duration such as `250ms`, `30s`, `2m` or `1h`. A bare number is a number of seconds
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_durations() {
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration(" 30s "), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration("5 days"), None);
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let timeouts = RouteTimeouts::new()
+            .route("/export", Duration::from_secs(120))
+            .route("/export/{format}/", Duration::from_secs(60))
+            .route_method(HttpMethod::POST, "/export", Duration::from_secs(300));
+        let get = HttpMethod::GET;
+        assert_eq!(timeouts.timeout_for(&get, "/export"), Some(Duration::from_secs(120)));
+        assert_eq!(timeouts.timeout_for(&get, "/export/{format}/preview"), Some(Duration::from_secs(60)));
+        assert_eq!(timeouts.timeout_for(&HttpMethod::POST, "/export"), Some(Duration::from_secs(300)));
+        assert_eq!(timeouts.timeout_for(&get, "/exports"), None);
+        assert_eq!(timeouts.longest(), Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn follows_the_config() {
+        let values = [("timeouts.default", "10s"), ("timeouts./reports", "1m"), ("timeouts.bogus", "1m"), ("port", "3000")];
+        let config = RuntimeConfig::new(values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+            .reloadable(["timeouts"]);
+        let timeouts = RouteTimeouts::new().route("/reports", Duration::from_secs(5)).from_config(&config);
+        let get = HttpMethod::GET;
+        assert_eq!(timeouts.timeout_for(&get, "/reports/{id}"), Some(Duration::from_secs(60)));
+        assert_eq!(timeouts.timeout_for(&get, "/"), Some(Duration::from_secs(10)));
+
+        let mut values = (*config.snapshot()).clone();
+        values.remove("timeouts./reports");
+        values.insert("timeouts.POST /import".to_string(), "5m".to_string());
+        config.replace(values);
+        assert_eq!(timeouts.timeout_for(&get, "/reports/{id}"), Some(Duration::from_secs(5)));
+        assert_eq!(timeouts.timeout_for(&HttpMethod::POST, "/import"), Some(Duration::from_secs(300)));
+        assert_eq!(timeouts.timeout_for(&get, "/import"), Some(Duration::from_secs(10)));
+    }
+}
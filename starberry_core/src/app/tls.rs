@@ -0,0 +1,182 @@
+//! Serving HTTPS from the application itself.
+//!
+//! `TlsCertificates` chooses the certificate of each handshake from the server name asked
+//! by the client (SNI). Certificates can be inserted and replaced while the server runs, new
+//! handshakes use the new certificate right away, which is how the ACME client installs the
+//! renewed ones without a restart. It also answers the TLS-ALPN-01 challenges (RFC 8737)
+//! with the validation certificates published in it.
+//!
+//! # Example
+//! ```rust,ignore
+//! let certs = TlsCertificates::new();
+//! certs.insert_pem(
+//!     "example.com",
+//!     &std::fs::read_to_string("certs/example.com.crt")?,
+//!     &std::fs::read_to_string("certs/example.com.key")?,
+//! )?;
+//! let app = App::new().binding("0.0.0.0:443").tls(certs).redirect_http("0.0.0.0:80").build();
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use rustls::ServerConfig;
+use rustls::crypto::ring::default_provider;
+use rustls::crypto::ring::sign::any_supported_type;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+/// The ALPN protocol of the TLS-ALPN-01 challenge
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// The certificates served by the TLS listener of an application, by domain
+#[derive(Clone)]
+pub struct TlsCertificates {
+    resolver: Arc<Resolver>,
+    acceptor: TlsAcceptor,
+}
+
+#[derive(Debug, Default)]
+struct Resolver {
+    certs: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+    /// The validation certificates of the pending TLS-ALPN-01 challenges
+    challenges: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = client_hello.server_name()?.to_ascii_lowercase();
+        if client_hello.alpn().is_some_and(|mut protocols| protocols.any(|p| p == ACME_TLS_ALPN)) {
+            return self.challenges.read().unwrap_or_else(|e| e.into_inner()).get(&name).cloned();
+        }
+        let certs = self.certs.read().unwrap_or_else(|e| e.into_inner());
+        certs
+            .get(&name)
+            .or_else(|| {
+                let (_, parent) = name.split_once('.')?;
+                certs.get(&format!("*.{}", parent))
+            })
+            .cloned()
+    }
+}
+
+impl TlsCertificates {
+    /// Creates an empty set of certificates. Handshakes fail until the certificate of
+    /// their domain is inserted
+    pub fn new() -> Self {
+        let resolver = Arc::new(Resolver::default());
+        let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default protocol versions")
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+        config.alpn_protocols = vec![b"http/1.1".to_vec(), ACME_TLS_ALPN.to_vec()];
+        Self {
+            resolver,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        }
+    }
+
+    /// Serves `cert` for `domain`, replacing its previous certificate. A domain such as
+    /// `*.example.com` is used for the subdomains without a certificate of their own
+    pub fn insert<T: Into<String>>(&self, domain: T, cert: CertifiedKey) {
+        self.resolver
+            .certs
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(domain.into().to_ascii_lowercase(), Arc::new(cert));
+    }
+
+    /// Serves a PEM certificate chain, leaf first, with its PEM private key for `domain`
+    pub fn insert_pem<T: Into<String>>(&self, domain: T, cert_chain_pem: &str, private_key_pem: &str) -> io::Result<()> {
+        let chain = CertificateDer::pem_slice_iter(cert_chain_pem.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid_data)?;
+        if chain.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no certificate in the chain"));
+        }
+        let key = PrivateKeyDer::from_pem_slice(private_key_pem.as_bytes()).map_err(invalid_data)?;
+        let key = any_supported_type(&key).map_err(invalid_data)?;
+        self.insert(domain, CertifiedKey::new(chain, key));
+        Ok(())
+    }
+
+    /// Stops serving the certificate of `domain`
+    pub fn remove(&self, domain: &str) -> bool {
+        self.resolver
+            .certs
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&domain.to_ascii_lowercase())
+            .is_some()
+    }
+
+    /// Returns `true` if a certificate is served for `domain`, not counting wildcards
+    pub fn contains(&self, domain: &str) -> bool {
+        self.resolver
+            .certs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(&domain.to_ascii_lowercase())
+    }
+
+    /// Answers the TLS-ALPN-01 challenge of `domain` with a validation certificate, DER
+    /// encoded, and its PKCS#8 private key
+    pub fn insert_challenge<T: Into<String>>(&self, domain: T, cert_der: Vec<u8>, pkcs8_key_der: Vec<u8>) -> io::Result<()> {
+        let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(pkcs8_key_der));
+        let key = any_supported_type(&key).map_err(invalid_data)?;
+        let cert = CertifiedKey::new(vec![CertificateDer::from(cert_der)], key);
+        self.resolver
+            .challenges
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(domain.into().to_ascii_lowercase(), Arc::new(cert));
+        Ok(())
+    }
+
+    /// Withdraws the TLS-ALPN-01 challenge of `domain` once it is validated
+    pub fn remove_challenge(&self, domain: &str) {
+        self.resolver
+            .challenges
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&domain.to_ascii_lowercase());
+    }
+
+    /// Performs the server handshake. Returns `None` for the connections of a TLS-ALPN-01
+    /// validation, which are closed as soon as the handshake is done
+    pub(crate) async fn accept(&self, stream: TcpStream) -> io::Result<Option<TlsStream<TcpStream>>> {
+        let mut stream = self.acceptor.accept(stream).await?;
+        if stream.get_ref().1.alpn_protocol() == Some(ACME_TLS_ALPN) {
+            let _ = stream.shutdown().await;
+            return Ok(None);
+        }
+        Ok(Some(stream))
+    }
+}
+
+impl Default for TlsCertificates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TlsCertificates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut domains: Vec<String> = self.resolver.certs.read().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect();
+        domains.sort();
+        f.debug_struct("TlsCertificates").field("domains", &domains).finish()
+    }
+}
+
+fn invalid_data<E: fmt::Debug>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
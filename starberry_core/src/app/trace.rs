@@ -0,0 +1,197 @@
+//! Trace context propagation, following W3C Trace Context (`traceparent` and `tracestate`).
+//!
+//! Each request is handled within a `TraceContext`: a child of the `traceparent` sent by
+//! the client or a proxy, or the root of a new trace. Requests sent with the HTTP client
+//! while it is handled carry the request id and a `traceparent` naming a new child span,
+//! and each of them is recorded as a `Span` handed to the hooks registered with `on_span`,
+//! to be logged or exported to a tracing backend.
+//!
+//! # Example
+//! ```rust,ignore
+//! trace::on_span(|span| {
+//!     println!("{} {} {}{} -> {:?} in {:?}", span.trace_id, span.method, span.host, span.path, span.status, span.duration);
+//! });
+//! ```
+
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use once_cell::sync::Lazy;
+use starberry_lib::uuid::uuid_v4;
+
+tokio::task_local! {
+    static TRACE: TraceContext;
+}
+
+/// The position of the current task in a distributed trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits shared by every span of the trace
+    pub trace_id: String,
+    /// 16 lowercase hex digits identifying the current span
+    pub span_id: String,
+    /// Whether the caller records the trace
+    pub sampled: bool,
+    /// The vendor specific `tracestate` header, passed along unchanged
+    pub state: Option<String>,
+}
+
+impl TraceContext {
+    /// The first span of a new trace
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: random_hex(32),
+            span_id: random_hex(16),
+            sampled: true,
+            state: None,
+        }
+    }
+
+    /// Parses a `traceparent` header, `00-<trace id>-<parent id>-<flags>`. Versions above
+    /// `00` are read the same way, as the specification requires
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+        if !is_hex(version, 2) || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        if !is_hex(trace_id, 32) || !is_hex(span_id, 16) || !is_hex(flags, 2) {
+            return None;
+        }
+        if trace_id.bytes().all(|b| b == b'0') || span_id.bytes().all(|b| b == b'0') {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            sampled: u8::from_str_radix(flags, 16).ok()? & 1 == 1,
+            state: None,
+        })
+    }
+
+    pub fn with_state<T: Into<String>>(mut self, state: T) -> Self {
+        self.state = Some(state.into());
+        self
+    }
+
+    /// A new span of the same trace, whose parent is this one
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: random_hex(16),
+            ..self.clone()
+        }
+    }
+
+    /// The `traceparent` header naming this span
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, if self.sampled { "01" } else { "00" })
+    }
+}
+
+/// Returns the trace context of the current task, if it handles a request or was spawned by one
+pub fn current_trace() -> Option<TraceContext> {
+    TRACE.try_with(|trace| trace.clone()).ok()
+}
+
+/// Runs a future within the given trace context, making it available through `current_trace`
+pub async fn with_trace<F: Future>(trace: TraceContext, fut: F) -> F::Output {
+    TRACE.scope(trace, fut).await
+}
+
+/// Runs a future within the trace context if there is one
+pub(crate) async fn scoped<F: Future>(trace: Option<TraceContext>, fut: F) -> F::Output {
+    match trace {
+        Some(trace) => TRACE.scope(trace, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Runs a closure within the trace context if there is one
+pub(crate) fn sync_scoped<T, F: FnOnce() -> T>(trace: Option<TraceContext>, f: F) -> T {
+    match trace {
+        Some(trace) => TRACE.sync_scope(trace, f),
+        None => f(),
+    }
+}
+
+/// A request sent to another service while handling a request
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub trace_id: String,
+    pub span_id: String,
+    /// The span of the request being handled
+    pub parent_id: Option<String>,
+    /// The id of the request being handled
+    pub request_id: Option<String>,
+    pub method: String,
+    /// The upstream host, with its port if it is not the default one
+    pub host: String,
+    pub path: String,
+    /// The status of the response, `None` if no response was received
+    pub status: Option<u16>,
+    /// Why no response was received
+    pub error: Option<String>,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+}
+
+type SpanHook = Arc<dyn Fn(&Span) + Send + Sync>;
+
+static SPAN_HOOKS: Lazy<RwLock<Vec<SpanHook>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Registers a hook called with every recorded span
+pub fn on_span<F: Fn(&Span) + Send + Sync + 'static>(hook: F) {
+    SPAN_HOOKS.write().unwrap_or_else(|e| e.into_inner()).push(Arc::new(hook));
+}
+
+/// Hands a span to the hooks registered with `on_span`
+pub fn record_span(span: Span) {
+    let hooks = SPAN_HOOKS.read().unwrap_or_else(|e| e.into_inner()).clone();
+    for hook in hooks {
+        hook(&span);
+    }
+}
+
+fn random_hex(digits: usize) -> String {
+    uuid_v4().replace('-', "")[..digits].to_string()
+}
+
+fn is_hex(value: &str, digits: usize) -> bool {
+    value.len() == digits && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_traceparent() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let trace = TraceContext::parse(header).unwrap();
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(trace.sampled);
+        assert_eq!(trace.traceparent(), header);
+
+        let child = trace.child();
+        assert_eq!(child.trace_id, trace.trace_id);
+        assert_ne!(child.span_id, trace.span_id);
+        assert!(TraceContext::parse(&child.traceparent()).is_some());
+
+        assert!(TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+        assert!(TraceContext::parse("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").is_none());
+        assert!(TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra").is_none());
+        assert!(TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra").is_some());
+    }
+
+    #[tokio::test]
+    async fn trace_is_scoped_to_the_task() {
+        let trace = TraceContext::new_root();
+        let current = with_trace(trace.clone(), async { current_trace() }).await;
+        assert_eq!(current, Some(trace));
+        assert_eq!(current_trace(), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! Zero-downtime restarts by handing the listening socket over to a new process.
+//!
+//! With `AppBuilder::graceful_upgrade`, sending `SIGUSR2` to a running server starts the
+//! executable again, usually a freshly deployed binary, with the same arguments. The listening
+//! socket is passed to it as its standard input, so it accepts on the very same socket and no
+//! connection is refused while the processes switch. Once the new process reports that it is
+//! serving, the old one stops accepting, lets its open connections finish, up to the max
+//! connection time, and exits. If the new process does not report in time, it is killed and the
+//! old one keeps serving.
+//!
+//! Only the main listener is handed over: the new process binds the listener of
+//! `AppBuilder::redirect_http` again once the old one released it. Only available on Unix,
+//! the signal is ignored elsewhere.
+//!
+//! # Example
+//! ```rust,ignore
+//! App::new().graceful_upgrade(GracefulUpgrade::new()).build();
+//! // Then, after deploying the new binary
+//! // $ kill -USR2 $(pidof my_server)
+//! ```
+
+use std::io;
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+
+/// Set in the new process, tells that its standard input is the listening socket
+#[cfg(unix)]
+const LISTENER_ENV: &str = "STARBERRY_LISTENER_FD";
+
+/// Set in the new process, the Unix socket on which it reports that it is serving
+#[cfg(unix)]
+const READY_ENV: &str = "STARBERRY_UPGRADE_READY";
+
+#[cfg(unix)]
+const READY_MESSAGE: &[u8] = b"ready";
+
+/// The settings of in-place upgrades, enabled by `AppBuilder::graceful_upgrade`
+#[derive(Debug, Clone)]
+pub struct GracefulUpgrade {
+    ready_timeout: Duration,
+}
+
+impl GracefulUpgrade {
+    pub fn new() -> Self {
+        Self { ready_timeout: Duration::from_secs(30) }
+    }
+
+    /// Sets how long the new process may take to start serving, 30 seconds by default
+    pub fn ready_timeout(mut self, timeout: Duration) -> Self {
+        self.ready_timeout = timeout;
+        self
+    }
+}
+
+impl Default for GracefulUpgrade {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The listening socket given by the process this one replaces, if it was started by an upgrade
+#[cfg(unix)]
+pub(crate) fn inherited_listener() -> Option<TcpListener> {
+    use std::mem::ManuallyDrop;
+    use std::os::fd::FromRawFd;
+
+    if std::env::var(LISTENER_ENV).ok()?.trim() != "0" {
+        return None;
+    }
+    // SAFETY: the previous process set the listening socket as our standard input, which
+    // nothing else in the server reads. It is only kept when it is indeed a listening socket
+    let listener = ManuallyDrop::new(unsafe { std::net::TcpListener::from_raw_fd(0) });
+    listener.local_addr().ok()?;
+    let listener = ManuallyDrop::into_inner(listener);
+    listener.set_nonblocking(true).ok()?;
+    TcpListener::from_std(listener).ok()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn inherited_listener() -> Option<TcpListener> {
+    None
+}
+
+/// Tells the process this one replaces that it is serving, so the old one can stop
+#[cfg(unix)]
+pub(crate) async fn notify_ready() {
+    use tokio::io::AsyncWriteExt;
+
+    let Ok(path) = std::env::var(READY_ENV) else {
+        return;
+    };
+    let notified = async {
+        let mut stream = tokio::net::UnixStream::connect(&path).await?;
+        stream.write_all(READY_MESSAGE).await?;
+        stream.shutdown().await
+    };
+    if let Err(e) = notified.await {
+        eprintln!("Failed to report the upgrade as ready: {}", e);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn notify_ready() {}
+
+/// Waits for `SIGUSR2` when upgrades are enabled, forever otherwise
+pub(crate) struct UpgradeSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl UpgradeSignal {
+    pub(crate) fn new(enabled: bool) -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+            let signal = if enabled {
+                signal(SignalKind::user_defined2())
+                    .map_err(|e| eprintln!("Failed to listen for upgrade signals: {}", e))
+                    .ok()
+            } else {
+                None
+            };
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = enabled;
+            Self {}
+        }
+    }
+
+    pub(crate) async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal
+            && signal.recv().await.is_some()
+        {
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+/// Starts the executable again with the listening socket, and waits until the new process
+/// serves. Returns its process id
+#[cfg(unix)]
+pub(crate) async fn spawn_successor(listener: &TcpListener, upgrade: &GracefulUpgrade) -> io::Result<u32> {
+    use std::os::fd::AsFd;
+    use std::process::{Command, Stdio};
+
+    let socket = listener.as_fd().try_clone_to_owned()?;
+    let path = std::env::temp_dir().join(format!("starberry-upgrade-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let ready = tokio::net::UnixListener::bind(&path)?;
+
+    let spawned = Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .env(LISTENER_ENV, "0")
+        .env(READY_ENV, &path)
+        .stdin(Stdio::from(socket))
+        .spawn();
+    let result = match spawned {
+        Ok(mut child) => match wait_ready(&ready, upgrade.ready_timeout).await {
+            Ok(()) => Ok(child.id()),
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(e)
+            }
+        },
+        Err(e) => Err(e),
+    };
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn spawn_successor(_listener: &TcpListener, _upgrade: &GracefulUpgrade) -> io::Result<u32> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "In-place upgrades need Unix"))
+}
+
+/// Waits for the new process to connect and report that it is serving
+#[cfg(unix)]
+async fn wait_ready(ready: &tokio::net::UnixListener, timeout: Duration) -> io::Result<()> {
+    use tokio::io::AsyncReadExt;
+
+    let reported = async {
+        let (mut stream, _) = ready.accept().await?;
+        let mut message = Vec::new();
+        stream.read_to_end(&mut message).await?;
+        if message == READY_MESSAGE {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected upgrade report"))
+        }
+    };
+    match tokio::time::timeout(timeout, reported).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "The new process did not report as ready")),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_the_ready_report() {
+        let path = std::env::temp_dir().join(format!("starberry-upgrade-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ready = UnixListener::bind(&path).unwrap();
+
+        let silent = wait_ready(&ready, Duration::from_millis(50)).await.unwrap_err();
+        assert_eq!(silent.kind(), io::ErrorKind::TimedOut);
+
+        let reporter = path.clone();
+        tokio::spawn(async move {
+            let mut stream = UnixStream::connect(&reporter).await.unwrap();
+            stream.write_all(READY_MESSAGE).await.unwrap();
+        });
+        wait_ready(&ready, Duration::from_secs(5)).await.unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -5,11 +5,17 @@ use super::super::extensions::ParamsClone;
 use std::future::Future;
 use std::pin::Pin;
 use std::slice::Iter; 
+use std::collections::HashMap; 
 use std::sync::Arc; 
 use std::sync::RwLock; 
+use akari::Value; 
+use once_cell::sync::Lazy; 
 use regex::Regex; 
+use starberry_lib::url_encoding::encode_url_owned; 
+use crate::http::response::response_templates::set_template_global; 
 // pub static ROOT_URL: OnceLock<Url> = OnceLock::new();  
 use super::super::app::middleware::*; 
+use super::application::App; 
 
 pub struct Url<R: Rx> {
     pub path: PathPattern,
@@ -18,6 +24,8 @@ pub struct Url<R: Rx> {
     pub method: RwLock<Option<Arc<dyn AsyncFinalHandler<R>>>>, 
     pub middlewares: RwLock<Vec<Arc<dyn AsyncMiddleware<R>>>>,  
     pub params: RwLock<ParamsClone>, 
+    /// The name given by `set_name`, used by `url_for` 
+    pub name: RwLock<Option<String>>, 
 } 
 
 #[derive(Clone, Debug)] 
@@ -132,6 +140,17 @@ impl std::fmt::Display for PathPattern {
     }
 } 
 
+/// A registered route, as listed by `Url::routes` 
+#[derive(Debug, Clone, PartialEq, Eq)] 
+pub struct RouteInfo { 
+    /// The pattern of the route, e.g. `/user/{id}` 
+    pub pattern: String, 
+    /// The name given with `set_name` 
+    pub name: Option<String>, 
+    /// The type names of the middlewares run before the handler, in order 
+    pub middlewares: Vec<&'static str>, 
+} 
+
 pub enum Children<R: Rx> {
     Nil,
     Some(Vec<Arc<Url<R>>>),
@@ -170,11 +189,7 @@ impl<R: Rx + 'static> Url<R> {
             let guard = self.method.read().unwrap();
             guard.clone()
         }; 
-        // Lock the middleware 
-        let middlewares = { 
-            let guard = self.middlewares.read().unwrap(); 
-            guard.clone() 
-        }; 
+        let middlewares = self.active_middlewares(); 
         // Runs the function inside it 
         if let Some(method) = final_handler { 
             run_chain(middlewares, method, rx).await 
@@ -185,6 +200,14 @@ impl<R: Rx + 'static> Url<R> {
         }  
     } 
 
+    /// The middlewares run before the handler, without those skipped with a `SkipMiddleware` param 
+    pub fn active_middlewares(&self) -> Vec<Arc<dyn AsyncMiddleware<R>>> { 
+        let mut middlewares = self.middlewares.read().unwrap().clone(); 
+        let params = self.params.read().unwrap(); 
+        middlewares.retain(|middleware| !params.contains_type_id(middleware.skip_marker())); 
+        middlewares 
+    } 
+
     /// Walk the URL tree based on the path segments.
     /// Returns Some(Arc<Self>) if a matching URL is found, otherwise None.
     pub fn walk<'a>(
@@ -314,6 +337,47 @@ impl<R: Rx + 'static> Url<R> {
         }
     } 
 
+    /// Returns the pattern of the route leading to this url, e.g. `/user/{id}/posts`. 
+    /// Unlike the request path it is the same for every request of the route, which makes it usable in logs and metrics. 
+    pub fn route_pattern(&self) -> String { 
+        let mut segments = Vec::new(); 
+        let mut current = self; 
+        while let Ancestor::Some(ancestor) = &current.ancestor { 
+            segments.push(match &current.path { 
+                PathPattern::Literal(path) => path.clone(), 
+                PathPattern::Regex(regex) => format!("<{}>", regex), 
+                PathPattern::Pattern(_, arg) | PathPattern::Argument(arg) => format!("{{{}}}", arg), 
+                PathPattern::Any => "*".to_string(), 
+                PathPattern::AnyPath => "**".to_string(), 
+            }); 
+            current = ancestor.as_ref(); 
+        } 
+        segments.reverse(); 
+        format!("/{}", segments.join("/")) 
+    } 
+
+    /// Lists the urls under this one which have a handler, in registration order. 
+    pub fn routes(self: &Arc<Self>) -> Vec<RouteInfo> { 
+        let mut routes = Vec::new(); 
+        self._collect_routes(&mut routes); 
+        routes 
+    } 
+
+    fn _collect_routes(self: &Arc<Self>, routes: &mut Vec<RouteInfo>) { 
+        if self.method.read().unwrap().is_some() { 
+            routes.push(RouteInfo { 
+                pattern: self.route_pattern(), 
+                name: self.name.read().unwrap().clone(), 
+                middlewares: self.active_middlewares().iter().map(|m| m.name()).collect(), 
+            }); 
+        } 
+        if let Children::Some(children) = &*self.children.read().unwrap() { 
+            for child in children.iter() { 
+                child._collect_routes(routes); 
+            } 
+        } 
+    } 
+
     /// Retrieves a cloned value of type `T` from the URL's parameter storage.
     /// Returns `Some(T)` if the parameter exists and matches the type, `None` otherwise. 
     pub fn get_params<T: ParamValue + Clone + 'static>(&self) -> Option<T> {
@@ -406,6 +470,7 @@ impl<R: Rx + 'static> Url<R> {
             method: RwLock::new(function), 
             middlewares: RwLock::new(middleware), 
             params: RwLock::new(self.combine_params(&params)),  
+            name: RwLock::new(None), 
         });
 
         // Now lock for writing and insert the new child
@@ -447,6 +512,7 @@ impl<R: Rx + 'static> Url<R> {
             method: RwLock::new(None), 
             middlewares: RwLock::new(vec!()), 
             params: RwLock::new(ParamsClone::new()), 
+            name: RwLock::new(None), 
         }); 
         new_url 
     } 
@@ -563,6 +629,14 @@ impl<R: Rx + 'static> Url<R> {
         *guard = middlewares; 
     } 
 
+    /// Names the route leading to this url, so its path can be built with `url_for`. 
+    /// A name given twice refers to the last url. 
+    pub fn set_name<T: Into<String>>(&self, name: T) { 
+        let name = name.into(); 
+        register_route_name(name.clone(), self.route_pattern()); 
+        *self.name.write().unwrap() = Some(name); 
+    } 
+
     /// Combine the current URL's parameters with the provided parameters. 
     pub fn combine_params(&self, params: &ParamsClone) -> ParamsClone { 
         let guard = self.params.read().unwrap(); 
@@ -579,6 +653,102 @@ impl<R: Rx + 'static> Url<R> {
         return original 
     } 
 
+    /// Whether this url is a dangling url, returned when a registration fails. 
+    pub fn is_dangling(&self) -> bool { 
+        matches!(self.ancestor, Ancestor::Nil) && matches!(self.path, PathPattern::Any) 
+    } 
+
+    /// Creates a detached root URL. 
+    /// Library crates can build a reusable url tree on it and let the host application 
+    /// mount it under a prefix with `App::mount` or `Url::mount`. 
+    pub fn root() -> Arc<Self> { 
+        Arc::new(Self::default()) 
+    } 
+
+    /// Register a literal child url under this url, reusing this url's middlewares. 
+    /// Returns a dangling url if the registration fails. 
+    pub fn lit_url<T: AsRef<str>>(self: &Arc<Self>, url: T) -> Arc<Self> { 
+        let middlewares = self.middlewares.read().unwrap().clone(); 
+        match self.clone().literal_url(url.as_ref(), None, middlewares, ParamsClone::default()) { 
+            Ok(url) => url, 
+            Err(e) => { 
+                eprintln!("{}", e); 
+                dangling_url() 
+            } 
+        } 
+    } 
+
+    /// Walk or create the given segments under this url, reusing this url's middlewares. 
+    pub fn reg_from(self: &Arc<Self>, segments: &[PathPattern]) -> Arc<Self> { 
+        let middlewares = self.middlewares.read().unwrap().clone(); 
+        let mut current = self.clone(); 
+        for seg in segments { 
+            current = match current.get_child_or_create(seg.clone()) { 
+                Ok(url) => url, 
+                Err(e) => { 
+                    eprintln!("{}", e); 
+                    return dangling_url(); 
+                } 
+            }; 
+            current.set_middlewares(middlewares.clone()); 
+        } 
+        current 
+    } 
+
+    /// Mount a url tree under this url at the given prefix. 
+    /// The tree is copied node by node, so the mounted urls get this url as their ancestor 
+    /// and argument indices are resolved against the full path. 
+    /// Each mounted url runs the middlewares of the mount point first, then the middlewares of 
+    /// the tree root, then its own. Params of the tree are combined with the ones of the mount point. 
+    /// # Arguments 
+    /// * `prefix` - The literal prefix, e.g. "/blog". An empty prefix or "/" mounts at this url. 
+    /// * `tree` - The root of the tree to mount, usually built with `Url::root()`. 
+    /// # Returns 
+    /// * `Ok(Arc<Url>)` - The url the tree was mounted at. 
+    /// * `Err(String)` - An error message. 
+    pub fn mount(self: &Arc<Self>, prefix: &str, tree: &Arc<Self>) -> Result<Arc<Self>, String> { 
+        let mut mount_point = self.clone(); 
+        for segment in prefix.split('/').filter(|s| !s.is_empty()) { 
+            mount_point = mount_point.get_child_or_create(PathPattern::literal_path(segment))?; 
+        } 
+
+        let mut chain = mount_point.middlewares.read().unwrap().clone(); 
+        push_missing(&mut chain, &tree.middlewares.read().unwrap()); 
+        mount_point.set_middlewares(chain.clone()); 
+        if let Some(method) = tree.method.read().unwrap().clone() { 
+            mount_point.set_method(method); 
+        } 
+        if let Some(name) = tree.name.read().unwrap().clone() { 
+            mount_point.set_name(name); 
+        } 
+        let params = mount_point.combine_params(&tree.params.read().unwrap()); 
+        *mount_point.params.write().unwrap() = params; 
+
+        mount_point._graft(tree, &chain)?; 
+        Ok(mount_point) 
+    } 
+
+    /// Copies the children of `source` under this url, prepending `chain` to their middlewares. 
+    fn _graft(self: &Arc<Self>, source: &Arc<Self>, chain: &[Arc<dyn AsyncMiddleware<R>>]) -> Result<(), String> { 
+        let children = match &*source.children.read().unwrap() { 
+            Children::Nil => return Ok(()), 
+            Children::Some(children) => children.clone(), 
+        }; 
+        for child in children.iter() { 
+            let mut middlewares = chain.to_vec(); 
+            push_missing(&mut middlewares, &child.middlewares.read().unwrap()); 
+            let method = child.method.read().unwrap().clone(); 
+            let params = child.params.read().unwrap().clone(); 
+            let new_child = self.childbirth(child.path.clone(), method, middlewares, params)?; 
+            // The name now refers to the mounted route 
+            if let Some(name) = child.name.read().unwrap().clone() { 
+                new_child.set_name(name); 
+            } 
+            new_child._graft(child, chain)?; 
+        } 
+        Ok(()) 
+    } 
+
 } 
 
 impl <R: Rx + 'static> Default for Url<R> {
@@ -590,10 +760,148 @@ impl <R: Rx + 'static> Default for Url<R> {
             ancestor: Ancestor::Nil,
             middlewares: RwLock::new(vec![]),
             params: RwLock::new(ParamsClone::default()),
+            name: RwLock::new(None),
         } 
     }
 }
 
+/// Anything urls can be registered under: `App`, `Url` and their `Arc`s. 
+/// Used by the `reg!` macro to resolve the ancestor by type instead of by name. 
+pub trait RegAncestor<R: Rx + 'static> { 
+    /// Walk or create the given segments under this ancestor. 
+    /// Returns a dangling url if the registration fails. 
+    fn reg_segments(&self, segments: &[PathPattern]) -> Arc<Url<R>>; 
+} 
+
+impl<R: Rx + 'static> RegAncestor<R> for App { 
+    fn reg_segments(&self, segments: &[PathPattern]) -> Arc<Url<R>> { 
+        match self.handler.reg_from::<R>(segments) { 
+            Ok(url) => url, 
+            Err(e) => { 
+                eprintln!("{}", e); 
+                dangling_url() 
+            } 
+        } 
+    } 
+} 
+
+impl<R: Rx + 'static> RegAncestor<R> for Arc<App> { 
+    fn reg_segments(&self, segments: &[PathPattern]) -> Arc<Url<R>> { 
+        self.as_ref().reg_segments(segments) 
+    } 
+} 
+
+impl<R: Rx + 'static> RegAncestor<R> for Arc<Url<R>> { 
+    fn reg_segments(&self, segments: &[PathPattern]) -> Arc<Url<R>> { 
+        self.reg_from(segments) 
+    } 
+} 
+
+impl<R: Rx + 'static> RegAncestor<R> for Url<R> { 
+    /// A url is only reachable through its ancestor, so the shared handle is looked up there. 
+    /// Registering under a detached url which is not behind an `Arc` fails. 
+    fn reg_segments(&self, segments: &[PathPattern]) -> Arc<Url<R>> { 
+        let this = match &self.ancestor { 
+            Ancestor::Some(ancestor) => match &*ancestor.children.read().unwrap() { 
+                Children::Some(children) => children.iter().find(|c| std::ptr::eq(c.as_ref(), self)).cloned(), 
+                Children::Nil => None, 
+            }, 
+            Ancestor::Nil => None, 
+        }; 
+        match this { 
+            Some(this) => this.reg_from(segments), 
+            None => { 
+                eprintln!("Cannot register under a detached url: {}", self.path); 
+                dangling_url() 
+            } 
+        } 
+    } 
+} 
+
+/// Appends the middlewares in `extra` that are not already in `chain`. 
+fn push_missing<R: Rx>(chain: &mut Vec<Arc<dyn AsyncMiddleware<R>>>, extra: &[Arc<dyn AsyncMiddleware<R>>]) { 
+    for middleware in extra { 
+        if !chain.iter().any(|m| Arc::ptr_eq(m, middleware)) { 
+            chain.push(middleware.clone()); 
+        } 
+    } 
+} 
+
+/// The route patterns of the named urls, shared by every protocol 
+static ROUTE_NAMES: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(|| RwLock::new(HashMap::new())); 
+
+/// Records a named route and exposes the names to templates as the `routes` dict, 
+/// mapping each name to its pattern, e.g. `/user/{id}` 
+fn register_route_name(name: String, pattern: String) { 
+    let routes = { 
+        let mut names = ROUTE_NAMES.write().unwrap_or_else(|e| e.into_inner()); 
+        names.insert(name, pattern); 
+        names.iter().map(|(name, pattern)| (name.clone(), Value::Str(pattern.clone()))).collect() 
+    }; 
+    set_template_global("routes", Value::Dict(routes)); 
+} 
+
+/// The pattern of a named route, e.g. `/user/{id}` 
+pub fn route_pattern_of(name: &str) -> Option<String> { 
+    ROUTE_NAMES.read().unwrap_or_else(|e| e.into_inner()).get(name).cloned() 
+} 
+
+/// The named routes and their patterns, sorted by name
+pub fn route_names() -> Vec<(String, String)> {
+    let mut routes: Vec<(String, String)> = ROUTE_NAMES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(name, pattern)| (name.clone(), pattern.clone()))
+        .collect();
+    routes.sort();
+    routes
+}
+
+/// Builds the path of a named route. Each `{argument}` segment is replaced by the 
+/// percent-encoded value of the argument of the same name, the arguments left over are 
+/// appended as a query string. 
+/// 
+/// Fails if the route is unknown, an argument is missing, or the route contains an 
+/// unnamed regex or wildcard segment, which cannot be filled. 
+/// 
+/// # Example 
+/// ```rust,ignore 
+/// #[url(reg![&APP, LitUrl("user"), ArgUrl("id")], name = "user_detail")] 
+/// async fn user_detail() -> HttpResponse { ... } 
+/// 
+/// assert_eq!(url_for("user_detail", [("id", 42)]).unwrap(), "/user/42"); 
+/// assert_eq!(url_for("user_detail", [("id", "7"), ("tab", "posts")]).unwrap(), "/user/7?tab=posts"); 
+/// ``` 
+pub fn url_for<I, K, V>(name: &str, args: I) -> Result<String, String> 
+where 
+    I: IntoIterator<Item = (K, V)>, 
+    K: AsRef<str>, 
+    V: ToString, 
+{ 
+    let pattern = route_pattern_of(name).ok_or_else(|| format!("No route named `{}`", name))?; 
+    let mut args: Vec<(String, String)> = args.into_iter().map(|(k, v)| (k.as_ref().to_string(), v.to_string())).collect(); 
+    let mut segments = Vec::new(); 
+    for segment in pattern.split('/').filter(|s| !s.is_empty()) { 
+        if let Some(arg) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) { 
+            let index = args.iter().position(|(k, _)| k == arg) 
+                .ok_or_else(|| format!("Missing argument `{}` for route `{}`", arg, name))?; 
+            segments.push(encode_url_owned(&args.remove(index).1)); 
+        } else if segment == "*" || segment == "**" || segment.starts_with('<') { 
+            return Err(format!("Route `{}` has an unnamed segment `{}`", name, segment)); 
+        } else { 
+            segments.push(segment.to_string()); 
+        } 
+    } 
+    let mut path = format!("/{}", segments.join("/")); 
+    if !args.is_empty() { 
+        let query: Vec<String> = args.iter().map(|(k, v)| format!("{}={}", encode_url_owned(k), encode_url_owned(v))).collect(); 
+        path.push('?'); 
+        path.push_str(&query.join("&")); 
+    } 
+    Ok(path) 
+} 
+
 pub fn dangling_url<R: Rx>() -> Arc<Url<R>> { 
     Arc::new(Url { 
         path: PathPattern::Any, 
@@ -602,5 +910,63 @@ pub fn dangling_url<R: Rx>() -> Arc<Url<R>> {
         method: RwLock::new(None), 
         middlewares: RwLock::new(vec!()), 
         params: RwLock::new(ParamsClone::default()), 
+        name: RwLock::new(None), 
     }) 
 } 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::context::HttpReqCtx;
+
+    #[test]
+    fn url_for_fills_named_routes() {
+        let root = Url::<HttpReqCtx>::root();
+        root.reg_from(&[PathPattern::literal_path("user"), PathPattern::argument("id")])
+            .set_name("test_user_detail");
+        root.reg_from(&[PathPattern::literal_path("files"), PathPattern::any_path()])
+            .set_name("test_files");
+
+        assert_eq!(url_for("test_user_detail", [("id", 42)]).unwrap(), "/user/42");
+        assert_eq!(
+            url_for("test_user_detail", [("id", "a b"), ("tab", "posts")]).unwrap(),
+            "/user/a%20b?tab=posts"
+        );
+        assert!(url_for("test_user_detail", Vec::<(&str, &str)>::new()).is_err());
+        assert!(url_for("test_files", Vec::<(&str, &str)>::new()).is_err());
+        assert!(url_for("test_missing", Vec::<(&str, &str)>::new()).is_err());
+
+        let app = Url::<HttpReqCtx>::root();
+        app.mount("/admin", &root).unwrap();
+        assert_eq!(url_for("test_user_detail", [("id", 1)]).unwrap(), "/admin/user/1");
+    }
+
+    #[test]
+    fn routes_list_handlers() {
+        let root = Url::<HttpReqCtx>::root();
+        let user = root.reg_from(&[PathPattern::literal_path("user"), PathPattern::argument("id")]);
+        user.set_method(Arc::new(|ctx: HttpReqCtx| async move { ctx }));
+        user.set_name("test_routes_user");
+        root.reg_from(&[PathPattern::literal_path("empty")]);
+
+        let routes = root.routes();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].pattern, "/user/{id}");
+        assert_eq!(routes[0].name.as_deref(), Some("test_routes_user"));
+        assert!(routes[0].middlewares.is_empty());
+    }
+
+    #[test]
+    fn skipped_middlewares_do_not_run() {
+        let root = Url::<HttpReqCtx>::root();
+        let hooks = root.reg_from(&[PathPattern::literal_path("hooks")]);
+        hooks.set_method(Arc::new(|ctx: HttpReqCtx| async move { ctx }));
+        hooks.set_middlewares(vec![Arc::new(LoggingMiddleware) as Arc<dyn AsyncMiddleware<HttpReqCtx>>]);
+        assert_eq!(hooks.active_middlewares().len(), 1);
+
+        hooks.set_params(SkipMiddleware::<LoggingMiddleware>::new());
+        assert!(hooks.active_middlewares().is_empty());
+        assert!(root.routes()[0].middlewares.is_empty());
+        assert_eq!(hooks.middlewares.read().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+//! A `SecretsProvider` reading the version 2 KV secrets engine of HashiCorp Vault.
+//!
+//! The secret `database/prod#password` is the field `password` of the Vault secret at
+//! `database/prod`. Without `#`, the field is `value`.
+//!
+//! # Example
+//! ```rust,ignore
+//! let vault = VaultSecrets::new("https://vault.internal:8200", std::env::var("VAULT_TOKEN")?).mount("apps");
+//! let key = vault.require("billing/jwt#private_key").await?;
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use akari::Value;
+
+use crate::app::middleware::BoxFuture;
+use crate::app::secrets::{Secret, SecretError, SecretsProvider, json_body, member};
+use crate::http::body::HttpBody;
+use crate::http::context::HttpResCtx;
+use crate::http::http_value::{HttpMethod, HttpVersion};
+use crate::http::meta::HttpMeta;
+use crate::http::request::HttpRequest;
+use crate::http::safety::HttpSafety;
+use crate::http::start_line::HttpStartLine;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reads secrets from a Vault server with a token
+#[derive(Clone)]
+pub struct VaultSecrets {
+    /// The scheme and host of the server, e.g. `https://vault.internal:8200`
+    address: String,
+    token: Secret,
+    mount: String,
+    namespace: Option<String>,
+    timeout: Duration,
+    safety: HttpSafety,
+}
+
+impl VaultSecrets {
+    pub fn new(address: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            address: address.into().trim_end_matches('/').to_string(),
+            token: Secret::new(token),
+            mount: "secret".to_string(),
+            namespace: None,
+            timeout: DEFAULT_TIMEOUT,
+            safety: HttpSafety::new(),
+        }
+    }
+
+    /// Sets the path where the KV engine is mounted, `secret` by default
+    pub fn mount(mut self, mount: impl Into<String>) -> Self {
+        self.mount = mount.into().trim_matches('/').to_string();
+        self
+    }
+
+    /// Sets the Vault Enterprise namespace of the secrets
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Sets how long reading a secret may take, 10 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The API path of a secret and the field holding its value
+    fn locate<'a>(&self, name: &'a str) -> Result<(String, &'a str), SecretError> {
+        let (path, field) = name.split_once('#').unwrap_or((name, "value"));
+        let path = path.trim_matches('/');
+        if path.is_empty() || field.is_empty() || path.split('/').any(|segment| segment == "..") {
+            return Err(SecretError::Invalid(format!("{} is not a Vault secret", name)));
+        }
+        Ok((format!("/v1/{}/data/{}", self.mount, path), field))
+    }
+
+    fn request(&self, path: String) -> HttpRequest {
+        let mut meta = HttpMeta::new(HttpStartLine::new_request(HttpVersion::Http11, HttpMethod::GET, path), HashMap::new());
+        meta.set_attribute("x-vault-token", self.token.expose().to_string());
+        if let Some(namespace) = &self.namespace {
+            meta.set_attribute("x-vault-namespace", namespace.clone());
+        }
+        HttpRequest::new(meta, HttpBody::Empty)
+    }
+}
+
+impl SecretsProvider for VaultSecrets {
+    fn get(&self, name: &str) -> BoxFuture<Result<Option<Secret>, SecretError>> {
+        let vault = self.clone();
+        let located = self.locate(name).map(|(path, field)| (path, field.to_string()));
+        Box::pin(async move {
+            let (path, field) = located?;
+            let sent = HttpResCtx::send_request(vault.address.clone(), vault.request(path), vault.safety.clone());
+            let response = match tokio::time::timeout(vault.timeout, sent).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(e)) => return Err(SecretError::Unavailable(format!("Vault: {}", e))),
+                Err(_) => return Err(SecretError::Unavailable("Vault timed out".to_string())),
+            };
+            match response.meta.start_line.status_code().as_u16() {
+                404 => return Ok(None),
+                status if status >= 300 => {
+                    return Err(SecretError::Unavailable(format!("Vault answered with status {}", status)));
+                }
+                _ => {}
+            }
+            let body = json_body(&response.body).ok_or_else(|| SecretError::Unavailable("Vault answered without JSON".to_string()))?;
+            Ok(secret_field(&body, &field))
+        })
+    }
+}
+
+/// The field of the secret in a response of the KV engine, `{"data": {"data": {...}}}`
+fn secret_field(body: &Value, field: &str) -> Option<Secret> {
+    let data = member(member(body, "data")?, "data")?;
+    match member(data, field)? {
+        Value::Str(value) => Some(Secret::new(value.clone())),
+        other => Some(Secret::new(other.into_json())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_secrets() {
+        let vault = VaultSecrets::new("https://vault.internal:8200/", "token").mount("/apps/");
+        assert_eq!(vault.address, "https://vault.internal:8200");
+        let (path, field) = vault.locate("billing/jwt#private_key").unwrap();
+        assert_eq!((path.as_str(), field), ("/v1/apps/data/billing/jwt", "private_key"));
+        let (path, field) = vault.locate("db_password").unwrap();
+        assert_eq!((path.as_str(), field), ("/v1/apps/data/db_password", "value"));
+        assert!(vault.locate("../sys/seal").is_err());
+        assert!(vault.locate("billing#").is_err());
+    }
+
+    #[test]
+    fn reads_the_field() {
+        let body = Value::from_json(r#"{"data": {"data": {"password": "hunter2", "port": 5432}, "metadata": {}}}"#).unwrap();
+        assert_eq!(secret_field(&body, "password").unwrap().expose(), "hunter2");
+        assert_eq!(secret_field(&body, "port").unwrap().expose(), "5432");
+        assert!(secret_field(&body, "user").is_none());
+    }
+}
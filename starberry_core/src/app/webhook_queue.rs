@@ -0,0 +1,635 @@
+//! Delivery of webhooks to other services, at least once.
+//!
+//! Events are enqueued into a `WebhookStore`, kept in memory or in a directory so that they
+//! survive a restart, and a worker running as a background task of the application sends
+//! them. Each delivery is signed following Standard Webhooks (`webhook-id`, `webhook-timestamp`
+//! and `webhook-signature`), which `WebhookVerifier` checks on the receiving side. A failed
+//! delivery is retried with an exponential backoff; once it ran out of attempts, or if the
+//! receiver answered `410 Gone`, it is moved to the dead letters, where it can be inspected
+//! and redelivered.
+//!
+//! A webhook stays in the store until the receiver acknowledged it with a 2xx status, so a
+//! crash while sending leads to a second delivery rather than a lost one. Receivers should
+//! use the `webhook-id` header to ignore duplicates.
+//!
+//! # Example
+//! ```rust,ignore
+//! let queue = WebhookQueue::new(DirWebhookStore::new("data/webhooks"))
+//!     .secret(std::env::var("WEBHOOK_SECRET")?)
+//!     .on_dead_letter(|webhook| eprintln!("Giving up on {} to {}", webhook.event, webhook.url));
+//! queue.start(APP.tasks());
+//! APP.manage(queue);
+//!
+//! // In a handler
+//! let queue = req.state::<WebhookQueue>().unwrap();
+//! queue.enqueue("https://example.com/hooks", "order.paid", object!({ id: order.id })).await?;
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use akari::Value;
+use starberry_lib::date::{format_rfc3339, parse_rfc3339};
+use starberry_lib::encoding::base64_encode;
+use starberry_lib::ende::signing::sign_hmac_sha256;
+use starberry_lib::uuid::uuid_v7;
+use tokio::sync::Notify;
+
+use crate::app::middleware::BoxFuture;
+use crate::app::task::TaskTracker;
+use crate::http::body::HttpBody;
+use crate::http::context::HttpResCtx;
+use crate::http::http_value::{HttpContentType, HttpMethod, HttpVersion};
+use crate::http::meta::HttpMeta;
+use crate::http::request::HttpRequest;
+use crate::http::safety::HttpSafety;
+use crate::http::start_line::HttpStartLine;
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_BATCH: usize = 32;
+
+/// An event waiting to be delivered
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingWebhook {
+    /// Unique id, sent as `webhook-id`
+    pub id: String,
+    /// The full url of the receiver, e.g. `https://example.com/hooks`
+    pub url: String,
+    /// The type of the event, sent as `webhook-event`
+    pub event: String,
+    /// The JSON body
+    pub payload: String,
+    /// The number of delivery attempts so far
+    pub attempts: u32,
+    pub created_at: SystemTime,
+    /// When the next attempt may be made
+    pub next_attempt: SystemTime,
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+}
+
+impl OutgoingWebhook {
+    pub fn new<U: Into<String>, E: Into<String>>(url: U, event: E, payload: &Value) -> Self {
+        let now = SystemTime::now();
+        Self {
+            id: uuid_v7(),
+            url: url.into(),
+            event: event.into(),
+            payload: payload.into_json(),
+            attempts: 0,
+            created_at: now,
+            next_attempt: now,
+            last_error: None,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let mut info = HashMap::new();
+        info.insert("id".to_string(), Value::Str(self.id.clone()));
+        info.insert("url".to_string(), Value::Str(self.url.clone()));
+        info.insert("event".to_string(), Value::Str(self.event.clone()));
+        info.insert("payload".to_string(), Value::Str(self.payload.clone()));
+        info.insert("attempts".to_string(), Value::Str(self.attempts.to_string()));
+        info.insert("created_at".to_string(), Value::Str(format_rfc3339(self.created_at)));
+        info.insert("next_attempt".to_string(), Value::Str(format_rfc3339(self.next_attempt)));
+        if let Some(error) = &self.last_error {
+            info.insert("last_error".to_string(), Value::Str(error.clone()));
+        }
+        Value::Dict(info).into_json()
+    }
+
+    fn from_json(json: &str) -> Option<Self> {
+        let Ok(Value::Dict(info)) = Value::from_json(json) else {
+            return None;
+        };
+        let field = |key: &str| match info.get(key) {
+            Some(Value::Str(value)) => Some(value.clone()),
+            _ => None,
+        };
+        Some(Self {
+            id: field("id")?,
+            url: field("url")?,
+            event: field("event")?,
+            payload: field("payload")?,
+            attempts: field("attempts")?.parse().ok()?,
+            created_at: parse_rfc3339(&field("created_at")?)?,
+            next_attempt: parse_rfc3339(&field("next_attempt")?)?,
+            last_error: field("last_error"),
+        })
+    }
+}
+
+/// Where the webhooks waiting to be delivered and the dead letters are kept
+pub trait WebhookStore: Send + Sync + 'static {
+    /// Saves a webhook waiting to be delivered, replacing the one with the same id
+    fn save(&self, webhook: OutgoingWebhook) -> BoxFuture<io::Result<()>>;
+    /// The webhooks whose next attempt is due, the earliest first
+    fn due(&self, now: SystemTime, limit: usize) -> BoxFuture<io::Result<Vec<OutgoingWebhook>>>;
+    /// Forgets a delivered webhook
+    fn remove(&self, id: String) -> BoxFuture<io::Result<()>>;
+    /// Moves a webhook to the dead letters
+    fn bury(&self, webhook: OutgoingWebhook) -> BoxFuture<io::Result<()>>;
+    fn dead_letters(&self) -> BoxFuture<io::Result<Vec<OutgoingWebhook>>>;
+    /// Removes a webhook from the dead letters and returns it
+    fn take_dead(&self, id: String) -> BoxFuture<io::Result<Option<OutgoingWebhook>>>;
+}
+
+/// Keeps the webhooks in memory, they are lost when the application stops
+#[derive(Debug, Clone, Default)]
+pub struct MemoryWebhookStore {
+    pending: Arc<Mutex<HashMap<String, OutgoingWebhook>>>,
+    dead: Arc<Mutex<HashMap<String, OutgoingWebhook>>>,
+}
+
+impl MemoryWebhookStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl WebhookStore for MemoryWebhookStore {
+    fn save(&self, webhook: OutgoingWebhook) -> BoxFuture<io::Result<()>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).insert(webhook.id.clone(), webhook);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn due(&self, now: SystemTime, limit: usize) -> BoxFuture<io::Result<Vec<OutgoingWebhook>>> {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let due = take_due(pending.values().cloned().collect(), now, limit);
+        Box::pin(async move { Ok(due) })
+    }
+
+    fn remove(&self, id: String) -> BoxFuture<io::Result<()>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn bury(&self, webhook: OutgoingWebhook) -> BoxFuture<io::Result<()>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&webhook.id);
+        self.dead.lock().unwrap_or_else(|e| e.into_inner()).insert(webhook.id.clone(), webhook);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn dead_letters(&self) -> BoxFuture<io::Result<Vec<OutgoingWebhook>>> {
+        let dead = self.dead.lock().unwrap_or_else(|e| e.into_inner()).values().cloned().collect();
+        Box::pin(async move { Ok(dead) })
+    }
+
+    fn take_dead(&self, id: String) -> BoxFuture<io::Result<Option<OutgoingWebhook>>> {
+        let webhook = self.dead.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+        Box::pin(async move { Ok(webhook) })
+    }
+}
+
+/// Keeps each webhook in a JSON file, under `pending/` while it waits to be delivered
+/// and under `dead/` once it is a dead letter
+#[derive(Debug, Clone)]
+pub struct DirWebhookStore {
+    dir: PathBuf,
+}
+
+impl DirWebhookStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, folder: &str, id: &str) -> io::Result<PathBuf> {
+        // The id ends up in a file name, do not let it leave the directory
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid webhook id"));
+        }
+        Ok(self.dir.join(folder).join(format!("{}.json", id)))
+    }
+
+    async fn write(&self, folder: &str, webhook: &OutgoingWebhook) -> io::Result<()> {
+        let path = self.path(folder, &webhook.id)?;
+        tokio::fs::create_dir_all(self.dir.join(folder)).await?;
+        // Write then rename, so a crash never leaves a truncated file behind
+        let temp = path.with_extension("json.tmp");
+        tokio::fs::write(&temp, webhook.to_json()).await?;
+        tokio::fs::rename(&temp, &path).await
+    }
+
+    async fn read(&self, folder: &str, id: &str) -> io::Result<Option<OutgoingWebhook>> {
+        match tokio::fs::read_to_string(self.path(folder, id)?).await {
+            Ok(json) => OutgoingWebhook::from_json(&json)
+                .map(Some)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid webhook file")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(&self, folder: &str, id: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(folder, id)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    async fn read_all(&self, folder: &str) -> io::Result<Vec<OutgoingWebhook>> {
+        let mut entries = match tokio::fs::read_dir(self.dir.join(folder)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut webhooks = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            // A file which cannot be read is skipped rather than blocking the whole queue
+            let Ok(json) = tokio::fs::read_to_string(&path).await else { continue };
+            match OutgoingWebhook::from_json(&json) {
+                Some(webhook) => webhooks.push(webhook),
+                None => eprintln!("Skipping invalid webhook file {}", path.display()),
+            }
+        }
+        Ok(webhooks)
+    }
+}
+
+impl WebhookStore for DirWebhookStore {
+    fn save(&self, webhook: OutgoingWebhook) -> BoxFuture<io::Result<()>> {
+        let store = self.clone();
+        Box::pin(async move { store.write("pending", &webhook).await })
+    }
+
+    fn due(&self, now: SystemTime, limit: usize) -> BoxFuture<io::Result<Vec<OutgoingWebhook>>> {
+        let store = self.clone();
+        Box::pin(async move { Ok(take_due(store.read_all("pending").await?, now, limit)) })
+    }
+
+    fn remove(&self, id: String) -> BoxFuture<io::Result<()>> {
+        let store = self.clone();
+        Box::pin(async move { store.delete("pending", &id).await })
+    }
+
+    fn bury(&self, webhook: OutgoingWebhook) -> BoxFuture<io::Result<()>> {
+        let store = self.clone();
+        Box::pin(async move {
+            store.write("dead", &webhook).await?;
+            store.delete("pending", &webhook.id).await
+        })
+    }
+
+    fn dead_letters(&self) -> BoxFuture<io::Result<Vec<OutgoingWebhook>>> {
+        let store = self.clone();
+        Box::pin(async move { store.read_all("dead").await })
+    }
+
+    fn take_dead(&self, id: String) -> BoxFuture<io::Result<Option<OutgoingWebhook>>> {
+        let store = self.clone();
+        Box::pin(async move {
+            let webhook = store.read("dead", &id).await?;
+            store.delete("dead", &id).await?;
+            Ok(webhook)
+        })
+    }
+}
+
+fn take_due(mut webhooks: Vec<OutgoingWebhook>, now: SystemTime, limit: usize) -> Vec<OutgoingWebhook> {
+    webhooks.retain(|webhook| webhook.next_attempt <= now);
+    webhooks.sort_by_key(|webhook| webhook.next_attempt);
+    webhooks.truncate(limit);
+    webhooks
+}
+
+/// How failed deliveries are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of attempts before a webhook becomes a dead letter
+    pub max_attempts: u32,
+    /// The delay after the first failure, doubled after each following one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// 8 attempts, waiting 10 seconds after the first failure and at most an hour
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 8,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(3600),
+        }
+    }
+
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// The delay before the next attempt, after the given number of failed ones
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How one delivery attempt ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    Delivered,
+    /// The receiver does not want this webhook anymore, do not retry
+    Gone,
+    Failed(String),
+}
+
+type DeadLetterHook = Arc<dyn Fn(&OutgoingWebhook) + Send + Sync>;
+
+/// Sends the webhooks of a store, see the module documentation
+#[derive(Clone)]
+pub struct WebhookQueue {
+    store: Arc<dyn WebhookStore>,
+    secret: Option<Vec<u8>>,
+    retry: RetryPolicy,
+    poll_interval: Duration,
+    timeout: Duration,
+    batch: usize,
+    safety: HttpSafety,
+    on_dead_letter: Option<DeadLetterHook>,
+    wake: Arc<Notify>,
+}
+
+impl WebhookQueue {
+    pub fn new<S: WebhookStore>(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+            secret: None,
+            retry: RetryPolicy::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            batch: DEFAULT_BATCH,
+            safety: HttpSafety::new(),
+            on_dead_letter: None,
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Signs the deliveries with this secret. Without one they are sent unsigned
+    pub fn secret<S: AsRef<[u8]>>(mut self, secret: S) -> Self {
+        self.secret = Some(secret.as_ref().to_vec());
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Sets how often the store is checked for due webhooks, 1 second by default.
+    /// Enqueued webhooks are sent at once regardless
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets how long the receiver has to answer, 10 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the safety settings used to read the responses of the receivers
+    pub fn safety(mut self, safety: HttpSafety) -> Self {
+        self.safety = safety;
+        self
+    }
+
+    /// Registers a hook called with each webhook moved to the dead letters
+    pub fn on_dead_letter<F: Fn(&OutgoingWebhook) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.on_dead_letter = Some(Arc::new(hook));
+        self
+    }
+
+    /// Saves an event to be delivered to the url and wakes the worker. Returns the id of the webhook
+    pub async fn enqueue<U: Into<String>, E: Into<String>>(&self, url: U, event: E, payload: Value) -> io::Result<String> {
+        let webhook = OutgoingWebhook::new(url, event, &payload);
+        let id = webhook.id.clone();
+        self.store.save(webhook).await?;
+        self.wake.notify_one();
+        Ok(id)
+    }
+
+    /// The webhooks which could not be delivered
+    pub async fn dead_letters(&self) -> io::Result<Vec<OutgoingWebhook>> {
+        self.store.dead_letters().await
+    }
+
+    /// Moves a dead letter back to the queue with a fresh set of attempts. Returns `false` if there is no such dead letter
+    pub async fn redeliver(&self, id: &str) -> io::Result<bool> {
+        let Some(mut webhook) = self.store.take_dead(id.to_string()).await? else {
+            return Ok(false);
+        };
+        webhook.attempts = 0;
+        webhook.next_attempt = SystemTime::now();
+        webhook.last_error = None;
+        self.store.save(webhook).await?;
+        self.wake.notify_one();
+        Ok(true)
+    }
+
+    /// Runs the worker as a background task until the tracker shuts down
+    pub fn start(&self, tasks: &TaskTracker) {
+        let queue = self.clone();
+        let shutdown = tasks.shutdown_token();
+        tasks.spawn(async move {
+            loop {
+                let sent = match queue.run_once().await {
+                    Ok(sent) => sent,
+                    Err(e) => {
+                        eprintln!("Webhook queue: {}", e);
+                        0
+                    }
+                };
+                // A full batch means more webhooks may be due already
+                if sent >= queue.batch {
+                    continue;
+                }
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = queue.wake.notified() => {}
+                    _ = tokio::time::sleep(queue.poll_interval) => {}
+                }
+            }
+        });
+    }
+
+    /// Makes one attempt for each due webhook and returns how many were attempted
+    pub async fn run_once(&self) -> io::Result<usize> {
+        let due = self.store.due(SystemTime::now(), self.batch).await?;
+        let count = due.len();
+        for webhook in due {
+            self.attempt(webhook).await?;
+        }
+        Ok(count)
+    }
+
+    async fn attempt(&self, mut webhook: OutgoingWebhook) -> io::Result<()> {
+        let now = SystemTime::now();
+        webhook.attempts += 1;
+        // Saved as failed before sending, so a crash during the attempt still counts it
+        webhook.next_attempt = now + self.retry.delay(webhook.attempts);
+        self.store.save(webhook.clone()).await?;
+
+        match self.send(&webhook, now).await {
+            Outcome::Delivered => self.store.remove(webhook.id).await,
+            Outcome::Failed(error) if webhook.attempts < self.retry.max_attempts => {
+                webhook.last_error = Some(error);
+                self.store.save(webhook).await
+            }
+            outcome => {
+                webhook.last_error = match outcome {
+                    Outcome::Failed(error) => Some(error),
+                    _ => Some("410 Gone".to_string()),
+                };
+                if let Some(hook) = &self.on_dead_letter {
+                    hook(&webhook);
+                }
+                self.store.bury(webhook).await
+            }
+        }
+    }
+
+    async fn send(&self, webhook: &OutgoingWebhook, now: SystemTime) -> Outcome {
+        let Some((host, request)) = self.request(webhook, now) else {
+            return Outcome::Failed(format!("Invalid url {}", webhook.url));
+        };
+        let sent = HttpResCtx::send_request(host, request, self.safety.clone());
+        match tokio::time::timeout(self.timeout, sent).await {
+            Ok(Ok(response)) => match response.meta.start_line.status_code().as_u16() {
+                200..=299 => Outcome::Delivered,
+                410 => Outcome::Gone,
+                status => Outcome::Failed(format!("Status {}", status)),
+            },
+            Ok(Err(e)) => Outcome::Failed(e.to_string()),
+            Err(_) => Outcome::Failed("Timed out".to_string()),
+        }
+    }
+
+    /// The host and the signed request delivering the webhook, `None` if the url is invalid
+    fn request(&self, webhook: &OutgoingWebhook, now: SystemTime) -> Option<(String, HttpRequest)> {
+        let (host, path) = split_url(&webhook.url)?;
+        let mut meta = HttpMeta::new(
+            HttpStartLine::new_request(HttpVersion::Http11, HttpMethod::POST, path),
+            HashMap::new(),
+        );
+        meta.set_content_type(HttpContentType::ApplicationJson());
+        let timestamp = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().to_string();
+        meta.set_attribute("webhook-id", webhook.id.clone());
+        meta.set_attribute("webhook-timestamp", timestamp.clone());
+        meta.set_attribute("webhook-event", webhook.event.clone());
+        if let Some(secret) = &self.secret {
+            let signed = format!("{}.{}.{}", webhook.id, timestamp, webhook.payload);
+            let signature = base64_encode(sign_hmac_sha256(secret, signed.as_bytes()));
+            meta.set_attribute("webhook-signature", format!("v1,{}", signature));
+        }
+        let body = HttpBody::Binary(webhook.payload.as_bytes().to_vec());
+        Some((host, HttpRequest::new(meta, body)))
+    }
+}
+
+impl fmt::Debug for WebhookQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookQueue")
+            .field("retry", &self.retry)
+            .field("poll_interval", &self.poll_interval)
+            .field("timeout", &self.timeout)
+            .field("signed", &self.secret.is_some())
+            .finish()
+    }
+}
+
+/// Splits `https://example.com/hooks` into `https://example.com` and `/hooks`
+pub(crate) fn split_url(url: &str) -> Option<(String, String)> {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"))?;
+    let scheme_len = url.len() - rest.len();
+    let host_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    if host_end == 0 {
+        return None;
+    }
+    let path = &rest[host_end..];
+    let path = if path.starts_with('/') { path.to_string() } else { format!("/{}", path) };
+    Some((url[..scheme_len + host_end].to_string(), path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::webhook::{SignatureScheme, WebhookVerifier};
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy::new().base_delay(Duration::from_secs(10)).max_delay(Duration::from_secs(60));
+        assert_eq!(policy.delay(1), Duration::from_secs(10));
+        assert_eq!(policy.delay(3), Duration::from_secs(40));
+        assert_eq!(policy.delay(4), Duration::from_secs(60));
+        assert_eq!(policy.delay(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn splits_urls() {
+        assert_eq!(
+            split_url("https://example.com:8443/hooks?v=1"),
+            Some(("https://example.com:8443".to_string(), "/hooks?v=1".to_string()))
+        );
+        assert_eq!(split_url("http://example.com"), Some(("http://example.com".to_string(), "/".to_string())));
+        assert_eq!(split_url("example.com/hooks"), None);
+    }
+
+    #[test]
+    fn signs_deliveries() {
+        let queue = WebhookQueue::new(MemoryWebhookStore::new()).secret("secret");
+        let webhook = OutgoingWebhook::new("https://example.com/hooks", "order.paid", &Value::from_json("{\"id\":1}").unwrap());
+        let (host, request) = queue.request(&webhook, SystemTime::now()).unwrap();
+        assert_eq!(host, "https://example.com");
+        let verifier = WebhookVerifier::new(SignatureScheme::StandardWebhooks, "secret");
+        assert_eq!(verifier.verify(&request.meta, webhook.payload.as_bytes()), Ok(()));
+        assert_eq!(OutgoingWebhook::from_json(&webhook.to_json()).map(|w| w.payload), Some(webhook.payload));
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_become_dead_letters() {
+        let store = MemoryWebhookStore::new();
+        let queue = WebhookQueue::new(store.clone()).retry(RetryPolicy::new().max_attempts(2).base_delay(Duration::ZERO));
+        let id = queue.enqueue("not a url", "ping", Value::new("")).await.unwrap();
+
+        assert_eq!(queue.run_once().await.unwrap(), 1);
+        let pending = store.due(SystemTime::now(), 10).await.unwrap();
+        assert_eq!(pending[0].attempts, 1);
+        assert!(pending[0].last_error.is_some());
+
+        assert_eq!(queue.run_once().await.unwrap(), 1);
+        assert_eq!(queue.run_once().await.unwrap(), 0);
+        let dead = queue.dead_letters().await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].id, id);
+
+        assert!(queue.redeliver(&id).await.unwrap());
+        assert!(queue.dead_letters().await.unwrap().is_empty());
+        assert_eq!(store.due(SystemTime::now(), 10).await.unwrap()[0].attempts, 0);
+    }
+}
@@ -0,0 +1,321 @@
+//! `/robots.txt`, `/.well-known/security.txt` and other documents under `/.well-known/`
+//! (RFC 8615).
+//!
+//! A `WellKnown` collects the documents. Given to `AppBuilder::well_known`, it is kept in
+//! the config of the application and its routes are registered when the application is
+//! built, so no handler has to be written for them.
+//!
+//! # Example
+//! ```rust,ignore
+//! pub static APP: SApp = Lazy::new(|| {
+//!     App::new()
+//!         .well_known(
+//!             WellKnown::new()
+//!                 .robots(RobotsTxt::new().user_agent("*").disallow("/admin/").sitemap("https://example.com/sitemap.xml"))
+//!                 .security_txt(SecurityTxt::new("mailto:security@example.com", expires))
+//!                 .json("openid-configuration", openid_configuration()),
+//!         )
+//!         .build()
+//! });
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use akari::Value;
+use starberry_lib::date::format_rfc3339;
+
+use super::application::App;
+use super::urls::PathPattern;
+use crate::http::context::HttpReqCtx;
+use crate::http::http_value::{HttpContentType, StatusCode};
+use crate::http::response::HttpResponse;
+use crate::http::response::response_templates::normal_response;
+
+/// The path of the robots exclusion file (RFC 9309)
+pub const ROBOTS_PATH: &str = "/robots.txt";
+
+/// The prefix of the well-known documents
+pub const WELL_KNOWN_PREFIX: &str = "/.well-known/";
+
+/// The rules of `/robots.txt`, grouped by user agent
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobotsTxt {
+    groups: Vec<RobotsGroup>,
+    sitemaps: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct RobotsGroup {
+    user_agents: Vec<String>,
+    rules: Vec<(&'static str, String)>,
+}
+
+impl RobotsTxt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every crawler may visit every page
+    pub fn allow_all() -> Self {
+        Self::new().user_agent("*").allow("/")
+    }
+
+    /// No crawler may visit any page
+    pub fn disallow_all() -> Self {
+        Self::new().user_agent("*").disallow("/")
+    }
+
+    /// Starts the rules for a user agent. Consecutive user agents share the rules that follow
+    pub fn user_agent<T: Into<String>>(mut self, user_agent: T) -> Self {
+        match self.groups.last_mut() {
+            Some(group) if group.rules.is_empty() => group.user_agents.push(user_agent.into()),
+            _ => self.groups.push(RobotsGroup { user_agents: vec![user_agent.into()], rules: Vec::new() }),
+        }
+        self
+    }
+
+    pub fn allow<T: Into<String>>(self, path: T) -> Self {
+        self.rule("Allow", path.into())
+    }
+
+    pub fn disallow<T: Into<String>>(self, path: T) -> Self {
+        self.rule("Disallow", path.into())
+    }
+
+    /// Asks the crawlers of the current user agent to wait between requests, not part of
+    /// RFC 9309 but honoured by several crawlers
+    pub fn crawl_delay(self, seconds: u64) -> Self {
+        self.rule("Crawl-delay", seconds.to_string())
+    }
+
+    /// Points the crawlers at a sitemap, given as an absolute url
+    pub fn sitemap<T: Into<String>>(mut self, url: T) -> Self {
+        self.sitemaps.push(url.into());
+        self
+    }
+
+    fn rule(mut self, field: &'static str, value: String) -> Self {
+        if self.groups.is_empty() {
+            self = self.user_agent("*");
+        }
+        if let Some(group) = self.groups.last_mut() {
+            group.rules.push((field, value));
+        }
+        self
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for group in &self.groups {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            for user_agent in &group.user_agents {
+                out.push_str(&format!("User-agent: {}\n", user_agent));
+            }
+            for (field, value) in &group.rules {
+                out.push_str(&format!("{}: {}\n", field, value));
+            }
+        }
+        if !self.sitemaps.is_empty() && !out.is_empty() {
+            out.push('\n');
+        }
+        for sitemap in &self.sitemaps {
+            out.push_str(&format!("Sitemap: {}\n", sitemap));
+        }
+        out
+    }
+}
+
+/// The security contacts of the site, served at `/.well-known/security.txt` (RFC 9116)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityTxt {
+    contacts: Vec<String>,
+    expires: SystemTime,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl SecurityTxt {
+    /// A contact, e.g. `mailto:security@example.com` or an https url, and the time after
+    /// which the file should no longer be trusted, less than a year away
+    pub fn new<T: Into<String>>(contact: T, expires: SystemTime) -> Self {
+        Self { contacts: vec![contact.into()], expires, fields: Vec::new() }
+    }
+
+    pub fn contact<T: Into<String>>(mut self, contact: T) -> Self {
+        self.contacts.push(contact.into());
+        self
+    }
+
+    /// The url of a key to encrypt the reports with
+    pub fn encryption<T: Into<String>>(self, url: T) -> Self {
+        self.field("Encryption", url.into())
+    }
+
+    pub fn acknowledgments<T: Into<String>>(self, url: T) -> Self {
+        self.field("Acknowledgments", url.into())
+    }
+
+    /// The languages the reports may be written in, e.g. `en, fr`
+    pub fn preferred_languages<T: Into<String>>(self, languages: T) -> Self {
+        self.field("Preferred-Languages", languages.into())
+    }
+
+    /// The url the file is served at
+    pub fn canonical<T: Into<String>>(self, url: T) -> Self {
+        self.field("Canonical", url.into())
+    }
+
+    pub fn policy<T: Into<String>>(self, url: T) -> Self {
+        self.field("Policy", url.into())
+    }
+
+    pub fn hiring<T: Into<String>>(self, url: T) -> Self {
+        self.field("Hiring", url.into())
+    }
+
+    fn field(mut self, field: &'static str, value: String) -> Self {
+        self.fields.push((field, value));
+        self
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for contact in &self.contacts {
+            out.push_str(&format!("Contact: {}\n", contact));
+        }
+        out.push_str(&format!("Expires: {}\n", format_rfc3339(self.expires)));
+        for (field, value) in &self.fields {
+            out.push_str(&format!("{}: {}\n", field, value));
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Document {
+    content_type: HttpContentType,
+    body: Vec<u8>,
+}
+
+/// The documents of `/robots.txt` and `/.well-known/`
+#[derive(Debug, Clone, Default)]
+pub struct WellKnown {
+    robots: Option<RobotsTxt>,
+    documents: HashMap<String, Document>,
+}
+
+impl WellKnown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn robots(mut self, robots: RobotsTxt) -> Self {
+        self.robots = Some(robots);
+        self
+    }
+
+    pub fn security_txt(self, security: SecurityTxt) -> Self {
+        self.document("security.txt", HttpContentType::TextPlain(), security.to_text())
+    }
+
+    /// Serves `body` at `/.well-known/<name>`. A document of the same name is replaced
+    pub fn document<T: Into<String>, B: Into<Vec<u8>>>(mut self, name: T, content_type: HttpContentType, body: B) -> Self {
+        let name = name.into().trim_matches('/').to_string();
+        self.documents.insert(name, Document { content_type, body: body.into() });
+        self
+    }
+
+    /// Serves a JSON document at `/.well-known/<name>`, e.g. `openid-configuration`
+    pub fn json<T: Into<String>>(self, name: T, value: Value) -> Self {
+        self.document(name, HttpContentType::ApplicationJson(), value.into_json())
+    }
+
+    /// The names of the documents under `/.well-known/`, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.documents.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// The response for a request path, `None` if no document is served there
+    pub fn respond(&self, path: &str) -> Option<HttpResponse> {
+        if path == ROBOTS_PATH {
+            let robots = self.robots.as_ref()?;
+            return Some(normal_response(StatusCode::OK, robots.to_text()).content_type(HttpContentType::TextPlain()));
+        }
+        let document = self.documents.get(path.strip_prefix(WELL_KNOWN_PREFIX)?)?;
+        Some(normal_response(StatusCode::OK, document.body.clone()).content_type(document.content_type.clone()))
+    }
+
+    /// Registers a route for `/robots.txt` and for each document
+    pub fn register(self, app: &Arc<App>) {
+        let mut paths = Vec::new();
+        if self.robots.is_some() {
+            paths.push(ROBOTS_PATH.to_string());
+        }
+        paths.extend(self.names().into_iter().map(|name| format!("{}{}", WELL_KNOWN_PREFIX, name)));
+
+        let well_known = Arc::new(self);
+        for path in paths {
+            let segments: Vec<PathPattern> = path.split('/').filter(|s| !s.is_empty()).map(PathPattern::literal_path).collect();
+            let well_known = well_known.clone();
+            app.reg_from::<HttpReqCtx>(&segments).set_method(Arc::new(move |mut req: HttpReqCtx| {
+                let well_known = well_known.clone();
+                async move {
+                    if let Some(response) = well_known.respond(&req.path()) {
+                        req.response = response;
+                    }
+                    req
+                }
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn writes_robots_txt() {
+        let robots = RobotsTxt::new()
+            .user_agent("Googlebot")
+            .user_agent("Bingbot")
+            .disallow("/admin/")
+            .user_agent("*")
+            .allow("/")
+            .crawl_delay(5)
+            .sitemap("https://example.com/sitemap.xml");
+        assert_eq!(
+            robots.to_text(),
+            "User-agent: Googlebot\nUser-agent: Bingbot\nDisallow: /admin/\n\n\
+             User-agent: *\nAllow: /\nCrawl-delay: 5\n\nSitemap: https://example.com/sitemap.xml\n"
+        );
+        assert_eq!(RobotsTxt::new().disallow("/").to_text(), RobotsTxt::disallow_all().to_text());
+    }
+
+    #[test]
+    fn serves_documents() {
+        let expires = UNIX_EPOCH + Duration::from_secs(784111777);
+        let well_known = WellKnown::new()
+            .robots(RobotsTxt::allow_all())
+            .security_txt(SecurityTxt::new("mailto:security@example.com", expires).preferred_languages("en"))
+            .document("/change-password", HttpContentType::TextPlain(), "/account/password");
+        assert_eq!(well_known.names(), vec!["change-password", "security.txt"]);
+
+        let mut security = well_known.respond("/.well-known/security.txt").unwrap();
+        assert_eq!(security.meta.get_content_type().unwrap().to_string(), "text/plain");
+        assert!(well_known.respond("/robots.txt").is_some());
+        assert!(well_known.respond("/.well-known/missing").is_none());
+        assert!(well_known.respond("/security.txt").is_none());
+        assert_eq!(
+            SecurityTxt::new("mailto:security@example.com", expires).preferred_languages("en").to_text(),
+            "Contact: mailto:security@example.com\nExpires: 1994-11-06T08:49:37Z\nPreferred-Languages: en\n"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! A key-value cache abstraction with expiring entries.
+//!
+//! `Cache` is implemented by the backends, such as the in-process `MemoryCache`.
+//! Values are raw bytes so that every backend can store them, the string helpers
+//! and `CacheExt::get_or_compute` cover the common cases. A cache is shared with
+//! the rest of the application through `AppBuilder::cache` and `App::cache`, the sessions
+//! of sbmstd and the rate limits of starberry_oauth are then kept in it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::connection::error::ConnectionError;
+
+/// A cache shared between the handlers and middlewares of an application
+pub type SharedCache = Arc<dyn Cache>;
+
+#[derive(Debug)]
+pub enum CacheError {
+    /// The backend could not be reached
+    Connection(ConnectionError),
+    /// The backend refused or failed the operation
+    Backend(String),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connection(err) => write!(f, "Cache connection error: {}", err),
+            Self::Backend(err) => write!(f, "Cache backend error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<ConnectionError> for CacheError {
+    fn from(err: ConnectionError) -> Self {
+        Self::Connection(err)
+    }
+}
+
+/// A key-value store whose entries may expire
+#[async_trait]
+pub trait Cache: Send + Sync {
+    /// Returns the value stored under `key`, `None` if it is missing or expired
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError>;
+
+    /// Stores a value, replacing the previous one. It expires after `ttl` if given
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError>;
+
+    /// Removes a value. Returns `true` if it was present
+    async fn delete(&self, key: &str) -> Result<bool, CacheError>;
+
+    /// Returns the value stored under `key` as an UTF-8 string
+    async fn get_string(&self, key: &str) -> Result<Option<String>, CacheError> {
+        Ok(self
+            .get(key)
+            .await?
+            .map(|value| String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    /// Stores a string value
+    async fn set_string(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), CacheError> {
+        self.set(key, value.as_bytes().to_vec(), ttl).await
+    }
+}
+
+/// Helpers available on every `Cache`, including `dyn Cache`
+#[async_trait]
+pub trait CacheExt: Cache {
+    /// Returns the cached value, or computes it, stores it for `ttl` and returns it.
+    /// The value is computed without caching it if the backend fails
+    async fn get_or_compute<F, Fut>(&self, key: &str, ttl: Option<Duration>, compute: F) -> Vec<u8>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Vec<u8>> + Send,
+    {
+        if let Ok(Some(value)) = self.get(key).await {
+            return value;
+        }
+        let value = compute().await;
+        if let Err(e) = self.set(key, value.clone(), ttl).await {
+            eprintln!("Failed to cache {}: {}", key, e);
+        }
+        value
+    }
+}
+
+impl<C: Cache + ?Sized> CacheExt for C {}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    value: Vec<u8>,
+    expires: Option<Instant>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    entries: HashMap<String, MemoryEntry>,
+    /// Keys ordered from the least to the most recently used
+    usage: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl MemoryState {
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.usage.remove(&entry.last_used);
+            entry.last_used = clock;
+            self.usage.insert(clock, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Option<MemoryEntry> {
+        let entry = self.entries.remove(key)?;
+        self.usage.remove(&entry.last_used);
+        Some(entry)
+    }
+}
+
+/// An in-process cache evicting the least recently used entry once full.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::cache::{Cache, MemoryCache};
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// runtime.block_on(async {
+///     let cache = MemoryCache::new(2);
+///     cache.set_string("a", "1", None).await.unwrap();
+///     cache.set_string("b", "2", None).await.unwrap();
+///     cache.get("a").await.unwrap();
+///     // "b" is the least recently used entry
+///     cache.set_string("c", "3", None).await.unwrap();
+///     assert_eq!(cache.get_string("b").await.unwrap(), None);
+///     assert_eq!(cache.get_string("a").await.unwrap().as_deref(), Some("1"));
+/// });
+/// ```
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryCache {
+    /// Creates a cache holding at most `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::new(MemoryState::default()),
+        }
+    }
+
+    /// Returns the number of entries, expired ones included until they are evicted
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes every expired entry
+    pub fn purge_expired(&self) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let expired: Vec<String> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires.is_some_and(|expires| expires <= now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            state.remove(&key);
+        }
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let expired = match state.entries.get(key) {
+            None => return Ok(None),
+            Some(entry) => entry.expires.is_some_and(|expires| expires <= Instant::now()),
+        };
+        if expired {
+            state.remove(key);
+            return Ok(None);
+        }
+        state.touch(key);
+        Ok(state.entries.get(key).map(|entry| entry.value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.remove(key);
+        while state.entries.len() >= self.capacity {
+            let Some((_, oldest)) = state.usage.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+        state.entries.insert(
+            key.to_string(),
+            MemoryEntry {
+                value,
+                expires: ttl.map(|ttl| Instant::now() + ttl),
+                last_used: 0,
+            },
+        );
+        state.touch(key);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, CacheError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(state.remove(key).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn entries_expire() {
+        let cache = MemoryCache::new(8);
+        cache.set_string("a", "1", Some(Duration::from_millis(10))).await.unwrap();
+        cache.set_string("b", "2", None).await.unwrap();
+        assert_eq!(cache.get_string("a").await.unwrap().as_deref(), Some("1"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(cache.get_string("a").await.unwrap(), None);
+        assert_eq!(cache.get_string("b").await.unwrap().as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn delete_and_replace() {
+        let cache = MemoryCache::new(2);
+        cache.set_string("a", "1", None).await.unwrap();
+        cache.set_string("a", "2", None).await.unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get_string("a").await.unwrap().as_deref(), Some("2"));
+        assert!(cache.delete("a").await.unwrap());
+        assert!(!cache.delete("a").await.unwrap());
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn computes_missing_values_once() {
+        let cache: SharedCache = Arc::new(MemoryCache::new(2));
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        for _ in 0..2 {
+            let value = cache
+                .get_or_compute("k", None, || async {
+                    calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    b"v".to_vec()
+                })
+                .await;
+            assert_eq!(value, b"v");
+        }
+        assert_eq!(calls.into_inner(), 1);
+    }
+}
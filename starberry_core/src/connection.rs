@@ -3,11 +3,16 @@ pub mod receive;
 pub mod transmit; 
 pub mod error; 
 pub mod builder; 
+pub mod info; 
+pub mod cancel; 
 pub mod test; 
 
 pub use self::builder::ConnectionBuilder;  
 pub use self::builder::Protocol; 
+pub use self::builder::tls_handshake; 
 pub use self::connection::Connection; 
+pub use self::info::{ConnectionInfo, TlsInfo}; 
+pub use self::cancel::{CancellationToken, CancelReason}; 
 pub use self::error::Result; 
 
 pub use self::{ 
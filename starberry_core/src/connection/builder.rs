@@ -22,6 +22,7 @@ pub enum Protocol {
     Redis,
     HTTP, 
     WebSocket, 
+    SMTP, 
     Custom,
 } 
 
@@ -34,6 +35,7 @@ impl fmt::Display for Protocol {
             Self::Redis => write!(f, "redis"), // Redis protocol 
             Self::HTTP => write!(f, "http"), // HTTP scheme 
             Self::WebSocket => write!(f, "ws"),  // WebSocket schemes 
+            Self::SMTP => write!(f, "smtp"), // SMTP protocol 
             Self::Custom => write!(f, "custom"),
         }
     }
@@ -103,6 +105,7 @@ impl ConnectionBuilder {
             Protocol::Redis if self.port == 0 => self.port = 6379,
             Protocol::HTTP if self.port == 0 => self.port = if self.use_tls { 443 } else { 80 },
             Protocol::WebSocket if self.port == 0 => self.port = if self.use_tls { 443 } else { 80 }, 
+            Protocol::SMTP if self.port == 0 => self.port = if self.use_tls { 465 } else { 587 }, 
             _ => {}
         }
         self
@@ -182,6 +185,7 @@ impl ConnectionBuilder {
             Protocol::Redis => if self.use_tls { "redis+ssl" } else { "redis" },
             Protocol::HTTP => if self.use_tls { "https" } else { "http" },
             Protocol::WebSocket => if self.use_tls { "wss" } else { "ws" }, 
+            Protocol::SMTP => if self.use_tls { "smtps" } else { "smtp" }, 
             Protocol::Custom => if self.use_tls { "tls" } else { "tcp" },
         };
         
@@ -241,28 +245,36 @@ impl ConnectionBuilder {
             return Ok(Connection::Tcp(tcp));
         }
 
-        // 2) TLS root store
-        let mut root_store = RootCertStore::empty();
-        root_store.extend(TLS_SERVER_ROOTS.iter().cloned()); 
-
-        // 3) Build a client config  (the old `with_safe_defaults()` is gone)
-        let provider = Arc::new(default_provider()); 
-        let config =  ClientConfig::builder_with_provider(provider) 
-            .with_safe_default_protocol_versions()
-            .map_err(|e| ConnectionError::TlsError(e.to_string()))?
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
-
-        // 4) Hand-shake
-        let connector = TlsConnector::from(Arc::new(config));
-        let server_name = ServerName::try_from(self.host.to_owned())
-            .map_err(|_| ConnectionError::HostResolutionFailed(self.host.clone()))?;
-
-        let tls_stream = connector
-            .connect(server_name, tcp)
-            .await
-            .map_err(|e| ConnectionError::TlsError(e.to_string()))?;
-
-        Ok(Connection::Tls(tls_stream))
+        // 2) TLS handshake
+        tls_handshake(&self.host, tcp).await
     }
 } 
+
+/// Performs a TLS handshake over an established TCP stream, verifying the certificate of `host`
+/// against the webpki roots. Used when connecting with TLS and when upgrading a plain
+/// connection in protocols such as SMTP `STARTTLS`.
+pub async fn tls_handshake(host: &str, tcp: TcpStream) -> Result<Connection> {
+    // 1) TLS root store
+    let mut root_store = RootCertStore::empty();
+    root_store.extend(TLS_SERVER_ROOTS.iter().cloned()); 
+
+    // 2) Build a client config  (the old `with_safe_defaults()` is gone)
+    let provider = Arc::new(default_provider()); 
+    let config =  ClientConfig::builder_with_provider(provider) 
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ConnectionError::TlsError(e.to_string()))?
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    // 3) Hand-shake
+    let connector = TlsConnector::from(Arc::new(config));
+    let server_name = ServerName::try_from(host.to_owned())
+        .map_err(|_| ConnectionError::HostResolutionFailed(host.to_string()))?;
+
+    let tls_stream = connector
+        .connect(server_name, tcp)
+        .await
+        .map_err(|e| ConnectionError::TlsError(e.to_string()))?;
+
+    Ok(Connection::Tls(tls_stream))
+} 
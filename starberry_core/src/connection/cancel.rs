@@ -0,0 +1,86 @@
+//! Cancellation of the work bound to a connection.
+//!
+//! A `CancellationToken` is created for every accepted connection. It is cancelled
+//! when the client disconnects or when the connection exceeds its time limit, so
+//! that long-running handlers can stop early instead of computing a response that
+//! will never be delivered.
+
+use std::sync::{Arc, OnceLock};
+
+use tokio::sync::Notify;
+
+/// The reason a token was cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// The client closed the connection before the response was sent
+    ClientDisconnected,
+    /// The connection exceeded its configured time limit
+    Timeout,
+    /// The token was cancelled manually
+    Manual,
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    reason: OnceLock<CancelReason>,
+    notify: Notify,
+}
+
+/// A cheaply clonable token signalling that the work of a connection should stop.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::connection::{CancellationToken, CancelReason};
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// runtime.block_on(async {
+///     let token = CancellationToken::new();
+///     let watcher = token.clone();
+///     assert!(!watcher.is_cancelled());
+///
+///     token.cancel(CancelReason::Manual);
+///     watcher.cancelled().await;
+///     assert_eq!(watcher.reason(), Some(CancelReason::Manual));
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<CancelState>,
+}
+
+impl CancellationToken {
+    /// Creates a new token which is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token, waking every task waiting on `cancelled()`.
+    /// Only the first reason is kept if the token is cancelled several times.
+    pub fn cancel(&self, reason: CancelReason) {
+        if self.state.reason.set(reason).is_ok() {
+            self.state.notify.notify_waiters();
+        }
+    }
+
+    /// Returns `true` if the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.state.reason.get().is_some()
+    }
+
+    /// Returns why the token was cancelled, `None` if it is still active
+    pub fn reason(&self) -> Option<CancelReason> {
+        self.state.reason.get().copied()
+    }
+
+    /// Waits until the token is cancelled. Returns immediately if it already is.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.state.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
@@ -11,6 +11,7 @@ use std::task::{Context, Poll};
 use tokio::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf, ReadHalf, WriteHalf}; 
 use tokio::net::TcpStream;
 use tokio_rustls::client::TlsStream;
+use tokio_rustls::server::TlsStream as ServerTlsStream;
 
 /// Represents a connection which can be either plain TCP or secured with TLS.
 pub enum Connection {
@@ -18,6 +19,8 @@ pub enum Connection {
     Tcp(TcpStream),
     /// A secure TLS connection built on top of a TCP stream.
     Tls(TlsStream<TcpStream>),
+    /// A TLS connection accepted by the server, see `TlsCertificates`.
+    ServerTls(ServerTlsStream<TcpStream>),
 }
 
 impl Connection {
@@ -46,6 +49,19 @@ impl Connection {
     pub fn new_tls(stream: TlsStream<TcpStream>) -> Self {
         Connection::Tls(stream)
     } 
+
+    /// Creates a new `Connection` instance wrapping a TLS stream accepted by the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - A server side `TlsStream<TcpStream>` whose handshake is complete.
+    ///
+    /// # Returns
+    ///
+    /// A `Connection::ServerTls` variant wrapping the provided stream.
+    pub fn new_server_tls(stream: ServerTlsStream<TcpStream>) -> Self {
+        Connection::ServerTls(stream)
+    }
     
 
     /// Provides mutable access to the underlying stream for read operations.
@@ -58,6 +74,7 @@ impl Connection {
         match self {
             Connection::Tcp(stream) => stream,
             Connection::Tls(stream) => stream,
+            Connection::ServerTls(stream) => stream,
         }
     } 
 
@@ -87,9 +104,17 @@ impl Connection {
         match self {
             Connection::Tcp(stream) => stream,
             Connection::Tls(stream) => stream,
+            Connection::ServerTls(stream) => stream,
         }
     } 
 
+    /// Collects the peer address, local address and TLS details of this connection.
+    ///
+    /// This must be called before the connection is split.
+    pub fn info(&self) -> super::ConnectionInfo {
+        super::ConnectionInfo::from_connection(self)
+    }
+
     /// Gracefully shuts down the connection by closing the write half.
     ///
     /// This sends a FIN packet (TCP) or TLS close_notify alert to notify the peer
@@ -106,6 +131,7 @@ impl Connection {
         match self {
             Connection::Tcp(stream) => stream.shutdown().await,
             Connection::Tls(stream) => stream.shutdown().await,
+            Connection::ServerTls(stream) => stream.shutdown().await,
         }
     } 
 }
@@ -133,6 +159,7 @@ impl AsyncRead for Connection {
         match self.get_mut() {
             Connection::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
             Connection::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            Connection::ServerTls(stream) => Pin::new(stream).poll_read(cx, buf),
         }
     }
 }
@@ -159,6 +186,7 @@ impl AsyncWrite for Connection {
         match self.get_mut() {
             Connection::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
             Connection::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            Connection::ServerTls(stream) => Pin::new(stream).poll_write(cx, buf),
         }
     }
 
@@ -180,6 +208,7 @@ impl AsyncWrite for Connection {
         match self.get_mut() {
             Connection::Tcp(stream) => Pin::new(stream).poll_flush(cx),
             Connection::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            Connection::ServerTls(stream) => Pin::new(stream).poll_flush(cx),
         }
     }
 
@@ -201,6 +230,7 @@ impl AsyncWrite for Connection {
         match self.get_mut() {
             Connection::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
             Connection::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            Connection::ServerTls(stream) => Pin::new(stream).poll_shutdown(cx),
         }
     }
 } 
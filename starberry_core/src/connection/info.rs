@@ -0,0 +1,127 @@
+//! Information about an established connection, such as the peer address and the
+//! negotiated TLS parameters. It is collected once when the connection is accepted
+//! and handed to the protocol handler, since it can no longer be queried once the
+//! connection has been split into read and write halves.
+//!
+//! It also carries the deadline and the cancellation token of the connection.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use super::cancel::CancellationToken;
+use super::connection::Connection;
+
+/// Details of the TLS session of a connection
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// The negotiated protocol version, e.g. `TLSv1_3`
+    pub version: Option<String>,
+    /// The negotiated cipher suite, e.g. `TLS13_AES_128_GCM_SHA256`
+    pub cipher: Option<String>,
+    /// The server name requested by the client (SNI), when known
+    pub sni: Option<String>,
+    /// The application protocol negotiated with ALPN, e.g. `h2`
+    pub alpn: Option<String>,
+}
+
+/// Information about the connection a request was received on
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionInfo {
+    /// The address of the remote peer
+    pub peer_addr: Option<SocketAddr>,
+    /// The local address the connection was accepted on
+    pub local_addr: Option<SocketAddr>,
+    /// TLS details, `None` for plain TCP connections
+    pub tls: Option<TlsInfo>,
+    /// The application protocol spoken on the connection, e.g. `http/1.1` or `h2`
+    pub protocol: Option<String>,
+    /// The instant after which the connection will be closed, if limited
+    pub deadline: Option<Instant>,
+    /// Cancelled when the client disconnects or the deadline elapses
+    pub cancel: CancellationToken,
+}
+
+impl ConnectionInfo {
+    /// Collects the information of a connection
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - The connection to inspect, before it is split
+    ///
+    /// # Returns
+    ///
+    /// A `ConnectionInfo` with every field that could be determined
+    pub fn from_connection(connection: &Connection) -> Self {
+        match connection {
+            Connection::Tcp(stream) => Self {
+                peer_addr: stream.peer_addr().ok(),
+                local_addr: stream.local_addr().ok(),
+                tls: None,
+                protocol: None,
+                ..Default::default()
+            },
+            Connection::Tls(stream) => {
+                let (tcp, session) = stream.get_ref();
+                let alpn = session
+                    .alpn_protocol()
+                    .map(|p| String::from_utf8_lossy(p).into_owned());
+                Self {
+                    peer_addr: tcp.peer_addr().ok(),
+                    local_addr: tcp.local_addr().ok(),
+                    tls: Some(TlsInfo {
+                        version: session.protocol_version().map(|v| format!("{:?}", v)),
+                        cipher: session
+                            .negotiated_cipher_suite()
+                            .map(|s| format!("{:?}", s.suite())),
+                        sni: None,
+                        alpn: alpn.clone(),
+                    }),
+                    protocol: alpn,
+                    ..Default::default()
+                }
+            }
+            Connection::ServerTls(stream) => {
+                let (tcp, session) = stream.get_ref();
+                let alpn = session
+                    .alpn_protocol()
+                    .map(|p| String::from_utf8_lossy(p).into_owned());
+                Self {
+                    peer_addr: tcp.peer_addr().ok(),
+                    local_addr: tcp.local_addr().ok(),
+                    tls: Some(TlsInfo {
+                        version: session.protocol_version().map(|v| format!("{:?}", v)),
+                        cipher: session
+                            .negotiated_cipher_suite()
+                            .map(|s| format!("{:?}", s.suite())),
+                        sni: session.server_name().map(str::to_string),
+                        alpn: alpn.clone(),
+                    }),
+                    protocol: alpn,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the connection is secured with TLS
+    pub fn is_secure(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// Sets the deadline of the connection
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the cancellation token of the connection
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Returns the IP address of the remote peer
+    pub fn peer_ip(&self) -> Option<std::net::IpAddr> {
+        self.peer_addr.map(|addr| addr.ip())
+    }
+}
@@ -6,7 +6,7 @@ use tokio::io::{BufReader, BufWriter, ReadHalf, WriteHalf};
 use async_trait::async_trait; 
 
 use crate::app::urls::Url;
-use crate::connection::Connection; 
+use crate::connection::{Connection, ConnectionInfo}; 
 use crate::app::application::App; 
 
 #[async_trait] 
@@ -14,7 +14,7 @@ pub trait Rx: Sized + Send + Sync {
 
     fn test_protocol(initial_bytes: &[u8]) -> bool;
     
-    async fn process(app: Arc<App>, root_handler: Arc<Url<Self>>, read_half: BufReader<ReadHalf<Connection>>, write_half: BufWriter<WriteHalf<Connection>>); 
+    async fn process(app: Arc<App>, root_handler: Arc<Url<Self>>, conn_info: ConnectionInfo, read_half: BufReader<ReadHalf<Connection>>, write_half: BufWriter<WriteHalf<Connection>>); 
 
     // async fn process_direct(app: Arc<App>, root_handler: Self::RootHandler, stream: Connection) { 
     //     let (read_stream, write_stream) = stream.split();
@@ -10,6 +10,8 @@ use std::{
 /// Each type can have exactly one value
 pub struct Params { 
     inner: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    /// The type names of the stored values, as `TypeId` cannot be printed meaningfully
+    names: HashMap<TypeId, &'static str>,
 } 
 
 impl Params { 
@@ -27,7 +29,8 @@ impl Params {
     /// ``` 
     pub fn new() -> Self { 
         Self { 
-            inner: HashMap::new() 
+            inner: HashMap::new(), 
+            names: HashMap::new(), 
         }
     } 
 
@@ -47,6 +50,7 @@ impl Params {
     /// ```
     pub fn set<T: 'static + Send + Sync>(&mut self, value: T) {
         self.inner.insert(TypeId::of::<T>(), Box::new(value));
+        self.names.insert(TypeId::of::<T>(), std::any::type_name::<T>());
     } 
 
     /// Retrieves a reference to a value from the type-based params storage.
@@ -116,11 +120,19 @@ impl Params {
     /// }
     /// ```
     pub fn take<T: 'static + Send + Sync>(&mut self) -> Option<T> {
+        self.names.remove(&TypeId::of::<T>());
         self.inner
             .remove(&TypeId::of::<T>())
             .and_then(|boxed| boxed.downcast::<T>().ok())
             .map(|boxed| *boxed)
     } 
+
+    /// The type names of the stored values, sorted 
+    pub fn type_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.names.values().copied().collect();
+        names.sort_unstable();
+        names
+    } 
 }  
 
 impl fmt::Display for Params {
@@ -303,6 +315,11 @@ impl Locals {
         self.inner.keys().map(|s| s.as_str()).collect()
     } 
 
+    /// Retrieves a value without knowing its type, e.g. to describe it in an error report
+    pub fn get_any(&self, key: &str) -> Option<&(dyn Any + Send + Sync)> {
+        self.inner.get(key).map(|boxed| &**boxed)
+    } 
+
     //
     // Utility bridging methods
     //
@@ -535,6 +552,11 @@ impl ParamsClone {
             self.inner.insert(*ty, (**value).clone_box());
         } 
     } 
+
+    /// Whether a value of the type with the given id is stored, for types only known at runtime
+    pub fn contains_type_id(&self, ty: TypeId) -> bool {
+        self.inner.contains_key(&ty)
+    }
 } 
 
 impl Clone for ParamsClone {
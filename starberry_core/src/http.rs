@@ -3,10 +3,28 @@ pub mod body;
 pub mod context; 
 pub mod cookie; 
 pub mod encoding; 
+pub mod compression; 
 pub mod form; 
+pub mod bound_form; 
+pub mod sanitize; 
+pub mod markdown; 
+pub mod sniff; 
+pub mod query; 
+pub mod validate; 
+pub mod pagination; 
+pub mod json; 
+pub mod long_poll; 
+pub mod problem; 
+pub mod xml; 
+pub mod feed; 
+pub mod temp_file; 
+pub mod circuit_breaker; 
+pub mod webhook; 
 pub mod meta; 
 pub mod http_value; 
 pub mod response; 
 pub mod net; 
+pub mod proxy; 
 pub mod start_line; 
 pub mod safety; 
+pub mod error; 
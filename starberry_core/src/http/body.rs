@@ -4,7 +4,7 @@ use super::form::*;
 use super::http_value::*;
 use super::meta::HttpMeta; 
 use akari::Value;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncBufReadExt};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 static EMPTY: Vec<u8> = Vec::new();
 
@@ -25,17 +25,32 @@ impl HttpBody {
         header: &mut HttpMeta, 
         parse_config: &HttpSafety 
     ) -> Self {
-        let parsed;
+        Self::try_parse(buf_reader, header, parse_config)
+            .await
+            .expect("Failed to read body buffer")
+    }
+
+    /// Reads and parses the body, failing if it cannot be read or exceeds the maximum body size. 
+    /// An oversized body fails with `ErrorKind::FileTooLarge` as soon as the limit is crossed, 
+    /// without reading the rest of it. 
+    pub async fn try_parse<R: AsyncRead + Unpin>(
+        buf_reader: &mut tokio::io::BufReader<R>,
+        header: &mut HttpMeta, 
+        parse_config: &HttpSafety 
+    ) -> std::io::Result<Self> {
         // let content_length = header.get_content_length().unwrap_or(0).min(max_size);
         // // println!("Content‐Length header says: {}", content_length);
 
-        let body_buffer = Self::read_binary_info(buf_reader, header, parse_config)
-            .await
-            .expect("Failed to read body buffer"); 
+        let body_buffer = Self::read_binary_info(buf_reader, header, parse_config).await?; 
         // println!("Read {} bytes", body_buffer.len());
         // println!("Body buffer: {:?}", body_buffer);
 
-        parsed = match header
+        Ok(Self::from_bytes(body_buffer, header))
+    }
+
+    /// Parses a body which has already been read, according to its content type 
+    pub fn from_bytes(body_buffer: Vec<u8>, header: &mut HttpMeta) -> Self {
+        match header
             .get_content_type()
             .unwrap_or(HttpContentType::from_str(""))
         {
@@ -55,9 +70,7 @@ impl HttpBody {
                 Self::parse_files(body_buffer, boundary.unwrap_or("".to_string()))
             }
             _ => Self::parse_text(body_buffer),
-        };
-
-        parsed
+        }
     }
 
     pub async fn read_binary_info<R: AsyncRead + Unpin>(
@@ -72,8 +85,14 @@ impl HttpBody {
             safety_setting: &HttpSafety,
             content_length: usize, 
         ) -> std::io::Result<Vec<u8>> { 
-            let effective_content_length = std::cmp::min(content_length, safety_setting.effective_body_size()); 
-            let mut body_buffer = vec![0; effective_content_length];
+            // Refuse before reading anything rather than truncating the body 
+            if !safety_setting.check_body_size(content_length) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::FileTooLarge,
+                    "Body exceeds maximum size",
+                ));
+            }
+            let mut body_buffer = vec![0; content_length];
             buf_reader.read_exact(&mut body_buffer).await?;
             Ok(body_buffer)
         }
@@ -106,7 +125,7 @@ impl HttpBody {
                 current_size += chunk_size; 
                 if !safety_setting.check_body_size(current_size) {
                     return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
+                        std::io::ErrorKind::FileTooLarge,
                         "Chunked body exceeds maximum size",
                     ));
                 }
@@ -144,10 +163,87 @@ impl HttpBody {
 
         // Apply decompression based on Transfer-Encoding
         let raw_data = encoding.content().decode_compressed(raw_data)?; 
+        if !parse_config.check_body_size(raw_data.len()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::FileTooLarge,
+                "Decompressed body exceeds maximum size",
+            ));
+        }
 
         Ok(raw_data)
     }
 
+    /// Copies the body to `writer` as it is read, without holding it in memory, and returns its size. 
+    /// The size limit of `parse_config` applies as for `try_parse`. A compressed body is read and 
+    /// decoded by `read_binary_info` first, as decoding needs the whole body 
+    pub async fn stream_to<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+        buf_reader: &mut tokio::io::BufReader<R>, 
+        header: &mut HttpMeta, 
+        parse_config: &HttpSafety, 
+        writer: &mut W, 
+    ) -> std::io::Result<u64> {
+        /// Copies exactly `length` bytes from the reader 
+        async fn copy_exact<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+            buf_reader: &mut tokio::io::BufReader<R>, 
+            writer: &mut W, 
+            length: u64, 
+        ) -> std::io::Result<()> {
+            let copied = tokio::io::copy(&mut (&mut *buf_reader).take(length), writer).await?;
+            if copied < length {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Body ended early"));
+            }
+            Ok(())
+        }
+
+        let encoding = header.get_encoding().unwrap_or_default(); 
+        if !encoding.content().is_identity() {
+            let data = Self::read_binary_info(buf_reader, header, parse_config).await?;
+            writer.write_all(&data).await?;
+            return Ok(data.len() as u64);
+        }
+
+        if !encoding.transfer().is_chunked() {
+            let content_length = header.get_content_length().unwrap_or(0);
+            if !parse_config.check_body_size(content_length) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::FileTooLarge,
+                    "Body exceeds maximum size",
+                ));
+            }
+            copy_exact(buf_reader, writer, content_length as u64).await?;
+            return Ok(content_length as u64);
+        }
+
+        let mut current_size = 0;
+        loop {
+            let mut size_line = String::new();
+            buf_reader.read_line(&mut size_line).await?;
+            let chunk_size = usize::from_str_radix(size_line.trim_end_matches(['\r', '\n']), 16)
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid chunk size"))?;
+            if chunk_size == 0 {
+                break;
+            }
+            current_size += chunk_size;
+            if !parse_config.check_body_size(current_size) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::FileTooLarge,
+                    "Chunked body exceeds maximum size",
+                ));
+            }
+            copy_exact(buf_reader, writer, chunk_size as u64).await?;
+            let mut crlf = [0; 2];
+            buf_reader.read_exact(&mut crlf).await?;
+            if crlf != [b'\r', b'\n'] {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Invalid chunk terminator",
+                ));
+            }
+        }
+        header.append_from_request_stream(buf_reader, parse_config, false).await.map_err(|_| std::io::Error::new(std::io::ErrorKind::NetworkUnreachable, "Error parsing headers"))?;
+        Ok(current_size as u64)
+    }
+
     /// Write a response body to the TcpStream buffer
     /// This will automatically set the content length and content type for the meta if it is not set
     pub async fn into_static(&mut self, meta: &mut HttpMeta) -> &[u8] {
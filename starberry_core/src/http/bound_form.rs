@@ -0,0 +1,420 @@
+//! Forms rendered in templates and bound to structs.
+//!
+//! A struct deriving `FromQuery`, `Validate` and `Form` describes a form once: `Form` lists its
+//! fields with their label and input type, `FromQuery` parses the submitted values and
+//! `Validate` checks them. `BoundForm` holds the submitted values with the errors of every
+//! field, so a rejected form is rendered again as it was filled, with its errors.
+//!
+//! `BoundForm::to_value` gives the form to a template: `-[ form.html ]-` writes every field,
+//! `-[ form.fields.email.html ]-` a single one, and `form.fields.email.value` or
+//! `form.fields.email.error` give the parts to templates writing their own markup.
+//!
+//! The derive accepts these field attributes:
+//! - `#[form(label = "E-mail")]`, by default the name of the field, e.g. `Full name` for `full_name`
+//! - `#[form(kind = "textarea")]`, the `type` of the input, by default `checkbox` for `bool`,
+//!   `number` for numbers, `email` or `password` for fields named so, and `text` otherwise
+//! - `#[form(skip)]` leaves the field out of the rendered form
+//!
+//! Fields are required unless they are `Option`, `Vec`, `bool` or have a `#[query(default)]`,
+//! and `#[query(rename = "..")]` renames the input too.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[derive(FromQuery, Validate, Form)]
+//! struct Signup {
+//!     #[validate(length(min = 3, max = 20))]
+//!     username: String,
+//!     #[validate(email)]
+//!     email: String,
+//!     #[form(label = "Tell us about you", kind = "textarea")]
+//!     bio: Option<String>,
+//!     newsletter: bool,
+//! }
+//!
+//! #[url(reg![&APP, LitUrl("signup")])]
+//! async fn signup() -> HttpResponse {
+//!     if req.method() == GET {
+//!         return akari_render!("signup.html", form = BoundForm::<Signup>::new().to_value());
+//!     }
+//!     match req.bind_form::<Signup>().await.into_result() {
+//!         Ok(signup) => redirect_response(&format!("/welcome/{}", signup.username)),
+//!         Err(form) => akari_render!("signup.html", form = form.to_value()),
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use akari::Value;
+
+use super::query::{FromQuery, QueryMap};
+use super::sanitize::escape_html;
+use super::validate::Validate;
+
+/// A field of a form, as rendered in templates
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormField {
+    /// The name of the input, the key of its value
+    pub name: String,
+    pub label: String,
+    /// The `type` of the input, or `textarea`
+    pub kind: String,
+    pub required: bool,
+}
+
+impl FormField {
+    pub fn new<N: Into<String>, L: Into<String>, K: Into<String>>(name: N, label: L, kind: K) -> Self {
+        Self { name: name.into(), label: label.into(), kind: kind.into(), required: false }
+    }
+
+    pub fn required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+}
+
+/// A struct which can be rendered as a form, usually through `#[derive(Form)]`
+pub trait Form {
+    /// The fields of the form, in their order of rendering
+    fn form_fields() -> Vec<FormField>;
+
+    /// The values of the fields, to fill the form when editing an existing value
+    fn form_values(&self) -> QueryMap;
+}
+
+/// A value written in an input, the opposite of `FromQueryValue`
+pub trait FormValue {
+    /// Every value of the field: none for a missing `Option`, several for a `Vec`
+    fn form_values(&self) -> Vec<String>;
+}
+
+impl FormValue for String {
+    fn form_values(&self) -> Vec<String> {
+        vec![self.clone()]
+    }
+}
+
+impl FormValue for str {
+    fn form_values(&self) -> Vec<String> {
+        vec![self.to_string()]
+    }
+}
+
+impl<T: FormValue> FormValue for Option<T> {
+    fn form_values(&self) -> Vec<String> {
+        self.as_ref().map(T::form_values).unwrap_or_default()
+    }
+}
+
+impl<T: FormValue> FormValue for Vec<T> {
+    fn form_values(&self) -> Vec<String> {
+        self.iter().flat_map(T::form_values).collect()
+    }
+}
+
+macro_rules! form_value_via_to_string {
+    ($($t:ty),*) => {
+        $(
+            impl FormValue for $t {
+                fn form_values(&self) -> Vec<String> {
+                    vec![self.to_string()]
+                }
+            }
+        )*
+    };
+}
+
+form_value_via_to_string!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+/// A form with the values it was filled with and the errors of its fields
+#[derive(Debug, Clone)]
+pub struct BoundForm<T> {
+    fields: Vec<FormField>,
+    data: QueryMap,
+    /// The errors of the fields, in their order of discovery
+    errors: Vec<(String, String)>,
+    value: Option<T>,
+}
+
+impl<T: Form> BoundForm<T> {
+    /// An empty form, to be filled by the user
+    pub fn new() -> Self {
+        Self { fields: T::form_fields(), data: QueryMap::default(), errors: Vec::new(), value: None }
+    }
+
+    /// A form filled with the values of an existing value, e.g. to edit it
+    pub fn from_value(value: T) -> Self {
+        let data = value.form_values();
+        Self { fields: T::form_fields(), data, errors: Vec::new(), value: Some(value) }
+    }
+
+    /// Whether the submitted values were parsed and broke no rule
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty() && self.value.is_some()
+    }
+
+    /// The parsed value, `None` if the form is invalid or empty
+    pub fn value(&self) -> Option<&T> {
+        self.value.as_ref().filter(|_| self.errors.is_empty())
+    }
+
+    /// The parsed value, or the form to render again with its errors
+    pub fn into_result(self) -> Result<T, Self> {
+        if self.is_valid() {
+            Ok(self.value.expect("checked by is_valid"))
+        } else {
+            Err(self)
+        }
+    }
+
+    /// The submitted values
+    pub fn data(&self) -> &QueryMap {
+        &self.data
+    }
+
+    /// The errors of a field
+    pub fn errors(&self, field: &str) -> Vec<&str> {
+        self.errors.iter().filter(|(name, _)| name == field).map(|(_, message)| message.as_str()).collect()
+    }
+
+    /// Adds an error found after the validation, e.g. a username already taken, making the form invalid
+    pub fn add_error<F: Into<String>, M: Into<String>>(&mut self, field: F, message: M) {
+        self.errors.push((field.into(), message.into()));
+    }
+
+    /// The HTML of a field: its label, input and errors
+    pub fn field_html(&self, name: &str) -> Option<String> {
+        self.fields.iter().find(|field| field.name == name).map(|field| self.render_field(field))
+    }
+
+    /// The HTML of every field
+    pub fn html(&self) -> String {
+        self.fields.iter().map(|field| self.render_field(field)).collect()
+    }
+
+    /// The form for a template: `valid`, `html`, the `errors` of fields which are not
+    /// rendered and `fields`, mapping each name to its `name`, `label`, `type`, `required`,
+    /// `value`, `values`, `errors`, `error` (its errors joined) and `html`
+    pub fn to_value(&self) -> Value {
+        let mut fields = HashMap::new();
+        for field in &self.fields {
+            let errors = self.errors(&field.name);
+            let mut entry = HashMap::new();
+            entry.insert("name".to_string(), Value::Str(field.name.clone()));
+            entry.insert("label".to_string(), Value::Str(field.label.clone()));
+            entry.insert("type".to_string(), Value::Str(field.kind.clone()));
+            entry.insert("required".to_string(), boolean(field.required));
+            entry.insert("value".to_string(), Value::Str(self.shown_values(field).first().cloned().unwrap_or_default()));
+            entry.insert("values".to_string(), Value::List(self.shown_values(field).into_iter().map(Value::Str).collect()));
+            entry.insert("error".to_string(), Value::Str(errors.join("; ")));
+            entry.insert("errors".to_string(), Value::List(errors.iter().map(|e| Value::Str(e.to_string())).collect()));
+            entry.insert("html".to_string(), Value::Str(self.render_field(field)));
+            fields.insert(field.name.clone(), Value::Dict(entry));
+        }
+        let other_errors = self
+            .errors
+            .iter()
+            .filter(|(name, _)| !self.fields.iter().any(|field| field.name == *name))
+            .map(|(name, message)| Value::Str(format!("{} {}", name, message)))
+            .collect();
+        let mut form = HashMap::new();
+        form.insert("valid".to_string(), boolean(self.is_valid()));
+        form.insert("html".to_string(), Value::Str(self.html()));
+        form.insert("errors".to_string(), Value::List(other_errors));
+        form.insert("fields".to_string(), Value::Dict(fields));
+        Value::Dict(form)
+    }
+
+    /// The values written back in a field. Passwords are never sent back to the client
+    fn shown_values(&self, field: &FormField) -> Vec<String> {
+        if field.kind == "password" {
+            return Vec::new();
+        }
+        self.data.get_all(&field.name).into_iter().map(str::to_string).collect()
+    }
+
+    fn render_field(&self, field: &FormField) -> String {
+        let errors = self.errors(&field.name);
+        let id = format!("field-{}", escape_html(&field.name));
+        let name = escape_html(&field.name);
+        let value = self.shown_values(field).into_iter().next().unwrap_or_default();
+        let mut attributes = format!(" id=\"{}\" name=\"{}\"", id, name);
+        if field.required {
+            attributes.push_str(" required");
+        }
+        if !errors.is_empty() {
+            attributes.push_str(&format!(" aria-invalid=\"true\" aria-describedby=\"{}-errors\"", id));
+        }
+        let input = match field.kind.as_str() {
+            "textarea" => format!("<textarea{}>{}</textarea>", attributes, escape_html(&value)),
+            "checkbox" => {
+                let checked = matches!(value.to_ascii_lowercase().as_str(), "true" | "1" | "on" | "yes");
+                format!("<input type=\"checkbox\"{} value=\"true\"{}>", attributes, if checked { " checked" } else { "" })
+            }
+            kind => format!("<input type=\"{}\"{} value=\"{}\">", escape_html(kind), attributes, escape_html(&value)),
+        };
+        let mut html = format!(
+            "<div class=\"field{}\"><label for=\"{}\">{}</label>{}",
+            if errors.is_empty() { "" } else { " has-errors" },
+            id,
+            escape_html(&field.label),
+            input
+        );
+        if !errors.is_empty() {
+            html.push_str(&format!("<ul class=\"errors\" id=\"{}-errors\">", id));
+            for error in errors {
+                html.push_str(&format!("<li>{}</li>", escape_html(error)));
+            }
+            html.push_str("</ul>");
+        }
+        html.push_str("</div>\n");
+        html
+    }
+}
+
+impl<T: Form + FromQuery + Validate> BoundForm<T> {
+    /// Parses and validates submitted values. An unchecked checkbox sends nothing, so its
+    /// field is bound to `false`
+    pub fn bind(data: &QueryMap) -> Self {
+        let mut form = Self::new();
+        form.data = data.clone();
+        for field in &form.fields {
+            if field.kind == "checkbox" && !form.data.contains(&field.name) {
+                form.data.push(field.name.clone(), "false");
+            }
+        }
+        match T::from_query(&form.data) {
+            Ok(value) => {
+                if let Err(violations) = value.validate() {
+                    form.errors.extend(violations.0.into_iter().map(|v| (v.field, v.message)));
+                }
+                form.value = Some(value);
+            }
+            Err(errors) => form.errors.extend(errors.0.into_iter().map(|e| (e.field, e.message))),
+        }
+        form
+    }
+}
+
+impl<T: Form> Default for BoundForm<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn boolean(value: bool) -> Value {
+    Value::from_json(if value { "true" } else { "false" }).unwrap_or(Value::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::query::QueryErrors;
+    use crate::http::validate::{ValidationErrors, rules};
+
+    /// What `#[derive(FromQuery, Validate, Form)]` writes
+    #[derive(Debug)]
+    struct Signup {
+        email: String,
+        password: String,
+        bio: Option<String>,
+        newsletter: bool,
+    }
+
+    impl FromQuery for Signup {
+        fn from_query(query: &QueryMap) -> Result<Self, QueryErrors> {
+            let mut errors = QueryErrors::new();
+            let mut required = |key: &str| match query.get(key) {
+                Some(value) => value.to_string(),
+                None => {
+                    errors.push(key, "missing field");
+                    String::new()
+                }
+            };
+            let (email, password) = (required("email"), required("password"));
+            let newsletter = query.get("newsletter") == Some("true");
+            let bio = query.get("bio").map(str::to_string);
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+            Ok(Self { email, password, bio, newsletter })
+        }
+    }
+
+    impl Validate for Signup {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if let Err(message) = rules::email(&self.email) {
+                errors.push("email", "email", message);
+            }
+            if let Err(message) = rules::length(&self.password, Some(8), None) {
+                errors.push("password", "length", message);
+            }
+            errors.into_result()
+        }
+    }
+
+    impl Form for Signup {
+        fn form_fields() -> Vec<FormField> {
+            vec![
+                FormField::new("email", "Email", "email").required(true),
+                FormField::new("password", "Password", "password").required(true),
+                FormField::new("bio", "Bio", "textarea"),
+                FormField::new("newsletter", "Newsletter", "checkbox"),
+            ]
+        }
+
+        fn form_values(&self) -> QueryMap {
+            let mut values = QueryMap::default();
+            for (key, field) in [("email", self.email.form_values()), ("password", self.password.form_values())] {
+                field.into_iter().for_each(|value| values.push(key, value));
+            }
+            self.bio.form_values().into_iter().for_each(|value| values.push("bio", value));
+            self.newsletter.form_values().into_iter().for_each(|value| values.push("newsletter", value));
+            values
+        }
+    }
+
+    #[test]
+    fn binds_valid_values() {
+        let form = BoundForm::<Signup>::bind(&QueryMap::parse("email=a%40example.com&password=correct+horse"));
+        assert!(form.is_valid());
+        let signup = form.into_result().unwrap();
+        assert_eq!(signup.password, "correct horse");
+        assert!(!signup.newsletter);
+        assert_eq!(signup.bio, None);
+    }
+
+    #[test]
+    fn keeps_values_and_errors() {
+        let mut form = BoundForm::<Signup>::bind(&QueryMap::parse("email=%22%3E&password=short&newsletter=true"));
+        assert!(!form.is_valid());
+        assert_eq!(form.errors("email"), vec!["is not a valid email address"]);
+        let html = form.field_html("email").unwrap();
+        assert_eq!(
+            html,
+            "<div class=\"field has-errors\"><label for=\"field-email\">Email</label><input type=\"email\" id=\"field-email\" \
+             name=\"email\" required aria-invalid=\"true\" aria-describedby=\"field-email-errors\" value=\"&quot;&gt;\">\
+             <ul class=\"errors\" id=\"field-email-errors\"><li>is not a valid email address</li></ul></div>\n"
+        );
+        assert!(form.field_html("password").unwrap().contains("value=\"\""));
+        assert!(form.field_html("newsletter").unwrap().contains(" checked"));
+
+        form.add_error("captcha", "is wrong");
+        let Value::Dict(value) = form.to_value() else { panic!("not a dict") };
+        assert!(matches!(&value["errors"], Value::List(errors) if errors.len() == 1));
+        let Value::Dict(fields) = &value["fields"] else { panic!("no fields") };
+        let Value::Dict(password) = &fields["password"] else { panic!("no password") };
+        assert!(matches!(&password["error"], Value::Str(error) if error == "length must be at least 8"));
+    }
+
+    #[test]
+    fn fills_from_a_value() {
+        let signup = Signup { email: "a@example.com".to_string(), password: "secret".to_string(), bio: Some("<hi>".to_string()), newsletter: true };
+        let form = BoundForm::from_value(signup);
+        assert!(form.errors("email").is_empty());
+        assert!(form.field_html("bio").unwrap().contains("<textarea id=\"field-bio\" name=\"bio\">&lt;hi&gt;</textarea>"));
+        assert!(form.field_html("password").unwrap().contains("value=\"\""));
+        assert_eq!(BoundForm::<Signup>::new().html().matches("<div class=\"field\">").count(), 4);
+    }
+}
@@ -0,0 +1,371 @@
+//! A circuit breaker for the requests sent with the HTTP client.
+//!
+//! Each upstream host has its own circuit. While it is closed, requests go through and the
+//! outcomes of the latest ones are kept. Once enough of them failed, the circuit opens and
+//! requests are refused at once, answered by the fallback if one is set, instead of waiting
+//! on a failing dependency. After a while the circuit is half-open: a few trial requests go
+//! through, closing the circuit again if they succeed or reopening it if one fails.
+//!
+//! A request fails when no response is received in time or the response has a 5xx status.
+//!
+//! # Example
+//! ```rust,ignore
+//! static PAYMENTS: Lazy<CircuitBreaker> = Lazy::new(|| {
+//!     CircuitBreaker::new()
+//!         .timeout(Duration::from_secs(5))
+//!         .host("https://payments.example.com", BreakerSettings::new().failure_rate(0.2).open_for(Duration::from_secs(60)))
+//!         .fallback(|_host| return_status(StatusCode::SERVICE_UNAVAILABLE))
+//! });
+//!
+//! let response = PAYMENTS.send("https://payments.example.com", get_request("/status"), HttpSafety::new()).await?;
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::connection::error::ConnectionError;
+use crate::http::context::HttpResCtx;
+use crate::http::request::HttpRequest;
+use crate::http::response::HttpResponse;
+use crate::http::safety::HttpSafety;
+
+/// The state of the circuit of a host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests go through
+    Closed,
+    /// Requests are refused
+    Open,
+    /// A few trial requests go through
+    HalfOpen,
+}
+
+/// When a circuit opens and for how long
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakerSettings {
+    failure_rate: f64,
+    minimum_requests: usize,
+    window: usize,
+    open_for: Duration,
+    half_open_requests: usize,
+}
+
+impl BreakerSettings {
+    /// Opens after half of the latest 20 requests failed, at least 10 of them being known,
+    /// for 30 seconds, then lets one trial request through
+    pub fn new() -> Self {
+        Self {
+            failure_rate: 0.5,
+            minimum_requests: 10,
+            window: 20,
+            open_for: Duration::from_secs(30),
+            half_open_requests: 1,
+        }
+    }
+
+    /// Sets the share of failed requests opening the circuit, between 0.0 and 1.0
+    pub fn failure_rate(mut self, rate: f64) -> Self {
+        self.failure_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the number of outcomes needed before the failure rate is considered
+    pub fn minimum_requests(mut self, count: usize) -> Self {
+        self.minimum_requests = count.max(1);
+        self.window = self.window.max(self.minimum_requests);
+        self
+    }
+
+    /// Sets the number of latest outcomes the failure rate is computed on
+    pub fn window(mut self, count: usize) -> Self {
+        self.window = count.max(self.minimum_requests);
+        self
+    }
+
+    /// Sets how long the circuit stays open before trial requests are let through
+    pub fn open_for(mut self, duration: Duration) -> Self {
+        self.open_for = duration;
+        self
+    }
+
+    /// Sets the number of trial requests which must succeed to close the circuit
+    pub fn half_open_requests(mut self, count: usize) -> Self {
+        self.half_open_requests = count.max(1);
+        self
+    }
+}
+
+impl Default for BreakerSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+enum Circuit {
+    Closed { outcomes: VecDeque<bool> },
+    Open { until: Instant },
+    HalfOpen { in_flight: usize, successes: usize },
+}
+
+impl Circuit {
+    fn state(&self) -> CircuitState {
+        match self {
+            Self::Closed { .. } => CircuitState::Closed,
+            Self::Open { .. } => CircuitState::Open,
+            Self::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Whether a request may go through, `Err` with the time left while the circuit is open
+    fn allow(&mut self, settings: &BreakerSettings, now: Instant) -> Result<(), Duration> {
+        match self {
+            Self::Closed { .. } => Ok(()),
+            Self::Open { until } if now < *until => Err(*until - now),
+            Self::Open { .. } => {
+                *self = Self::HalfOpen { in_flight: 1, successes: 0 };
+                Ok(())
+            }
+            Self::HalfOpen { in_flight, .. } if *in_flight < settings.half_open_requests => {
+                *in_flight += 1;
+                Ok(())
+            }
+            Self::HalfOpen { .. } => Err(Duration::ZERO),
+        }
+    }
+
+    fn record(&mut self, settings: &BreakerSettings, success: bool, now: Instant) {
+        match self {
+            Self::Closed { outcomes } => {
+                outcomes.push_back(success);
+                while outcomes.len() > settings.window {
+                    outcomes.pop_front();
+                }
+                let failures = outcomes.iter().filter(|success| !**success).count();
+                if outcomes.len() >= settings.minimum_requests
+                    && failures as f64 >= settings.failure_rate * outcomes.len() as f64
+                    && failures > 0
+                {
+                    *self = Self::Open { until: now + settings.open_for };
+                }
+            }
+            // A request let through before the circuit opened
+            Self::Open { .. } => {}
+            Self::HalfOpen { .. } if !success => *self = Self::Open { until: now + settings.open_for },
+            Self::HalfOpen { in_flight, successes } => {
+                *in_flight = in_flight.saturating_sub(1);
+                *successes += 1;
+                if *successes >= settings.half_open_requests {
+                    *self = Self::Closed { outcomes: VecDeque::new() };
+                }
+            }
+        }
+    }
+}
+
+/// Why `CircuitBreaker::send` returned no response
+#[derive(Debug)]
+pub enum BreakerError {
+    /// The circuit of the host is open, `retry_in` is the time left before trial requests
+    Open { host: String, retry_in: Duration },
+    /// No response was received within the timeout
+    Timeout,
+    Connection(ConnectionError),
+}
+
+impl fmt::Display for BreakerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open { host, retry_in } => write!(f, "Circuit open for {}, retry in {:?}", host, retry_in),
+            Self::Timeout => write!(f, "Request timed out"),
+            Self::Connection(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BreakerError {}
+
+impl From<ConnectionError> for BreakerError {
+    fn from(e: ConnectionError) -> Self {
+        Self::Connection(e)
+    }
+}
+
+type Fallback = Arc<dyn Fn(&str) -> HttpResponse + Send + Sync>;
+
+/// Sends requests through a circuit per upstream host, see the module documentation
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    settings: BreakerSettings,
+    hosts: HashMap<String, BreakerSettings>,
+    timeout: Option<Duration>,
+    fallback: Option<Fallback>,
+    circuits: Arc<Mutex<HashMap<String, Circuit>>>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self {
+            settings: BreakerSettings::new(),
+            hosts: HashMap::new(),
+            timeout: None,
+            fallback: None,
+            circuits: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Sets the settings of the hosts without settings of their own
+    pub fn settings(mut self, settings: BreakerSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Sets the settings of a host
+    pub fn host<T: AsRef<str>>(mut self, host: T, settings: BreakerSettings) -> Self {
+        self.hosts.insert(host_key(host.as_ref()), settings);
+        self
+    }
+
+    /// Fails the requests which get no response within `duration`
+    pub fn timeout(mut self, duration: Duration) -> Self {
+        self.timeout = Some(duration);
+        self
+    }
+
+    /// Answers the requests refused by an open circuit, given the host
+    pub fn fallback<F: Fn(&str) -> HttpResponse + Send + Sync + 'static>(mut self, fallback: F) -> Self {
+        self.fallback = Some(Arc::new(fallback));
+        self
+    }
+
+    /// The state of the circuit of a host
+    pub fn state(&self, host: &str) -> CircuitState {
+        self.circuits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&host_key(host))
+            .map_or(CircuitState::Closed, Circuit::state)
+    }
+
+    /// Closes the circuit of a host, forgetting its failures
+    pub fn reset(&self, host: &str) {
+        self.circuits.lock().unwrap_or_else(|e| e.into_inner()).remove(&host_key(host));
+    }
+
+    /// Sends a request with `HttpResCtx::send_request` unless the circuit of the host is open
+    pub async fn send<T: Into<String>>(
+        &self,
+        host: T,
+        request: HttpRequest,
+        safety_config: HttpSafety,
+    ) -> Result<HttpResponse, BreakerError> {
+        let host = host.into();
+        let key = host_key(&host);
+        if let Err(retry_in) = self.allow(&key, Instant::now()) {
+            return match &self.fallback {
+                Some(fallback) => Ok(fallback(&key)),
+                None => Err(BreakerError::Open { host: key, retry_in }),
+            };
+        }
+        let sent = HttpResCtx::send_request(host, request, safety_config);
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, sent).await.unwrap_or(Err(ConnectionError::ConnectionTimeout)),
+            None => sent.await,
+        };
+        let success = result
+            .as_ref()
+            .is_ok_and(|response| response.meta.start_line.status_code().as_u16() < 500);
+        self.record(&key, success, Instant::now());
+        match result {
+            Err(ConnectionError::ConnectionTimeout) => Err(BreakerError::Timeout),
+            result => result.map_err(BreakerError::from),
+        }
+    }
+
+    fn settings_of(&self, key: &str) -> BreakerSettings {
+        self.hosts.get(key).copied().unwrap_or(self.settings)
+    }
+
+    fn allow(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let settings = self.settings_of(key);
+        self.circuits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key.to_string())
+            .or_insert_with(|| Circuit::Closed { outcomes: VecDeque::new() })
+            .allow(&settings, now)
+    }
+
+    fn record(&self, key: &str, success: bool, now: Instant) {
+        let settings = self.settings_of(key);
+        if let Some(circuit) = self.circuits.lock().unwrap_or_else(|e| e.into_inner()).get_mut(key) {
+            circuit.record(&settings, success, now);
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hosts are compared without scheme, trailing slash or case
+fn host_key(host: &str) -> String {
+    let host = host.trim();
+    let host = host.strip_prefix("https://").or_else(|| host.strip_prefix("http://")).unwrap_or(host);
+    host.trim_end_matches('/').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_and_recovers() {
+        let breaker = CircuitBreaker::new().host(
+            "api.example.com",
+            BreakerSettings::new().minimum_requests(4).window(4).open_for(Duration::from_secs(10)),
+        );
+        let key = "api.example.com";
+        let start = Instant::now();
+        for success in [true, false, true] {
+            assert!(breaker.allow(key, start).is_ok());
+            breaker.record(key, success, start);
+        }
+        assert_eq!(breaker.state("https://API.example.com/"), CircuitState::Closed);
+        breaker.record(key, false, start);
+        assert_eq!(breaker.state(key), CircuitState::Open);
+        assert_eq!(breaker.allow(key, start + Duration::from_secs(4)), Err(Duration::from_secs(6)));
+
+        // One trial request at a time once the circuit is half-open
+        let later = start + Duration::from_secs(10);
+        assert!(breaker.allow(key, later).is_ok());
+        assert_eq!(breaker.state(key), CircuitState::HalfOpen);
+        assert!(breaker.allow(key, later).is_err());
+        breaker.record(key, false, later);
+        assert_eq!(breaker.state(key), CircuitState::Open);
+
+        let later = later + Duration::from_secs(10);
+        assert!(breaker.allow(key, later).is_ok());
+        breaker.record(key, true, later);
+        assert_eq!(breaker.state(key), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn open_circuit_uses_the_fallback() {
+        use crate::http::http_value::StatusCode;
+        use crate::http::request::request_templates::get_request;
+        use crate::http::response::response_templates::return_status;
+
+        let breaker = CircuitBreaker::new()
+            .settings(BreakerSettings::new().minimum_requests(1).window(1))
+            .fallback(|_| return_status(StatusCode::SERVICE_UNAVAILABLE));
+        breaker.allow("down.example.com", Instant::now()).unwrap();
+        breaker.record("down.example.com", false, Instant::now());
+        let response = breaker.send("http://down.example.com", get_request("/"), HttpSafety::new()).await.unwrap();
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
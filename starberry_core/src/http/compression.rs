@@ -0,0 +1,322 @@
+//! The settings of response compression: the level of each coding, the smallest body worth
+//! compressing, the content types compressed and the routes using other levels.
+//!
+//! They are given to the application with `AppBuilder::compression` and applied by the
+//! `Compression` middleware of `sbmstd`. Latency sensitive endpoints can use faster levels
+//! or skip compression, while large static pages take the better but slower ones.
+//!
+//! # Example
+//! ```rust,ignore
+//! let compression = CompressionConfig::new()
+//!     .brotli_quality(5)
+//!     .min_size(512)
+//!     .allow_content_type("application/x-ndjson")
+//!     .route("/api/quotes", CompressionLevels::FAST)
+//!     .skip_route("/events");
+//! App::new().compression(compression).build();
+//! ```
+
+use std::io;
+
+use starberry_lib::compression;
+
+use crate::http::body::HttpBody;
+use crate::http::encoding::ContentCoding;
+use crate::http::http_value::HttpContentType;
+use crate::http::meta::HttpMeta;
+use crate::http::response::HttpResponse;
+
+/// The content types compressed by default. Entries ending with `/` cover every subtype
+const DEFAULT_CONTENT_TYPES: [&str; 10] = [
+    "text/",
+    "application/json",
+    "application/problem+json",
+    "application/javascript",
+    "application/xml",
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/manifest+json",
+    "application/wasm",
+    "image/svg+xml",
+];
+
+/// Bodies smaller than this are sent as they are, as compression would barely save anything
+const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// The level used for each coding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionLevels {
+    /// From 0 to 9
+    pub gzip: u32,
+    /// From 0 to 9
+    pub deflate: u32,
+    /// From 0 to 11
+    pub brotli: u32,
+    /// From 1 to 22
+    pub zstd: i32,
+}
+
+impl CompressionLevels {
+    /// A balance between speed and size for responses compressed on each request
+    pub const DEFAULT: Self = Self {
+        gzip: 6,
+        deflate: 6,
+        brotli: compression::BROTLI_STREAM_QUALITY,
+        zstd: compression::ZSTD_DEFAULT_LEVEL,
+    };
+
+    /// The fastest levels, for latency sensitive endpoints
+    pub const FAST: Self = Self { gzip: 1, deflate: 1, brotli: 1, zstd: 1 };
+
+    /// The best levels, for large bodies which rarely change
+    pub const BEST: Self = Self { gzip: 9, deflate: 9, brotli: 11, zstd: 19 };
+
+    /// Compresses data with the coding at its level
+    pub fn encode(&self, coding: &ContentCoding, data: &[u8]) -> io::Result<Vec<u8>> {
+        match coding {
+            ContentCoding::Gzip => compression::compress_gzip_level(data, self.gzip),
+            ContentCoding::Deflate => compression::compress_deflate_level(data, self.deflate),
+            ContentCoding::Brotli => compression::compress_brotli_quality(data, self.brotli),
+            ContentCoding::Zstd => compression::compress_zstd(data, self.zstd.clamp(1, 22)),
+            _ => ContentCoding::encode_compressed(coding, data),
+        }
+    }
+}
+
+impl Default for CompressionLevels {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// How responses are compressed, set with `AppBuilder::compression`
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    levels: CompressionLevels,
+    min_size: usize,
+    content_types: Vec<String>,
+    codings: Vec<ContentCoding>,
+    /// Path prefixes with their own levels, `None` when they are not compressed
+    routes: Vec<(String, Option<CompressionLevels>)>,
+}
+
+impl CompressionConfig {
+    pub fn new() -> Self {
+        Self {
+            levels: CompressionLevels::DEFAULT,
+            min_size: DEFAULT_MIN_SIZE,
+            content_types: DEFAULT_CONTENT_TYPES.iter().map(|content_type| content_type.to_string()).collect(),
+            codings: ContentCoding::COMPRESSIONS.to_vec(),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Sets the levels of every coding
+    pub fn levels(mut self, levels: CompressionLevels) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    /// Sets the GZIP level, 6 by default
+    pub fn gzip_level(mut self, level: u32) -> Self {
+        self.levels.gzip = level;
+        self
+    }
+
+    /// Sets the DEFLATE level, 6 by default
+    pub fn deflate_level(mut self, level: u32) -> Self {
+        self.levels.deflate = level;
+        self
+    }
+
+    /// Sets the Brotli quality, `BROTLI_STREAM_QUALITY` by default
+    pub fn brotli_quality(mut self, quality: u32) -> Self {
+        self.levels.brotli = quality;
+        self
+    }
+
+    /// Sets the Zstandard level, `ZSTD_DEFAULT_LEVEL` by default
+    pub fn zstd_level(mut self, level: i32) -> Self {
+        self.levels.zstd = level;
+        self
+    }
+
+    /// Sets the size of the smallest body compressed, 1 KB by default
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Replaces the content types compressed, e.g. `application/json`. An entry ending with
+    /// `/`, e.g. `text/`, covers every subtype. Images, videos and archives are already
+    /// compressed and are left out by default
+    pub fn content_types<I: IntoIterator<Item = S>, S: Into<String>>(mut self, content_types: I) -> Self {
+        self.content_types = content_types.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds a content type to the ones compressed
+    pub fn allow_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_types.push(content_type.into());
+        self
+    }
+
+    /// Sets the codings offered, by order of preference when the client accepts several
+    /// equally. `ContentCoding::COMPRESSIONS` by default
+    pub fn codings<I: IntoIterator<Item = ContentCoding>>(mut self, codings: I) -> Self {
+        self.codings = codings.into_iter().collect();
+        self
+    }
+
+    /// Uses other levels for the paths under the prefix. The longest matching prefix wins
+    pub fn route(mut self, prefix: &str, levels: CompressionLevels) -> Self {
+        self.routes.push((prefix.trim_end_matches('/').to_string(), Some(levels)));
+        self
+    }
+
+    /// Does not compress the responses of the paths under the prefix, e.g. server sent events
+    pub fn skip_route(mut self, prefix: &str) -> Self {
+        self.routes.push((prefix.trim_end_matches('/').to_string(), None));
+        self
+    }
+
+    pub fn get_codings(&self) -> &[ContentCoding] {
+        &self.codings
+    }
+
+    /// The levels for a path, `None` if its responses are not compressed
+    pub fn levels_for(&self, path: &str) -> Option<CompressionLevels> {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(Some(self.levels), |(_, levels)| *levels)
+    }
+
+    /// Whether bodies of the content type, e.g. `text/html; charset=utf-8`, are compressed
+    pub fn allows(&self, content_type: &str) -> bool {
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        self.content_types.iter().any(|allowed| {
+            let allowed = allowed.trim().to_ascii_lowercase();
+            if allowed.ends_with('/') {
+                essence.starts_with(&allowed)
+            } else {
+                essence == allowed
+            }
+        })
+    }
+
+    /// Compresses the body of a response with the coding chosen for the request, `None` when
+    /// the client accepts none. Returns whether the body was compressed. Responses already
+    /// encoded, marked `no-transform`, too small or of another content type are left alone
+    pub fn apply(&self, response: &mut HttpResponse, coding: Option<&ContentCoding>, levels: &CompressionLevels) -> bool {
+        let status = response.meta.start_line.status_code().as_u16();
+        if status < 200 || matches!(status, 204 | 206 | 304) || response.meta.get_header("content-encoding").is_some() {
+            return false;
+        }
+        if response
+            .meta
+            .get_header("cache-control")
+            .is_some_and(|cache_control| cache_control.to_ascii_lowercase().contains("no-transform"))
+        {
+            return false;
+        }
+        // The content type a text or JSON body would be sent with, kept once it is binary
+        let content_type = match (&response.body, response.meta.get_content_type()) {
+            (HttpBody::Text(_), _) => HttpContentType::TextPlain(),
+            (_, Some(content_type)) => content_type,
+            (HttpBody::Json(_), None) => HttpContentType::ApplicationJson(),
+            _ => return false,
+        };
+        if !self.allows(&content_type.to_string()) {
+            return false;
+        }
+        let data = match &response.body {
+            HttpBody::Text(text) => text.as_bytes().to_vec(),
+            HttpBody::Binary(bytes) => bytes.clone(),
+            HttpBody::Json(json) => json.into_json().into_bytes(),
+            _ => return false,
+        };
+        if data.len() < self.min_size {
+            return false;
+        }
+        // The representation depends on Accept-Encoding, even when it is not compressed
+        add_vary(&mut response.meta);
+        let Some(coding) = coding else {
+            return false;
+        };
+        let compressed = match levels.encode(coding, &data) {
+            Ok(compressed) if compressed.len() < data.len() => compressed,
+            _ => return false,
+        };
+        response.meta.set_content_type(content_type);
+        response.meta.set_content_length(compressed.len());
+        response.meta.set_attribute("content-encoding", coding.as_str());
+        response.body = HttpBody::Binary(compressed);
+        true
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn add_vary(meta: &mut HttpMeta) {
+    match meta.get_header("vary") {
+        Some(vary)
+            if vary
+                .split(',')
+                .any(|field| field.trim() == "*" || field.trim().eq_ignore_ascii_case("accept-encoding")) => {}
+        Some(vary) => meta.set_attribute("vary", format!("{}, Accept-Encoding", vary)),
+        None => meta.set_attribute("vary", "Accept-Encoding"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::response::response_templates::{html_response, serve_bytes, text_response};
+
+    fn page() -> String {
+        "<p>Sweet and small</p>".repeat(200)
+    }
+
+    #[test]
+    fn compresses_allowed_bodies() {
+        let config = CompressionConfig::new();
+        let levels = config.levels_for("/").unwrap();
+        let mut response = html_response(page());
+        assert!(config.apply(&mut response, Some(&ContentCoding::Gzip), &levels));
+        assert_eq!(response.meta.get_header("content-encoding").as_deref(), Some("gzip"));
+        assert_eq!(response.meta.get_header("vary").as_deref(), Some("Accept-Encoding"));
+        let HttpBody::Binary(compressed) = &response.body else { panic!("Expected a binary body") };
+        assert_eq!(compression::decompress_gzip(compressed).unwrap(), page().into_bytes());
+
+        let mut small = text_response("Hello");
+        assert!(!config.apply(&mut small, Some(&ContentCoding::Gzip), &levels));
+        let mut image = serve_bytes(HttpContentType::ImageJpeg(), page());
+        assert!(!config.apply(&mut image, Some(&ContentCoding::Gzip), &levels));
+        let mut declined = text_response(page());
+        assert!(!config.apply(&mut declined, None, &levels));
+        assert_eq!(declined.meta.get_header("vary").as_deref(), Some("Accept-Encoding"));
+    }
+
+    #[test]
+    fn routes_override_the_levels() {
+        let config = CompressionConfig::new()
+            .gzip_level(9)
+            .route("/api/", CompressionLevels::FAST)
+            .skip_route("/api/events");
+        assert_eq!(config.levels_for("/blog").map(|levels| levels.gzip), Some(9));
+        assert_eq!(config.levels_for("/api/quotes"), Some(CompressionLevels::FAST));
+        assert_eq!(config.levels_for("/api/events/stream"), None);
+        assert!(config.allows("application/json; charset=utf-8"));
+        assert!(config.allows("text/css"));
+        assert!(!config.allows("application/zip"));
+    }
+}
@@ -1,28 +1,61 @@
-use crate::app::{application::App, urls::Url};
+use crate::app::reporter::ErrorReport;
+use crate::app::timeouts::RouteTimeouts;
+use crate::app::trace::{self, Span, TraceContext};
+use crate::app::{application::App, task, urls::Url};
 use crate::connection::error::ConnectionError;
-use crate::connection::{Connection, ConnectionBuilder};
+use crate::connection::{CancelReason, CancellationToken, Connection, ConnectionBuilder, ConnectionInfo};
 use crate::connection::{Rx, Tx};
 use crate::extensions::{Locals, Params};
 use crate::http::cookie::{Cookie, CookieMap};
+use crate::http::encoding::ContentCoding;
+use crate::http::proxy::TrustedProxies;
 use crate::http::request::HttpRequest;
 use crate::http::safety::HttpSafety;
 use crate::http::{
     body::HttpBody,
+    bound_form::{BoundForm, Form},
+    error::ErrorContext,
     form::{MultiForm, UrlEncodedForm},
-    http_value::HttpMethod,
+    http_value::{Authorization, HttpMethod, StrictTransportSecurity},
     meta::HttpMeta,
+    query::{FromQuery, QueryErrors, QueryMap},
     response::HttpResponse,
+    temp_file::TempFile,
+    validate::{ExtractError, Validate},
+    xml::{XmlError, from_xml},
 };
 use akari::Value;
 use async_trait::async_trait;
+use futures::FutureExt;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::io::{AsyncWriteExt, BufReader, BufWriter, ReadHalf, WriteHalf};
 
 use super::http_value::StatusCode;
 use super::response::response_templates;
 
+/// How long each phase of a request took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestTiming {
+    /// Reading the request line and headers
+    pub read: Duration,
+    /// Running the middlewares and the handler, including reading the body if it was parsed
+    pub handle: Duration,
+    /// Writing the response
+    pub write: Duration,
+}
+
+impl RequestTiming {
+    pub fn total(&self) -> Duration {
+        self.read + self.handle + self.write
+    }
+}
+
+type SentHook = Box<dyn FnOnce(&HttpResponse, &RequestTiming) + Send + Sync>;
+
 /// The `RequestContext` struct is used to hold the context of a request.
 pub struct HttpReqCtx {
     pub request: HttpRequest,
@@ -33,6 +66,13 @@ pub struct HttpReqCtx {
     pub response: HttpResponse,
     pub params: Params,
     pub locals: Locals,
+    pub conn_info: ConnectionInfo,
+    pub request_id: String,
+    body_limit: Option<usize>,
+    body_error: Option<StatusCode>,
+    started_at: Instant,
+    read_time: Duration,
+    on_sent: Vec<SentHook>,
 }
 
 impl HttpReqCtx {
@@ -53,6 +93,13 @@ impl HttpReqCtx {
             response: HttpResponse::default(),
             params: Default::default(),
             locals: Default::default(),
+            conn_info: Default::default(),
+            request_id: starberry_lib::uuid::uuid_v7(),
+            body_limit: None,
+            body_error: None,
+            started_at: Instant::now(),
+            read_time: Duration::ZERO,
+            on_sent: Vec::new(),
         }
     }
 
@@ -60,9 +107,11 @@ impl HttpReqCtx {
     pub async fn handle(
         app: Arc<App>,
         root_handler: Arc<Url<HttpReqCtx>>,
+        mut conn_info: ConnectionInfo,
         mut reader: BufReader<ReadHalf<Connection>>,
         writer: BufWriter<WriteHalf<Connection>>,
     ) -> Self {
+        let started_at = Instant::now();
         // Create one BufReader up-front, pass this throughout.
         let request = HttpRequest::parse_lazy(
             &mut reader,
@@ -72,18 +121,165 @@ impl HttpReqCtx {
         .await;
         let endpoint = root_handler.walk_str(&request.meta.path()).await;
         // let endpoint = dangling_url();
-        Self::new(request, reader, writer, app.clone(), endpoint.clone())
+        if conn_info.protocol.is_none() {
+            conn_info.protocol = Some(request.meta.start_line.http_version().to_string().to_lowercase());
+        }
+        let mut ctx = Self::new(request, reader, writer, app.clone(), endpoint.clone());
+        ctx.conn_info = conn_info;
+        ctx.started_at = started_at;
+        ctx.read_time = started_at.elapsed();
+        // Keep the id given by a proxy in front of the server so logs can be correlated
+        if let Some(id) = ctx.request.meta.get_header("x-request-id") {
+            let id = id.trim();
+            if !id.is_empty() && id.len() <= 128 && id.chars().all(|c| c.is_ascii_graphic()) {
+                ctx.request_id = id.to_string();
+            }
+        }
+        ctx
     }
 
     /// Runs the endpoint and sending the response.
     pub async fn run(mut self) {
         let endpoint = self.endpoint.clone();
+        let handle_start = Instant::now();
         if let Err(s) = self.request_check(&endpoint){ 
             self.response = response_templates::return_status(s);
-            return self.send_response().await; 
+            return self.finish(handle_start).await; 
+        };
+        let request_id = self.request_id.clone();
+        // A route with its own time limit replaces the deadline of the connection
+        let route_timeout = self.app.config.get::<RouteTimeouts>().and_then(|timeouts| {
+            timeouts.timeout_for(&self.request.meta.method(), &endpoint.route_pattern())
+        });
+        if let Some(timeout) = route_timeout {
+            self.conn_info.deadline = Some(self.started_at + timeout);
+        }
+        let deadline = self.conn_info.deadline;
+        let cancel = self.conn_info.cancel.clone();
+        // The request is a span of the trace of the caller, or the root of a new trace
+        let trace = match self.request.meta.get_header("traceparent").and_then(|h| TraceContext::parse(&h)) {
+            Some(parent) => {
+                let state = self.request.meta.get_header("tracestate");
+                let trace = parent.child();
+                match state {
+                    Some(state) => trace.with_state(state),
+                    None => trace,
+                }
+            }
+            None => TraceContext::new_root(),
         };
-        let parsed = endpoint.run(self);
-        parsed.await.send_response().await;
+        // Taken beforehand, as the context is lost if the handler panics
+        let app = self.app.clone();
+        let snapshot = app.error_reporter().map(|_| self.error_context());
+        let parsed = task::with_request_id(request_id.clone(), trace::with_trace(trace, endpoint.run(self)));
+        let handled = AssertUnwindSafe(parsed).catch_unwind();
+        let outcome = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), handled).await.ok(),
+            None => Some(handled.await),
+        };
+        match outcome {
+            Some(Ok(ctx)) => ctx.finish(handle_start).await,
+            Some(Err(payload)) => {
+                // The connection is closed without response, its writer went with the context
+                eprintln!("⚠️ Request {} panicked", request_id);
+                if let Some(mut context) = snapshot {
+                    context.status = StatusCode::INTERNAL_SERVER_ERROR.as_u16();
+                    context.elapsed = handle_start.elapsed();
+                    app.report_error(ErrorReport::panic(&*payload, context));
+                }
+            }
+            None => {
+                // Closed without response as well, like a connection exceeding its time
+                cancel.cancel(CancelReason::Timeout);
+                eprintln!("⚠️ Request {} timed out after {:?}", request_id, handle_start.elapsed());
+                if let Some(mut context) = snapshot {
+                    context.status = StatusCode::GATEWAY_TIMEOUT.as_u16();
+                    context.elapsed = handle_start.elapsed();
+                    app.report_error(ErrorReport::error("Request timed out", context));
+                }
+            }
+        }
+    }
+
+    /// Sends the response and runs the hooks registered with `on_sent`
+    async fn finish(mut self, handle_start: Instant) {
+        let handle = handle_start.elapsed();
+        if let Some(hsts) = self.app.config.get::<StrictTransportSecurity>()
+            && self.response.meta.get_header("strict-transport-security").is_none()
+        {
+            self.response.meta.set_attribute("strict-transport-security", hsts.to_string());
+        }
+        let write_start = Instant::now();
+        let _ = self.response.send(&mut self.writer).await;
+        let timing = RequestTiming {
+            read: self.read_time,
+            handle,
+            write: write_start.elapsed(),
+        };
+        for hook in self.on_sent.drain(..) {
+            hook(&self.response, &timing);
+        }
+        let status = self.response.meta.start_line.status_code();
+        if status.as_u16() >= 500 {
+            // The body of a plain text error, e.g. from `HttpError`, tells what went wrong
+            let plain = self
+                .response
+                .meta
+                .get_content_type()
+                .is_none_or(|content_type| content_type.to_string().starts_with("text/plain"))
+                && self.response.meta.get_header("content-encoding").is_none();
+            let detail = match &self.response.body {
+                HttpBody::Text(text) if plain => text.trim().to_string(),
+                HttpBody::Binary(bytes) if plain => String::from_utf8_lossy(bytes).trim().to_string(),
+                _ => String::new(),
+            };
+            let message = if detail.is_empty() { status.to_string() } else { format!("{}: {}", status, detail) };
+            self.report_error(message);
+        }
+    }
+
+    /// Gives an error to the reporter set with `AppBuilder::error_reporter`, with the context of
+    /// the request. Responses with a 5xx status are reported without calling this
+    pub fn report_error(&self, message: impl Into<String>) {
+        if self.app.error_reporter().is_some() {
+            self.app.report_error(ErrorReport::error(message, self.error_context()));
+        }
+    }
+
+    /// Registers a hook called once the response has been written, with the timing of the request.
+    /// Used by middlewares which need the whole duration of the request, such as access logs
+    pub fn on_sent<F: FnOnce(&HttpResponse, &RequestTiming) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.on_sent.push(Box::new(hook));
+    }
+
+    /// The time elapsed since the server started reading the request
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// A snapshot of the request for error pages and error logs: the route, the names of the
+    /// params and locals with the values which can be shown, the status and the time spent
+    pub fn error_context(&self) -> ErrorContext {
+        let mut locals: Vec<(String, String)> = self
+            .locals
+            .keys()
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.locals.get_any(key)?;
+                Some((key.to_string(), ErrorContext::describe_local(key, value)))
+            })
+            .collect();
+        locals.sort();
+        ErrorContext {
+            request_id: self.request_id.clone(),
+            method: self.request.meta.method().to_string(),
+            path: self.path(),
+            route: self.endpoint.route_pattern(),
+            status: self.response.meta.start_line.status_code().as_u16(),
+            params: self.params.type_names().into_iter().map(str::to_string).collect(),
+            locals,
+            elapsed: self.elapsed(),
+        }
     }
 
     /// Checks whether the request fulfills the endpoint's security requirements.
@@ -129,12 +325,115 @@ impl HttpReqCtx {
     /// Note that request body will not be automatically parsed unless this function is called
     /// The automatic parsing is not recommended, as it can lead to performance issues and security vulnerabilities.
     /// If you didn't parse body, the body will be `HttpBody::Unparsed`.
+    /// If the body cannot be read, e.g. because it exceeds the limit, it is left empty and the error is kept in `body_error`.
     pub async fn parse_body(&mut self) {
+        let safety_settings = self.body_safety();
+        if let Err(status) = self
+            .request
+            .parse_body(&mut self.reader, &safety_settings)
+            .await
+        {
+            self.body_error = Some(status);
+        }
+    }
+
+    /// The safety settings of the endpoint, with the body limit of `limit_body` if it is lower
+    fn body_safety(&self) -> HttpSafety {
         let mut safety_settings = self.endpoint.get_params::<HttpSafety>().unwrap_or_default();
         safety_settings.update(&self.endpoint.get_params::<HttpSafety>().unwrap_or_default());
-        self.request
-            .parse_body(&mut self.reader, &safety_settings)
-            .await;
+        if let Some(limit) = self.body_limit {
+            let max = safety_settings.max_body_size().map_or(limit, |max| max.min(limit));
+            safety_settings.set_max_body_size(Some(max));
+        }
+        safety_settings
+    }
+
+    /// Streams the body of the request to a temporary file, deleted when the returned `TempFile`
+    /// is dropped, for tools which need a file path. The limits of `parse_body` apply, reading
+    /// fails with `PAYLOAD_TOO_LARGE` as soon as they are crossed.
+    /// The body is not kept in memory, so it cannot be parsed afterwards. If it was already
+    /// parsed, a text or binary body is written to the file and other bodies fail with `CONFLICT`
+    pub async fn save_body_to_tempfile(&mut self) -> Result<TempFile, StatusCode> {
+        let (temp, mut file) = TempFile::create().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let written = match &self.request.body {
+            HttpBody::Unparsed => {
+                let safety_settings = self.body_safety();
+                let result =
+                    HttpBody::stream_to(&mut self.reader, &mut self.request.meta, &safety_settings, &mut file).await;
+                // The body has been consumed, do not try to read it again
+                self.request.body = HttpBody::Empty;
+                if let Err(e) = result {
+                    let status = match e.kind() {
+                        std::io::ErrorKind::FileTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+                        std::io::ErrorKind::InvalidData
+                        | std::io::ErrorKind::UnexpectedEof
+                        | std::io::ErrorKind::NetworkUnreachable => StatusCode::BAD_REQUEST,
+                        _ => StatusCode::INTERNAL_SERVER_ERROR,
+                    };
+                    self.body_error = Some(status.clone());
+                    return Err(status);
+                }
+                Ok(())
+            }
+            HttpBody::Binary(bytes) => file.write_all(bytes).await,
+            HttpBody::Text(text) => file.write_all(text.as_bytes()).await,
+            HttpBody::Empty => match self.body_error.clone() {
+                Some(status) => return Err(status),
+                None => Ok(()),
+            },
+            _ => return Err(StatusCode::CONFLICT),
+        };
+        written
+            .and(file.flush().await)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(temp)
+    }
+
+    /// Reads the body of the request and returns its bytes exactly as they were sent, e.g. to
+    /// check a signature. The body is also parsed as by `parse_body`, so `json` or `form` can
+    /// still be used afterwards. If it was already parsed, a text or binary body is returned
+    /// and other bodies fail with `CONFLICT`, as they cannot be given back byte for byte
+    pub async fn raw_body(&mut self) -> Result<Vec<u8>, StatusCode> {
+        match &self.request.body {
+            HttpBody::Unparsed => {
+                let safety_settings = self.body_safety();
+                match HttpBody::read_binary_info(&mut self.reader, &mut self.request.meta, &safety_settings).await {
+                    Ok(bytes) => {
+                        self.request.body = HttpBody::from_bytes(bytes.clone(), &mut self.request.meta);
+                        Ok(bytes)
+                    }
+                    Err(e) => {
+                        // The stream is left in an unknown state, do not try to read it again
+                        self.request.body = HttpBody::Empty;
+                        let status = if e.kind() == std::io::ErrorKind::FileTooLarge {
+                            StatusCode::PAYLOAD_TOO_LARGE
+                        } else {
+                            StatusCode::BAD_REQUEST
+                        };
+                        self.body_error = Some(status.clone());
+                        Err(status)
+                    }
+                }
+            }
+            HttpBody::Binary(bytes) => Ok(bytes.clone()),
+            HttpBody::Text(text) => Ok(text.as_bytes().to_vec()),
+            HttpBody::Empty => match self.body_error.clone() {
+                Some(status) => Err(status),
+                None => Ok(Vec::new()),
+            },
+            _ => Err(StatusCode::CONFLICT),
+        }
+    }
+
+    /// Limits the size of the body of this request. The limit is enforced while the body is read,
+    /// which stops as soon as it is crossed. The limit of the endpoint still applies if it is lower
+    pub fn limit_body(&mut self, max: usize) {
+        self.body_limit = Some(max);
+    }
+
+    /// The status to answer with if reading the body failed, `PAYLOAD_TOO_LARGE` if it exceeded the limit
+    pub fn body_error(&self) -> Option<StatusCode> {
+        self.body_error.clone()
     }
 
     /// Returns the body of the request as a reference to `HttpBody`.
@@ -200,6 +499,17 @@ impl HttpReqCtx {
         }
     }
 
+    /// Parses the body of the request as XML, e.g. from `application/xml` or `text/xml`.
+    /// The document is returned as a dict holding its root element, see `http::xml`
+    pub async fn xml(&mut self) -> Result<Value, XmlError> {
+        self.parse_body().await;
+        match &self.request.body {
+            HttpBody::Text(text) => from_xml(text),
+            HttpBody::Binary(bytes) => from_xml(&String::from_utf8_lossy(bytes)),
+            _ => Err(XmlError::new(0, "The body is not an XML document")),
+        }
+    }
+
     /// Get the path by using index
     pub fn get_path(&mut self, part: usize) -> String {
         self.request.meta.get_path(part)
@@ -220,6 +530,123 @@ impl HttpReqCtx {
         self.request.meta.get_url_args(key)
     }
 
+    /// Get every decoded pair of the query string, including repeated keys
+    pub fn query_map(&self) -> QueryMap {
+        QueryMap::from_target(&self.request.meta.url())
+    }
+
+    /// Extract a struct deriving `FromQuery` from the query string.
+    /// The error can be returned from the handler as a 400 Bad Request
+    pub fn query<T: FromQuery>(&self) -> Result<T, QueryErrors> {
+        T::from_query(&self.query_map())
+    }
+
+    /// Extract a struct from the query string and validate it. A query which cannot be
+    /// parsed is a 400 Bad Request, one breaking the rules of the struct is a 422
+    pub fn valid_query<T: FromQuery + Validate>(&self) -> Result<T, ExtractError> {
+        let value = self.query::<T>()?;
+        value.validate()?;
+        Ok(value)
+    }
+
+    /// Bind the url-encoded body of the request to a form, see `BoundForm::bind`. A request
+    /// without such a body gives a form whose required fields are missing
+    pub async fn bind_form<T: Form + FromQuery + Validate>(&mut self) -> BoundForm<T> {
+        let data: QueryMap = self
+            .form_or_default()
+            .await
+            .get_all()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        BoundForm::bind(&data)
+    }
+
+    /// Get the information of the connection the request was received on
+    pub fn connection_info(&self) -> &ConnectionInfo {
+        &self.conn_info
+    }
+
+    /// Get the address of the client. Behind the proxies given to `AppBuilder::trusted_proxies`
+    /// it is read from their forwarding headers, otherwise it is the address of the peer
+    pub fn client_ip(&self) -> Option<std::net::IpAddr> {
+        let peer = self.conn_info.peer_ip()?;
+        Some(match self.app.config().get::<TrustedProxies>() {
+            Some(proxies) => proxies.resolve(peer, &self.request.meta),
+            None => peer,
+        })
+    }
+
+    /// Get a shared state of the application, added with `App::manage`
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.app.state::<T>()
+    }
+
+    /// Get the cache of the application, set with `AppBuilder::cache`
+    pub fn cache(&self) -> Option<crate::cache::SharedCache> {
+        self.app.cache()
+    }
+
+    /// Get the storage of the application, set with `AppBuilder::storage`
+    pub fn storage(&self) -> Option<crate::app::storage::SharedStorage> {
+        self.app.storage()
+    }
+
+    /// Get the instant after which the connection will be closed, if limited
+    pub fn deadline(&self) -> Option<std::time::Instant> {
+        self.conn_info.deadline
+    }
+
+    /// Get the time left before the connection is closed, if limited
+    pub fn time_remaining(&self) -> Option<std::time::Duration> {
+        self.conn_info
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()))
+    }
+
+    /// Get the cancellation token of the request.
+    /// It is cancelled when the client disconnects or the connection times out, clone it
+    /// into spawned tasks or long-running queries so they can stop early
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.conn_info.cancel.clone()
+    }
+
+    /// Whether the request has been cancelled and its response will never be delivered
+    pub fn is_cancelled(&self) -> bool {
+        self.conn_info.cancel.is_cancelled()
+    }
+
+    /// Get the id of the request, taken from the `X-Request-Id` header or generated.
+    /// It is also available through `starberry_core::app::task::current_request_id` while the request is handled
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Run a blocking closure, such as a CPU-heavy computation or a synchronous library call, on the blocking pool.
+    /// The server waits for it before shutting down and the request id is propagated to it
+    pub fn spawn_blocking<F, T>(&self, f: F) -> tokio::task::JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.app.spawn_blocking(f)
+    }
+
+    /// Spawn a background task which may outlive the request, e.g. sending an email.
+    /// The server waits for it before shutting down and the request id is propagated to it
+    pub fn spawn_task<F>(&self, fut: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.app.spawn_task(fut)
+    }
+
+    /// Get the address of the remote peer. Note that behind a reverse proxy this is the proxy's address
+    pub fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.conn_info.peer_addr
+    }
+
     /// Get the preferred by the user
     pub fn get_preferred_language(&mut self) -> Option<String> {
         self.request
@@ -234,6 +661,26 @@ impl HttpReqCtx {
             .unwrap_or_else(|| default.as_ref().to_string())
     }
 
+    /// Get the content coding the client prefers among the ones supported by the server.
+    /// Returns None if the client did not send Accept-Encoding or accepts none of them
+    pub fn get_preferred_encoding(&mut self, supported: &[ContentCoding]) -> Option<ContentCoding> {
+        self.request
+            .meta
+            .get_accept_encoding()
+            .and_then(|accept| accept.preferred(supported))
+    }
+
+    /// Get the compression the client prefers among `ContentCoding::COMPRESSIONS`
+    /// (zstd, br, gzip, deflate)
+    pub fn get_preferred_compression(&mut self) -> Option<ContentCoding> {
+        self.get_preferred_encoding(&ContentCoding::COMPRESSIONS)
+    }
+
+    /// Get the credentials sent in the Authorization header
+    pub fn get_authorization(&mut self) -> Option<Authorization> {
+        self.request.meta.get_authorization()
+    }
+
     /// Get the part of the url by using its given name
     pub fn get_arg<S: AsRef<str>>(&mut self, arg: S) -> Option<String> {
         match self.get_arg_index(arg.as_ref()) {
@@ -268,10 +715,11 @@ impl Rx for HttpReqCtx {
     async fn process(
         app: Arc<App>,
         root_handler: Arc<Url<HttpReqCtx>>,
+        conn_info: ConnectionInfo,
         reader: BufReader<ReadHalf<Connection>>,
         writer: BufWriter<WriteHalf<Connection>>,
     ) {
-        let handler = Self::handle(app, root_handler, reader, writer).await;
+        let handler = Self::handle(app, root_handler, conn_info, reader, writer).await;
         handler.run().await;
     }
 
@@ -313,13 +761,60 @@ impl HttpResCtx {
 
     /// Sends a request to the given host and returns a `HttpResCtx` context.
     /// This function will automatically determine whether to use HTTP or HTTPS based on the host string.
+    /// Sent while a request is handled, it carries the id of that request in `X-Request-Id` and a 
+    /// child span of its trace in `traceparent`, and the exchange is recorded with `trace::record_span`
     pub async fn send_request<T: Into<String>>(
         host: T,
+        mut request: HttpRequest,
+        safety_config: HttpSafety,
+    ) -> Result<HttpResponse, ConnectionError> { 
+        let host_str = host.into();
+        let Some(parent) = trace::current_trace() else {
+            return Self::send_untraced(host_str, request, safety_config).await;
+        };
+        let request_id = task::current_request_id();
+        if let Some(id) = &request_id
+            && request.meta.get_header("x-request-id").is_none()
+        {
+            request.meta.set_attribute("x-request-id", id.clone());
+        }
+        let span = parent.child();
+        if request.meta.get_header("traceparent").is_none() {
+            request.meta.set_attribute("traceparent", span.traceparent());
+            if let Some(state) = &span.state {
+                request.meta.set_attribute("tracestate", state.clone());
+            }
+        }
+        let method = request.meta.method().to_string();
+        let path = request.meta.path();
+        let host = host_str.trim_start_matches("https://").trim_start_matches("http://").to_string();
+        let started_at = SystemTime::now();
+        let start = Instant::now();
+
+        let result = Self::send_untraced(host_str, request, safety_config).await;
+
+        trace::record_span(Span {
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            parent_id: Some(parent.span_id),
+            request_id,
+            method,
+            host,
+            path,
+            status: result.as_ref().ok().map(|response| response.meta.start_line.status_code().into()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            started_at,
+            duration: start.elapsed(),
+        });
+        result
+    }
+
+    async fn send_untraced(
+        host_str: String,
         request: HttpRequest,
         safety_config: HttpSafety,
     ) -> Result<HttpResponse, ConnectionError> { 
         // Test whether the host uses https
-        let host_str = host.into();
         let (is_https, without_scheme) = if host_str.starts_with("https://") {
             (true, host_str.trim_start_matches("https://"))
         } else if host_str.starts_with("http://") {
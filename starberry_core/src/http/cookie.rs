@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+use std::time::SystemTime;
+
+use starberry_lib::date::{format_http_date, parse_http_date};
 
 use crate::http::meta::HeaderValue;
 
@@ -155,6 +158,13 @@ pub struct Cookie{
     pub max_age: Option<String>, 
     pub secure: Option<bool>, 
     pub http_only: Option<bool>, 
+    /// `Strict`, `Lax` or `None`, whether the cookie is sent with cross site requests 
+    pub same_site: Option<String>, 
+    /// Stored apart for each top level site (CHIPS), requires `Secure` 
+    pub partitioned: Option<bool>, 
+    /// Not needed for the site to work, e.g. analytics. It is never sent, but the 
+    /// `CookieConsent` middleware drops it until the client consented 
+    pub non_essential: bool, 
 } 
 
 impl Cookie{ 
@@ -175,6 +185,9 @@ impl Cookie{
             max_age: None, 
             secure: None, 
             http_only: None, 
+            same_site: None, 
+            partitioned: None, 
+            non_essential: false, 
         } 
     } 
 
@@ -241,6 +254,10 @@ impl Cookie{
                 cookie.set_http_only(true);
                 continue;
             }
+            if attr.eq_ignore_ascii_case("Partitioned") {
+                cookie.set_partitioned(true);
+                continue;
+            }
             
             // Parse key=value attributes
             let attr_parts: Vec<&str> = attr.splitn(2, '=').collect();
@@ -253,6 +270,7 @@ impl Cookie{
                     "domain" => cookie.set_domain(attr_value),
                     "expires" => cookie.set_expires(attr_value),
                     "max-age" => cookie.set_max_age(attr_value),
+                    "samesite" => cookie.set_same_site(attr_value),
                     _ => {} // Ignore unknown attributes
                 }
             }
@@ -313,6 +331,16 @@ impl Cookie{
         self.expires = Some(expires.to_string()); 
     } 
 
+    /// Sets the expiry of the cookie to the given time, formatted as an HTTP date 
+    pub fn expires_at(self, time: SystemTime) -> Self { 
+        self.expires(format_http_date(time)) 
+    } 
+
+    /// Gets the expiry of the cookie as a time, if it is set and is a valid HTTP date 
+    pub fn get_expires_time(&self) -> Option<SystemTime> { 
+        self.expires.as_deref().and_then(parse_http_date) 
+    } 
+
     pub fn clear_expires(&mut self) { 
         self.expires = None; 
     } 
@@ -366,6 +394,74 @@ impl Cookie{
         self.http_only = None; 
     } 
 
+    /// Sets whether the cookie is sent with cross site requests: `Strict`, `Lax` or `None`, 
+    /// which browsers only accept with `Secure` 
+    /// 
+    /// # Examples 
+    /// 
+    /// ```rust 
+    /// use starberry_core::http::cookie::Cookie; 
+    /// 
+    /// let cookie = Cookie::new("abc123").path("/").secure(true).same_site("Lax"); 
+    /// assert_eq!(cookie.to_string(), "abc123; Path=/; Secure; SameSite=Lax"); 
+    /// ``` 
+    pub fn same_site<T: ToString>(self, same_site: T) -> Self { 
+        Self { same_site: Some(same_site.to_string()), ..self } 
+    } 
+
+    pub fn get_same_site(&self) -> Option<String> { 
+        self.same_site.clone() 
+    } 
+
+    pub fn set_same_site<T: ToString>(&mut self, same_site: T) { 
+        self.same_site = Some(same_site.to_string()); 
+    } 
+
+    pub fn clear_same_site(&mut self) { 
+        self.same_site = None; 
+    } 
+
+    /// Indicates whether the cookie is partitioned by top level site (CHIPS), so an embedded 
+    /// third party gets a separate jar on each site. Browsers require `Secure` as well 
+    /// 
+    /// # Examples 
+    /// 
+    /// ```rust 
+    /// use starberry_core::http::cookie::Cookie; 
+    /// 
+    /// let cookie = Cookie::new("abc123").path("/").secure(true).partitioned(true); 
+    /// assert_eq!(cookie.to_string(), "abc123; Path=/; Secure; Partitioned"); 
+    /// ``` 
+    pub fn partitioned(self, partitioned: bool) -> Self { 
+        Self { partitioned: Some(partitioned), ..self } 
+    } 
+
+    pub fn get_partitioned(&self) -> Option<bool> { 
+        self.partitioned 
+    } 
+
+    pub fn set_partitioned(&mut self, partitioned: bool) { 
+        self.partitioned = Some(partitioned); 
+    } 
+
+    pub fn clear_partitioned(&mut self) { 
+        self.partitioned = None; 
+    } 
+
+    /// Marks the cookie as not needed for the site to work, e.g. analytics or advertising, 
+    /// so it is only set once the client consented 
+    pub fn non_essential(self) -> Self { 
+        Self { non_essential: true, ..self } 
+    } 
+
+    pub fn is_essential(&self) -> bool { 
+        !self.non_essential 
+    } 
+
+    pub fn set_essential(&mut self, essential: bool) { 
+        self.non_essential = !essential; 
+    } 
+
     /// Returns a string formatted for a Set-Cookie header including all attributes.
     ///
     /// # Returns
@@ -404,6 +500,12 @@ impl Cookie{
                 result.push_str("; HttpOnly"); 
             } 
         } 
+        if let Some(ref same_site) = self.same_site { 
+            result.push_str(&format!("; SameSite={}", same_site)); 
+        } 
+        if let Some(true) = self.partitioned { 
+            result.push_str("; Partitioned"); 
+        } 
         result 
     } 
 
@@ -133,6 +133,27 @@ pub enum ContentCoding {
 }
 
 impl ContentCoding {
+    /// The codings the server can compress responses with, in order of preference
+    /// when the client weights several of them equally.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use starberry_core::http::encoding::{AcceptEncoding, ContentCoding};
+    ///
+    /// let accept = AcceptEncoding::from_string("gzip, br, zstd");
+    /// assert_eq!(accept.preferred(&ContentCoding::COMPRESSIONS), Some(ContentCoding::Zstd));
+    ///
+    /// let accept = AcceptEncoding::from_string("gzip, zstd;q=0.5");
+    /// assert_eq!(accept.preferred(&ContentCoding::COMPRESSIONS), Some(ContentCoding::Gzip));
+    /// ```
+    pub const COMPRESSIONS: [ContentCoding; 4] = [
+        ContentCoding::Zstd,
+        ContentCoding::Brotli,
+        ContentCoding::Gzip,
+        ContentCoding::Deflate,
+    ];
+
     /// Creates a new `ContentCoding` from a string.
     ///
     /// The string is trimmed and converted to lowercase before matching.
@@ -195,6 +216,45 @@ impl ContentCoding {
         }
     } 
 
+    /// Compresses data with this coding. Zstandard uses `ZSTD_DEFAULT_LEVEL`
+    pub fn encode_compressed(encoding: &ContentCoding, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match encoding {
+            ContentCoding::Gzip => compression::compress_gzip(data),
+            ContentCoding::Deflate => compression::compress_deflate(data),
+            ContentCoding::Brotli => compression::compress_brotli(data),
+            ContentCoding::Zstd => compression::compress_zstd(data, compression::ZSTD_DEFAULT_LEVEL),
+            ContentCoding::Compress => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "compress encoding not supported",
+            )),
+            _ => Ok(data.to_vec()), // Identity or unsupported
+        }
+    }
+
+    /// Incremental compressor for this coding, for bodies sent in chunks.
+    /// Returns None for identity, `compress` and unknown codings
+    pub fn stream_encoder(&self) -> Option<compression::StreamEncoder> {
+        match self {
+            ContentCoding::Gzip => Some(compression::StreamEncoder::gzip()),
+            ContentCoding::Deflate => Some(compression::StreamEncoder::deflate()),
+            ContentCoding::Brotli => Some(compression::StreamEncoder::brotli()),
+            ContentCoding::Zstd => compression::StreamEncoder::zstd(compression::ZSTD_DEFAULT_LEVEL).ok(),
+            _ => None,
+        }
+    }
+
+    /// Incremental decompressor for this coding, for bodies received in chunks.
+    /// Returns None for identity, `compress` and unknown codings
+    pub fn stream_decoder(&self) -> Option<compression::StreamDecoder> {
+        match self {
+            ContentCoding::Gzip => Some(compression::StreamDecoder::gzip()),
+            ContentCoding::Deflate => Some(compression::StreamDecoder::deflate()),
+            ContentCoding::Brotli => Some(compression::StreamDecoder::brotli()),
+            ContentCoding::Zstd => compression::StreamDecoder::zstd().ok(),
+            _ => None,
+        }
+    }
+
     pub fn decode_compressed(encoding: &ContentCoding, data: &[u8]) -> std::io::Result<Vec<u8>> {
         match encoding {
             ContentCoding::Gzip => compression::decompress_gzip(data),
@@ -625,3 +685,161 @@ impl HttpEncoding {
         &self.content
     }
 } 
+
+/// Represents the `Accept-Encoding` request header, a list of content codings
+/// with their quality values (q-values).
+///
+/// The special tokens `identity` and `*` are kept as `ContentCoding::Other`
+/// entries and are taken into account when computing weights.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AcceptEncoding {
+    codings: Vec<(ContentCoding, f32)>,
+}
+
+impl AcceptEncoding {
+    /// Parses an `Accept-Encoding` header string.
+    ///
+    /// Empty entries are skipped, a missing or invalid q-value defaults to 1.0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use starberry_core::http::encoding::{AcceptEncoding, ContentCoding};
+    ///
+    /// let accept = AcceptEncoding::from_string("gzip, br;q=0.8, *;q=0");
+    /// assert_eq!(accept.get_weight(&ContentCoding::Gzip), 1.0);
+    /// assert_eq!(accept.get_weight(&ContentCoding::Brotli), 0.8);
+    /// assert_eq!(accept.get_weight(&ContentCoding::Zstd), 0.0);
+    /// ```
+    pub fn from_string<S: AsRef<str>>(s: S) -> Self {
+        let mut codings = Vec::new();
+
+        for part in s.as_ref().split(',') {
+            let mut parts = part.splitn(2, ';');
+            let name = parts.next().unwrap_or("").trim();
+            if name.is_empty() {
+                continue;
+            }
+
+            // Default weight = 1.0
+            let mut weight = 1.0;
+
+            // Parse q-value if exists
+            if let Some(q_str) = parts.next().and_then(|q| q.trim().strip_prefix("q=")) {
+                weight = q_str.trim().parse::<f32>().unwrap_or(1.0).clamp(0.0, 1.0);
+            }
+
+            codings.push((ContentCoding::from_string(name), weight));
+        }
+
+        Self { codings }
+    }
+
+    /// Returns all listed codings with their weights, in header order.
+    pub fn codings(&self) -> &[(ContentCoding, f32)] {
+        &self.codings
+    }
+
+    /// Returns `true` if the header did not list any coding.
+    pub fn is_empty(&self) -> bool {
+        self.codings.is_empty()
+    }
+
+    /// Gets the weight the client assigned to a content coding.
+    ///
+    /// An explicitly listed coding wins over the `*` wildcard. `identity` is
+    /// acceptable with weight 1.0 unless it is excluded explicitly or through `*;q=0`.
+    ///
+    /// # Returns
+    ///
+    /// The q-value of the coding, 0.0 if the coding is not acceptable
+    pub fn get_weight(&self, coding: &ContentCoding) -> f32 {
+        if let Some((_, w)) = self.codings.iter().find(|(c, _)| c == coding) {
+            return *w;
+        }
+        if let Some((_, w)) = self.codings.iter().find(|(c, _)| c.as_str() == "*") {
+            return *w;
+        }
+        if coding.as_str() == "identity" {
+            return 1.0;
+        }
+        0.0
+    }
+
+    /// Checks whether a content coding is acceptable (weight above zero).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use starberry_core::http::encoding::{AcceptEncoding, ContentCoding};
+    ///
+    /// let accept = AcceptEncoding::from_string("gzip;q=0, deflate");
+    /// assert!(!accept.accepts(&ContentCoding::Gzip));
+    /// assert!(accept.accepts(&ContentCoding::Deflate));
+    /// ```
+    pub fn accepts(&self, coding: &ContentCoding) -> bool {
+        self.get_weight(coding) > 0.0
+    }
+
+    /// Picks the best coding among the ones supported by the server.
+    ///
+    /// The coding with the highest weight wins, ties are broken by the order of
+    /// `supported`. Returns `None` if none of them is acceptable, in which case
+    /// the response should be sent without content coding.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use starberry_core::http::encoding::{AcceptEncoding, ContentCoding};
+    ///
+    /// let accept = AcceptEncoding::from_string("gzip;q=0.5, br");
+    /// let supported = [ContentCoding::Zstd, ContentCoding::Gzip, ContentCoding::Brotli];
+    /// assert_eq!(accept.preferred(&supported), Some(ContentCoding::Brotli));
+    ///
+    /// let accept = AcceptEncoding::from_string("deflate");
+    /// assert_eq!(accept.preferred(&supported), None);
+    /// ```
+    pub fn preferred(&self, supported: &[ContentCoding]) -> Option<ContentCoding> {
+        let mut best: Option<(&ContentCoding, f32)> = None;
+        for coding in supported {
+            let weight = self.get_weight(coding);
+            if weight > 0.0 && best.is_none_or(|(_, w)| weight > w) {
+                best = Some((coding, weight));
+            }
+        }
+        best.map(|(c, _)| c.clone())
+    }
+
+    /// Adds a coding with the given weight (maintains insertion order).
+    pub fn add_coding(&mut self, coding: ContentCoding, weight: f32) {
+        self.codings.push((coding, weight));
+    }
+
+    /// Serializes to `Accept-Encoding` header format, omitting q-values of 1.0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use starberry_core::http::encoding::AcceptEncoding;
+    ///
+    /// let accept = AcceptEncoding::from_string("gzip;q=1.0, br;q=0.500");
+    /// assert_eq!(accept.to_header_string(), "gzip, br;q=0.5");
+    /// ```
+    pub fn to_header_string(&self) -> String {
+        self.codings
+            .iter()
+            .map(|(coding, weight)| {
+                if (weight - 1.0).abs() < f32::EPSILON {
+                    coding.as_str().to_string()
+                } else {
+                    let weight_str = format!("{:.3}", weight)
+                        .trim_end_matches('0')
+                        .trim_end_matches('.')
+                        .to_string();
+                    format!("{};q={}", coding.as_str(), weight_str)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
@@ -0,0 +1,229 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use akari::Value;
+
+use super::http_value::StatusCode;
+use super::response::HttpResponse;
+use super::response::response_templates::{normal_response, return_status};
+
+/// Conversion of a handler's output into an `HttpResponse`.
+/// The `#[url]` macro uses this to turn the `Err` branch of a handler returning
+/// `Result<HttpResponse, E>` into an error response.
+pub trait IntoResponse {
+    fn into_response(self) -> HttpResponse;
+}
+
+impl IntoResponse for HttpResponse {
+    fn into_response(self) -> HttpResponse {
+        self
+    }
+}
+
+impl IntoResponse for StatusCode {
+    fn into_response(self) -> HttpResponse {
+        return_status(self)
+    }
+}
+
+/// A plain string is sent as a 500 Internal Server Error with the string as body
+impl IntoResponse for String {
+    fn into_response(self) -> HttpResponse {
+        normal_response(StatusCode::INTERNAL_SERVER_ERROR, self)
+    }
+}
+
+impl IntoResponse for &str {
+    fn into_response(self) -> HttpResponse {
+        self.to_string().into_response()
+    }
+}
+
+impl<B: Into<Vec<u8>>> IntoResponse for (StatusCode, B) {
+    fn into_response(self) -> HttpResponse {
+        normal_response(self.0, self.1)
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> HttpResponse {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(err) => err.into_response(),
+        }
+    }
+}
+
+/// The error type of the framework for handlers.
+/// It carries the status code to respond with and a message sent as the body.
+///
+/// # Example
+/// ```rust
+/// use starberry_core::http::error::{HttpError, IntoResponse};
+/// use starberry_core::http::http_value::StatusCode;
+///
+/// fn find(id: u32) -> Result<String, HttpError> {
+///     if id == 0 {
+///         return Err(HttpError::not_found("No such item"));
+///     }
+///     Ok(format!("Item {}", id))
+/// }
+///
+/// let response = find(0).unwrap_err().into_response();
+/// assert_eq!(response.meta.start_line.status_code(), StatusCode::NOT_FOUND);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl HttpError {
+    pub fn new<T: Into<String>>(status: StatusCode, message: T) -> Self {
+        Self { status, message: message.into() }
+    }
+
+    pub fn bad_request<T: Into<String>>(message: T) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn unauthorized<T: Into<String>>(message: T) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    pub fn forbidden<T: Into<String>>(message: T) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    pub fn not_found<T: Into<String>>(message: T) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn internal<T: Into<String>>(message: T) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> HttpResponse {
+        normal_response(self.status, self.message)
+    }
+}
+
+/// A missing file is a 404 and a denied one a 403. The text of the error names paths of the
+/// server, so it is logged and the client only gets a generic message
+impl From<std::io::Error> for HttpError {
+    fn from(err: std::io::Error) -> Self {
+        eprintln!("I/O error in a handler: {}", err);
+        match err.kind() {
+            std::io::ErrorKind::NotFound => Self::not_found("Not Found"),
+            std::io::ErrorKind::PermissionDenied => Self::forbidden("Forbidden"),
+            _ => Self::internal("Internal Server Error"),
+        }
+    }
+}
+
+/// Names containing one of these are treated as secrets in error reports
+const SECRET_HINTS: [&str; 7] = ["secret", "password", "token", "key", "credential", "auth", "cookie"];
+
+const REDACTED: &str = "[redacted]";
+
+/// What was known about a request when it ended with an error, for error pages and error logs.
+/// Params are listed by type name only. Locals holding a string, a number or a bool show their
+/// value unless their key looks secret, other locals only show their key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub request_id: String,
+    pub method: String,
+    pub path: String,
+    /// The pattern of the matched route, e.g. `/user/{id}`
+    pub route: String,
+    pub status: u16,
+    /// The type names of the params of the request, sorted
+    pub params: Vec<String>,
+    /// The keys of the locals of the request with their shown value, sorted by key
+    pub locals: Vec<(String, String)>,
+    /// The time since the request was read
+    pub elapsed: Duration,
+}
+
+impl ErrorContext {
+    /// Describes a local as shown in the report
+    pub fn describe_local(key: &str, value: &(dyn Any + Send + Sync)) -> String {
+        let lower = key.to_ascii_lowercase();
+        if SECRET_HINTS.iter().any(|hint| lower.contains(hint)) {
+            return REDACTED.to_string();
+        }
+        if let Some(value) = value.downcast_ref::<String>() {
+            return value.clone();
+        }
+        if let Some(value) = value.downcast_ref::<&'static str>() {
+            return value.to_string();
+        }
+        if let Some(value) = value.downcast_ref::<bool>() {
+            return value.to_string();
+        }
+        macro_rules! numbers {
+            ($($ty:ty),*) => {
+                $(if let Some(value) = value.downcast_ref::<$ty>() {
+                    return value.to_string();
+                })*
+            };
+        }
+        numbers!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+        "[not shown]".to_string()
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut map = HashMap::new();
+        map.insert("request_id".to_string(), Value::Str(self.request_id.clone()));
+        map.insert("method".to_string(), Value::Str(self.method.clone()));
+        map.insert("path".to_string(), Value::Str(self.path.clone()));
+        map.insert("route".to_string(), Value::Str(self.route.clone()));
+        map.insert("status".to_string(), Value::Str(self.status.to_string()));
+        map.insert(
+            "params".to_string(),
+            Value::List(self.params.iter().map(|name| Value::Str(name.clone())).collect()),
+        );
+        map.insert(
+            "locals".to_string(),
+            Value::Dict(self.locals.iter().map(|(key, value)| (key.clone(), Value::Str(value.clone()))).collect()),
+        );
+        map.insert("elapsed_ms".to_string(), Value::Str(self.elapsed.as_millis().to_string()));
+        Value::Dict(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locals_are_redacted() {
+        assert_eq!(ErrorContext::describe_local("tenant", &"acme".to_string()), "acme");
+        assert_eq!(ErrorContext::describe_local("retries", &3u32), "3");
+        assert_eq!(ErrorContext::describe_local("session_token", &"abc".to_string()), REDACTED);
+        assert_eq!(ErrorContext::describe_local("API_KEY", &42i64), REDACTED);
+        assert_eq!(ErrorContext::describe_local("cart", &vec![1, 2]), "[not shown]");
+    }
+
+    #[test]
+    fn io_errors_hide_their_text() {
+        let err = HttpError::from(std::io::Error::new(std::io::ErrorKind::NotFound, "/srv/app/missing.txt"));
+        assert_eq!((err.status, err.message.as_str()), (StatusCode::NOT_FOUND, "Not Found"));
+        let err = HttpError::from(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+        let err = HttpError::from(std::io::Error::other("disk on fire at /dev/sda"));
+        assert_eq!((err.status, err.message.as_str()), (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"));
+    }
+}
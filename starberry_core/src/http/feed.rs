@@ -0,0 +1,342 @@
+//! RSS 2.0 and Atom feeds.
+//!
+//! A `Feed` is built from its channel details and an iterator of `FeedItem`s, and written
+//! as RSS with `rss_response` or as Atom with `atom_response` from `response_templates`,
+//! with their content types.
+//! Dates are written in the format of each standard, RFC 2822 for RSS and RFC 3339 for Atom.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[url(reg![&APP, LitUrl("feed.xml")])]
+//! async fn feed() -> HttpResponse {
+//!     let feed = Feed::new("Blog", "https://example.com/", "The latest posts")
+//!         .with_self_link("https://example.com/feed.xml")
+//!         .items(posts().iter().map(|post| {
+//!             FeedItem::new(&post.title, format!("https://example.com/posts/{}", post.slug))
+//!                 .with_summary(&post.summary)
+//!                 .published(post.published_at)
+//!         }));
+//!     rss_response(&feed)
+//! }
+//! ```
+
+use std::time::SystemTime;
+
+use starberry_lib::date::{format_email_date, format_rfc3339};
+
+use super::xml::{XML_DECLARATION, escape_xml};
+
+/// The media type of RSS documents
+pub const RSS_CONTENT_TYPE: &str = "application/rss+xml";
+
+/// The media type of Atom documents
+pub const ATOM_CONTENT_TYPE: &str = "application/atom+xml";
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+const DUBLIN_CORE_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+/// An entry of a feed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: String,
+    /// A permanent identifier of the item, its link by default
+    pub id: Option<String>,
+    /// A plain text summary
+    pub summary: Option<String>,
+    /// The full content, as HTML
+    pub content: Option<String>,
+    pub author: Option<String>,
+    pub published: Option<SystemTime>,
+    pub updated: Option<SystemTime>,
+    pub categories: Vec<String>,
+}
+
+impl FeedItem {
+    pub fn new<T: Into<String>, L: Into<String>>(title: T, link: L) -> Self {
+        Self {
+            title: title.into(),
+            link: link.into(),
+            id: None,
+            summary: None,
+            content: None,
+            author: None,
+            published: None,
+            updated: None,
+            categories: Vec::new(),
+        }
+    }
+
+    pub fn with_id<T: Into<String>>(mut self, id: T) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_summary<T: Into<String>>(mut self, summary: T) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    /// Sets the full content of the item, as HTML
+    pub fn with_content<T: Into<String>>(mut self, html: T) -> Self {
+        self.content = Some(html.into());
+        self
+    }
+
+    pub fn with_author<T: Into<String>>(mut self, author: T) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn published(mut self, time: SystemTime) -> Self {
+        self.published = Some(time);
+        self
+    }
+
+    pub fn updated(mut self, time: SystemTime) -> Self {
+        self.updated = Some(time);
+        self
+    }
+
+    pub fn category<T: Into<String>>(mut self, category: T) -> Self {
+        self.categories.push(category.into());
+        self
+    }
+
+    fn last_change(&self) -> Option<SystemTime> {
+        self.updated.or(self.published)
+    }
+}
+
+/// A feed, written as RSS 2.0 or Atom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feed {
+    pub title: String,
+    /// The url of the site the feed belongs to
+    pub link: String,
+    pub description: String,
+    /// The url of the feed itself
+    pub self_link: Option<String>,
+    /// A permanent identifier of the feed, the self link or the link by default
+    pub id: Option<String>,
+    pub language: Option<String>,
+    pub author: Option<String>,
+    /// The time of the last change, the latest change of the items by default
+    pub updated: Option<SystemTime>,
+    pub items: Vec<FeedItem>,
+}
+
+impl Feed {
+    pub fn new<T: Into<String>, L: Into<String>, D: Into<String>>(title: T, link: L, description: D) -> Self {
+        Self {
+            title: title.into(),
+            link: link.into(),
+            description: description.into(),
+            self_link: None,
+            id: None,
+            language: None,
+            author: None,
+            updated: None,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn with_self_link<T: Into<String>>(mut self, url: T) -> Self {
+        self.self_link = Some(url.into());
+        self
+    }
+
+    pub fn with_id<T: Into<String>>(mut self, id: T) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the language of the feed, e.g. `en-us`
+    pub fn with_language<T: Into<String>>(mut self, language: T) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    pub fn with_author<T: Into<String>>(mut self, author: T) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn updated(mut self, time: SystemTime) -> Self {
+        self.updated = Some(time);
+        self
+    }
+
+    pub fn item(mut self, item: FeedItem) -> Self {
+        self.items.push(item);
+        self
+    }
+
+    pub fn items<I: IntoIterator<Item = FeedItem>>(mut self, items: I) -> Self {
+        self.items.extend(items);
+        self
+    }
+
+    /// The time of the last change of the feed, now if nothing tells it
+    fn last_change(&self) -> SystemTime {
+        self.updated
+            .or_else(|| self.items.iter().filter_map(FeedItem::last_change).max())
+            .unwrap_or_else(SystemTime::now)
+    }
+
+    /// The feed as an RSS 2.0 document
+    pub fn to_rss(&self) -> String {
+        let mut out = String::from(XML_DECLARATION);
+        out.push_str(&format!(
+            "<rss version=\"2.0\" xmlns:atom=\"{}\" xmlns:dc=\"{}\"><channel>",
+            ATOM_NAMESPACE, DUBLIN_CORE_NAMESPACE
+        ));
+        push_element(&mut out, "title", &self.title);
+        push_element(&mut out, "link", &self.link);
+        push_element(&mut out, "description", &self.description);
+        if let Some(self_link) = &self.self_link {
+            out.push_str(&format!(
+                "<atom:link href=\"{}\" rel=\"self\" type=\"{}\"/>",
+                escape_xml(self_link),
+                RSS_CONTENT_TYPE
+            ));
+        }
+        if let Some(language) = &self.language {
+            push_element(&mut out, "language", language);
+        }
+        if let Some(author) = &self.author {
+            push_element(&mut out, "dc:creator", author);
+        }
+        push_element(&mut out, "lastBuildDate", &format_email_date(self.last_change()));
+        for item in &self.items {
+            out.push_str("<item>");
+            push_element(&mut out, "title", &item.title);
+            push_element(&mut out, "link", &item.link);
+            match &item.id {
+                Some(id) if *id != item.link => {
+                    out.push_str(&format!("<guid isPermaLink=\"false\">{}</guid>", escape_xml(id)))
+                }
+                _ => out.push_str(&format!("<guid isPermaLink=\"true\">{}</guid>", escape_xml(&item.link))),
+            }
+            if let Some(description) = item.content.as_ref().or(item.summary.as_ref()) {
+                push_element(&mut out, "description", description);
+            }
+            if let Some(author) = &item.author {
+                push_element(&mut out, "dc:creator", author);
+            }
+            for category in &item.categories {
+                push_element(&mut out, "category", category);
+            }
+            if let Some(published) = item.published.or(item.updated) {
+                push_element(&mut out, "pubDate", &format_email_date(published));
+            }
+            out.push_str("</item>");
+        }
+        out.push_str("</channel></rss>");
+        out
+    }
+
+    /// The feed as an Atom document. Atom requires an author, the title of the feed
+    /// is used when neither the feed nor an item has one
+    pub fn to_atom(&self) -> String {
+        let updated = self.last_change();
+        let mut out = String::from(XML_DECLARATION);
+        out.push_str(&format!("<feed xmlns=\"{}\">", ATOM_NAMESPACE));
+        push_element(&mut out, "title", &self.title);
+        push_element(&mut out, "subtitle", &self.description);
+        let id = self.id.as_ref().or(self.self_link.as_ref()).unwrap_or(&self.link);
+        push_element(&mut out, "id", id);
+        out.push_str(&format!("<link href=\"{}\" rel=\"alternate\"/>", escape_xml(&self.link)));
+        if let Some(self_link) = &self.self_link {
+            out.push_str(&format!(
+                "<link href=\"{}\" rel=\"self\" type=\"{}\"/>",
+                escape_xml(self_link),
+                ATOM_CONTENT_TYPE
+            ));
+        }
+        push_element(&mut out, "updated", &format_rfc3339(updated));
+        let every_item_has_author = !self.items.is_empty() && self.items.iter().all(|item| item.author.is_some());
+        match &self.author {
+            Some(author) => push_author(&mut out, author),
+            None if !every_item_has_author => push_author(&mut out, &self.title),
+            None => {}
+        }
+        for item in &self.items {
+            out.push_str("<entry>");
+            push_element(&mut out, "title", &item.title);
+            push_element(&mut out, "id", item.id.as_ref().unwrap_or(&item.link));
+            out.push_str(&format!("<link href=\"{}\" rel=\"alternate\"/>", escape_xml(&item.link)));
+            push_element(&mut out, "updated", &format_rfc3339(item.last_change().unwrap_or(updated)));
+            if let Some(published) = item.published {
+                push_element(&mut out, "published", &format_rfc3339(published));
+            }
+            if let Some(author) = &item.author {
+                push_author(&mut out, author);
+            }
+            for category in &item.categories {
+                out.push_str(&format!("<category term=\"{}\"/>", escape_xml(category)));
+            }
+            if let Some(summary) = &item.summary {
+                push_element(&mut out, "summary", summary);
+            }
+            if let Some(content) = &item.content {
+                out.push_str(&format!("<content type=\"html\">{}</content>", escape_xml(content)));
+            }
+            out.push_str("</entry>");
+        }
+        out.push_str("</feed>");
+        out
+    }
+
+}
+
+fn push_element(out: &mut String, name: &str, text: &str) {
+    out.push_str(&format!("<{}>{}</{}>", name, escape_xml(text), name));
+}
+
+fn push_author(out: &mut String, name: &str) {
+    out.push_str(&format!("<author><name>{}</name></author>", escape_xml(name)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::http::response::response_templates::atom_response;
+    use crate::http::xml::from_xml;
+
+    fn feed() -> Feed {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        Feed::new("Blog & news", "https://example.com/", "Posts")
+            .with_self_link("https://example.com/feed.xml")
+            .items([
+                FeedItem::new("First", "https://example.com/1").with_summary("<b>hi</b>").published(time),
+                FeedItem::new("Second", "https://example.com/2").with_id("urn:post:2").category("rust"),
+            ])
+    }
+
+    #[test]
+    fn writes_rss() {
+        let rss = feed().to_rss();
+        assert!(from_xml(&rss).is_ok());
+        assert!(rss.contains("<title>Blog &amp; news</title>"));
+        assert!(rss.contains("<description>&lt;b&gt;hi&lt;/b&gt;</description>"));
+        assert!(rss.contains("<pubDate>Sun, 6 Nov 1994 08:49:37 +0000</pubDate>"));
+        assert!(rss.contains("<lastBuildDate>Sun, 6 Nov 1994 08:49:37 +0000</lastBuildDate>"));
+        assert!(rss.contains("<guid isPermaLink=\"false\">urn:post:2</guid>"));
+    }
+
+    #[test]
+    fn writes_atom() {
+        let atom = feed().to_atom();
+        assert!(from_xml(&atom).is_ok());
+        assert!(atom.contains("<id>https://example.com/feed.xml</id>"));
+        assert!(atom.contains("<updated>1994-11-06T08:49:37Z</updated>"));
+        assert!(atom.contains("<author><name>Blog &amp; news</name></author>"));
+        assert!(atom.contains("<category term=\"rust\"/>"));
+
+        let mut response = atom_response(&feed());
+        assert_eq!(response.meta.get_content_type().unwrap().to_string(), ATOM_CONTENT_TYPE);
+    }
+}
@@ -481,6 +481,12 @@ impl MultiFormFieldFile{
     pub fn data(&self) -> &[u8] { 
         &self.data 
     } 
+
+    /// The type of the file detected from its bytes, e.g. `image/png`, unlike `content_type` 
+    /// which is given by the client. `None` for formats without a signature, see `sniff` 
+    pub fn sniffed_type(&self) -> Option<&'static str> { 
+        super::sniff::sniff(&self.data) 
+    } 
 } 
 
 impl Default for MultiFormFieldFile { 
@@ -3,6 +3,8 @@
 
 use std::{collections::HashMap, hash::Hash}; 
 use starberry_lib::url_encoding::*; 
+use starberry_lib::encoding::{base64_decode, base64_encode}; 
+pub use starberry_lib::date::{format_http_date, parse_http_date}; 
 
 #[derive(Debug, Clone)]  
 pub enum HttpVersion { 
@@ -133,7 +135,12 @@ impl PartialEq<HttpMethod> for &HttpMethod {
 /// This enum includes the most common HTTP status codes and provides methods
 /// to check status code categories, convert between numeric and string representations,
 /// and perform other common operations.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///
+/// Status codes without a named constant (e.g. 499 or vendor specific codes) are
+/// represented as `CUSTOM(code)`, so statuses received from upstream servers are
+/// forwarded unchanged.
+#[derive(Debug, Clone)]
+#[repr(u16)]
 pub enum StatusCode {
     // 1xx - Informational
     CONTINUE = 100,
@@ -209,6 +216,9 @@ pub enum StatusCode {
 
     // Unknown status code
     UNKNOWN = 0,
+
+    // Any other status code, e.g. 499 or a vendor specific code
+    CUSTOM(u16),
 }
 
 impl StatusCode {
@@ -221,11 +231,78 @@ impl StatusCode {
     /// # Examples
     ///
     /// ```
-    /// let code = StatusCode::OK;
-    /// assert_eq!(code.as_u16(), 200);
+    /// use starberry_core::http::http_value::StatusCode;
+    ///
+    /// assert_eq!(StatusCode::OK.as_u16(), 200);
+    /// assert_eq!(StatusCode::CUSTOM(499).as_u16(), 499);
     /// ```
     pub fn as_u16(&self) -> u16 {
-        self.clone() as u16
+        match self {
+            StatusCode::CONTINUE => 100,
+            StatusCode::SWITCHING_PROTOCOLS => 101,
+            StatusCode::PROCESSING => 102,
+            StatusCode::EARLY_HINTS => 103,
+            StatusCode::OK => 200,
+            StatusCode::CREATED => 201,
+            StatusCode::ACCEPTED => 202,
+            StatusCode::NON_AUTHORITATIVE_INFORMATION => 203,
+            StatusCode::NO_CONTENT => 204,
+            StatusCode::RESET_CONTENT => 205,
+            StatusCode::PARTIAL_CONTENT => 206,
+            StatusCode::MULTI_STATUS => 207,
+            StatusCode::ALREADY_REPORTED => 208,
+            StatusCode::IM_USED => 226,
+            StatusCode::MULTIPLE_CHOICES => 300,
+            StatusCode::MOVED_PERMANENTLY => 301,
+            StatusCode::FOUND => 302,
+            StatusCode::SEE_OTHER => 303,
+            StatusCode::NOT_MODIFIED => 304,
+            StatusCode::USE_PROXY => 305,
+            StatusCode::TEMPORARY_REDIRECT => 307,
+            StatusCode::PERMANENT_REDIRECT => 308,
+            StatusCode::BAD_REQUEST => 400,
+            StatusCode::UNAUTHORIZED => 401,
+            StatusCode::PAYMENT_REQUIRED => 402,
+            StatusCode::FORBIDDEN => 403,
+            StatusCode::NOT_FOUND => 404,
+            StatusCode::METHOD_NOT_ALLOWED => 405,
+            StatusCode::NOT_ACCEPTABLE => 406,
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED => 407,
+            StatusCode::REQUEST_TIMEOUT => 408,
+            StatusCode::CONFLICT => 409,
+            StatusCode::GONE => 410,
+            StatusCode::LENGTH_REQUIRED => 411,
+            StatusCode::PRECONDITION_FAILED => 412,
+            StatusCode::PAYLOAD_TOO_LARGE => 413,
+            StatusCode::URI_TOO_LONG => 414,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => 415,
+            StatusCode::RANGE_NOT_SATISFIABLE => 416,
+            StatusCode::EXPECTATION_FAILED => 417,
+            StatusCode::IM_A_TEAPOT => 418,
+            StatusCode::MISDIRECTED_REQUEST => 421,
+            StatusCode::UNPROCESSABLE_ENTITY => 422,
+            StatusCode::LOCKED => 423,
+            StatusCode::FAILED_DEPENDENCY => 424,
+            StatusCode::TOO_EARLY => 425,
+            StatusCode::UPGRADE_REQUIRED => 426,
+            StatusCode::PRECONDITION_REQUIRED => 428,
+            StatusCode::TOO_MANY_REQUESTS => 429,
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE => 431,
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS => 451,
+            StatusCode::INTERNAL_SERVER_ERROR => 500,
+            StatusCode::NOT_IMPLEMENTED => 501,
+            StatusCode::BAD_GATEWAY => 502,
+            StatusCode::SERVICE_UNAVAILABLE => 503,
+            StatusCode::GATEWAY_TIMEOUT => 504,
+            StatusCode::HTTP_VERSION_NOT_SUPPORTED => 505,
+            StatusCode::VARIANT_ALSO_NEGOTIATES => 506,
+            StatusCode::INSUFFICIENT_STORAGE => 507,
+            StatusCode::LOOP_DETECTED => 508,
+            StatusCode::NOT_EXTENDED => 510,
+            StatusCode::NETWORK_AUTHENTICATION_REQUIRED => 511,
+            StatusCode::UNKNOWN => 0,
+            StatusCode::CUSTOM(code) => *code,
+        }
     }
 
     /// Returns a string representation of the status code.
@@ -310,6 +387,7 @@ impl StatusCode {
             StatusCode::NETWORK_AUTHENTICATION_REQUIRED => "511 Network Authentication Required",
             
             StatusCode::UNKNOWN => "0 Unknown",
+            StatusCode::CUSTOM(code) => return code.to_string(),
         }.to_string()
     }
 
@@ -317,7 +395,7 @@ impl StatusCode {
     ///
     /// # Returns
     ///
-    /// The reason phrase part of the status code.
+    /// The reason phrase part of the status code. Custom status codes have an empty reason phrase.
     pub fn reason_phrase(&self) -> &'static str {
         match self {
             StatusCode::CONTINUE => "Continue",
@@ -388,6 +466,7 @@ impl StatusCode {
             StatusCode::NETWORK_AUTHENTICATION_REQUIRED => "Network Authentication Required",
             
             StatusCode::UNKNOWN => "Unknown",
+            StatusCode::CUSTOM(_) => "",
         }
     }
 
@@ -399,7 +478,18 @@ impl StatusCode {
     ///
     /// # Returns
     ///
-    /// The corresponding StatusCode enum value, or UNKNOWN for unsupported codes.
+    /// The corresponding StatusCode enum value. Three digit codes without a named
+    /// constant are kept as `CUSTOM`, any other value gives UNKNOWN.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use starberry_core::http::http_value::StatusCode;
+    ///
+    /// assert_eq!(StatusCode::from_u16(404), StatusCode::NOT_FOUND);
+    /// assert_eq!(StatusCode::from_u16(499), StatusCode::CUSTOM(499));
+    /// assert_eq!(StatusCode::from_u16(42), StatusCode::UNKNOWN);
+    /// ```
     pub fn from_u16(code: u16) -> Self {
         match code {
             100 => StatusCode::CONTINUE,
@@ -469,6 +559,7 @@ impl StatusCode {
             510 => StatusCode::NOT_EXTENDED,
             511 => StatusCode::NETWORK_AUTHENTICATION_REQUIRED,
             
+            _ if (100..=999).contains(&code) => StatusCode::CUSTOM(code),
             _ => StatusCode::UNKNOWN,
         }
     }
@@ -666,6 +757,22 @@ impl StatusCode {
     }
 }
 
+// Status codes are compared by their numeric value, so that `CUSTOM(404)` and
+// `NOT_FOUND` are considered the same status
+impl PartialEq for StatusCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_u16() == other.as_u16()
+    }
+}
+
+impl Eq for StatusCode {}
+
+impl Hash for StatusCode {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_u16().hash(state);
+    }
+}
+
 impl std::fmt::Display for StatusCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.to_string())
@@ -1622,3 +1729,944 @@ impl AcceptLang {
         self.most_preferred() 
     }  
 }
+
+/// Represents the credentials carried by an `Authorization` header.
+///
+/// The `Basic` scheme is decoded from standard base64 (RFC 7617), the `Bearer`
+/// scheme keeps the token as is (RFC 6750). Any other scheme is kept untouched.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::Authorization;
+///
+/// let auth = Authorization::parse("Basic dXNlcjpwYTpzcw==").unwrap();
+/// assert_eq!(auth, Authorization::basic("user", "pa:ss"));
+///
+/// let auth = Authorization::parse("bearer abc.def").unwrap();
+/// assert_eq!(auth.bearer_token(), Some("abc.def"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Authorization {
+    /// Basic authentication with decoded user id and password
+    Basic { user: String, pass: String },
+    /// Bearer token authentication
+    Bearer(String),
+    /// Any other authentication scheme with its raw credentials
+    Other { scheme: String, credentials: String },
+}
+
+impl Authorization {
+    /// Creates Basic credentials from a user id and password
+    pub fn basic<U: Into<String>, P: Into<String>>(user: U, pass: P) -> Self {
+        Self::Basic { user: user.into(), pass: pass.into() }
+    }
+
+    /// Creates Bearer credentials from a token
+    pub fn bearer<T: Into<String>>(token: T) -> Self {
+        Self::Bearer(token.into())
+    }
+
+    /// Parses an `Authorization` header value.
+    ///
+    /// The scheme is matched case-insensitively.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the value is empty, if a Bearer token is missing, or if Basic
+    /// credentials are not valid base64 encoded `user:pass` UTF-8 text.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (scheme, credentials) = match value.split_once(' ') {
+            Some((scheme, credentials)) => (scheme, credentials.trim()),
+            None => (value, ""),
+        };
+        if scheme.is_empty() {
+            return None;
+        }
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = base64_decode(credentials).ok()?;
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (user, pass) = decoded.split_once(':')?;
+            Some(Self::basic(user, pass))
+        } else if scheme.eq_ignore_ascii_case("bearer") {
+            if credentials.is_empty() {
+                return None;
+            }
+            Some(Self::bearer(credentials))
+        } else {
+            Some(Self::Other { scheme: scheme.to_string(), credentials: credentials.to_string() })
+        }
+    }
+
+    /// Returns the authentication scheme name
+    pub fn scheme(&self) -> &str {
+        match self {
+            Self::Basic { .. } => "Basic",
+            Self::Bearer(_) => "Bearer",
+            Self::Other { scheme, .. } => scheme,
+        }
+    }
+
+    /// Returns the token if this is Bearer authentication
+    pub fn bearer_token(&self) -> Option<&str> {
+        match self {
+            Self::Bearer(token) => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Returns the user id and password if this is Basic authentication
+    pub fn basic_credentials(&self) -> Option<(&str, &str)> {
+        match self {
+            Self::Basic { user, pass } => Some((user, pass)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Authorization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Basic { user, pass } => {
+                write!(f, "Basic {}", base64_encode(format!("{}:{}", user, pass)))
+            }
+            Self::Bearer(token) => write!(f, "Bearer {}", token),
+            Self::Other { scheme, credentials } if credentials.is_empty() => write!(f, "{}", scheme),
+            Self::Other { scheme, credentials } => write!(f, "{} {}", scheme, credentials),
+        }
+    }
+}
+
+/// Represents the directives of a `Cache-Control` header.
+///
+/// Well-known directives are exposed as fields, any other directive is kept in
+/// `extensions` so that it is preserved when the header is serialized again.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::CacheControl;
+///
+/// let cc = CacheControl::parse("public, max-age=3600, stale-while-revalidate=60");
+/// assert!(cc.public);
+/// assert_eq!(cc.max_age, Some(3600));
+/// assert_eq!(cc.stale_while_revalidate, Some(60));
+///
+/// let cc = CacheControl::new().with_private().with_max_age(0).with_no_store();
+/// assert_eq!(cc.to_string(), "no-store, private, max-age=0");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheControl {
+    /// `max-age=<seconds>`
+    pub max_age: Option<u64>,
+    /// `s-maxage=<seconds>`, for shared caches
+    pub s_maxage: Option<u64>,
+    /// `stale-while-revalidate=<seconds>`
+    pub stale_while_revalidate: Option<u64>,
+    /// `stale-if-error=<seconds>`
+    pub stale_if_error: Option<u64>,
+    /// `no-store`
+    pub no_store: bool,
+    /// `no-cache`
+    pub no_cache: bool,
+    /// `private`
+    pub private: bool,
+    /// `public`
+    pub public: bool,
+    /// `must-revalidate`
+    pub must_revalidate: bool,
+    /// `proxy-revalidate`
+    pub proxy_revalidate: bool,
+    /// `no-transform`
+    pub no_transform: bool,
+    /// `immutable`
+    pub immutable: bool,
+    /// Any other directive, with its optional argument
+    pub extensions: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+    /// Creates an empty `CacheControl` without any directive
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a `Cache-Control` header value.
+    ///
+    /// Directive names are case-insensitive. A known directive whose argument is
+    /// not a valid number is ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut cc = Self::new();
+        for directive in value.split(',') {
+            let directive = directive.trim();
+            if directive.is_empty() {
+                continue;
+            }
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name.trim().to_ascii_lowercase(), Some(arg.trim().trim_matches('"'))),
+                None => (directive.to_ascii_lowercase(), None),
+            };
+            let seconds = arg.and_then(|a| a.parse::<u64>().ok());
+            match name.as_str() {
+                "max-age" => cc.max_age = seconds,
+                "s-maxage" => cc.s_maxage = seconds,
+                "stale-while-revalidate" => cc.stale_while_revalidate = seconds,
+                "stale-if-error" => cc.stale_if_error = seconds,
+                "no-store" => cc.no_store = true,
+                "no-cache" => cc.no_cache = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                "must-revalidate" => cc.must_revalidate = true,
+                "proxy-revalidate" => cc.proxy_revalidate = true,
+                "no-transform" => cc.no_transform = true,
+                "immutable" => cc.immutable = true,
+                _ => cc.extensions.push((name, arg.map(|a| a.to_string()))),
+            }
+        }
+        cc
+    }
+
+    /// Sets `max-age`
+    pub fn with_max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Sets `s-maxage`
+    pub fn with_s_maxage(mut self, seconds: u64) -> Self {
+        self.s_maxage = Some(seconds);
+        self
+    }
+
+    /// Sets `stale-while-revalidate`
+    pub fn with_stale_while_revalidate(mut self, seconds: u64) -> Self {
+        self.stale_while_revalidate = Some(seconds);
+        self
+    }
+
+    /// Sets `stale-if-error`
+    pub fn with_stale_if_error(mut self, seconds: u64) -> Self {
+        self.stale_if_error = Some(seconds);
+        self
+    }
+
+    /// Sets `no-store`
+    pub fn with_no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    /// Sets `no-cache`
+    pub fn with_no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Sets `private`, unsetting `public`
+    pub fn with_private(mut self) -> Self {
+        self.private = true;
+        self.public = false;
+        self
+    }
+
+    /// Sets `public`, unsetting `private`
+    pub fn with_public(mut self) -> Self {
+        self.public = true;
+        self.private = false;
+        self
+    }
+
+    /// Sets `must-revalidate`
+    pub fn with_must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// Sets `immutable`
+    pub fn with_immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Adds a custom directive
+    pub fn with_extension<T: Into<String>>(mut self, name: T, value: Option<String>) -> Self {
+        self.extensions.push((name.into(), value));
+        self
+    }
+
+    /// Returns `true` if the response may be stored by a cache
+    pub fn is_cacheable(&self) -> bool {
+        !self.no_store
+    }
+}
+
+impl std::fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut directives: Vec<String> = Vec::new();
+        let flags = [
+            (self.no_store, "no-store"),
+            (self.no_cache, "no-cache"),
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.must_revalidate, "must-revalidate"),
+            (self.proxy_revalidate, "proxy-revalidate"),
+            (self.no_transform, "no-transform"),
+            (self.immutable, "immutable"),
+        ];
+        for (set, name) in flags {
+            if set {
+                directives.push(name.to_string());
+            }
+        }
+        let values = [
+            (self.max_age, "max-age"),
+            (self.s_maxage, "s-maxage"),
+            (self.stale_while_revalidate, "stale-while-revalidate"),
+            (self.stale_if_error, "stale-if-error"),
+        ];
+        for (value, name) in values {
+            if let Some(seconds) = value {
+                directives.push(format!("{}={}", name, seconds));
+            }
+        }
+        for (name, value) in &self.extensions {
+            match value {
+                Some(value) => directives.push(format!("{}={}", name, value)),
+                None => directives.push(name.clone()),
+            }
+        }
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+/// Represents a `Strict-Transport-Security` header (RFC 6797), telling browsers to only
+/// reach the site over HTTPS for `max_age` seconds.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::StrictTransportSecurity;
+///
+/// let hsts = StrictTransportSecurity::preload();
+/// assert!(hsts.is_preloadable());
+/// assert_eq!(hsts.to_string(), "max-age=63072000; includeSubDomains; preload");
+///
+/// let hsts = StrictTransportSecurity::parse("max-age=300").unwrap();
+/// assert_eq!(hsts, StrictTransportSecurity::new(300));
+/// assert!(!hsts.is_preloadable());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StrictTransportSecurity {
+    /// `max-age=<seconds>`
+    pub max_age: u64,
+    /// `includeSubDomains`
+    pub include_subdomains: bool,
+    /// `preload`
+    pub preload: bool,
+}
+
+impl StrictTransportSecurity {
+    /// The shortest `max-age` accepted by the browsers' preload list, one year
+    pub const PRELOAD_MIN_AGE: u64 = 31_536_000;
+
+    /// Creates a policy for this host only
+    pub fn new(max_age: u64) -> Self {
+        Self {
+            max_age,
+            include_subdomains: false,
+            preload: false,
+        }
+    }
+
+    /// A policy meeting the requirements of the browsers' preload list (hstspreload.org):
+    /// two years, covering all subdomains, with the `preload` directive.
+    /// Submitting a domain to the list is hard to undo, make sure every subdomain serves HTTPS
+    pub fn preload() -> Self {
+        Self {
+            max_age: 2 * Self::PRELOAD_MIN_AGE,
+            include_subdomains: true,
+            preload: true,
+        }
+    }
+
+    /// Sets `includeSubDomains`
+    pub fn with_include_subdomains(mut self) -> Self {
+        self.include_subdomains = true;
+        self
+    }
+
+    /// Sets `preload`
+    pub fn with_preload(mut self) -> Self {
+        self.preload = true;
+        self
+    }
+
+    /// Returns `true` if the policy can be submitted to the preload list
+    pub fn is_preloadable(&self) -> bool {
+        self.preload && self.include_subdomains && self.max_age >= Self::PRELOAD_MIN_AGE
+    }
+
+    /// Parses a `Strict-Transport-Security` header value.
+    /// Returns `None` when the required `max-age` directive is missing or invalid
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut max_age = None;
+        let mut hsts = Self::new(0);
+        for directive in value.split(';') {
+            let directive = directive.trim();
+            match directive.split_once('=') {
+                Some((name, arg)) if name.trim().eq_ignore_ascii_case("max-age") => {
+                    max_age = Some(arg.trim().trim_matches('"').parse::<u64>().ok()?);
+                }
+                _ if directive.eq_ignore_ascii_case("includesubdomains") => hsts.include_subdomains = true,
+                _ if directive.eq_ignore_ascii_case("preload") => hsts.preload = true,
+                _ => {}
+            }
+        }
+        hsts.max_age = max_age?;
+        Some(hsts)
+    }
+}
+
+impl std::fmt::Display for StrictTransportSecurity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "max-age={}", self.max_age)?;
+        if self.include_subdomains {
+            write!(f, "; includeSubDomains")?;
+        }
+        if self.preload {
+            write!(f, "; preload")?;
+        }
+        Ok(())
+    }
+}
+
+/// An entity tag as used by the `ETag`, `If-Match` and `If-None-Match` headers (RFC 9110 8.8.3).
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::EntityTag;
+///
+/// let tag = EntityTag::parse("W/\"v1\"").unwrap();
+/// assert!(tag.weak);
+/// assert!(tag.weak_eq(&EntityTag::strong("v1")));
+/// assert!(!tag.strong_eq(&EntityTag::strong("v1")));
+/// assert_eq!(tag.to_string(), "W/\"v1\"");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntityTag {
+    /// Whether this is a weak validator (`W/` prefix)
+    pub weak: bool,
+    /// The opaque tag, without the surrounding quotes
+    pub tag: String,
+}
+
+impl EntityTag {
+    /// Creates a strong entity tag
+    pub fn strong<T: Into<String>>(tag: T) -> Self {
+        Self { weak: false, tag: tag.into() }
+    }
+
+    /// Creates a weak entity tag
+    pub fn weak<T: Into<String>>(tag: T) -> Self {
+        Self { weak: true, tag: tag.into() }
+    }
+
+    /// Parses a single entity tag such as `"abc"` or `W/"abc"`.
+    ///
+    /// Returns `None` if the tag is not properly quoted.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, rest) = match value.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let tag = rest.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+        Some(Self { weak, tag: tag.to_string() })
+    }
+
+    /// Strong comparison: both tags must be strong and identical
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison: the opaque tags must be identical, weakness is ignored
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl std::fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// The value of an `If-Match` or `If-None-Match` header, either `*` or a list of entity tags.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::{EntityTag, EntityTagCondition};
+///
+/// let condition = EntityTagCondition::parse("\"a,b\", W/\"c\"");
+/// assert!(condition.matches_weak(&EntityTag::strong("c")));
+/// assert!(condition.matches_strong(&EntityTag::strong("a,b")));
+/// assert!(!condition.matches_strong(&EntityTag::strong("c")));
+///
+/// assert!(EntityTagCondition::parse("*").matches_strong(&EntityTag::weak("anything")));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityTagCondition {
+    /// `*`, matches any current representation
+    Any,
+    /// A list of entity tags
+    Tags(Vec<EntityTag>),
+}
+
+impl EntityTagCondition {
+    /// Parses the header value. Malformed entries in the list are skipped.
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if value == "*" {
+            return Self::Any;
+        }
+
+        // Entity tags may contain commas, so split only outside of quotes
+        Self::Tags(split_unquoted(value, ',').into_iter().filter_map(EntityTag::parse).collect())
+    }
+
+    /// Checks the condition against the current entity tag using strong comparison (If-Match)
+    pub fn matches_strong(&self, etag: &EntityTag) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.iter().any(|t| t.strong_eq(etag)),
+        }
+    }
+
+    /// Checks the condition against the current entity tag using weak comparison (If-None-Match)
+    pub fn matches_weak(&self, etag: &EntityTag) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.iter().any(|t| t.weak_eq(etag)),
+        }
+    }
+}
+
+impl std::fmt::Display for EntityTagCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Any => write!(f, "*"),
+            Self::Tags(tags) => write!(
+                f,
+                "{}",
+                tags.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+}
+
+/// Splits a header value on `separator`, ignoring separators inside quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            c if c == separator && !in_quotes => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// A single element of a `Forwarded` header (RFC 7239), describing one proxy hop.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ForwardedElement {
+    /// `for`: the client or proxy that made the request to this hop
+    pub forwarded_for: Option<String>,
+    /// `by`: the interface where the request came in to the proxy
+    pub by: Option<String>,
+    /// `host`: the original Host header received by the proxy
+    pub host: Option<String>,
+    /// `proto`: the protocol used to make the request, e.g. `https`
+    pub proto: Option<String>,
+    /// Any other parameter
+    pub extensions: Vec<(String, String)>,
+}
+
+impl ForwardedElement {
+    /// Parses one element such as `for=192.0.2.60;proto=http;by=203.0.113.43`.
+    ///
+    /// Parameter names are case-insensitive and quoted values are unescaped.
+    pub fn parse(value: &str) -> Self {
+        let mut element = Self::default();
+        for pair in split_unquoted(value, ';') {
+            let Some((name, raw)) = pair.split_once('=') else {
+                continue;
+            };
+            let raw = raw.trim();
+            let value = if raw.starts_with('"') {
+                unescape_quoted_string(raw)
+            } else {
+                raw.to_string()
+            };
+            match name.trim().to_ascii_lowercase().as_str() {
+                "for" => element.forwarded_for = Some(value),
+                "by" => element.by = Some(value),
+                "host" => element.host = Some(value),
+                "proto" => element.proto = Some(value.to_ascii_lowercase()),
+                other => element.extensions.push((other.to_string(), value)),
+            }
+        }
+        element
+    }
+
+    /// Returns the address of the `for` node without the port and IPv6 brackets.
+    ///
+    /// Returns `None` for obfuscated identifiers (starting with `_`) and `unknown`.
+    pub fn for_addr(&self) -> Option<&str> {
+        let node = self.forwarded_for.as_deref()?;
+        if node.starts_with('_') || node.eq_ignore_ascii_case("unknown") {
+            return None;
+        }
+        if let Some(rest) = node.strip_prefix('[') {
+            return rest.split(']').next();
+        }
+        Some(node.split(':').next().unwrap_or(node))
+    }
+}
+
+impl std::fmt::Display for ForwardedElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params = [
+            ("for", self.forwarded_for.as_ref()),
+            ("by", self.by.as_ref()),
+            ("host", self.host.as_ref()),
+            ("proto", self.proto.as_ref()),
+        ];
+        let formatted = params
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .chain(self.extensions.iter().map(|(name, value)| (name.as_str(), value)))
+            .map(|(name, value)| {
+                // Tokens can be sent as is, anything else (IPv6, ports) must be quoted
+                if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)) {
+                    format!("{}={}", name, value)
+                } else {
+                    format!("{}=\"{}\"", name, escape_quoted_string(value))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        write!(f, "{}", formatted)
+    }
+}
+
+/// Represents the `Forwarded` header (RFC 7239), one element per proxy hop.
+///
+/// The first element was added by the proxy closest to the client. Note that the
+/// header can be forged by the client, only the elements added by trusted proxies
+/// should be relied upon.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::Forwarded;
+///
+/// let forwarded = Forwarded::parse("for=\"[2001:db8:cafe::17]:4711\";proto=HTTPS, for=192.0.2.43");
+/// assert_eq!(forwarded.elements().len(), 2);
+/// assert_eq!(forwarded.client_addr(), Some("2001:db8:cafe::17"));
+/// assert_eq!(forwarded.elements()[0].proto.as_deref(), Some("https"));
+/// assert_eq!(forwarded.elements()[1].for_addr(), Some("192.0.2.43"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Forwarded {
+    elements: Vec<ForwardedElement>,
+}
+
+impl Forwarded {
+    /// Creates an empty `Forwarded` header
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a `Forwarded` header value, skipping empty elements
+    pub fn parse(value: &str) -> Self {
+        let elements = split_unquoted(value, ',')
+            .into_iter()
+            .filter(|element| !element.trim().is_empty())
+            .map(ForwardedElement::parse)
+            .collect();
+        Self { elements }
+    }
+
+    /// Returns all elements, from the closest to the client to the closest to this server
+    pub fn elements(&self) -> &[ForwardedElement] {
+        &self.elements
+    }
+
+    /// Appends an element, as a proxy does when forwarding a request
+    pub fn push(&mut self, element: ForwardedElement) {
+        self.elements.push(element);
+    }
+
+    /// Returns the address of the original client as reported by the first element
+    pub fn client_addr(&self) -> Option<&str> {
+        self.elements.first().and_then(|element| element.for_addr())
+    }
+}
+
+impl std::fmt::Display for Forwarded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.elements.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+        )
+    }
+}
+
+/// A single byte range of a `Range` header (RFC 9110 14.1.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRangeSpec {
+    /// `first-last`, both inclusive
+    FromTo(u64, u64),
+    /// `first-`, from an offset to the end of the representation
+    From(u64),
+    /// `-length`, the last `length` bytes of the representation
+    Suffix(u64),
+}
+
+impl ByteRangeSpec {
+    /// Parses a single range such as `0-499`, `500-` or `-200`.
+    ///
+    /// Returns `None` if the range is malformed or if `first` is greater than `last`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (first, last) = value.trim().split_once('-')?;
+        let (first, last) = (first.trim(), last.trim());
+        match (first.is_empty(), last.is_empty()) {
+            (true, false) => Some(Self::Suffix(last.parse().ok()?)),
+            (false, true) => Some(Self::From(first.parse().ok()?)),
+            (false, false) => {
+                let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                if first > last {
+                    return None;
+                }
+                Some(Self::FromTo(first, last))
+            }
+            (true, true) => None,
+        }
+    }
+
+    /// Resolves the range against the length of the representation.
+    ///
+    /// # Returns
+    ///
+    /// The inclusive `(start, end)` offsets clamped to the representation, or
+    /// `None` if the range is not satisfiable.
+    pub fn to_satisfiable_range(&self, length: u64) -> Option<(u64, u64)> {
+        match *self {
+            Self::FromTo(first, last) if first < length => Some((first, last.min(length - 1))),
+            Self::From(first) if first < length => Some((first, length - 1)),
+            Self::Suffix(suffix) if suffix > 0 && length > 0 => Some((length.saturating_sub(suffix), length - 1)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ByteRangeSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FromTo(first, last) => write!(f, "{}-{}", first, last),
+            Self::From(first) => write!(f, "{}-", first),
+            Self::Suffix(suffix) => write!(f, "-{}", suffix),
+        }
+    }
+}
+
+/// Represents the `Range` request header. Only the `bytes` unit is supported.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::{ByteRangeSpec, Range};
+///
+/// let range = Range::parse("bytes=0-49, 200-, -50").unwrap();
+/// assert!(range.is_multipart());
+/// assert_eq!(range.ranges()[2], ByteRangeSpec::Suffix(50));
+/// assert_eq!(range.satisfiable_ranges(150), vec![(0, 49), (100, 149)]);
+///
+/// assert!(Range::parse("items=0-5").is_none());
+/// assert!(Range::parse("bytes=5-1").is_none());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    ranges: Vec<ByteRangeSpec>,
+}
+
+impl Range {
+    /// Creates a `Range` from a list of byte ranges
+    pub fn new(ranges: Vec<ByteRangeSpec>) -> Self {
+        Self { ranges }
+    }
+
+    /// Parses a `Range` header value.
+    ///
+    /// Returns `None` if the unit is not `bytes`, if the list is empty, or if any
+    /// range is malformed. Such a header must be ignored by the server.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (unit, ranges) = value.trim().split_once('=')?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+        let ranges = ranges
+            .split(',')
+            .filter(|range| !range.trim().is_empty())
+            .map(ByteRangeSpec::parse)
+            .collect::<Option<Vec<_>>>()?;
+        if ranges.is_empty() {
+            return None;
+        }
+        Some(Self { ranges })
+    }
+
+    /// Returns the requested byte ranges in header order
+    pub fn ranges(&self) -> &[ByteRangeSpec] {
+        &self.ranges
+    }
+
+    /// Returns `true` if more than one range was requested, which calls for a
+    /// `multipart/byteranges` response
+    pub fn is_multipart(&self) -> bool {
+        self.ranges.len() > 1
+    }
+
+    /// Resolves all ranges against the length of the representation, dropping the
+    /// unsatisfiable ones and merging those that overlap or are adjacent.
+    ///
+    /// An empty result means that a `416 Range Not Satisfiable` should be sent.
+    pub fn satisfiable_ranges(&self, length: u64) -> Vec<(u64, u64)> {
+        let mut ranges: Vec<(u64, u64)> = self
+            .ranges
+            .iter()
+            .filter_map(|range| range.to_satisfiable_range(length))
+            .collect();
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        merged
+    }
+
+    /// Returns `true` if at least one range can be served for the given length
+    pub fn is_satisfiable(&self, length: u64) -> bool {
+        self.ranges.iter().any(|range| range.to_satisfiable_range(length).is_some())
+    }
+}
+
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bytes={}",
+            self.ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", ")
+        )
+    }
+}
+
+/// Represents the `Content-Range` response header (RFC 9110 14.4).
+///
+/// # Examples
+///
+/// ```
+/// use starberry_core::http::http_value::ContentRange;
+///
+/// let range = ContentRange::bytes(0, 99, Some(1000));
+/// assert_eq!(range.to_string(), "bytes 0-99/1000");
+/// assert_eq!(ContentRange::parse("bytes */1000"), Some(ContentRange::Unsatisfied(1000)));
+/// assert_eq!(ContentRange::parse("bytes 0-99/*"), Some(ContentRange::bytes(0, 99, None)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentRange {
+    /// The inclusive range sent in this response, with the complete length if known
+    Bytes { start: u64, end: u64, complete_length: Option<u64> },
+    /// Sent with `416 Range Not Satisfiable`, carrying the current length
+    Unsatisfied(u64),
+}
+
+impl ContentRange {
+    /// Creates a `Content-Range` for a satisfied byte range
+    pub fn bytes(start: u64, end: u64, complete_length: Option<u64>) -> Self {
+        Self::Bytes { start, end, complete_length }
+    }
+
+    /// Parses a `Content-Range` header value.
+    ///
+    /// Returns `None` if the value is malformed or describes an invalid range.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (unit, rest) = value.trim().split_once(' ')?;
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+        let (range, length) = rest.trim().split_once('/')?;
+        let length = match length.trim() {
+            "*" => None,
+            length => Some(length.parse::<u64>().ok()?),
+        };
+        if range.trim() == "*" {
+            return length.map(Self::Unsatisfied);
+        }
+        let (start, end) = range.split_once('-')?;
+        let (start, end) = (start.trim().parse::<u64>().ok()?, end.trim().parse::<u64>().ok()?);
+        if start > end || length.is_some_and(|length| end >= length) {
+            return None;
+        }
+        Some(Self::bytes(start, end, length))
+    }
+
+    /// Returns the number of bytes in the range, 0 for an unsatisfied range
+    pub fn len(&self) -> u64 {
+        match self {
+            Self::Bytes { start, end, .. } => end - start + 1,
+            Self::Unsatisfied(_) => 0,
+        }
+    }
+
+    /// Returns `true` for an unsatisfied range
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Self::Unsatisfied(_))
+    }
+}
+
+impl std::fmt::Display for ContentRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bytes { start, end, complete_length: Some(length) } => write!(f, "bytes {}-{}/{}", start, end, length),
+            Self::Bytes { start, end, complete_length: None } => write!(f, "bytes {}-{}/*", start, end),
+            Self::Unsatisfied(length) => write!(f, "bytes */{}", length),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Conversions of Rust values to JSON.
+//!
+//! With the `serde` feature, any `Serialize` value converts to an akari `Value` through
+//! `to_value`, and can be sent with `serialized_json_response` or `akari_json!(value)`,
+//! so existing structs are returned without being rebuilt with `object!`.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[derive(Serialize)]
+//! struct User {
+//!     id: u64,
+//!     name: String,
+//! }
+//!
+//! #[url(reg![&APP, LitUrl("me")])]
+//! async fn me() -> HttpResponse {
+//!     let user = User { id: 1, name: "Ada".to_string() };
+//!     akari_json!(user)
+//! }
+//! ```
+
+use akari::Value;
+
+/// Converts a `Serialize` value to a `Value`, going through its JSON text
+#[cfg(feature = "serde")]
+pub fn to_value<T: serde::Serialize + ?Sized>(value: &T) -> Result<Value, String> {
+    let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    Value::from_json(&json).map_err(|_| format!("Cannot read the JSON of a serialized value: {}", json))
+}
+
+/// Picks the conversion of the expression given to `akari_json!` by autoref: values
+/// convertible into `Value` are used as they are, other values are serialized
+#[doc(hidden)]
+pub mod bridge {
+    use akari::Value;
+
+    use crate::http::response::HttpResponse;
+    use crate::http::response::response_templates::json_response;
+
+    pub struct JsonBody<'a, T>(pub &'a T);
+
+    pub trait JsonFromValue {
+        fn to_json_response(&self) -> HttpResponse;
+    }
+
+    impl<T: Clone + Into<Value>> JsonFromValue for &JsonBody<'_, T> {
+        fn to_json_response(&self) -> HttpResponse {
+            json_response(self.0.clone().into())
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub trait JsonFromSerialize {
+        fn to_json_response(&self) -> HttpResponse;
+    }
+
+    #[cfg(feature = "serde")]
+    impl<T: serde::Serialize> JsonFromSerialize for JsonBody<'_, T> {
+        fn to_json_response(&self) -> HttpResponse {
+            crate::http::response::response_templates::serialized_json_response(self.0)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::bridge::*;
+    use super::*;
+    use crate::http::http_value::StatusCode;
+
+    #[derive(serde::Serialize)]
+    struct User {
+        id: u64,
+        name: String,
+        tags: Vec<&'static str>,
+    }
+
+    #[test]
+    fn serializes_through_json() {
+        let user = User { id: 7, name: "Ada \"L\"".to_string(), tags: vec!["admin"] };
+        let json = to_value(&user).unwrap().into_json();
+        assert!(json.contains("admin"));
+        assert!(json.contains("Ada"));
+    }
+
+    // The `&&` mirrors the expansion of `akari_json!`, which picks the impl by autoref
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn bridge_prefers_values() {
+        let value = Value::new("plain");
+        let response = (&&JsonBody(&value)).to_json_response();
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::OK);
+        let user = User { id: 1, name: String::new(), tags: vec![] };
+        let response = (&&JsonBody(&user)).to_json_response();
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::OK);
+    }
+}
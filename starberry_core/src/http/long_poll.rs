@@ -0,0 +1,202 @@
+//! Long polling, for clients which cannot use WebSockets.
+//!
+//! The handler parks on a `Notify`, a `watch` channel or any future until fresh data
+//! is available, the poll times out, or the client disconnects. A timed out poll is
+//! answered with 204 No Content, or 304 Not Modified, and the client polls again.
+//!
+//! # Example
+//! ```rust,ignore
+//! static MESSAGES: Lazy<watch::Sender<(u64, Vec<String>)>> = Lazy::new(|| watch::channel((0, vec![])).0);
+//!
+//! #[url(reg![&APP, LitUrl("messages")])]
+//! async fn messages() -> HttpResponse {
+//!     let since = req.query_map().get("since").and_then(|v| v.parse::<u64>().ok());
+//!     let poll = LongPoll::for_request(req, Duration::from_secs(30));
+//!     let outcome = poll.newer(&mut MESSAGES.subscribe(), since, |(version, _)| *version).await;
+//!     poll.respond(outcome, |(version, messages)| {
+//!         let mut body = object!({});
+//!         body.set("version", version);
+//!         body.set("messages", messages);
+//!         json_response(body)
+//!     })
+//! }
+//! ```
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::sync::{Notify, watch};
+
+use crate::connection::CancellationToken;
+
+use super::context::HttpReqCtx;
+use super::http_value::StatusCode;
+use super::response::HttpResponse;
+use super::response::response_templates::return_status;
+
+/// How a long poll ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollOutcome<T> {
+    /// Fresh data is available
+    Ready(T),
+    /// Nothing happened before the timeout
+    TimedOut,
+    /// The client went away, the response will never be delivered
+    Disconnected,
+}
+
+impl<T> PollOutcome<T> {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Ready(_))
+    }
+}
+
+/// The waiting policy of a long poll
+#[derive(Debug, Clone)]
+pub struct LongPoll {
+    timeout: Duration,
+    timeout_status: StatusCode,
+    cancel: CancellationToken,
+}
+
+impl LongPoll {
+    /// Below the 60 seconds after which many proxies drop idle requests
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Time kept before the connection limit of the request to deliver the response
+    const DEADLINE_MARGIN: Duration = Duration::from_secs(1);
+
+    /// A poll which is only ended by data or the timeout
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            timeout_status: StatusCode::NO_CONTENT,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// A poll for a request. It ends when the client disconnects, and early enough
+    /// to answer before the connection reaches its time limit
+    pub fn for_request(req: &HttpReqCtx, timeout: Duration) -> Self {
+        let timeout = match req.time_remaining() {
+            Some(remaining) => timeout.min(remaining.saturating_sub(Self::DEADLINE_MARGIN)),
+            None => timeout,
+        };
+        Self::new(timeout).with_cancellation(req.cancellation_token())
+    }
+
+    /// Ends the poll when the token is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Answers a timed out poll with 304 Not Modified instead of 204 No Content
+    pub fn not_modified(mut self) -> Self {
+        self.timeout_status = StatusCode::NOT_MODIFIED;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Waits for a future
+    pub async fn wait<F: Future>(&self, fut: F) -> PollOutcome<F::Output> {
+        tokio::select! {
+            output = fut => PollOutcome::Ready(output),
+            _ = tokio::time::sleep(self.timeout) => PollOutcome::TimedOut,
+            _ = self.cancel.cancelled() => PollOutcome::Disconnected,
+        }
+    }
+
+    /// Waits for the next notification. A permit stored by `Notify::notify_one`
+    /// before the poll started ends it at once
+    pub async fn notified(&self, notify: &Notify) -> PollOutcome<()> {
+        self.wait(notify.notified()).await
+    }
+
+    /// Waits for a value of the channel whose version is above `since`, the last version
+    /// seen by the client. A value published between two polls is returned at once, so
+    /// nothing is missed. Without `since` the current value is returned
+    pub async fn newer<T, V, F>(&self, rx: &mut watch::Receiver<T>, since: Option<V>, version: F) -> PollOutcome<T>
+    where
+        T: Clone,
+        V: PartialOrd,
+        F: Fn(&T) -> V,
+    {
+        let outcome = self
+            .wait(rx.wait_for(|value| since.as_ref().is_none_or(|since| version(value) > *since)))
+            .await;
+        match outcome {
+            PollOutcome::Ready(Ok(value)) => PollOutcome::Ready(value.clone()),
+            // The sender is gone, no value will ever come
+            PollOutcome::Ready(Err(_)) => PollOutcome::TimedOut,
+            PollOutcome::TimedOut => PollOutcome::TimedOut,
+            PollOutcome::Disconnected => PollOutcome::Disconnected,
+        }
+    }
+
+    /// Builds the response of a poll, with `ready` for fresh data
+    pub fn respond<T, F: FnOnce(T) -> HttpResponse>(&self, outcome: PollOutcome<T>, ready: F) -> HttpResponse {
+        match outcome {
+            PollOutcome::Ready(data) => ready(data),
+            PollOutcome::TimedOut => return_status(self.timeout_status.clone()),
+            // Nobody reads it, keep it empty
+            PollOutcome::Disconnected => return_status(StatusCode::NO_CONTENT),
+        }
+    }
+}
+
+impl Default for LongPoll {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::connection::CancelReason;
+
+    #[tokio::test]
+    async fn notify_timeout_and_disconnect() {
+        let poll = LongPoll::new(Duration::from_millis(20));
+        let notify = Arc::new(Notify::new());
+        notify.notify_one();
+        assert_eq!(poll.notified(&notify).await, PollOutcome::Ready(()));
+        assert_eq!(poll.notified(&notify).await, PollOutcome::TimedOut);
+
+        let cancel = CancellationToken::new();
+        let poll = LongPoll::new(Duration::from_secs(10)).with_cancellation(cancel.clone());
+        cancel.cancel(CancelReason::ClientDisconnected);
+        assert_eq!(poll.notified(&notify).await, PollOutcome::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn newer_values() {
+        let (tx, mut rx) = watch::channel(1u64);
+        let poll = LongPoll::new(Duration::from_millis(20));
+        assert_eq!(poll.newer(&mut rx, Some(0), |v| *v).await, PollOutcome::Ready(1));
+        assert_eq!(poll.newer(&mut rx, Some(1), |v| *v).await, PollOutcome::TimedOut);
+
+        let poll = LongPoll::new(Duration::from_secs(10));
+        let publisher = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            tx.send(2).unwrap();
+            tx
+        });
+        assert_eq!(poll.newer(&mut rx, Some(1), |v| *v).await, PollOutcome::Ready(2));
+        drop(publisher.await.unwrap());
+        assert_eq!(poll.newer(&mut rx, Some(2), |v| *v).await, PollOutcome::TimedOut);
+    }
+
+    #[test]
+    fn timed_out_status() {
+        let poll = LongPoll::default().not_modified();
+        let response = poll.respond(PollOutcome::<()>::TimedOut, |_| unreachable!());
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::NOT_MODIFIED);
+    }
+}
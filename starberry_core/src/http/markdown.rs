@@ -0,0 +1,971 @@
+//! Renders Markdown to HTML, for blogs and documentation written by the users of an application.
+//!
+//! The CommonMark blocks are supported: paragraphs, ATX and setext headings, thematic breaks,
+//! indented and fenced code blocks, block quotes and lists; and the inlines: emphasis, code
+//! spans, links, images, autolinks, backslash escapes and hard line breaks. Reference links and
+//! the GitHub extensions such as tables are not.
+//!
+//! The output is safe by default: raw HTML is escaped and links or images whose URL uses a
+//! scheme other than `http`, `https` or `mailto` are written as plain text. With `raw_html`, the
+//! HTML is kept and the whole output is cleaned by an `HtmlSanitizer`.
+//!
+//! Templates insert values as they are, so Markdown is rendered before it is handed to them,
+//! with `MarkdownRenderer::filter` for the values of the template data.
+//!
+//! # Example
+//! ```
+//! use starberry_core::http::markdown::{MarkdownRenderer, markdown};
+//! assert_eq!(markdown("# Hello *world*"), "<h1>Hello <em>world</em></h1>\n");
+//! assert_eq!(markdown("<script>x</script>"), "<p>&lt;script&gt;x&lt;/script&gt;</p>\n");
+//!
+//! let renderer = MarkdownRenderer::new().highlighter(|lang, code| {
+//!     (lang == "sh").then(|| format!("<span class=\"shell\">{}</span>", code.trim_end()))
+//! });
+//! assert_eq!(renderer.render("```sh\nls\n```"), "<pre><code class=\"language-sh\"><span class=\"shell\">ls</span></code></pre>\n");
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use akari::Value;
+
+use super::sanitize::{HtmlSanitizer, decode_entities, escape_html, push_text, url_allowed};
+
+/// Turns the code of a fenced block and its language into HTML, `None` to escape it as usual
+type Highlighter = Arc<dyn Fn(&str, &str) -> Option<String> + Send + Sync>;
+
+/// How deep block quotes, lists and links may nest, the deeper levels are rendered as text so
+/// the recursion of the parser stays bounded whatever the input
+const MAX_NESTING: usize = 32;
+
+/// Renders Markdown with the default options of `MarkdownRenderer::new`
+pub fn markdown(text: &str) -> String {
+    MarkdownRenderer::new().render(text)
+}
+
+/// Renders Markdown to HTML, see the module documentation
+#[derive(Clone)]
+pub struct MarkdownRenderer {
+    /// Cleans the output when raw HTML is kept
+    raw_html: Option<HtmlSanitizer>,
+    highlighter: Option<Highlighter>,
+    url_schemes: HashSet<String>,
+}
+
+impl MarkdownRenderer {
+    /// Escapes raw HTML and allows the `http`, `https` and `mailto` URLs
+    pub fn new() -> Self {
+        Self {
+            raw_html: None,
+            highlighter: None,
+            url_schemes: ["http", "https", "mailto"].iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Keeps the raw HTML of the text and cleans the whole output with the sanitizer, which
+    /// must allow the tags written for the Markdown and by the highlighter
+    pub fn raw_html(mut self, sanitizer: HtmlSanitizer) -> Self {
+        self.raw_html = Some(sanitizer);
+        self
+    }
+
+    /// Sets the function writing the HTML of fenced code blocks, given their language and code.
+    /// It escapes the code itself, and returns `None` for the languages it does not know
+    pub fn highlighter<F>(mut self, highlighter: F) -> Self
+    where
+        F: Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.highlighter = Some(Arc::new(highlighter));
+        self
+    }
+
+    /// Sets the schemes allowed in the URLs of links and images. Relative URLs are always allowed
+    pub fn url_schemes(mut self, schemes: &[&str]) -> Self {
+        self.url_schemes = schemes.iter().map(|scheme| scheme.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Renders the strings of a value of template data, in lists and dicts too
+    pub fn filter(&self, value: &Value) -> Value {
+        match value {
+            Value::Str(text) => Value::Str(self.render(text)),
+            Value::List(items) => Value::List(items.iter().map(|item| self.filter(item)).collect()),
+            Value::Dict(map) => Value::Dict(map.iter().map(|(key, item)| (key.clone(), self.filter(item))).collect()),
+            other => other.clone(),
+        }
+    }
+
+    pub fn render(&self, text: &str) -> String {
+        let lines: Vec<String> = text.lines().map(|line| line.replace('\t', "    ")).collect();
+        let mut out = String::new();
+        for block in self.blocks(&lines, 0) {
+            self.write_block(&mut out, &block, false);
+        }
+        match &self.raw_html {
+            Some(sanitizer) => sanitizer.sanitize(&out),
+            None => out,
+        }
+    }
+
+    fn blocks(&self, lines: &[String], depth: usize) -> Vec<Block> {
+        if depth >= MAX_NESTING {
+            let text = lines.iter().map(|line| line.trim()).collect::<Vec<_>>().join("\n");
+            let text = text.trim();
+            return if text.is_empty() { Vec::new() } else { vec![Block::Paragraph(text.to_string())] };
+        }
+        let mut blocks = Vec::new();
+        let mut i = 0;
+        while i < lines.len() {
+            let line = &lines[i];
+            if line.trim().is_empty() {
+                i += 1;
+            } else if indent(line) >= 4 {
+                let mut code = Vec::new();
+                while i < lines.len() && (lines[i].trim().is_empty() || indent(&lines[i]) >= 4) {
+                    code.push(strip_indent(&lines[i], 4));
+                    i += 1;
+                }
+                while code.last().is_some_and(|line| line.trim().is_empty()) {
+                    code.pop();
+                }
+                blocks.push(Block::Code { lang: String::new(), code: code.join("\n") + "\n" });
+            } else if let Some(fence) = Fence::parse(line) {
+                let mut code = Vec::new();
+                i += 1;
+                while i < lines.len() {
+                    let line = &lines[i];
+                    i += 1;
+                    if fence.is_closed_by(line) {
+                        break;
+                    }
+                    code.push(strip_indent(line, fence.indent));
+                }
+                let code = if code.is_empty() { String::new() } else { code.join("\n") + "\n" };
+                blocks.push(Block::Code { lang: fence.lang, code });
+            } else if let Some((level, text)) = atx_heading(line) {
+                blocks.push(Block::Heading(level, text));
+                i += 1;
+            } else if is_rule(line) {
+                blocks.push(Block::Rule);
+                i += 1;
+            } else if quote_content(line).is_some() {
+                let mut inner: Vec<String> = Vec::new();
+                while i < lines.len() {
+                    let line = &lines[i];
+                    if let Some(content) = quote_content(line) {
+                        inner.push(content.to_string());
+                    } else if self.continues_paragraph(line, inner.last()) {
+                        inner.push(line.clone());
+                    } else {
+                        break;
+                    }
+                    i += 1;
+                }
+                blocks.push(Block::Quote(self.blocks(&inner, depth + 1)));
+            } else if let Some(marker) = ListMarker::parse(line) {
+                i = self.list(lines, i, marker, &mut blocks, depth);
+            } else if self.raw_html.is_some() && starts_html_block(line) {
+                let start = i;
+                while i < lines.len() && !lines[i].trim().is_empty() {
+                    i += 1;
+                }
+                blocks.push(Block::Html(lines[start..i].join("\n")));
+            } else {
+                let mut text = vec![line.trim_start().to_string()];
+                i += 1;
+                let mut heading = None;
+                while i < lines.len() && !lines[i].trim().is_empty() {
+                    if let Some(level) = setext_level(&lines[i]) {
+                        heading = Some(level);
+                        i += 1;
+                        break;
+                    }
+                    if self.interrupts_paragraph(&lines[i]) {
+                        break;
+                    }
+                    text.push(lines[i].trim_start().to_string());
+                    i += 1;
+                }
+                let text = text.join("\n").trim_end().to_string();
+                blocks.push(match heading {
+                    Some(level) => Block::Heading(level, text),
+                    None => Block::Paragraph(text),
+                });
+            }
+        }
+        blocks
+    }
+
+    /// Parses the list starting at line `i`, returning the line after it
+    fn list(&self, lines: &[String], mut i: usize, first: ListMarker, blocks: &mut Vec<Block>, depth: usize) -> usize {
+        let mut items = Vec::new();
+        let mut loose = false;
+        while i < lines.len() {
+            let Some(marker) = ListMarker::parse(&lines[i]).filter(|marker| marker.continues(&first)) else {
+                break;
+            };
+            if is_rule(&lines[i]) {
+                break;
+            }
+            let mut item = vec![lines[i].get(marker.width..).unwrap_or("").to_string()];
+            i += 1;
+            while i < lines.len() {
+                let line = &lines[i];
+                if line.trim().is_empty() {
+                    // A blank line belongs to the item if the item goes on after it
+                    let next = lines[i..].iter().position(|line| !line.trim().is_empty()).map(|n| i + n);
+                    match next {
+                        Some(next) if indent(&lines[next]) >= marker.width => {
+                            item.extend((i..next).map(|_| String::new()));
+                            loose |= !item.iter().all(|line| line.trim().is_empty());
+                            i = next;
+                            continue;
+                        }
+                        _ => break,
+                    }
+                } else if indent(line) >= marker.width {
+                    item.push(strip_indent(line, marker.width));
+                } else if self.continues_paragraph(line, item.last()) {
+                    item.push(line.trim_start().to_string());
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+            items.push(self.blocks(&item, depth + 1));
+            // Blank lines between two items make the list loose
+            let next = lines[i..].iter().position(|line| !line.trim().is_empty()).map_or(lines.len(), |n| i + n);
+            if next > i && next < lines.len() && ListMarker::parse(&lines[next]).is_some_and(|marker| marker.continues(&first)) {
+                loose = true;
+                i = next;
+            }
+        }
+        blocks.push(Block::List { start: first.start, loose, items });
+        i
+    }
+
+    /// Whether the line starts a block which ends a paragraph
+    fn interrupts_paragraph(&self, line: &str) -> bool {
+        indent(line) < 4
+            && (Fence::parse(line).is_some()
+                || atx_heading(line).is_some()
+                || is_rule(line)
+                || quote_content(line).is_some()
+                || (self.raw_html.is_some() && starts_html_block(line))
+                || ListMarker::parse(line).is_some_and(|marker| !marker.empty && marker.start.is_none_or(|start| start == 1)))
+    }
+
+    /// Whether the line is the lazy continuation of a paragraph ending with `previous`
+    fn continues_paragraph(&self, line: &str, previous: Option<&String>) -> bool {
+        !line.trim().is_empty()
+            && previous.is_some_and(|previous| !previous.trim().is_empty())
+            && !self.interrupts_paragraph(line)
+            && ListMarker::parse(line).is_none()
+    }
+
+    fn write_block(&self, out: &mut String, block: &Block, tight: bool) {
+        match block {
+            Block::Heading(level, text) => out.push_str(&format!("<h{0}>{1}</h{0}>\n", level, self.inline(text, 0))),
+            Block::Paragraph(text) if tight => out.push_str(&self.inline(text, 0)),
+            Block::Paragraph(text) => out.push_str(&format!("<p>{}</p>\n", self.inline(text, 0))),
+            Block::Rule => out.push_str("<hr />\n"),
+            Block::Html(html) => {
+                out.push_str(html);
+                out.push('\n');
+            }
+            Block::Code { lang, code } => {
+                let highlighted = self.highlighter.as_ref().filter(|_| !lang.is_empty()).and_then(|highlight| highlight(lang, code));
+                let class = if lang.is_empty() { String::new() } else { format!(" class=\"language-{}\"", escape_html(lang)) };
+                let code = highlighted.unwrap_or_else(|| escape_html(code));
+                out.push_str(&format!("<pre><code{}>{}</code></pre>\n", class, code));
+            }
+            Block::Quote(blocks) => {
+                out.push_str("<blockquote>\n");
+                for block in blocks {
+                    self.write_block(out, block, false);
+                }
+                out.push_str("</blockquote>\n");
+            }
+            Block::List { start, loose, items } => {
+                let (open, close) = match start {
+                    Some(1) => ("<ol>".to_string(), "</ol>"),
+                    Some(start) => (format!("<ol start=\"{}\">", start), "</ol>"),
+                    None => ("<ul>".to_string(), "</ul>"),
+                };
+                out.push_str(&open);
+                out.push('\n');
+                for item in items {
+                    out.push_str("<li>");
+                    if *loose && !item.is_empty() {
+                        out.push('\n');
+                    }
+                    for (n, block) in item.iter().enumerate() {
+                        // A tight paragraph has no line break of its own before the next block
+                        if n > 0 && !*loose && matches!(item[n - 1], Block::Paragraph(_)) {
+                            out.push('\n');
+                        }
+                        self.write_block(out, block, !*loose);
+                    }
+                    out.push_str("</li>\n");
+                }
+                out.push_str(close);
+                out.push('\n');
+            }
+        }
+    }
+
+    /// Renders the inlines of a paragraph or heading, or of the text of a link nested `depth` deep
+    fn inline(&self, text: &str, depth: usize) -> String {
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut pending = String::new();
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        // The delimiters are matched once for the whole text, not searched again from each opening
+        let brackets = match_brackets(text);
+        let closes: Vec<usize> = text.match_indices('>').map(|(at, _)| at).collect();
+        // The lengths of the backtick runs no later run closes
+        let mut unclosed_runs = HashSet::new();
+        let mut k = 0;
+        let flush = |nodes: &mut Vec<Node>, pending: &mut String| {
+            if !pending.is_empty() {
+                let mut html = String::new();
+                push_text(&mut html, pending);
+                nodes.push(Node::Html(html));
+                pending.clear();
+            }
+        };
+        while k < chars.len() {
+            let (at, c) = chars[k];
+            let rest = &text[at..];
+            match c {
+                '\\' if rest[1..].starts_with('\n') => {
+                    flush(&mut nodes, &mut pending);
+                    nodes.push(Node::Html("<br />\n".to_string()));
+                    k += 2;
+                }
+                '\\' if rest[1..].starts_with(|c: char| c.is_ascii_punctuation()) => {
+                    flush(&mut nodes, &mut pending);
+                    nodes.push(Node::Html(escape_html(&rest[1..2])));
+                    k += 2;
+                }
+                '\n' => {
+                    let spaces = pending.len() - pending.trim_end_matches(' ').len();
+                    pending.truncate(pending.len() - spaces);
+                    flush(&mut nodes, &mut pending);
+                    nodes.push(Node::Html(if spaces >= 2 { "<br />\n" } else { "\n" }.to_string()));
+                    k += 1;
+                    while k < chars.len() && chars[k].1 == ' ' {
+                        k += 1;
+                    }
+                }
+                '`' => {
+                    let run = rest.len() - rest.trim_start_matches('`').len();
+                    let span = if unclosed_runs.contains(&run) { None } else { code_span(rest, run) };
+                    match span {
+                        Some((code, len)) => {
+                            flush(&mut nodes, &mut pending);
+                            nodes.push(Node::Html(format!("<code>{}</code>", escape_html(&code))));
+                            k = char_index(&chars, at + len);
+                        }
+                        None => {
+                            unclosed_runs.insert(run);
+                            pending.push_str(&rest[..run]);
+                            k += run;
+                        }
+                    }
+                }
+                '!' | '[' if rest.starts_with("![") || c == '[' => {
+                    let image = c == '!';
+                    let open = if image { 1 } else { 0 };
+                    let text_end = brackets[at + open].filter(|_| depth < MAX_NESTING);
+                    match text_end.and_then(|end| link(&rest[open..], end - at - open)) {
+                        Some(link) => {
+                            flush(&mut nodes, &mut pending);
+                            nodes.push(Node::Html(self.write_link(&link, image, depth)));
+                            k = char_index(&chars, at + open + link.len);
+                        }
+                        None => {
+                            pending.push(c);
+                            k += 1;
+                        }
+                    }
+                }
+                '<' => match closes.get(closes.partition_point(|&close| close < at)).and_then(|close| self.angle(rest, close - at)) {
+                    Some((html, len)) => {
+                        flush(&mut nodes, &mut pending);
+                        nodes.push(Node::Html(html));
+                        k = char_index(&chars, at + len);
+                    }
+                    None => {
+                        pending.push(c);
+                        k += 1;
+                    }
+                },
+                '*' | '_' => {
+                    flush(&mut nodes, &mut pending);
+                    let count = rest.len() - rest.trim_start_matches(c).len();
+                    let before = text[..at].chars().next_back();
+                    let after = rest[count..].chars().next();
+                    nodes.push(Node::Delim(Delim::new(c, count, before, after)));
+                    k += count;
+                }
+                _ => {
+                    pending.push(c);
+                    k += 1;
+                }
+            }
+        }
+        flush(&mut nodes, &mut pending);
+        emphasis(&mut nodes);
+        nodes.iter().map(Node::html).collect()
+    }
+
+    fn write_link(&self, link: &Link, image: bool, depth: usize) -> String {
+        let url = decode_entities(&link.url);
+        let text = self.inline(&link.text, depth + 1);
+        if !url_allowed(&url, &self.url_schemes) {
+            // The text is kept, without the link
+            return if image { escape_html(&plain_text(&text)) } else { text };
+        }
+        let title = link.title.as_ref().map_or(String::new(), |title| format!(" title=\"{}\"", escape_html(&decode_entities(title))));
+        if image {
+            let alt = plain_text(&text);
+            format!("<img src=\"{}\" alt=\"{}\"{} />", escape_html(&url), escape_html(&alt), title)
+        } else {
+            format!("<a href=\"{}\"{}>{}</a>", escape_html(&url), title, text)
+        }
+    }
+
+    /// An autolink such as `<https://example.com>`, or raw HTML when it is kept, with its length.
+    /// `end` is the position of the first `>` in `rest`
+    fn angle(&self, rest: &str, end: usize) -> Option<(String, usize)> {
+        let inner = &rest[1..end];
+        if !inner.is_empty() && !inner.contains([' ', '<', '\n']) {
+            let scheme = inner.split(':').next().unwrap_or("");
+            let is_uri = inner.contains(':')
+                && (2..=32).contains(&scheme.len())
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'));
+            let is_email = !is_uri && inner.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'));
+            if is_uri || is_email {
+                let href = if is_email { format!("mailto:{}", inner) } else { inner.to_string() };
+                if !url_allowed(&href, &self.url_schemes) {
+                    return None;
+                }
+                return Some((format!("<a href=\"{}\">{}</a>", escape_html(&href), escape_html(inner)), end + 1));
+            }
+        }
+        if self.raw_html.is_some() && starts_html(rest) {
+            return Some((rest[..=end].to_string(), end + 1));
+        }
+        None
+    }
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    Code { lang: String, code: String },
+    Quote(Vec<Block>),
+    /// `start` is the number of the first item of an ordered list
+    List { start: Option<u64>, loose: bool, items: Vec<Vec<Block>> },
+    Rule,
+    Html(String),
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Removes up to `width` spaces at the start of the line
+fn strip_indent(line: &str, width: usize) -> String {
+    line[indent(line).min(width)..].to_string()
+}
+
+struct Fence {
+    indent: usize,
+    marker: char,
+    len: usize,
+    lang: String,
+}
+
+impl Fence {
+    fn parse(line: &str) -> Option<Self> {
+        let indent = indent(line);
+        let rest = &line[indent..];
+        let marker = rest.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+        let len = rest.len() - rest.trim_start_matches(marker).len();
+        let info = rest[len..].trim();
+        if indent > 3 || len < 3 || (marker == '`' && info.contains('`')) {
+            return None;
+        }
+        let lang = info.split_whitespace().next().unwrap_or("").to_string();
+        Some(Self { indent, marker, len, lang })
+    }
+
+    fn is_closed_by(&self, line: &str) -> bool {
+        let rest = line.trim_start_matches(' ');
+        let len = rest.len() - rest.trim_start_matches(self.marker).len();
+        indent(line) < 4 && len >= self.len && rest[len..].trim().is_empty()
+    }
+}
+
+/// The level and text of a `#` heading
+fn atx_heading(line: &str) -> Option<(usize, String)> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let rest = line.trim_start();
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    let text = &rest[level..];
+    if !(1..=6).contains(&level) || !(text.is_empty() || text.starts_with(' ')) {
+        return None;
+    }
+    // A closing sequence of `#` is dropped when it follows a space
+    let text = text.trim();
+    let without_closing = text.trim_end_matches('#');
+    let text = if without_closing.is_empty() || without_closing.ends_with(' ') { without_closing.trim_end() } else { text };
+    Some((level, text.to_string()))
+}
+
+/// `***`, `---` or `___`, with any spaces between the characters
+fn is_rule(line: &str) -> bool {
+    let rest = line.trim();
+    let Some(marker) = rest.chars().next().filter(|c| matches!(c, '*' | '-' | '_')) else {
+        return false;
+    };
+    indent(line) < 4 && rest.chars().all(|c| c == marker || c == ' ') && rest.matches(marker).count() >= 3
+}
+
+/// The level of the heading underlined by the line, `===` or `---`
+fn setext_level(line: &str) -> Option<usize> {
+    let rest = line.trim();
+    if indent(line) > 3 || rest.is_empty() {
+        return None;
+    }
+    if rest.chars().all(|c| c == '=') {
+        Some(1)
+    } else if rest.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// The content of a block quote line, after `>` and an optional space
+fn quote_content(line: &str) -> Option<&str> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let rest = line.trim_start().strip_prefix('>')?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+/// Whether the line starts with a tag, comment or declaration
+fn starts_html(line: &str) -> bool {
+    let rest = line.trim_start();
+    let mut chars = rest.chars();
+    chars.next() == Some('<') && chars.next().is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?'))
+}
+
+/// The tags starting an HTML block, others such as `<b>` start a paragraph
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "details", "dialog", "div", "dl", "fieldset", "figcaption", "figure",
+    "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "iframe", "li", "main", "nav", "ol", "p",
+    "pre", "script", "section", "style", "summary", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "ul",
+];
+
+/// Whether the line starts an HTML block, with a block level tag or a comment
+fn starts_html_block(line: &str) -> bool {
+    let Some(rest) = line.trim_start().strip_prefix('<') else {
+        return false;
+    };
+    if rest.starts_with("!--") {
+        return true;
+    }
+    let name: String = rest.trim_start_matches('/').chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+    indent(line) < 4 && BLOCK_TAGS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+struct ListMarker {
+    /// The number of an ordered item, `None` for a bullet
+    start: Option<u64>,
+    /// The bullet, or the `.` or `)` after the number
+    delimiter: char,
+    /// The indentation of the content of the item
+    width: usize,
+    /// Whether the item has nothing on its first line
+    empty: bool,
+}
+
+impl ListMarker {
+    fn parse(line: &str) -> Option<Self> {
+        let indent = indent(line);
+        if indent > 3 {
+            return None;
+        }
+        let rest = &line[indent..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let (start, delimiter) = match rest[digits..].chars().next()? {
+            c @ ('-' | '+' | '*') if digits == 0 => (None, c),
+            c @ ('.' | ')') if (1..=9).contains(&digits) => (Some(rest[..digits].parse().ok()?), c),
+            _ => return None,
+        };
+        let marker_end = indent + digits + 1;
+        let after = &line[marker_end..];
+        let spaces = indent_of(after);
+        if !after.is_empty() && spaces == 0 {
+            return None;
+        }
+        let empty = after.trim().is_empty();
+        // Content indented by more than 4 spaces is indented code, starting one space after the marker
+        let width = if empty || spaces > 4 { marker_end + 1 } else { marker_end + spaces };
+        Some(Self { start, delimiter, width, empty })
+    }
+
+    /// Whether an item with this marker belongs to the list started by `first`
+    fn continues(&self, first: &ListMarker) -> bool {
+        self.start.is_some() == first.start.is_some() && self.delimiter == first.delimiter
+    }
+}
+
+fn indent_of(text: &str) -> usize {
+    text.len() - text.trim_start_matches(' ').len()
+}
+
+/// The content of the code span at the start of `rest` opened by `run` backticks, with the
+/// length of the span
+fn code_span(rest: &str, run: usize) -> Option<(String, usize)> {
+    let mut from = run;
+    loop {
+        let start = from + rest[from..].find('`')?;
+        let len = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        if len == run {
+            let code = rest[run..start].replace('\n', " ");
+            let stripped = code.len() >= 2 && code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty();
+            let code = if stripped { code[1..code.len() - 1].to_string() } else { code };
+            return Some((code, start + len));
+        }
+        from = start + len;
+    }
+}
+
+struct Link {
+    text: String,
+    url: String,
+    title: Option<String>,
+    /// The length of the link in the source, from `[` to `)`
+    len: usize,
+}
+
+/// The position of the `]` closing each `[` of the text, the escaped ones left out
+fn match_brackets(text: &str) -> Vec<Option<usize>> {
+    let bytes = text.as_bytes();
+    let mut matches = vec![None; bytes.len()];
+    let mut open = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 1,
+            b'[' => open.push(pos),
+            b']' => {
+                if let Some(start) = open.pop() {
+                    matches[start] = Some(pos);
+                }
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+    matches
+}
+
+/// How deep the parentheses of a link destination may nest
+const MAX_LINK_PARENS: usize = 32;
+
+/// Parses an inline link `[text](url "title")` at the start of `rest`, whose text is closed by
+/// the `]` at `text_end`
+fn link(rest: &str, text_end: usize) -> Option<Link> {
+    let bytes = rest.as_bytes();
+    let mut pos = text_end + 1;
+    if bytes.get(pos) != Some(&b'(') {
+        return None;
+    }
+    pos += 1;
+    let skip_spaces = |pos: &mut usize| {
+        while bytes.get(*pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            *pos += 1;
+        }
+    };
+    skip_spaces(&mut pos);
+    let url = if bytes.get(pos) == Some(&b'<') {
+        let end = pos + 1 + rest[pos + 1..].find(['>', '<', '\n'])?;
+        if bytes[end] != b'>' {
+            return None;
+        }
+        let url = &rest[pos + 1..end];
+        pos = end + 1;
+        url
+    } else {
+        let start = pos;
+        let mut parens = 0;
+        while let Some(&b) = bytes.get(pos) {
+            match b {
+                b'\\' => pos += 1,
+                b'(' if parens == MAX_LINK_PARENS => return None,
+                b'(' => parens += 1,
+                b')' if parens == 0 => break,
+                b')' => parens -= 1,
+                b if b.is_ascii_whitespace() || b.is_ascii_control() => break,
+                _ => {}
+            }
+            pos += 1;
+        }
+        &rest[start..pos.min(rest.len())]
+    };
+    skip_spaces(&mut pos);
+    let mut title = None;
+    if let Some(&quote) = bytes.get(pos).filter(|b| matches!(b, b'"' | b'\'' | b'(')) {
+        let close = if quote == b'(' { b')' } else { quote };
+        let start = pos + 1;
+        pos = start;
+        while *bytes.get(pos)? != close {
+            // A title in parentheses cannot hold another one
+            if quote == b'(' && bytes[pos] == b'(' {
+                return None;
+            }
+            if bytes[pos] == b'\\' {
+                pos += 1;
+            }
+            pos += 1;
+        }
+        title = Some(unescape(&rest[start..pos]));
+        pos += 1;
+        skip_spaces(&mut pos);
+    }
+    if bytes.get(pos) != Some(&b')') {
+        return None;
+    }
+    Some(Link { text: rest[1..text_end].to_string(), url: unescape(url), title, len: pos + 1 })
+}
+
+/// Removes the backslashes escaping punctuation
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && next.is_ascii_punctuation() => out.push(chars.next().unwrap_or(c)),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The text of rendered inlines, for the `alt` of an image
+fn plain_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&out)
+}
+
+/// The index in `chars` of the character at byte `at`
+fn char_index(chars: &[(usize, char)], at: usize) -> usize {
+    chars.partition_point(|(byte, _)| *byte < at)
+}
+
+enum Node {
+    Html(String),
+    Delim(Delim),
+}
+
+impl Node {
+    fn html(&self) -> String {
+        match self {
+            Node::Html(html) => html.clone(),
+            Node::Delim(delim) => {
+                format!("{}{}{}", delim.closing_tags, delim.marker.to_string().repeat(delim.count), delim.opening_tags)
+            }
+        }
+    }
+}
+
+/// A run of `*` or `_` which may open or close emphasis
+struct Delim {
+    marker: char,
+    /// The characters not used by emphasis yet
+    count: usize,
+    original: usize,
+    can_open: bool,
+    can_close: bool,
+    opening_tags: String,
+    closing_tags: String,
+}
+
+impl Delim {
+    fn new(marker: char, count: usize, before: Option<char>, after: Option<char>) -> Self {
+        let is_space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+        let is_punctuation = |c: Option<char>| c.is_some_and(|c| c.is_ascii_punctuation());
+        let left = !is_space(after) && (!is_punctuation(after) || is_space(before) || is_punctuation(before));
+        let right = !is_space(before) && (!is_punctuation(before) || is_space(after) || is_punctuation(after));
+        let (can_open, can_close) = if marker == '*' {
+            (left, right)
+        } else {
+            (left && (!right || is_punctuation(before)), right && (!left || is_punctuation(after)))
+        };
+        Self { marker, count, original: count, can_open, can_close, opening_tags: String::new(), closing_tags: String::new() }
+    }
+}
+
+/// Matches the delimiter runs into `<em>` and `<strong>`, the innermost first
+///
+/// The runs which may still open emphasis are kept on a stack, and a closer finding no opener
+/// records where the search stopped, so the next closers of its kind do not search again.
+fn emphasis(nodes: &mut [Node]) {
+    let mut openers: Vec<usize> = Vec::new();
+    // By marker, length modulo 3 and whether the closer may open: the node before which no
+    // opener is left for it
+    let mut bottoms: HashMap<(char, usize, bool), usize> = HashMap::new();
+    for closer in 0..nodes.len() {
+        let Node::Delim(close) = &nodes[closer] else {
+            continue;
+        };
+        let key = (close.marker, close.original % 3, close.can_open);
+        while let Node::Delim(close) = &nodes[closer] {
+            if !close.can_close || close.count == 0 {
+                break;
+            }
+            let (marker, close_count, close_original, close_can_open) = (close.marker, close.count, close.original, close.can_open);
+            let bottom = bottoms.get(&key).copied().unwrap_or(0);
+            let found = (0..openers.len()).rev().take_while(|&s| openers[s] >= bottom).find(|&s| match &nodes[openers[s]] {
+                Node::Delim(open) => {
+                    open.marker == marker
+                        && open.can_open
+                        && open.count > 0
+                        // The rule of 3, so `*a**b*` is not read as `<em>a</em><em>b</em>`
+                        && !((open.can_close || close_can_open)
+                            && (open.original + close_original) % 3 == 0
+                            && !(open.original % 3 == 0 && close_original % 3 == 0))
+                }
+                Node::Html(_) => false,
+            });
+            let Some(found) = found else {
+                bottoms.insert(key, closer);
+                break;
+            };
+            let Node::Delim(open) = &mut nodes[openers[found]] else {
+                break;
+            };
+            let used = if open.count >= 2 && close_count >= 2 { 2 } else { 1 };
+            let tag = if used == 2 { "strong" } else { "em" };
+            open.count -= used;
+            open.opening_tags.insert_str(0, &format!("<{}>", tag));
+            // The runs between the two are left as text
+            openers.truncate(if open.count == 0 { found } else { found + 1 });
+            if let Node::Delim(close) = &mut nodes[closer] {
+                close.count -= used;
+                close.closing_tags.push_str(&format!("</{}>", tag));
+            }
+        }
+        if let Node::Delim(close) = &nodes[closer]
+            && close.can_open
+            && close.count > 0
+        {
+            openers.push(closer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_blocks() {
+        let text = "Title\n=====\n\nSome *text*\nacross lines.  \nBroken.\n\n## Sub ##\n\n---\n\n> quoted\ncontinued\n\n    code <b>\n";
+        assert_eq!(
+            markdown(text),
+            "<h1>Title</h1>\n<p>Some <em>text</em>\nacross lines.<br />\nBroken.</p>\n<h2>Sub</h2>\n<hr />\n\
+             <blockquote>\n<p>quoted\ncontinued</p>\n</blockquote>\n<pre><code>code &lt;b&gt;\n</code></pre>\n"
+        );
+        assert_eq!(markdown("~~~rust\nfn main() {}\n~~~"), "<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n");
+    }
+
+    #[test]
+    fn renders_lists() {
+        assert_eq!(markdown("- a\n- b\n  - c\n"), "<ul>\n<li>a</li>\n<li>b\n<ul>\n<li>c</li>\n</ul>\n</li>\n</ul>\n");
+        assert_eq!(markdown("3. a\n\n4. b\n"), "<ol start=\"3\">\n<li>\n<p>a</p>\n</li>\n<li>\n<p>b</p>\n</li>\n</ol>\n");
+        assert_eq!(markdown("- a\n+ b\n"), "<ul>\n<li>a</li>\n</ul>\n<ul>\n<li>b</li>\n</ul>\n");
+    }
+
+    #[test]
+    fn renders_inlines() {
+        assert_eq!(markdown("**bold** and _em_ and ***both***"), "<p><strong>bold</strong> and <em>em</em> and <em><strong>both</strong></em></p>\n");
+        assert_eq!(markdown("snake_case_name and 2*3*4"), "<p>snake_case_name and 2<em>3</em>4</p>\n");
+        assert_eq!(markdown("`a <b>` `` x ` y ``"), "<p><code>a &lt;b&gt;</code> <code>x ` y</code></p>\n");
+        assert_eq!(
+            markdown("[site](https://example.com \"Home\") ![cat *pic*](/cat.png)"),
+            "<p><a href=\"https://example.com\" title=\"Home\">site</a> <img src=\"/cat.png\" alt=\"cat pic\" /></p>\n"
+        );
+        assert_eq!(markdown("<https://example.com?a=1&b=2>"), "<p><a href=\"https://example.com?a=1&amp;b=2\">https://example.com?a=1&amp;b=2</a></p>\n");
+        assert_eq!(markdown("\\*not em\\* &copy; & co"), "<p>*not em* &copy; &amp; co</p>\n");
+    }
+
+    #[test]
+    fn is_safe_by_default() {
+        assert_eq!(markdown("<img src=x onerror=alert(1)>"), "<p>&lt;img src=x onerror=alert(1)&gt;</p>\n");
+        assert_eq!(markdown("[click](javascript:alert(1))"), "<p>click</p>\n");
+        assert_eq!(markdown("[click](JAVA&#x09;SCRIPT:alert(1))"), "<p>click</p>\n");
+        assert_eq!(markdown("<javascript:alert(1)>"), "<p>&lt;javascript:alert(1)&gt;</p>\n");
+
+        let renderer = MarkdownRenderer::new().raw_html(HtmlSanitizer::new());
+        assert_eq!(renderer.render("<b onclick=\"x()\">hi</b> *there*"), "<p><b>hi</b> <em>there</em></p>\n");
+        assert_eq!(renderer.render("<div>\n<script>x()</script>\n</div>"), "\n\n\n");
+    }
+
+    #[test]
+    fn highlights_and_filters() {
+        let renderer = MarkdownRenderer::new().highlighter(|lang, code| (lang == "rust").then(|| format!("<i>{}</i>", escape_html(code))));
+        assert_eq!(renderer.render("```rust\na<b\n```"), "<pre><code class=\"language-rust\"><i>a&lt;b\n</i></code></pre>\n");
+        assert_eq!(renderer.render("```py\nx\n```"), "<pre><code class=\"language-py\">x\n</code></pre>\n");
+        let filtered = renderer.filter(&Value::List(vec![Value::Str("*hi*".to_string())]));
+        assert!(matches!(&filtered, Value::List(items) if matches!(&items[0], Value::Str(html) if html == "<p><em>hi</em></p>\n")));
+    }
+
+    #[test]
+    fn bounds_nesting() {
+        let html = markdown(&">".repeat(100_000));
+        assert_eq!(html.matches("<blockquote>").count(), MAX_NESTING);
+        assert!(html.contains("<p>&gt;&gt;"));
+        let html = markdown(&format!("{}x", "1) ".repeat(10_000)));
+        assert_eq!(html.matches("<ol>").count(), MAX_NESTING);
+
+        let links = format!("{}a{}", "[".repeat(20_000), "](/u)".repeat(20_000));
+        let html = markdown(&links);
+        assert_eq!(html.matches("<a href").count(), MAX_NESTING);
+    }
+
+    #[test]
+    fn renders_unmatched_delimiters_in_linear_time() {
+        for text in ["[".repeat(300_000), "<".repeat(300_000), "[]".repeat(150_000), "[a](x".repeat(60_000), "a* ".repeat(100_000)] {
+            let html = markdown(&text);
+            assert!(html.starts_with("<p>"));
+        }
+        let html = markdown(&format!("{}{}", "[".repeat(60_000), "]".repeat(60_000)));
+        assert_eq!(html, format!("<p>{}{}</p>\n", "[".repeat(60_000), "]".repeat(60_000)));
+        assert_eq!(markdown("*a **b** c* `` ` ``"), "<p><em>a <strong>b</strong> c</em> <code>`</code></p>\n");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::http::encoding::HttpEncoding;
+use crate::http::encoding::{AcceptEncoding, HttpEncoding};
 use crate::http::safety::HttpSafety;
 
 use super::cookie::{Cookie, CookieMap}; 
@@ -8,6 +8,7 @@ use super::start_line::HttpStartLine;
 use std::collections::{HashMap, HashSet}; 
 use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader}; 
 use std::str; 
+use std::time::SystemTime; 
 
 /// RequestHeader is a struct that represents the headers of an HTTP request. 
 #[derive(Debug, Clone)]
@@ -30,6 +31,9 @@ pub struct HttpMeta {
     /// Transfer-Encoding header, used for chunked transfer encoding in responses 
     encoding: Option<HttpEncoding>, 
 
+    /// Accept-Encoding header, the content codings accepted by the client 
+    accept_encoding: Option<AcceptEncoding>, 
+
     // Host header, overrides the content length from the hashmap if present  
     host: Option<String>, 
 
@@ -38,7 +42,34 @@ pub struct HttpMeta {
     lang: Option<AcceptLang>, 
 
     /// Location header, used for redirects in responses 
-    location: Option<String> 
+    location: Option<String>, 
+
+    /// Authorization header, the credentials sent by the client 
+    authorization: Option<Authorization>, 
+
+    /// Cache-Control header, overrides the cache-control value from the hashmap if present 
+    cache_control: Option<CacheControl>, 
+
+    /// If-Match header, entity tags required for conditional requests 
+    if_match: Option<EntityTagCondition>, 
+
+    /// If-None-Match header, entity tags rejected for conditional requests 
+    if_none_match: Option<EntityTagCondition>, 
+
+    /// If-Modified-Since header 
+    if_modified_since: Option<SystemTime>, 
+
+    /// If-Unmodified-Since header 
+    if_unmodified_since: Option<SystemTime>, 
+
+    /// Forwarded header, the proxy hops the request went through 
+    forwarded: Option<Forwarded>, 
+
+    /// Range header in request, the byte ranges requested by the client 
+    range: Option<Range>, 
+
+    /// Content-Range header in response, the byte range carried by a partial response 
+    content_range: Option<ContentRange>, 
 } 
 
 /// Represents a value for an HTTP header, which can be either a single string or multiple values.
@@ -535,9 +566,19 @@ impl HttpMeta {
             content_disposition: None, 
             cookies: None, 
             encoding: None, 
+            accept_encoding: None, 
             host: None, 
             lang: None, 
             location: None, 
+            authorization: None, 
+            cache_control: None, 
+            if_match: None, 
+            if_none_match: None, 
+            if_modified_since: None, 
+            if_unmodified_since: None, 
+            forwarded: None, 
+            range: None, 
+            content_range: None, 
         }
     } 
 
@@ -2153,6 +2194,667 @@ impl HttpMeta {
         self.header.remove("content-encoding");
     }
 
+    /// Gets the Accept-Encoding preferences from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the accept-encoding header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<AcceptEncoding>` - The accepted content codings, or None if the header is not present.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use starberry_core::http::encoding::ContentCoding;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("accept-encoding".to_string(), HeaderValue::new("gzip;q=0.5, br"));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// let accept = meta.get_accept_encoding().unwrap();
+    /// assert!(accept.accepts(&ContentCoding::Gzip));
+    /// assert_eq!(accept.preferred(&[ContentCoding::Gzip, ContentCoding::Brotli]), Some(ContentCoding::Brotli));
+    /// ```
+    pub fn get_accept_encoding(&mut self) -> Option<AcceptEncoding> {
+        if let Some(ref accept) = self.accept_encoding {
+            return Some(accept.clone());
+        }
+        self.parse_accept_encoding()
+    }
+
+    /// Parses the Accept-Encoding header from the headers map and stores it in the accept_encoding field.
+    ///
+    /// Multiple Accept-Encoding headers are combined as a single comma-separated list.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<AcceptEncoding>` - The parsed value, or None if the header is not present.
+    pub fn parse_accept_encoding(&mut self) -> Option<AcceptEncoding> {
+        let accept = self.header
+            .get("accept-encoding")
+            .map(|values| AcceptEncoding::from_string(values.as_str()));
+        self.accept_encoding = accept.clone();
+        accept
+    }
+
+    /// Sets the accept_encoding field.
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_encoding` - The Accept-Encoding value to cache
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::HttpMeta;
+    /// use starberry_core::http::encoding::AcceptEncoding;
+    ///
+    /// let mut meta = HttpMeta::default();
+    /// meta.set_accept_encoding(Some(AcceptEncoding::from_string("gzip, deflate")));
+    /// assert!(meta.represent().contains("accept-encoding: gzip, deflate\r\n"));
+    /// ```
+    pub fn set_accept_encoding(&mut self, accept_encoding: Option<AcceptEncoding>) {
+        self.accept_encoding = accept_encoding;
+    }
+
+    /// Clears the cached accept_encoding field without modifying the header map.
+    ///
+    /// Subsequent calls to `get_accept_encoding()` will re-parse the value from headers.
+    pub fn clear_accept_encoding(&mut self) {
+        self.accept_encoding = None;
+    }
+
+    /// Deletes the Accept-Encoding header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_accept_encoding(&mut self) {
+        self.accept_encoding = None;
+        self.header.remove("accept-encoding");
+    }
+
+    /// Gets the credentials from the Authorization header.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the authorization header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Authorization>` - The credentials, or None if the header is missing or malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use starberry_core::http::http_value::Authorization;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("authorization".to_string(), HeaderValue::new("Basic YWxhZGRpbjpvcGVuc2VzYW1l"));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// let auth = meta.get_authorization().unwrap();
+    /// assert_eq!(auth.basic_credentials(), Some(("aladdin", "opensesame")));
+    /// ```
+    pub fn get_authorization(&mut self) -> Option<Authorization> {
+        if let Some(ref auth) = self.authorization {
+            return Some(auth.clone());
+        }
+        self.parse_authorization()
+    }
+
+    /// Parses the Authorization header from the headers map and stores it in the authorization field.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Authorization>` - The parsed credentials, or None if the header is missing or malformed.
+    pub fn parse_authorization(&mut self) -> Option<Authorization> {
+        let auth = self.header
+            .get("authorization")
+            .and_then(|value| Authorization::parse(&value.first()));
+        self.authorization = auth.clone();
+        auth
+    }
+
+    /// Sets the authorization field.
+    ///
+    /// # Arguments
+    ///
+    /// * `authorization` - The credentials to send
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::HttpMeta;
+    /// use starberry_core::http::http_value::Authorization;
+    ///
+    /// let mut meta = HttpMeta::default();
+    /// meta.set_authorization(Some(Authorization::bearer("token")));
+    /// assert!(meta.represent().contains("authorization: Bearer token\r\n"));
+    /// ```
+    pub fn set_authorization(&mut self, authorization: Option<Authorization>) {
+        self.authorization = authorization;
+    }
+
+    /// Clears the cached authorization field without modifying the header map.
+    ///
+    /// Subsequent calls to `get_authorization()` will re-parse the value from headers.
+    pub fn clear_authorization(&mut self) {
+        self.authorization = None;
+    }
+
+    /// Deletes the Authorization header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_authorization(&mut self) {
+        self.authorization = None;
+        self.header.remove("authorization");
+    }
+
+    /// Gets the Cache-Control directives from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the cache-control header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<CacheControl>` - The directives, or None if the header is not present.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("cache-control".to_string(), HeaderValue::new("private, max-age=60"));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// let cache_control = meta.get_cache_control().unwrap();
+    /// assert!(cache_control.private);
+    /// assert_eq!(cache_control.max_age, Some(60));
+    /// ```
+    pub fn get_cache_control(&mut self) -> Option<CacheControl> {
+        if let Some(ref cache_control) = self.cache_control {
+            return Some(cache_control.clone());
+        }
+        self.parse_cache_control()
+    }
+
+    /// Parses the Cache-Control header from the headers map and stores it in the cache_control field.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<CacheControl>` - The parsed directives, or None if not present.
+    pub fn parse_cache_control(&mut self) -> Option<CacheControl> {
+        let cache_control = self.header
+            .get("cache-control")
+            .map(|value| CacheControl::parse(&value.as_str()));
+        self.cache_control = cache_control.clone();
+        cache_control
+    }
+
+    /// Sets the cache_control field.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache_control` - The directives to set.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::HttpMeta;
+    /// use starberry_core::http::http_value::CacheControl;
+    ///
+    /// let mut meta = HttpMeta::default();
+    /// meta.set_cache_control(CacheControl::new().with_no_store());
+    /// assert!(meta.represent().contains("cache-control: no-store\r\n"));
+    /// ```
+    pub fn set_cache_control(&mut self, cache_control: CacheControl) {
+        self.cache_control = Some(cache_control);
+    }
+
+    /// Clears the cached cache_control field without modifying the header map.
+    ///
+    /// Subsequent calls to `get_cache_control()` will re-parse the value from headers.
+    pub fn clear_cache_control(&mut self) {
+        self.cache_control = None;
+    }
+
+    /// Deletes the Cache-Control header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_cache_control(&mut self) {
+        self.cache_control = None;
+        self.header.remove("cache-control");
+    }
+
+    /// Gets the If-Match header from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the if-match header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<EntityTagCondition>` - The parsed value, or None if the header is missing or malformed.
+    pub fn get_if_match(&mut self) -> Option<EntityTagCondition> {
+        if let Some(ref value) = self.if_match {
+            return Some(value.clone());
+        }
+        self.parse_if_match()
+    }
+
+    /// Parses the If-Match header from the headers map and stores it in the if_match field.
+    pub fn parse_if_match(&mut self) -> Option<EntityTagCondition> {
+        let value = self.header
+            .get("if-match")
+            .map(|value| EntityTagCondition::parse(&value.as_str()));
+        self.if_match = value.clone();
+        value
+    }
+
+    /// Sets the if_match field.
+    pub fn set_if_match(&mut self, if_match: Option<EntityTagCondition>) {
+        self.if_match = if_match;
+    }
+
+    /// Clears the cached if_match field without modifying the header map.
+    pub fn clear_if_match(&mut self) {
+        self.if_match = None;
+    }
+
+    /// Deletes the If-Match header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_if_match(&mut self) {
+        self.if_match = None;
+        self.header.remove("if-match");
+    }
+
+    /// Gets the If-None-Match header from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the if-none-match header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<EntityTagCondition>` - The parsed value, or None if the header is missing or malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use starberry_core::http::http_value::EntityTag;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("if-none-match".to_string(), HeaderValue::new("\"v1\", W/\"v2\""));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// let condition = meta.get_if_none_match().unwrap();
+    /// assert!(condition.matches_weak(&EntityTag::strong("v2")));
+    /// ```
+    pub fn get_if_none_match(&mut self) -> Option<EntityTagCondition> {
+        if let Some(ref value) = self.if_none_match {
+            return Some(value.clone());
+        }
+        self.parse_if_none_match()
+    }
+
+    /// Parses the If-None-Match header from the headers map and stores it in the if_none_match field.
+    pub fn parse_if_none_match(&mut self) -> Option<EntityTagCondition> {
+        let value = self.header
+            .get("if-none-match")
+            .map(|value| EntityTagCondition::parse(&value.as_str()));
+        self.if_none_match = value.clone();
+        value
+    }
+
+    /// Sets the if_none_match field.
+    pub fn set_if_none_match(&mut self, if_none_match: Option<EntityTagCondition>) {
+        self.if_none_match = if_none_match;
+    }
+
+    /// Clears the cached if_none_match field without modifying the header map.
+    pub fn clear_if_none_match(&mut self) {
+        self.if_none_match = None;
+    }
+
+    /// Deletes the If-None-Match header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_if_none_match(&mut self) {
+        self.if_none_match = None;
+        self.header.remove("if-none-match");
+    }
+
+    /// Gets the If-Modified-Since header from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the if-modified-since header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<SystemTime>` - The parsed value, or None if the header is missing or malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use std::collections::HashMap;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("if-modified-since".to_string(), HeaderValue::new("Sun, 06 Nov 1994 08:49:37 GMT"));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// assert_eq!(meta.get_if_modified_since(), Some(UNIX_EPOCH + Duration::from_secs(784111777)));
+    /// ```
+    pub fn get_if_modified_since(&mut self) -> Option<SystemTime> {
+        if self.if_modified_since.is_some() {
+            return self.if_modified_since;
+        }
+        self.parse_if_modified_since()
+    }
+
+    /// Parses the If-Modified-Since header from the headers map and stores it in the if_modified_since field.
+    pub fn parse_if_modified_since(&mut self) -> Option<SystemTime> {
+        let value = self.header
+            .get("if-modified-since")
+            .and_then(|value| parse_http_date(&value.first()));
+        self.if_modified_since = value;
+        value
+    }
+
+    /// Sets the if_modified_since field.
+    pub fn set_if_modified_since(&mut self, if_modified_since: Option<SystemTime>) {
+        self.if_modified_since = if_modified_since;
+    }
+
+    /// Clears the cached if_modified_since field without modifying the header map.
+    pub fn clear_if_modified_since(&mut self) {
+        self.if_modified_since = None;
+    }
+
+    /// Deletes the If-Modified-Since header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_if_modified_since(&mut self) {
+        self.if_modified_since = None;
+        self.header.remove("if-modified-since");
+    }
+
+    /// Gets the If-Unmodified-Since header from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the if-unmodified-since header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<SystemTime>` - The parsed value, or None if the header is missing or malformed.
+    pub fn get_if_unmodified_since(&mut self) -> Option<SystemTime> {
+        if self.if_unmodified_since.is_some() {
+            return self.if_unmodified_since;
+        }
+        self.parse_if_unmodified_since()
+    }
+
+    /// Parses the If-Unmodified-Since header from the headers map and stores it in the if_unmodified_since field.
+    pub fn parse_if_unmodified_since(&mut self) -> Option<SystemTime> {
+        let value = self.header
+            .get("if-unmodified-since")
+            .and_then(|value| parse_http_date(&value.first()));
+        self.if_unmodified_since = value;
+        value
+    }
+
+    /// Sets the if_unmodified_since field.
+    pub fn set_if_unmodified_since(&mut self, if_unmodified_since: Option<SystemTime>) {
+        self.if_unmodified_since = if_unmodified_since;
+    }
+
+    /// Clears the cached if_unmodified_since field without modifying the header map.
+    pub fn clear_if_unmodified_since(&mut self) {
+        self.if_unmodified_since = None;
+    }
+
+    /// Deletes the If-Unmodified-Since header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_if_unmodified_since(&mut self) {
+        self.if_unmodified_since = None;
+        self.header.remove("if-unmodified-since");
+    }
+
+    /// Evaluates the conditional request headers against the current state of the
+    /// target resource, following the order defined in RFC 9110 13.2.2.
+    ///
+    /// # Arguments
+    ///
+    /// * `etag` - The current entity tag of the resource, if any.
+    /// * `last_modified` - The last modification time of the resource, if known.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(StatusCode::PRECONDITION_FAILED)` if If-Match or If-Unmodified-Since fails.
+    /// * `Some(StatusCode::NOT_MODIFIED)` for GET and HEAD requests whose cached copy is still valid.
+    /// * `None` if the request should be processed normally.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use starberry_core::http::http_value::{EntityTag, StatusCode};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("if-none-match".to_string(), HeaderValue::new("\"v1\""));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// let etag = EntityTag::strong("v1");
+    /// assert_eq!(meta.evaluate_preconditions(Some(&etag), None), Some(StatusCode::NOT_MODIFIED));
+    /// let etag = EntityTag::strong("v2");
+    /// assert_eq!(meta.evaluate_preconditions(Some(&etag), None), None);
+    /// ```
+    pub fn evaluate_preconditions(
+        &mut self,
+        etag: Option<&EntityTag>,
+        last_modified: Option<SystemTime>,
+    ) -> Option<StatusCode> {
+        // HTTP dates only have a precision of one second
+        let last_modified = last_modified.map(|time| {
+            let secs = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs)
+        });
+        let safe_method = matches!(self.method(), HttpMethod::GET | HttpMethod::HEAD);
+
+        if let Some(condition) = self.get_if_match() {
+            if !etag.is_some_and(|etag| condition.matches_strong(etag)) {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        } else if last_modified
+            .zip(self.get_if_unmodified_since())
+            .is_some_and(|(modified, since)| modified > since)
+        {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+
+        if let Some(condition) = self.get_if_none_match() {
+            if etag.is_some_and(|etag| condition.matches_weak(etag)) {
+                return Some(if safe_method { StatusCode::NOT_MODIFIED } else { StatusCode::PRECONDITION_FAILED });
+            }
+        } else if safe_method
+            && last_modified
+                .zip(self.get_if_modified_since())
+                .is_some_and(|(modified, since)| modified <= since)
+        {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+
+        None
+    }
+
+    /// Gets the Forwarded header from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the forwarded header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Forwarded>` - The parsed value, or None if the header is missing or malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("forwarded".to_string(), HeaderValue::new("for=192.0.2.60;proto=https;host=example.com"));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// let forwarded = meta.get_forwarded().unwrap();
+    /// assert_eq!(forwarded.client_addr(), Some("192.0.2.60"));
+    /// assert_eq!(forwarded.elements()[0].host.as_deref(), Some("example.com"));
+    /// ```
+    pub fn get_forwarded(&mut self) -> Option<Forwarded> {
+        if let Some(ref value) = self.forwarded {
+            return Some(value.clone());
+        }
+        self.parse_forwarded()
+    }
+
+    /// Parses the Forwarded header from the headers map and stores it in the forwarded field.
+    ///
+    /// Multiple Forwarded headers are combined in order, as if they were a single list.
+    pub fn parse_forwarded(&mut self) -> Option<Forwarded> {
+        let value = self.header
+            .get("forwarded")
+            .map(|value| Forwarded::parse(&value.as_str()));
+        self.forwarded = value.clone();
+        value
+    }
+
+    /// Sets the forwarded field.
+    pub fn set_forwarded(&mut self, forwarded: Option<Forwarded>) {
+        self.forwarded = forwarded;
+    }
+
+    /// Clears the cached forwarded field without modifying the header map.
+    pub fn clear_forwarded(&mut self) {
+        self.forwarded = None;
+    }
+
+    /// Deletes the Forwarded header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_forwarded(&mut self) {
+        self.forwarded = None;
+        self.header.remove("forwarded");
+    }
+
+    /// Gets the Range header from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the range header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Range>` - The parsed value, or None if the header is missing or malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::{HttpMeta, HeaderValue};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("range".to_string(), HeaderValue::new("bytes=-500"));
+    /// let mut meta = HttpMeta::new(Default::default(), headers);
+    ///
+    /// let range = meta.get_range().unwrap();
+    /// assert_eq!(range.satisfiable_ranges(2000), vec![(1500, 1999)]);
+    /// ```
+    pub fn get_range(&mut self) -> Option<Range> {
+        if let Some(ref value) = self.range {
+            return Some(value.clone());
+        }
+        self.parse_range()
+    }
+
+    /// Parses the Range header from the headers map and stores it in the range field.
+    pub fn parse_range(&mut self) -> Option<Range> {
+        let value = self.header
+            .get("range")
+            .and_then(|value| Range::parse(&value.first()));
+        self.range = value.clone();
+        value
+    }
+
+    /// Sets the range field.
+    pub fn set_range(&mut self, range: Option<Range>) {
+        self.range = range;
+    }
+
+    /// Clears the cached range field without modifying the header map.
+    pub fn clear_range(&mut self) {
+        self.range = None;
+    }
+
+    /// Deletes the Range header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_range(&mut self) {
+        self.range = None;
+        self.header.remove("range");
+    }
+
+    /// Gets the Content-Range header from the HTTP meta data.
+    ///
+    /// Returns the cached value if available, otherwise parses
+    /// the content-range header from the headers map.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<ContentRange>` - The parsed value, or None if the header is missing or malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::meta::HttpMeta;
+    /// use starberry_core::http::http_value::ContentRange;
+    ///
+    /// let mut meta = HttpMeta::default();
+    /// meta.set_content_range(Some(ContentRange::bytes(0, 9, Some(100))));
+    /// assert_eq!(meta.get_content_range().unwrap().len(), 10);
+    /// assert!(meta.represent().contains("content-range: bytes 0-9/100\r\n"));
+    /// ```
+    pub fn get_content_range(&mut self) -> Option<ContentRange> {
+        if let Some(ref value) = self.content_range {
+            return Some(value.clone());
+        }
+        self.parse_content_range()
+    }
+
+    /// Parses the Content-Range header from the headers map and stores it in the content_range field.
+    pub fn parse_content_range(&mut self) -> Option<ContentRange> {
+        let value = self.header
+            .get("content-range")
+            .and_then(|value| ContentRange::parse(&value.first()));
+        self.content_range = value.clone();
+        value
+    }
+
+    /// Sets the content_range field.
+    pub fn set_content_range(&mut self, content_range: Option<ContentRange>) {
+        self.content_range = content_range;
+    }
+
+    /// Clears the cached content_range field without modifying the header map.
+    pub fn clear_content_range(&mut self) {
+        self.content_range = None;
+    }
+
+    /// Deletes the Content-Range header completely, clearing both the cached field
+    /// and removing it from the header map.
+    pub fn delete_content_range(&mut self) {
+        self.content_range = None;
+        self.header.remove("content-range");
+    }
+
     /// Serializes the HTTP meta data to a string representation.
     ///
     /// This method generates a properly formatted HTTP header section,
@@ -2250,6 +2952,58 @@ impl HttpMeta {
             } 
         } 
         
+        // Add accept-encoding if present 
+        if let Some(ref accept_encoding) = self.accept_encoding {
+            result.push_str(&format!("accept-encoding: {}\r\n", accept_encoding.to_header_string()));
+            handled_headers.insert("accept-encoding".to_string());
+        } 
+        
+        // Add authorization if present 
+        if let Some(ref authorization) = self.authorization {
+            result.push_str(&format!("authorization: {}\r\n", authorization));
+            handled_headers.insert("authorization".to_string());
+        } 
+
+        // Add cache-control if present 
+        if let Some(ref cache_control) = self.cache_control {
+            result.push_str(&format!("cache-control: {}\r\n", cache_control));
+            handled_headers.insert("cache-control".to_string());
+        } 
+
+        // Add conditional request headers if present 
+        if let Some(ref if_match) = self.if_match {
+            result.push_str(&format!("if-match: {}\r\n", if_match));
+            handled_headers.insert("if-match".to_string());
+        } 
+        if let Some(ref if_none_match) = self.if_none_match {
+            result.push_str(&format!("if-none-match: {}\r\n", if_none_match));
+            handled_headers.insert("if-none-match".to_string());
+        } 
+        if let Some(if_modified_since) = self.if_modified_since {
+            result.push_str(&format!("if-modified-since: {}\r\n", format_http_date(if_modified_since)));
+            handled_headers.insert("if-modified-since".to_string());
+        } 
+        if let Some(if_unmodified_since) = self.if_unmodified_since {
+            result.push_str(&format!("if-unmodified-since: {}\r\n", format_http_date(if_unmodified_since)));
+            handled_headers.insert("if-unmodified-since".to_string());
+        } 
+
+        // Add forwarded if present 
+        if let Some(ref forwarded) = self.forwarded {
+            result.push_str(&format!("forwarded: {}\r\n", forwarded));
+            handled_headers.insert("forwarded".to_string());
+        } 
+
+        // Add range and content-range if present 
+        if let Some(ref range) = self.range {
+            result.push_str(&format!("range: {}\r\n", range));
+            handled_headers.insert("range".to_string());
+        } 
+        if let Some(ref content_range) = self.content_range {
+            result.push_str(&format!("content-range: {}\r\n", content_range));
+            handled_headers.insert("content-range".to_string());
+        } 
+
         // Add cookies based on whether this is a request or response
         if let Some(ref cookies) = self.cookies {
             if self.start_line.is_request() {
@@ -2297,9 +3051,19 @@ impl Default for HttpMeta {
             content_disposition: None, 
             cookies: None, 
             encoding: None, 
+            accept_encoding: None, 
             host: None, 
             lang: None, 
             location: None, 
+            authorization: None, 
+            cache_control: None, 
+            if_match: None, 
+            if_none_match: None, 
+            if_modified_since: None, 
+            if_unmodified_since: None, 
+            forwarded: None, 
+            range: None, 
+            content_range: None, 
         }
     } 
 }
@@ -2,6 +2,8 @@ use std::fmt::Write;
 
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
 
+use starberry_lib::date::http_date_now;
+
 use crate::http::http_value::StatusCode;
 
 use super::meta::HttpMeta; 
@@ -24,11 +26,22 @@ pub async fn parse_lazy<R: AsyncRead + Unpin>(stream: &mut BufReader<R>, config:
 
 pub async fn parse_body<R: AsyncRead + Unpin>(meta: &mut HttpMeta, body: &mut HttpBody, reader: &mut BufReader<R>, safety_setting: &HttpSafety) -> Result<(), StatusCode> {
     if let HttpBody::Unparsed = *body {
-        *body = HttpBody::parse(
-            reader,
-            meta,
-            safety_setting 
-        ).await;
+        match HttpBody::try_parse(reader, meta, safety_setting).await {
+            Ok(HttpBody::Files(form)) if !safety_setting.check_files(&form) => {
+                *body = HttpBody::Empty;
+                return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+            }
+            Ok(parsed) => *body = parsed,
+            Err(e) => {
+                // The stream is left in an unknown state, do not try to read it again 
+                *body = HttpBody::Empty;
+                return Err(if e.kind() == std::io::ErrorKind::FileTooLarge {
+                    StatusCode::PAYLOAD_TOO_LARGE
+                } else {
+                    StatusCode::BAD_REQUEST
+                });
+            }
+        }
     }
     Ok(())
 } 
@@ -38,6 +51,7 @@ pub async fn send<W: AsyncWrite +  Unpin>(meta: &mut HttpMeta, body: &mut HttpBo
 
     // Add the values such as content length into header 
     let bin = body.into_static(meta).await; 
+    let bin = if add_standard_headers(meta) { bin } else { &[] }; 
     write!( 
         &mut headers,
         "{}", 
@@ -52,3 +66,22 @@ pub async fn send<W: AsyncWrite +  Unpin>(meta: &mut HttpMeta, body: &mut HttpBo
     
     Ok(()) 
 } 
+
+/// Adds the headers the server is responsible for (RFC 9110 6.6.1 and 8.6) to a response. 
+/// The `Date` header is added unless the handler set one. Responses which cannot carry 
+/// content (1xx and 204) lose their Content-Length. 
+/// Returns whether the body should be written. 
+fn add_standard_headers(meta: &mut HttpMeta) -> bool {
+    if !meta.start_line.is_response() {
+        return true;
+    }
+    if meta.get_header("date").is_none() {
+        meta.set_attribute("date", http_date_now());
+    }
+    let status = meta.start_line.status_code().as_u16();
+    if status < 200 || status == 204 {
+        meta.delete_content_length();
+        return false;
+    }
+    true
+}
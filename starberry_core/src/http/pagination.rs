@@ -0,0 +1,302 @@
+//! Pagination of list endpoints.
+//!
+//! `Pagination` is extracted from the `page` and `per_page` query parameters, or from
+//! `cursor` for keyset pagination. A `Page` wraps the items of one page and renders the
+//! standard envelope, with links to the neighbouring pages built from the current url.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[url(reg![&APP, LitUrl("posts")])]
+//! async fn posts() -> Result<HttpResponse, QueryErrors> {
+//!     let pagination: Pagination = req.query()?;
+//!     let total = count_posts().await;
+//!     let items: Vec<Value> = load_posts(pagination.offset(), pagination.limit()).await;
+//!     let page = Page::new(items, &pagination).with_total(total);
+//!     Ok(json_response(page.to_value(&req.request.meta.url())))
+//! }
+//! ```
+
+use akari::Value;
+
+use crate::object;
+
+use super::query::{FromQuery, FromQueryValue, QueryErrors, QueryMap};
+use starberry_lib::url_encoding::encode_url_owned;
+
+/// The page requested by a client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pagination {
+    /// The page number, starting at 1
+    pub page: u64,
+    pub per_page: u64,
+    /// The opaque position after which a keyset paginated list continues
+    pub cursor: Option<String>,
+}
+
+impl Pagination {
+    pub const DEFAULT_PER_PAGE: u64 = 20;
+    /// Larger `per_page` values are lowered to this one, so a client cannot load a whole table
+    pub const MAX_PER_PAGE: u64 = 100;
+
+    /// A page, with `per_page` kept between 1 and `MAX_PER_PAGE`
+    pub fn new(page: u64, per_page: u64) -> Self {
+        Self {
+            page: page.max(1),
+            per_page: per_page.clamp(1, Self::MAX_PER_PAGE),
+            cursor: None,
+        }
+    }
+
+    /// The page after `cursor`
+    pub fn after<T: Into<String>>(cursor: T, per_page: u64) -> Self {
+        Self {
+            cursor: Some(cursor.into()),
+            ..Self::new(1, per_page)
+        }
+    }
+
+    /// The number of rows to skip. Always 0 for cursor pagination
+    pub fn offset(&self) -> u64 {
+        if self.cursor.is_some() {
+            return 0;
+        }
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+
+    /// The number of rows to return
+    pub fn limit(&self) -> u64 {
+        self.per_page
+    }
+
+    pub fn is_cursor(&self) -> bool {
+        self.cursor.is_some()
+    }
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self::new(1, Self::DEFAULT_PER_PAGE)
+    }
+}
+
+/// Reads `page`, `per_page` and `cursor`. Missing values use the defaults, a `per_page`
+/// above `MAX_PER_PAGE` is lowered, and values which are not numbers are a 400
+impl FromQuery for Pagination {
+    fn from_query(query: &QueryMap) -> Result<Self, QueryErrors> {
+        let mut errors = QueryErrors::new();
+        let mut number = |key: &str, default: u64| match query.get(key) {
+            Some(value) => u64::from_query_value(value).unwrap_or_else(|e| {
+                errors.push(key, e);
+                default
+            }),
+            None => default,
+        };
+        let page = number("page", 1);
+        let per_page = number("per_page", Self::DEFAULT_PER_PAGE);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let mut pagination = Self::new(page, per_page);
+        pagination.cursor = query.get("cursor").filter(|c| !c.is_empty()).map(str::to_string);
+        Ok(pagination)
+    }
+}
+
+/// Links to the neighbouring pages
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageLinks {
+    pub first: Option<String>,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+    pub last: Option<String>,
+}
+
+/// One page of a list with what is known about the others
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub per_page: u64,
+    /// The number of items in the whole list, when it was counted
+    pub total: Option<u64>,
+    /// The cursor of the next page for cursor pagination, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, pagination: &Pagination) -> Self {
+        Self {
+            items,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total: None,
+            next_cursor: None,
+        }
+    }
+
+    pub fn with_total(mut self, total: u64) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    pub fn with_next_cursor<C: Into<String>>(mut self, cursor: C) -> Self {
+        self.next_cursor = Some(cursor.into());
+        self
+    }
+
+    /// The number of pages, when the total is known. An empty list has one empty page
+    pub fn total_pages(&self) -> Option<u64> {
+        self.total.map(|total| total.div_ceil(self.per_page).max(1))
+    }
+
+    pub fn has_prev(&self) -> bool {
+        self.next_cursor.is_none() && self.page > 1
+    }
+
+    /// Without a total or a cursor, a full page is assumed to have a successor
+    pub fn has_next(&self) -> bool {
+        if self.next_cursor.is_some() {
+            return true;
+        }
+        match self.total_pages() {
+            Some(pages) => self.page < pages,
+            None => self.items.len() as u64 >= self.per_page,
+        }
+    }
+
+    /// Builds the links from the target of the current request, e.g. `/posts?tag=rust&page=2`,
+    /// keeping its other query parameters
+    pub fn links(&self, target: &str) -> PageLinks {
+        let link = |page: Option<u64>, cursor: Option<&str>| page_target(target, page, cursor, self.per_page);
+        if let Some(cursor) = &self.next_cursor {
+            return PageLinks {
+                first: Some(link(None, None)),
+                next: Some(link(None, Some(cursor))),
+                ..Default::default()
+            };
+        }
+        PageLinks {
+            first: Some(link(Some(1), None)),
+            prev: self.has_prev().then(|| link(Some(self.page - 1), None)),
+            next: self.has_next().then(|| link(Some(self.page + 1), None)),
+            last: self.total_pages().map(|pages| link(Some(pages), None)),
+        }
+    }
+
+    /// Maps the items, keeping the pagination
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            page: self.page,
+            per_page: self.per_page,
+            total: self.total,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+impl<T: Into<Value>> Page<T> {
+    /// The JSON envelope of the page:
+    /// `{"items": [..], "page": 2, "per_page": 20, "total": 45, "total_pages": 3, "links": {..}}`.
+    /// `total`, `total_pages` and the links which do not exist are left out
+    pub fn to_value(self, target: &str) -> Value {
+        let links = self.links(target);
+        let total_pages = self.total_pages();
+        let mut envelope = object!({});
+        envelope.set("items", Value::List(self.items.into_iter().map(Into::into).collect()));
+        envelope.set("page", self.page);
+        envelope.set("per_page", self.per_page);
+        if let Some(total) = self.total {
+            envelope.set("total", total);
+        }
+        if let Some(pages) = total_pages {
+            envelope.set("total_pages", pages);
+        }
+        if let Some(cursor) = self.next_cursor {
+            envelope.set("next_cursor", cursor);
+        }
+        let mut link_values = object!({});
+        for (name, link) in [("first", links.first), ("prev", links.prev), ("next", links.next), ("last", links.last)] {
+            if let Some(link) = link {
+                link_values.set(name, link);
+            }
+        }
+        envelope.set("links", link_values);
+        envelope
+    }
+}
+
+/// Replaces the pagination parameters of a request target
+fn page_target(target: &str, page: Option<u64>, cursor: Option<&str>, per_page: u64) -> String {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut params: Vec<String> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or("");
+            !pair.is_empty() && !matches!(key, "page" | "per_page" | "cursor")
+        })
+        .map(str::to_string)
+        .collect();
+    if let Some(page) = page {
+        params.push(format!("page={}", page));
+    }
+    if let Some(cursor) = cursor {
+        params.push(format!("cursor={}", encode_url_owned(cursor)));
+    }
+    params.push(format!("per_page={}", per_page));
+    format!("{}?{}", path, params.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_and_clamps() {
+        let pagination = Pagination::from_query_str("page=3&per_page=500").unwrap();
+        assert_eq!(pagination, Pagination::new(3, Pagination::MAX_PER_PAGE));
+        assert_eq!(pagination.offset(), 200);
+        assert_eq!(Pagination::from_query_str("").unwrap(), Pagination::default());
+        let errors = Pagination::from_query_str("page=two").unwrap_err();
+        assert!(errors.get("page").is_some());
+        let cursor = Pagination::from_query_str("cursor=abc&per_page=10").unwrap();
+        assert!(cursor.is_cursor());
+        assert_eq!(cursor.offset(), 0);
+    }
+
+    #[test]
+    fn links_keep_other_parameters() {
+        let page = Page::new(vec![1; 20], &Pagination::new(2, 20)).with_total(45);
+        assert_eq!(page.total_pages(), Some(3));
+        let links = page.links("/posts?tag=rust&page=2&per_page=20");
+        assert_eq!(links.prev.as_deref(), Some("/posts?tag=rust&page=1&per_page=20"));
+        assert_eq!(links.next.as_deref(), Some("/posts?tag=rust&page=3&per_page=20"));
+        assert_eq!(links.last.as_deref(), Some("/posts?tag=rust&page=3&per_page=20"));
+
+        let last = Page::new(vec![1; 5], &Pagination::new(3, 20)).with_total(45);
+        assert!(!last.has_next());
+        assert_eq!(last.links("/posts").next, None);
+    }
+
+    #[test]
+    fn cursor_links() {
+        let page = Page::new(vec![1; 10], &Pagination::after("a", 10)).with_next_cursor("b c");
+        let links = page.links("/feed?cursor=a");
+        assert_eq!(links.next.as_deref(), Some("/feed?cursor=b%20c&per_page=10"));
+        assert_eq!(links.prev, None);
+    }
+
+    #[test]
+    fn envelope() {
+        let page = Page::new(vec!["a", "b"], &Pagination::new(1, 2)).with_total(3);
+        let envelope = page.to_value("/letters");
+        assert_eq!(envelope.get("items").len(), 2);
+        assert_eq!(envelope.get("items").idx(1).string(), "b");
+        assert_eq!(envelope.get("page").numerical(), 1.0);
+        assert_eq!(envelope.get("total").numerical(), 3.0);
+        assert_eq!(envelope.get("total_pages").numerical(), 2.0);
+        assert_eq!(envelope.get("links").get("next").string(), "/letters?page=2&per_page=2");
+        assert_eq!(envelope.get("links").get("prev"), &Value::None);
+        assert_eq!(envelope.get("next_cursor"), &Value::None);
+    }
+}
@@ -0,0 +1,276 @@
+//! Problem details for HTTP APIs (RFC 7807).
+//!
+//! A `Problem` describes an error with a type uri, a title, a status, a detail and the
+//! instance it happened on. It is sent as an `application/problem+json` document, or
+//! as an HTML error page to clients preferring HTML, see `Problem::respond`.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[url(reg![&APP, LitUrl("account"), ArgUrl("id")])]
+//! async fn account() -> Result<HttpResponse, Problem> {
+//!     let id = req.get_arg("id").unwrap_or_default();
+//!     let Some(account) = find_account(&id) else {
+//!         return Err(Problem::new(StatusCode::NOT_FOUND)
+//!             .with_type("https://example.com/probs/no-account")
+//!             .with_detail(format!("No account {}", id))
+//!             .with_instance(req.path()));
+//!     };
+//!     Ok(akari_json!(account))
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use akari::Value;
+
+use super::error::{HttpError, IntoResponse};
+use super::http_value::{HttpContentType, StatusCode};
+use super::response::HttpResponse;
+use super::response::response_templates::{html_response, json_response};
+
+/// The media type of problem documents
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The type of a problem which has no more semantics than its status
+pub const ABOUT_BLANK: &str = "about:blank";
+
+/// An RFC 7807 problem document
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// A uri identifying the kind of problem, `about:blank` by default
+    pub type_uri: String,
+    /// A short summary of the kind of problem, the reason phrase of the status by default
+    pub title: String,
+    pub status: StatusCode,
+    /// An explanation specific to this occurrence
+    pub detail: Option<String>,
+    /// A uri identifying this occurrence, e.g. the request path
+    pub instance: Option<String>,
+    /// Additional members of the document
+    pub extensions: HashMap<String, Value>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            type_uri: ABOUT_BLANK.to_string(),
+            title: status.reason_phrase().to_string(),
+            status,
+            detail: None,
+            instance: None,
+            extensions: HashMap::new(),
+        }
+    }
+
+    pub fn with_type<T: Into<String>>(mut self, type_uri: T) -> Self {
+        self.type_uri = type_uri.into();
+        self
+    }
+
+    pub fn with_title<T: Into<String>>(mut self, title: T) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn with_detail<T: Into<String>>(mut self, detail: T) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_instance<T: Into<String>>(mut self, instance: T) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Adds a member to the document. The standard members cannot be replaced
+    pub fn with_extension<T: Into<String>>(mut self, name: T, value: Value) -> Self {
+        self.extensions.insert(name.into(), value);
+        self
+    }
+
+    /// The problem document
+    pub fn to_value(&self) -> Value {
+        let mut map = self.extensions.clone();
+        map.insert("type".to_string(), Value::Str(self.type_uri.clone()));
+        map.insert("title".to_string(), Value::Str(self.title.clone()));
+        map.insert(
+            "status".to_string(),
+            Value::from_json(&self.status.as_u16().to_string()).unwrap_or(Value::new("")),
+        );
+        if let Some(detail) = &self.detail {
+            map.insert("detail".to_string(), Value::Str(detail.clone()));
+        }
+        if let Some(instance) = &self.instance {
+            map.insert("instance".to_string(), Value::Str(instance.clone()));
+        }
+        Value::Dict(map)
+    }
+
+    /// Reads a problem document. Missing members take their default values
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let Value::Dict(map) = value else {
+            return None;
+        };
+        let text = |key: &str| match map.get(key) {
+            Some(Value::Str(s)) => Some(s.clone()),
+            _ => None,
+        };
+        let status = map
+            .get("status")
+            .and_then(|status| status.into_json().trim().trim_matches('"').parse::<f64>().ok())
+            .map(|status| StatusCode::from_u16(status as u16))?;
+        let mut problem = Self::new(status);
+        if let Some(type_uri) = text("type") {
+            problem.type_uri = type_uri;
+        }
+        if let Some(title) = text("title") {
+            problem.title = title;
+        }
+        problem.detail = text("detail");
+        problem.instance = text("instance");
+        problem.extensions = map
+            .iter()
+            .filter(|(key, _)| !["type", "title", "status", "detail", "instance"].contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Some(problem)
+    }
+
+    /// The problem as an `application/problem+json` response
+    pub fn to_json_response(&self) -> HttpResponse {
+        json_response(self.to_value())
+            .status(self.status.clone())
+            .content_type(HttpContentType::from_str(PROBLEM_JSON))
+    }
+
+    /// The problem as a minimal HTML error page
+    pub fn to_html_response(&self) -> HttpResponse {
+        let mut page = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{} {}</title></head><body><h1>{}</h1>",
+            self.status.as_u16(),
+            escape_html(&self.title),
+            escape_html(&self.title),
+        );
+        if let Some(detail) = &self.detail {
+            page.push_str(&format!("<p>{}</p>", escape_html(detail)));
+        }
+        page.push_str("</body></html>");
+        html_response(page).status(self.status.clone())
+    }
+
+    /// Answers with the HTML page or the problem document, following the `Accept`
+    /// header of the request, see `prefers_html`
+    pub fn respond(&self, accept: Option<&str>) -> HttpResponse {
+        if prefers_html(accept) { self.to_html_response() } else { self.to_json_response() }
+    }
+}
+
+/// Whether a client sending this `Accept` header wants an HTML page rather than a JSON
+/// document. Browsers list `text/html`, API clients list JSON types or nothing, so HTML
+/// is only chosen when it is explicitly accepted at least as much as JSON
+pub fn prefers_html(accept: Option<&str>) -> bool {
+    let Some(accept) = accept else {
+        return false;
+    };
+    let mut html = 0.0f32;
+    let mut json = 0.0f32;
+    for entry in accept.split(',') {
+        let mut parts = entry.split(';');
+        let media = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media.as_str() {
+            "text/html" | "application/xhtml+xml" => html = html.max(q),
+            "application/json" | PROBLEM_JSON | "application/*" => json = json.max(q),
+            _ => {}
+        }
+    }
+    html > 0.0 && html >= json
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+impl From<HttpError> for Problem {
+    fn from(err: HttpError) -> Self {
+        let problem = Self::new(err.status);
+        if err.message.is_empty() { problem } else { problem.with_detail(err.message) }
+    }
+}
+
+impl From<StatusCode> for Problem {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status)
+    }
+}
+
+/// Without the request, a problem is always sent as a problem document. Place the
+/// `ProblemDetails` middleware to serve HTML pages to browsers
+impl IntoResponse for Problem {
+    fn into_response(self) -> HttpResponse {
+        self.to_json_response()
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{} {}: {}", self.status.as_u16(), self.title, detail),
+            None => write!(f, "{} {}", self.status.as_u16(), self.title),
+        }
+    }
+}
+
+impl std::error::Error for Problem {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::body::HttpBody;
+
+    #[test]
+    fn negotiates_on_accept() {
+        assert!(!prefers_html(None));
+        assert!(!prefers_html(Some("*/*")));
+        assert!(!prefers_html(Some("application/json")));
+        assert!(prefers_html(Some("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")));
+        assert!(!prefers_html(Some("text/html;q=0.5, application/problem+json")));
+        assert!(!prefers_html(Some("text/html;q=0")));
+    }
+
+    #[test]
+    fn round_trips_documents() {
+        let problem = Problem::new(StatusCode::NOT_FOUND)
+            .with_type("https://example.com/probs/no-account")
+            .with_detail("No account 7")
+            .with_instance("/account/7")
+            .with_extension("account", Value::new("7"));
+        let read = Problem::from_value(&problem.to_value()).unwrap();
+        assert_eq!(read.status, StatusCode::NOT_FOUND);
+        assert_eq!(read.title, "Not Found");
+        assert_eq!(read.type_uri, problem.type_uri);
+        assert_eq!(read.detail, problem.detail);
+        assert_eq!(read.instance, problem.instance);
+        assert!(read.extensions.contains_key("account"));
+
+        let mut response = problem.to_json_response();
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(response.meta.get_content_type().unwrap().to_string(), PROBLEM_JSON);
+    }
+
+    #[test]
+    fn html_pages_are_escaped() {
+        let response = Problem::new(StatusCode::BAD_REQUEST).with_detail("<script>").to_html_response();
+        let HttpBody::Binary(page) = &response.body else { panic!("the page is not binary") };
+        let page = String::from_utf8_lossy(page);
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(page.contains("Bad Request"));
+    }
+}
@@ -0,0 +1,288 @@
+//! The address of the client behind reverse proxies.
+//!
+//! Behind a load balancer the peer of every connection is the proxy, the client being named
+//! in the `Forwarded` or `X-Forwarded-For` header. Since a client can send these headers
+//! itself, they are only read when the peer is one of the `TrustedProxies` given to
+//! `AppBuilder::trusted_proxies`, and only the one header the proxies set is read, as a proxy
+//! passes the other one on as the client sent it. The hops are then walked from the closest
+//! one, and the first address which is not a trusted proxy is the client.
+//! `HttpReqCtx::client_ip` gives it, or the peer address when no proxy is trusted.
+//!
+//! # Example
+//! ```rust,ignore
+//! App::new().trusted_proxies(TrustedProxies::new().trust("10.0.0.0/8")).build();
+//! // Behind a proxy setting `Forwarded` rather than `X-Forwarded-For`
+//! App::new().trusted_proxies(TrustedProxies::new().trust("10.0.0.0/8").header(ForwardedHeader::Forwarded)).build();
+//! let ip = req.client_ip();
+//! ```
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::http::http_value::Forwarded;
+use crate::http::meta::HttpMeta;
+
+/// A range of IPv4 or IPv6 addresses such as `10.0.0.0/8` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    address: IpAddr,
+    prefix: u8,
+}
+
+/// The error of a range which could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCidr(pub String);
+
+impl fmt::Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not an IP address or a CIDR range", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCidr {}
+
+impl Cidr {
+    /// Creates the range of the addresses sharing the first `prefix` bits of the address
+    pub fn new(address: IpAddr, prefix: u8) -> Result<Self, InvalidCidr> {
+        let address = address.to_canonical();
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(InvalidCidr(format!("{}/{}", address, prefix)));
+        }
+        Ok(Self { address, prefix })
+    }
+
+    /// Parses a range such as `192.168.0.0/16`. A single address is a range of one address
+    pub fn parse(text: &str) -> Result<Self, InvalidCidr> {
+        let text = text.trim();
+        let invalid = || InvalidCidr(text.to_string());
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (text, None),
+        };
+        let address = address.trim_start_matches('[').trim_end_matches(']');
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let prefix = prefix.unwrap_or(if address.to_canonical().is_ipv4() { 32 } else { 128 });
+        Self::new(address, prefix).map_err(|_| invalid())
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether the address is in the range. IPv4 addresses mapped to IPv6, `::ffff:a.b.c.d`,
+    /// are compared as IPv4 addresses
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// The header in which the trusted proxies name the client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For: <client>, <proxy1>`, set by most proxies and load balancers
+    #[default]
+    XForwardedFor,
+    /// `Forwarded: for=<client>, for=<proxy1>`, as defined by RFC 7239
+    Forwarded,
+}
+
+/// The proxies whose forwarding header is believed, set with `AppBuilder::trusted_proxies`
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+    header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts the loopback and private networks, where the proxies of most deployments are
+    pub fn local() -> Self {
+        ["127.0.0.0/8", "10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "::1", "fc00::/7"]
+            .into_iter()
+            .fold(Self::new(), |proxies, range| proxies.trust(range))
+    }
+
+    /// Trusts the proxies of a range such as `10.0.0.0/8`, or a single address.
+    /// Panics if the range is invalid, as the application could not be trusted to start
+    pub fn trust(self, range: &str) -> Self {
+        let range = Cidr::parse(range).unwrap_or_else(|e| panic!("Invalid trusted proxy: {}", e));
+        self.trust_range(range)
+    }
+
+    /// Trusts the proxies of a parsed range
+    pub fn trust_range(mut self, range: Cidr) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Reads the client from this header, `X-Forwarded-For` by default. The other header is
+    /// ignored, since the proxies pass it on unchecked
+    pub fn header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn ranges(&self) -> &[Cidr] {
+        &self.ranges
+    }
+
+    pub fn forwarded_header(&self) -> ForwardedHeader {
+        self.header
+    }
+
+    /// Whether the address is one of a trusted proxy
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// The address of the client of a request received from the peer. The forwarding headers
+    /// are walked from the closest hop while it is a trusted proxy, the first other address
+    /// being the client. When every hop is trusted, the farthest one is the client
+    pub fn resolve(&self, peer: IpAddr, meta: &HttpMeta) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+        let hops = forwarded_hops(meta, self.header);
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            // An obfuscated or garbled hop cannot be followed further
+            let Some(ip) = hop.as_deref().and_then(parse_node) else {
+                break;
+            };
+            client = ip;
+            if !self.is_trusted(ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// The address of each hop named in the header, from the client to the closest,
+/// `None` for a hop without a usable address
+fn forwarded_hops(meta: &HttpMeta, header: ForwardedHeader) -> Vec<Option<String>> {
+    match header {
+        ForwardedHeader::Forwarded => meta
+            .get_header("forwarded")
+            .map(|value| {
+                Forwarded::parse(&value)
+                    .elements()
+                    .iter()
+                    .map(|element| element.for_addr().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        ForwardedHeader::XForwardedFor => meta
+            .get_header("x-forwarded-for")
+            .map(|value| value.split(',').map(|hop| Some(hop.trim().to_string())).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Reads an address written as `192.0.2.60`, `192.0.2.60:4711`, `2001:db8::1` or `[2001:db8::1]:4711`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.rsplit_once(':').and_then(|(ip, _)| ip.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::meta::HeaderValue;
+    use std::collections::HashMap;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    fn meta(name: &str, value: &str) -> HttpMeta {
+        let mut headers = HashMap::new();
+        headers.insert(name.to_string(), HeaderValue::new(value));
+        HttpMeta::new(Default::default(), headers)
+    }
+
+    #[test]
+    fn matches_ranges() {
+        let private = Cidr::parse("10.0.0.0/8").unwrap();
+        assert!(private.contains(ip("10.20.30.40")));
+        assert!(private.contains(ip("::ffff:10.1.2.3")));
+        assert!(!private.contains(ip("11.0.0.1")));
+        let documentation = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(documentation.contains(ip("2001:db8:cafe::17")));
+        assert!(!documentation.contains(ip("2001:db9::1")));
+        assert!(!documentation.contains(ip("10.0.0.1")));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("203.0.113.9")));
+        assert!(Cidr::parse("::1").unwrap().contains(ip("::1")));
+        assert_eq!(Cidr::parse("192.168.1.7").unwrap().to_string(), "192.168.1.7/32");
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("localhost").is_err());
+    }
+
+    #[test]
+    fn resolves_the_client_behind_trusted_proxies() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8");
+        let chain = meta("x-forwarded-for", "198.51.100.1, 203.0.113.5, 10.0.0.2");
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &chain), ip("203.0.113.5"));
+        // The headers of an untrusted peer are ignored
+        assert_eq!(proxies.resolve(ip("192.0.2.1"), &chain), ip("192.0.2.1"));
+
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &meta("via", "1.1 proxy")), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn reads_only_the_configured_header() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8");
+        // A `Forwarded` header sent by the client is passed on by a proxy setting `X-Forwarded-For`
+        let mut spoofed = meta("x-forwarded-for", "203.0.113.5");
+        spoofed.set_attribute("forwarded", "for=198.51.100.1");
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &spoofed), ip("203.0.113.5"));
+
+        let proxies = proxies.header(ForwardedHeader::Forwarded);
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &spoofed), ip("198.51.100.1"));
+        let forwarded = meta("forwarded", "for=\"[2001:db8::17]:4711\", for=10.0.0.3");
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &forwarded), ip("2001:db8::17"));
+        let hidden = meta("forwarded", "for=_hidden, for=10.0.0.3");
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &hidden), ip("10.0.0.3"));
+        let only_xff = meta("x-forwarded-for", "203.0.113.5");
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &only_xff), ip("10.0.0.1"));
+    }
+}
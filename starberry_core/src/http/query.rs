@@ -0,0 +1,255 @@
+//! Typed extraction of query strings.
+//!
+//! `QueryMap` keeps every `key=value` pair of a query string, including repeated keys.
+//! Structs deriving `FromQuery` are filled from it, each field being parsed with
+//! `FromQueryValue` (or a custom function), and every invalid field is reported in
+//! `QueryErrors`, which is sent as a 400 Bad Request.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[derive(FromQuery)]
+//! struct Search {
+//!     q: String,
+//!     #[query(default = 1)]
+//!     page: u32,
+//!     tag: Vec<String>,
+//!     #[query(rename = "sort-by")]
+//!     sort_by: Option<String>,
+//! }
+//!
+//! #[url(reg![&APP, LitUrl("search")])]
+//! async fn search() -> Result<HttpResponse, QueryErrors> {
+//!     let search: Search = req.query()?;
+//!     Ok(text_response(format!("{} page {}", search.q, search.page)))
+//! }
+//! ```
+
+use std::fmt;
+
+use akari::Value;
+use starberry_lib::url_encoding::decode_url_owned;
+
+use crate::object;
+
+use super::error::IntoResponse;
+use super::http_value::StatusCode;
+use super::response::HttpResponse;
+use super::response::response_templates::json_response;
+
+/// The decoded pairs of a query string, in their order of appearance
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryMap {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryMap {
+    /// Parses a query string, with or without its leading `?`.
+    /// `+` stands for a space, a key without `=` has an empty value
+    pub fn parse(query: &str) -> Self {
+        let query = query.strip_prefix('?').unwrap_or(query);
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_component(key), decode_component(value))
+            })
+            .collect();
+        Self { pairs }
+    }
+
+    /// Parses the query string of a request target such as `/search?q=rust`
+    pub fn from_target(target: &str) -> Self {
+        match target.split_once('?') {
+            Some((_, query)) => Self::parse(query.split('#').next().unwrap_or("")),
+            None => Self::default(),
+        }
+    }
+
+    /// The first value of a key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of a repeated key, such as `?tag=a&tag=b`
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.pairs.iter().filter(|(k, _)| k == key).map(|(_, v)| v.as_str()).collect()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.pairs.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Appends a pair, after any pair of the same key
+    pub fn push<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.pairs.push((key.into(), value.into()));
+    }
+}
+
+impl FromIterator<(String, String)> for QueryMap {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(pairs: I) -> Self {
+        Self { pairs: pairs.into_iter().collect() }
+    }
+}
+
+fn decode_component(component: &str) -> String {
+    decode_url_owned(&component.replace('+', " "))
+}
+
+/// A field of a query which is missing or could not be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every invalid field of a query. Sent as a 400 Bad Request whose JSON body maps
+/// each field to its error, e.g. `{"errors": {"page": "invalid digit found in string"}}`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryErrors(pub Vec<QueryError>);
+
+impl QueryErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<F: Into<String>, M: Into<String>>(&mut self, field: F, message: M) {
+        self.0.push(QueryError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The error of a field
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.0.iter().find(|e| e.field == field).map(|e| e.message.as_str())
+    }
+}
+
+impl fmt::Display for QueryErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(|e| format!("{}: {}", e.field, e.message)).collect();
+        write!(f, "Invalid query: {}", errors.join(", "))
+    }
+}
+
+impl std::error::Error for QueryErrors {}
+
+impl IntoResponse for QueryErrors {
+    fn into_response(self) -> HttpResponse {
+        let mut errors = object!({});
+        for error in &self.0 {
+            errors.set(error.field.as_str(), error.message.as_str());
+        }
+        let mut body = object!({});
+        body.set("errors", errors);
+        json_response(body).status(StatusCode::BAD_REQUEST)
+    }
+}
+
+/// A struct filled from a query string, usually through `#[derive(FromQuery)]`.
+///
+/// The derive accepts these field attributes:
+/// - `#[query(rename = "name")]` reads another key than the field name
+/// - `#[query(default)]` uses `Default::default()` when the key is missing,
+///   `#[query(default = expr)]` uses the expression
+/// - `#[query(with = path::to::function)]` parses the values with a
+///   `fn(&str) -> Result<T, E>`, `E` being displayable, instead of `FromQueryValue`
+///
+/// `Option<T>` fields may be missing, `Vec<T>` fields collect every value of a
+/// repeated key, any other field is required.
+pub trait FromQuery: Sized {
+    fn from_query(query: &QueryMap) -> Result<Self, QueryErrors>;
+
+    /// Parses a query string, see `QueryMap::parse`
+    fn from_query_str(query: &str) -> Result<Self, QueryErrors> {
+        Self::from_query(&QueryMap::parse(query))
+    }
+}
+
+/// A single value of a query string
+pub trait FromQueryValue: Sized {
+    fn from_query_value(value: &str) -> Result<Self, String>;
+}
+
+impl FromQueryValue for String {
+    fn from_query_value(value: &str) -> Result<Self, String> {
+        Ok(value.to_string())
+    }
+}
+
+/// Accepts `true`/`false`, `1`/`0`, `on`/`off` and `yes`/`no`. An empty value,
+/// as sent by `?flag`, is `true`
+impl FromQueryValue for bool {
+    fn from_query_value(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "" | "true" | "1" | "on" | "yes" => Ok(true),
+            "false" | "0" | "off" | "no" => Ok(false),
+            _ => Err(format!("expected a boolean, got \"{}\"", value)),
+        }
+    }
+}
+
+macro_rules! from_query_value_via_from_str {
+    ($($t:ty),*) => {
+        $(
+            impl FromQueryValue for $t {
+                fn from_query_value(value: &str) -> Result<Self, String> {
+                    value.trim().parse::<$t>().map_err(|e| e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+from_query_value_via_from_str!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, char,
+    std::net::IpAddr
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_repeated_and_encoded_pairs() {
+        let query = QueryMap::from_target("/search?q=rust+web&tag=a&tag=b%20c&flag&empty=");
+        assert_eq!(query.get("q"), Some("rust web"));
+        assert_eq!(query.get_all("tag"), vec!["a", "b c"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get("empty"), Some(""));
+        assert_eq!(query.get("missing"), None);
+        assert!(QueryMap::from_target("/search").is_empty());
+    }
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(u32::from_query_value("42"), Ok(42));
+        assert!(u32::from_query_value("-1").is_err());
+        assert_eq!(bool::from_query_value(""), Ok(true));
+        assert_eq!(bool::from_query_value("off"), Ok(false));
+        assert!(bool::from_query_value("maybe").is_err());
+    }
+
+    #[test]
+    fn errors_are_reported_per_field() {
+        let mut errors = QueryErrors::new();
+        errors.push("page", "invalid digit found in string");
+        assert_eq!(errors.get("page"), Some("invalid digit found in string"));
+        assert_eq!(errors.to_string(), "Invalid query: page: invalid digit found in string");
+        let response = errors.into_response();
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
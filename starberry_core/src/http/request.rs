@@ -37,7 +37,8 @@ impl HttpRequest {
     } 
 
     /// Parses the HTTP request body from a stream if the body has not been parsed yet. 
-    pub async fn parse_body<R: AsyncRead + Unpin>(&mut self, reader: &mut BufReader<R>, config: &HttpSafety) {
+    /// Returns the status to answer with if the body could not be read. 
+    pub async fn parse_body<R: AsyncRead + Unpin>(&mut self, reader: &mut BufReader<R>, config: &HttpSafety) -> Result<(), StatusCode> {
         // if let HttpBody::Unparsed = self.body {
         //     self.body = HttpBody::parse(
         //         reader,
@@ -45,7 +46,7 @@ impl HttpRequest {
         //         &mut self.meta,
         //     ).await;
         // }; 
-        let _ = net::parse_body(&mut self.meta, &mut self.body, reader, config).await; 
+        net::parse_body(&mut self.meta, &mut self.body, reader, config).await
     } 
 
     /// Add a cookie into the response metadata. 
@@ -1,4 +1,4 @@
-use crate::http::http_value::{ContentDisposition, StatusCode}; 
+use crate::http::http_value::{CacheControl, ContentDisposition, EntityTag, StatusCode}; 
 use crate::http::safety::HttpSafety; 
 
 use super::cookie::Cookie; 
@@ -7,6 +7,7 @@ use super::http_value::HttpContentType;
 use super::meta::HttpMeta;
 use super::net;
 use super::start_line::{HttpStartLine, ResponseStartLine}; 
+use starberry_lib::date::parse_http_date; 
 use std::collections::HashMap; 
 use tokio::io::{AsyncRead, AsyncWrite, BufReader, BufWriter}; 
 
@@ -70,6 +71,32 @@ impl HttpResponse {
         self 
     } 
 
+    /// Set the Cache-Control directives for the response. 
+    pub fn cache_control(mut self, cache_control: CacheControl) -> Self { 
+        self.meta.set_cache_control(cache_control); 
+        self 
+    } 
+
+    /// Answer the conditional headers of the request against the `ETag` and `Last-Modified` 
+    /// headers of the response. A cached copy which is still valid gets a `304 Not Modified` 
+    /// without body, a failed `If-Match` gets a `412 Precondition Failed` 
+    /// # Example 
+    /// ```rust,ignore 
+    /// serve_embedded!("favicon.ico").revalidate(&mut req.meta) 
+    /// ``` 
+    pub fn revalidate(mut self, request: &mut HttpMeta) -> Self { 
+        let etag = self.meta.get_header("etag").and_then(|value| EntityTag::parse(&value)); 
+        let last_modified = self.meta.get_header("last-modified").and_then(|value| parse_http_date(&value)); 
+        match request.evaluate_preconditions(etag.as_ref(), last_modified) { 
+            Some(StatusCode::NOT_MODIFIED) => { 
+                self.body = HttpBody::Empty; 
+                self.status(StatusCode::NOT_MODIFIED) 
+            } 
+            Some(status) => response_templates::return_status(status), 
+            None => self, 
+        } 
+    } 
+
     /// Send a status 
     pub fn status<T: Into<StatusCode>>(mut self, status: T) -> Self { 
         self.meta.start_line.set_status_code(status); 
@@ -113,15 +140,25 @@ impl Default for HttpResponse {
 pub mod response_templates {
     use std::path::Path; 
     use std::collections::HashMap; 
+    use std::sync::RwLock; 
 
     use akari::Value;
     use akari::TemplateManager;
+    use once_cell::sync::Lazy; 
+    use starberry_lib::ende::digest::sha256_hex; 
 
     use crate::http::body::HttpBody;
-    use crate::http::http_value::{HttpContentType, HttpVersion, StatusCode};
+    use crate::http::feed::{ATOM_CONTENT_TYPE, Feed, RSS_CONTENT_TYPE};
+    use crate::http::http_value::{CacheControl, EntityTag, HttpContentType, HttpVersion, StatusCode};
     use crate::http::meta::HttpMeta; 
     use crate::http::start_line::HttpStartLine; 
     use super::HttpResponse; 
+
+    /// Embedded assets change with a new build, they are cached for a day and then 
+    /// revalidated with their ETag 
+    pub const EMBEDDED_MAX_AGE: u64 = 24 * 3600; 
+
+    const EMBEDDED_ETAG_LENGTH: usize = 16; 
  
     /// Creates a plain text HTTP response with status 200 OK.
     ///
@@ -247,7 +284,17 @@ pub mod response_templates {
         let mut meta = HttpMeta::new(start_line, HashMap::new()); 
         let file_path = Path::new("templates").join(file); 
         // Set the response content type based on the file extension 
-        meta.set_content_type(match file_path.extension().and_then(|s| s.to_str()) {
+        meta.set_content_type(static_content_type(&file_path));
+        let body = match std::fs::read(file_path) { 
+            Ok(content) => content,
+            Err(_) => return return_status(StatusCode::NOT_FOUND), 
+        }; 
+        HttpResponse::new(meta, HttpBody::Binary(body)) 
+    }
+
+    /// The content type of a static file, guessed from its extension 
+    pub fn static_content_type(path: &Path) -> HttpContentType { 
+        match path.extension().and_then(|s| s.to_str()) {
             Some("html") => HttpContentType::TextHtml(),
             Some("css") => HttpContentType::TextCss(),
             Some("js") => HttpContentType::ApplicationJavascript(),
@@ -255,14 +302,35 @@ pub mod response_templates {
             Some("png") => HttpContentType::ImagePng(),
             Some("jpg") | Some("jpeg") => HttpContentType::ImageJpeg(),
             Some("gif") => HttpContentType::ImageGif(),
+            Some("ico") => HttpContentType::from_str("image/x-icon"),
+            Some("svg") => HttpContentType::from_str("image/svg+xml"),
+            Some("webp") => HttpContentType::from_str("image/webp"),
+            Some("txt") => HttpContentType::TextPlain(),
             _ => HttpContentType::ApplicationOctetStream(), // Default binary type
-        });
-        let body = match std::fs::read(file_path) { 
-            Ok(content) => content,
-            Err(_) => return return_status(StatusCode::NOT_FOUND), 
-        }; 
-        HttpResponse::new(meta, HttpBody::Binary(body)) 
-    }
+        }
+    } 
+
+    /// Serves bytes held in memory, such as a favicon embedded with `serve_embedded!`. 
+    /// The response is cached for `EMBEDDED_MAX_AGE` and carries a strong ETag derived from 
+    /// the bytes, so browsers revalidate it once it is stale, see `HttpResponse::revalidate`. 
+    /// 
+    /// # Examples 
+    /// 
+    /// ```rust 
+    /// use starberry_core::http::response::response_templates; 
+    /// use starberry_core::http::http_value::HttpContentType; 
+    /// 
+    /// let response = response_templates::serve_bytes(HttpContentType::TextPlain(), "User-agent: *"); 
+    /// assert!(response.meta.get_header("etag").is_some()); 
+    /// ``` 
+    pub fn serve_bytes(content_type: HttpContentType, bytes: impl Into<Vec<u8>>) -> HttpResponse { 
+        let bytes = bytes.into(); 
+        let etag = EntityTag::strong(&sha256_hex(&bytes)[..EMBEDDED_ETAG_LENGTH]); 
+        normal_response(StatusCode::OK, bytes) 
+            .content_type(content_type) 
+            .cache_control(CacheControl::new().with_public().with_max_age(EMBEDDED_MAX_AGE)) 
+            .add_header("etag", etag.to_string()) 
+    } 
 
     /// Creates an HTTP response with a specified status code and binary body.
     ///
@@ -325,6 +393,106 @@ pub mod response_templates {
         HttpResponse::new(meta, HttpBody::Json(body)) 
     } 
 
+    /// Creates a JSON HTTP response with status 200 OK from any `Serialize` value.
+    /// A value which fails to serialize gives a 500 Internal Server Error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::response::response_templates;
+    ///
+    /// let response = response_templates::serialized_json_response(&vec!["a", "b"]);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn serialized_json_response<T: serde::Serialize + ?Sized>(body: &T) -> HttpResponse {
+        match crate::http::json::to_value(body) {
+            Ok(value) => json_response(value),
+            Err(e) => normal_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+        }
+    }
+
+    /// Creates an XML HTTP response with status 200 OK. A dict holding a single key is
+    /// written with the key as the root element, other values under `<response>`.
+    /// See `http::xml` for the mapping of values to elements.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::response::response_templates;
+    /// use akari::{object, Value};
+    ///
+    /// let mut rss = object!({});
+    /// rss.set("@version", "2.0");
+    /// let mut body = object!({});
+    /// body.set("rss", rss);
+    /// let response = response_templates::xml_response(body);
+    /// ```
+    pub fn xml_response(body: Value) -> HttpResponse {
+        normal_response(StatusCode::OK, crate::http::xml::value_to_xml(&body))
+            .content_type(HttpContentType::ApplicationXml())
+    }
+
+    /// Creates an XML HTTP response with status 200 OK from any `Serialize` value,
+    /// under the given root element. A value which fails to serialize gives a 500 Internal Server Error.
+    #[cfg(feature = "serde")]
+    pub fn serialized_xml_response<T: serde::Serialize + ?Sized>(root: &str, body: &T) -> HttpResponse {
+        match crate::http::xml::serialize_to_xml(root, body) {
+            Ok(xml) => normal_response(StatusCode::OK, xml).content_type(HttpContentType::ApplicationXml()),
+            Err(e) => normal_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+        }
+    }
+
+    /// Creates an RSS 2.0 HTTP response with status 200 OK from a feed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use starberry_core::http::feed::{Feed, FeedItem};
+    /// use starberry_core::http::response::response_templates;
+    ///
+    /// let feed = Feed::new("Blog", "https://example.com/", "The latest posts")
+    ///     .item(FeedItem::new("Hello", "https://example.com/hello"));
+    /// let response = response_templates::rss_response(&feed);
+    /// ```
+    pub fn rss_response(feed: &Feed) -> HttpResponse {
+        normal_response(StatusCode::OK, feed.to_rss()).content_type(HttpContentType::from_str(RSS_CONTENT_TYPE))
+    }
+
+    /// Creates an Atom HTTP response with status 200 OK from a feed.
+    pub fn atom_response(feed: &Feed) -> HttpResponse {
+        normal_response(StatusCode::OK, feed.to_atom()).content_type(HttpContentType::from_str(ATOM_CONTENT_TYPE))
+    }
+
+    /// Values available in every template rendered by `template_response` 
+    static TEMPLATE_GLOBALS: Lazy<RwLock<HashMap<String, Value>>> = Lazy::new(|| RwLock::new(HashMap::new())); 
+
+    /// Makes a value available in every template under `name`. The data passed to 
+    /// `template_response` takes precedence over a global of the same name. 
+    /// 
+    /// # Examples 
+    /// 
+    /// ```rust 
+    /// use starberry_core::http::response::response_templates::set_template_global; 
+    /// use akari::Value; 
+    /// 
+    /// set_template_global("site_name", Value::new("Starberry")); 
+    /// ``` 
+    pub fn set_template_global<T: Into<String>>(name: T, value: Value) { 
+        TEMPLATE_GLOBALS.write().unwrap_or_else(|e| e.into_inner()).insert(name.into(), value); 
+    } 
+
+    /// The names of the values set by `set_template_global`, sorted 
+    pub fn template_global_names() -> Vec<String> { 
+        let mut names: Vec<String> = TEMPLATE_GLOBALS.read().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect(); 
+        names.sort(); 
+        names 
+    } 
+
+    /// Removes a value set by `set_template_global` 
+    pub fn remove_template_global(name: &str) -> Option<Value> { 
+        TEMPLATE_GLOBALS.write().unwrap_or_else(|e| e.into_inner()).remove(name) 
+    } 
+
     /// Creates an HTML response from a template with data binding.
     ///
     /// # Arguments
@@ -350,7 +518,10 @@ pub mod response_templates {
     ///
     /// let response = response_templates::template_response("user_profile.html", data);
     /// ```
-    pub fn template_response(file: &str, data: HashMap<String, Value>) -> HttpResponse { 
+    pub fn template_response(file: &str, mut data: HashMap<String, Value>) -> HttpResponse { 
+        for (name, value) in TEMPLATE_GLOBALS.read().unwrap_or_else(|e| e.into_inner()).iter() { 
+            data.entry(name.clone()).or_insert_with(|| value.clone()); 
+        } 
         let template_manager = TemplateManager::new("templates");
         let result = match template_manager.render(file, &data){ 
             Ok(content) => content,
@@ -435,3 +606,55 @@ pub mod response_templates {
 //         }};
 //     } 
 // }
+
+/// Serves a file embedded in the binary at compile time, so it is never read from the disk. 
+/// The path is relative to the current source file, as for `include_bytes!`. The content 
+/// type is guessed from the extension and the response of `serve_bytes` is built once. 
+/// # Example 
+/// ```rust,ignore 
+/// #[url(reg![&APP, LitUrl("favicon.ico")])] 
+/// async fn favicon() -> HttpResponse { 
+///     serve_embedded!("../static/favicon.ico").revalidate(&mut req.meta) 
+/// } 
+/// ``` 
+#[macro_export] 
+macro_rules! serve_embedded { 
+    ($path:literal) => {{ 
+        static RESPONSE: ::std::sync::OnceLock<$crate::http::response::HttpResponse> = ::std::sync::OnceLock::new(); 
+        RESPONSE 
+            .get_or_init(|| { 
+                $crate::http::response::response_templates::serve_bytes( 
+                    $crate::http::response::response_templates::static_content_type(::std::path::Path::new($path)), 
+                    &include_bytes!($path)[..], 
+                ) 
+            }) 
+            .clone() 
+    }}; 
+} 
+
+#[cfg(test)] 
+mod tests { 
+    use std::collections::HashMap; 
+
+    use super::*; 
+    use crate::http::meta::HeaderValue; 
+    use crate::http::response::response_templates::serve_bytes; 
+
+    #[test] 
+    fn embedded_bytes_are_revalidated() { 
+        let mut response = serve_embedded!("response.rs"); 
+        assert_eq!(response.meta.get_cache_control().unwrap().to_string(), "public, max-age=86400"); 
+        let etag = response.meta.get_header("etag").unwrap(); 
+        assert_eq!(etag, serve_bytes(HttpContentType::TextPlain(), include_bytes!("response.rs").to_vec()).meta.get_header("etag").unwrap()); 
+
+        let mut headers = HashMap::new(); 
+        headers.insert("if-none-match".to_string(), HeaderValue::new(etag.clone())); 
+        let mut request = HttpMeta::new(Default::default(), headers); 
+        let revalidated = response.clone().revalidate(&mut request); 
+        assert_eq!(revalidated.meta.start_line.status_code(), StatusCode::NOT_MODIFIED); 
+        assert_eq!(revalidated.meta.get_header("etag"), Some(etag)); 
+
+        let mut request = HttpMeta::new(Default::default(), HashMap::new()); 
+        assert_eq!(response.revalidate(&mut request).meta.start_line.status_code(), StatusCode::OK); 
+    } 
+} 
@@ -1,4 +1,6 @@
+use super::form::MultiForm;
 use super::http_value::{HttpContentType, HttpMethod};
+use super::sniff::type_matches;
 
 /// Centralized HTTP safety configuration with explicit state tracking
 /// 
@@ -15,6 +17,9 @@ pub struct HttpSafety {
     /// Allowed content types (None = allow all content types)
     allowed_content_types: Option<Vec<HttpContentType>>,
     
+    /// Allowed types of the files of a multipart body, detected from their bytes (None = allow all files)
+    allowed_file_types: Option<Vec<String>>,
+    
     /// Maximum header section size (None = use default)
     max_header_size: Option<usize>,
     
@@ -40,7 +45,7 @@ impl HttpSafety {
     /// 
     /// # Examples
     /// ```
-    /// # use starberry::safety::HttpSafety;
+    /// # use starberry_core::http::safety::HttpSafety;
     /// let safety = HttpSafety::new();
     /// assert!(safety.max_body_size().is_none());
     /// ```
@@ -49,6 +54,7 @@ impl HttpSafety {
             max_body_size: None,
             allowed_methods: None,
             allowed_content_types: None,
+            allowed_file_types: None,
             max_header_size: None,
             max_line_length: None,
             max_headers: None,
@@ -159,6 +165,54 @@ impl HttpSafety {
         }
     }
 
+    // --------------------------------------------------
+    // File Type Allow List Configuration
+    // --------------------------------------------------
+    
+    /// Gets the allowed file types list (None if unset = allow all)
+    pub fn allowed_file_types(&self) -> Option<&[String]> {
+        self.allowed_file_types.as_deref()
+    }
+    
+    /// Sets the allowed file types list, of types such as `image/png` or `image/*`
+    pub fn set_allowed_file_types(&mut self, types: Option<Vec<String>>) {
+        self.allowed_file_types = types;
+    }
+    
+    /// Adds a file type to the allow list
+    pub fn add_file_type<T: Into<String>>(&mut self, file_type: T) {
+        let file_type = file_type.into();
+        let types = self.allowed_file_types.get_or_insert_with(Vec::new);
+        if !types.contains(&file_type) {
+            types.push(file_type);
+        }
+    }
+    
+    /// Checks if a file is allowed, by the type detected from its bytes rather than the one
+    /// given by the client. A file of unknown type is only allowed without an allow list
+    /// 
+    /// # Examples
+    /// ```
+    /// # use starberry_core::http::safety::HttpSafety;
+    /// let safety = HttpSafety::new().with_allowed_file_types(vec!["image/*"]);
+    /// assert!(safety.check_file_type(b"\x89PNG\r\n\x1a\n"));
+    /// assert!(!safety.check_file_type(b"%PDF-1.7"));
+    /// assert!(!safety.check_file_type(b"plain text"));
+    /// ```
+    pub fn check_file_type(&self, data: &[u8]) -> bool {
+        match &self.allowed_file_types {
+            Some(types) => super::sniff::sniff(data)
+                .is_some_and(|sniffed| types.iter().any(|allowed| type_matches(allowed, sniffed))),
+            None => true,  // No restrictions
+        }
+    }
+    
+    /// Checks if every file of a multipart form is allowed
+    pub fn check_files(&self, form: &MultiForm) -> bool {
+        self.allowed_file_types.is_none()
+            || form.get_all().values().filter_map(|field| field.get_files()).flatten().all(|file| self.check_file_type(file.data()))
+    }
+
     // --------------------------------------------------
     // Header Size Configuration
     // --------------------------------------------------
@@ -242,7 +296,7 @@ impl HttpSafety {
     /// 
     /// # Examples
     /// ```
-    /// # use starberry::safety::HttpSafety;
+    /// # use starberry_core::http::safety::HttpSafety;
     /// let mut base = HttpSafety::new();
     /// base.set_max_body_size(Some(1024));
     /// 
@@ -262,6 +316,9 @@ impl HttpSafety {
         if source.allowed_content_types.is_some() {
             self.allowed_content_types = source.allowed_content_types.clone();
         }
+        if source.allowed_file_types.is_some() {
+            self.allowed_file_types = source.allowed_file_types.clone();
+        }
         if source.max_header_size.is_some() {
             self.max_header_size = source.max_header_size;
         }
@@ -282,22 +339,22 @@ impl HttpSafety {
     /// 
     /// # Examples
     /// ```
-    /// # use starberry::safety::HttpSafety;
-    /// # use starberry::http_value::HttpMethod;
+    /// # use starberry_core::http::safety::HttpSafety;
+    /// # use starberry_core::http::http_value::HttpMethod;
     /// let mut global = HttpSafety::new();
     /// global.set_max_body_size(Some(2048));
-    /// global.set_allowed_methods(Some(vec![HttpMethod::Get, HttpMethod::Post]));
+    /// global.set_allowed_methods(Some(vec![HttpMethod::GET, HttpMethod::POST]));
     /// 
     /// let mut route = HttpSafety::new();
     /// route.set_max_body_size(Some(1024));
-    /// route.set_allowed_methods(Some(vec![HttpMethod::Post]));
+    /// route.set_allowed_methods(Some(vec![HttpMethod::POST]));
     /// 
     /// global.merge(&route);
     /// 
     /// assert_eq!(global.max_body_size(), Some(1024));
     /// assert_eq!(
     ///     global.allowed_methods(),
-    ///     Some(vec![HttpMethod::Post].as_slice())
+    ///     Some(vec![HttpMethod::POST].as_slice())
     /// );
     /// ```
     pub fn merge(&mut self, other: &HttpSafety) {
@@ -347,6 +404,19 @@ impl HttpSafety {
             (None, Some(_)) => other.allowed_content_types.clone(),
             (None, None) => None,
         };
+        
+        // Merge file type allow lists
+        self.allowed_file_types = match (&self.allowed_file_types, &other.allowed_file_types) {
+            (Some(a), Some(b)) => Some(
+                a.iter()
+                .filter(|t| b.contains(t))
+                .cloned()
+                .collect()
+            ),
+            (Some(_), None) => self.allowed_file_types.clone(),
+            (None, Some(_)) => other.allowed_file_types.clone(),
+            (None, None) => None,
+        };
     }
     
     // --------------------------------------------------
@@ -383,6 +453,12 @@ impl HttpSafety {
         self
     }
     
+    /// Builder method to set the file type allow list, e.g. `vec!["image/png", "image/jpeg"]`
+    pub fn with_allowed_file_types<T: Into<String>>(mut self, types: Vec<T>) -> Self {
+        self.set_allowed_file_types(Some(types.into_iter().map(Into::into).collect()));
+        self
+    }
+    
     /// Builder method to set header size
     pub fn with_max_header_size(mut self, size: usize) -> Self {
         self.set_max_header_size(Some(size));
@@ -414,6 +490,7 @@ impl Default for &HttpSafety {
             max_body_size: None, 
             allowed_methods: None,
             allowed_content_types: None,
+            allowed_file_types: None,
             max_header_size: None, 
             max_line_length: None, 
             max_headers: None, 
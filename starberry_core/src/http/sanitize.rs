@@ -0,0 +1,473 @@
+//! Cleans HTML written by users, e.g. comments or profiles rendered from Markdown, so it can be
+//! inserted in a page without running scripts.
+//!
+//! The sanitizer keeps the tags and attributes of an allowlist and drops the others, keeping the
+//! text inside them. The content of `script`, `style` and similar elements is dropped with them.
+//! URLs in `href`, `src` and `cite` may only be relative or use an allowed scheme, `http`,
+//! `https` and `mailto` by default. The output is always well formed: text is escaped and the
+//! elements left open are closed.
+//!
+//! Templates insert values as they are, so user HTML is sanitized before it is handed to them,
+//! with `HtmlSanitizer::filter` for the values of the template data.
+//!
+//! # Example
+//! ```
+//! use starberry_core::http::sanitize::{HtmlSanitizer, sanitize_html};
+//! let clean = sanitize_html("<p onclick=\"steal()\">Hi <script>steal()</script><b>there</b></p>");
+//! assert_eq!(clean, "<p>Hi <b>there</b></p>");
+//!
+//! let text_only = HtmlSanitizer::empty();
+//! assert_eq!(text_only.sanitize("<i>1 < 2</i>"), "1 &lt; 2");
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use akari::Value;
+use once_cell::sync::Lazy;
+
+/// The tags kept by `HtmlSanitizer::new`
+const DEFAULT_TAGS: &[&str] = &[
+    "a", "abbr", "b", "blockquote", "br", "code", "dd", "del", "dl", "dt", "em", "figcaption", "figure", "h1",
+    "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "ins", "kbd", "li", "ol", "p", "pre", "q", "s", "small",
+    "span", "strong", "sub", "sup", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "u", "ul",
+];
+
+/// The attributes kept by `HtmlSanitizer::new`, by tag, `*` for every tag
+const DEFAULT_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("*", &["title"]),
+    ("a", &["href"]),
+    ("img", &["src", "alt", "width", "height"]),
+    ("blockquote", &["cite"]),
+    ("q", &["cite"]),
+    ("ol", &["start"]),
+    ("td", &["colspan", "rowspan"]),
+    ("th", &["colspan", "rowspan"]),
+];
+
+/// The elements dropped with their content, whatever the allowlist
+const DROPPED_WITH_CONTENT: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "noscript", "noembed", "noframes", "template", "textarea",
+    "title", "xmp", "svg", "math",
+];
+
+/// Elements without content nor closing tag
+const VOID_ELEMENTS: &[&str] = &["area", "br", "col", "hr", "img", "wbr"];
+
+/// Attributes holding a URL, whose scheme is checked
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "cite"];
+
+static DEFAULT_SANITIZER: Lazy<HtmlSanitizer> = Lazy::new(HtmlSanitizer::new);
+
+/// Sanitizes HTML with the default allowlist of `HtmlSanitizer::new`
+pub fn sanitize_html(html: &str) -> String {
+    DEFAULT_SANITIZER.sanitize(html)
+}
+
+/// An allowlist of tags, attributes and URL schemes, see the module documentation
+#[derive(Debug, Clone)]
+pub struct HtmlSanitizer {
+    tags: HashSet<String>,
+    /// The attributes allowed by tag, `*` for every tag
+    attributes: HashMap<String, HashSet<String>>,
+    url_schemes: HashSet<String>,
+    /// The `rel` set on every link, replacing the one written by the user
+    link_rel: Option<String>,
+}
+
+impl HtmlSanitizer {
+    /// Keeps the tags of text formatting, lists, tables, links and images, and sets
+    /// `rel="noopener noreferrer nofollow"` on links
+    pub fn new() -> Self {
+        let mut sanitizer = Self::empty().allow_tags(DEFAULT_TAGS).link_rel(Some("noopener noreferrer nofollow"));
+        for (tag, attributes) in DEFAULT_ATTRIBUTES {
+            sanitizer = sanitizer.allow_attributes(tag, attributes);
+        }
+        sanitizer
+    }
+
+    /// Keeps no tag at all, only the text
+    pub fn empty() -> Self {
+        Self {
+            tags: HashSet::new(),
+            attributes: HashMap::new(),
+            url_schemes: ["http", "https", "mailto"].iter().map(|s| s.to_string()).collect(),
+            link_rel: None,
+        }
+    }
+
+    /// Keeps these tags. `script`, `style` and the like are dropped even if allowed
+    pub fn allow_tags(mut self, tags: &[&str]) -> Self {
+        self.tags.extend(tags.iter().map(|tag| tag.to_ascii_lowercase()));
+        self
+    }
+
+    /// Drops these tags, keeping their text
+    pub fn remove_tags(mut self, tags: &[&str]) -> Self {
+        for tag in tags {
+            self.tags.remove(&tag.to_ascii_lowercase());
+        }
+        self
+    }
+
+    /// Keeps these attributes on a tag, or on every tag with `*`. Event handlers such as
+    /// `onclick` and `style` are dropped unless allowed here, which is unsafe
+    pub fn allow_attributes(mut self, tag: &str, attributes: &[&str]) -> Self {
+        self.attributes
+            .entry(tag.to_ascii_lowercase())
+            .or_default()
+            .extend(attributes.iter().map(|attribute| attribute.to_ascii_lowercase()));
+        self
+    }
+
+    /// Sets the schemes allowed in URLs, e.g. `["https"]`. Relative URLs are always allowed
+    pub fn url_schemes(mut self, schemes: &[&str]) -> Self {
+        self.url_schemes = schemes.iter().map(|scheme| scheme.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Sets the `rel` of every link, or keeps the allowed one written by the user with `None`
+    pub fn link_rel(mut self, rel: Option<&str>) -> Self {
+        self.link_rel = rel.map(str::to_string);
+        self
+    }
+
+    /// Sanitizes the strings of a value of template data, in lists and dicts too
+    pub fn filter(&self, value: &Value) -> Value {
+        match value {
+            Value::Str(html) => Value::Str(self.sanitize(html)),
+            Value::List(items) => Value::List(items.iter().map(|item| self.filter(item)).collect()),
+            Value::Dict(map) => Value::Dict(map.iter().map(|(key, item)| (key.clone(), self.filter(item))).collect()),
+            other => other.clone(),
+        }
+    }
+
+    /// Keeps the allowed markup of the HTML and escapes the rest
+    pub fn sanitize(&self, html: &str) -> String {
+        let mut out = String::with_capacity(html.len());
+        let mut open: Vec<String> = Vec::new();
+        let mut rest = html;
+        while let Some(lt) = rest.find('<') {
+            push_text(&mut out, &rest[..lt]);
+            rest = &rest[lt..];
+            let after = &rest[1..];
+            if let Some(comment) = after.strip_prefix("!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            } else if after.starts_with(['!', '?']) {
+                // Doctypes, CDATA and processing instructions
+                rest = after.find('>').map_or("", |end| &after[end + 1..]);
+            } else if let Some(tag) = parse_tag(rest) {
+                rest = &rest[tag.len..];
+                if !tag.finished {
+                    // An unfinished tag is dropped with the rest of the input
+                } else if tag.closing {
+                    self.close(&mut out, &mut open, &tag.name);
+                } else if DROPPED_WITH_CONTENT.contains(&tag.name.as_str()) {
+                    if !tag.self_closing {
+                        rest = skip_element(rest, &tag.name);
+                    }
+                } else if self.tags.contains(&tag.name) {
+                    self.open(&mut out, &mut open, &tag);
+                }
+            } else {
+                out.push_str("&lt;");
+                rest = after;
+            }
+        }
+        push_text(&mut out, rest);
+        while let Some(name) = open.pop() {
+            out.push_str(&format!("</{}>", name));
+        }
+        out
+    }
+
+    fn open(&self, out: &mut String, open: &mut Vec<String>, tag: &Tag) {
+        out.push('<');
+        out.push_str(&tag.name);
+        for (name, value) in &tag.attributes {
+            if !self.allows_attribute(&tag.name, name) || (tag.name == "a" && name == "rel" && self.link_rel.is_some()) {
+                continue;
+            }
+            let value = decode_entities(value);
+            if URL_ATTRIBUTES.contains(&name.as_str()) && !url_allowed(&value, &self.url_schemes) {
+                continue;
+            }
+            out.push_str(&format!(" {}=\"{}\"", name, escape_html(&value)));
+        }
+        if let (true, Some(rel)) = (tag.name == "a", &self.link_rel) {
+            out.push_str(&format!(" rel=\"{}\"", escape_html(rel)));
+        }
+        out.push('>');
+        if !VOID_ELEMENTS.contains(&tag.name.as_str()) {
+            open.push(tag.name.clone());
+        }
+    }
+
+    /// Closes the element and the ones opened inside it, ignoring a closing tag without element
+    fn close(&self, out: &mut String, open: &mut Vec<String>, name: &str) {
+        let Some(position) = open.iter().rposition(|opened| opened == name) else {
+            return;
+        };
+        for opened in open.drain(position..).rev() {
+            out.push_str(&format!("</{}>", opened));
+        }
+    }
+
+    fn allows_attribute(&self, tag: &str, attribute: &str) -> bool {
+        [tag, "*"].iter().any(|key| self.attributes.get(*key).is_some_and(|allowed| allowed.contains(attribute)))
+    }
+
+}
+
+/// Whether the URL, with its character references decoded, is relative or uses one of the
+/// schemes. Browsers ignore whitespace and control characters in the scheme, so
+/// `java\tscript:` is read as `javascript:`
+pub(crate) fn url_allowed(url: &str, schemes: &HashSet<String>) -> bool {
+    let url: String = url.chars().filter(|c| !c.is_whitespace() && !c.is_control()).collect();
+    let scheme_end = url.find([':', '/', '?', '#']);
+    match scheme_end {
+        Some(end) if url[end..].starts_with(':') => schemes.contains(&url[..end].to_ascii_lowercase()),
+        _ => true,
+    }
+}
+
+impl Default for HtmlSanitizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A start or end tag
+struct Tag {
+    /// The lowercase name
+    name: String,
+    /// The lowercase names and the raw values
+    attributes: Vec<(String, String)>,
+    closing: bool,
+    self_closing: bool,
+    /// Whether the tag ends with `>` before the end of the input
+    finished: bool,
+    /// The length of the tag in the source, up to and including `>`
+    len: usize,
+}
+
+/// Parses the tag at the start of `html`, `None` if `<` does not start a tag
+fn parse_tag(html: &str) -> Option<Tag> {
+    let bytes = html.as_bytes();
+    let mut pos = 1;
+    let closing = bytes.get(pos) == Some(&b'/');
+    if closing {
+        pos += 1;
+    }
+    if !bytes.get(pos)?.is_ascii_alphabetic() {
+        return None;
+    }
+    let name_start = pos;
+    while pos < bytes.len() && !is_tag_delimiter(bytes[pos]) {
+        pos += 1;
+    }
+    let name = html[name_start..pos].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+            self_closing = bytes[pos] == b'/';
+            pos += 1;
+        }
+        match bytes.get(pos) {
+            None => return Some(Tag { name, attributes, closing, self_closing, finished: false, len: html.len() }),
+            Some(b'>') => return Some(Tag { name, attributes, closing, self_closing, finished: true, len: pos + 1 }),
+            _ => {}
+        }
+        self_closing = false;
+        let attribute_start = pos;
+        while pos < bytes.len() && !is_tag_delimiter(bytes[pos]) && bytes[pos] != b'=' {
+            pos += 1;
+        }
+        let attribute = html[attribute_start..pos].to_ascii_lowercase();
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let mut value = "";
+        if bytes.get(pos) == Some(&b'=') {
+            pos += 1;
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            match bytes.get(pos) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let end = html[pos + 1..].find(quote as char).map_or(html.len(), |end| pos + 1 + end);
+                    value = &html[pos + 1..end];
+                    pos = (end + 1).min(html.len());
+                }
+                _ => {
+                    let start = pos;
+                    while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+                        pos += 1;
+                    }
+                    value = &html[start..pos];
+                }
+            }
+        }
+        if !attribute.is_empty() && !attributes.iter().any(|(name, _)| *name == attribute) {
+            attributes.push((attribute, value.to_string()));
+        }
+    }
+}
+
+fn is_tag_delimiter(byte: u8) -> bool {
+    byte.is_ascii_whitespace() || byte == b'/' || byte == b'>'
+}
+
+/// Skips the content of an element dropped with it, up to and including its closing tag
+fn skip_element<'a>(html: &'a str, name: &str) -> &'a str {
+    let bytes = html.as_bytes();
+    let mut from = 0;
+    // The name is compared in place, the input is never copied
+    while let Some(found) = html[from..].find("</") {
+        let start = from + found + 2;
+        let end = start + name.len();
+        if bytes.get(start..end).is_some_and(|tag| tag.eq_ignore_ascii_case(name.as_bytes()))
+            && bytes.get(end).is_none_or(|&b| is_tag_delimiter(b))
+        {
+            return html[end..].find('>').map_or("", |close| &html[end + close + 1..]);
+        }
+        from = start;
+    }
+    ""
+}
+
+/// Writes text, keeping its character references and escaping the markup characters
+pub(crate) fn push_text(out: &mut String, text: &str) {
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&escape_html(&rest[..amp]));
+        let reference = &rest[amp..];
+        let len = character_reference_len(reference);
+        if len > 0 {
+            out.push_str(&reference[..len]);
+        } else {
+            out.push_str("&amp;");
+        }
+        rest = &reference[len.max(1)..];
+    }
+    out.push_str(&escape_html(rest));
+}
+
+/// The length of the character reference at the start of the text, `&amp;` or `&#38;`,
+/// 0 if there is none
+fn character_reference_len(text: &str) -> usize {
+    let body = &text[1..];
+    let digits = if let Some(hex) = body.strip_prefix("#x").or_else(|| body.strip_prefix("#X")) {
+        hex.find(|c: char| !c.is_ascii_hexdigit()).filter(|&n| n > 0).map(|n| n + 2)
+    } else if let Some(decimal) = body.strip_prefix('#') {
+        decimal.find(|c: char| !c.is_ascii_digit()).filter(|&n| n > 0).map(|n| n + 1)
+    } else {
+        body.find(|c: char| !c.is_ascii_alphanumeric()).filter(|&n| n > 0)
+    };
+    match digits {
+        Some(n) if body[n..].starts_with(';') => n + 2,
+        _ => 0,
+    }
+}
+
+/// Decodes the character references of an attribute value, the numeric ones and the named ones
+/// which could hide a scheme. Other named references are kept as written
+pub(crate) fn decode_entities(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let reference = &rest[amp..];
+        let end = reference.find(';').filter(|&end| end > 1 && end < 12);
+        let decoded = end.and_then(|end| {
+            let name = &reference[1..end];
+            let code = match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok(),
+                Some(decimal) => decimal.parse().ok(),
+                None => match name {
+                    "amp" => Some(u32::from('&')),
+                    "lt" => Some(u32::from('<')),
+                    "gt" => Some(u32::from('>')),
+                    "quot" => Some(u32::from('"')),
+                    "apos" => Some(u32::from('\'')),
+                    "colon" => Some(u32::from(':')),
+                    "tab" => Some(u32::from('\t')),
+                    "newline" => Some(u32::from('\n')),
+                    "nbsp" => Some(0xa0),
+                    _ => None,
+                },
+            };
+            Some((char::from_u32(code?).unwrap_or('\u{fffd}'), end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &reference[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &reference[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_allowed_markup() {
+        let html = "<h2 id=\"x\">Title</h2><p>A <a href=\"https://example.com/?a=1&amp;b=2\" rel=\"me\">link</a></p><br/>";
+        assert_eq!(
+            sanitize_html(html),
+            "<h2>Title</h2><p>A <a href=\"https://example.com/?a=1&amp;b=2\" rel=\"noopener noreferrer nofollow\">link</a></p><br>"
+        );
+        assert_eq!(sanitize_html("<IMG SRC=/a.png ALT='a \"cat\"'>"), "<img src=\"/a.png\" alt=\"a &quot;cat&quot;\">");
+        assert_eq!(sanitize_html("Fish &amp; chips &copy; &#169; & co"), "Fish &amp; chips &copy; &#169; &amp; co");
+    }
+
+    #[test]
+    fn removes_scripts() {
+        assert_eq!(sanitize_html("a<script>alert(1)</script >b"), "ab");
+        assert_eq!(sanitize_html("a<SCRIPT src=x.js></SCRIPT>b<style>p{}</style>c"), "abc");
+        assert_eq!(sanitize_html("<img src=x onerror=alert(1)>"), "<img src=\"x\">");
+        assert_eq!(sanitize_html("<a href=\"javascript:alert(1)\">x</a>"), "<a rel=\"noopener noreferrer nofollow\">x</a>");
+        assert!(!sanitize_html("<a href=\"java\tscript&#58;alert(1)\">x</a>").contains("href"));
+        assert!(!sanitize_html("<a href=\"&#x6A;avascript:alert(1)\">x</a>").contains("href"));
+        assert_eq!(sanitize_html("<img src=\"data:image/svg+xml,<svg onload=alert(1)>\">"), "<img>");
+        assert_eq!(sanitize_html("<!-- <script>alert(1)</script> -->ok"), "ok");
+        assert_eq!(sanitize_html("<svg><script>alert(1)</script></svg>"), "");
+        assert_eq!(sanitize_html("<p title=\"x\" style=\"color:red\">a"), "<p title=\"x\">a</p>");
+        assert_eq!(sanitize_html("<script>a</scripts>b</ScRiPt\n>c"), "c");
+        assert_eq!(sanitize_html(&"<script>x</script>".repeat(50_000)), "");
+    }
+
+    #[test]
+    fn balances_and_escapes() {
+        assert_eq!(sanitize_html("<b><i>a</b> c</i>"), "<b><i>a</i></b> c");
+        assert_eq!(sanitize_html("</p>1 < 2 > 0<div>x</div>"), "1 &lt; 2 &gt; 0x");
+        assert_eq!(sanitize_html("<p>unclosed <a href=\"/x"), "<p>unclosed </p>");
+        assert_eq!(sanitize_html("<script>never closed"), "");
+    }
+
+    #[test]
+    fn configures_the_allowlist() {
+        let sanitizer = HtmlSanitizer::new().remove_tags(&["img"]).url_schemes(&["https"]).link_rel(None);
+        assert_eq!(sanitizer.sanitize("<img src=a.png>"), "");
+        assert_eq!(sanitizer.sanitize("<a href=\"http://a\" rel=\"me\">x</a>"), "<a>x</a>");
+        let sanitizer = HtmlSanitizer::empty().allow_tags(&["mark"]).allow_attributes("mark", &["class"]);
+        assert_eq!(sanitizer.sanitize("<mark class=\"hl\" id=\"m\">x</mark><b>y</b>"), "<mark class=\"hl\">x</mark>y");
+
+        let mut data = HashMap::new();
+        data.insert("bio".to_string(), Value::Str("<b onclick=\"x()\">hi</b>".to_string()));
+        let filtered = sanitizer.filter(&Value::Dict(data));
+        assert!(matches!(&filtered, Value::Dict(map) if matches!(map.get("bio"), Some(Value::Str(bio)) if bio == "hi")));
+    }
+}
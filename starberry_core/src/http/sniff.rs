@@ -0,0 +1,118 @@
+//! Detects the type of a file from its first bytes, its "magic number", instead of trusting the
+//! `Content-Type` sent by the client, which can be anything.
+//!
+//! Only binary formats with a signature are detected. Text formats such as CSV, JSON or SVG have
+//! none and give `None`, so an allowlist of sniffed types refuses them.
+//!
+//! # Example
+//! ```
+//! use starberry_core::http::sniff::{sniff, type_matches};
+//! let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+//! assert_eq!(sniff(png), Some("image/png"));
+//! assert!(type_matches("image/*", "image/png"));
+//! assert_eq!(sniff(b"<script>alert(1)</script>"), None);
+//! ```
+
+/// Signatures at the start of the file, checked in order
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"BM", "image/bmp"),
+    (b"\0\0\x01\0", "image/x-icon"),
+    (b"II*\0", "image/tiff"),
+    (b"MM\0*", "image/tiff"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"PK\x05\x06", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"Rar!\x1a\x07", "application/vnd.rar"),
+    (b"\x7fELF", "application/x-executable"),
+    (b"MZ", "application/vnd.microsoft.portable-executable"),
+    (b"ID3", "audio/mpeg"),
+    (b"fLaC", "audio/flac"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+];
+
+/// The brands of an ISO base media file, `....ftyp<brand>`, and their type
+const FTYP_BRANDS: &[(&[u8], &str)] = &[
+    (b"avif", "image/avif"),
+    (b"avis", "image/avif"),
+    (b"heic", "image/heic"),
+    (b"heix", "image/heic"),
+    (b"mif1", "image/heif"),
+    (b"qt  ", "video/quicktime"),
+    (b"M4A ", "audio/mp4"),
+];
+
+/// The type of the file, e.g. `image/png`, or `None` if it has no known signature
+pub fn sniff(data: &[u8]) -> Option<&'static str> {
+    // RIFF containers carry their format at offset 8
+    if data.len() >= 12 && data.starts_with(b"RIFF") {
+        return match &data[8..12] {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        let brand = &data[8..12];
+        let sniffed = FTYP_BRANDS.iter().find(|(b, _)| *b == brand).map_or("video/mp4", |(_, mime)| *mime);
+        return Some(sniffed);
+    }
+    // MPEG audio frames without an ID3 tag start with an 11 bit sync word
+    if data.len() >= 2 && data[0] == 0xff && data[1] & 0xe0 == 0xe0 && data[1] & 0x06 != 0 {
+        return Some("audio/mpeg");
+    }
+    SIGNATURES.iter().find(|(magic, _)| data.starts_with(magic)).map(|(_, mime)| *mime)
+}
+
+/// Whether the type matches a pattern such as `image/png`, `image/*` or `*/*`, ignoring case
+/// and parameters
+pub fn type_matches(pattern: &str, mime: &str) -> bool {
+    let essence = |s: &str| s.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    let (pattern, mime) = (essence(pattern), essence(mime));
+    match pattern.strip_suffix("/*") {
+        Some("*") => true,
+        Some(top) => mime.split('/').next() == Some(top),
+        None => pattern == mime,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_common_formats() {
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\0\x10JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff(b"GIF89a\x01\0"), Some("image/gif"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WAVEfmt "), Some("audio/wav"));
+        assert_eq!(sniff(b"\0\0\0\x1cftypavif\0\0\0\0"), Some("image/avif"));
+        assert_eq!(sniff(b"\0\0\0\x18ftypisom\0\0\x02\0"), Some("video/mp4"));
+        assert_eq!(sniff(b"%PDF-1.7\n"), Some("application/pdf"));
+        assert_eq!(sniff(b"PK\x03\x04\x14\0"), Some("application/zip"));
+        assert_eq!(sniff(b"MZ\x90\0"), Some("application/vnd.microsoft.portable-executable"));
+        assert_eq!(sniff(b"\xff\xfb\x90\x64"), Some("audio/mpeg"));
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"RIFF"), None);
+        assert_eq!(sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+    }
+
+    #[test]
+    fn matches_patterns() {
+        assert!(type_matches("image/png", "IMAGE/PNG"));
+        assert!(type_matches("image/*", "image/webp"));
+        assert!(type_matches("*/*", "application/pdf"));
+        assert!(type_matches("application/pdf", "application/pdf; qs=0.9"));
+        assert!(!type_matches("image/*", "application/pdf"));
+        assert!(!type_matches("image/png", "image/pngx"));
+    }
+}
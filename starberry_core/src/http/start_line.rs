@@ -198,13 +198,13 @@ impl ResponseStartLine {
     ///
     /// A string representation of the ResponseStartLine.
     pub fn represent(&self) -> String {
-        format!("{} {}", self.http_version.to_string(), self.status_code.to_string())
+        format!("{} {} {}", self.http_version.to_string(), self.status_code.as_u16(), self.status_code.reason_phrase())
     }
 }
 
 impl std::fmt::Display for ResponseStartLine { 
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { 
-        write!(f, "{} {}", self.http_version.to_string(), self.status_code.to_string()) 
+        write!(f, "{} {} {}", self.http_version.to_string(), self.status_code.as_u16(), self.status_code.reason_phrase()) 
     } 
 } 
 
@@ -0,0 +1,110 @@
+//! Temporary files which are deleted when dropped.
+//!
+//! `HttpReqCtx::save_body_to_tempfile` streams the body of a request into a `TempFile`, for
+//! handlers passing the body to tools which only read from a path.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[url(reg![&APP, LitUrl("convert")])]
+//! async fn convert() -> HttpResponse {
+//!     let upload = match req.save_body_to_tempfile().await {
+//!         Ok(upload) => upload,
+//!         Err(status) => return return_status(status),
+//!     };
+//!     let output = tokio::process::Command::new("pandoc").arg(upload.path()).output().await;
+//!     ...
+//! }   // The file is deleted here
+//! ```
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use starberry_lib::uuid::uuid_v4;
+
+use crate::app::storage::Storage;
+
+/// A file in the temporary directory, deleted when dropped unless it is persisted
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates an empty file in the temporary directory of the system, readable only
+    /// by its owner on Unix, and opens it for writing
+    pub async fn create() -> io::Result<(Self, tokio::fs::File)> {
+        Self::create_in(std::env::temp_dir()).await
+    }
+
+    /// Creates an empty file in `dir` and opens it for writing
+    pub async fn create_in<P: AsRef<Path>>(dir: P) -> io::Result<(Self, tokio::fs::File)> {
+        let path = dir.as_ref().join(format!("starberry-{}.tmp", uuid_v4()));
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let file = options.open(&path).await?;
+        Ok((Self { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The size of the file in bytes
+    pub fn len(&self) -> u64 {
+        std::fs::metadata(&self.path).map(|metadata| metadata.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stores the file under `key` in a `Storage`, e.g. the one of the application.
+    /// The temporary file is still deleted when dropped
+    pub async fn store(&self, storage: &dyn Storage, key: impl Into<String>) -> io::Result<()> {
+        storage.put_file(key.into(), self.path.clone()).await
+    }
+
+    /// Moves the file to `to`, where it is kept. Falls back to copying when `to` is on
+    /// another file system
+    pub fn persist<P: AsRef<Path>>(self, to: P) -> io::Result<PathBuf> {
+        let to = to.as_ref().to_path_buf();
+        if std::fs::rename(&self.path, &to).is_err() {
+            // The temporary file is still deleted on drop
+            std::fs::copy(&self.path, &to)?;
+        }
+        Ok(to)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn deleted_on_drop_unless_persisted() {
+        let (temp, mut file) = TempFile::create().await.unwrap();
+        file.write_all(b"hello").await.unwrap();
+        file.flush().await.unwrap();
+        let path = temp.path().to_path_buf();
+        assert_eq!(temp.len(), 5);
+        drop(temp);
+        assert!(!path.exists());
+
+        let (temp, _) = TempFile::create().await.unwrap();
+        let kept = std::env::temp_dir().join(format!("starberry-kept-{}", uuid_v4()));
+        let from = temp.path().to_path_buf();
+        assert_eq!(temp.persist(&kept).unwrap(), kept);
+        assert!(kept.exists() && !from.exists());
+        std::fs::remove_file(kept).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+//! Validation of extracted request data.
+//!
+//! Structs deriving `Validate` check their fields with the rules of the `rules` module
+//! and report every violation in `ValidationErrors`, sent as a 422 Unprocessable Entity.
+//! `HttpReqCtx::valid_query` extracts and validates a query in one step.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[derive(FromQuery, Validate)]
+//! struct Signup {
+//!     #[validate(length(min = 3, max = 20), regex = "^[a-z0-9_]+$")]
+//!     username: String,
+//!     #[validate(email)]
+//!     email: String,
+//!     #[validate(range(min = 13, max = 130))]
+//!     age: Option<u32>,
+//!     #[validate(custom = not_reserved)]
+//!     handle: String,
+//! }
+//!
+//! fn not_reserved(handle: &String) -> Result<(), String> {
+//!     if handle == "admin" { Err("is reserved".to_string()) } else { Ok(()) }
+//! }
+//! ```
+
+use std::fmt;
+
+use akari::Value;
+
+use crate::object;
+
+use super::error::IntoResponse;
+use super::http_value::StatusCode;
+use super::query::QueryErrors;
+use super::response::HttpResponse;
+use super::response::response_templates::json_response;
+
+/// A rule broken by a field
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub field: String,
+    /// The name of the rule, e.g. `length` or `email`
+    pub rule: String,
+    pub message: String,
+}
+
+/// Every violation found in a value. Sent as a 422 Unprocessable Entity whose JSON body
+/// maps each field to its errors, e.g. `{"errors": {"email": "is not a valid email address"}}`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationErrors(pub Vec<Violation>);
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<F: Into<String>, R: Into<String>, M: Into<String>>(&mut self, field: F, rule: R, message: M) {
+        self.0.push(Violation {
+            field: field.into(),
+            rule: rule.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The violations of a field
+    pub fn field(&self, field: &str) -> Vec<&Violation> {
+        self.0.iter().filter(|v| v.field == field).collect()
+    }
+
+    /// `Ok(())` if there is no violation
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let violations: Vec<String> = self.0.iter().map(|v| format!("{} {}", v.field, v.message)).collect();
+        write!(f, "Validation failed: {}", violations.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> HttpResponse {
+        let mut fields: Vec<(String, Vec<String>)> = Vec::new();
+        for violation in self.0 {
+            match fields.iter_mut().find(|(field, _)| *field == violation.field) {
+                Some((_, messages)) => messages.push(violation.message),
+                None => fields.push((violation.field, vec![violation.message])),
+            }
+        }
+        let mut errors = object!({});
+        for (field, messages) in fields {
+            errors.set(field.as_str(), messages.join("; ").as_str());
+        }
+        let mut body = object!({});
+        body.set("errors", errors);
+        json_response(body).status(StatusCode::UNPROCESSABLE_ENTITY)
+    }
+}
+
+/// A value whose fields can be checked, usually through `#[derive(Validate)]`.
+///
+/// The derive accepts these rules in `#[validate(...)]`, applied to the content of
+/// `Option` fields only when it is present:
+/// - `length(min = 1, max = 64)` on strings (counted in characters) and collections
+/// - `range(min = 0, max = 100)` on any `PartialOrd` value
+/// - `email`
+/// - `regex = "^[a-z]+$"`
+/// - `custom = path::to::function`, a `fn(&T) -> Result<(), String>`
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// Why an extractor validating its result failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractError {
+    /// The data could not be parsed, sent as 400 Bad Request
+    Malformed(QueryErrors),
+    /// The data was parsed but broke some rules, sent as 422 Unprocessable Entity
+    Invalid(ValidationErrors),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(errors) => errors.fmt(f),
+            Self::Invalid(errors) => errors.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+impl From<QueryErrors> for ExtractError {
+    fn from(errors: QueryErrors) -> Self {
+        Self::Malformed(errors)
+    }
+}
+
+impl From<ValidationErrors> for ExtractError {
+    fn from(errors: ValidationErrors) -> Self {
+        Self::Invalid(errors)
+    }
+}
+
+impl IntoResponse for ExtractError {
+    fn into_response(self) -> HttpResponse {
+        match self {
+            Self::Malformed(errors) => errors.into_response(),
+            Self::Invalid(errors) => errors.into_response(),
+        }
+    }
+}
+
+/// Values whose length can be checked by the `length` rule
+pub trait HasLength {
+    fn length(&self) -> usize;
+}
+
+impl HasLength for str {
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl HasLength for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T> HasLength for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> HasLength for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+/// The rules used by `#[derive(Validate)]`. Each returns the message of the violation
+pub mod rules {
+    use std::collections::HashMap;
+    use std::fmt::Display;
+    use std::sync::RwLock;
+
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+
+    use super::HasLength;
+
+    pub fn length<V: HasLength + ?Sized>(value: &V, min: Option<usize>, max: Option<usize>) -> Result<(), String> {
+        let length = value.length();
+        match (min, max) {
+            (Some(min), Some(max)) if length < min || length > max => {
+                Err(format!("length must be between {} and {}", min, max))
+            }
+            (Some(min), None) if length < min => Err(format!("length must be at least {}", min)),
+            (None, Some(max)) if length > max => Err(format!("length must be at most {}", max)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn range<T: PartialOrd + Display>(value: &T, min: Option<T>, max: Option<T>) -> Result<(), String> {
+        match (min, max) {
+            (Some(min), Some(max)) if *value < min || *value > max => {
+                Err(format!("must be between {} and {}", min, max))
+            }
+            (Some(min), None) if *value < min => Err(format!("must be at least {}", min)),
+            (None, Some(max)) if *value > max => Err(format!("must be at most {}", max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// A pragmatic check of the address syntax: one `@`, a non-empty local part and
+    /// a domain made of dot separated labels. Only sending a mail proves an address exists
+    pub fn email<V: AsRef<str> + ?Sized>(value: &V) -> Result<(), String> {
+        let value = value.as_ref();
+        let valid = match value.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty()
+                    && local.len() <= 64
+                    && !local.contains(char::is_whitespace)
+                    && domain.contains('.')
+                    && domain.split('.').all(|label| {
+                        !label.is_empty()
+                            && !label.starts_with('-')
+                            && !label.ends_with('-')
+                            && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                    })
+            }
+            None => false,
+        };
+        if valid && value.len() <= 254 {
+            Ok(())
+        } else {
+            Err("is not a valid email address".to_string())
+        }
+    }
+
+    /// Compiled patterns, shared by every validation of the same rule
+    static PATTERNS: Lazy<RwLock<HashMap<&'static str, Regex>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+    /// Panics if the pattern is not a valid regex, as it is written in the source code
+    pub fn regex<V: AsRef<str> + ?Sized>(value: &V, pattern: &'static str) -> Result<(), String> {
+        let cached = PATTERNS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(pattern)
+            .map(|regex| regex.is_match(value.as_ref()));
+        let matched = match cached {
+            Some(matched) => matched,
+            None => {
+                let regex = Regex::new(pattern).unwrap_or_else(|e| panic!("Invalid validation regex {}: {}", pattern, e));
+                let matched = regex.is_match(value.as_ref());
+                PATTERNS.write().unwrap_or_else(|e| e.into_inner()).insert(pattern, regex);
+                matched
+            }
+        };
+        if matched {
+            Ok(())
+        } else {
+            Err(format!("does not match {}", pattern))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_counts_characters() {
+        assert!(rules::length("héllo", Some(5), Some(5)).is_ok());
+        assert_eq!(rules::length("ab", Some(3), None), Err("length must be at least 3".to_string()));
+        assert!(rules::length(&vec![1, 2, 3], None, Some(2)).is_err());
+    }
+
+    #[test]
+    fn range_bounds_are_inclusive() {
+        assert!(rules::range(&10, Some(1), Some(10)).is_ok());
+        assert_eq!(rules::range(&0, Some(1), Some(10)), Err("must be between 1 and 10".to_string()));
+        assert!(rules::range(&2.5, None, Some(2.0)).is_err());
+    }
+
+    #[test]
+    fn emails() {
+        assert!(rules::email("user.name+tag@example.co.uk").is_ok());
+        for invalid in ["", "user", "@example.com", "user@", "user@localhost", "us er@example.com", "user@-a.com"] {
+            assert!(rules::email(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn regexes() {
+        assert!(rules::regex("abc_1", "^[a-z0-9_]+$").is_ok());
+        assert!(rules::regex("ABC", "^[a-z0-9_]+$").is_err());
+    }
+
+    #[test]
+    fn errors_are_unprocessable() {
+        let mut errors = ValidationErrors::new();
+        assert_eq!(errors.clone().into_result(), Ok(()));
+        errors.push("email", "email", "is not a valid email address");
+        assert_eq!(errors.field("email").len(), 1);
+        let response = ExtractError::from(errors).into_response();
+        assert_eq!(response.meta.start_line.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
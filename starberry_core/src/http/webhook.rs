@@ -0,0 +1,459 @@
+//! Verification of signed webhooks.
+//!
+//! A `WebhookVerifier` checks the HMAC-SHA256 signature of a delivery against the raw bytes
+//! of its body, rejects deliveries whose timestamp is outside the tolerance, and remembers
+//! the signed content of the deliveries it accepted so a captured request cannot be replayed.
+//! Headers outside the signature are never trusted to identify a delivery. Several secrets can
+//! be given while one is being rotated. `WebhookRoute` wraps a handler and answers invalid
+//! deliveries before it runs, so the handler only sees verified requests.
+//!
+//! # Example
+//! ```rust,ignore
+//! let verifier = WebhookVerifier::new(SignatureScheme::github(), std::env::var("GITHUB_WEBHOOK_SECRET")?);
+//!
+//! APP.reg_from::<HttpReqCtx>(&[LitUrl("hooks"), LitUrl("github")])
+//!     .set_method(Arc::new(WebhookRoute::new(verifier, |mut req: HttpReqCtx| async move {
+//!         let event = req.json_or_default().await.clone();
+//!         req.response = text_response("ok");
+//!         req
+//!     })));
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use starberry_lib::encoding::{base64_decode, hex_decode};
+use starberry_lib::ende::digest::sha256_hex;
+use starberry_lib::ende::signing::verify_hmac_sha256;
+
+use crate::app::middleware::{AsyncFinalHandler, BoxFuture};
+
+use super::context::HttpReqCtx;
+use super::http_value::StatusCode;
+use super::meta::HttpMeta;
+use super::response::HttpResponse;
+use super::response::response_templates::{normal_response, return_status};
+
+const DEFAULT_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// Where a provider puts the signature of its deliveries and what it signs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// One header holding the hex HMAC of the body after an optional prefix,
+    /// e.g. `X-Hub-Signature-256: sha256=<hex>`. There is no timestamp
+    Hmac { header: String, prefix: String },
+    /// `Stripe-Signature: t=<timestamp>,v1=<hex>`, signing `<timestamp>.<body>`
+    Stripe,
+    /// `X-Slack-Signature: v0=<hex>` with `X-Slack-Request-Timestamp`, signing `v0:<timestamp>:<body>`
+    Slack,
+    /// The Standard Webhooks headers `webhook-id`, `webhook-timestamp` and
+    /// `webhook-signature: v1,<base64>`, signing `<id>.<timestamp>.<body>`.
+    /// A `whsec_` secret is base64 decoded first
+    StandardWebhooks,
+}
+
+impl SignatureScheme {
+    pub fn hmac<T: Into<String>, U: Into<String>>(header: T, prefix: U) -> Self {
+        Self::Hmac {
+            header: header.into().to_lowercase(),
+            prefix: prefix.into(),
+        }
+    }
+
+    /// The scheme of GitHub, `X-Hub-Signature-256: sha256=<hex>`
+    pub fn github() -> Self {
+        Self::hmac("x-hub-signature-256", "sha256=")
+    }
+}
+
+/// Why a delivery was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    /// The signature or one of the headers it needs is missing
+    MissingSignature,
+    /// No secret gives the signature
+    InvalidSignature,
+    /// The timestamp cannot be read
+    InvalidTimestamp,
+    /// The timestamp is outside the tolerance
+    Expired,
+    /// The delivery was already accepted
+    Replayed,
+    /// The body could not be read
+    Body(StatusCode),
+}
+
+impl WebhookError {
+    /// The response sent for a rejected delivery
+    pub fn response(&self) -> HttpResponse {
+        match self {
+            Self::Body(status) => return_status(status.clone()),
+            Self::Replayed => normal_response(StatusCode::CONFLICT, self.to_string()),
+            Self::MissingSignature | Self::InvalidTimestamp => normal_response(StatusCode::BAD_REQUEST, self.to_string()),
+            Self::InvalidSignature | Self::Expired => normal_response(StatusCode::UNAUTHORIZED, self.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSignature => write!(f, "Missing webhook signature"),
+            Self::InvalidSignature => write!(f, "Invalid webhook signature"),
+            Self::InvalidTimestamp => write!(f, "Invalid webhook timestamp"),
+            Self::Expired => write!(f, "Webhook timestamp outside the tolerance"),
+            Self::Replayed => write!(f, "Webhook already delivered"),
+            Self::Body(status) => write!(f, "Cannot read webhook body: {}", status),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// The deliveries accepted recently, remembered long enough for their timestamp to expire
+#[derive(Debug)]
+pub struct ReplayCache {
+    ttl: Duration,
+    seen: Mutex<HashMap<String, Instant>>,
+}
+
+impl ReplayCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records the key, `false` if it was already recorded within the ttl
+    pub fn insert(&self, key: &str) -> bool {
+        self.insert_at(key, Instant::now())
+    }
+
+    fn insert_at(&self, key: &str, now: Instant) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        seen.retain(|_, at| now.duration_since(*at) < self.ttl);
+        if seen.contains_key(key) {
+            return false;
+        }
+        seen.insert(key.to_string(), now);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// What was signed and the signatures sent with it
+struct Delivery {
+    signed: Vec<u8>,
+    signatures: Vec<Vec<u8>>,
+    timestamp: Option<u64>,
+}
+
+/// Checks the deliveries of one provider, see the module documentation
+#[derive(Clone)]
+pub struct WebhookVerifier {
+    scheme: SignatureScheme,
+    secrets: Vec<Vec<u8>>,
+    tolerance: Duration,
+    replays: Option<Arc<ReplayCache>>,
+}
+
+/// Leaves the secrets out
+impl fmt::Debug for WebhookVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookVerifier")
+            .field("scheme", &self.scheme)
+            .field("secrets", &self.secrets.len())
+            .field("tolerance", &self.tolerance)
+            .field("replays", &self.replays)
+            .finish()
+    }
+}
+
+impl WebhookVerifier {
+    pub fn new<S: AsRef<[u8]>>(scheme: SignatureScheme, secret: S) -> Self {
+        Self {
+            scheme,
+            secrets: Vec::new(),
+            tolerance: DEFAULT_TOLERANCE,
+            replays: Some(Arc::new(ReplayCache::new(DEFAULT_TOLERANCE * 2))),
+        }
+        .secret(secret)
+    }
+
+    /// Also accepts deliveries signed with this secret, e.g. the new one while rotating
+    pub fn secret<S: AsRef<[u8]>>(mut self, secret: S) -> Self {
+        let secret = secret.as_ref();
+        let key = match (&self.scheme, secret.strip_prefix(b"whsec_")) {
+            (SignatureScheme::StandardWebhooks, Some(encoded)) => base64_decode(encoded).unwrap_or_else(|_| secret.to_vec()),
+            _ => secret.to_vec(),
+        };
+        self.secrets.push(key);
+        self
+    }
+
+    /// Sets how far the timestamp of a delivery may be from now, 5 minutes by default.
+    /// The replay cache remembers deliveries for twice as long
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        if self.replays.is_some() {
+            self.replays = Some(Arc::new(ReplayCache::new(tolerance * 2)));
+        }
+        self
+    }
+
+    /// Shares a replay cache, e.g. between the verifiers of the secrets of several tenants
+    pub fn replay_cache(mut self, cache: Arc<ReplayCache>) -> Self {
+        self.replays = Some(cache);
+        self
+    }
+
+    /// Accepts the same delivery several times
+    pub fn allow_replays(mut self) -> Self {
+        self.replays = None;
+        self
+    }
+
+    /// Checks a delivery given the headers of the request and the raw bytes of its body
+    pub fn verify(&self, meta: &HttpMeta, body: &[u8]) -> Result<(), WebhookError> {
+        self.verify_at(meta, body, SystemTime::now())
+    }
+
+    fn verify_at(&self, meta: &HttpMeta, body: &[u8], now: SystemTime) -> Result<(), WebhookError> {
+        let delivery = self.delivery(meta, body)?;
+        let valid = delivery.signatures.iter().any(|signature| {
+            self.secrets
+                .iter()
+                .any(|secret| verify_hmac_sha256(secret, &delivery.signed, signature))
+        });
+        if !valid {
+            return Err(WebhookError::InvalidSignature);
+        }
+        if let Some(timestamp) = delivery.timestamp {
+            let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            if now.abs_diff(timestamp) > self.tolerance.as_secs() {
+                return Err(WebhookError::Expired);
+            }
+        }
+        // Only remember verified deliveries, so forged ones cannot fill the cache. The key is
+        // the signed content, which holds the timestamp and the id of the schemes sending them,
+        // as the unsigned headers and the signatures sent with it can be changed by a replay
+        if let Some(replays) = &self.replays
+            && !replays.insert(&sha256_hex(&delivery.signed))
+        {
+            return Err(WebhookError::Replayed);
+        }
+        Ok(())
+    }
+
+    fn delivery(&self, meta: &HttpMeta, body: &[u8]) -> Result<Delivery, WebhookError> {
+        let header = |name: &str| meta.get_header(name).map(|value| value.trim().to_string());
+        let timestamp = |value: &str| value.trim().parse::<u64>().map_err(|_| WebhookError::InvalidTimestamp);
+        let hex = |value: &str| hex_decode(value.trim()).map_err(|_| WebhookError::InvalidSignature);
+        let delivery = match &self.scheme {
+            SignatureScheme::Hmac { header: name, prefix } => {
+                let value = header(name).ok_or(WebhookError::MissingSignature)?;
+                let signature = value.strip_prefix(prefix.as_str()).ok_or(WebhookError::InvalidSignature)?;
+                Delivery {
+                    signed: body.to_vec(),
+                    signatures: vec![hex(signature)?],
+                    timestamp: None,
+                }
+            }
+            SignatureScheme::Stripe => {
+                let value = header("stripe-signature").ok_or(WebhookError::MissingSignature)?;
+                let mut sent_at = None;
+                let mut signatures = Vec::new();
+                for (key, value) in value.split(',').filter_map(|part| part.split_once('=')) {
+                    match key.trim() {
+                        "t" => sent_at = Some(value.trim().to_string()),
+                        // Signatures of other versions are ignored, as Stripe does
+                        "v1" => signatures.extend(hex(value).ok()),
+                        _ => {}
+                    }
+                }
+                let sent_at = sent_at.ok_or(WebhookError::MissingSignature)?;
+                if signatures.is_empty() {
+                    return Err(WebhookError::MissingSignature);
+                }
+                Delivery {
+                    signed: [sent_at.as_bytes(), &b"."[..], body].concat(),
+                    signatures,
+                    timestamp: Some(timestamp(&sent_at)?),
+                }
+            }
+            SignatureScheme::Slack => {
+                let value = header("x-slack-signature").ok_or(WebhookError::MissingSignature)?;
+                let sent_at = header("x-slack-request-timestamp").ok_or(WebhookError::MissingSignature)?;
+                let signature = value.strip_prefix("v0=").ok_or(WebhookError::InvalidSignature)?;
+                Delivery {
+                    signed: [&b"v0:"[..], sent_at.as_bytes(), &b":"[..], body].concat(),
+                    signatures: vec![hex(signature)?],
+                    timestamp: Some(timestamp(&sent_at)?),
+                }
+            }
+            SignatureScheme::StandardWebhooks => {
+                let id = header("webhook-id").ok_or(WebhookError::MissingSignature)?;
+                let sent_at = header("webhook-timestamp").ok_or(WebhookError::MissingSignature)?;
+                let value = header("webhook-signature").ok_or(WebhookError::MissingSignature)?;
+                let signatures: Vec<Vec<u8>> = value
+                    .split_whitespace()
+                    .filter_map(|signature| signature.strip_prefix("v1,"))
+                    .filter_map(|signature| base64_decode(signature).ok())
+                    .collect();
+                if signatures.is_empty() {
+                    return Err(WebhookError::MissingSignature);
+                }
+                Delivery {
+                    signed: [id.as_bytes(), &b"."[..], sent_at.as_bytes(), &b"."[..], body].concat(),
+                    signatures,
+                    timestamp: Some(timestamp(&sent_at)?),
+                }
+            }
+        };
+        Ok(delivery)
+    }
+}
+
+/// A handler which only runs for verified deliveries, invalid ones are answered with the
+/// response of their `WebhookError`. The body is parsed, so the handler can read it as usual
+pub struct WebhookRoute {
+    verifier: WebhookVerifier,
+    handler: Arc<dyn AsyncFinalHandler<HttpReqCtx>>,
+}
+
+impl WebhookRoute {
+    pub fn new<H: AsyncFinalHandler<HttpReqCtx>>(verifier: WebhookVerifier, handler: H) -> Self {
+        Self {
+            verifier,
+            handler: Arc::new(handler),
+        }
+    }
+}
+
+impl AsyncFinalHandler<HttpReqCtx> for WebhookRoute {
+    fn handle(&self, mut req: HttpReqCtx) -> BoxFuture<HttpReqCtx> {
+        let verifier = self.verifier.clone();
+        let handler = self.handler.clone();
+        Box::pin(async move {
+            let verified = match req.raw_body().await {
+                Ok(body) => verifier.verify(&req.request.meta, &body),
+                Err(status) => Err(WebhookError::Body(status)),
+            };
+            if let Err(e) = verified {
+                req.response = e.response();
+                return req;
+            }
+            handler.handle(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starberry_lib::encoding::{base64_encode, hex_encode};
+    use starberry_lib::ende::signing::sign_hmac_sha256;
+
+    use super::*;
+    use crate::http::meta::HeaderValue;
+
+    fn meta(headers: &[(&str, String)]) -> HttpMeta {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), HeaderValue::new(value.clone())))
+            .collect();
+        HttpMeta::new(Default::default(), headers)
+    }
+
+    #[test]
+    fn verifies_hmac_header() {
+        let body = br#"{"action":"opened"}"#;
+        let signature = format!("sha256={}", hex_encode(sign_hmac_sha256(b"old", body)));
+        let verifier = WebhookVerifier::new(SignatureScheme::github(), "new").secret("old");
+        let request = meta(&[("x-hub-signature-256", signature.clone())]);
+        assert_eq!(verifier.verify(&request, body), Ok(()));
+        assert_eq!(verifier.verify(&request, body), Err(WebhookError::Replayed));
+        assert_eq!(verifier.verify(&request, b"{}"), Err(WebhookError::InvalidSignature));
+        assert_eq!(verifier.verify(&meta(&[]), body), Err(WebhookError::MissingSignature));
+        assert_eq!(
+            WebhookVerifier::new(SignatureScheme::github(), "new").verify(&request, body),
+            Err(WebhookError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn checks_timestamps() {
+        let body = b"{}";
+        let now = SystemTime::now();
+        let sent_at = now.duration_since(UNIX_EPOCH).unwrap().as_secs().to_string();
+        let signed = [sent_at.as_bytes(), &b"."[..], &body[..]].concat();
+        let header = format!("t={},v1={}", sent_at, hex_encode(sign_hmac_sha256(b"secret", &signed)));
+        let request = meta(&[("stripe-signature", header)]);
+        let verifier = WebhookVerifier::new(SignatureScheme::Stripe, "secret").allow_replays();
+        assert_eq!(verifier.verify_at(&request, body, now), Ok(()));
+        assert_eq!(verifier.verify_at(&request, body, now), Ok(()));
+        assert_eq!(
+            verifier.verify_at(&request, body, now + Duration::from_secs(301)),
+            Err(WebhookError::Expired)
+        );
+    }
+
+    #[test]
+    fn replays_are_keyed_on_the_signed_content() {
+        let body = b"{}";
+        let now = SystemTime::now();
+        let sent_at = now.duration_since(UNIX_EPOCH).unwrap().as_secs().to_string();
+        let signed = [sent_at.as_bytes(), &b"."[..], &body[..]].concat();
+        let signature = hex_encode(sign_hmac_sha256(b"secret", &signed));
+        let verifier = WebhookVerifier::new(SignatureScheme::Stripe, "secret");
+        let request = meta(&[("stripe-signature", format!("t={},v1={}", sent_at, signature))]);
+        assert_eq!(verifier.verify_at(&request, body, now), Ok(()));
+        // A junk signature put first does not make the delivery new
+        let replayed = meta(&[("stripe-signature", format!("t={},v1=00ff,v1={}", sent_at, signature))]);
+        assert_eq!(verifier.verify_at(&replayed, body, now), Err(WebhookError::Replayed));
+        // Neither does an unsigned header
+        let verifier = WebhookVerifier::new(SignatureScheme::github(), "secret");
+        let signature = format!("sha256={}", hex_encode(sign_hmac_sha256(b"secret", body)));
+        let first = meta(&[("x-hub-signature-256", signature.clone()), ("x-github-delivery", "1".to_string())]);
+        let second = meta(&[("x-hub-signature-256", signature), ("x-github-delivery", "2".to_string())]);
+        assert_eq!(verifier.verify(&first, body), Ok(()));
+        assert_eq!(verifier.verify(&second, body), Err(WebhookError::Replayed));
+        assert!(format!("{:?}", verifier).contains("secrets: 1,"));
+    }
+
+    #[test]
+    fn verifies_standard_webhooks() {
+        let key = b"0123456789abcdef";
+        let secret = format!("whsec_{}", base64_encode(key));
+        let sent_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs().to_string();
+        let signed = format!("msg_1.{}.hello", sent_at);
+        let signature = format!("v1,bogus v1,{}", base64_encode(sign_hmac_sha256(key, signed.as_bytes())));
+        let request = meta(&[
+            ("webhook-id", "msg_1".to_string()),
+            ("webhook-timestamp", sent_at),
+            ("webhook-signature", signature),
+        ]);
+        let verifier = WebhookVerifier::new(SignatureScheme::StandardWebhooks, secret);
+        assert_eq!(verifier.verify(&request, b"hello"), Ok(()));
+        assert_eq!(verifier.verify(&request, b"hello"), Err(WebhookError::Replayed));
+    }
+
+    #[test]
+    fn replay_cache_forgets() {
+        let cache = ReplayCache::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(cache.insert_at("a", start));
+        assert!(!cache.insert_at("a", start + Duration::from_secs(5)));
+        assert!(cache.insert_at("a", start + Duration::from_secs(11)));
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,440 @@
+//! Conversions between XML documents and akari `Value`s, for sitemaps, feeds and
+//! partners speaking XML.
+//!
+//! An element maps to a `Value` as follows:
+//! - an element with only text is a string, `<loc>/a</loc>` is `"loc": "/a"`
+//! - attributes are the keys starting with `@`, the text next to children or attributes is `#text`
+//! - repeated children are a list, `<url>..</url><url>..</url>` is `"url": [.., ..]`
+//!
+//! Dicts do not keep the order of their keys, so children are written sorted by name.
+//! The parser does not read DTDs nor expand custom entities.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[url(reg![&APP, LitUrl("sitemap.xml")])]
+//! async fn sitemap() -> HttpResponse {
+//!     let urls = ["/", "/about"].iter().map(|path| {
+//!         let mut url = object!({});
+//!         url.set("loc", format!("https://example.com{}", path));
+//!         url
+//!     });
+//!     let mut urlset = object!({});
+//!     urlset.set("@xmlns", "http://www.sitemaps.org/schemas/sitemap/0.9");
+//!     urlset.set("url", Value::List(urls.collect()));
+//!     let mut body = object!({});
+//!     body.set("urlset", urlset);
+//!     xml_response(body)
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use akari::Value;
+
+/// The declaration starting the written documents
+pub const XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>";
+
+/// The root element of documents whose value does not name one
+pub const DEFAULT_ROOT: &str = "response";
+
+/// Elements nested deeper are rejected, so a hostile document cannot exhaust the stack
+const MAX_DEPTH: usize = 256;
+
+/// A document which cannot be parsed, with the byte offset of the problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl XmlError {
+    pub fn new<T: Into<String>>(position: usize, message: T) -> Self {
+        Self { position, message: message.into() }
+    }
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid XML at byte {}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for XmlError {}
+
+/// Writes a document whose root element is `root`. A list is written as `item` children
+pub fn to_xml(root: &str, value: &Value) -> String {
+    let mut out = String::from(XML_DECLARATION);
+    match value {
+        Value::List(_) => {
+            let mut wrapper = HashMap::new();
+            wrapper.insert("item".to_string(), value.clone());
+            write_element(&mut out, root, &Value::Dict(wrapper));
+        }
+        _ => write_element(&mut out, root, value),
+    }
+    out
+}
+
+/// Writes a document from a value holding a single element, e.g. `{"urlset": {..}}`.
+/// Other values are written under the `response` root
+pub fn value_to_xml(value: &Value) -> String {
+    if let Value::Dict(map) = value
+        && map.len() == 1
+    {
+        let (root, inner) = map.iter().next().unwrap();
+        if !root.starts_with('@') && !root.starts_with('#') {
+            return to_xml(root, inner);
+        }
+    }
+    to_xml(DEFAULT_ROOT, value)
+}
+
+/// Writes a `Serialize` value under the root element
+#[cfg(feature = "serde")]
+pub fn serialize_to_xml<T: serde::Serialize + ?Sized>(root: &str, value: &T) -> Result<String, String> {
+    super::json::to_value(value).map(|value| to_xml(root, &value))
+}
+
+fn write_element(out: &mut String, name: &str, value: &Value) {
+    match value {
+        Value::List(items) => {
+            for item in items {
+                write_element(out, name, item);
+            }
+        }
+        Value::Dict(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('<');
+            out.push_str(name);
+            for key in keys.iter().filter(|key| key.starts_with('@')) {
+                out.push_str(&format!(" {}=\"{}\"", &key[1..], escape_xml(&scalar_text(&map[*key]))));
+            }
+            let text = map.get("#text").map(scalar_text).unwrap_or_default();
+            let children: Vec<&&String> = keys.iter().filter(|key| !key.starts_with('@') && **key != "#text").collect();
+            if text.is_empty() && children.is_empty() {
+                out.push_str("/>");
+                return;
+            }
+            out.push('>');
+            out.push_str(&escape_xml(&text));
+            for key in children {
+                write_element(out, key, &map[*key]);
+            }
+            out.push_str(&format!("</{}>", name));
+        }
+        _ => {
+            let text = scalar_text(value);
+            if text.is_empty() {
+                out.push_str(&format!("<{}/>", name));
+            } else {
+                out.push_str(&format!("<{}>{}</{}>", name, escape_xml(&text), name));
+            }
+        }
+    }
+}
+
+/// The text of a string, number or bool. Null is empty
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::Str(text) => text.clone(),
+        _ => {
+            let json = value.into_json();
+            if json == "null" { String::new() } else { json }
+        }
+    }
+}
+
+/// Escapes the markup characters of a text or attribute value
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Parses a document into a dict holding its root element, e.g. `{"urlset": {..}}`
+pub fn from_xml(text: &str) -> Result<Value, XmlError> {
+    let mut parser = Parser { text, pos: 0 };
+    parser.skip_misc()?;
+    if parser.at_end() {
+        return Err(parser.error("The document has no root element"));
+    }
+    let (name, value) = parser.element(0)?;
+    parser.skip_misc()?;
+    if !parser.at_end() {
+        return Err(parser.error("Content after the root element"));
+    }
+    let mut root = HashMap::new();
+    root.insert(name, value);
+    Ok(Value::Dict(root))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.text.len()
+    }
+
+    fn error<T: Into<String>>(&self, message: T) -> XmlError {
+        XmlError::new(self.pos, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Moves after the next `end`
+    fn skip_past(&mut self, end: &str) -> Result<(), XmlError> {
+        match self.rest().find(end) {
+            Some(index) => {
+                self.pos += index + end.len();
+                Ok(())
+            }
+            None => Err(self.error(format!("Missing {}", end))),
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), XmlError> {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected {}", token)))
+        }
+    }
+
+    /// Skips the whitespace, declarations, comments and doctype around the root element
+    fn skip_misc(&mut self) -> Result<(), XmlError> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!DOCTYPE") {
+                if self.rest().split('>').next().is_some_and(|doctype| doctype.contains('[')) {
+                    return Err(self.error("Internal DTDs are not supported"));
+                }
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, XmlError> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '>' | '/' | '=' | '<'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(self.error("Expected a name"));
+        }
+        let name = rest[..end].to_string();
+        self.pos += end;
+        Ok(name)
+    }
+
+    fn element(&mut self, depth: usize) -> Result<(String, Value), XmlError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("The document is nested too deeply"));
+        }
+        self.expect("<")?;
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok((name, build(attributes, Vec::new(), String::new())));
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let attribute = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error("Expected a quoted attribute value")),
+            };
+            self.pos += 1;
+            let Some(end) = self.rest().find(quote) else {
+                return Err(self.error("Unterminated attribute value"));
+            };
+            let value = unescape(&self.rest()[..end], self.pos)?;
+            self.pos += end + 1;
+            attributes.push((attribute, value));
+        }
+
+        let mut children = Vec::new();
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error(format!("Unclosed element {}", name)));
+            } else if rest.starts_with("</") {
+                self.pos += 2;
+                let closing = self.name()?;
+                if closing != name {
+                    return Err(self.error(format!("Expected </{}>, found </{}>", name, closing)));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok((name, build(attributes, children, text)));
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let Some(end) = cdata.find("]]>") else {
+                    return Err(self.error("Unterminated CDATA section"));
+                };
+                text.push_str(&cdata[..end]);
+                self.pos += "<![CDATA[".len() + end + "]]>".len();
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                children.push(self.element(depth + 1)?);
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                text.push_str(&unescape(&rest[..end], self.pos)?);
+                self.pos += end;
+            }
+        }
+    }
+}
+
+/// The value of an element, see the module documentation
+fn build(attributes: Vec<(String, String)>, children: Vec<(String, Value)>, text: String) -> Value {
+    if attributes.is_empty() && children.is_empty() {
+        return Value::Str(text);
+    }
+    let mut map: HashMap<String, Value> = HashMap::new();
+    for (name, value) in attributes {
+        map.insert(format!("@{}", name), Value::Str(value));
+    }
+    for (name, value) in children {
+        match map.remove(&name) {
+            None => {
+                map.insert(name, value);
+            }
+            Some(Value::List(mut items)) => {
+                items.push(value);
+                map.insert(name, Value::List(items));
+            }
+            Some(previous) => {
+                map.insert(name, Value::List(vec![previous, value]));
+            }
+        }
+    }
+    if !text.trim().is_empty() {
+        map.insert("#text".to_string(), Value::Str(text.trim().to_string()));
+    }
+    Value::Dict(map)
+}
+
+fn unescape(text: &str, position: usize) -> Result<String, XmlError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find(';') else {
+            return Err(XmlError::new(position, "Unterminated entity"));
+        };
+        let entity = &rest[start + 1..start + end];
+        let decoded = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    u32::from_str_radix(hex, 16).ok()
+                } else {
+                    entity.strip_prefix('#').and_then(|decimal| decimal.parse::<u32>().ok())
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+        match decoded {
+            Some(c) => out.push(c),
+            None => return Err(XmlError::new(position, format!("Unknown entity &{};", entity))),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dict(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    #[test]
+    fn writes_documents() {
+        let urls = Value::List(vec![
+            dict(vec![("loc", Value::new("https://example.com/?a=1&b=2"))]),
+            dict(vec![("loc", Value::new("https://example.com/about"))]),
+        ]);
+        let sitemap = dict(vec![(
+            "urlset",
+            dict(vec![("@xmlns", Value::new("http://www.sitemaps.org/schemas/sitemap/0.9")), ("url", urls)]),
+        )]);
+        assert_eq!(
+            value_to_xml(&sitemap),
+            format!(
+                "{}<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\"><url><loc>https://example.com/?a=1&amp;b=2</loc></url><url><loc>https://example.com/about</loc></url></urlset>",
+                XML_DECLARATION
+            )
+        );
+        assert_eq!(to_xml("empty", &dict(vec![])), format!("{}<empty/>", XML_DECLARATION));
+    }
+
+    #[test]
+    fn parses_documents() {
+        let xml = r#"<?xml version="1.0"?>
+            <!-- order -->
+            <order id="7" status='paid'>
+                <item>Tea &amp; biscuits</item>
+                <item>Jam &#x263A;</item>
+                <note><![CDATA[<fragile>]]></note>
+                <gift/>
+            </order>"#;
+        let Value::Dict(root) = from_xml(xml).unwrap() else { panic!("the root is not a dict") };
+        let Some(Value::Dict(order)) = root.get("order") else { panic!("no order") };
+        assert!(matches!(order.get("@id"), Some(Value::Str(id)) if id == "7"));
+        assert!(matches!(order.get("@status"), Some(Value::Str(status)) if status == "paid"));
+        assert!(matches!(order.get("note"), Some(Value::Str(note)) if note == "<fragile>"));
+        assert!(matches!(order.get("gift"), Some(Value::Str(gift)) if gift.is_empty()));
+        let Some(Value::List(items)) = order.get("item") else { panic!("the items are not a list") };
+        assert!(matches!(&items[0], Value::Str(item) if item == "Tea & biscuits"));
+        assert!(matches!(&items[1], Value::Str(item) if item == "Jam \u{263A}"));
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert!(from_xml("<a><b></a>").is_err());
+        assert!(from_xml("<a>&unknown;</a>").is_err());
+        assert!(from_xml("<a/><b/>").is_err());
+        assert!(from_xml("<!DOCTYPE a [<!ENTITY x \"y\">]><a>&x;</a>").is_err());
+        assert!(from_xml(&"<a>".repeat(MAX_DEPTH + 2)).is_err());
+    }
+}
@@ -2,4 +2,5 @@ pub mod http;
 pub mod app; 
 pub mod connection; 
 pub mod extensions; 
+pub mod cache; 
 pub use akari::*; 
\ No newline at end of file
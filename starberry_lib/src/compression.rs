@@ -11,7 +11,7 @@
 //! | GZIP         | `gzip`        | `compress_gzip`, `decompress_gzip` |
 //! | DEFLATE      | `deflate`     | `compress_deflate`, `decompress_deflate` |
 //! | Brotli       | `br`          | `compress_brotli`, `decompress_brotli` |
-//! | Zstandard    | `zstd`        | `compress_zstd`, `decompress_zstd` |
+//! | Zstandard    | `zstd`        | `compress_zstd`, `decompress_zstd`, `compress_zstd_stream`, `decompress_zstd_stream` |
 //!
 //! # Examples
 //!
@@ -32,14 +32,37 @@
 //! let compressed = compress_gzip(data).unwrap();
 //! // Send compressed data with Content-Encoding: gzip
 //! ```
+//!
+//! ## Streaming
+//! Bodies which arrive or leave in chunks are handled by `StreamEncoder` and
+//! `StreamDecoder`, which return the output produced by each chunk instead of
+//! buffering the whole payload.
+//! ```
+//! use starberry_lib::compression::{StreamDecoder, StreamEncoder};
+//!
+//! let mut encoder = StreamEncoder::gzip();
+//! let mut compressed = encoder.feed(b"Hello ").unwrap();
+//! compressed.extend(encoder.feed(b"world!").unwrap());
+//! compressed.extend(encoder.finish().unwrap());
+//!
+//! let mut decoder = StreamDecoder::gzip();
+//! let mut decompressed = decoder.feed(&compressed).unwrap();
+//! decompressed.extend(decoder.finish().unwrap());
+//! assert_eq!(decompressed, b"Hello world!");
+//! ```
 
 use flate2::{bufread, write, Compression};
 use brotli::{CompressorWriter as BrotliCompressor, Decompressor as BrotliDecompressor};
 use zstd::stream::{read::Decoder as ZstdDecoder, write::Encoder as ZstdEncoder};
-use std::io::{Read, Write}; 
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex}; 
 
 static CHUNK_SIZE: usize = 4096; 
 
+/// Default Zstandard level, the one of the zstd command line tool. 
+/// It compresses better than GZIP while being faster, which suits HTTP responses 
+pub const ZSTD_DEFAULT_LEVEL: i32 = 3; 
+
 /// Decompresses GZIP-encoded data
 ///
 /// # Arguments
@@ -84,7 +107,12 @@ pub fn decompress_gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
 /// let compressed = compress_gzip(data).unwrap();
 /// ```
 pub fn compress_gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
-    let mut encoder = write::GzEncoder::new(Vec::new(), Compression::default());
+    compress_gzip_level(data, Compression::default().level())
+}
+
+/// Compresses data using GZIP encoding at the given level, from 0 (stored) to 9 (best)
+pub fn compress_gzip_level(data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+    let mut encoder = write::GzEncoder::new(Vec::new(), Compression::new(level.min(9)));
     encoder.write_all(data)?;
     encoder.finish()
 }
@@ -124,7 +152,12 @@ pub fn decompress_deflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
 ///
 /// DEFLATE-compressed data as `Vec<u8>` or `std::io::Error` on failure
 pub fn compress_deflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
-    let mut encoder = write::DeflateEncoder::new(Vec::new(), Compression::default());
+    compress_deflate_level(data, Compression::default().level())
+}
+
+/// Compresses data using DEFLATE encoding at the given level, from 0 (stored) to 9 (best)
+pub fn compress_deflate_level(data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+    let mut encoder = write::DeflateEncoder::new(Vec::new(), Compression::new(level.min(9)));
     encoder.write_all(data)?;
     encoder.finish()
 }
@@ -165,7 +198,14 @@ pub fn decompress_brotli(data: &[u8]) -> std::io::Result<Vec<u8>> {
 ///
 /// Brotli-compressed data as `Vec<u8>` or `std::io::Error` on failure
 pub fn compress_brotli(data: &[u8]) -> std::io::Result<Vec<u8>> {
-    let mut compressor = BrotliCompressor::new(Vec::new(), 4096, 11, 22);
+    compress_brotli_quality(data, 11)
+}
+
+/// Compresses data using Brotli encoding at the given quality, from 0 (fastest) to 11 (best). 
+/// The best qualities are meant for assets compressed once, `BROTLI_STREAM_QUALITY` suits 
+/// responses compressed on each request 
+pub fn compress_brotli_quality(data: &[u8], quality: u32) -> std::io::Result<Vec<u8>> {
+    let mut compressor = BrotliCompressor::new(Vec::new(), CHUNK_SIZE, quality.min(11), 22);
     compressor.write_all(data)?;
     Ok(compressor.into_inner())
 }
@@ -201,7 +241,7 @@ pub fn decompress_zstd(data: &[u8]) -> std::io::Result<Vec<u8>> {
 /// # Arguments
 ///
 /// * `data` - Raw byte slice to compress
-/// * `level` - Compression level (1-21, where 1 is fastest, 21 is best compression)
+/// * `level` - Compression level (1-22, where 1 is fastest, 22 is best compression), see `ZSTD_DEFAULT_LEVEL`
 ///
 /// # Returns
 ///
@@ -211,3 +251,388 @@ pub fn compress_zstd(data: &[u8], level: i32) -> std::io::Result<Vec<u8>> {
     encoder.write_all(data)?;
     encoder.finish()
 }
+
+/// Compresses everything read from `reader` into `writer` using Zstandard encoding, 
+/// without holding the whole payload in memory 
+///
+/// # Example
+/// ```
+/// # use starberry_lib::compression::{compress_zstd_stream, decompress_zstd_stream, ZSTD_DEFAULT_LEVEL};
+/// let mut compressed = Vec::new();
+/// compress_zstd_stream(&b"Hello world!"[..], &mut compressed, ZSTD_DEFAULT_LEVEL).unwrap();
+/// let mut decompressed = Vec::new();
+/// decompress_zstd_stream(&compressed[..], &mut decompressed).unwrap();
+/// assert_eq!(decompressed, b"Hello world!");
+/// ```
+pub fn compress_zstd_stream<R: Read, W: Write>(reader: R, writer: W, level: i32) -> std::io::Result<()> {
+    zstd::stream::copy_encode(reader, writer, level)
+}
+
+/// Decompresses everything read from `reader` into `writer` using Zstandard encoding, 
+/// without holding the whole payload in memory 
+pub fn decompress_zstd_stream<R: Read, W: Write>(reader: R, writer: W) -> std::io::Result<()> {
+    zstd::stream::copy_decode(reader, writer)
+}
+
+/// Brotli quality used by `StreamEncoder::brotli`. 
+/// The highest quality is too slow to compress responses while they are sent 
+pub const BROTLI_STREAM_QUALITY: u32 = 5; 
+
+/// Output sink shared between a stream coder and its owner, so the bytes
+/// produced by each chunk can be taken out while the coder keeps its state
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum EncoderKind {
+    Gzip(write::GzEncoder<SharedBuffer>),
+    Deflate(write::DeflateEncoder<SharedBuffer>),
+    Brotli(Box<BrotliCompressor<SharedBuffer>>),
+    Zstd(ZstdEncoder<'static, SharedBuffer>),
+}
+
+/// Incremental compressor. Feed it chunks as they become available, each call 
+/// returns the compressed bytes ready to be sent (possibly none) 
+///
+/// # Example
+/// ```
+/// # use starberry_lib::compression::{StreamEncoder, ZSTD_DEFAULT_LEVEL};
+/// let mut encoder = StreamEncoder::zstd(ZSTD_DEFAULT_LEVEL).unwrap();
+/// let mut out = encoder.feed(b"first chunk").unwrap();
+/// out.extend(encoder.flush().unwrap()); // Everything so far can be decoded by the client
+/// out.extend(encoder.feed(b"second chunk").unwrap());
+/// out.extend(encoder.finish().unwrap());
+/// ```
+pub struct StreamEncoder {
+    kind: EncoderKind,
+    output: SharedBuffer,
+}
+
+impl StreamEncoder {
+    /// GZIP encoder with the default level
+    pub fn gzip() -> Self {
+        let output = SharedBuffer::default();
+        Self {
+            kind: EncoderKind::Gzip(write::GzEncoder::new(output.clone(), Compression::default())),
+            output,
+        }
+    }
+
+    /// DEFLATE encoder with the default level
+    pub fn deflate() -> Self {
+        let output = SharedBuffer::default();
+        Self {
+            kind: EncoderKind::Deflate(write::DeflateEncoder::new(output.clone(), Compression::default())),
+            output,
+        }
+    }
+
+    /// Brotli encoder with `BROTLI_STREAM_QUALITY`
+    pub fn brotli() -> Self {
+        let output = SharedBuffer::default();
+        Self {
+            kind: EncoderKind::Brotli(Box::new(BrotliCompressor::new(
+                output.clone(),
+                CHUNK_SIZE,
+                BROTLI_STREAM_QUALITY,
+                22,
+            ))),
+            output,
+        }
+    }
+
+    /// Zstandard encoder with the given level, see `ZSTD_DEFAULT_LEVEL`
+    pub fn zstd(level: i32) -> std::io::Result<Self> {
+        let output = SharedBuffer::default();
+        Ok(Self {
+            kind: EncoderKind::Zstd(ZstdEncoder::new(output.clone(), level)?),
+            output,
+        })
+    }
+
+    /// Compresses a chunk and returns the compressed bytes produced so far. 
+    /// Encoders keep data back to compress it better, call `flush` to force it out
+    pub fn feed(&mut self, chunk: &[u8]) -> std::io::Result<Vec<u8>> {
+        match &mut self.kind {
+            EncoderKind::Gzip(encoder) => encoder.write_all(chunk)?,
+            EncoderKind::Deflate(encoder) => encoder.write_all(chunk)?,
+            EncoderKind::Brotli(encoder) => encoder.write_all(chunk)?,
+            EncoderKind::Zstd(encoder) => encoder.write_all(chunk)?,
+        }
+        Ok(self.output.take())
+    }
+
+    /// Forces out everything fed so far, so the receiver can decode it without waiting 
+    /// for the end of the stream. Flushing often makes the compression worse
+    pub fn flush(&mut self) -> std::io::Result<Vec<u8>> {
+        match &mut self.kind {
+            EncoderKind::Gzip(encoder) => encoder.flush()?,
+            EncoderKind::Deflate(encoder) => encoder.flush()?,
+            EncoderKind::Brotli(encoder) => encoder.flush()?,
+            EncoderKind::Zstd(encoder) => encoder.flush()?,
+        }
+        Ok(self.output.take())
+    }
+
+    /// Ends the stream and returns the remaining bytes, including the trailer
+    pub fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self.kind {
+            EncoderKind::Gzip(encoder) => {
+                encoder.finish()?;
+            }
+            EncoderKind::Deflate(encoder) => {
+                encoder.finish()?;
+            }
+            EncoderKind::Brotli(encoder) => {
+                encoder.into_inner();
+            }
+            EncoderKind::Zstd(encoder) => {
+                encoder.finish()?;
+            }
+        }
+        Ok(self.output.take())
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+/// Raw DEFLATE has no trailer, so the end of the stream is only known from its last block
+struct RawInflate {
+    inner: flate2::Decompress,
+    ended: bool,
+}
+
+impl RawInflate {
+    fn new() -> Self {
+        Self { inner: flate2::Decompress::new(false), ended: false }
+    }
+
+    /// Decodes the chunk into `output`, ignoring any bytes after the end of the stream
+    fn feed(&mut self, mut chunk: &[u8], output: &mut Vec<u8>) -> std::io::Result<()> {
+        while !self.ended {
+            output.reserve(CHUNK_SIZE);
+            let (read, written) = (self.inner.total_in(), output.len());
+            let status = self
+                .inner
+                .decompress_vec(chunk, output, flate2::FlushDecompress::None)
+                .map_err(invalid_data)?;
+            let read = (self.inner.total_in() - read) as usize;
+            chunk = &chunk[read..];
+            self.ended = status == flate2::Status::StreamEnd;
+            if read == 0 && output.len() == written {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+enum DecoderKind {
+    Gzip(write::GzDecoder<SharedBuffer>),
+    Deflate(Box<RawInflate>),
+    Brotli(Box<brotli::DecompressorWriter<SharedBuffer>>),
+    Zstd(zstd::stream::zio::Writer<SharedBuffer, zstd::stream::raw::Decoder<'static>>),
+}
+
+/// Incremental decompressor. Feed it chunks of the compressed body as they arrive, 
+/// each call returns the decompressed bytes available so far 
+pub struct StreamDecoder {
+    kind: DecoderKind,
+    output: SharedBuffer,
+}
+
+impl StreamDecoder {
+    /// GZIP decoder
+    pub fn gzip() -> Self {
+        let output = SharedBuffer::default();
+        Self {
+            kind: DecoderKind::Gzip(write::GzDecoder::new(output.clone())),
+            output,
+        }
+    }
+
+    /// DEFLATE decoder
+    pub fn deflate() -> Self {
+        let output = SharedBuffer::default();
+        Self {
+            kind: DecoderKind::Deflate(Box::new(RawInflate::new())),
+            output,
+        }
+    }
+
+    /// Brotli decoder
+    pub fn brotli() -> Self {
+        let output = SharedBuffer::default();
+        Self {
+            kind: DecoderKind::Brotli(Box::new(brotli::DecompressorWriter::new(output.clone(), CHUNK_SIZE))),
+            output,
+        }
+    }
+
+    /// Zstandard decoder
+    pub fn zstd() -> std::io::Result<Self> {
+        let output = SharedBuffer::default();
+        Ok(Self {
+            kind: DecoderKind::Zstd(zstd::stream::zio::Writer::new(output.clone(), zstd::stream::raw::Decoder::new()?)),
+            output,
+        })
+    }
+
+    /// Decompresses a chunk and returns every byte it could decode, the decoders being
+    /// flushed so nothing is kept back. Errors with `InvalidData` when the input is not a valid stream
+    pub fn feed(&mut self, chunk: &[u8]) -> std::io::Result<Vec<u8>> {
+        match &mut self.kind {
+            DecoderKind::Gzip(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.flush()?;
+            }
+            DecoderKind::Deflate(decoder) => {
+                decoder.feed(chunk, &mut self.output.0.lock().unwrap())?;
+            }
+            DecoderKind::Brotli(decoder) => {
+                decoder.write_all(chunk).and_then(|_| decoder.flush()).map_err(invalid_data)?;
+            }
+            DecoderKind::Zstd(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.flush()?;
+            }
+        }
+        Ok(self.output.take())
+    }
+
+    /// Ends the stream and returns the remaining decoded bytes. 
+    /// Errors with `UnexpectedEof` if the compressed stream was cut short, or with the error of the codec
+    pub fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self.kind {
+            DecoderKind::Gzip(decoder) => {
+                decoder.finish()?;
+            }
+            DecoderKind::Deflate(decoder) => {
+                if !decoder.ended {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "deflate stream is incomplete"));
+                }
+            }
+            DecoderKind::Brotli(mut decoder) => {
+                decoder.close().map_err(|e| match e.kind() {
+                    std::io::ErrorKind::UnexpectedEof => e,
+                    _ => invalid_data(e),
+                })?;
+            }
+            DecoderKind::Zstd(mut decoder) => {
+                decoder.finish()?;
+            }
+        }
+        Ok(self.output.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        (0..20_000u32).flat_map(|i| format!("line {} of the body\n", i % 97).into_bytes()).collect()
+    }
+
+    fn round_trip(mut encoder: StreamEncoder, mut decoder: StreamDecoder) {
+        let data = sample();
+        let mut compressed = Vec::new();
+        for chunk in data.chunks(1000) {
+            compressed.extend(encoder.feed(chunk).unwrap());
+        }
+        compressed.extend(encoder.finish().unwrap());
+        assert!(compressed.len() < data.len());
+
+        let mut decompressed = Vec::new();
+        for chunk in compressed.chunks(333) {
+            decompressed.extend(decoder.feed(chunk).unwrap());
+        }
+        decompressed.extend(decoder.finish().unwrap());
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn streams_round_trip() {
+        round_trip(StreamEncoder::gzip(), StreamDecoder::gzip());
+        round_trip(StreamEncoder::deflate(), StreamDecoder::deflate());
+        round_trip(StreamEncoder::brotli(), StreamDecoder::brotli());
+        round_trip(StreamEncoder::zstd(ZSTD_DEFAULT_LEVEL).unwrap(), StreamDecoder::zstd().unwrap());
+    }
+
+    #[test]
+    fn streams_match_one_shot_functions() {
+        let data = sample();
+        let mut encoder = StreamEncoder::gzip();
+        let mut compressed = encoder.feed(&data).unwrap();
+        compressed.extend(encoder.finish().unwrap());
+        assert_eq!(decompress_gzip(&compressed).unwrap(), data);
+
+        let mut decoder = StreamDecoder::zstd().unwrap();
+        let mut decompressed = decoder.feed(&compress_zstd(&data, ZSTD_DEFAULT_LEVEL).unwrap()).unwrap();
+        decompressed.extend(decoder.finish().unwrap());
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn levels_round_trip() {
+        let data = sample();
+        let fast = compress_gzip_level(&data, 1).unwrap();
+        let best = compress_gzip_level(&data, 9).unwrap();
+        assert!(best.len() <= fast.len());
+        assert_eq!(decompress_gzip(&fast).unwrap(), data);
+        assert_eq!(decompress_deflate(&compress_deflate_level(&data, 0).unwrap()).unwrap(), data);
+        assert_eq!(decompress_brotli(&compress_brotli_quality(&data, 1).unwrap()).unwrap(), data);
+    }
+
+    #[test]
+    fn flush_makes_prefix_decodable() {
+        let mut encoder = StreamEncoder::gzip();
+        let mut compressed = encoder.feed(b"event: tick\n\n").unwrap();
+        compressed.extend(encoder.flush().unwrap());
+
+        let mut decoder = StreamDecoder::gzip();
+        assert_eq!(decoder.feed(&compressed).unwrap(), b"event: tick\n\n");
+    }
+
+    #[test]
+    fn truncated_stream_is_an_error() {
+        let data = sample();
+        let streams = [
+            (compress_gzip(&data).unwrap(), StreamDecoder::gzip()),
+            (compress_deflate(&data).unwrap(), StreamDecoder::deflate()),
+            (compress_brotli(&data).unwrap(), StreamDecoder::brotli()),
+            (compress_zstd(&data, ZSTD_DEFAULT_LEVEL).unwrap(), StreamDecoder::zstd().unwrap()),
+        ];
+        for (i, (compressed, mut decoder)) in streams.into_iter().enumerate() {
+            decoder.feed(&compressed[..compressed.len() / 2]).unwrap();
+            assert!(decoder.finish().is_err(), "stream {} was not detected as truncated", i);
+        }
+    }
+
+    #[test]
+    fn invalid_stream_is_an_error() {
+        let mut decoder = StreamDecoder::brotli();
+        let fed = decoder.feed(&[0xff; 64]);
+        assert!(fed.is_err() || decoder.finish().is_err());
+        let mut decoder = StreamDecoder::zstd().unwrap();
+        assert!(decoder.feed(b"not a zstd frame").is_err());
+    }
+}
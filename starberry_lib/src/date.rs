@@ -0,0 +1,311 @@
+//! Date formatting and parsing for HTTP headers, cookies, emails and APIs, in UTC.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const HTTP_DATE_DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const HTTP_DATE_MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses an HTTP-date (RFC 9110 5.6.7) into a `SystemTime`.
+///
+/// The preferred IMF-fixdate format is accepted as well as the obsolete RFC 850
+/// and asctime formats. Dates before the Unix epoch are rejected.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_lib::date::parse_http_date;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let expected = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(expected));
+/// assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(expected));
+/// assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(expected));
+/// assert_eq!(parse_http_date("yesterday"), None);
+/// ```
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let (day, month, year, time) = match parts.as_slice() {
+        // IMF-fixdate: Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => (day.parse::<u32>().ok()?, *month, year.parse::<i64>().ok()?, *time),
+        // RFC 850: Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut date = date.split('-');
+            let day = date.next()?.parse::<u32>().ok()?;
+            let month = date.next()?;
+            let year = date.next()?.parse::<i64>().ok()?;
+            let year = if year < 70 { year + 2000 } else if year < 100 { year + 1900 } else { year };
+            (day, month, year, *time)
+        }
+        // asctime: Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => (day.parse::<u32>().ok()?, *month, year.parse::<i64>().ok()?, *time),
+        _ => return None,
+    };
+
+    let month = HTTP_DATE_MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let mut time = time.split(':').map(|t| t.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some() || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    let secs = days as u64 * 86400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Formats a `SystemTime` as an IMF-fixdate, the format required for HTTP headers.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_lib::date::format_http_date;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+/// ```
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    // 1970-01-01 was a Thursday
+    let weekday = HTTP_DATE_DAYS[((days + 3) % 7) as usize];
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        weekday,
+        day,
+        HTTP_DATE_MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Last formatted `Date` header value, keyed by its Unix second
+static HTTP_DATE_CACHE: Mutex<(u64, String)> = Mutex::new((0, String::new()));
+
+/// The current time as an HTTP-date, for the `Date` response header. 
+/// The string is formatted at most once per second and shared between requests.
+pub fn http_date_now() -> String {
+    let now = SystemTime::now();
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut cache = HTTP_DATE_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.0 != secs || cache.1.is_empty() {
+        *cache = (secs, format_http_date(now));
+    }
+    cache.1.clone()
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Proleptic Gregorian date (year, month, day) for a number of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Splits seconds since the epoch into days and the seconds of the day
+fn split_secs(time: SystemTime) -> (i64, u64) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    ((secs / 86400) as i64, secs % 86400)
+}
+
+/// Formats a `SystemTime` as an RFC 5322 date, the format of the `Date` header of emails.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_lib::date::format_email_date;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(format_email_date(time), "Sun, 6 Nov 1994 08:49:37 +0000");
+/// ```
+pub fn format_email_date(time: SystemTime) -> String {
+    let (days, rem) = split_secs(time);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {} {} {:04} {:02}:{:02}:{:02} +0000",
+        HTTP_DATE_DAYS[((days + 3) % 7) as usize],
+        day,
+        HTTP_DATE_MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Formats a `SystemTime` as an RFC 3339 timestamp in UTC, with milliseconds if it has any.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_lib::date::format_rfc3339;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// assert_eq!(format_rfc3339(UNIX_EPOCH + Duration::from_secs(784111777)), "1994-11-06T08:49:37Z");
+/// assert_eq!(format_rfc3339(UNIX_EPOCH + Duration::from_millis(1500)), "1970-01-01T00:00:01.500Z");
+/// ```
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let (days, rem) = split_secs(time);
+    let (year, month, day) = civil_from_days(days);
+    let millis = time.duration_since(UNIX_EPOCH).map(|d| d.subsec_millis()).unwrap_or(0);
+    let fraction = if millis == 0 { String::new() } else { format!(".{:03}", millis) };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+        fraction
+    )
+}
+
+/// Parses an RFC 3339 timestamp, with any fraction of second and any UTC offset.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_lib::date::parse_rfc3339;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let expected = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(parse_rfc3339("1994-11-06T08:49:37Z"), Some(expected));
+/// assert_eq!(parse_rfc3339("1994-11-06T10:49:37+02:00"), Some(expected));
+/// assert_eq!(parse_rfc3339("1994-11-06 08:49:37.25z"), Some(expected + Duration::from_millis(250)));
+/// assert_eq!(parse_rfc3339("1994-11-06"), None);
+/// ```
+pub fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let value = value.trim();
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<u64> {
+        let digits = value.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Fraction of second
+    let mut rest = &value[19..];
+    let mut nanos = 0u32;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        for (i, b) in fraction.bytes().take(digits).enumerate() {
+            if i < 9 {
+                nanos += (b - b'0') as u32 * 10u32.pow(8 - i as u32);
+            }
+        }
+        rest = &fraction[digits..];
+    }
+
+    // Offset from UTC
+    let offset: i64 = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let digits = [*h1, *h2, *m1, *m2];
+            if !digits.iter().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let hours = ((h1 - b'0') * 10 + (h2 - b'0')) as i64;
+            let minutes = ((m1 - b'0') * 10 + (m2 - b'0')) as i64;
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' { -offset } else { offset }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year as i64, month as u32, day as u32);
+    let secs = days * 86400 + (hour * 3600 + minute * 60 + second) as i64 - offset;
+    if secs < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::new(secs as u64, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_date_now_is_current() {
+        let parsed = parse_http_date(&http_date_now()).unwrap();
+        let now = SystemTime::now();
+        assert!(parsed <= now);
+        assert!(now.duration_since(parsed).unwrap() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn civil_round_trip() {
+        for days in [-719468, -1, 0, 59, 10957, 11016, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn http_date_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+    }
+
+    #[test]
+    fn rfc3339_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        assert_eq!(parse_rfc3339(&format_rfc3339(time)), Some(time));
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00+01:00"), None);
+        assert_eq!(parse_rfc3339("2024-13-01T00:00:00Z"), None);
+    }
+}
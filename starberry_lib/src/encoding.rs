@@ -0,0 +1,118 @@
+//! Base64 and hex encoding shared by the crates of starberry.
+
+use base64::{Engine as _, engine::general_purpose};
+
+/// The input of a decoding function was not valid for its encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError(pub String);
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Decode error: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Encodes bytes with the standard base64 alphabet, with padding
+///
+/// # Example
+/// ```
+/// use starberry_lib::encoding::base64_encode;
+/// assert_eq!(base64_encode("hi?"), "aGk/");
+/// ```
+pub fn base64_encode<T: AsRef<[u8]>>(input: T) -> String {
+    general_purpose::STANDARD.encode(input)
+}
+
+/// Decodes standard base64, with padding
+pub fn base64_decode<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>, DecodeError> {
+    general_purpose::STANDARD
+        .decode(input)
+        .map_err(|e| DecodeError(e.to_string()))
+}
+
+/// Encodes bytes with the URL safe base64 alphabet, without padding, as used by JWT and PKCE
+///
+/// # Example
+/// ```
+/// use starberry_lib::encoding::base64_url_encode;
+/// assert_eq!(base64_url_encode("hi?"), "aGk_");
+/// ```
+pub fn base64_url_encode<T: AsRef<[u8]>>(input: T) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(input)
+}
+
+/// Decodes URL safe base64, with or without padding
+pub fn base64_url_decode<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>, DecodeError> {
+    let input = input.as_ref();
+    let trimmed = input.strip_suffix(b"==").or_else(|| input.strip_suffix(b"=")).unwrap_or(input);
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(trimmed)
+        .map_err(|e| DecodeError(e.to_string()))
+}
+
+/// Encodes bytes as lowercase hex
+///
+/// # Example
+/// ```
+/// use starberry_lib::encoding::hex_encode;
+/// assert_eq!(hex_encode([0x0f, 0xa0]), "0fa0");
+/// ```
+pub fn hex_encode<T: AsRef<[u8]>>(input: T) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let input = input.as_ref();
+    let mut out = String::with_capacity(input.len() * 2);
+    for byte in input {
+        out.push(DIGITS[(byte >> 4) as usize] as char);
+        out.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    out
+}
+
+/// Decodes hex, in lowercase or uppercase
+pub fn hex_decode<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>, DecodeError> {
+    fn digit(c: u8) -> Result<u8, DecodeError> {
+        match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(DecodeError(format!("invalid hex digit {:?}", c as char))),
+        }
+    }
+    let input = input.as_ref();
+    if input.len() % 2 != 0 {
+        return Err(DecodeError("odd number of hex digits".to_string()));
+    }
+    input
+        .chunks(2)
+        .map(|pair| Ok(digit(pair[0])? << 4 | digit(pair[1])?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trip() {
+        let data = b"\x00\xffstarberry";
+        assert_eq!(base64_decode(base64_encode(data)).unwrap(), data);
+        assert_eq!(base64_url_decode(base64_url_encode(data)).unwrap(), data);
+        assert!(base64_decode("not base64!").is_err());
+    }
+
+    #[test]
+    fn base64_url_accepts_padding() {
+        assert_eq!(base64_url_decode("aGk").unwrap(), b"hi");
+        assert_eq!(base64_url_decode("aGk=").unwrap(), b"hi");
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(hex_encode(b"\x01\xab"), "01ab");
+        assert_eq!(hex_decode("01AB").unwrap(), b"\x01\xab");
+        assert!(hex_decode("abc").is_err());
+        assert!(hex_decode("zz").is_err());
+    }
+}
@@ -3,7 +3,7 @@ pub mod aes {
         Aes256Gcm, Nonce,
         aead::{Aead, KeyInit},
     };
-    use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+    use crate::encoding::{base64_decode, base64_encode};
     use hkdf::Hkdf;
     use rand::RngCore;
     use rand::rngs::OsRng;
@@ -111,13 +111,12 @@ pub mod aes {
         serialized.extend_from_slice(&data.salt);
         serialized.extend_from_slice(&data.nonce);
         serialized.extend_from_slice(&data.ciphertext);
-        BASE64.encode(serialized)
+        base64_encode(serialized)
     }
 
     // Deserialize from string back to EncryptedData
     pub fn deserialize_encrypted_data(serialized: &str) -> Result<EncryptedData, String> {
-        let decoded = BASE64
-            .decode(serialized)
+        let decoded = base64_decode(serialized)
             .map_err(|e| format!("Base64 decoding failed: {}", e))?;
 
         if decoded.len() < 16 + 12 {
@@ -140,6 +139,92 @@ pub mod aes {
     }
 }
 
+/// HMAC signatures, constant time comparison and key derivation, 
+/// used to sign cookies, CSRF tokens and to verify webhooks 
+pub mod signing {
+    use hkdf::Hkdf;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use crate::encoding::{base64_url_decode, base64_url_encode};
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    /// Signs the data with HMAC-SHA256
+    pub fn sign_hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(data);
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Checks an HMAC-SHA256 tag in constant time
+    pub fn verify_hmac_sha256(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(data);
+        mac.verify_slice(tag).is_ok()
+    }
+
+    /// Signs the data and returns the tag as URL safe base64, ready for cookies and headers
+    pub fn sign(key: &[u8], data: &[u8]) -> String {
+        base64_url_encode(sign_hmac_sha256(key, data))
+    }
+
+    /// Checks a tag produced by `sign`
+    pub fn verify(key: &[u8], data: &[u8], signature: &str) -> bool {
+        match base64_url_decode(signature) {
+            Ok(tag) => verify_hmac_sha256(key, data, &tag),
+            Err(_) => false,
+        }
+    }
+
+    /// Compares two byte strings in a time which only depends on their length, 
+    /// so that the comparison of secrets does not leak how many bytes match
+    pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+        let mut diff = 0u8;
+        for (x, y) in a.iter().zip(b) {
+            diff |= x ^ y;
+        }
+        // Keep the compiler from turning the loop into an early exit
+        std::hint::black_box(diff) == 0
+    }
+
+    /// Derives a key for one purpose from a master secret with HKDF-SHA256. 
+    /// Different `context` strings give independent keys, e.g. "cookies" and "csrf"
+    pub fn derive_key(secret: &[u8], context: &str) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(context.as_bytes(), &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        key
+    }
+
+    /// Derives a key from a password with PBKDF2-HMAC-SHA256. 
+    /// Use at least 600 000 iterations for passwords chosen by users
+    pub fn derive_key_from_password(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<HmacSha256>(password.as_bytes(), salt, iterations, &mut key)
+            .expect("HMAC accepts keys of any size");
+        key
+    }
+}
+
+/// Digests of data, e.g. to fingerprint files or compare contents
+pub mod digest {
+    use sha2::{Digest, Sha256};
+
+    pub fn sha256(data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+
+    /// The SHA-256 digest as lowercase hex
+    pub fn sha256_hex(data: &[u8]) -> String {
+        crate::encoding::hex_encode(sha256(data))
+    }
+}
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -159,4 +244,46 @@ mod test {
 
         assert_eq!(decrypted, plaintext);
     }
+
+    #[test]
+    fn hmac_sha256_rfc4231() {
+        // Test case 2 of RFC 4231
+        let tag = super::signing::sign_hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            crate::encoding::hex_encode(tag),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(super::signing::verify_hmac_sha256(b"Jefe", b"what do ya want for nothing?", &tag));
+        assert!(!super::signing::verify_hmac_sha256(b"Jefe", b"what do ya want for something?", &tag));
+    }
+
+    #[test]
+    fn sha256_digest() {
+        assert_eq!(
+            super::digest::sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn signatures() {
+        let signature = super::signing::sign(b"key", b"session=42");
+        assert!(super::signing::verify(b"key", b"session=42", &signature));
+        assert!(!super::signing::verify(b"other", b"session=42", &signature));
+        assert!(!super::signing::verify(b"key", b"session=42", "not base64!"));
+    }
+
+    #[test]
+    fn constant_time_eq() {
+        assert!(super::signing::constant_time_eq(b"abc", b"abc"));
+        assert!(!super::signing::constant_time_eq(b"abc", b"abd"));
+        assert!(!super::signing::constant_time_eq(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn derived_keys_depend_on_context() {
+        let cookies = super::signing::derive_key(b"secret", "cookies");
+        assert_eq!(cookies, super::signing::derive_key(b"secret", "cookies"));
+        assert_ne!(cookies, super::signing::derive_key(b"secret", "csrf"));
+    }
 }
@@ -0,0 +1,463 @@
+//! Signing and verification of JSON Web Tokens, for applications minting their own tokens, e.g.
+//! API keys, email confirmation links or session tokens, without an OAuth provider.
+//!
+//! A `Jwt` holds the keys of one algorithm, HS256, RS256 or ES256, and the rules of the
+//! verification: the expected issuer and audience, and the leeway given to the clocks. The
+//! registered claims are the fields of `Claims`, the claims of the application its `extra`
+//! field, a map by default or any serde struct.
+//!
+//! # Example
+//! ```
+//! use std::time::Duration;
+//! use starberry_lib::jwt::{Claims, Jwt};
+//!
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Confirm {
+//!     email: String,
+//! }
+//!
+//! let jwt = Jwt::hs256(b"a long random secret").issuer("https://example.com");
+//! let claims = Claims::new(Confirm { email: "user@example.com".into() })
+//!     .subject("42")
+//!     .expires_in(Duration::from_secs(3600));
+//! let token = jwt.sign(&claims).unwrap();
+//!
+//! let verified = jwt.verify::<Confirm>(&token).unwrap();
+//! assert_eq!(verified.sub.as_deref(), Some("42"));
+//! assert_eq!(verified.extra.email, "user@example.com");
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, encode, Header, Validation};
+pub use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+/// Errors of signing and verifying tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwtError {
+    /// A key could not be read, e.g. a PEM of another algorithm
+    InvalidKey(String),
+    /// The `Jwt` only verifies tokens, it has no private key
+    MissingKey,
+    /// The token is not a JWT of the algorithm, or its claims do not match the type
+    Malformed(String),
+    InvalidSignature,
+    Expired,
+    /// The `nbf` claim is in the future
+    NotYetValid,
+    /// The issuer or the audience is not the expected one, or a required claim is missing
+    InvalidClaims(String),
+    /// The token could not be signed
+    Signing(String),
+}
+
+impl std::fmt::Display for JwtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JwtError::InvalidKey(msg) => write!(f, "Invalid key: {}", msg),
+            JwtError::MissingKey => write!(f, "No key to sign the token"),
+            JwtError::Malformed(msg) => write!(f, "Malformed token: {}", msg),
+            JwtError::InvalidSignature => write!(f, "Invalid token signature"),
+            JwtError::Expired => write!(f, "The token is expired"),
+            JwtError::NotYetValid => write!(f, "The token is not valid yet"),
+            JwtError::InvalidClaims(msg) => write!(f, "Invalid token claims: {}", msg),
+            JwtError::Signing(msg) => write!(f, "Token signing failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+impl From<jsonwebtoken::errors::Error> for JwtError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        match err.kind() {
+            ErrorKind::InvalidSignature => JwtError::InvalidSignature,
+            ErrorKind::ExpiredSignature => JwtError::Expired,
+            ErrorKind::ImmatureSignature => JwtError::NotYetValid,
+            ErrorKind::InvalidIssuer => JwtError::InvalidClaims("iss".to_string()),
+            ErrorKind::InvalidAudience => JwtError::InvalidClaims("aud".to_string()),
+            ErrorKind::InvalidSubject => JwtError::InvalidClaims("sub".to_string()),
+            ErrorKind::MissingRequiredClaim(claim) => JwtError::InvalidClaims(format!("missing {}", claim)),
+            ErrorKind::InvalidAlgorithm | ErrorKind::InvalidAlgorithmName => JwtError::Malformed("unexpected algorithm".to_string()),
+            ErrorKind::InvalidEcdsaKey | ErrorKind::InvalidRsaKey(_) | ErrorKind::InvalidKeyFormat => JwtError::InvalidKey(err.to_string()),
+            _ => JwtError::Malformed(err.to_string()),
+        }
+    }
+}
+
+/// The claims of a token: the registered claims of RFC 7519 and those of the application,
+/// flattened next to them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims<T = Map<String, Value>> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    /// A single audience is written as a string, several as an array
+    #[serde(default, skip_serializing_if = "Vec::is_empty", serialize_with = "serialize_aud", deserialize_with = "deserialize_aud")]
+    pub aud: Vec<String>,
+    /// Expiration time, in seconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<u64>,
+    /// Not before, in seconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
+    /// Issued at, in seconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(flatten)]
+    pub extra: T,
+}
+
+impl<T> Claims<T> {
+    /// Claims issued now, carrying the claims of the application
+    pub fn new(extra: T) -> Self {
+        Self {
+            iss: None,
+            sub: None,
+            aud: Vec::new(),
+            exp: None,
+            nbf: None,
+            iat: Some(now()),
+            jti: None,
+            extra,
+        }
+    }
+
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.iss = Some(issuer.into());
+        self
+    }
+
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.sub = Some(subject.into());
+        self
+    }
+
+    /// Adds an audience of the token
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.aud.push(audience.into());
+        self
+    }
+
+    /// Sets the token to expire after the duration
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.exp = Some(now() + duration.as_secs());
+        self
+    }
+
+    /// Sets the token to be valid only after the duration
+    pub fn not_before(mut self, duration: Duration) -> Self {
+        self.nbf = Some(now() + duration.as_secs());
+        self
+    }
+
+    /// Sets the unique identifier of the token, e.g. to refuse its reuse
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.jti = Some(id.into());
+        self
+    }
+}
+
+impl Claims {
+    /// Claims issued now, without claims of the application
+    pub fn empty() -> Self {
+        Self::new(Map::new())
+    }
+
+    /// Sets a claim of the application
+    pub fn claim(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extra.insert(name.into(), value.into());
+        self
+    }
+}
+
+fn serialize_aud<S: Serializer>(aud: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    match aud {
+        [single] => serializer.serialize_str(single),
+        many => many.serialize(serializer),
+    }
+}
+
+fn deserialize_aud<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match Audience::deserialize(deserializer)? {
+        Audience::One(aud) => vec![aud],
+        Audience::Many(aud) => aud,
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Signs and verifies the tokens of one key
+#[derive(Clone)]
+pub struct Jwt {
+    algorithm: Algorithm,
+    encoding_key: Option<EncodingKey>,
+    decoding_key: DecodingKey,
+    key_id: Option<String>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: Duration,
+    require_exp: bool,
+}
+
+impl Jwt {
+    /// A `Jwt` of keys read by the application, without a private key it only verifies tokens
+    pub fn from_keys(algorithm: Algorithm, encoding_key: Option<EncodingKey>, decoding_key: DecodingKey) -> Self {
+        Self {
+            algorithm,
+            encoding_key,
+            decoding_key,
+            key_id: None,
+            issuer: None,
+            audience: None,
+            leeway: Duration::from_secs(60),
+            require_exp: true,
+        }
+    }
+
+    /// HMAC SHA-256 with a shared secret, which should be at least 32 random bytes
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self::from_keys(Algorithm::HS256, Some(EncodingKey::from_secret(secret)), DecodingKey::from_secret(secret))
+    }
+
+    /// RSA SHA-256 with a PEM encoded key pair
+    pub fn rs256(private_key_pem: &[u8], public_key_pem: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self::from_keys(
+            Algorithm::RS256,
+            Some(EncodingKey::from_rsa_pem(private_key_pem).map_err(|e| JwtError::InvalidKey(e.to_string()))?),
+            DecodingKey::from_rsa_pem(public_key_pem).map_err(|e| JwtError::InvalidKey(e.to_string()))?,
+        ))
+    }
+
+    /// Verifies RS256 tokens signed by another party, with its PEM encoded public key
+    pub fn rs256_verifier(public_key_pem: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self::from_keys(
+            Algorithm::RS256,
+            None,
+            DecodingKey::from_rsa_pem(public_key_pem).map_err(|e| JwtError::InvalidKey(e.to_string()))?,
+        ))
+    }
+
+    /// ECDSA P-256 SHA-256 with a PEM encoded key pair, the private key in PKCS#8
+    pub fn es256(private_key_pem: &[u8], public_key_pem: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self::from_keys(
+            Algorithm::ES256,
+            Some(EncodingKey::from_ec_pem(private_key_pem).map_err(|e| JwtError::InvalidKey(e.to_string()))?),
+            DecodingKey::from_ec_pem(public_key_pem).map_err(|e| JwtError::InvalidKey(e.to_string()))?,
+        ))
+    }
+
+    /// Verifies ES256 tokens signed by another party, with its PEM encoded public key
+    pub fn es256_verifier(public_key_pem: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self::from_keys(
+            Algorithm::ES256,
+            None,
+            DecodingKey::from_ec_pem(public_key_pem).map_err(|e| JwtError::InvalidKey(e.to_string()))?,
+        ))
+    }
+
+    /// Sets the `kid` written in the header of the signed tokens
+    pub fn key_id(mut self, kid: impl Into<String>) -> Self {
+        self.key_id = Some(kid.into());
+        self
+    }
+
+    /// Only accepts the tokens of the issuer, which is written in the signed tokens without one
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Only accepts the tokens intended for the audience, which is written in the signed tokens
+    /// without one
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Sets how far the clocks of the issuer and the verifier may differ when checking `exp`
+    /// and `nbf`, 60 seconds by default
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Accepts the tokens without an `exp` claim, which never expire
+    pub fn allow_no_expiry(mut self) -> Self {
+        self.require_exp = false;
+        self
+    }
+
+    /// The algorithm of the key
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Signs the claims into a compact token
+    pub fn sign<T: Serialize>(&self, claims: &Claims<T>) -> Result<String, JwtError> {
+        let key = self.encoding_key.as_ref().ok_or(JwtError::MissingKey)?;
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.key_id.clone();
+        // The issuer and audience of the `Jwt` are written when the claims leave them out, so
+        // the `Jwt` verifies the tokens it signs
+        let mut claims = serde_json::to_value(claims).map_err(|e| JwtError::Signing(e.to_string()))?;
+        if let Value::Object(map) = &mut claims {
+            if let Some(issuer) = &self.issuer {
+                map.entry("iss").or_insert_with(|| Value::from(issuer.as_str()));
+            }
+            if let Some(audience) = &self.audience {
+                map.entry("aud").or_insert_with(|| Value::from(audience.as_str()));
+            }
+        }
+        encode(&header, &claims, key).map_err(|e| JwtError::Signing(e.to_string()))
+    }
+
+    /// Verifies the signature and the claims of a token, returning its claims
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<Claims<T>, JwtError> {
+        self.verify_with(token, &self.decoding_key)
+    }
+
+    /// Verifies a token with another key than the one of the `Jwt`, e.g. the key of a JWKS
+    /// picked by the `kid` of the token, under the same rules
+    pub fn verify_with<T: DeserializeOwned>(&self, token: &str, decoding_key: &DecodingKey) -> Result<Claims<T>, JwtError> {
+        Ok(decode::<Claims<T>>(token, decoding_key, &self.validation())?.claims)
+    }
+
+    fn validation(&self) -> Validation {
+        let mut validation = Validation::new(self.algorithm);
+        validation.leeway = self.leeway.as_secs();
+        validation.validate_nbf = true;
+        validation.validate_exp = self.require_exp;
+        validation.required_spec_claims.clear();
+        if self.require_exp {
+            validation.required_spec_claims.insert("exp".to_string());
+        }
+        // jsonwebtoken only compares the claims present, a token without them would pass
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+            validation.required_spec_claims.insert("iss".to_string());
+        }
+        match &self.audience {
+            Some(audience) => {
+                validation.set_audience(&[audience]);
+                validation.required_spec_claims.insert("aud".to_string());
+            }
+            None => validation.validate_aud = false,
+        }
+        validation
+    }
+}
+
+impl std::fmt::Debug for Jwt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jwt")
+            .field("algorithm", &self.algorithm)
+            .field("can_sign", &self.encoding_key.is_some())
+            .field("key_id", &self.key_id)
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("leeway", &self.leeway)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hs256_round_trip() {
+        let jwt = Jwt::hs256(b"secret").issuer("me").audience("api").key_id("k1");
+        let claims = Claims::empty()
+            .issuer("me")
+            .audience("api")
+            .subject("42")
+            .claim("role", "admin")
+            .expires_in(Duration::from_secs(60));
+        let token = jwt.sign(&claims).unwrap();
+        assert_eq!(jsonwebtoken::decode_header(&token).unwrap().kid.as_deref(), Some("k1"));
+        let verified: Claims = jwt.verify(&token).unwrap();
+        assert_eq!(verified, claims);
+        assert_eq!(verified.extra.get("role"), Some(&Value::from("admin")));
+    }
+
+    #[test]
+    fn signs_with_its_issuer_and_audience() {
+        let jwt = Jwt::hs256(b"secret").issuer("me").audience("api");
+        let token = jwt.sign(&Claims::empty().expires_in(Duration::from_secs(60))).unwrap();
+        let verified: Claims = jwt.verify(&token).unwrap();
+        assert_eq!(verified.iss.as_deref(), Some("me"));
+        assert_eq!(verified.aud, vec!["api".to_string()]);
+
+        // Claims setting their own issuer keep it
+        let token = jwt.sign(&Claims::empty().issuer("them").expires_in(Duration::from_secs(60))).unwrap();
+        assert_eq!(jwt.verify::<Map<String, Value>>(&token), Err(JwtError::InvalidClaims("iss".to_string())));
+    }
+
+    #[test]
+    fn rejects_other_keys_and_claims() {
+        let claims = Claims::empty().issuer("me").expires_in(Duration::from_secs(60));
+        let token = Jwt::hs256(b"secret").sign(&claims).unwrap();
+        assert_eq!(Jwt::hs256(b"other").verify::<Map<String, Value>>(&token), Err(JwtError::InvalidSignature));
+        assert_eq!(
+            Jwt::hs256(b"secret").issuer("them").verify::<Map<String, Value>>(&token),
+            Err(JwtError::InvalidClaims("iss".to_string()))
+        );
+        assert!(matches!(
+            Jwt::hs256(b"secret").audience("api").verify::<Map<String, Value>>(&token),
+            Err(JwtError::InvalidClaims(_))
+        ));
+
+        let token = Jwt::hs256(b"secret").sign(&Claims::empty().audience("api").expires_in(Duration::from_secs(60))).unwrap();
+        assert_eq!(
+            Jwt::hs256(b"secret").issuer("me").verify::<Map<String, Value>>(&token),
+            Err(JwtError::InvalidClaims("missing iss".to_string()))
+        );
+    }
+
+    #[test]
+    fn expiry_and_leeway() {
+        let mut claims = Claims::empty();
+        claims.exp = Some(now() - 30);
+        let token = Jwt::hs256(b"secret").sign(&claims).unwrap();
+        assert!(Jwt::hs256(b"secret").verify::<Map<String, Value>>(&token).is_ok());
+        assert_eq!(
+            Jwt::hs256(b"secret").leeway(Duration::ZERO).verify::<Map<String, Value>>(&token),
+            Err(JwtError::Expired)
+        );
+
+        let token = Jwt::hs256(b"secret").sign(&Claims::empty()).unwrap();
+        assert!(matches!(Jwt::hs256(b"secret").verify::<Map<String, Value>>(&token), Err(JwtError::InvalidClaims(_))));
+        assert!(Jwt::hs256(b"secret").allow_no_expiry().verify::<Map<String, Value>>(&token).is_ok());
+    }
+
+    #[test]
+    fn audience_as_string_or_array() {
+        let claims: Claims = serde_json::from_str(r#"{"aud":"api","exp":1}"#).unwrap();
+        assert_eq!(claims.aud, vec!["api".to_string()]);
+        let claims: Claims = serde_json::from_str(r#"{"aud":["a","b"]}"#).unwrap();
+        assert_eq!(claims.aud, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(serde_json::to_string(&Claims { iat: None, ..Claims::empty().audience("api") }).unwrap(), r#"{"aud":"api"}"#);
+    }
+
+    #[test]
+    fn verifier_cannot_sign() {
+        let jwt = Jwt { encoding_key: None, ..Jwt::hs256(b"secret") };
+        assert_eq!(jwt.sign(&Claims::empty()), Err(JwtError::MissingKey));
+    }
+}
@@ -3,24 +3,61 @@ use rand::Rng;
 
 
 /// Generates a random string of the specified length using printable ASCII characters. 
+/// 
+/// The characters include quotes and other symbols, which makes the result unsuitable for 
+/// cookies, URLs and headers. For security sensitive identifiers use `secure_token`. 
 pub fn random_string(length: usize) -> String {
     let mut rng = rand::rng();
     let bytes: Vec<u8> = (0..length).map(|_| rng.random_range(33..127)).collect();
     String::from_utf8(bytes).unwrap()
 } 
 
-
+/// Generates a random string of the specified length using ASCII letters and digits. 
+/// 
+/// It draws from the thread local generator of `rand`, a CSPRNG seeded by the OS, so it may be 
+/// used for identifiers which must not be guessed, such as request ids. `secure_token` reads the 
+/// OS generator directly and is preferred for secrets such as session or CSRF tokens. 
 pub fn random_alphanumeric_string(length: usize) -> String {
     const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-    let mut rng = rand::thread_rng();
+    let mut rng = rand::rng();
     (0..length)
         .map(|_| {
-            let idx = rng.gen_range(0..CHARSET.len());
+            let idx = rng.random_range(0..CHARSET.len());
             CHARSET[idx] as char
         })
         .collect()
 }
 
+/// Generates a secret token from `bytes` random bytes of the OS CSPRNG, encoded as URL safe 
+/// base64 without padding, so it can be used in cookies, URLs and headers as is. 
+/// 
+/// 32 bytes (256 bits) is recommended for session, CSRF, password reset and API tokens. 
+/// 
+/// # Panics 
+/// 
+/// Panics if the OS random generator is unavailable. 
+/// 
+/// # Example 
+/// ``` 
+/// let token = starberry_lib::secure_token(32); 
+/// assert_eq!(token.len(), 43); 
+/// ``` 
+#[cfg(feature = "encoding")]
+pub fn secure_token(bytes: usize) -> String {
+    use rand::TryRngCore;
+    let mut buf = vec![0u8; bytes];
+    rand::rngs::OsRng
+        .try_fill_bytes(&mut buf)
+        .expect("OS random generator unavailable");
+    encoding::base64_url_encode(buf)
+}
+
+pub mod uuid; 
+pub mod date; 
+
+#[cfg(feature = "encoding")]
+pub mod encoding; 
+
 #[cfg(feature = "ende")]
 pub mod ende; 
 
@@ -30,3 +67,8 @@ pub mod url_encoding;
 #[cfg(feature = "compression")] 
 pub mod compression; 
 
+#[cfg(feature = "password")] 
+pub mod password; 
+
+#[cfg(feature = "jwt")] 
+pub mod jwt; 
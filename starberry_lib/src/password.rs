@@ -0,0 +1,173 @@
+//! Password hashing for login flows.
+//!
+//! New hashes use argon2id and are stored in the PHC string format, e.g.
+//! `$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`, which records the algorithm, its version
+//! and its parameters next to the hash. Hashes made with other parameters, or bcrypt hashes
+//! imported from another system, still verify, and `needs_rehash` tells when to replace them.
+//!
+//! # Example
+//! ```
+//! use starberry_lib::password::{hash_password, needs_rehash, verify_password, PasswordParams};
+//!
+//! let hash = hash_password("correct horse battery staple").unwrap();
+//! assert!(verify_password("correct horse battery staple", &hash).unwrap());
+//! assert!(!verify_password("wrong", &hash).unwrap());
+//! assert!(!needs_rehash(&hash, &PasswordParams::default()));
+//! ```
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::TryRngCore;
+use rand::rngs::OsRng;
+
+/// Errors of password hashing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordError {
+    /// The stored hash is not in a supported format
+    InvalidHash(String),
+    /// The hash could not be computed, e.g. because of invalid parameters
+    Hashing(String),
+}
+
+impl std::fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordError::InvalidHash(msg) => write!(f, "Invalid password hash: {}", msg),
+            PasswordError::Hashing(msg) => write!(f, "Password hashing failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+/// The cost parameters of argon2id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordParams {
+    /// Memory in KiB
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordParams {
+    /// The minimum recommended by OWASP: 19 MiB, 2 iterations, 1 lane
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl PasswordParams {
+    fn argon2(&self) -> Result<Argon2<'static>, PasswordError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| PasswordError::Hashing(e.to_string()))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix))
+}
+
+/// Hashes a password with argon2id and the default parameters
+pub fn hash_password(password: &str) -> Result<String, PasswordError> {
+    hash_password_with(password, &PasswordParams::default())
+}
+
+/// Hashes a password with argon2id and the given parameters, with a random salt
+pub fn hash_password_with(password: &str, params: &PasswordParams) -> Result<String, PasswordError> {
+    let mut salt = [0u8; 16];
+    OsRng
+        .try_fill_bytes(&mut salt)
+        .map_err(|e| PasswordError::Hashing(format!("Failed to fill salt: {}", e)))?;
+    let salt = SaltString::encode_b64(&salt).map_err(|e| PasswordError::Hashing(e.to_string()))?;
+    params
+        .argon2()?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| PasswordError::Hashing(e.to_string()))
+}
+
+/// Checks a password against a stored argon2 or bcrypt hash.
+/// Returns `Err` only if the hash itself is malformed
+pub fn verify_password(password: &str, hash: &str) -> Result<bool, PasswordError> {
+    if is_bcrypt(hash) {
+        return bcrypt::verify(password, hash).map_err(|e| PasswordError::InvalidHash(e.to_string()));
+    }
+    let parsed = PasswordHash::new(hash).map_err(|e| PasswordError::InvalidHash(e.to_string()))?;
+    // The algorithm, version and parameters are read from the hash
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(PasswordError::InvalidHash(e.to_string())),
+    }
+}
+
+/// Whether the hash should be replaced by a new one, computed after a successful login:
+/// bcrypt hashes, argon2 variants other than argon2id, and hashes with other parameters
+pub fn needs_rehash(hash: &str, params: &PasswordParams) -> bool {
+    if is_bcrypt(hash) {
+        return true;
+    }
+    let Ok(parsed) = PasswordHash::new(hash) else {
+        return true;
+    };
+    if parsed.algorithm != Algorithm::Argon2id.ident() || parsed.version != Some(Version::V0x13.into()) {
+        return true;
+    }
+    match Params::try_from(&parsed) {
+        Ok(current) => {
+            current.m_cost() != params.memory_kib
+                || current.t_cost() != params.iterations
+                || current.p_cost() != params.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cheap() -> PasswordParams {
+        PasswordParams {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn argon2id_round_trip() {
+        let hash = hash_password_with("secret", &cheap()).unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(verify_password("secret", &hash).unwrap());
+        assert!(!verify_password("Secret", &hash).unwrap());
+        // Each hash has its own salt
+        assert_ne!(hash, hash_password_with("secret", &cheap()).unwrap());
+    }
+
+    #[test]
+    fn verifies_bcrypt() {
+        let hash = bcrypt::hash("secret", 4).unwrap();
+        assert!(verify_password("secret", &hash).unwrap());
+        assert!(!verify_password("other", &hash).unwrap());
+        assert!(needs_rehash(&hash, &cheap()));
+    }
+
+    #[test]
+    fn rehash_on_parameter_change() {
+        let hash = hash_password_with("secret", &cheap()).unwrap();
+        assert!(!needs_rehash(&hash, &cheap()));
+        assert!(needs_rehash(&hash, &PasswordParams::default()));
+    }
+
+    #[test]
+    fn malformed_hash() {
+        assert!(verify_password("secret", "plain text").is_err());
+        assert!(needs_rehash("plain text", &cheap()));
+    }
+}
@@ -0,0 +1,201 @@
+//! UUID generation: version 4 (random) and version 7 (time-ordered).
+//!
+//! Version 7 UUIDs start with the Unix time in milliseconds, so they sort by creation time,
+//! which keeps database indexes compact when used as keys. Within the process they are
+//! strictly increasing, even when several are created in the same millisecond.
+//!
+//! # Example
+//! ```
+//! use starberry_lib::uuid::Uuid;
+//!
+//! let id = Uuid::new_v7();
+//! assert_eq!(id.version(), 7);
+//! assert_eq!(id.to_string().len(), 36);
+//! assert_eq!(Uuid::parse(&id.to_string()), Some(id));
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+
+/// A 128 bit universally unique identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uuid([u8; 16]);
+
+/// The last timestamp and counter given to a v7 UUID, as `millis << 12 | counter`
+static LAST_V7: AtomicU64 = AtomicU64::new(0);
+
+impl Uuid {
+    /// The nil UUID, all zeros
+    pub const NIL: Uuid = Uuid([0; 16]);
+
+    /// Creates a random (version 4) UUID
+    pub fn new_v4() -> Self {
+        let mut bytes: [u8; 16] = rand::rng().random();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Self(bytes)
+    }
+
+    /// Creates a time-ordered (version 7) UUID
+    pub fn new_v7() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time error")
+            .as_millis() as u64;
+        let mut rng = rand::rng();
+        // Start the counter of a new millisecond at a random point of its lower half
+        let candidate = (now << 12) | rng.random_range(0..0x800);
+        let mut last = LAST_V7.load(Ordering::Relaxed);
+        let stamp = loop {
+            let next = candidate.max(last + 1);
+            match LAST_V7.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => break next,
+                Err(current) => last = current,
+            }
+        };
+        Self::v7_from_parts(stamp >> 12, (stamp & 0xfff) as u16, rng.random())
+    }
+
+    fn v7_from_parts(millis: u64, counter: u16, random: u64) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = 0x70 | ((counter >> 8) as u8 & 0x0f);
+        bytes[7] = counter as u8;
+        bytes[8..].copy_from_slice(&random.to_be_bytes());
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Self(bytes)
+    }
+
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> [u8; 16] {
+        self.0
+    }
+
+    /// The version of the UUID, 4 or 7 for the generated ones
+    pub fn version(&self) -> u8 {
+        self.0[6] >> 4
+    }
+
+    /// The creation time in milliseconds since the Unix epoch, for version 7 UUIDs
+    pub fn timestamp_millis(&self) -> Option<u64> {
+        if self.version() != 7 {
+            return None;
+        }
+        let mut millis = [0u8; 8];
+        millis[2..].copy_from_slice(&self.0[..6]);
+        Some(u64::from_be_bytes(millis))
+    }
+
+    /// The 32 hex digits without hyphens
+    pub fn simple(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Parses the hyphenated or simple form, in any case
+    pub fn parse(input: &str) -> Option<Self> {
+        let hex: Vec<u8> = match input.len() {
+            36 => {
+                if [8, 13, 18, 23].iter().any(|&i| input.as_bytes()[i] != b'-') {
+                    return None;
+                }
+                input.bytes().filter(|&b| b != b'-').collect()
+            }
+            32 => input.bytes().collect(),
+            _ => return None,
+        };
+        if hex.len() != 32 || !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let mut bytes = [0u8; 16];
+        for (i, pair) in hex.chunks(2).enumerate() {
+            let pair = std::str::from_utf8(pair).ok()?;
+            bytes[i] = u8::from_str_radix(pair, 16).ok()?;
+        }
+        Some(Self(bytes))
+    }
+}
+
+impl std::fmt::Display for Uuid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let simple = self.simple();
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &simple[..8],
+            &simple[8..12],
+            &simple[12..16],
+            &simple[16..20],
+            &simple[20..]
+        )
+    }
+}
+
+impl std::str::FromStr for Uuid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| format!("Invalid UUID: {}", s))
+    }
+}
+
+impl From<Uuid> for String {
+    fn from(uuid: Uuid) -> Self {
+        uuid.to_string()
+    }
+}
+
+/// Creates a random (version 4) UUID in its hyphenated form
+pub fn uuid_v4() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Creates a time-ordered (version 7) UUID in its hyphenated form
+pub fn uuid_v7() -> String {
+    Uuid::new_v7().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v4_layout() {
+        let id = Uuid::new_v4();
+        assert_eq!(id.version(), 4);
+        assert_eq!(id.as_bytes()[8] >> 6, 0b10);
+        assert_ne!(id, Uuid::new_v4());
+        assert_eq!(id.timestamp_millis(), None);
+    }
+
+    #[test]
+    fn v7_is_ordered() {
+        let ids: Vec<Uuid> = (0..1000).map(|_| Uuid::new_v7()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids.iter().all(|id| id.version() == 7 && id.as_bytes()[8] >> 6 == 0b10));
+    }
+
+    #[test]
+    fn v7_timestamp() {
+        let id = Uuid::v7_from_parts(0x0123_4567_89ab, 0xcde, 0);
+        assert_eq!(id.timestamp_millis(), Some(0x0123_4567_89ab));
+        assert_eq!(id.to_string(), "01234567-89ab-7cde-8000-000000000000");
+    }
+
+    #[test]
+    fn parse_forms() {
+        let id = Uuid::new_v4();
+        assert_eq!(Uuid::parse(&id.to_string()), Some(id));
+        assert_eq!(Uuid::parse(&id.simple().to_uppercase()), Some(id));
+        assert_eq!(Uuid::parse("not-a-uuid"), None);
+        assert_eq!(Uuid::parse("0123456789ab-cdef-0123-4567-89abcdef"), None);
+    }
+}
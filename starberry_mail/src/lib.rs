@@ -0,0 +1,3 @@
+pub mod mail; 
+
+pub use mail::*;
\ No newline at end of file
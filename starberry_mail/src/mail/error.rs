@@ -0,0 +1,49 @@
+use std::error::Error as StdError;
+use std::fmt;
+use starberry_core::connection::error::ConnectionError;
+
+/// Represents errors that can occur while sending emails.
+#[derive(Debug, Clone)]
+pub enum MailError {
+    ConnectionError(String),
+    ProtocolError(String),
+    /// The server rejected a command, with its reply code and text
+    Rejected(u16, String),
+    AuthenticationError(String),
+    TemplateError(String),
+    /// The email is missing a sender, a recipient or a body
+    InvalidMessage(String),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::ConnectionError(msg) => write!(f, "Connection error: {}", msg),
+            MailError::ProtocolError(msg) => write!(f, "Protocol error: {}", msg),
+            MailError::Rejected(code, msg) => write!(f, "Rejected by the server: {} {}", code, msg),
+            MailError::AuthenticationError(msg) => write!(f, "Authentication error: {}", msg),
+            MailError::TemplateError(msg) => write!(f, "Template error: {}", msg),
+            MailError::InvalidMessage(msg) => write!(f, "Invalid message: {}", msg),
+        }
+    }
+}
+
+impl StdError for MailError {}
+
+impl From<std::io::Error> for MailError {
+    fn from(error: std::io::Error) -> Self {
+        MailError::ConnectionError(error.to_string())
+    }
+}
+
+impl From<ConnectionError> for MailError {
+    fn from(err: ConnectionError) -> Self {
+        match err {
+            ConnectionError::ProtocolError(msg) | ConnectionError::TlsError(msg) => {
+                MailError::ProtocolError(msg)
+            }
+            ConnectionError::AuthenticationFailed => MailError::AuthenticationError(err.to_string()),
+            _ => MailError::ConnectionError(err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use akari::{TemplateManager, Value};
+use tokio::sync::Mutex;
+
+use super::error::MailError;
+use super::message::{Email, Mailbox};
+use super::smtp::{SmtpClient, SmtpConfig};
+
+/// Sends emails through an SMTP server, reusing the session between emails.
+///
+/// Register it as a shared state of the application to use it from handlers.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let mailer = Mailer::new(SmtpConfig::new("smtp.example.com").credentials("user", "secret"))
+///     .default_from("Starberry <noreply@example.com>");
+/// let app = App::new().manage(mailer).build();
+///
+/// // In a handler
+/// let mailer = req.state::<Mailer>().unwrap();
+/// mailer
+///     .send_template(
+///         Email::new().to("alice@example.com").subject("Reset your password"),
+///         "reset.html",
+///         Some("reset.txt"),
+///         HashMap::from([("link".to_string(), Value::from(link))]),
+///     )
+///     .await?;
+/// ```
+pub struct Mailer {
+    config: SmtpConfig,
+    from: Option<Mailbox>,
+    templates: String,
+    session: Mutex<Option<SmtpClient>>,
+}
+
+impl Mailer {
+    pub fn new(config: SmtpConfig) -> Self {
+        Self {
+            config,
+            from: None,
+            templates: "templates".to_string(),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Sets the sender of the emails which do not have one.
+    pub fn default_from<M: Into<Mailbox>>(mut self, from: M) -> Self {
+        self.from = Some(from.into());
+        self
+    }
+
+    /// Sets the directory of the email templates, `templates` by default.
+    pub fn templates<T: Into<String>>(mut self, dir: T) -> Self {
+        self.templates = dir.into();
+        self
+    }
+
+    /// Renders an akari template of the template directory.
+    pub fn render(&self, template: &str, data: &HashMap<String, Value>) -> Result<String, MailError> {
+        TemplateManager::new(&self.templates)
+            .render(template, data)
+            .map_err(|e| MailError::TemplateError(e.to_string()))
+    }
+
+    /// Sends an email. The SMTP session is kept open and reused by the next emails,
+    /// a new one is opened if it was closed by the server in the meantime.
+    pub async fn send(&self, mut email: Email) -> Result<(), MailError> {
+        if email.from.is_none() {
+            email.from = self.from.clone();
+        }
+        email.validate()?;
+
+        let mut session = self.session.lock().await;
+        if let Some(client) = session.as_mut()
+            && client.noop().await.is_err()
+        {
+            *session = None;
+        }
+        if session.is_none() {
+            *session = Some(SmtpClient::connect(&self.config).await?);
+        }
+        let client = session.as_mut().expect("session opened above");
+        let result = client.send(&email).await;
+        if !client.is_open() {
+            *session = None;
+        }
+        result
+    }
+
+    /// Renders the HTML template, and the text template if given, into the body of the email and sends it.
+    pub async fn send_template(
+        &self,
+        email: Email,
+        html_template: &str,
+        text_template: Option<&str>,
+        data: HashMap<String, Value>,
+    ) -> Result<(), MailError> {
+        let mut email = email.html(self.render(html_template, &data)?);
+        if let Some(text_template) = text_template {
+            email = email.text(self.render(text_template, &data)?);
+        }
+        self.send(email).await
+    }
+
+    /// Closes the SMTP session, if one is open.
+    pub async fn close(&self) -> Result<(), MailError> {
+        if let Some(mut client) = self.session.lock().await.take() {
+            client.quit().await?;
+        }
+        Ok(())
+    }
+}
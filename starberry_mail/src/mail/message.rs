@@ -0,0 +1,331 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use starberry_lib::encoding::base64_encode;
+use starberry_lib::date::format_email_date;
+use starberry_lib::random_alphanumeric_string;
+
+use super::error::MailError;
+
+/// An email address with an optional display name, e.g. `Alice <alice@example.com>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mailbox {
+    pub name: Option<String>,
+    pub address: String,
+}
+
+impl Mailbox {
+    pub fn new<T: Into<String>>(address: T) -> Self {
+        Self {
+            name: None,
+            address: address.into(),
+        }
+    }
+
+    pub fn with_name<N: Into<String>, T: Into<String>>(name: N, address: T) -> Self {
+        Self {
+            name: Some(name.into()),
+            address: address.into(),
+        }
+    }
+
+    /// The domain part of the address, used to build the `Message-ID`
+    pub fn domain(&self) -> &str {
+        self.address.rsplit_once('@').map(|(_, domain)| domain).unwrap_or("localhost")
+    }
+}
+
+impl From<&str> for Mailbox {
+    /// Parses `Name <address>` or a bare address
+    fn from(s: &str) -> Self {
+        let s = s.trim();
+        match (s.rfind('<'), s.ends_with('>')) {
+            (Some(start), true) => {
+                let name = s[..start].trim().trim_matches('"').trim();
+                Self {
+                    name: (!name.is_empty()).then(|| name.to_string()),
+                    address: s[start + 1..s.len() - 1].trim().to_string(),
+                }
+            }
+            _ => Self::new(s),
+        }
+    }
+}
+
+impl From<String> for Mailbox {
+    fn from(s: String) -> Self {
+        Mailbox::from(s.as_str())
+    }
+}
+
+impl fmt::Display for Mailbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} <{}>", encode_phrase(name), self.address),
+            None => write!(f, "<{}>", self.address),
+        }
+    }
+}
+
+/// Quotes a display name, or encodes it as a MIME encoded-word if it is not ASCII
+fn encode_phrase(s: &str) -> String {
+    if s.is_ascii() {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        encode_word(s)
+    }
+}
+
+/// Encodes a header value as a MIME encoded-word if it is not ASCII
+fn encode_header(s: &str) -> String {
+    if s.is_ascii() {
+        s.to_string()
+    } else {
+        encode_word(s)
+    }
+}
+
+fn encode_word(s: &str) -> String {
+    format!("=?UTF-8?B?{}?=", base64_encode(s))
+}
+
+/// Base64 encodes a body, wrapping lines at 76 characters
+fn encode_body(body: &str) -> String {
+    let encoded = base64_encode(body);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for chunk in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// An email with a plain text and/or an HTML body.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_mail::Email;
+///
+/// let email = Email::new()
+///     .from("Starberry <noreply@example.com>")
+///     .to("alice@example.com")
+///     .subject("Welcome")
+///     .text("Hello Alice");
+/// assert_eq!(email.recipients(), vec!["alice@example.com"]);
+/// assert!(email.format().unwrap().contains("Subject: Welcome\r\n"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Email {
+    pub from: Option<Mailbox>,
+    pub to: Vec<Mailbox>,
+    pub cc: Vec<Mailbox>,
+    pub bcc: Vec<Mailbox>,
+    pub reply_to: Option<Mailbox>,
+    pub subject: String,
+    pub text: Option<String>,
+    pub html: Option<String>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Email {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from<M: Into<Mailbox>>(mut self, from: M) -> Self {
+        self.from = Some(from.into());
+        self
+    }
+
+    pub fn to<M: Into<Mailbox>>(mut self, to: M) -> Self {
+        self.to.push(to.into());
+        self
+    }
+
+    pub fn cc<M: Into<Mailbox>>(mut self, cc: M) -> Self {
+        self.cc.push(cc.into());
+        self
+    }
+
+    /// Adds a recipient which does not appear in the headers
+    pub fn bcc<M: Into<Mailbox>>(mut self, bcc: M) -> Self {
+        self.bcc.push(bcc.into());
+        self
+    }
+
+    pub fn reply_to<M: Into<Mailbox>>(mut self, reply_to: M) -> Self {
+        self.reply_to = Some(reply_to.into());
+        self
+    }
+
+    pub fn subject<T: Into<String>>(mut self, subject: T) -> Self {
+        self.subject = subject.into();
+        self
+    }
+
+    /// Sets the plain text body
+    pub fn text<T: Into<String>>(mut self, text: T) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Sets the HTML body. If a text body is set as well, both are sent as alternatives
+    pub fn html<T: Into<String>>(mut self, html: T) -> Self {
+        self.html = Some(html.into());
+        self
+    }
+
+    /// Adds a custom header
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns the address of every recipient, including Cc and Bcc
+    pub fn recipients(&self) -> Vec<&str> {
+        self.to
+            .iter()
+            .chain(&self.cc)
+            .chain(&self.bcc)
+            .map(|mailbox| mailbox.address.as_str())
+            .collect()
+    }
+
+    /// Checks that the email has a sender, a recipient and a body
+    pub fn validate(&self) -> Result<(), MailError> {
+        if self.from.is_none() {
+            return Err(MailError::InvalidMessage("no sender".to_string()));
+        }
+        if self.recipients().is_empty() {
+            return Err(MailError::InvalidMessage("no recipient".to_string()));
+        }
+        if self.text.is_none() && self.html.is_none() {
+            return Err(MailError::InvalidMessage("no body".to_string()));
+        }
+        let header_injection = |s: &str| s.contains('\r') || s.contains('\n');
+        let mut mailboxes = self.from.iter().chain(&self.reply_to).chain(&self.to).chain(&self.cc).chain(&self.bcc);
+        if header_injection(&self.subject)
+            || mailboxes.any(|m| header_injection(&m.address) || m.name.as_deref().is_some_and(header_injection))
+            || self.headers.iter().any(|(k, v)| header_injection(k) || header_injection(v))
+        {
+            return Err(MailError::InvalidMessage("line break in a header".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Formats the email as an RFC 5322 message with CRLF line endings
+    pub fn format(&self) -> Result<String, MailError> {
+        self.validate()?;
+        let from = self.from.as_ref().expect("validated");
+        let join = |list: &[Mailbox]| list.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ");
+
+        let mut out = String::new();
+        out.push_str(&format!("Date: {}\r\n", format_email_date(SystemTime::now())));
+        out.push_str(&format!("From: {}\r\n", from));
+        if !self.to.is_empty() {
+            out.push_str(&format!("To: {}\r\n", join(&self.to)));
+        }
+        if !self.cc.is_empty() {
+            out.push_str(&format!("Cc: {}\r\n", join(&self.cc)));
+        }
+        if let Some(reply_to) = &self.reply_to {
+            out.push_str(&format!("Reply-To: {}\r\n", reply_to));
+        }
+        out.push_str(&format!("Subject: {}\r\n", encode_header(&self.subject)));
+        out.push_str(&format!(
+            "Message-ID: <{}@{}>\r\n",
+            random_alphanumeric_string(24),
+            from.domain()
+        ));
+        for (key, value) in &self.headers {
+            out.push_str(&format!("{}: {}\r\n", key, encode_header(value)));
+        }
+        out.push_str("MIME-Version: 1.0\r\n");
+
+        let part = |content_type: &str, body: &str| {
+            format!(
+                "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+                content_type,
+                encode_body(body)
+            )
+        };
+        match (&self.text, &self.html) {
+            (Some(text), Some(html)) => {
+                let boundary = format!("starberry-{}", random_alphanumeric_string(24));
+                out.push_str(&format!(
+                    "Content-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n",
+                    boundary
+                ));
+                out.push_str(&format!("--{}\r\n{}", boundary, part("text/plain", text)));
+                out.push_str(&format!("--{}\r\n{}", boundary, part("text/html", html)));
+                out.push_str(&format!("--{}--\r\n", boundary));
+            }
+            (Some(text), None) => out.push_str(&part("text/plain", text)),
+            (None, Some(html)) => out.push_str(&part("text/html", html)),
+            (None, None) => unreachable!("validated"),
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mailboxes() {
+        assert_eq!(
+            Mailbox::from("\"Alice A\" <alice@example.com>"),
+            Mailbox::with_name("Alice A", "alice@example.com")
+        );
+        assert_eq!(Mailbox::from(" bob@example.com "), Mailbox::new("bob@example.com"));
+        assert_eq!(Mailbox::from("<bob@example.com>"), Mailbox::new("bob@example.com"));
+        assert_eq!(Mailbox::new("bob@example.com").domain(), "example.com");
+    }
+
+    #[test]
+    fn encodes_non_ascii_headers() {
+        let mailbox = Mailbox::with_name("Zoë", "zoe@example.com");
+        assert_eq!(mailbox.to_string(), "=?UTF-8?B?Wm/Dqw==?= <zoe@example.com>");
+        assert_eq!(encode_header("ok"), "ok");
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_email_date(std::time::UNIX_EPOCH), "Thu, 1 Jan 1970 00:00:00 +0000");
+        // 2000-02-29 12:34:56
+        let leap = std::time::UNIX_EPOCH + std::time::Duration::from_secs(951_827_696);
+        assert_eq!(format_email_date(leap), "Tue, 29 Feb 2000 12:34:56 +0000");
+    }
+
+    #[test]
+    fn builds_alternative_bodies() {
+        let email = Email::new()
+            .from("a@example.com")
+            .to("b@example.com")
+            .bcc("c@example.com")
+            .subject("Hi")
+            .text("plain")
+            .html("<b>rich</b>");
+        let formatted = email.format().unwrap();
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("text/plain"));
+        assert!(formatted.contains("text/html"));
+        assert!(!formatted.contains("c@example.com"));
+        assert_eq!(email.recipients(), vec!["b@example.com", "c@example.com"]);
+    }
+
+    #[test]
+    fn rejects_incomplete_or_injected_messages() {
+        assert!(Email::new().to("b@example.com").text("x").format().is_err());
+        assert!(Email::new().from("a@example.com").text("x").format().is_err());
+        assert!(Email::new().from("a@example.com").to("b@example.com").format().is_err());
+        let injected = Email::new()
+            .from("a@example.com")
+            .to("b@example.com")
+            .subject("Hi\r\nBcc: evil@example.com")
+            .text("x");
+        assert!(injected.format().is_err());
+    }
+}
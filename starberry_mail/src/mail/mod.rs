@@ -0,0 +1,9 @@
+pub mod error;
+pub mod message;
+pub mod smtp;
+pub mod mailer;
+
+pub use error::MailError;
+pub use message::{Email, Mailbox};
+pub use smtp::{SmtpClient, SmtpConfig, SmtpSecurity, SmtpReply};
+pub use mailer::Mailer;
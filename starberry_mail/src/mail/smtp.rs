@@ -0,0 +1,322 @@
+use std::time::Duration;
+
+use starberry_lib::encoding::base64_encode;
+use starberry_core::connection::{tls_handshake, Connection, ConnectionBuilder, Protocol};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
+
+use super::error::MailError;
+use super::message::Email;
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Plain text, only for local relays
+    None,
+    /// Plain connection upgraded with `STARTTLS`, usually on port 587
+    StartTls,
+    /// TLS from the start, usually on port 465
+    Tls,
+}
+
+/// Represents an SMTP server configuration.
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    host: String,
+    port: u16,
+    security: SmtpSecurity,
+    username: Option<String>,
+    password: Option<String>,
+    hello_name: String,
+    timeout: Duration,
+}
+
+impl SmtpConfig {
+    /// Creates a configuration for the given server, using `STARTTLS` on port 587.
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            port: 587,
+            security: SmtpSecurity::StartTls,
+            username: None,
+            password: None,
+            hello_name: "localhost".to_string(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the port of the server.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Sets how the connection is secured.
+    pub fn security(mut self, security: SmtpSecurity) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Sets the username and password used to authenticate.
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.username = Some(username.to_string());
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Sets the name sent with `EHLO`, usually the domain of the application.
+    pub fn hello_name(mut self, name: &str) -> Self {
+        self.hello_name = name.to_string();
+        self
+    }
+
+    /// Sets the maximum time to wait for each reply of the server.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// A reply of the server: a three digit code and one or more lines of text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtpReply {
+    pub code: u16,
+    pub lines: Vec<String>,
+}
+
+impl SmtpReply {
+    pub fn is_positive(&self) -> bool {
+        (200..400).contains(&self.code)
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join(" ")
+    }
+}
+
+/// Escapes the lines starting with a dot and terminates the data with `<CRLF>.<CRLF>`
+fn dot_stuff(message: &str) -> String {
+    let mut out = String::with_capacity(message.len() + 8);
+    for line in message.split("\r\n") {
+        if line.starts_with('.') {
+            out.push('.');
+        }
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    // The message already ends with CRLF, which produced an empty last line
+    if message.ends_with("\r\n") {
+        out.truncate(out.len() - 2);
+    }
+    out.push_str(".\r\n");
+    out
+}
+
+/// An open SMTP session.
+pub struct SmtpClient {
+    stream: Option<BufStream<Connection>>,
+    extensions: Vec<String>,
+    timeout: Duration,
+}
+
+impl SmtpClient {
+    /// Connects to the server, secures the connection and authenticates.
+    pub async fn connect(config: &SmtpConfig) -> Result<Self, MailError> {
+        let stream = ConnectionBuilder::new(&config.host, config.port)
+            .protocol(Protocol::SMTP)
+            .tls(config.security == SmtpSecurity::Tls)
+            .max_connection_time(config.timeout)
+            .retry_attempts(0)
+            .connect()
+            .await?;
+        let mut client = Self {
+            stream: Some(BufStream::new(stream)),
+            extensions: Vec::new(),
+            timeout: config.timeout,
+        };
+        let greeting = client.read_reply().await?;
+        client.expect(greeting, &[220])?;
+        client.ehlo(&config.hello_name).await?;
+
+        if config.security == SmtpSecurity::StartTls {
+            if !client.supports("STARTTLS") {
+                return Err(MailError::ProtocolError("the server does not support STARTTLS".to_string()));
+            }
+            let reply = client.command("STARTTLS").await?;
+            client.expect(reply, &[220])?;
+            let plain = client.stream.take().map(|stream| stream.into_inner());
+            let tcp = match plain {
+                Some(Connection::Tcp(tcp)) => tcp,
+                _ => return Err(MailError::ProtocolError("connection is already secured".to_string())),
+            };
+            client.stream = Some(BufStream::new(tls_handshake(&config.host, tcp).await?));
+            // The capabilities must be queried again over the secured connection
+            client.ehlo(&config.hello_name).await?;
+        }
+
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            client.login(username, password).await?;
+        }
+        Ok(client)
+    }
+
+    fn stream(&mut self) -> Result<&mut BufStream<Connection>, MailError> {
+        self.stream
+            .as_mut()
+            .ok_or_else(|| MailError::ConnectionError("connection is closed".to_string()))
+    }
+
+    /// Whether the server announced the extension in its `EHLO` reply
+    pub fn supports(&self, extension: &str) -> bool {
+        self.extensions
+            .iter()
+            .any(|line| line.split_whitespace().next().is_some_and(|name| name.eq_ignore_ascii_case(extension)))
+    }
+
+    async fn read_reply(&mut self) -> Result<SmtpReply, MailError> {
+        let timeout = self.timeout;
+        let stream = self.stream()?;
+        let read = async {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await? == 0 {
+                    return Err(MailError::ConnectionError("connection closed by the server".to_string()));
+                }
+                let line = line.trim_end_matches(['\r', '\n']);
+                let code = line
+                    .get(..3)
+                    .and_then(|code| code.parse::<u16>().ok())
+                    .ok_or_else(|| MailError::ProtocolError(format!("invalid reply {:?}", line)))?;
+                lines.push(line.get(4..).unwrap_or_default().to_string());
+                // "250-..." continues, "250 ..." is the last line
+                if line.as_bytes().get(3) != Some(&b'-') {
+                    return Ok(SmtpReply { code, lines });
+                }
+            }
+        };
+        let result = tokio::time::timeout(timeout, read)
+            .await
+            .unwrap_or_else(|_| Err(MailError::ConnectionError("no reply from the server".to_string())));
+        if result.is_err() {
+            self.stream = None;
+        }
+        result
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), MailError> {
+        let stream = self.stream()?;
+        let written = async {
+            stream.write_all(data).await?;
+            stream.flush().await
+        }
+        .await;
+        if let Err(e) = written {
+            self.stream = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Sends a command line and returns the reply of the server
+    pub async fn command(&mut self, line: &str) -> Result<SmtpReply, MailError> {
+        if line.contains('\r') || line.contains('\n') {
+            return Err(MailError::InvalidMessage(format!("line break in command {:?}", line)));
+        }
+        self.write(format!("{}\r\n", line).as_bytes()).await?;
+        self.read_reply().await
+    }
+
+    fn expect(&self, reply: SmtpReply, codes: &[u16]) -> Result<SmtpReply, MailError> {
+        if codes.contains(&reply.code) {
+            Ok(reply)
+        } else {
+            Err(MailError::Rejected(reply.code, reply.text()))
+        }
+    }
+
+    async fn ehlo(&mut self, name: &str) -> Result<(), MailError> {
+        let reply = self.command(&format!("EHLO {}", name)).await?;
+        let reply = self.expect(reply, &[250])?;
+        // The first line is the greeting, the following ones the extensions
+        self.extensions = reply.lines.into_iter().skip(1).collect();
+        Ok(())
+    }
+
+    async fn login(&mut self, username: &str, password: &str) -> Result<(), MailError> {
+        let auth = self
+            .extensions
+            .iter()
+            .find(|line| line.to_ascii_uppercase().starts_with("AUTH"))
+            .map(|line| line.to_ascii_uppercase())
+            .unwrap_or_default();
+        let reply = if auth.contains("PLAIN") || !auth.contains("LOGIN") {
+            let token = base64_encode(format!("\0{}\0{}", username, password));
+            self.command(&format!("AUTH PLAIN {}", token)).await?
+        } else {
+            let reply = self.command("AUTH LOGIN").await?;
+            self.expect(reply, &[334])?;
+            let reply = self.command(&base64_encode(username)).await?;
+            self.expect(reply, &[334])?;
+            self.command(&base64_encode(password)).await?
+        };
+        if reply.code != 235 {
+            return Err(MailError::AuthenticationError(format!("{} {}", reply.code, reply.text())));
+        }
+        Ok(())
+    }
+
+    /// Sends an email. The session can be reused for further emails afterwards
+    pub async fn send(&mut self, email: &Email) -> Result<(), MailError> {
+        let message = email.format()?;
+        let from = email.from.as_ref().map(|from| from.address.clone()).unwrap_or_default();
+        let reply = self.command(&format!("MAIL FROM:<{}>", from)).await?;
+        self.expect(reply, &[250])?;
+        for recipient in email.recipients() {
+            let reply = self.command(&format!("RCPT TO:<{}>", recipient)).await?;
+            if let Err(e) = self.expect(reply, &[250, 251]) {
+                // Leave the session clean for the next email
+                let _ = self.command("RSET").await;
+                return Err(e);
+            }
+        }
+        let reply = self.command("DATA").await?;
+        self.expect(reply, &[354])?;
+        self.write(dot_stuff(&message).as_bytes()).await?;
+        let reply = self.read_reply().await?;
+        self.expect(reply, &[250])?;
+        Ok(())
+    }
+
+    /// Checks that the session is still usable, e.g. before reusing it
+    pub async fn noop(&mut self) -> Result<(), MailError> {
+        let reply = self.command("NOOP").await?;
+        self.expect(reply, &[250]).map(|_| ())
+    }
+
+    /// Whether the connection is still open
+    pub fn is_open(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Ends the session.
+    pub async fn quit(&mut self) -> Result<(), MailError> {
+        if self.stream.is_some() {
+            let _ = self.command("QUIT").await;
+        }
+        if let Some(mut stream) = self.stream.take() {
+            stream.get_mut().shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stuffs_leading_dots() {
+        assert_eq!(dot_stuff("a\r\n.b\r\n"), "a\r\n..b\r\n.\r\n");
+        assert_eq!(dot_stuff("a"), "a\r\n.\r\n");
+    }
+}
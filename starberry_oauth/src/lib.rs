@@ -7,6 +7,13 @@ pub mod openid;
 pub mod social;
 
 pub use oauth_core::middleware::OAuthLayer;
+pub use oauth_core::issuers::{IssuerRegistry, Tenant};
+pub use oauth_core::dpop::{DpopKey, DpopProof, DpopValidator};
+pub use oauth_core::par::PushedRequests;
+pub use oauth_core::rate_limiter::{CacheRateLimiter, RateLimiter};
+pub use oauth_core::jar::RequestObjectVerifier;
+pub use oauth_core::consent::{ConsentGrant, ConsentPage, DefaultConsentPage, GrantRecord, ScopeDescriptions, TemplateConsentPage};
+pub use oauth_core::db::DBAuthorizer;
 pub use oauth_core::memory::{InMemoryClientStore, InMemoryTokenManager, InMemoryAuthorizer, InMemoryTokenStorage};
 pub use oauth_core::oauth_client::OAuthClient;
 pub use oauth_core::http_client::{OAuthHttpClient, HttpRequest, HttpResponse, RedirectPolicy, HttpClientError, InMemoryHttpClient};
@@ -0,0 +1,248 @@
+//! The consent screen of the authorization endpoint and the grants remembered for the users.
+//!
+//! `OAuthLayer` asks the user to approve the scopes requested by a client with a `ConsentPage`,
+//! `DefaultConsentPage` or an akari template with `TemplateConsentPage`, describing each scope
+//! with `ScopeDescriptions`. A logged in user may ask to remember the decision: the grant is
+//! then recorded by the `Authorizer`, and later requests of the client for the same scopes skip
+//! the screen. The grants of a user are listed and revoked with `Authorizer::list_grants` and
+//! `Authorizer::revoke_grant`, e.g. from the security page of their account.
+
+use std::collections::HashMap;
+
+use starberry_core::Value;
+use starberry_core::http::response::HttpResponse;
+use starberry_core::http::response::response_templates::{html_response, template_response};
+
+/// The descriptions of the scopes of OpenID Connect
+pub const DEFAULT_SCOPE_DESCRIPTIONS: [(&str, &str); 6] = [
+    ("openid", "Sign you in with your account"),
+    ("profile", "See your name and profile picture"),
+    ("email", "See your email address"),
+    ("address", "See your postal address"),
+    ("phone", "See your phone number"),
+    ("offline_access", "Keep access to your data while you are not using the application"),
+];
+
+/// The text shown to the user for each scope
+#[derive(Debug, Clone)]
+pub struct ScopeDescriptions {
+    descriptions: HashMap<String, String>,
+}
+
+impl ScopeDescriptions {
+    /// The descriptions of the scopes of OpenID Connect
+    pub fn new() -> Self {
+        Self {
+            descriptions: DEFAULT_SCOPE_DESCRIPTIONS
+                .iter()
+                .map(|(scope, description)| (scope.to_string(), description.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Sets the description of a scope
+    pub fn describe(mut self, scope: impl Into<String>, description: impl Into<String>) -> Self {
+        self.descriptions.insert(scope.into(), description.into());
+        self
+    }
+
+    /// The description of a scope, or its name if it has none
+    pub fn get(&self, scope: &str) -> String {
+        self.descriptions.get(scope).cloned().unwrap_or_else(|| scope.to_string())
+    }
+}
+
+impl Default for ScopeDescriptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The consent of a user to a client, remembered for its next authorization requests
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GrantRecord {
+    pub client_id: String,
+    pub user_id: String,
+    /// Every scope granted to the client, accumulated over the consents
+    pub scopes: Vec<String>,
+    /// When the consent was last given, in seconds since the epoch
+    pub granted_at: i64,
+}
+
+impl GrantRecord {
+    /// Whether the record grants all the scopes
+    pub fn covers(&self, scopes: &[String]) -> bool {
+        scopes.iter().all(|scope| self.scopes.contains(scope))
+    }
+}
+
+/// A scope shown on the consent screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeItem {
+    pub name: String,
+    pub description: String,
+}
+
+/// What the consent screen asks the user
+#[derive(Debug, Clone)]
+pub struct ConsentRequest {
+    pub client_id: String,
+    /// The logged in user, who may ask to remember the decision
+    pub user_id: Option<String>,
+    pub scopes: Vec<ScopeItem>,
+    /// Where the form posts the decision, the authorization endpoint
+    pub action: String,
+    /// The hidden fields of the form: the CSRF token and the parameters of the request
+    pub fields: Vec<(String, String)>,
+}
+
+/// The decision of the user, stored in `req.params` for the handler of the authorization
+/// endpoint to issue the authorization code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsentGrant {
+    pub client_id: String,
+    pub user_id: Option<String>,
+    pub scopes: Vec<String>,
+    pub redirect_uri: String,
+    pub state: Option<String>,
+    /// Whether the consent was remembered from an earlier request, without showing the screen
+    pub remembered: bool,
+}
+
+/// Renders the consent screen. The form must post the hidden fields to the action, with
+/// `action` set to `approve` or `deny` and, to remember the decision, `remember` set to `1`
+pub trait ConsentPage: Send + Sync + 'static {
+    fn render(&self, request: &ConsentRequest) -> HttpResponse;
+}
+
+/// A plain consent screen, listing the descriptions of the scopes
+#[derive(Debug, Clone, Default)]
+pub struct DefaultConsentPage;
+
+impl ConsentPage for DefaultConsentPage {
+    fn render(&self, request: &ConsentRequest) -> HttpResponse {
+        let fields: String = request
+            .fields
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "    <input type=\"hidden\" name=\"{}\" value=\"{}\" />\n",
+                    escape_html(name),
+                    escape_html(value)
+                )
+            })
+            .collect();
+        let scopes: String = request
+            .scopes
+            .iter()
+            .map(|scope| format!("    <li>{}</li>\n", escape_html(&scope.description)))
+            .collect();
+        let remember = if request.user_id.is_some() {
+            "    <label><input type=\"checkbox\" name=\"remember\" value=\"1\" /> Remember my decision</label>\n"
+        } else {
+            ""
+        };
+        html_response(format!(
+            r#"<!DOCTYPE html>
+<html><body>
+<h1>Authorize access for client {client}</h1>
+<form method="POST" action="{action}">
+{fields}    <p>The application will be able to:</p>
+    <ul>
+{scopes}    </ul>
+{remember}    <button type="submit" name="action" value="approve">Approve</button>
+    <button type="submit" name="action" value="deny">Deny</button>
+</form>
+</body></html>"#,
+            client = escape_html(&request.client_id),
+            action = escape_html(&request.action),
+        ))
+    }
+}
+
+/// A consent screen rendered from an akari template of the `templates` directory, given
+/// `client_id`, `user_id` (empty if anonymous), `action`, `scopes` with the `name` and
+/// `description` of each scope, and `fields` with the `name` and `value` of each hidden field
+#[derive(Debug, Clone)]
+pub struct TemplateConsentPage {
+    file: String,
+}
+
+impl TemplateConsentPage {
+    pub fn new(file: impl Into<String>) -> Self {
+        Self { file: file.into() }
+    }
+
+    /// The values given to the template
+    pub fn data(request: &ConsentRequest) -> HashMap<String, Value> {
+        let dict = |pairs: [(&str, &str); 2]| {
+            Value::Dict(pairs.iter().map(|(key, value)| (key.to_string(), Value::Str(value.to_string()))).collect())
+        };
+        let mut data = HashMap::new();
+        data.insert("client_id".to_string(), Value::Str(request.client_id.clone()));
+        data.insert("user_id".to_string(), Value::Str(request.user_id.clone().unwrap_or_default()));
+        data.insert("action".to_string(), Value::Str(request.action.clone()));
+        data.insert(
+            "scopes".to_string(),
+            Value::List(request.scopes.iter().map(|scope| dict([("name", scope.name.as_str()), ("description", scope.description.as_str())])).collect()),
+        );
+        data.insert(
+            "fields".to_string(),
+            Value::List(request.fields.iter().map(|(name, value)| dict([("name", name.as_str()), ("value", value.as_str())])).collect()),
+        );
+        data
+    }
+}
+
+impl ConsentPage for TemplateConsentPage {
+    fn render(&self, request: &ConsentRequest) -> HttpResponse {
+        template_response(&self.file, Self::data(request))
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starberry_core::http::body::HttpBody;
+
+    #[test]
+    fn test_scope_descriptions() {
+        let descriptions = ScopeDescriptions::new().describe("orders:read", "See your orders");
+        assert_eq!(descriptions.get("email"), "See your email address");
+        assert_eq!(descriptions.get("orders:read"), "See your orders");
+        assert_eq!(descriptions.get("unknown"), "unknown");
+    }
+
+    #[test]
+    fn test_default_page_escapes_the_request() {
+        let request = ConsentRequest {
+            client_id: "<script>".to_string(),
+            user_id: None,
+            scopes: vec![ScopeItem { name: "email".to_string(), description: "See your email address".to_string() }],
+            action: "/oauth/authorize".to_string(),
+            fields: vec![("state".to_string(), "\"><img>".to_string())],
+        };
+        let body = match DefaultConsentPage.render(&request).body {
+            HttpBody::Binary(bytes) => String::from_utf8(bytes).unwrap(),
+            _ => panic!("Expected a binary body"),
+        };
+        assert!(body.contains("&lt;script&gt;"));
+        assert!(body.contains("value=\"&quot;&gt;&lt;img&gt;\""));
+        assert!(!body.contains("name=\"remember\""));
+    }
+}
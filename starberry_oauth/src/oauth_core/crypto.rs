@@ -1,15 +1,14 @@
 //! Cryptographic utilities for OAuth (PKCE, HMAC, AES-GCM, RSA) using `ring`.
 
 use ring::{digest, hmac, aead, signature, error::Unspecified, constant_time::verify_slices_are_equal};
-use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use base64::Engine;
+use starberry_lib::encoding::base64_url_encode;
 
 /// Generate a PKCE code challenge from the given verifier using SHA-256 and base64url (no padding).
 pub fn pkce_code_challenge(verifier: &str) -> String {
     // SHA-256 hash of the verifier
     let hash = digest::digest(&digest::SHA256, verifier.as_bytes());
     // Base64URL encode without padding
-    URL_SAFE_NO_PAD.encode(hash.as_ref())
+    base64_url_encode(hash.as_ref())
 }
 
 /// Create an HMAC tag for the given data using the provided secret key.
@@ -1,10 +1,11 @@
-//! Database-backed TokenManager for opaque tokens.
+//! Database-backed TokenManager for opaque tokens and Authorizer for remembered consents.
 
 use std::{pin::Pin, future::Future};
 use uuid::Uuid;
 use chrono::Utc;
 use super::types::{TokenModel, Token, OAuthError, Grant};
-use super::oauth_provider::TokenManager;
+use super::oauth_provider::{Authorizer, TokenManager};
+use super::consent::GrantRecord;
 use starberry_sql::sql::builder::SqlQuery;
 use starberry_sql::sql::pool::SqlPool;
 use async_trait::async_trait;
@@ -92,4 +93,87 @@ impl TokenManager for DBTokenManager {
             id_token: None,
         })
     }
-} 
\ No newline at end of file
+}
+
+/// An Authorizer remembering the consents in the `oauth_grants` table, with the columns
+/// `client_id`, `user_id`, `scopes` (space separated) and `granted_at` (seconds since the epoch).
+pub struct DBAuthorizer {
+    pool: SqlPool,
+}
+
+impl DBAuthorizer {
+    /// Create a new DBAuthorizer with a connection pool.
+    pub fn new(pool: SqlPool) -> Self {
+        Self { pool }
+    }
+
+    async fn get_grant(&self, client_id: &str, user_id: &str) -> Result<Option<GrantRecord>, OAuthError> {
+        let sql = "SELECT client_id, user_id, scopes, granted_at FROM oauth_grants WHERE client_id = $1 AND user_id = $2";
+        let rows = SqlQuery::new(sql)
+            .bind(client_id.to_owned())
+            .bind(user_id.to_owned())
+            .fetch_all_pool(&self.pool)
+            .await
+            .map_err(|_| OAuthError::ServerError)?;
+        Ok(rows.first().and_then(grant_from_row))
+    }
+}
+
+fn grant_from_row(row: &std::collections::HashMap<String, String>) -> Option<GrantRecord> {
+    Some(GrantRecord {
+        client_id: row.get("client_id")?.clone(),
+        user_id: row.get("user_id")?.clone(),
+        scopes: row.get("scopes")?.split_whitespace().map(str::to_string).collect(),
+        granted_at: row.get("granted_at")?.parse().ok()?,
+    })
+}
+
+#[async_trait]
+impl Authorizer for DBAuthorizer {
+    async fn record_consent(&self, client_id: &str, user_id: &str, scopes: &[String]) -> Result<(), OAuthError> {
+        let mut granted = self.get_grant(client_id, user_id).await?
+            .map(|grant| grant.scopes)
+            .unwrap_or_default();
+        for scope in scopes {
+            if !granted.contains(scope) {
+                granted.push(scope.clone());
+            }
+        }
+        self.revoke_grant(client_id, user_id).await?;
+        let sql = "INSERT INTO oauth_grants (client_id, user_id, scopes, granted_at) VALUES ($1, $2, $3, $4)";
+        SqlQuery::new(sql)
+            .bind(client_id.to_owned())
+            .bind(user_id.to_owned())
+            .bind(granted.join(" "))
+            .bind(Utc::now().timestamp())
+            .execute_pool(&self.pool)
+            .await
+            .map_err(|_| OAuthError::ServerError)?;
+        Ok(())
+    }
+
+    async fn check_consent(&self, client_id: &str, user_id: &str, scopes: &[String]) -> Result<bool, OAuthError> {
+        Ok(self.get_grant(client_id, user_id).await?.is_some_and(|grant| grant.covers(scopes)))
+    }
+
+    async fn list_grants(&self, user_id: &str) -> Result<Vec<GrantRecord>, OAuthError> {
+        let sql = "SELECT client_id, user_id, scopes, granted_at FROM oauth_grants WHERE user_id = $1 ORDER BY client_id";
+        let rows = SqlQuery::new(sql)
+            .bind(user_id.to_owned())
+            .fetch_all_pool(&self.pool)
+            .await
+            .map_err(|_| OAuthError::ServerError)?;
+        Ok(rows.iter().filter_map(grant_from_row).collect())
+    }
+
+    async fn revoke_grant(&self, client_id: &str, user_id: &str) -> Result<bool, OAuthError> {
+        let sql = "DELETE FROM oauth_grants WHERE client_id = $1 AND user_id = $2";
+        let deleted = SqlQuery::new(sql)
+            .bind(client_id.to_owned())
+            .bind(user_id.to_owned())
+            .execute_pool(&self.pool)
+            .await
+            .map_err(|_| OAuthError::ServerError)?;
+        Ok(deleted > 0)
+    }
+}
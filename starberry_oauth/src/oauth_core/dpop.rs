@@ -0,0 +1,344 @@
+//! DPoP, the proof of possession of RFC 9449.
+//!
+//! The client signs each request with a key of its own, sending the proof in the `DPoP` header.
+//! The token endpoint binds the tokens it issues to the key, by its JWK thumbprint, and the
+//! resource server only accepts a bound token with a proof signed by the same key, so a leaked
+//! token cannot be used by someone else.
+//!
+//! `OAuthLayer::dpop` verifies the proofs sent to the token endpoint and stores them in
+//! `req.params` as `DpopProof`, for the handler issuing the tokens to bind them with
+//! `DpopValidator::bind` or `JWTTokenManager::generate_dpop_token`. The proofs sent to the
+//! protected routes are verified against the binding of the token.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::Utc;
+use dashmap::DashMap;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde_json::{json, Value};
+use starberry_core::http::context::HttpReqCtx;
+use starberry_lib::encoding::{base64_url_decode, base64_url_encode};
+use uuid::Uuid;
+
+use super::types::{OAuthError, Token, TokenModel};
+
+/// The algorithms accepted for the proofs, the asymmetric ones
+const ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::EdDSA,
+];
+
+/// Beyond this many remembered proofs, the expired ones are forgotten
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// A verified DPoP proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DpopProof {
+    /// The JWK thumbprint of the key of the client
+    pub jkt: String,
+    /// The unique identifier of the proof
+    pub jti: String,
+    /// When the proof was created, in seconds since the epoch
+    pub iat: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct ProofClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: u64,
+    #[serde(default)]
+    ath: Option<String>,
+}
+
+/// Verifies the DPoP proofs and keeps the bindings of the opaque tokens
+#[derive(Clone)]
+pub struct DpopValidator {
+    max_age: u64,
+    origin: Option<String>,
+    /// The proofs already used, by thumbprint and `jti`, with their `iat`
+    seen: Arc<DashMap<String, u64>>,
+    /// The thumbprint each opaque token is bound to, with its expiry
+    bindings: Arc<DashMap<String, (String, u64)>>,
+}
+
+impl DpopValidator {
+    /// Accepts the proofs created in the last 5 minutes
+    pub fn new() -> Self {
+        Self {
+            max_age: 300,
+            origin: None,
+            seen: Arc::new(DashMap::new()),
+            bindings: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Sets how old, in seconds, a proof may be
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = seconds;
+        self
+    }
+
+    /// Sets the public origin of the server, e.g. `https://api.example.com`, the proofs are
+    /// made for. By default it is read from the `Host` header and the TLS of the connection,
+    /// which is wrong behind a proxy terminating TLS
+    pub fn origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Verifies a proof of a request with the method and URL. `access_token` is the token sent
+    /// with the proof to a protected route, whose hash must be the `ath` claim
+    pub fn verify(&self, proof: &str, method: &str, url: &str, access_token: Option<&str>) -> Result<DpopProof, OAuthError> {
+        let header = decode_header(proof).map_err(|_| OAuthError::InvalidDpopProof)?;
+        if !header.typ.as_deref().is_some_and(|typ| typ.eq_ignore_ascii_case("dpop+jwt")) || !ALGORITHMS.contains(&header.alg) {
+            return Err(OAuthError::InvalidDpopProof);
+        }
+        let jwk = header.jwk.ok_or(OAuthError::InvalidDpopProof)?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| OAuthError::InvalidDpopProof)?;
+        let jkt = serde_json::to_value(&jwk)
+            .ok()
+            .and_then(|jwk| jwk_thumbprint(&jwk))
+            .ok_or(OAuthError::InvalidDpopProof)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        let claims = decode::<ProofClaims>(proof, &key, &validation)
+            .map_err(|_| OAuthError::InvalidDpopProof)?
+            .claims;
+
+        if claims.htm != method || strip_query(&claims.htu) != strip_query(url) {
+            return Err(OAuthError::InvalidDpopProof);
+        }
+        let now = Utc::now().timestamp() as u64;
+        if now.abs_diff(claims.iat) > self.max_age {
+            return Err(OAuthError::InvalidDpopProof);
+        }
+        if let Some(access_token) = access_token
+            && claims.ath.as_deref() != Some(access_token_hash(access_token).as_str())
+        {
+            return Err(OAuthError::InvalidDpopProof);
+        }
+        self.remember(&jkt, &claims.jti, claims.iat, now)?;
+        Ok(DpopProof { jkt, jti: claims.jti, iat: claims.iat })
+    }
+
+    /// Binds an opaque token to the key of a proof. JWT access tokens are bound by their
+    /// `cnf` claim instead
+    pub fn bind(&self, token: &Token, jkt: impl Into<String>) {
+        let now = Utc::now().timestamp() as u64;
+        if self.bindings.len() > PRUNE_THRESHOLD {
+            self.bindings.retain(|_, (_, expires)| *expires > now);
+        }
+        self.bindings.insert(token.access_token.clone(), (jkt.into(), now + token.expires_in));
+    }
+
+    /// The thumbprint of the key a token is bound to, from `bind` or the `cnf` claim of a JWT
+    pub fn bound_key(&self, token: &Token) -> Option<String> {
+        if let TokenModel::DPoP { jkt } = &token.model {
+            return Some(jkt.clone());
+        }
+        if let Some(binding) = self.bindings.get(&token.access_token) {
+            return Some(binding.0.clone());
+        }
+        let payload = token.access_token.split('.').nth(1)?;
+        let claims: Value = serde_json::from_slice(&base64_url_decode(payload).ok()?).ok()?;
+        claims.get("cnf")?.get("jkt")?.as_str().map(str::to_string)
+    }
+
+    /// The URL of the request the proofs are checked against, without its query
+    pub fn request_url(&self, req: &mut HttpReqCtx) -> String {
+        let path = req.path();
+        let path = strip_query(&path);
+        match &self.origin {
+            Some(origin) => format!("{}{}", origin, path),
+            None => {
+                let scheme = if req.connection_info().tls.is_some() { "https" } else { "http" };
+                let host = req.meta().get_host().unwrap_or_default();
+                format!("{}://{}{}", scheme, host, path)
+            }
+        }
+    }
+
+    /// Verifies the proof sent to the token endpoint, if any
+    pub(crate) fn check_token_request(&self, req: &mut HttpReqCtx) -> Result<Option<DpopProof>, OAuthError> {
+        let Some(proof) = proof_header(req)? else {
+            return Ok(None);
+        };
+        let url = self.request_url(req);
+        let method = req.method().to_string();
+        self.verify(&proof, &method, &url, None).map(Some)
+    }
+
+    /// Verifies the proof sent to a protected route with a token. A bound token requires the
+    /// `DPoP` scheme and a proof of its key, while a bearer token must not use the scheme
+    pub(crate) fn check_resource(
+        &self,
+        req: &mut HttpReqCtx,
+        access_token: &str,
+        token: &Token,
+        dpop_scheme: bool,
+    ) -> Result<Option<DpopProof>, OAuthError> {
+        match (self.bound_key(token), dpop_scheme) {
+            (None, false) => Ok(None),
+            (Some(jkt), true) => {
+                let proof = proof_header(req)?.ok_or(OAuthError::InvalidDpopProof)?;
+                let url = self.request_url(req);
+                let method = req.method().to_string();
+                let proof = self.verify(&proof, &method, &url, Some(access_token))?;
+                if proof.jkt != jkt {
+                    return Err(OAuthError::InvalidDpopProof);
+                }
+                Ok(Some(proof))
+            }
+            // A bound token sent as a bearer token, or an unbound one sent with the DPoP scheme
+            _ => Err(OAuthError::InvalidToken),
+        }
+    }
+
+    /// Rejects the replay of a proof
+    fn remember(&self, jkt: &str, jti: &str, iat: u64, now: u64) -> Result<(), OAuthError> {
+        if self.seen.len() > PRUNE_THRESHOLD {
+            let max_age = self.max_age;
+            self.seen.retain(|_, seen| now.abs_diff(*seen) <= max_age);
+        }
+        let key = format!("{}:{}", jkt, jti);
+        if self.seen.contains_key(&key) {
+            return Err(OAuthError::InvalidDpopProof);
+        }
+        self.seen.insert(key, iat);
+        Ok(())
+    }
+}
+
+impl Default for DpopValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A P-256 key of a client signing its DPoP proofs
+#[derive(Clone)]
+pub struct DpopKey {
+    pair: Arc<EcdsaKeyPair>,
+    jwk: Value,
+    thumbprint: String,
+}
+
+impl DpopKey {
+    /// Generates a new key
+    pub fn generate() -> Result<Self, OAuthError> {
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+            .map_err(|_| OAuthError::ServerError)?;
+        Self::from_pkcs8(pkcs8.as_ref())
+    }
+
+    /// Loads a P-256 key in PKCS#8 DER, to keep the bindings across restarts of the client
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self, OAuthError> {
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &SystemRandom::new())
+            .map_err(|_| OAuthError::ServerError)?;
+        // Uncompressed point: 0x04, then the coordinates
+        let point = pair.public_key().as_ref();
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": base64_url_encode(&point[1..33]),
+            "y": base64_url_encode(&point[33..65]),
+        });
+        let thumbprint = jwk_thumbprint(&jwk).ok_or(OAuthError::ServerError)?;
+        Ok(Self { pair: Arc::new(pair), jwk, thumbprint })
+    }
+
+    /// The public key, as a JWK
+    pub fn jwk(&self) -> &Value {
+        &self.jwk
+    }
+
+    /// The JWK thumbprint of the key, the `jkt` the tokens are bound to
+    pub fn thumbprint(&self) -> &str {
+        &self.thumbprint
+    }
+
+    /// Creates the proof of a request. `access_token` is given for the requests to protected
+    /// routes, `nonce` when the server asked for one with the `DPoP-Nonce` header
+    pub fn proof(&self, method: &str, url: &str, access_token: Option<&str>, nonce: Option<&str>) -> Result<String, OAuthError> {
+        let header = json!({ "typ": "dpop+jwt", "alg": "ES256", "jwk": self.jwk });
+        let mut claims = json!({
+            "jti": Uuid::new_v4().to_string(),
+            "htm": method,
+            "htu": strip_query(url),
+            "iat": Utc::now().timestamp(),
+        });
+        if let Some(access_token) = access_token {
+            claims["ath"] = Value::String(access_token_hash(access_token));
+        }
+        if let Some(nonce) = nonce {
+            claims["nonce"] = Value::String(nonce.to_string());
+        }
+        let input = format!(
+            "{}.{}",
+            base64_url_encode(header.to_string()),
+            base64_url_encode(claims.to_string())
+        );
+        let signature = self.pair
+            .sign(&SystemRandom::new(), input.as_bytes())
+            .map_err(|_| OAuthError::ServerError)?;
+        Ok(format!("{}.{}", input, base64_url_encode(signature.as_ref())))
+    }
+}
+
+impl fmt::Debug for DpopKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DpopKey").field("thumbprint", &self.thumbprint).finish()
+    }
+}
+
+/// The JWK thumbprint of RFC 7638: the base64url SHA-256 of the required members of the key,
+/// serialized in lexicographic order
+pub fn jwk_thumbprint(jwk: &Value) -> Option<String> {
+    let members: &[&str] = match jwk.get("kty")?.as_str()? {
+        "EC" => &["crv", "kty", "x", "y"],
+        "RSA" => &["e", "kty", "n"],
+        "OKP" => &["crv", "kty", "x"],
+        _ => return None,
+    };
+    let required = members
+        .iter()
+        .map(|member| Some((*member, jwk.get(*member)?.as_str()?)))
+        .collect::<Option<BTreeMap<_, _>>>()?;
+    let canonical = serde_json::to_string(&required).ok()?;
+    Some(base64_url_encode(digest(&SHA256, canonical.as_bytes())))
+}
+
+/// The `ath` claim of the proofs sent with an access token
+pub fn access_token_hash(access_token: &str) -> String {
+    base64_url_encode(digest(&SHA256, access_token.as_bytes()))
+}
+
+/// The single `DPoP` header of the request
+fn proof_header(req: &mut HttpReqCtx) -> Result<Option<String>, OAuthError> {
+    match req.meta().get_header("dpop") {
+        // Several headers are joined by commas, which a JWT never contains
+        Some(proof) if proof.contains(',') => Err(OAuthError::InvalidDpopProof),
+        proof => Ok(proof.map(|proof| proof.trim().to_string())),
+    }
+}
+
+fn strip_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
@@ -4,9 +4,7 @@ use super::types::{Token, OAuthError, TokenModel};
 use super::oauth_provider::TokenStorage;
 use super::http_client::{OAuthHttpClient, HttpRequest, RedirectPolicy};
 use starberry_lib::url_encoding::encode_url_owned;
-use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use base64::Engine;
-use starberry_core::http::http_value::HttpMethod;
+use starberry_core::http::http_value::{Authorization, HttpMethod};
 use serde_json::Value;
 use tracing::{instrument, debug};
 
@@ -154,9 +152,8 @@ impl ClientCredentialsFlow {
             .into_bytes();
         // Build headers
         let mut headers = vec![("Content-Type".into(), "application/x-www-form-urlencoded".into())];
-        let creds = format!("{}:{}", self.client_id, self.client_secret);
-        let auth = format!("Basic {}", URL_SAFE_NO_PAD.encode(creds.as_bytes()));
-        headers.push(("Authorization".into(), auth));
+        let auth = Authorization::basic(self.client_id.clone(), self.client_secret.clone());
+        headers.push(("Authorization".into(), auth.to_string()));
         // Build request
         let request = HttpRequest {
             method: HttpMethod::POST,
@@ -0,0 +1,119 @@
+//! Validation of access tokens from several issuers, e.g. one identity provider per tenant of
+//! a SaaS application.
+//!
+//! Each tenant has its issuer and the `TokenManager` validating its tokens, usually a
+//! `JWTTokenManager` with the audience expected from the issuer and the JWKS of the issuer.
+//! A token is given to the tenant bound to the host of the request, or otherwise to the
+//! tenant whose issuer matches the `iss` claim.
+
+use std::sync::Arc;
+
+use jsonwebtoken::decode_header;
+use starberry_lib::encoding::base64_url_decode;
+
+use super::jwks::JwksCache;
+use super::jwt::JWTTokenManager;
+use super::oauth_provider::TokenManager;
+use super::types::{OAuthError, Token};
+
+/// The tenant a token was validated for, stored in `req.params` by `OAuthLayer`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant {
+    /// The name given to `IssuerRegistry::issuer`
+    pub id: String,
+    /// The issuer of the token, its `iss` claim
+    pub issuer: String,
+}
+
+#[derive(Clone)]
+struct Registered {
+    tenant: Tenant,
+    manager: Arc<dyn TokenManager>,
+    hosts: Vec<String>,
+}
+
+/// The issuers trusted by `OAuthLayer::issuers`
+#[derive(Clone, Default)]
+pub struct IssuerRegistry {
+    issuers: Vec<Registered>,
+}
+
+impl IssuerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts the tokens of the issuer for the tenant, validated by the manager
+    pub fn issuer(mut self, tenant: impl Into<String>, issuer: impl Into<String>, manager: Arc<dyn TokenManager>) -> Self {
+        self.issuers.push(Registered {
+            tenant: Tenant { id: tenant.into(), issuer: issuer.into() },
+            manager,
+            hosts: Vec::new(),
+        });
+        self
+    }
+
+    /// Trusts the RS256 tokens of the issuer for the tenant, signed with a key of the JWKS and
+    /// intended for the audience
+    pub fn jwks(self, tenant: impl Into<String>, issuer: impl Into<String>, audience: impl Into<String>, jwks: JwksCache) -> Self {
+        let issuer = issuer.into();
+        let manager = JWTTokenManager::from_jwks(jwks).with_claims(issuer.clone(), audience);
+        self.issuer(tenant, issuer, Arc::new(manager))
+    }
+
+    /// Binds a host, e.g. `acme.example.com`, to a tenant registered before. The requests to it
+    /// only accept the tokens of the issuer of the tenant
+    pub fn host(mut self, tenant: &str, host: impl Into<String>) -> Self {
+        let host = normalize_host(&host.into());
+        if let Some(registered) = self.issuers.iter_mut().find(|registered| registered.tenant.id == tenant) {
+            registered.hosts.push(host);
+        }
+        self
+    }
+
+    /// The tenant bound to a host, which may include a port
+    pub fn tenant_for_host(&self, host: &str) -> Option<&Tenant> {
+        let host = normalize_host(host);
+        self.issuers
+            .iter()
+            .find(|registered| registered.hosts.contains(&host))
+            .map(|registered| &registered.tenant)
+    }
+
+    /// Validates a token with the manager of the tenant of the host, or of the issuer named in
+    /// the token. Tokens of unknown issuers are invalid
+    pub async fn validate(&self, token: &str, host: Option<&str>) -> Result<(Tenant, Token), OAuthError> {
+        let by_host = host.map(normalize_host).and_then(|host| {
+            self.issuers.iter().find(|registered| registered.hosts.contains(&host))
+        });
+        let registered = match by_host {
+            Some(registered) => registered,
+            None => {
+                let issuer = unverified_issuer(token).ok_or(OAuthError::InvalidToken)?;
+                self.issuers
+                    .iter()
+                    .find(|registered| registered.tenant.issuer == issuer)
+                    .ok_or(OAuthError::InvalidToken)?
+            }
+        };
+        let token = registered.manager.validate_token(token).await?;
+        Ok((registered.tenant.clone(), token))
+    }
+}
+
+fn normalize_host(host: &str) -> String {
+    let host = host.trim().to_ascii_lowercase();
+    match host.rsplit_once(':') {
+        // Keep IPv6 literals such as `[::1]` whole
+        Some((name, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => name.to_string(),
+        _ => host,
+    }
+}
+
+/// The `iss` claim of a JWT, read before its signature is checked to pick the key
+fn unverified_issuer(token: &str) -> Option<String> {
+    decode_header(token).ok()?;
+    let payload = token.split('.').nth(1)?;
+    let claims: serde_json::Value = serde_json::from_slice(&base64_url_decode(payload).ok()?).ok()?;
+    claims.get("iss")?.as_str().map(str::to_string)
+}
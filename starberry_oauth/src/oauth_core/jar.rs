@@ -0,0 +1,157 @@
+//! JWT-secured authorization requests, the request objects of RFC 9101.
+//!
+//! The client sends the parameters of its authorization request as the claims of a JWT it
+//! signed, in the `request` parameter, so they cannot be tampered with in the browser. The
+//! objects are signed with RS256 by a key of the JWKS of the client, or with HS256 by its
+//! secret.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+
+use super::jwks::JwksCache;
+use super::types::{Client, OAuthError};
+
+/// The claims of the JWT which are not parameters of the request
+const JWT_CLAIMS: [&str; 6] = ["iss", "aud", "exp", "iat", "nbf", "jti"];
+
+/// Verifies the request objects sent to the authorization and pushed authorization endpoints
+#[derive(Clone)]
+pub struct RequestObjectVerifier {
+    audience: String,
+    jwks: Arc<HashMap<String, JwksCache>>,
+}
+
+impl RequestObjectVerifier {
+    /// Accepts the objects intended for the provider, the `aud` being its issuer identifier
+    pub fn new(issuer: impl Into<String>) -> Self {
+        Self {
+            audience: issuer.into(),
+            jwks: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Verifies the RS256 objects of the client with the keys of its JWKS
+    pub fn client_jwks(mut self, client_id: impl Into<String>, jwks: JwksCache) -> Self {
+        Arc::make_mut(&mut self.jwks).insert(client_id.into(), jwks);
+        self
+    }
+
+    /// Verifies a request object of the client, returning the parameters of the request.
+    /// The object must be issued by the client, for the provider, and not be expired
+    pub async fn verify(&self, request: &str, client: &Client) -> Result<HashMap<String, String>, OAuthError> {
+        let header = decode_header(request).map_err(|_| OAuthError::InvalidRequestObject)?;
+        let key = match header.alg {
+            Algorithm::RS256 => {
+                let jwks = self.jwks.get(&client.id).ok_or(OAuthError::InvalidRequestObject)?;
+                let kid = header.kid.ok_or(OAuthError::InvalidRequestObject)?;
+                jwks.get(&kid).await.map_err(|_| OAuthError::InvalidRequestObject)?
+            }
+            Algorithm::HS256 => {
+                let secret = client.secret.as_ref().ok_or(OAuthError::InvalidRequestObject)?;
+                DecodingKey::from_secret(secret.as_bytes())
+            }
+            _ => return Err(OAuthError::InvalidRequestObject),
+        };
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[self.audience.as_str()]);
+        validation.set_issuer(&[client.id.as_str()]);
+        // Only compared when present otherwise, an object made for another server would pass
+        validation.required_spec_claims.extend(["aud".to_string(), "iss".to_string()]);
+        let claims = decode::<Map<String, Value>>(request, &key, &validation)
+            .map_err(|_| OAuthError::InvalidRequestObject)?
+            .claims;
+        request_parameters(claims, &client.id)
+    }
+}
+
+/// The parameters of a verified request object. Its `client_id` must be the one of the client,
+/// and it cannot refer to another request
+pub(crate) fn request_parameters(claims: Map<String, Value>, client_id: &str) -> Result<HashMap<String, String>, OAuthError> {
+    if claims.contains_key("request") || claims.contains_key("request_uri") {
+        return Err(OAuthError::InvalidRequestObject);
+    }
+    if claims.get("client_id").is_some_and(|id| id.as_str() != Some(client_id)) {
+        return Err(OAuthError::InvalidRequestObject);
+    }
+    let mut params: HashMap<String, String> = claims
+        .into_iter()
+        .filter(|(name, _)| !JWT_CLAIMS.contains(&name.as_str()))
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value,
+                // e.g. the `claims` or `authorization_details` parameters, which are JSON
+                value => value.to_string(),
+            };
+            (name, value)
+        })
+        .collect();
+    params.insert("client_id".to_string(), client_id.to_string());
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn claims(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_request_parameters() {
+        let params = request_parameters(
+            claims(json!({
+                "iss": "client1",
+                "aud": "https://auth.local",
+                "exp": 1,
+                "response_type": "code",
+                "max_age": 300,
+                "claims": { "userinfo": { "email": null } },
+            })),
+            "client1",
+        )
+        .unwrap();
+        assert_eq!(params.get("client_id").map(String::as_str), Some("client1"));
+        assert_eq!(params.get("response_type").map(String::as_str), Some("code"));
+        assert_eq!(params.get("max_age").map(String::as_str), Some("300"));
+        assert_eq!(params.get("claims").map(String::as_str), Some(r#"{"userinfo":{"email":null}}"#));
+        assert!(!params.contains_key("iss") && !params.contains_key("exp"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_requires_issuer_and_audience() {
+        let client = Client {
+            id: "client1".to_string(),
+            secret: Some("secret".to_string()),
+            redirect_uris: Vec::new(),
+            post_logout_redirect_uris: Vec::new(),
+        };
+        let verifier = RequestObjectVerifier::new("https://auth.local");
+        let sign = |claims: Value| encode(&Header::default(), &claims, &EncodingKey::from_secret(b"secret")).unwrap();
+        let exp = chrono::Utc::now().timestamp() + 60;
+
+        let request = sign(json!({ "iss": "client1", "aud": "https://auth.local", "exp": exp, "scope": "openid" }));
+        let params = verifier.verify(&request, &client).await.unwrap();
+        assert_eq!(params.get("scope").map(String::as_str), Some("openid"));
+        for claims in [json!({ "aud": "https://auth.local", "exp": exp }), json!({ "iss": "client1", "exp": exp })] {
+            let result = verifier.verify(&sign(claims), &client).await;
+            assert!(matches!(result, Err(OAuthError::InvalidRequestObject)));
+        }
+    }
+
+    #[test]
+    fn test_request_parameters_of_another_client() {
+        let result = request_parameters(claims(json!({ "client_id": "client2" })), "client1");
+        assert!(matches!(result, Err(OAuthError::InvalidRequestObject)));
+        let result = request_parameters(claims(json!({ "request_uri": "urn:x" })), "client1");
+        assert!(matches!(result, Err(OAuthError::InvalidRequestObject)));
+    }
+}
@@ -1,19 +1,25 @@
 //! JWT-based TokenManager for OAuth2.
+//!
+//! Applications signing their own tokens outside of OAuth use `starberry_lib::jwt` instead.
 
-use std::{pin::Pin, future::Future};
-use jsonwebtoken::{EncodingKey, DecodingKey, Header, Validation, encode, decode, decode_header};
+use std::time::Duration;
+use jsonwebtoken::decode_header;
 use serde::{Serialize, Deserialize};
 use chrono::Utc;
+use starberry_lib::jwt::{Algorithm, Claims, DecodingKey, EncodingKey, Jwt};
 use super::types::{JWTAlgorithm, TokenModel, Token, Grant, OAuthError};
 use super::oauth_provider::TokenManager;
 use async_trait::async_trait;
 use super::jwks::JwksCache;
 use tracing::instrument;
+use starberry_core::app::secrets::{SecretsProvider, SecretError};
 
 /// A TokenManager that issues JWT access tokens.
+///
+/// The tokens are signed and verified by a `starberry_lib::jwt::Jwt`, which holds the keys and
+/// the expected issuer and audience.
 pub struct JWTTokenManager {
-    encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
+    jwt: Jwt,
     algorithm: JWTAlgorithm,
     expiration_seconds: u64,
     issuer: Option<String>,
@@ -24,23 +30,56 @@ pub struct JWTTokenManager {
 impl JWTTokenManager {
     /// Create a new JWTTokenManager using HS256 and a shared secret.
     pub fn new_hs256(secret: &[u8], expiration_seconds: u64) -> Self {
-        Self {
-            encoding_key: EncodingKey::from_secret(secret),
-            decoding_key: DecodingKey::from_secret(secret),
-            algorithm: JWTAlgorithm::HS256,
-            expiration_seconds,
-            issuer: None,
-            audience: None,
-            jwks_cache: None,
-        }
+        Self::with_jwt(Jwt::hs256(secret), JWTAlgorithm::HS256, expiration_seconds)
     }
 
     /// Create a new JWTTokenManager using RS256 and RSA key pair.
     pub fn new_rs256(private_key_pem: &[u8], public_key_pem: &[u8], expiration_seconds: u64) -> Self {
+        let jwt = Jwt::from_keys(
+            Algorithm::RS256,
+            Some(EncodingKey::from_rsa_pem(private_key_pem).expect("Invalid private key")),
+            DecodingKey::from_rsa_pem(public_key_pem).expect("Invalid public key"),
+        );
+        Self::with_jwt(jwt, JWTAlgorithm::RS256, expiration_seconds)
+    }
+
+    /// Create a new JWTTokenManager using HS256, with the shared secret read from a secrets provider.
+    pub async fn hs256_from_secrets(secrets: &dyn SecretsProvider, secret_name: &str, expiration_seconds: u64) -> Result<Self, SecretError> {
+        let secret = secrets.require(secret_name).await?;
+        Ok(Self::new_hs256(secret.as_bytes(), expiration_seconds))
+    }
+
+    /// Create a new JWTTokenManager using RS256, with the PEM keys read from a secrets provider.
+    /// Unlike `new_rs256`, an invalid key is returned as an error.
+    pub async fn rs256_from_secrets(
+        secrets: &dyn SecretsProvider,
+        private_key_name: &str,
+        public_key_name: &str,
+        expiration_seconds: u64,
+    ) -> Result<Self, SecretError> {
+        let private_key = secrets.require(private_key_name).await?;
+        let public_key = secrets.require(public_key_name).await?;
+        let jwt = Jwt::from_keys(
+            Algorithm::RS256,
+            Some(EncodingKey::from_rsa_pem(private_key.as_bytes())
+                .map_err(|e| SecretError::Invalid(format!("{}: {}", private_key_name, e)))?),
+            DecodingKey::from_rsa_pem(public_key.as_bytes())
+                .map_err(|e| SecretError::Invalid(format!("{}: {}", public_key_name, e)))?,
+        );
+        Ok(Self::with_jwt(jwt, JWTAlgorithm::RS256, expiration_seconds))
+    }
+
+    /// Create a JWTTokenManager validating RS256 tokens signed with the keys of a JWKS, e.g. the
+    /// ones of an external identity provider. It cannot issue tokens.
+    pub fn from_jwks(jwks_cache: JwksCache) -> Self {
+        let jwt = Jwt::from_keys(Algorithm::RS256, None, DecodingKey::from_secret(&[]));
+        Self { jwks_cache: Some(jwks_cache), ..Self::with_jwt(jwt, JWTAlgorithm::RS256, 0) }
+    }
+
+    fn with_jwt(jwt: Jwt, algorithm: JWTAlgorithm, expiration_seconds: u64) -> Self {
         Self {
-            encoding_key: EncodingKey::from_rsa_pem(private_key_pem).expect("Invalid private key"),
-            decoding_key: DecodingKey::from_rsa_pem(public_key_pem).expect("Invalid public key"),
-            algorithm: JWTAlgorithm::RS256,
+            jwt,
+            algorithm,
             expiration_seconds,
             issuer: None,
             audience: None,
@@ -48,10 +87,12 @@ impl JWTTokenManager {
         }
     }
 
-    /// Configure expected issuer and audience.
+    /// Configure expected issuer and audience. They are also set in the issued tokens.
     pub fn with_claims(mut self, issuer: impl Into<String>, audience: impl Into<String>) -> Self {
-        self.issuer = Some(issuer.into());
-        self.audience = Some(audience.into());
+        let (issuer, audience) = (issuer.into(), audience.into());
+        self.jwt = self.jwt.issuer(issuer.clone()).audience(audience.clone());
+        self.issuer = Some(issuer);
+        self.audience = Some(audience);
         self
     }
 
@@ -60,22 +101,15 @@ impl JWTTokenManager {
         self.jwks_cache = Some(jwks_cache);
         self
     }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
-    scope: Option<String>,
-}
 
-#[async_trait]
-impl TokenManager for JWTTokenManager {
+    /// Issue an access token bound by DPoP to the key of the client, by its `cnf` claim.
     #[instrument(skip(self, grant), level = "debug")]
-    async fn generate_token(&self, grant: Grant) -> Result<Token, OAuthError> {
-        let encoding_key = self.encoding_key.clone();
+    pub fn generate_dpop_token(&self, grant: Grant, jkt: &str) -> Result<Token, OAuthError> {
+        self.issue(grant, Some(jkt.to_string()))
+    }
+
+    fn issue(&self, grant: Grant, jkt: Option<String>) -> Result<Token, OAuthError> {
         let alg = self.algorithm.clone();
-        let exp_secs = self.expiration_seconds as usize;
         // Determine subject and scope based on grant.
         let (sub, scope) = match grant {
             Grant::AuthorizationCode { code, .. } => (code, None),
@@ -84,22 +118,41 @@ impl TokenManager for JWTTokenManager {
             Grant::ResourceOwnerPassword { username, .. } => (username, None),
             Grant::DeviceCode { device_code, .. } => (device_code, None),
         };
-        let now = Utc::now().timestamp() as usize;
-        let claims = Claims { sub, exp: now + exp_secs, scope };
-        let header = match alg {
-            JWTAlgorithm::HS256 => Header::new(jsonwebtoken::Algorithm::HS256),
-            JWTAlgorithm::RS256 => Header::new(jsonwebtoken::Algorithm::RS256),
-        };
-        let token_str = encode(&header, &claims, &encoding_key).map_err(|_| OAuthError::ServerError)?;
+        let mut claims = Claims::new(AccessClaims {
+            scope,
+            cnf: jkt.map(|jkt| serde_json::json!({ "jkt": jkt })),
+        })
+        .subject(sub)
+        .expires_in(Duration::from_secs(self.expiration_seconds));
+        claims.iss = self.issuer.clone();
+        claims.aud = self.audience.iter().cloned().collect();
+        let token_str = self.jwt.sign(&claims).map_err(|_| OAuthError::ServerError)?;
         Ok(Token {
             model: TokenModel::JWT { algorithm: alg },
             access_token: token_str,
             refresh_token: None,
-            expires_in: exp_secs as u64,
+            expires_in: self.expiration_seconds,
             scope: None,
             id_token: None,
         })
     }
+}
+
+/// The claims of the access tokens besides the registered ones
+#[derive(Debug, Serialize, Deserialize)]
+struct AccessClaims {
+    scope: Option<String>,
+    /// The DPoP key the token is bound to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cnf: Option<serde_json::Value>,
+}
+
+#[async_trait]
+impl TokenManager for JWTTokenManager {
+    #[instrument(skip(self, grant), level = "debug")]
+    async fn generate_token(&self, grant: Grant) -> Result<Token, OAuthError> {
+        self.issue(grant, None)
+    }
 
     async fn revoke_token(&self, _token: &str) -> Result<(), OAuthError> {
         // Stateless JWT; revocation requires blacklist if needed
@@ -110,37 +163,24 @@ impl TokenManager for JWTTokenManager {
     async fn validate_token(&self, token: &str) -> Result<Token, OAuthError> {
         let alg = self.algorithm.clone();
         let token_owned = token.to_string();
-        // Set up validation checks
-        let mut validation = Validation::new(match alg {
-            JWTAlgorithm::HS256 => jsonwebtoken::Algorithm::HS256,
-            JWTAlgorithm::RS256 => jsonwebtoken::Algorithm::RS256,
-        });
-        validation.validate_exp = true;
-        if let Some(ref iss) = self.issuer {
-            validation.set_issuer(&[iss.clone()]);
-        }
-        if let Some(ref aud) = self.audience {
-            validation.set_audience(&[aud.clone()]);
-        }
         // Determine decoding key: JWKS cache overrides static key
-        let decoding_key = if let Some(cache) = &self.jwks_cache {
+        let claims = if let Some(cache) = &self.jwks_cache {
             let header = decode_header(&token_owned).map_err(|_| OAuthError::InvalidToken)?;
             let kid = header.kid.ok_or(OAuthError::InvalidToken)?;
-            cache.get(&kid).await.map_err(|_| OAuthError::InvalidToken)?
+            let decoding_key = cache.get(&kid).await.map_err(|_| OAuthError::InvalidToken)?;
+            self.jwt.verify_with::<AccessClaims>(&token_owned, &decoding_key)
         } else {
-            self.decoding_key.clone()
-        };
-        let token_data = decode::<Claims>(&token_owned, &decoding_key, &validation)
-            .map_err(|_| OAuthError::InvalidToken)?;
-        let claims = token_data.claims;
-        let now = Utc::now().timestamp() as usize;
-        let expires_in = if claims.exp > now { (claims.exp - now) as u64 } else { 0 };
+            self.jwt.verify::<AccessClaims>(&token_owned)
+        }
+        .map_err(|_| OAuthError::InvalidToken)?;
+        let now = Utc::now().timestamp() as u64;
+        let expires_in = claims.exp.map_or(0, |exp| exp.saturating_sub(now));
         Ok(Token {
             model: TokenModel::JWT { algorithm: alg },
             access_token: token_owned.clone(),
             refresh_token: None,
             expires_in,
-            scope: claims.scope,
+            scope: claims.extra.scope,
             id_token: None,
         })
     }
@@ -8,6 +8,8 @@ use dashmap::DashMap;
 use uuid::Uuid;
 use super::types::{Client, Grant, Token, TokenModel, OAuthError};
 use super::oauth_provider::{ClientStore, TokenManager, Authorizer, TokenStorage};
+use super::consent::GrantRecord;
+use chrono::Utc;
 use tokio::sync::RwLock;
 use std::collections::{HashMap, HashSet};
 use async_trait::async_trait;
@@ -80,7 +82,7 @@ impl TokenManager for InMemoryTokenManager {
 
 #[derive(Clone)]
 pub struct InMemoryAuthorizer {
-    consents: Arc<DashMap<(String, String), Vec<String>>>,
+    consents: Arc<DashMap<(String, String), GrantRecord>>,
 }
 
 impl InMemoryAuthorizer {
@@ -93,18 +95,44 @@ impl InMemoryAuthorizer {
 #[async_trait]
 impl Authorizer for InMemoryAuthorizer {
     async fn record_consent(&self, client_id: &str, user_id: &str, scopes: &[String]) -> Result<(), OAuthError> {
-        self.consents.insert((client_id.to_owned(), user_id.to_owned()), scopes.to_vec());
+        let mut record = self.consents
+            .entry((client_id.to_owned(), user_id.to_owned()))
+            .or_insert_with(|| GrantRecord {
+                client_id: client_id.to_owned(),
+                user_id: user_id.to_owned(),
+                scopes: Vec::new(),
+                granted_at: 0,
+            });
+        for scope in scopes {
+            if !record.scopes.contains(scope) {
+                record.scopes.push(scope.clone());
+            }
+        }
+        record.granted_at = Utc::now().timestamp();
         Ok(())
     }
 
     async fn check_consent(&self, client_id: &str, user_id: &str, scopes: &[String]) -> Result<bool, OAuthError> {
         if let Some(entry) = self.consents.get(&(client_id.to_owned(), user_id.to_owned())) {
-            let granted = entry.value();
-            Ok(scopes.iter().all(|s| granted.contains(s)))
+            Ok(entry.value().covers(scopes))
         } else {
             Ok(false)
         }
     }
+
+    async fn list_grants(&self, user_id: &str) -> Result<Vec<GrantRecord>, OAuthError> {
+        let mut grants: Vec<GrantRecord> = self.consents
+            .iter()
+            .filter(|entry| entry.value().user_id == user_id)
+            .map(|entry| entry.value().clone())
+            .collect();
+        grants.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        Ok(grants)
+    }
+
+    async fn revoke_grant(&self, client_id: &str, user_id: &str) -> Result<bool, OAuthError> {
+        Ok(self.consents.remove(&(client_id.to_owned(), user_id.to_owned())).is_some())
+    }
 }
 
 /// In-memory storage backend for OAuth tokens, PKCE verifiers, and CSRF states.
@@ -4,15 +4,27 @@ use starberry_core::app::middleware::AsyncMiddleware;
 use super::oauth_provider::{ClientStore, TokenManager, Authorizer};
 use super::memory::{InMemoryClientStore, InMemoryTokenManager, InMemoryAuthorizer};
 use super::types::OAuthContext;
-use starberry_core::http::http_value::StatusCode;
-use starberry_core::http::response::response_templates::return_status;
+use super::issuers::IssuerRegistry;
+use super::dpop::DpopValidator;
+use super::par::PushedRequests;
+use super::jar::RequestObjectVerifier;
+use super::crypto::constant_eq;
+use super::consent::{ConsentGrant, ConsentPage, ConsentRequest, DefaultConsentPage, ScopeDescriptions, ScopeItem};
+use super::types::parse_scopes;
+use sbmstd::AuthExt;
+use starberry_lib::url_encoding::encode_url_owned;
+use super::types::OAuthError;
+use super::rate_limiter::{CacheRateLimiter, RateLimiter};
+use starberry_core::cache::{MemoryCache, SharedCache};
+use starberry_core::http::http_value::{Authorization, StatusCode};
+use starberry_core::http::response::response_templates::{normal_response, redirect_response, return_status};
+use starberry_core::http::http_value::HttpContentType;
+use starberry_core::http::query::QueryMap;
 use uuid::Uuid;
 use std::collections::HashMap;
 use starberry_core::http::http_value::HttpMethod;
 use starberry_core::http::cookie::Cookie;
-use starberry_core::http::response::response_templates::html_response;
-use std::sync::{Mutex, OnceLock};
-use std::time::{Instant, Duration};
+use std::sync::OnceLock;
 use starberry_macro::middleware; 
 
 
@@ -22,8 +34,16 @@ pub struct OAuthLayer {
     client_store: Arc<dyn ClientStore>,
     token_manager: Arc<dyn TokenManager>,
     authorizer: Arc<dyn Authorizer>,
+    issuers: Option<IssuerRegistry>,
+    dpop: Option<DpopValidator>,
+    pushed_requests: Option<PushedRequests>,
+    request_objects: Option<RequestObjectVerifier>,
+    consent_page: Arc<dyn ConsentPage>,
+    scope_descriptions: ScopeDescriptions,
+    rate_limiter: Option<Arc<dyn RateLimiter>>,
     authorize_endpoint: String,
     token_endpoint: String,
+    par_endpoint: String,
 }
 
 impl OAuthLayer {
@@ -33,8 +53,16 @@ impl OAuthLayer {
             client_store: Arc::new(InMemoryClientStore::new(Vec::new())),
             token_manager: Arc::new(InMemoryTokenManager::new()),
             authorizer: Arc::new(InMemoryAuthorizer::new()),
+            issuers: None,
+            dpop: None,
+            pushed_requests: None,
+            request_objects: None,
+            consent_page: Arc::new(DefaultConsentPage),
+            scope_descriptions: ScopeDescriptions::new(),
+            rate_limiter: None,
             authorize_endpoint: "/oauth/authorize".into(),
             token_endpoint: "/oauth/token".into(),
+            par_endpoint: "/oauth/par".into(),
         }
     }
 
@@ -56,6 +84,56 @@ impl OAuthLayer {
         self
     }
 
+    /// Validates the bearer tokens of the protected routes with the issuers of several tenants,
+    /// selected by host or by `iss` claim, instead of the token manager. The tenant is stored
+    /// in `req.params` as `Tenant`.
+    pub fn issuers(mut self, issuers: IssuerRegistry) -> Self {
+        self.issuers = Some(issuers);
+        self
+    }
+
+    /// Verifies the DPoP proofs. The proof sent to the token endpoint is stored in `req.params`
+    /// as `DpopProof` for the handler to bind the issued token, and the bound tokens are only
+    /// accepted on the protected routes with the `DPoP` scheme and a proof of their key.
+    pub fn dpop(mut self, validator: DpopValidator) -> Self {
+        self.dpop = Some(validator);
+        self
+    }
+
+    /// Serves the pushed authorization request endpoint and accepts its `request_uri` at the
+    /// authorization endpoint.
+    pub fn pushed_requests(mut self, pushed: PushedRequests) -> Self {
+        self.pushed_requests = Some(pushed);
+        self
+    }
+
+    /// Accepts the authorization requests sent as request objects, in the `request` parameter.
+    pub fn request_objects(mut self, verifier: RequestObjectVerifier) -> Self {
+        self.request_objects = Some(verifier);
+        self
+    }
+
+    /// Sets the page asking the users to approve the scopes requested by a client, e.g. a
+    /// `TemplateConsentPage`.
+    pub fn consent_page(mut self, page: Arc<dyn ConsentPage>) -> Self {
+        self.consent_page = page;
+        self
+    }
+
+    /// Sets the descriptions of the scopes shown on the consent page.
+    pub fn scope_descriptions(mut self, descriptions: ScopeDescriptions) -> Self {
+        self.scope_descriptions = descriptions;
+        self
+    }
+
+    /// Sets the limiter of the authorization endpoint, per client, and of the token endpoint,
+    /// per IP address. Defaults to a `CacheRateLimiter` over the cache of the application, or
+    /// over an in-process cache if the application has none.
+    pub fn rate_limiter(mut self, limiter: Arc<dyn RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
     /// Overrides the authorization endpoint path.
     pub fn authorize_endpoint<S: Into<String>>(mut self, path: S) -> Self {
         self.authorize_endpoint = path.into();
@@ -68,6 +146,12 @@ impl OAuthLayer {
         self
     }
 
+    /// Overrides the pushed authorization request endpoint path.
+    pub fn par_endpoint<S: Into<String>>(mut self, path: S) -> Self {
+        self.par_endpoint = path.into();
+        self
+    }
+
     /// Use JWT access tokens with HS256 signing.
     pub fn use_jwt_hs256(mut self, secret: &[u8], expiration_seconds: u64) -> Self {
         use super::jwt::JWTTokenManager;
@@ -97,35 +181,36 @@ impl OAuthLayer {
     }
 }
 
-// Simple token bucket for rate limiting
-struct TokenBucket {
-    capacity: u32,
-    tokens: f64,
-    last: Instant,
-    refill_rate: f64, // tokens per second
+/// The cache of the default rate limiter of the applications without a cache
+fn local_rate_limits() -> SharedCache {
+    static CACHE: OnceLock<SharedCache> = OnceLock::new();
+    CACHE.get_or_init(|| Arc::new(MemoryCache::new(10_000))).clone()
 }
 
-impl TokenBucket {
-    fn new(capacity: u32, refill_rate: f64) -> Self {
-        Self { capacity, tokens: capacity as f64, last: Instant::now(), refill_rate }
+/// The configured rate limiter, or the default one over the cache of the application
+fn rate_limiter(configured: &Option<Arc<dyn RateLimiter>>, req: &HttpReqCtx) -> Arc<dyn RateLimiter> {
+    match configured {
+        Some(limiter) => limiter.clone(),
+        None => Arc::new(CacheRateLimiter::new(req.cache().unwrap_or_else(local_rate_limits)).prefix("oauth_rate:")),
     }
-    fn consume(&mut self, amount: u32) -> bool {
-        let now = Instant::now();
-        let elapsed = now.duration_since(self.last).as_secs_f64();
-        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity as f64);
-        self.last = now;
-        if self.tokens >= amount as f64 {
-            self.tokens -= amount as f64;
+}
+
+/// Consumes a token of `key`, answers 429 when the limit is reached. Requests are let through
+/// if the limiter fails
+async fn rate_limited(limiter: &Option<Arc<dyn RateLimiter>>, req: &mut HttpReqCtx, key: &str) -> bool {
+    match rate_limiter(limiter, req).consume(key).await {
+        Ok(true) => false,
+        Ok(false) => {
+            req.response = return_status(StatusCode::TOO_MANY_REQUESTS);
             true
-        } else {
+        }
+        Err(e) => {
+            eprintln!("OAuth rate limiter failed: {:?}", e);
             false
         }
     }
 }
 
-// Global rate limiter map: key -> TokenBucket
-static RATE_LIMITERS: OnceLock<Mutex<HashMap<String, TokenBucket>>> = OnceLock::new();
-
 impl AsyncMiddleware<HttpReqCtx> for OAuthLayer {
     fn as_any(&self) -> &dyn Any {
         self
@@ -145,6 +230,14 @@ impl AsyncMiddleware<HttpReqCtx> for OAuthLayer {
         let client_store = self.client_store.clone();
         let token_manager = self.token_manager.clone();
         let authorizer = self.authorizer.clone();
+        let issuers = self.issuers.clone();
+        let dpop = self.dpop.clone();
+        let pushed_requests = self.pushed_requests.clone();
+        let request_objects = self.request_objects.clone();
+        let par_path = self.par_endpoint.clone();
+        let consent_page = self.consent_page.clone();
+        let scope_descriptions = self.scope_descriptions.clone();
+        let limiter = self.rate_limiter.clone();
         #[cfg(feature = "social")]
         let social_providers: Vec<Arc<dyn crate::social::provider::ExternalLoginProvider>> = vec![];
 
@@ -180,24 +273,24 @@ impl AsyncMiddleware<HttpReqCtx> for OAuthLayer {
             if path_only == authorize_path {
                 let method = req.meta().method();
                 if method == HttpMethod::GET {
-                    // Parse query parameters
-                    let mut params: HashMap<String, String> = HashMap::new();
-                    for pair in query_string.split('&') {
-                        let mut iter = pair.splitn(2, '=');
-                        if let (Some(k), Some(v)) = (iter.next(), iter.next()) {
-                            params.insert(k.to_string(), v.to_string());
+                    // Parse query parameters, or the pushed request or request object they refer to
+                    let params: HashMap<String, String> = QueryMap::parse(query_string)
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect();
+                    let params = match resolve_authorization_request(
+                        params, client_store.as_ref(), pushed_requests.as_ref(), request_objects.as_ref(),
+                    ).await {
+                        Ok(params) => params,
+                        Err(err) => {
+                            req.response = err.into_response();
+                            return req;
                         }
-                    }
+                    };
                     let client_id = params.get("client_id").cloned().unwrap_or_default();
                     // Rate limit GET authorize per client_id
-                    {
-                        let map_mutex = RATE_LIMITERS.get_or_init(|| Mutex::new(HashMap::new()));
-                        let mut map = map_mutex.lock().unwrap();
-                        let bucket = map.entry(client_id.clone()).or_insert_with(|| TokenBucket::new(10, 10.0 / 60.0));
-                        if !bucket.consume(1) {
-                            req.response = return_status(StatusCode::TOO_MANY_REQUESTS);
-                            return req;
-                        }
+                    if rate_limited(&limiter, &mut req, &format!("authorize:{}", client_id)).await {
+                        return req;
                     }
                     let redirect_uri = params.get("redirect_uri").cloned().unwrap_or_default();
                     let response_type = params.get("response_type").cloned().unwrap_or_default();
@@ -218,31 +311,48 @@ impl AsyncMiddleware<HttpReqCtx> for OAuthLayer {
                     }
                     let code_challenge = code_challenge_opt.unwrap_or_default();
 
+                    // Skip the consent page if the user already granted the scopes to the client
+                    let scopes = parse_scopes(&scope);
+                    let user_id = req.user_id();
+                    let prompt_consent = params.get("prompt").is_some_and(|prompt| prompt.split(' ').any(|p| p == "consent"));
+                    if let (Some(user), false) = (&user_id, prompt_consent)
+                        && authorizer.check_consent(&client_id, user, &scopes).await.unwrap_or(false)
+                        && client.redirect_uris.contains(&redirect_uri)
+                    {
+                        req.params.set(ConsentGrant {
+                            client_id,
+                            user_id: user_id.clone(),
+                            scopes,
+                            redirect_uri,
+                            state: Some(state).filter(|state| !state.is_empty()),
+                            remembered: true,
+                        });
+                        return next(req).await;
+                    }
+
                     // Generate CSRF token and render consent form
                     let csrf_token = Uuid::new_v4().to_string();
-                    let html = format!(r#"<!DOCTYPE html>
-<html><body>
-<h1>Authorize access for client {}</h1>
-<form method=\"POST\" action=\"{}\"> 
-    <input type=\"hidden\" name=\"csrf_token\" value=\"{}\" />
-    <input type=\"hidden\" name=\"client_id\" value=\"{}\" />
-    <input type=\"hidden\" name=\"redirect_uri\" value=\"{}\" />
-    <input type=\"hidden\" name=\"response_type\" value=\"{}\" />
-    <input type=\"hidden\" name=\"scope\" value=\"{}\" />
-    <input type=\"hidden\" name=\"state\" value=\"{}\" />
-    <input type=\"hidden\" name=\"code_challenge\" value=\"{}\" />
-    <input type=\"hidden\" name=\"code_challenge_method\" value=\"{}\" />
-    <p>Requested scopes: {}</p>
-    <button type=\"submit\" name=\"action\" value=\"approve\">Approve</button>
-    <button type=\"submit\" name=\"action\" value=\"deny\">Deny</button>
-</form>
-</body></html>"#,
-                        client_id, authorize_path, csrf_token, client_id,
-                        redirect_uri, response_type, scope, state,
-                        code_challenge, code_challenge_method, scope
-                    );
-
-                    req.response = html_response(html)
+                    let fields = vec![
+                        ("csrf_token".to_string(), csrf_token.clone()),
+                        ("client_id".to_string(), client_id.clone()),
+                        ("redirect_uri".to_string(), redirect_uri),
+                        ("response_type".to_string(), response_type),
+                        ("scope".to_string(), scope),
+                        ("state".to_string(), state),
+                        ("code_challenge".to_string(), code_challenge),
+                        ("code_challenge_method".to_string(), code_challenge_method),
+                    ];
+                    let consent = ConsentRequest {
+                        client_id,
+                        user_id,
+                        scopes: scopes
+                            .iter()
+                            .map(|scope| ScopeItem { name: scope.clone(), description: scope_descriptions.get(scope) })
+                            .collect(),
+                        action: authorize_path.clone(),
+                        fields,
+                    };
+                    req.response = consent_page.render(&consent)
                         .add_cookie("csrf_token", Cookie::new(csrf_token).path("/").secure(true).http_only(true));
                     return req;
                 } else if method == HttpMethod::POST {
@@ -272,10 +382,64 @@ impl AsyncMiddleware<HttpReqCtx> for OAuthLayer {
                         return req;
                     }
                     // CSRF and PKCE validated; proceed to consent processing
+                    let (approved, remember, redirect_uri, scope, state) = {
+                        let f = req.form_or_default().await;
+                        (
+                            f.get("action").is_some_and(|action| action == "approve"),
+                            f.get("remember").is_some_and(|remember| remember == "1"),
+                            f.get("redirect_uri").cloned().unwrap_or_default(),
+                            f.get("scope").cloned().unwrap_or_default(),
+                            f.get("state").cloned().filter(|state| !state.is_empty()),
+                        )
+                    };
+                    if !client_post.redirect_uris.contains(&redirect_uri) {
+                        req.response = return_status(StatusCode::BAD_REQUEST);
+                        return req;
+                    }
+                    if !approved {
+                        let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+                        let mut location = format!("{}{}error=access_denied", redirect_uri, separator);
+                        if let Some(state) = &state {
+                            location.push_str(&format!("&state={}", encode_url_owned(state)));
+                        }
+                        req.response = redirect_response(&location);
+                        return req;
+                    }
+                    let scopes = parse_scopes(&scope);
+                    let user_id = req.user_id();
+                    if let (Some(user), true) = (&user_id, remember)
+                        && let Err(err) = authorizer.record_consent(&client_id_post, user, &scopes).await
+                    {
+                        req.response = err.into_response();
+                        return req;
+                    }
+                    req.params.set(ConsentGrant {
+                        client_id: client_id_post,
+                        user_id,
+                        scopes,
+                        redirect_uri,
+                        state,
+                        remembered: false,
+                    });
                 } else {
                     req.response = return_status(StatusCode::METHOD_NOT_ALLOWED);
                     return req;
                 }
+            } else if let Some(pushed) = pushed_requests.as_ref().filter(|_| path_only == par_path) {
+                if req.meta().method() != HttpMethod::POST {
+                    req.response = return_status(StatusCode::METHOD_NOT_ALLOWED);
+                    return req;
+                }
+                req.response = match push_authorization_request(&mut req, client_store.as_ref(), pushed, request_objects.as_ref()).await {
+                    Ok((request_uri, expires_in)) => {
+                        let body = serde_json::json!({ "request_uri": request_uri, "expires_in": expires_in });
+                        normal_response(StatusCode::CREATED, serde_json::to_vec(&body).unwrap_or_default())
+                            .content_type(HttpContentType::ApplicationJson())
+                            .add_header("cache-control", "no-store")
+                    }
+                    Err(err) => err.into_response(),
+                };
+                return req;
             } else if path_only == token_path {
                 // Obtain client IP from X-Forwarded-For header (populated by Nginx real_ip module)
                 let ip = if let Some(hv) = req.meta().header.get("x-forwarded-for") {
@@ -284,32 +448,61 @@ impl AsyncMiddleware<HttpReqCtx> for OAuthLayer {
                     "unknown".to_string()
                 };
                 // Rate limit token endpoint per client IP
-                {
-                    let map_mutex = RATE_LIMITERS.get_or_init(|| Mutex::new(HashMap::new()));
-                    let mut map = map_mutex.lock().unwrap();
-                    let bucket = map.entry(ip).or_insert_with(|| TokenBucket::new(10, 10.0 / 60.0));
-                    if !bucket.consume(1) {
-                        req.response = return_status(StatusCode::TOO_MANY_REQUESTS);
-                        return req;
+                if rate_limited(&limiter, &mut req, &format!("token:{}", ip)).await {
+                    return req;
+                }
+                if let Some(dpop) = &dpop {
+                    match dpop.check_token_request(&mut req) {
+                        Ok(Some(proof)) => req.params.set(proof),
+                        Ok(None) => {}
+                        Err(err) => {
+                            req.response = err.into_response();
+                            return req;
+                        }
                     }
                 }
                 // TODO: implement token endpoint logic
             } else {
                 // Protected: validate Bearer/JWT token and inject OAuthContext
-                let token_opt = req.meta().header.get("authorization")
-                    .map(|hv| hv.as_str())
-                    .and_then(|s| s.strip_prefix("Bearer ").map(str::to_string));
+                let (token_opt, dpop_scheme) = match req.meta().get_authorization() {
+                    Some(Authorization::Bearer(token)) => (Some(token), false),
+                    Some(Authorization::Other { scheme, credentials })
+                        if dpop.is_some() && scheme.eq_ignore_ascii_case("dpop") => (Some(credentials), true),
+                    _ => (None, false),
+                };
                 let token_str = if let Some(t) = token_opt {
                     t
                 } else {
                     req.response = return_status(StatusCode::UNAUTHORIZED);
                     return req;
                 };
-                let token_res = token_manager.validate_token(&token_str).await;
-                let token = if let Ok(tok) = token_res { tok } else {
+                let token_res = match &issuers {
+                    Some(issuers) => {
+                        let host = req.meta().get_host();
+                        issuers.validate(&token_str, host.as_deref()).await
+                            .map(|(tenant, token)| (Some(tenant), token))
+                    }
+                    None => token_manager.validate_token(&token_str).await.map(|token| (None, token)),
+                };
+                let (tenant, token) = if let Ok(validated) = token_res { validated } else {
                     req.response = return_status(StatusCode::UNAUTHORIZED);
                     return req;
                 };
+                if let Some(dpop) = &dpop {
+                    match dpop.check_resource(&mut req, &token_str, &token, dpop_scheme) {
+                        Ok(Some(proof)) => req.params.set(proof),
+                        Ok(None) => {}
+                        Err(err) => {
+                            let error = if matches!(err, OAuthError::InvalidDpopProof) { "invalid_dpop_proof" } else { "invalid_token" };
+                            req.response = return_status(StatusCode::UNAUTHORIZED)
+                                .add_header("www-authenticate", format!("DPoP error=\"{}\"", error));
+                            return req;
+                        }
+                    }
+                }
+                if let Some(tenant) = tenant {
+                    req.params.set(tenant);
+                }
                 let scopes = token.scope.clone()
                     .map(|s| s.split(' ').map(str::to_string).collect())
                     .unwrap_or_default();
@@ -325,4 +518,66 @@ impl AsyncMiddleware<HttpReqCtx> for OAuthLayer {
             next(req).await
         })
     }
-}
\ No newline at end of file
+}
+
+/// The parameters of an authorization request: the ones of its pushed request if it has a
+/// `request_uri`, of its request object if it has a `request`, or its own
+async fn resolve_authorization_request(
+    params: HashMap<String, String>,
+    client_store: &dyn ClientStore,
+    pushed: Option<&PushedRequests>,
+    request_objects: Option<&RequestObjectVerifier>,
+) -> Result<HashMap<String, String>, OAuthError> {
+    let client_id = params.get("client_id").cloned().unwrap_or_default();
+    if let Some(request_uri) = params.get("request_uri") {
+        return pushed.ok_or(OAuthError::InvalidRequestUri)?.take(&client_id, request_uri);
+    }
+    if pushed.is_some_and(PushedRequests::is_required) {
+        return Err(OAuthError::InvalidRequest);
+    }
+    if let Some(request) = params.get("request") {
+        let verifier = request_objects.ok_or(OAuthError::InvalidRequestObject)?;
+        let client = client_store.get_client(&client_id).await?;
+        return verifier.verify(request, &client).await;
+    }
+    Ok(params)
+}
+
+/// Authenticates the client of a pushed authorization request and stores its parameters
+async fn push_authorization_request(
+    req: &mut HttpReqCtx,
+    client_store: &dyn ClientStore,
+    pushed: &PushedRequests,
+    request_objects: Option<&RequestObjectVerifier>,
+) -> Result<(String, u64), OAuthError> {
+    let basic = match req.meta().get_authorization() {
+        Some(Authorization::Basic { user, pass }) => Some((user, pass)),
+        _ => None,
+    };
+    let mut params = req.form_or_default().await.data.clone();
+    let form_secret = params.remove("client_secret");
+    let (client_id, secret) = match basic {
+        Some((user, pass)) => (user, Some(pass)),
+        None => (params.get("client_id").cloned().unwrap_or_default(), form_secret),
+    };
+    let client = client_store.get_client(&client_id).await?;
+    if let Some(expected) = &client.secret {
+        let secret = secret.ok_or(OAuthError::InvalidClient)?;
+        if !constant_eq(expected.as_bytes(), secret.as_bytes()) {
+            return Err(OAuthError::InvalidClient);
+        }
+    }
+    if params.contains_key("request_uri") {
+        return Err(OAuthError::InvalidRequest);
+    }
+    if let Some(request) = params.get("request") {
+        let verifier = request_objects.ok_or(OAuthError::InvalidRequestObject)?;
+        params = verifier.verify(request, &client).await?;
+    }
+    params.insert("client_id".to_string(), client.id.clone());
+    let redirect_uri = params.get("redirect_uri").ok_or(OAuthError::InvalidRequest)?;
+    if !client.redirect_uris.contains(redirect_uri) {
+        return Err(OAuthError::InvalidRequest);
+    }
+    Ok(pushed.push(client.id, params))
+}
@@ -4,6 +4,11 @@ pub mod memory;
 pub mod middleware;
 pub mod jwt;
 pub mod jwks;
+pub mod issuers;
+pub mod dpop;
+pub mod par;
+pub mod jar;
+pub mod consent;
 pub mod db;
 pub mod cookie;
 pub mod crypto;
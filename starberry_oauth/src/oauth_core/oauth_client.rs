@@ -1,12 +1,12 @@
 use uuid::Uuid;
-use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use base64::Engine;
+use starberry_lib::encoding::base64_url_encode;
 use ring::rand::{SecureRandom, SystemRandom};
 use starberry_lib::url_encoding::encode_url_owned;
 use super::crypto::pkce_code_challenge;
 use starberry_core::http::http_value::HttpMethod;
 use super::http_client::{OAuthHttpClient, HttpRequest, HttpResponse, RedirectPolicy};
 use super::types::{Token, OAuthError, TokenModel};
+use super::dpop::DpopKey;
 use serde_json;
 use tracing::{instrument, debug};
 
@@ -29,6 +29,8 @@ pub struct OAuthClient {
     pub code_verifier: String,
     /// PKCE code challenge derived from the verifier.
     pub code_challenge: String,
+    /// Key signing the DPoP proofs, binding the tokens to this client.
+    pub dpop: Option<DpopKey>,
 }
 
 impl OAuthClient {
@@ -52,7 +54,7 @@ impl OAuthClient {
         let rng = SystemRandom::new();
         let mut buf = [0u8; 32];
         rng.fill(&mut buf).expect("PKCE code verifier generation failed");
-        let code_verifier = URL_SAFE_NO_PAD.encode(&buf);
+        let code_verifier = base64_url_encode(buf);
         // Generate PKCE challenge
         let code_challenge = pkce_code_challenge(&code_verifier);
 
@@ -65,6 +67,22 @@ impl OAuthClient {
             state,
             code_verifier,
             code_challenge,
+            dpop: None,
+        }
+    }
+
+    /// Requests tokens bound by DPoP to the key, which must then be used with `dpop_proof`.
+    pub fn with_dpop(mut self, key: DpopKey) -> Self {
+        self.dpop = Some(key);
+        self
+    }
+
+    /// Creates the DPoP proof of a request, `None` without a DPoP key. `access_token` is given
+    /// for the requests to protected resources, sent with the `DPoP` authorization scheme.
+    pub fn dpop_proof(&self, method: HttpMethod, url: &str, access_token: Option<&str>) -> Result<Option<String>, OAuthError> {
+        match &self.dpop {
+            Some(key) => key.proof(&method.to_string(), url, access_token, None).map(Some),
+            None => Ok(None),
         }
     }
 
@@ -133,18 +151,23 @@ impl OAuthClient {
             .join("&")
             .into_bytes();
 
-        let request = HttpRequest {
-            method: HttpMethod::POST,
-            url: self.token_url.clone(),
-            headers: vec![("Content-Type".to_string(), "application/x-www-form-urlencoded".to_string())],
-            body: Some(body_bytes),
-            timeout: None,
-            redirect_policy: RedirectPolicy::None,
-        };
-        let response = http_client
-            .execute(request)
+        let mut response = http_client
+            .execute(self.token_request(body_bytes.clone(), None)?)
             .await
             .map_err(|_| OAuthError::ServerError)?;
+        // The server may require a nonce in the DPoP proofs, given with the first error
+        if self.dpop.is_some() && response.status == 400 {
+            let nonce = response.headers.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("dpop-nonce"))
+                .map(|(_, value)| value.clone());
+            if let Some(nonce) = nonce {
+                debug!("Retrying token exchange with the DPoP nonce");
+                response = http_client
+                    .execute(self.token_request(body_bytes, Some(&nonce))?)
+                    .await
+                    .map_err(|_| OAuthError::ServerError)?;
+            }
+        }
         debug!(status=response.status, response_body=?String::from_utf8_lossy(response.body.as_ref()), "Token endpoint response");
         if response.status != 200 {
             debug!(status=response.status, "Token endpoint returned non-200 status");
@@ -156,8 +179,14 @@ impl OAuthClient {
         let refresh_token = v.get("refresh_token").and_then(|t| t.as_str()).map(|s| s.to_string());
         let expires_in = v.get("expires_in").and_then(|t| t.as_u64()).unwrap_or(0);
         let scope = v.get("scope").and_then(|t| t.as_str()).map(|s| s.to_string());
+        let token_type = v.get("token_type").and_then(|t| t.as_str()).unwrap_or_default();
+        let model = match &self.dpop {
+            Some(key) if token_type.eq_ignore_ascii_case("dpop") => TokenModel::DPoP { jkt: key.thumbprint().to_string() },
+            // A server without DPoP support issues a bearer token instead
+            _ => TokenModel::BearerOpaque,
+        };
         Ok(Token {
-            model: TokenModel::BearerOpaque,
+            model,
             access_token,
             refresh_token,
             expires_in,
@@ -165,4 +194,20 @@ impl OAuthClient {
             id_token: None,
         })
     }
-} 
\ No newline at end of file
+
+    /// The request to the token endpoint, with a DPoP proof if the client has a key.
+    fn token_request(&self, body: Vec<u8>, nonce: Option<&str>) -> Result<HttpRequest, OAuthError> {
+        let mut headers = vec![("Content-Type".to_string(), "application/x-www-form-urlencoded".to_string())];
+        if let Some(key) = &self.dpop {
+            headers.push(("DPoP".to_string(), key.proof("POST", &self.token_url, None, nonce)?));
+        }
+        Ok(HttpRequest {
+            method: HttpMethod::POST,
+            url: self.token_url.clone(),
+            headers,
+            body: Some(body),
+            timeout: None,
+            redirect_policy: RedirectPolicy::None,
+        })
+    }
+}
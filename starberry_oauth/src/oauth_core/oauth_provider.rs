@@ -7,6 +7,7 @@ use std::future::Future;
 use super::types::{Client, Grant, Token, OAuthError};
 use async_trait::async_trait;
 use super::types::UserContext;
+use super::consent::GrantRecord;
 
 /// Trait for retrieving OAuth2 clients.
 #[async_trait]
@@ -36,6 +37,16 @@ pub trait Authorizer: Send + Sync + 'static {
 
     /// Checks if the given client and user have consent for the specified scopes asynchronously.
     async fn check_consent(&self, client_id: &str, user_id: &str, scopes: &[String]) -> Result<bool, OAuthError>;
+
+    /// Lists the consents remembered for the user, one per client, asynchronously.
+    async fn list_grants(&self, _user_id: &str) -> Result<Vec<GrantRecord>, OAuthError> {
+        Ok(Vec::new())
+    }
+
+    /// Forgets the consent of the user to the client asynchronously, returning whether there was one.
+    async fn revoke_grant(&self, _client_id: &str, _user_id: &str) -> Result<bool, OAuthError> {
+        Ok(false)
+    }
 }
 
 /// Trait to abstract storage operations for access/refresh tokens, PKCE verifiers, and CSRF states.
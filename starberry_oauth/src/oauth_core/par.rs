@@ -0,0 +1,114 @@
+//! Pushed authorization requests, RFC 9126.
+//!
+//! The client posts the parameters of its authorization request to the pushed authorization
+//! endpoint, authenticated, and only sends the returned `request_uri` through the browser.
+//! Each `request_uri` can be used once, by the client that pushed it, before it expires.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use super::types::OAuthError;
+
+/// The prefix of the `request_uri` given to the clients
+pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+struct PushedRequest {
+    client_id: String,
+    params: HashMap<String, String>,
+    expires: Instant,
+}
+
+/// The authorization requests pushed by the clients, waiting to be used
+#[derive(Clone)]
+pub struct PushedRequests {
+    ttl: Duration,
+    required: bool,
+    requests: Arc<DashMap<String, PushedRequest>>,
+}
+
+impl PushedRequests {
+    /// Keeps the pushed requests for 60 seconds
+    pub fn new() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            required: false,
+            requests: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Sets how long a pushed request can be used
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Rejects the authorization requests which were not pushed, as required by FAPI 2.0
+    pub fn require(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Stores the parameters of a request of the client, returning its `request_uri` and the
+    /// seconds before it expires
+    pub fn push(&self, client_id: impl Into<String>, params: HashMap<String, String>) -> (String, u64) {
+        let now = Instant::now();
+        self.requests.retain(|_, request| request.expires > now);
+        let request_uri = format!("{}{}", REQUEST_URI_PREFIX, Uuid::new_v4().simple());
+        self.requests.insert(request_uri.clone(), PushedRequest {
+            client_id: client_id.into(),
+            params,
+            expires: now + self.ttl,
+        });
+        (request_uri, self.ttl.as_secs())
+    }
+
+    /// Takes the parameters of a pushed request, which must have been pushed by the client
+    pub fn take(&self, client_id: &str, request_uri: &str) -> Result<HashMap<String, String>, OAuthError> {
+        let (_, request) = self.requests
+            .remove_if(request_uri, |_, request| request.client_id == client_id)
+            .ok_or(OAuthError::InvalidRequestUri)?;
+        if request.expires <= Instant::now() {
+            return Err(OAuthError::InvalidRequestUri);
+        }
+        Ok(request.params)
+    }
+}
+
+impl Default for PushedRequests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pushed_requests_are_used_once() {
+        let pushed = PushedRequests::new();
+        let params = HashMap::from([("scope".to_string(), "openid".to_string())]);
+        let (request_uri, expires_in) = pushed.push("client1", params);
+        assert!(request_uri.starts_with(REQUEST_URI_PREFIX));
+        assert_eq!(expires_in, 60);
+        assert!(pushed.take("client2", &request_uri).is_err());
+        let params = pushed.take("client1", &request_uri).unwrap();
+        assert_eq!(params.get("scope").map(String::as_str), Some("openid"));
+        assert!(matches!(pushed.take("client1", &request_uri), Err(OAuthError::InvalidRequestUri)));
+    }
+
+    #[test]
+    fn test_pushed_requests_expire() {
+        let pushed = PushedRequests::new().ttl(Duration::ZERO);
+        let (request_uri, _) = pushed.push("client1", HashMap::new());
+        assert!(pushed.take("client1", &request_uri).is_err());
+    }
+}
@@ -1,6 +1,9 @@
 //! Rate limiting abstraction for OAuth middleware.
 
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use async_trait::async_trait;
+use starberry_core::cache::SharedCache;
 use super::types::OAuthError;
 
 /// Trait for rate limiting by a given key (e.g., client ID or IP address).
@@ -9,4 +12,103 @@ pub trait RateLimiter: Send + Sync + 'static {
     /// Attempts to consume one token for the specified key.
     /// Returns Ok(true) if allowed, Ok(false) if rate-limited, or Err on internal error.
     async fn consume(&self, key: &str) -> Result<bool, OAuthError>;
-} 
\ No newline at end of file
+}
+
+/// A token bucket per key, kept in a `Cache`.
+///
+/// The instances of an application sharing a cache backend such as Redis share their limits.
+/// The cache has no atomic update, so concurrent requests for the same key may read the same
+/// bucket and a burst can go slightly over the capacity.
+#[derive(Clone)]
+pub struct CacheRateLimiter {
+    cache: SharedCache,
+    prefix: String,
+    capacity: u32,
+    /// The time to refill the whole bucket
+    period: Duration,
+}
+
+impl CacheRateLimiter {
+    /// Creates a limiter allowing bursts of 10 requests per key, refilled over a minute.
+    pub fn new(cache: SharedCache) -> Self {
+        Self {
+            cache,
+            prefix: "rate_limit:".to_string(),
+            capacity: 10,
+            period: Duration::from_secs(60),
+        }
+    }
+
+    /// Allows bursts of `capacity` requests per key, refilled over `period`.
+    pub fn limit(mut self, capacity: u32, period: Duration) -> Self {
+        self.capacity = capacity;
+        self.period = period;
+        self
+    }
+
+    /// Sets the prefix of the cache keys, `rate_limit:` by default.
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+#[async_trait]
+impl RateLimiter for CacheRateLimiter {
+    async fn consume(&self, key: &str) -> Result<bool, OAuthError> {
+        let key = format!("{}{}", self.prefix, key);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let capacity = self.capacity as f64;
+        let refill_per_ms = capacity / self.period.as_millis().max(1) as f64;
+        let stored = self.cache.get_string(&key).await.map_err(|_| OAuthError::ServerError)?;
+        // The bucket is stored as "<tokens> <last update in unix milliseconds>"
+        let tokens = stored
+            .as_deref()
+            .and_then(|stored| stored.split_once(' '))
+            .and_then(|(tokens, last)| Some((tokens.parse::<f64>().ok()?, last.parse::<u64>().ok()?)))
+            .map(|(tokens, last)| (tokens + now.saturating_sub(last) as f64 * refill_per_ms).min(capacity))
+            .unwrap_or(capacity);
+        let allowed = tokens >= 1.0;
+        let tokens = if allowed { tokens - 1.0 } else { tokens };
+        // A bucket left alone for a period is full again, the entry can expire
+        self.cache
+            .set_string(&key, &format!("{} {}", tokens, now), Some(self.period))
+            .await
+            .map_err(|_| OAuthError::ServerError)?;
+        Ok(allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use starberry_core::cache::MemoryCache;
+
+    #[tokio::test]
+    async fn limits_each_key() {
+        let limiter = CacheRateLimiter::new(Arc::new(MemoryCache::new(16))).limit(2, Duration::from_secs(3600));
+        assert!(limiter.consume("a").await.unwrap());
+        assert!(limiter.consume("a").await.unwrap());
+        assert!(!limiter.consume("a").await.unwrap());
+        assert!(limiter.consume("b").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn buckets_refill() {
+        let limiter = CacheRateLimiter::new(Arc::new(MemoryCache::new(16))).limit(1, Duration::from_millis(50));
+        assert!(limiter.consume("a").await.unwrap());
+        assert!(!limiter.consume("a").await.unwrap());
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(limiter.consume("a").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn limiters_sharing_a_cache_share_their_buckets() {
+        let cache: SharedCache = Arc::new(MemoryCache::new(16));
+        let first = CacheRateLimiter::new(cache.clone()).limit(1, Duration::from_secs(3600));
+        let second = CacheRateLimiter::new(cache).limit(1, Duration::from_secs(3600));
+        assert!(first.consume("client").await.unwrap());
+        assert!(!second.consume("client").await.unwrap());
+    }
+}
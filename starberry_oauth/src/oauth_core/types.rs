@@ -9,6 +9,8 @@ pub struct Client {
     pub secret: Option<String>,
     /// Allowed redirect URIs.
     pub redirect_uris: Vec<String>,
+    /// URIs the user may be sent to after an RP-initiated logout.
+    pub post_logout_redirect_uris: Vec<String>,
 }
 
 /// OAuth2 grant types.
@@ -58,6 +60,8 @@ pub enum TokenModel {
     BearerOpaque,
     /// JSON Web Tokens with a signing algorithm.
     JWT { algorithm: JWTAlgorithm },
+    /// Tokens bound by DPoP to a key of the client, by its JWK thumbprint.
+    DPoP { jkt: String },
 }
 
 /// OAuth2 token representation.
@@ -98,6 +102,14 @@ pub enum OAuthError {
     HttpError(String),
     /// Client is not authorized to access this resource.
     Unauthorized,
+    /// The request is missing a parameter or is otherwise malformed.
+    InvalidRequest,
+    /// The request object of the authorization request is invalid.
+    InvalidRequestObject,
+    /// The `request_uri` of the authorization request is unknown, expired or already used.
+    InvalidRequestUri,
+    /// The DPoP proof is missing, malformed or does not match the request or the token.
+    InvalidDpopProof,
     /// Generic server-side error.
     ServerError,
 }
@@ -148,6 +160,10 @@ impl OAuthError {
             OAuthError::RateLimited => (StatusCode::TOO_MANY_REQUESTS, "rate_limited", "Too many requests"),
             OAuthError::HttpError(err) => (StatusCode::BAD_GATEWAY, "http_error", err.as_str()),
             OAuthError::Unauthorized => (StatusCode::FORBIDDEN, "unauthorized_client", "Client not authorized"),
+            OAuthError::InvalidRequest => (StatusCode::BAD_REQUEST, "invalid_request", "The request is invalid"),
+            OAuthError::InvalidRequestObject => (StatusCode::BAD_REQUEST, "invalid_request_object", "The request object is invalid"),
+            OAuthError::InvalidRequestUri => (StatusCode::BAD_REQUEST, "invalid_request_uri", "The request_uri is invalid or expired"),
+            OAuthError::InvalidDpopProof => (StatusCode::BAD_REQUEST, "invalid_dpop_proof", "The DPoP proof is invalid"),
             OAuthError::ServerError => (StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Internal server error"),
         };
         // Structured log
@@ -0,0 +1,271 @@
+//! The endpoints of an OpenID Connect provider serving its users: UserInfo, end session and
+//! check session iframe.
+//!
+//! # Example
+//! ```rust,ignore
+//! pub static APP: SApp = Lazy::new(|| App::new().build());
+//!
+//! OidcEndpoints::new(token_manager, client_store, Arc::new(Users::new(pool)))
+//!     .id_token_verifier(Jwt::rs256_verifier(public_key)?.issuer("https://auth.example.com"))
+//!     .on_logout(|req, _logout| {
+//!         req.session().clear();
+//!     })
+//!     .register(&APP);
+//! ```
+
+use std::sync::Arc;
+
+use starberry_core::app::application::App;
+use starberry_core::app::urls::PathPattern;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::http_value::{HttpContentType, HttpMethod, StatusCode};
+use starberry_core::http::response::HttpResponse;
+use starberry_core::http::response::response_templates::{html_response, normal_response, redirect_response, return_status};
+use starberry_lib::jwt::Jwt;
+
+use crate::oauth_core::consent::escape_html;
+use crate::oauth_core::oauth_provider::{ClientStore, TokenManager};
+use crate::oauth_core::types::{parse_scopes, OAuthError};
+use super::session::{browser_state_cookie, check_session_iframe, new_browser_state, LogoutRequest, BROWSER_STATE_COOKIE};
+use super::userinfo::{scoped_claims, UserInfoProvider};
+
+/// Called on each logout with the request, once the response is set, so the hook can end the
+/// session of the user and clear its cookies
+pub type LogoutHook = Arc<dyn Fn(&mut HttpReqCtx, &LogoutRequest) + Send + Sync>;
+
+/// The UserInfo, end session and check session endpoints, registered with `register`
+#[derive(Clone)]
+pub struct OidcEndpoints {
+    token_manager: Arc<dyn TokenManager>,
+    client_store: Arc<dyn ClientStore>,
+    userinfo: Arc<dyn UserInfoProvider>,
+    on_logout: Option<LogoutHook>,
+    id_token_verifier: Option<Jwt>,
+    userinfo_path: String,
+    end_session_path: String,
+    check_session_path: String,
+}
+
+impl OidcEndpoints {
+    /// The endpoints validating the access tokens with the token manager, finding the clients in
+    /// the store and loading the claims of the users with the provider
+    pub fn new(
+        token_manager: Arc<dyn TokenManager>,
+        client_store: Arc<dyn ClientStore>,
+        userinfo: Arc<dyn UserInfoProvider>,
+    ) -> Self {
+        Self {
+            token_manager,
+            client_store,
+            userinfo,
+            on_logout: None,
+            id_token_verifier: None,
+            userinfo_path: "/oauth/userinfo".into(),
+            end_session_path: "/oauth/logout".into(),
+            check_session_path: "/oauth/check_session".into(),
+        }
+    }
+
+    /// Sets the hook ending the session of the user at the OP
+    pub fn on_logout<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut HttpReqCtx, &LogoutRequest) + Send + Sync + 'static,
+    {
+        self.on_logout = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets the `Jwt` verifying the ID token hints of the logout requests, with the key and the
+    /// issuer of the ID tokens of the provider. Without it, every logout is confirmed by the user
+    pub fn id_token_verifier(mut self, verifier: Jwt) -> Self {
+        self.id_token_verifier = Some(verifier);
+        self
+    }
+
+    /// Sets the path of the UserInfo endpoint, `/oauth/userinfo` by default
+    pub fn userinfo_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.userinfo_path = path.into();
+        self
+    }
+
+    /// Sets the path of the end session endpoint, `/oauth/logout` by default
+    pub fn end_session_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.end_session_path = path.into();
+        self
+    }
+
+    /// Sets the path of the check session iframe, `/oauth/check_session` by default
+    pub fn check_session_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.check_session_path = path.into();
+        self
+    }
+
+    /// Registers the routes of the endpoints on the application
+    pub fn register(self, app: &Arc<App>) {
+        let endpoints = Arc::new(self);
+        let userinfo = endpoints.clone();
+        app.reg_from::<HttpReqCtx>(&segments(&endpoints.userinfo_path))
+            .set_method(Arc::new(move |req: HttpReqCtx| {
+                let endpoints = userinfo.clone();
+                async move { endpoints.userinfo(req).await }
+            }));
+        let end_session = endpoints.clone();
+        app.reg_from::<HttpReqCtx>(&segments(&endpoints.end_session_path))
+            .set_method(Arc::new(move |req: HttpReqCtx| {
+                let endpoints = end_session.clone();
+                async move { endpoints.end_session(req).await }
+            }));
+        app.reg_from::<HttpReqCtx>(&segments(&endpoints.check_session_path))
+            .set_method(Arc::new(|mut req: HttpReqCtx| async move {
+                req.response = html_response(check_session_iframe());
+                req
+            }));
+    }
+
+    /// Answers a UserInfo request, authorized by a bearer token in the Authorization header or,
+    /// for a POST, in the `access_token` form field. The token must have the `openid` scope
+    pub async fn userinfo(&self, mut req: HttpReqCtx) -> HttpReqCtx {
+        let mut bearer = req.meta().get_authorization()
+            .and_then(|auth| auth.bearer_token().map(str::to_string));
+        if bearer.is_none() && req.method() == HttpMethod::POST {
+            bearer = req.form().await.and_then(|form| form.get("access_token").cloned());
+        }
+        let Some(bearer) = bearer else {
+            req.response = return_status(StatusCode::UNAUTHORIZED).add_header("www-authenticate", "Bearer");
+            return req;
+        };
+        let token = match self.token_manager.validate_token(&bearer).await {
+            Ok(token) => token,
+            Err(_) => {
+                req.response = bearer_error(OAuthError::InvalidToken);
+                return req;
+            }
+        };
+        let scopes = parse_scopes(token.scope.as_deref().unwrap_or_default());
+        if !scopes.iter().any(|scope| scope == "openid") {
+            req.response = bearer_error(OAuthError::InsufficientScopes);
+            return req;
+        }
+        let claims = match self.userinfo.claims(&token).await {
+            Ok(Some(claims)) if claims.get("sub").is_some_and(|sub| sub.is_string()) => claims,
+            Ok(Some(_)) => {
+                req.response = OAuthError::ServerError.into_response();
+                return req;
+            }
+            Ok(None) => {
+                req.response = bearer_error(OAuthError::InvalidToken);
+                return req;
+            }
+            Err(err) => {
+                req.response = err.into_response();
+                return req;
+            }
+        };
+        let body = serde_json::to_vec(&scoped_claims(&claims, &scopes)).unwrap_or_default();
+        req.response = normal_response(StatusCode::OK, body)
+            .content_type(HttpContentType::ApplicationJson())
+            .add_header("cache-control", "no-store");
+        req
+    }
+
+    /// Answers an RP-initiated logout, with its parameters in the query or, for a POST, in the
+    /// form. The user is redirected to the `post_logout_redirect_uri` if registered by the
+    /// client, or shown a signed out page. The browser state is renewed, so the check session
+    /// iframes of the RPs see the logout
+    ///
+    /// A request without a valid ID token hint could come from any page, so the user is asked
+    /// to confirm the logout with a form, posted back with the browser state as its token
+    pub async fn end_session(&self, mut req: HttpReqCtx) -> HttpReqCtx {
+        let (mut logout, confirmation) = if req.method() == HttpMethod::POST {
+            match req.form().await {
+                Some(form) => (LogoutRequest::from_params(|name| form.get(name).map(String::as_str)), form.get("confirm").cloned()),
+                None => (LogoutRequest::default(), None),
+            }
+        } else {
+            let query = req.query_map();
+            (LogoutRequest::from_params(|name| query.get(name)), None)
+        };
+        let verified = self.id_token_verifier.as_ref().is_some_and(|verifier| logout.verify_hint(verifier));
+        let browser_state = req.get_cookie(BROWSER_STATE_COOKIE).map(|cookie| cookie.get_value().to_string()).filter(|state| !state.is_empty());
+        let confirmed = confirmation.is_some_and(|confirmation| browser_state.as_ref() == Some(&confirmation));
+        if !verified && !confirmed {
+            let (state, new) = match browser_state {
+                Some(state) => (state, false),
+                None => (new_browser_state(), true),
+            };
+            let mut response = logout_confirmation(&self.end_session_path, &logout, &state).add_header("cache-control", "no-store");
+            if new {
+                response = response.add_cookie(BROWSER_STATE_COOKIE, browser_state_cookie(state));
+            }
+            req.response = response;
+            return req;
+        }
+
+        let mut response = match &logout.post_logout_redirect_uri {
+            Some(_) => {
+                let client = match logout.client() {
+                    Some(client_id) => self.client_store.get_client(&client_id).await.ok(),
+                    None => None,
+                };
+                match client.and_then(|client| logout.redirect_to(&client)) {
+                    Some(location) => redirect_response(&location),
+                    None => {
+                        req.response = normal_response(StatusCode::BAD_REQUEST, "Invalid post_logout_redirect_uri")
+                            .content_type(HttpContentType::TextPlain());
+                        return req;
+                    }
+                }
+            }
+            None => html_response(
+                "<!DOCTYPE html>\n<html><body><h1>You have been signed out</h1></body></html>",
+            ),
+        };
+        response = response
+            .add_cookie(BROWSER_STATE_COOKIE, browser_state_cookie(new_browser_state()))
+            .add_header("cache-control", "no-store");
+        req.response = response;
+        if let Some(hook) = &self.on_logout {
+            hook(&mut req, &logout);
+        }
+        req
+    }
+}
+
+/// The page asking the user to confirm a logout, posting the parameters of the request back
+/// with the browser state, which another site cannot read
+fn logout_confirmation(action: &str, logout: &LogoutRequest, browser_state: &str) -> HttpResponse {
+    let params = [
+        ("client_id", logout.client_id.as_deref()),
+        ("post_logout_redirect_uri", logout.post_logout_redirect_uri.as_deref()),
+        ("state", logout.state.as_deref()),
+        ("confirm", Some(browser_state)),
+    ];
+    let fields: String = params
+        .iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .map(|(name, value)| format!("    <input type=\"hidden\" name=\"{}\" value=\"{}\" />\n", name, escape_html(value)))
+        .collect();
+    html_response(format!(
+        r#"<!DOCTYPE html>
+<html><body>
+<h1>Do you want to sign out?</h1>
+<form method="POST" action="{action}">
+{fields}    <button type="submit">Sign out</button>
+</form>
+</body></html>"#,
+        action = escape_html(action),
+    ))
+}
+
+/// An error of a bearer token, with its `WWW-Authenticate` challenge
+fn bearer_error(error: OAuthError) -> HttpResponse {
+    let challenge = match error {
+        OAuthError::InsufficientScopes => r#"Bearer error="insufficient_scope", scope="openid""#,
+        _ => r#"Bearer error="invalid_token""#,
+    };
+    error.into_response().add_header("www-authenticate", challenge)
+}
+
+fn segments(path: &str) -> Vec<PathPattern> {
+    path.split('/').filter(|s| !s.is_empty()).map(PathPattern::literal_path).collect()
+}
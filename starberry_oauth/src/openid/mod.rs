@@ -1,5 +1,12 @@
 #![cfg(feature = "openid")]
-//! Optional OpenID Connect server support (discovery, JWKS, id_token, userinfo).
+//! Optional OpenID Connect server support (discovery, JWKS, id_token, userinfo, session management).
 
 pub mod discovery;
+pub mod endpoints;
 pub mod oidc_token_manager;
+pub mod session;
+pub mod userinfo;
+
+pub use endpoints::{LogoutHook, OidcEndpoints};
+pub use session::LogoutRequest;
+pub use userinfo::{Claims, UserInfoProvider};
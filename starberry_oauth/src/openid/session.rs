@@ -0,0 +1,163 @@
+//! Session management of OpenID Connect: RP-Initiated Logout 1.0 and Session Management 1.0.
+//!
+//! The OP keeps a browser state in the `op_browser_state` cookie, readable by the script of
+//! the check session iframe. The authorization responses carry a `session_state` computed with
+//! `session_state`, which the iframe of the RP compares to the current browser state to detect
+//! a logout. The browser state must be renewed with `new_browser_state` on each login, and is
+//! renewed by the end session endpoint on logout.
+
+use ring::digest::{digest, SHA256};
+use serde_json::{Map, Value};
+use starberry_core::http::cookie::Cookie;
+use starberry_lib::encoding::base64_url_encode;
+use starberry_lib::jwt::Jwt;
+use starberry_lib::url_encoding::encode_url_owned;
+use uuid::Uuid;
+
+use crate::oauth_core::types::Client;
+
+/// The cookie holding the browser state of the OP
+pub const BROWSER_STATE_COOKIE: &str = "op_browser_state";
+
+/// A random browser state, to be set as the `browser_state_cookie` on login
+pub fn new_browser_state() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// The cookie carrying a browser state. It is not HttpOnly, as the check session iframe reads it
+pub fn browser_state_cookie(state: impl Into<String>) -> Cookie {
+    Cookie::new(state.into()).path("/").secure(true)
+}
+
+/// The `session_state` given to an RP of `origin` in its authorization response, the SHA-256
+/// of the client id, the origin, the browser state and the salt, followed by the salt
+pub fn session_state(client_id: &str, origin: &str, browser_state: &str, salt: &str) -> String {
+    let input = format!("{} {} {} {}", client_id, origin, browser_state, salt);
+    format!("{}.{}", base64_url_encode(digest(&SHA256, input.as_bytes())), salt)
+}
+
+/// A `session_state` with a random salt
+pub fn new_session_state(client_id: &str, origin: &str, browser_state: &str) -> String {
+    let salt = Uuid::new_v4().simple().to_string();
+    session_state(client_id, origin, browser_state, &salt[..16])
+}
+
+/// The page of the check session iframe. It answers the `client_id session_state` messages of
+/// the RP with `unchanged`, `changed` or `error`
+pub fn check_session_iframe() -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>Check session</title></head><body>
+<script>
+function browserState() {{
+  var match = document.cookie.match(/(?:^|;\s*){cookie}=([^;]*)/);
+  return match ? decodeURIComponent(match[1]) : "";
+}}
+function encode(buffer) {{
+  var text = String.fromCharCode.apply(null, new Uint8Array(buffer));
+  return btoa(text).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+}}
+window.addEventListener("message", function (event) {{
+  var parts = typeof event.data === "string" ? event.data.split(" ") : [];
+  var dot = parts.length === 2 ? parts[1].lastIndexOf(".") : -1;
+  if (dot < 0) {{
+    event.source.postMessage("error", event.origin);
+    return;
+  }}
+  var salt = parts[1].substring(dot + 1);
+  var input = parts[0] + " " + event.origin + " " + browserState() + " " + salt;
+  crypto.subtle.digest("SHA-256", new TextEncoder().encode(input)).then(function (hash) {{
+    var state = encode(hash) + "." + salt;
+    event.source.postMessage(state === parts[1] ? "unchanged" : "changed", event.origin);
+  }});
+}}, false);
+</script>
+</body></html>"#,
+        cookie = BROWSER_STATE_COOKIE
+    )
+}
+
+/// The parameters of an RP-initiated logout request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogoutRequest {
+    /// The ID token previously issued to the RP
+    pub id_token_hint: Option<String>,
+    /// The client logging out, required with `post_logout_redirect_uri` and no hint
+    pub client_id: Option<String>,
+    /// Where to send the user after the logout, among the URIs registered by the client
+    pub post_logout_redirect_uri: Option<String>,
+    /// Opaque value returned to the RP with the redirection
+    pub state: Option<String>,
+    /// The claims of the ID token hint, once `verify_hint` checked its signature
+    pub verified_hint: Option<IdTokenHint>,
+}
+
+/// The claims of a verified ID token hint naming the user and the client logging out
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdTokenHint {
+    pub sub: Option<String>,
+    pub aud: Vec<String>,
+}
+
+impl LogoutRequest {
+    /// Reads the request from its parameters, of the query or of the form
+    pub fn from_params<'a>(get: impl Fn(&str) -> Option<&'a str>) -> Self {
+        let param = |name: &str| get(name).filter(|value| !value.is_empty()).map(str::to_string);
+        Self {
+            id_token_hint: param("id_token_hint"),
+            client_id: param("client_id"),
+            post_logout_redirect_uri: param("post_logout_redirect_uri"),
+            state: param("state"),
+            verified_hint: None,
+        }
+    }
+
+    /// Verifies the signature and the issuer of the ID token hint with the `Jwt` of the ID tokens
+    /// of the provider, keeping its claims in `verified_hint`. An expired hint is still accepted,
+    /// as RPs send the ID token they were given at login
+    pub fn verify_hint(&mut self, verifier: &Jwt) -> bool {
+        let verifier = verifier.clone().allow_no_expiry();
+        self.verified_hint = self
+            .id_token_hint
+            .as_deref()
+            .and_then(|hint| verifier.verify::<Map<String, Value>>(hint).ok())
+            .map(|claims| IdTokenHint { sub: claims.sub, aud: claims.aud });
+        self.verified_hint.is_some()
+    }
+
+    /// The `sub` claim of the verified ID token hint
+    pub fn hinted_subject(&self) -> Option<String> {
+        self.verified_hint.as_ref()?.sub.clone()
+    }
+
+    /// The client of the request: `client_id`, or else the audience of the verified ID token
+    /// hint. `None` if both are given and differ
+    pub fn client(&self) -> Option<String> {
+        // A hint for several audiences does not name a client
+        let audience = self.verified_hint.as_ref().and_then(|hint| match hint.aud.as_slice() {
+            [audience] => Some(audience.clone()),
+            _ => None,
+        });
+        match (&self.client_id, audience) {
+            (Some(client_id), Some(audience)) if *client_id != audience => None,
+            (Some(client_id), _) => Some(client_id.clone()),
+            (None, audience) => audience,
+        }
+    }
+
+    /// Where to redirect once logged out: the `post_logout_redirect_uri` if registered by the
+    /// client, with the `state` appended
+    pub fn redirect_to(&self, client: &Client) -> Option<String> {
+        let uri = self.post_logout_redirect_uri.as_ref()?;
+        if !client.post_logout_redirect_uris.contains(uri) {
+            return None;
+        }
+        Some(match &self.state {
+            Some(state) => {
+                let separator = if uri.contains('?') { '&' } else { '?' };
+                format!("{}{}state={}", uri, separator, encode_url_owned(state))
+            }
+            None => uri.clone(),
+        })
+    }
+}
@@ -0,0 +1,85 @@
+//! The UserInfo endpoint of OpenID Connect Core 1.0, section 5.3.
+//!
+//! The claims of the user are loaded by a `UserInfoProvider` and only those granted by the
+//! scopes of the access token are returned: `sub` always, the profile claims with `profile`,
+//! `email` and `email_verified` with `email`, `address` with `address`, and the phone claims
+//! with `phone`.
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use starberry_lib::encoding::base64_url_decode;
+
+use crate::oauth_core::types::{OAuthError, Token, TokenModel, UserContext};
+
+/// The claims of a user, by claim name
+pub type Claims = Map<String, Value>;
+
+/// The standard claims released by each scope, OpenID Connect Core 1.0 section 5.4
+pub const SCOPE_CLAIMS: [(&str, &[&str]); 4] = [
+    (
+        "profile",
+        &[
+            "name", "family_name", "given_name", "middle_name", "nickname", "preferred_username",
+            "profile", "picture", "website", "gender", "birthdate", "zoneinfo", "locale", "updated_at",
+        ],
+    ),
+    ("email", &["email", "email_verified"]),
+    ("address", &["address"]),
+    ("phone", &["phone_number", "phone_number_verified"]),
+];
+
+/// Loads the claims of the user an access token was issued to
+#[async_trait]
+pub trait UserInfoProvider: Send + Sync {
+    /// The claims of the user of a validated token, including `sub`, or `None` if the user
+    /// no longer exists. The subject of a JWT access token is given by `token_subject`
+    async fn claims(&self, token: &Token) -> Result<Option<Claims>, OAuthError>;
+}
+
+/// The `sub` claim of a JWT access token already validated by its `TokenManager`
+pub fn token_subject(token: &Token) -> Option<String> {
+    if !matches!(token.model, TokenModel::JWT { .. }) {
+        return None;
+    }
+    let payload = token.access_token.split('.').nth(1)?;
+    let claims: Value = serde_json::from_slice(&base64_url_decode(payload).ok()?).ok()?;
+    claims.get("sub")?.as_str().map(str::to_string)
+}
+
+/// The claims of a `UserContext`
+pub fn user_claims(user: &UserContext) -> Claims {
+    let mut claims = Claims::new();
+    claims.insert("sub".into(), Value::String(user.subject.clone()));
+    if let Some(name) = &user.name {
+        claims.insert("name".into(), Value::String(name.clone()));
+    }
+    if let Some(picture) = &user.picture {
+        claims.insert("picture".into(), Value::String(picture.clone()));
+    }
+    if let Some(email) = &user.email {
+        claims.insert("email".into(), Value::String(email.clone()));
+    }
+    if let Some(verified) = user.email_verified {
+        claims.insert("email_verified".into(), Value::Bool(verified));
+    }
+    claims
+}
+
+/// The claims released by the scopes: `sub`, and the standard claims of each granted scope.
+/// Other claims are released when a scope of the same name is granted
+pub fn scoped_claims(claims: &Claims, scopes: &[String]) -> Claims {
+    let granted = |scope: &str| scopes.iter().any(|granted| granted == scope);
+    claims
+        .iter()
+        .filter(|(name, _)| {
+            if name.as_str() == "sub" {
+                return true;
+            }
+            match SCOPE_CLAIMS.iter().find(|(_, names)| names.contains(&name.as_str())) {
+                Some((scope, _)) => granted(scope),
+                None => granted(name),
+            }
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
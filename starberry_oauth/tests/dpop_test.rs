@@ -0,0 +1,65 @@
+use serde_json::json;
+use starberry_oauth::oauth_core::dpop::jwk_thumbprint;
+use starberry_oauth::oauth_core::jwt::JWTTokenManager;
+use starberry_oauth::oauth_core::types::{Grant, OAuthError, Token, TokenModel};
+use starberry_oauth::{DpopKey, DpopValidator};
+
+const TOKEN_URL: &str = "https://auth.local/oauth/token";
+const RESOURCE_URL: &str = "https://api.local/orders";
+
+#[test]
+fn test_proof_is_verified() {
+    let key = DpopKey::generate().unwrap();
+    let validator = DpopValidator::new();
+    let proof = key.proof("POST", TOKEN_URL, None, None).unwrap();
+    let verified = validator.verify(&proof, "POST", TOKEN_URL, None).unwrap();
+    assert_eq!(verified.jkt, key.thumbprint());
+    // A proof is only accepted once
+    assert!(matches!(validator.verify(&proof, "POST", TOKEN_URL, None), Err(OAuthError::InvalidDpopProof)));
+}
+
+#[test]
+fn test_proof_must_match_the_request() {
+    let key = DpopKey::generate().unwrap();
+    let validator = DpopValidator::new();
+    let proof = key.proof("POST", TOKEN_URL, None, None).unwrap();
+    assert!(validator.verify(&proof, "GET", TOKEN_URL, None).is_err());
+    assert!(validator.verify(&proof, "POST", RESOURCE_URL, None).is_err());
+    // The query is not part of the URL of the proof
+    let proof = key.proof("GET", &format!("{}?page=2", RESOURCE_URL), Some("abc"), None).unwrap();
+    assert!(validator.verify(&proof, "GET", RESOURCE_URL, Some("other")).is_err());
+    let proof = key.proof("GET", RESOURCE_URL, Some("abc"), None).unwrap();
+    assert!(validator.verify(&proof, "GET", &format!("{}?page=3", RESOURCE_URL), Some("abc")).is_ok());
+}
+
+#[test]
+fn test_thumbprint_uses_required_members() {
+    let key = DpopKey::generate().unwrap();
+    let mut jwk = key.jwk().clone();
+    jwk["alg"] = json!("ES256");
+    jwk["kid"] = json!("key-1");
+    assert_eq!(jwk_thumbprint(&jwk).as_deref(), Some(key.thumbprint()));
+    assert_eq!(jwk_thumbprint(&json!({ "kty": "oct", "k": "c2VjcmV0" })), None);
+}
+
+#[test]
+fn test_tokens_are_bound() {
+    let key = DpopKey::generate().unwrap();
+    let validator = DpopValidator::new();
+
+    let manager = JWTTokenManager::new_hs256(b"secret", 3600);
+    let jwt = manager.generate_dpop_token(Grant::ClientCredentials, key.thumbprint()).unwrap();
+    assert_eq!(validator.bound_key(&jwt).as_deref(), Some(key.thumbprint()));
+
+    let opaque = Token {
+        model: TokenModel::BearerOpaque,
+        access_token: "opaque".to_string(),
+        refresh_token: None,
+        expires_in: 3600,
+        scope: None,
+        id_token: None,
+    };
+    assert_eq!(validator.bound_key(&opaque), None);
+    validator.bind(&opaque, key.thumbprint());
+    assert_eq!(validator.bound_key(&opaque).as_deref(), Some(key.thumbprint()));
+}
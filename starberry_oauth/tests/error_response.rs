@@ -21,6 +21,10 @@ async fn test_oauth_error_into_response() {
         (OAuthError::RateLimited, StatusCode::TOO_MANY_REQUESTS, "rate_limited", "Too many requests"),
         (OAuthError::HttpError("oops".into()), StatusCode::BAD_GATEWAY, "http_error", "oops"),
         (OAuthError::Unauthorized, StatusCode::FORBIDDEN, "unauthorized_client", "Client not authorized"),
+        (OAuthError::InvalidRequest, StatusCode::BAD_REQUEST, "invalid_request", "The request is invalid"),
+        (OAuthError::InvalidRequestObject, StatusCode::BAD_REQUEST, "invalid_request_object", "The request object is invalid"),
+        (OAuthError::InvalidRequestUri, StatusCode::BAD_REQUEST, "invalid_request_uri", "The request_uri is invalid or expired"),
+        (OAuthError::InvalidDpopProof, StatusCode::BAD_REQUEST, "invalid_dpop_proof", "The DPoP proof is invalid"),
         (OAuthError::ServerError, StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Internal server error"),
     ];
 
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use starberry_oauth::oauth_core::jwt::JWTTokenManager;
+use starberry_oauth::oauth_core::oauth_provider::TokenManager;
+use starberry_oauth::oauth_core::types::Grant;
+use starberry_oauth::IssuerRegistry;
+
+fn manager(secret: &[u8], issuer: &str) -> JWTTokenManager {
+    JWTTokenManager::new_hs256(secret, 3600).with_claims(issuer, "api")
+}
+
+#[tokio::test]
+async fn selects_the_issuer() {
+    let registry = IssuerRegistry::new()
+        .issuer("acme", "https://id.acme.example", Arc::new(manager(b"acme-secret", "https://id.acme.example")))
+        .issuer("globex", "https://login.globex.example", Arc::new(manager(b"globex-secret", "https://login.globex.example")))
+        .host("acme", "acme.app.example");
+
+    let acme = manager(b"acme-secret", "https://id.acme.example").generate_token(Grant::ClientCredentials).await.unwrap();
+    let globex = manager(b"globex-secret", "https://login.globex.example").generate_token(Grant::ClientCredentials).await.unwrap();
+
+    let (tenant, _) = registry.validate(&acme.access_token, None).await.unwrap();
+    assert_eq!(tenant.id, "acme");
+    let (tenant, _) = registry.validate(&globex.access_token, Some("api.example:443")).await.unwrap();
+    assert_eq!(tenant.id, "globex");
+    assert_eq!(tenant.issuer, "https://login.globex.example");
+    assert_eq!(registry.tenant_for_host("ACME.app.example:8443").map(|tenant| tenant.id.as_str()), Some("acme"));
+
+    // The host of a tenant only accepts its issuer
+    assert!(registry.validate(&globex.access_token, Some("acme.app.example")).await.is_err());
+    // Unknown issuers and forged signatures are rejected
+    let unknown = manager(b"acme-secret", "https://evil.example").generate_token(Grant::ClientCredentials).await.unwrap();
+    assert!(registry.validate(&unknown.access_token, None).await.is_err());
+    let forged = manager(b"guessed", "https://id.acme.example").generate_token(Grant::ClientCredentials).await.unwrap();
+    assert!(registry.validate(&forged.access_token, None).await.is_err());
+    // The audience is checked
+    let other_audience = JWTTokenManager::new_hs256(b"acme-secret", 3600).with_claims("https://id.acme.example", "admin");
+    let token = other_audience.generate_token(Grant::ClientCredentials).await.unwrap();
+    assert!(registry.validate(&token.access_token, None).await.is_err());
+}
@@ -15,4 +15,94 @@ fn test_openid_discovery_struct() {
     };
     assert_eq!(disc.issuer, "issuer");
     assert_eq!(disc.jwks_uri, "jwks");
-} 
\ No newline at end of file
+} 
+#[cfg(feature = "openid")]
+mod session_management {
+    use serde_json::{json, Value};
+    use starberry_lib::jwt::{self, Jwt};
+    use starberry_oauth::oauth_core::types::Client;
+    use starberry_oauth::openid::session::{session_state, LogoutRequest};
+    use starberry_oauth::openid::userinfo::{scoped_claims, Claims};
+
+    const ISSUER: &str = "https://op.example";
+
+    /// An ID token of the provider, expired as the hints usually are
+    fn id_token(key: &[u8], sub: &str, aud: &str) -> String {
+        let mut claims = jwt::Claims::empty().issuer(ISSUER).subject(sub).audience(aud);
+        claims.exp = Some(1);
+        Jwt::hs256(key).sign(&claims).unwrap()
+    }
+
+    #[test]
+    fn test_userinfo_claims_follow_scopes() {
+        let claims: Claims = serde_json::from_value(json!({
+            "sub": "alice",
+            "name": "Alice",
+            "email": "alice@example.com",
+            "phone_number": "+1 555",
+            "groups": ["admin"],
+        }))
+        .unwrap();
+        let scoped = scoped_claims(&claims, &["openid".to_string(), "email".to_string()]);
+        assert_eq!(Value::Object(scoped), json!({ "sub": "alice", "email": "alice@example.com" }));
+        let scoped = scoped_claims(&claims, &["openid".to_string(), "profile".to_string(), "groups".to_string()]);
+        assert_eq!(Value::Object(scoped), json!({ "sub": "alice", "name": "Alice", "groups": ["admin"] }));
+    }
+
+    #[test]
+    fn test_session_state_is_salted_hash() {
+        let state = session_state("client1", "https://rp.example", "browser", "salt");
+        assert!(state.ends_with(".salt"));
+        assert_eq!(state, session_state("client1", "https://rp.example", "browser", "salt"));
+        assert_ne!(state, session_state("client1", "https://rp.example", "other", "salt"));
+    }
+
+    #[test]
+    fn test_logout_redirects_to_registered_uris() {
+        let client = Client {
+            id: "client1".to_string(),
+            secret: None,
+            redirect_uris: vec![],
+            post_logout_redirect_uris: vec!["https://rp.example/bye".to_string()],
+        };
+        let verifier = Jwt::hs256(b"op-secret").issuer(ISSUER);
+        let mut logout = LogoutRequest {
+            id_token_hint: Some(id_token(b"op-secret", "alice", "client1")),
+            post_logout_redirect_uri: Some("https://rp.example/bye".to_string()),
+            state: Some("a b".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(logout.client(), None);
+        assert!(logout.verify_hint(&verifier));
+        assert_eq!(logout.client().as_deref(), Some("client1"));
+        assert_eq!(logout.hinted_subject().as_deref(), Some("alice"));
+        assert_eq!(logout.redirect_to(&client).as_deref(), Some("https://rp.example/bye?state=a%20b"));
+
+        let elsewhere = LogoutRequest {
+            post_logout_redirect_uri: Some("https://evil.example".to_string()),
+            ..logout.clone()
+        };
+        assert_eq!(elsewhere.redirect_to(&client), None);
+
+        let mismatch = LogoutRequest { client_id: Some("client2".to_string()), ..logout };
+        assert_eq!(mismatch.client(), None);
+    }
+
+    #[test]
+    fn test_logout_ignores_forged_hints() {
+        let verifier = Jwt::hs256(b"op-secret").issuer(ISSUER);
+        let mut forged = LogoutRequest {
+            id_token_hint: Some(id_token(b"guessed", "alice", "client1")),
+            ..Default::default()
+        };
+        assert!(!forged.verify_hint(&verifier));
+        assert_eq!(forged.hinted_subject(), None);
+        assert_eq!(forged.client(), None);
+
+        let mut elsewhere = LogoutRequest {
+            id_token_hint: Some(id_token(b"op-secret", "alice", "client1")),
+            ..Default::default()
+        };
+        assert!(!elsewhere.verify_hint(&Jwt::hs256(b"op-secret").issuer("https://other.example")));
+    }
+}
@@ -8,6 +8,7 @@ async fn test_in_memory_client_store() {
         id: "client1".to_string(),
         secret: Some("secret".to_string()),
         redirect_uris: vec!["https://app.local/callback".to_string()],
+        post_logout_redirect_uris: Vec::new(),
     };
     let store = InMemoryClientStore::new(vec![client.clone()]);
     // Existing client
@@ -50,6 +51,25 @@ async fn test_in_memory_authorizer() {
     assert!(!auth.check_consent("cid", "uid", &scopes_full).await.unwrap());
 }
 
+#[tokio::test]
+async fn test_in_memory_authorizer_grants() {
+    let auth = InMemoryAuthorizer::new();
+    auth.record_consent("cid", "uid", &["read".to_string()]).await.unwrap();
+    auth.record_consent("cid", "uid", &["write".to_string()]).await.unwrap();
+    auth.record_consent("other", "uid", &["read".to_string()]).await.unwrap();
+    auth.record_consent("cid", "someone", &["read".to_string()]).await.unwrap();
+    // Consents accumulate per client
+    assert!(auth.check_consent("cid", "uid", &["read".to_string(), "write".to_string()]).await.unwrap());
+    let grants = auth.list_grants("uid").await.unwrap();
+    assert_eq!(grants.iter().map(|g| g.client_id.as_str()).collect::<Vec<_>>(), vec!["cid", "other"]);
+    assert_eq!(grants[0].scopes, vec!["read".to_string(), "write".to_string()]);
+    // Revoking forgets the consent of this user only
+    assert!(auth.revoke_grant("cid", "uid").await.unwrap());
+    assert!(!auth.revoke_grant("cid", "uid").await.unwrap());
+    assert!(!auth.check_consent("cid", "uid", &["read".to_string()]).await.unwrap());
+    assert!(auth.check_consent("cid", "someone", &["read".to_string()]).await.unwrap());
+}
+
 #[tokio::test]
 async fn test_in_memory_token_storage() {
     let storage = InMemoryTokenStorage::new();
@@ -0,0 +1,23 @@
+use starberry_core::app::secrets::{FileSecrets, SecretError};
+use starberry_oauth::oauth_core::jwt::JWTTokenManager;
+use starberry_oauth::oauth_core::oauth_provider::TokenManager;
+use starberry_oauth::oauth_core::types::Grant;
+
+#[tokio::test]
+async fn jwt_keys_from_secrets() {
+    let directory = std::env::temp_dir().join(format!("starberry-oauth-secrets-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("jwt_secret"), "a-long-shared-secret\n").unwrap();
+    std::fs::write(directory.join("jwt_private_key"), "not a pem").unwrap();
+    let secrets = FileSecrets::new(&directory);
+
+    let manager = JWTTokenManager::hs256_from_secrets(&secrets, "jwt_secret", 3600).await.unwrap();
+    let token = manager.generate_token(Grant::ClientCredentials).await.unwrap();
+    assert!(manager.validate_token(&token.access_token).await.is_ok());
+
+    let missing = JWTTokenManager::hs256_from_secrets(&secrets, "other_secret", 3600).await;
+    assert!(matches!(missing, Err(SecretError::Missing(_))));
+    let invalid = JWTTokenManager::rs256_from_secrets(&secrets, "jwt_private_key", "jwt_private_key", 3600).await;
+    assert!(matches!(invalid, Err(SecretError::Invalid(_))));
+    let _ = std::fs::remove_dir_all(&directory);
+}
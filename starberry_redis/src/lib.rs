@@ -0,0 +1,3 @@
+pub mod redis; 
+
+pub use redis::*;
\ No newline at end of file
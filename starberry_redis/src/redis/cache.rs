@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use starberry_core::cache::{Cache, CacheError};
+
+use super::pool::RedisPool;
+
+/// A `Cache` storing its entries in Redis, so that they are shared between instances
+/// of the application and survive restarts.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let pool = RedisPool::new(RedisConnectionBuilder::new("127.0.0.1", 6379), 8);
+/// App::new().cache(RedisCache::new(pool).prefix("myapp:")).build();
+/// ```
+#[derive(Clone)]
+pub struct RedisCache {
+    pool: RedisPool,
+    prefix: String,
+}
+
+impl RedisCache {
+    pub fn new(pool: RedisPool) -> Self {
+        Self {
+            pool,
+            prefix: String::new(),
+        }
+    }
+
+    /// Prepends a prefix to every key, to share a database between several applications
+    pub fn prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.get(self.key(key)).await?)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.set(self.key(key), value, ttl).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, CacheError> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.del(&[self.key(key)]).await? > 0)
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use starberry_core::connection::{Connection as GenericConnection, ConnectionBuilder, Protocol, Tx};
+use tokio::io::{AsyncWriteExt, BufStream};
+
+use super::error::RedisError;
+use super::pubsub::PubSub;
+use super::resp::{read_value, Cmd, Pipeline, RespValue, ToArg};
+
+/// Represents a Redis connection configuration.
+#[derive(Debug, Clone)]
+pub struct RedisConnectionBuilder {
+    host: String,
+    port: u16,
+    tls: bool,
+    username: Option<String>,
+    password: Option<String>,
+    database: Option<u32>,
+    max_connection_time: Option<Duration>,
+    query_timeout: Option<Duration>,
+}
+
+impl RedisConnectionBuilder {
+    /// Creates a new builder for the server at the given host and port (usually 6379).
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            tls: false,
+            username: None,
+            password: None,
+            database: None,
+            max_connection_time: None,
+            query_timeout: None,
+        }
+    }
+
+    /// Enables or disables TLS.
+    pub fn tls(mut self, enable: bool) -> Self {
+        self.tls = enable;
+        self
+    }
+
+    /// Sets the username, for servers using ACLs.
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    /// Sets the password sent with `AUTH`.
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Selects the logical database after connecting.
+    pub fn database(mut self, database: u32) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Sets the maximum time to establish the connection.
+    pub fn max_connection_time(mut self, duration: Duration) -> Self {
+        self.max_connection_time = Some(duration);
+        self
+    }
+
+    /// Sets the maximum time to wait for the reply of a command.
+    pub fn query_timeout(mut self, duration: Duration) -> Self {
+        self.query_timeout = Some(duration);
+        self
+    }
+
+    /// Connects, authenticates and selects the database.
+    pub async fn connect(&self) -> Result<RedisConnection, RedisError> {
+        let mut builder = ConnectionBuilder::new(&self.host, self.port)
+            .protocol(Protocol::Redis)
+            .tls(self.tls);
+        if let Some(duration) = self.max_connection_time {
+            builder = builder.max_connection_time(duration);
+        }
+        let stream = builder.connect().await?;
+        let mut conn = RedisConnection {
+            stream: Some(BufStream::new(stream)),
+            query_timeout: self.query_timeout,
+        };
+        if let Some(password) = &self.password {
+            let cmd = match &self.username {
+                Some(username) => Cmd::new("AUTH").arg(username).arg(password),
+                None => Cmd::new("AUTH").arg(password),
+            };
+            conn.query(&cmd).await?;
+        }
+        if let Some(database) = self.database {
+            conn.query(&Cmd::new("SELECT").arg(database)).await?;
+        }
+        Ok(conn)
+    }
+}
+
+/// An open connection to a Redis server.
+pub struct RedisConnection {
+    pub(super) stream: Option<BufStream<GenericConnection>>,
+    query_timeout: Option<Duration>,
+}
+
+impl RedisConnection {
+    fn stream(&mut self) -> Result<&mut BufStream<GenericConnection>, RedisError> {
+        self.stream
+            .as_mut()
+            .ok_or_else(|| RedisError::ConnectionError("connection is closed".to_string()))
+    }
+
+    /// Whether the connection can still be used. It is dropped after an I/O or protocol error,
+    /// since the replies may no longer match the commands
+    pub fn is_open(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    async fn exchange(&mut self, payload: &[u8], replies: usize) -> Result<Vec<RespValue>, RedisError> {
+        let timeout = self.query_timeout;
+        let stream = self.stream()?;
+        let work = async move {
+            stream.write_all(payload).await?;
+            stream.flush().await?;
+            let mut values = Vec::with_capacity(replies);
+            for _ in 0..replies {
+                values.push(read_value(stream).await?);
+            }
+            Ok::<_, RedisError>(values)
+        };
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, work)
+                .await
+                .unwrap_or_else(|_| Err(RedisError::TimeoutError("no reply from the server".to_string()))),
+            None => work.await,
+        };
+        if result.is_err() {
+            self.stream = None;
+        }
+        result
+    }
+
+    /// Sends a command and returns its reply. Error replies are returned as `RedisError::ServerError`
+    pub async fn query(&mut self, cmd: &Cmd) -> Result<RespValue, RedisError> {
+        let mut values = self.exchange(&cmd.encode(), 1).await?;
+        values.pop().unwrap_or(RespValue::Bulk(None)).into_result()
+    }
+
+    /// Sends every command of the pipeline in a single write and returns their replies in order.
+    /// Error replies are kept as `RespValue::Error` so that one failing command does not hide the others
+    pub async fn pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RedisError> {
+        if pipeline.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.exchange(&pipeline.encode(), pipeline.len()).await
+    }
+
+    /// Sends `PING`
+    pub async fn ping(&mut self) -> Result<(), RedisError> {
+        self.query(&Cmd::new("PING")).await.map(|_| ())
+    }
+
+    /// Gets the value of a key
+    pub async fn get<K: ToArg>(&mut self, key: K) -> Result<Option<Vec<u8>>, RedisError> {
+        self.query(&Cmd::new("GET").arg(key)).await?.into_bytes()
+    }
+
+    /// Gets the value of a key as an UTF-8 string
+    pub async fn get_string<K: ToArg>(&mut self, key: K) -> Result<Option<String>, RedisError> {
+        Ok(self
+            .get(key)
+            .await?
+            .map(|value| String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    /// Sets the value of a key, expiring after `ttl` if given
+    pub async fn set<K: ToArg, V: ToArg>(&mut self, key: K, value: V, ttl: Option<Duration>) -> Result<(), RedisError> {
+        let mut cmd = Cmd::new("SET").arg(key).arg(value);
+        if let Some(ttl) = ttl {
+            cmd = cmd.arg("PX").arg(ttl.as_millis().max(1));
+        }
+        self.query(&cmd).await.map(|_| ())
+    }
+
+    /// Deletes keys, returns how many existed
+    pub async fn del<K: ToArg>(&mut self, keys: &[K]) -> Result<i64, RedisError> {
+        self.query(&Cmd::new("DEL").args(keys)).await?.into_integer()
+    }
+
+    /// Whether a key exists
+    pub async fn exists<K: ToArg>(&mut self, key: K) -> Result<bool, RedisError> {
+        Ok(self.query(&Cmd::new("EXISTS").arg(key)).await?.into_integer()? > 0)
+    }
+
+    /// Sets the time to live of a key, returns `false` if it does not exist
+    pub async fn expire<K: ToArg>(&mut self, key: K, ttl: Duration) -> Result<bool, RedisError> {
+        let cmd = Cmd::new("PEXPIRE").arg(key).arg(ttl.as_millis().max(1));
+        Ok(self.query(&cmd).await?.into_integer()? == 1)
+    }
+
+    /// Increments the integer value of a key by `by`, returns the new value
+    pub async fn incr_by<K: ToArg>(&mut self, key: K, by: i64) -> Result<i64, RedisError> {
+        self.query(&Cmd::new("INCRBY").arg(key).arg(by)).await?.into_integer()
+    }
+
+    /// Publishes a message, returns the number of subscribers which received it
+    pub async fn publish<C: ToArg, M: ToArg>(&mut self, channel: C, message: M) -> Result<i64, RedisError> {
+        self.query(&Cmd::new("PUBLISH").arg(channel).arg(message)).await?.into_integer()
+    }
+
+    /// Turns the connection into a subscriber. It can no longer send regular commands
+    pub fn into_pubsub(self) -> PubSub {
+        PubSub::new(self)
+    }
+
+    /// Closes the connection.
+    pub async fn close(&mut self) -> Result<(), RedisError> {
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.write_all(&Cmd::new("QUIT").encode()).await;
+            let _ = stream.flush().await;
+            stream.get_mut().shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Tx for RedisConnection {
+    type Request = ();
+    type Response = RedisConnection;
+    type Config = RedisConnectionBuilder;
+    type Error = RedisError;
+
+    async fn process(&mut self, _: Self::Request) -> Result<&mut Self::Response, Self::Error> {
+        Ok(self)
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        self.close().await
+    }
+
+    async fn fetch<T: Into<String> + Send + Sync>(_: T, _: Self::Request, config: Self::Config) -> Result<Self::Response, Self::Error> {
+        config.connect().await
+    }
+}
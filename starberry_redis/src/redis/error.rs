@@ -0,0 +1,62 @@
+use std::error::Error as StdError;
+use std::fmt;
+use starberry_core::cache::CacheError;
+use starberry_core::connection::error::ConnectionError;
+
+/// Represents errors that can occur while talking to a Redis server.
+#[derive(Debug, Clone)]
+pub enum RedisError {
+    ConnectionError(String),
+    ProtocolError(String),
+    /// An error reply sent by the server, e.g. `WRONGTYPE ...`
+    ServerError(String),
+    TimeoutError(String),
+    PoolError(String),
+}
+
+impl RedisError {
+    /// Whether the connection is still usable after this error.
+    /// Only error replies of the server leave the connection in a known state
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, RedisError::ServerError(_))
+    }
+}
+
+impl fmt::Display for RedisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedisError::ConnectionError(msg) => write!(f, "Connection error: {}", msg),
+            RedisError::ProtocolError(msg) => write!(f, "Protocol error: {}", msg),
+            RedisError::ServerError(msg) => write!(f, "Server error: {}", msg),
+            RedisError::TimeoutError(msg) => write!(f, "Timeout error: {}", msg),
+            RedisError::PoolError(msg) => write!(f, "Pool error: {}", msg),
+        }
+    }
+}
+
+impl StdError for RedisError {}
+
+impl From<std::io::Error> for RedisError {
+    fn from(error: std::io::Error) -> Self {
+        RedisError::ConnectionError(error.to_string())
+    }
+}
+
+impl From<ConnectionError> for RedisError {
+    fn from(err: ConnectionError) -> Self {
+        match err {
+            ConnectionError::ConnectionTimeout => RedisError::TimeoutError(err.to_string()),
+            ConnectionError::ProtocolError(msg) | ConnectionError::TlsError(msg) => {
+                RedisError::ProtocolError(msg)
+            }
+            ConnectionError::PoolExhausted => RedisError::PoolError(err.to_string()),
+            _ => RedisError::ConnectionError(err.to_string()),
+        }
+    }
+}
+
+impl From<RedisError> for CacheError {
+    fn from(err: RedisError) -> Self {
+        CacheError::Backend(err.to_string())
+    }
+}
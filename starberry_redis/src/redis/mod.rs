@@ -0,0 +1,13 @@
+pub mod resp;
+pub mod error;
+pub mod connection;
+pub mod pool;
+pub mod pubsub;
+pub mod cache;
+
+pub use resp::{Cmd, Pipeline, RespValue, ToArg};
+pub use error::RedisError;
+pub use connection::{RedisConnectionBuilder, RedisConnection};
+pub use pool::{RedisPool, PooledRedisConnection};
+pub use pubsub::{PubSub, Message};
+pub use cache::RedisCache;
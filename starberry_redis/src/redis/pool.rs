@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore, OwnedSemaphorePermit};
+use async_trait::async_trait;
+use starberry_core::connection::transmit::Pool;
+
+use super::connection::{RedisConnectionBuilder, RedisConnection};
+use super::error::RedisError;
+
+/// Async connection pool for Redis connections.
+#[derive(Clone)]
+pub struct RedisPool {
+    builder: RedisConnectionBuilder,
+    connections: Arc<Mutex<VecDeque<RedisConnection>>>,
+    semaphore: Arc<Semaphore>,
+    max_size: usize,
+}
+
+impl RedisPool {
+    /// Create a new pool with the given `RedisConnectionBuilder` and maximum pool size.
+    pub fn new(builder: RedisConnectionBuilder, max_size: usize) -> Self {
+        let max_size = max_size.max(1);
+        Self {
+            builder,
+            connections: Arc::new(Mutex::new(VecDeque::with_capacity(max_size))),
+            semaphore: Arc::new(Semaphore::new(max_size)),
+            max_size,
+        }
+    }
+
+    /// Acquire a pooled connection, establishing a new one if necessary.
+    pub async fn get(&self) -> Result<PooledRedisConnection, RedisError> {
+        // Acquire a permit to ensure we don't exceed max_size
+        let permit = self.semaphore.clone().acquire_owned()
+            .await
+            .map_err(|_| RedisError::PoolError("Failed to acquire pool permit".into()))?;
+        // Try to reuse an existing connection
+        let idle = self.connections.lock().await.pop_front();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => self.builder.connect().await?,
+        };
+        Ok(PooledRedisConnection { pool: self.clone(), conn: Some(conn), _permit: permit })
+    }
+
+    /// Return a connection to the pool. Broken connections are dropped.
+    async fn release(&self, conn: RedisConnection) {
+        if !conn.is_open() {
+            return;
+        }
+        let mut conns = self.connections.lock().await;
+        if conns.len() < self.max_size {
+            conns.push_back(conn);
+        }
+        // Permit is released when `_permit` is dropped
+    }
+}
+
+/// Wrapper for a pooled connection that returns it to the pool on drop.
+pub struct PooledRedisConnection {
+    pool: RedisPool,
+    conn: Option<RedisConnection>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledRedisConnection {
+    /// Get a mutable reference to the underlying `RedisConnection`.
+    pub fn connection(&mut self) -> &mut RedisConnection {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl Deref for PooledRedisConnection {
+    type Target = RedisConnection;
+    fn deref(&self) -> &Self::Target {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledRedisConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledRedisConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let pool = self.pool.clone();
+            // Spawn a task to release the connection without blocking.
+            tokio::spawn(async move {
+                pool.release(conn).await;
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl Pool for RedisPool {
+    type Item = PooledRedisConnection;
+    type Error = RedisError;
+
+    async fn get(&self) -> std::result::Result<Self::Item, Self::Error> {
+        RedisPool::get(self).await
+    }
+
+    async fn release(&self, item: Self::Item) {
+        // Dropping the item returns its connection to the pool.
+        drop(item);
+    }
+}
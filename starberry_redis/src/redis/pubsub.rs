@@ -0,0 +1,144 @@
+use tokio::io::AsyncWriteExt;
+
+use super::connection::RedisConnection;
+use super::error::RedisError;
+use super::resp::{read_value, Cmd, RespValue, ToArg};
+
+/// A message received on a subscribed channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: String,
+    /// The pattern which matched the channel, for pattern subscriptions
+    pub pattern: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// Returns the payload as an UTF-8 string
+    pub fn payload_string(&self) -> String {
+        String::from_utf8_lossy(&self.payload).into_owned()
+    }
+}
+
+/// A connection in subscriber mode, created with `RedisConnection::into_pubsub`.
+pub struct PubSub {
+    conn: RedisConnection,
+}
+
+impl PubSub {
+    pub(super) fn new(conn: RedisConnection) -> Self {
+        Self { conn }
+    }
+
+    async fn send(&mut self, cmd: Cmd) -> Result<(), RedisError> {
+        let stream = self
+            .conn
+            .stream
+            .as_mut()
+            .ok_or_else(|| RedisError::ConnectionError("connection is closed".to_string()))?;
+        stream.write_all(&cmd.encode()).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    /// Subscribes to channels. The confirmations are skipped by `next_message`
+    pub async fn subscribe<C: ToArg>(&mut self, channels: &[C]) -> Result<(), RedisError> {
+        self.send(Cmd::new("SUBSCRIBE").args(channels)).await
+    }
+
+    /// Subscribes to every channel matching the glob patterns
+    pub async fn psubscribe<P: ToArg>(&mut self, patterns: &[P]) -> Result<(), RedisError> {
+        self.send(Cmd::new("PSUBSCRIBE").args(patterns)).await
+    }
+
+    /// Unsubscribes from channels, or from every channel if none is given
+    pub async fn unsubscribe<C: ToArg>(&mut self, channels: &[C]) -> Result<(), RedisError> {
+        self.send(Cmd::new("UNSUBSCRIBE").args(channels)).await
+    }
+
+    /// Unsubscribes from patterns, or from every pattern if none is given
+    pub async fn punsubscribe<P: ToArg>(&mut self, patterns: &[P]) -> Result<(), RedisError> {
+        self.send(Cmd::new("PUNSUBSCRIBE").args(patterns)).await
+    }
+
+    /// Waits for the next published message
+    pub async fn next_message(&mut self) -> Result<Message, RedisError> {
+        loop {
+            let stream = self
+                .conn
+                .stream
+                .as_mut()
+                .ok_or_else(|| RedisError::ConnectionError("connection is closed".to_string()))?;
+            let reply = match read_value(stream).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    self.conn.stream = None;
+                    return Err(e);
+                }
+            };
+            if let Some(message) = parse_message(reply.into_array()?)? {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+fn text(value: RespValue) -> Result<String, RedisError> {
+    Ok(String::from_utf8_lossy(&value.into_bytes()?.unwrap_or_default()).into_owned())
+}
+
+/// Parses a push reply, returns `None` for subscription confirmations
+fn parse_message(items: Vec<RespValue>) -> Result<Option<Message>, RedisError> {
+    let mut items = items.into_iter();
+    let kind = text(items.next().unwrap_or(RespValue::Bulk(None)))?;
+    match kind.as_str() {
+        "message" => {
+            let channel = text(items.next().unwrap_or(RespValue::Bulk(None)))?;
+            let payload = items.next().unwrap_or(RespValue::Bulk(None)).into_bytes()?;
+            Ok(Some(Message {
+                channel,
+                pattern: None,
+                payload: payload.unwrap_or_default(),
+            }))
+        }
+        "pmessage" => {
+            let pattern = text(items.next().unwrap_or(RespValue::Bulk(None)))?;
+            let channel = text(items.next().unwrap_or(RespValue::Bulk(None)))?;
+            let payload = items.next().unwrap_or(RespValue::Bulk(None)).into_bytes()?;
+            Ok(Some(Message {
+                channel,
+                pattern: Some(pattern),
+                payload: payload.unwrap_or_default(),
+            }))
+        }
+        "subscribe" | "psubscribe" | "unsubscribe" | "punsubscribe" | "pong" => Ok(None),
+        other => Err(RedisError::ProtocolError(format!("unexpected push reply {:?}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(Some(s.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn parses_push_replies() {
+        let confirmation = vec![bulk("subscribe"), bulk("news"), RespValue::Integer(1)];
+        assert_eq!(parse_message(confirmation).unwrap(), None);
+
+        let message = parse_message(vec![bulk("message"), bulk("news"), bulk("hello")])
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.channel, "news");
+        assert_eq!(message.payload_string(), "hello");
+
+        let message = parse_message(vec![bulk("pmessage"), bulk("n*"), bulk("news"), bulk("hi")])
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.pattern.as_deref(), Some("n*"));
+        assert_eq!(message.channel, "news");
+    }
+}
@@ -0,0 +1,295 @@
+//! Encoding of commands and decoding of replies in the RESP2 protocol.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use super::error::RedisError;
+
+/// A reply sent by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RespValue {
+    /// `+OK`
+    Simple(String),
+    /// `-ERR ...`
+    Error(String),
+    /// `:1`
+    Integer(i64),
+    /// `$3\r\nfoo`, `None` for the null bulk string `$-1`
+    Bulk(Option<Vec<u8>>),
+    /// `*2\r\n...`, `None` for the null array `*-1`
+    Array(Option<Vec<RespValue>>),
+}
+
+impl RespValue {
+    /// Turns an error reply into an `Err`
+    pub fn into_result(self) -> Result<RespValue, RedisError> {
+        match self {
+            RespValue::Error(msg) => Err(RedisError::ServerError(msg)),
+            value => Ok(value),
+        }
+    }
+
+    /// Returns the bytes of a bulk or simple string, `None` for nil
+    pub fn into_bytes(self) -> Result<Option<Vec<u8>>, RedisError> {
+        match self {
+            RespValue::Bulk(bytes) => Ok(bytes),
+            RespValue::Simple(s) => Ok(Some(s.into_bytes())),
+            RespValue::Error(msg) => Err(RedisError::ServerError(msg)),
+            other => Err(RedisError::ProtocolError(format!("expected a string, got {:?}", other))),
+        }
+    }
+
+    /// Returns the value of an integer reply
+    pub fn into_integer(self) -> Result<i64, RedisError> {
+        match self {
+            RespValue::Integer(n) => Ok(n),
+            RespValue::Error(msg) => Err(RedisError::ServerError(msg)),
+            other => Err(RedisError::ProtocolError(format!("expected an integer, got {:?}", other))),
+        }
+    }
+
+    /// Returns the items of an array reply, empty for the null array
+    pub fn into_array(self) -> Result<Vec<RespValue>, RedisError> {
+        match self {
+            RespValue::Array(items) => Ok(items.unwrap_or_default()),
+            RespValue::Error(msg) => Err(RedisError::ServerError(msg)),
+            other => Err(RedisError::ProtocolError(format!("expected an array, got {:?}", other))),
+        }
+    }
+}
+
+/// A value which can be sent as a command argument
+pub trait ToArg {
+    fn to_arg(&self) -> Vec<u8>;
+}
+
+impl ToArg for str {
+    fn to_arg(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToArg for String {
+    fn to_arg(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToArg for [u8] {
+    fn to_arg(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl ToArg for Vec<u8> {
+    fn to_arg(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+macro_rules! number_arg {
+    ($($ty:ty),*) => {
+        $(impl ToArg for $ty {
+            fn to_arg(&self) -> Vec<u8> {
+                self.to_string().into_bytes()
+            }
+        })*
+    };
+}
+
+number_arg!(i32, i64, u32, u64, usize, u128);
+
+impl<T: ToArg + ?Sized> ToArg for &T {
+    fn to_arg(&self) -> Vec<u8> {
+        (**self).to_arg()
+    }
+}
+
+/// A command and its arguments.
+///
+/// # Examples
+///
+/// ```
+/// use starberry_redis::Cmd;
+///
+/// let cmd = Cmd::new("SET").arg("key").arg("value");
+/// assert_eq!(cmd.encode(), b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmd {
+    args: Vec<Vec<u8>>,
+}
+
+impl Cmd {
+    pub fn new(name: &str) -> Self {
+        Self {
+            args: vec![name.as_bytes().to_vec()],
+        }
+    }
+
+    /// Appends an argument
+    pub fn arg<T: ToArg>(mut self, arg: T) -> Self {
+        self.args.push(arg.to_arg());
+        self
+    }
+
+    /// Appends several arguments
+    pub fn args<T: ToArg, I: IntoIterator<Item = T>>(mut self, args: I) -> Self {
+        self.args.extend(args.into_iter().map(|arg| arg.to_arg()));
+        self
+    }
+
+    /// Encodes the command as a RESP array of bulk strings
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = format!("*{}\r\n", self.args.len()).into_bytes();
+        for arg in &self.args {
+            out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            out.extend_from_slice(arg);
+            out.extend_from_slice(b"\r\n");
+        }
+        out
+    }
+}
+
+/// Several commands sent at once, their replies are read back in order
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub(super) commands: Vec<Cmd>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a command
+    pub fn cmd(mut self, cmd: Cmd) -> Self {
+        self.commands.push(cmd);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Encodes every command of the pipeline
+    pub fn encode(&self) -> Vec<u8> {
+        self.commands.iter().flat_map(|cmd| cmd.encode()).collect()
+    }
+}
+
+async fn read_line<R: AsyncBufRead + Unpin + Send>(reader: &mut R) -> Result<String, RedisError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(RedisError::ConnectionError("connection closed by the server".to_string()));
+    }
+    if !line.ends_with("\r\n") {
+        return Err(RedisError::ProtocolError(format!("unterminated line {:?}", line)));
+    }
+    line.truncate(line.len() - 2);
+    Ok(line)
+}
+
+fn parse_length(s: &str) -> Result<i64, RedisError> {
+    s.parse::<i64>()
+        .map_err(|_| RedisError::ProtocolError(format!("invalid length {:?}", s)))
+}
+
+/// Reads a single reply
+pub fn read_value<'a, R: AsyncBufRead + Unpin + Send>(
+    reader: &'a mut R,
+) -> Pin<Box<dyn Future<Output = Result<RespValue, RedisError>> + Send + 'a>> {
+    Box::pin(async move {
+        let line = read_line(reader).await?;
+        let rest = line.get(1..).unwrap_or_default();
+        match line.as_bytes().first() {
+            Some(b'+') => Ok(RespValue::Simple(rest.to_string())),
+            Some(b'-') => Ok(RespValue::Error(rest.to_string())),
+            Some(b':') => Ok(RespValue::Integer(parse_length(rest)?)),
+            Some(b'$') => {
+                let len = parse_length(rest)?;
+                if len < 0 {
+                    return Ok(RespValue::Bulk(None));
+                }
+                let mut data = vec![0u8; len as usize + 2];
+                reader.read_exact(&mut data).await?;
+                if !data.ends_with(b"\r\n") {
+                    return Err(RedisError::ProtocolError("unterminated bulk string".to_string()));
+                }
+                data.truncate(len as usize);
+                Ok(RespValue::Bulk(Some(data)))
+            }
+            Some(b'*') => {
+                let len = parse_length(rest)?;
+                if len < 0 {
+                    return Ok(RespValue::Array(None));
+                }
+                let mut items = Vec::with_capacity(len.min(1024) as usize);
+                for _ in 0..len {
+                    items.push(read_value(reader).await?);
+                }
+                Ok(RespValue::Array(Some(items)))
+            }
+            _ => Err(RedisError::ProtocolError(format!("unknown reply {:?}", line))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(mut input: &[u8]) -> Result<RespValue, RedisError> {
+        read_value(&mut input).await
+    }
+
+    #[tokio::test]
+    async fn parses_scalars() {
+        assert_eq!(parse(b"+OK\r\n").await.unwrap(), RespValue::Simple("OK".into()));
+        assert_eq!(parse(b"-ERR bad\r\n").await.unwrap(), RespValue::Error("ERR bad".into()));
+        assert_eq!(parse(b":-12\r\n").await.unwrap(), RespValue::Integer(-12));
+        assert_eq!(parse(b"$-1\r\n").await.unwrap(), RespValue::Bulk(None));
+        assert_eq!(
+            parse(b"$4\r\na\r\nb\r\n").await.unwrap(),
+            RespValue::Bulk(Some(b"a\r\nb".to_vec()))
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_nested_arrays() {
+        let value = parse(b"*2\r\n*1\r\n:1\r\n$1\r\nx\r\n").await.unwrap();
+        assert_eq!(
+            value,
+            RespValue::Array(Some(vec![
+                RespValue::Array(Some(vec![RespValue::Integer(1)])),
+                RespValue::Bulk(Some(b"x".to_vec())),
+            ]))
+        );
+        assert_eq!(parse(b"*-1\r\n").await.unwrap(), RespValue::Array(None));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_replies() {
+        assert!(parse(b"?\r\n").await.is_err());
+        assert!(parse(b"$3\r\nabcd\r\n").await.is_err());
+        assert!(parse(b"+OK").await.is_err());
+        assert!(parse(b"").await.is_err());
+    }
+
+    #[test]
+    fn encodes_pipelines() {
+        let pipeline = Pipeline::new()
+            .cmd(Cmd::new("INCR").arg("n"))
+            .cmd(Cmd::new("EXPIRE").arg("n").arg(10u64));
+        assert_eq!(
+            pipeline.encode(),
+            b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n*3\r\n$6\r\nEXPIRE\r\n$1\r\nn\r\n$2\r\n10\r\n".to_vec()
+        );
+    }
+}
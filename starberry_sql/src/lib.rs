@@ -1,3 +1,7 @@
+// Lets the code generated by `sql!` name this crate from within it
+extern crate self as starberry_sql;
+
 pub mod sql; 
 
-pub use sql::*;
\ No newline at end of file
+pub use sql::*;
+pub use starberry_macro::sql;
@@ -3,19 +3,44 @@ use super::error::DbError;
 use super::query::QueryResult;
 use super::encode::Encode;
 use super::row::FromRow;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use super::pool::SqlPool;
+use std::marker::PhantomData;
+use super::pool::{PooledSqlConnection, SqlPool};
+use starberry_core::connection::{CancellationToken, Protocol};
+use starberry_core::http::pagination::Pagination;
+use std::time::Duration;
 
 /// Builder for SQL queries, generated by the `sql!` macro.
 pub struct SqlQuery<'q> {
-    sql: &'q str,
+    sql: Cow<'q, str>,
     params: Vec<String>,
+    versioned: bool,
+    timeout: Option<Duration>,
+    cancel: Option<CancellationToken>,
+    replica: Option<bool>,
+    numbered: bool,
 }
 
 impl<'q> SqlQuery<'q> {
     /// Create a new SQL query builder.
     pub fn new(sql: &'q str) -> Self {
-        Self { sql, params: Vec::new() }
+        Self {
+            sql: Cow::Borrowed(sql),
+            params: Vec::new(),
+            versioned: false,
+            timeout: None,
+            cancel: None,
+            replica: None,
+            numbered: false,
+        }
+    }
+
+    /// Mark the placeholders of the SQL as `$1`, `$2`... in the order of the binds, as the
+    /// `sql!` macro writes them. They are then sent as `?` to MySQL.
+    pub fn numbered(mut self) -> Self {
+        self.numbered = true;
+        self
     }
 
     /// Bind a parameter to the query.
@@ -25,9 +50,107 @@ impl<'q> SqlQuery<'q> {
         self
     }
 
+    /// Restrict the query to one page by appending LIMIT and OFFSET. 
+    /// Cursor pages only get a LIMIT, the cursor has to be filtered on in the WHERE clause.
+    pub fn paginate(mut self, pagination: &Pagination) -> Self {
+        let mut sql = self.sql.trim_end().trim_end_matches(';').to_string();
+        sql.push_str(&format!(" LIMIT {}", pagination.limit()));
+        if !pagination.is_cursor() {
+            sql.push_str(&format!(" OFFSET {}", pagination.offset()));
+        }
+        self.sql = Cow::Owned(sql);
+        self
+    }
+
+    /// Lock the row optimistically on a version column. The statement only matches the row 
+    /// while `column` still equals `version`, and an UPDATE also increments it. `execute` then 
+    /// fails with `DbError::ConflictError` when no row is affected. 
+    /// Panics if `column` is not a (qualified) column name.
+    pub fn with_version(mut self, column: &str, version: i64) -> Self {
+        check_column(column);
+        let mut sql = self.sql.into_owned();
+        if is_statement(&sql, "UPDATE") {
+            sql = append_assignment(&sql, &format!("{} = {} + 1", column, column));
+        }
+        self.sql = Cow::Owned(add_condition(&sql, &format!("{} = {}", column, version)));
+        self.versioned = true;
+        self
+    }
+
+    /// Leave out the soft-deleted rows, those whose `column` (a nullable timestamp) is set. 
+    /// SELECT and UPDATE statements only match the rows where it is NULL, and a 
+    /// `DELETE FROM table ...` sets it to the current time instead of deleting the rows. 
+    /// With joins, qualify the column with its table. 
+    /// Panics if `column` is not a (qualified) column name.
+    pub fn soft_delete(mut self, column: &str) -> Self {
+        check_column(column);
+        let mut sql = self.sql.into_owned();
+        if is_statement(&sql, "DELETE") {
+            sql = delete_to_update(&sql, &format!("{} = CURRENT_TIMESTAMP", column));
+        }
+        self.sql = Cow::Owned(add_condition(&sql, &format!("{} IS NULL", column)));
+        self
+    }
+
+    /// Stop the query on the server and fail with `DbError::TimeoutError` if it runs longer than
+    /// the duration. Defaults to the `query_timeout` of the connection.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop the query on the server and fail with `DbError::CancelledError` once the token is
+    /// cancelled, usually `req.cancellation_token()` so queries end with their request.
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Run the query on a read replica when executed through a pool, even if its SQL does not
+    /// look read-only.
+    pub fn on_replica(mut self) -> Self {
+        self.replica = Some(true);
+        self
+    }
+
+    /// Run the query on the primary when executed through a pool, for reads that have to see
+    /// the latest writes or call functions with side effects.
+    pub fn on_primary(mut self) -> Self {
+        self.replica = Some(false);
+        self
+    }
+
+    /// Whether a pool runs the query on a read replica: if it is marked so, or else if it is a
+    /// SELECT that does not lock or write.
+    pub fn uses_replica(&self) -> bool {
+        self.replica.unwrap_or_else(|| is_read_only(&self.sql))
+    }
+
+    /// The SQL text of the query.
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// The SQL text sent to a database of the protocol, with `?` placeholders for MySQL when
+    /// the query is `numbered`.
+    pub fn sql_for(&self, protocol: Protocol) -> Cow<'_, str> {
+        sql_for(&self.sql, self.numbered, protocol)
+    }
+
+    /// The parameters bound to the query, encoded.
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// Map the rows of the query into `T`.
+    pub fn typed<T: FromRow>(self) -> TypedQuery<'q, T> {
+        TypedQuery { query: self, _row: PhantomData }
+    }
+
     /// Execute the query and return all rows as raw maps.
     pub async fn fetch_all(self, conn: &mut DbConnection) -> Result<Vec<HashMap<String, String>>, DbError> {
-        match conn.execute_query(self.sql, self.params).await? {
+        let sql = sql_for(&self.sql, self.numbered, conn.protocol);
+        match conn.execute_query_cancellable(&sql, self.params, self.timeout, self.cancel.as_ref()).await? {
             QueryResult::Rows(rows) => Ok(rows),
             QueryResult::Count(_) | QueryResult::Empty => Ok(Vec::new()),
             QueryResult::Error(e) => Err(e),
@@ -54,16 +177,29 @@ impl<'q> SqlQuery<'q> {
 
     /// Execute the query as a command, returning the affected row count.
     pub async fn execute(self, conn: &mut DbConnection) -> Result<usize, DbError> {
-        match conn.execute_query(self.sql, self.params).await? {
-            QueryResult::Count(n) => Ok(n),
-            _ => Ok(0),
+        let sql = sql_for(&self.sql, self.numbered, conn.protocol);
+        let count = match conn.execute_query_cancellable(&sql, self.params, self.timeout, self.cancel.as_ref()).await? {
+            QueryResult::Count(n) => n,
+            _ => 0,
+        };
+        check_version(self.versioned, count)
+    }
+
+    /// A connection of the pool for the query, see `uses_replica`.
+    async fn pooled_connection(&self, pool: &SqlPool) -> Result<PooledSqlConnection, DbError> {
+        if self.uses_replica() {
+            pool.get_read().await
+        } else {
+            pool.get().await
         }
     }
 
     /// Execute and fetch all rows using an async SqlPool.
     pub async fn fetch_all_pool(self, pool: &SqlPool) -> Result<Vec<HashMap<String, String>>, DbError> {
-        let mut pooled = pool.get().await?;
-        match pooled.connection().execute_query(self.sql, self.params).await? {
+        let mut pooled = self.pooled_connection(pool).await?;
+        let conn = pooled.connection();
+        let sql = sql_for(&self.sql, self.numbered, conn.protocol);
+        match conn.execute_query_cancellable(&sql, self.params, self.timeout, self.cancel.as_ref()).await? {
             QueryResult::Rows(rows) => Ok(rows),
             QueryResult::Count(_) | QueryResult::Empty => Ok(Vec::new()),
             QueryResult::Error(e) => Err(e),
@@ -78,13 +214,12 @@ impl<'q> SqlQuery<'q> {
 
     /// Execute command using an async SqlPool, returning affected row count.
     pub async fn execute_pool(self, pool: &SqlPool) -> Result<usize, DbError> {
-        let mut pooled = pool.get().await?;
-        let result = pooled.connection().execute_query(self.sql, self.params).await?;
-        if let QueryResult::Count(n) = result {
-            Ok(n)
-        } else {
-            Ok(0)
-        }
+        let mut pooled = self.pooled_connection(pool).await?;
+        let conn = pooled.connection();
+        let sql = sql_for(&self.sql, self.numbered, conn.protocol);
+        let result = conn.execute_query_cancellable(&sql, self.params, self.timeout, self.cancel.as_ref()).await?;
+        let count = if let QueryResult::Count(n) = result { n } else { 0 };
+        check_version(self.versioned, count)
     }
 
     /// Execute and map all rows via FromRow using an async SqlPool.
@@ -98,4 +233,247 @@ impl<'q> SqlQuery<'q> {
         let row = self.fetch_one_pool(pool).await?;
         T::from_row(&row)
     }
-} 
\ No newline at end of file
+} 
+/// A query whose rows are mapped into `T`, generated by the `sql!` macro when it names a type
+/// or selects named columns.
+pub struct TypedQuery<'q, T> {
+    query: SqlQuery<'q>,
+    _row: PhantomData<fn() -> T>,
+}
+
+impl<'q, T: FromRow> TypedQuery<'q, T> {
+    /// The untyped query, returning the rows as maps.
+    pub fn into_query(self) -> SqlQuery<'q> {
+        self.query
+    }
+
+    /// The SQL text of the query.
+    pub fn sql(&self) -> &str {
+        self.query.sql()
+    }
+
+    /// Run the query on a read replica of a pool, see `SqlQuery::on_replica`.
+    pub fn on_replica(self) -> Self {
+        self.query.on_replica().typed()
+    }
+
+    /// Run the query on the primary of a pool, see `SqlQuery::on_primary`.
+    pub fn on_primary(self) -> Self {
+        self.query.on_primary().typed()
+    }
+
+    /// Stop the query once it runs longer than the duration, see `SqlQuery::timeout`.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.query.timeout(timeout).typed()
+    }
+
+    /// Stop the query once the token is cancelled, see `SqlQuery::cancel_on`.
+    pub fn cancel_on(self, token: CancellationToken) -> Self {
+        self.query.cancel_on(token).typed()
+    }
+
+    /// Restrict the query to one page, see `SqlQuery::paginate`.
+    pub fn paginate(self, pagination: &Pagination) -> Self {
+        self.query.paginate(pagination).typed()
+    }
+
+    /// Leave out the soft-deleted rows, see `SqlQuery::soft_delete`.
+    pub fn soft_delete(self, column: &str) -> Self {
+        self.query.soft_delete(column).typed()
+    }
+
+    /// Execute the query and map all rows.
+    pub async fn fetch_all(self, conn: &mut DbConnection) -> Result<Vec<T>, DbError> {
+        self.query.fetch_all_as(conn).await
+    }
+
+    /// Execute the query and map the first row.
+    pub async fn fetch_one(self, conn: &mut DbConnection) -> Result<T, DbError> {
+        self.query.fetch_one_as(conn).await
+    }
+
+    /// Execute and map all rows using an async SqlPool.
+    pub async fn fetch_all_pool(self, pool: &SqlPool) -> Result<Vec<T>, DbError> {
+        self.query.fetch_all_as_pool(pool).await
+    }
+
+    /// Execute and map the first row using an async SqlPool.
+    pub async fn fetch_one_pool(self, pool: &SqlPool) -> Result<T, DbError> {
+        self.query.fetch_one_as_pool(pool).await
+    }
+}
+
+/// The clauses that may follow the WHERE clause of a statement
+const AFTER_WHERE: [&str; 11] =
+    ["GROUP", "HAVING", "WINDOW", "ORDER", "LIMIT", "OFFSET", "RETURNING", "FOR", "UNION", "INTERSECT", "EXCEPT"];
+
+fn check_column(column: &str) {
+    assert!(
+        column.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$')),
+        "Invalid column {}",
+        column
+    );
+}
+
+fn check_version(versioned: bool, count: usize) -> Result<usize, DbError> {
+    if versioned && count == 0 {
+        Err(DbError::ConflictError("The row was changed or removed since it was read".into()))
+    } else {
+        Ok(count)
+    }
+}
+
+/// The SQL of a query for the protocol. MySQL only takes `?` placeholders, bound in order, so
+/// the `$n` of a numbered query are replaced outside of the quotes and comments
+fn sql_for(sql: &str, numbered: bool, protocol: Protocol) -> Cow<'_, str> {
+    if !numbered || protocol != Protocol::MySQL {
+        return Cow::Borrowed(sql);
+    }
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let mut out = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut prev = ' ';
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        out.push(c);
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            prev = c;
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                out.push(chars.next().expect("peeked"));
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    out.push(c);
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            '$' if !is_word(prev) && chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                out.pop();
+                out.push('?');
+                while chars.next_if(|c| c.is_ascii_digit()).is_some() {}
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    Cow::Owned(out)
+}
+
+/// The SQL in uppercase, with the content of the quotes and of the parentheses blanked so only 
+/// the keywords of the statement itself are found. Byte offsets are kept
+fn statement_mask(sql: &str) -> Vec<u8> {
+    let mut mask = sql.as_bytes().to_ascii_uppercase();
+    let mut quote = None;
+    let mut depth = 0usize;
+    for (i, &b) in sql.as_bytes().iter().enumerate() {
+        if let Some(q) = quote {
+            if b == q {
+                quote = None;
+            } else {
+                mask[i] = b' ';
+            }
+            continue;
+        }
+        match b {
+            b'\'' | b'"' | b'`' => quote = Some(b),
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => mask[i] = b' ',
+            _ => {}
+        }
+    }
+    mask
+}
+
+/// The offset of the first of the keywords at or after `from`
+fn find_keyword(mask: &[u8], keywords: &[&str], from: usize) -> Option<usize> {
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'$';
+    (from..mask.len()).find(|&i| {
+        (i == 0 || !is_word(mask[i - 1]))
+            && keywords.iter().any(|keyword| {
+                mask[i..].starts_with(keyword.as_bytes()) && mask.get(i + keyword.len()).is_none_or(|&b| !is_word(b))
+            })
+    })
+}
+
+fn is_statement(sql: &str, keyword: &str) -> bool {
+    find_keyword(&statement_mask(sql), &[keyword], 0).is_some_and(|i| sql[..i].trim().is_empty())
+}
+
+/// The statement without its trailing `;`
+fn statement(sql: &str) -> &str {
+    sql.trim_end().trim_end_matches(';').trim_end()
+}
+
+/// Whether the statement only reads. Words that may write or lock anywhere in it send it to the
+/// primary, even in a string, which is only slower
+fn is_read_only(sql: &str) -> bool {
+    (is_statement(sql, "SELECT") || is_statement(sql, "WITH"))
+        && find_keyword(
+            sql.to_ascii_uppercase().as_bytes(),
+            &["INSERT", "UPDATE", "DELETE", "MERGE", "INTO", "SHARE", "NEXTVAL", "SETVAL"],
+            0,
+        )
+        .is_none()
+}
+
+/// Adds the condition to the WHERE clause of the statement, or adds one
+fn add_condition(sql: &str, condition: &str) -> String {
+    let sql = statement(sql);
+    let mask = statement_mask(sql);
+    match find_keyword(&mask, &["WHERE"], 0) {
+        Some(start) => {
+            let start = start + "WHERE".len();
+            let end = find_keyword(&mask, &AFTER_WHERE, start).unwrap_or(sql.len());
+            let rest = &sql[end..];
+            format!("{} ({}) AND {}{}{}", &sql[..start], sql[start..end].trim(), condition, if rest.is_empty() { "" } else { " " }, rest)
+        }
+        None => {
+            let from = find_keyword(&mask, &["FROM"], 0).unwrap_or(0);
+            let end = find_keyword(&mask, &AFTER_WHERE, from).unwrap_or(sql.len());
+            let rest = &sql[end..];
+            format!("{} WHERE {}{}{}", sql[..end].trim_end(), condition, if rest.is_empty() { "" } else { " " }, rest)
+        }
+    }
+}
+
+/// Adds an assignment to the SET clause of an UPDATE
+fn append_assignment(sql: &str, assignment: &str) -> String {
+    let sql = statement(sql);
+    let mask = statement_mask(sql);
+    let Some(set) = find_keyword(&mask, &["SET"], 0) else {
+        return sql.to_string();
+    };
+    let end = find_keyword(&mask, &["FROM", "WHERE", "RETURNING", "ORDER", "LIMIT"], set).unwrap_or(sql.len());
+    format!("{}, {} {}", sql[..end].trim_end(), assignment, &sql[end..]).trim_end().to_string()
+}
+
+/// Turns `DELETE FROM table ...` into `UPDATE table SET assignment ...`
+fn delete_to_update(sql: &str, assignment: &str) -> String {
+    let sql = statement(sql);
+    let mask = statement_mask(sql);
+    let Some(from) = find_keyword(&mask, &["FROM"], 0) else {
+        return sql.to_string();
+    };
+    let table_start = from + "FROM".len();
+    let table_start = table_start + sql[table_start..].len() - sql[table_start..].trim_start().len();
+    let table_end = sql[table_start..].find(char::is_whitespace).map_or(sql.len(), |i| table_start + i);
+    format!("UPDATE {} SET {}{}", &sql[table_start..table_end], assignment, &sql[table_end..])
+}
@@ -0,0 +1,306 @@
+//! Bulk loads and exports: multi-row inserts for PostgreSQL and MySQL, and the `COPY` protocol
+//! of PostgreSQL streaming data from an `AsyncRead` or into an `AsyncWrite`.
+//!
+//! # Example
+//! ```rust,ignore
+//! let rows = users.iter().map(|u| vec![u.id.to_string(), u.name.clone()]).collect();
+//! conn.insert_many("users", &["id", "name"], rows).await?;
+//!
+//! let file = tokio::fs::File::open("users.tsv").await?;
+//! conn.copy_in("COPY users (id, name) FROM STDIN", file).await?;
+//!
+//! let mut out = tokio::fs::File::create("export.csv").await?;
+//! conn.copy_out("COPY users TO STDOUT WITH (FORMAT csv, HEADER)", &mut out).await?;
+//! ```
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use starberry_core::connection::{Connection as GenericConnection, Protocol};
+
+use super::connection::DbConnection;
+use super::error::DbError;
+use super::pool::SqlPool;
+
+/// The most parameters of a statement, for both PostgreSQL and MySQL
+const MAX_PARAMS: usize = 65535;
+/// The most rows inserted by a statement of `insert_many`
+const MAX_ROWS_PER_STATEMENT: usize = 1000;
+/// The size of the `CopyData` messages sent by `copy_in`
+const COPY_CHUNK: usize = 64 * 1024;
+
+/// Quotes a table or column name, possibly schema qualified. Each part may only contain letters,
+/// digits, `_` and `$`
+fn quote_identifier(name: &str, mysql: bool) -> Result<String, DbError> {
+    let quote = if mysql { '`' } else { '"' };
+    name.split('.')
+        .map(|part| {
+            if part.is_empty() || !part.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '$') {
+                Err(DbError::QueryError(format!("Invalid identifier {}", name)))
+            } else {
+                Ok(format!("{}{}{}", quote, part, quote))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|parts| parts.join("."))
+}
+
+/// An `INSERT` of the rows, with `$n` or, for MySQL, `?` placeholders
+fn insert_statement(head: &str, rows: Vec<Vec<String>>, mysql: bool) -> (String, Vec<String>) {
+    let mut sql = head.to_string();
+    let mut params = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        sql.push_str(if i == 0 { "(" } else { ", (" });
+        for (j, value) in row.into_iter().enumerate() {
+            if j > 0 {
+                sql.push_str(", ");
+            }
+            params.push(value);
+            if mysql {
+                sql.push('?');
+            } else {
+                sql.push_str(&format!("${}", params.len()));
+            }
+        }
+        sql.push(')');
+    }
+    (sql, params)
+}
+
+/// Formats a row for `COPY ... FROM STDIN` in the default text format: the values separated by
+/// tabs and escaped, `None` written as `\N`, followed by a newline
+pub fn copy_row(values: &[Option<&str>]) -> String {
+    let mut line = String::new();
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            line.push('\t');
+        }
+        match value {
+            Some(value) => {
+                for c in value.chars() {
+                    match c {
+                        '\\' => line.push_str("\\\\"),
+                        '\t' => line.push_str("\\t"),
+                        '\n' => line.push_str("\\n"),
+                        '\r' => line.push_str("\\r"),
+                        c => line.push(c),
+                    }
+                }
+            }
+            None => line.push_str("\\N"),
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Writes a frontend message: its type, its length and its body
+async fn send_message(stream: &mut GenericConnection, tag: u8, body: &[u8]) -> Result<(), DbError> {
+    let mut message = Vec::with_capacity(body.len() + 5);
+    message.push(tag);
+    message.extend_from_slice(&((body.len() + 4) as u32).to_be_bytes());
+    message.extend_from_slice(body);
+    stream.write_all(&message).await.map_err(|e| DbError::ProtocolError(e.to_string()))
+}
+
+/// Reads a backend message, returning its type and its body
+async fn read_message(stream: &mut GenericConnection) -> Result<(u8, Vec<u8>), DbError> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).await.map_err(|e| DbError::ProtocolError(e.to_string()))?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let mut body = vec![0u8; len.saturating_sub(4)];
+    stream.read_exact(&mut body).await.map_err(|e| DbError::ProtocolError(e.to_string()))?;
+    Ok((header[0], body))
+}
+
+fn error_message(body: &[u8]) -> DbError {
+    DbError::QueryError(String::from_utf8_lossy(&body[..body.len().saturating_sub(1)]).to_string())
+}
+
+/// The row count of a `COPY n` CommandComplete
+fn copy_count(body: &[u8]) -> Option<u64> {
+    String::from_utf8_lossy(&body[..body.len().saturating_sub(1)])
+        .split_whitespace()
+        .last()
+        .and_then(|n| n.parse().ok())
+}
+
+/// Reads the messages until ReadyForQuery, returning the row count of the command or its error
+async fn finish(stream: &mut GenericConnection, mut error: Option<DbError>) -> Result<u64, DbError> {
+    let mut count = 0;
+    loop {
+        let (tag, body) = read_message(stream).await?;
+        match tag {
+            b'C' => count = copy_count(&body).unwrap_or(0),
+            b'E' if error.is_none() => error = Some(error_message(&body)),
+            b'Z' => break,
+            _ => {}
+        }
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(count),
+    }
+}
+
+impl DbConnection {
+    /// Inserts the rows into the columns of the table, returning the number of rows inserted.
+    /// The values are encoded strings, as given to `SqlQuery::bind`. The rows are sent in
+    /// statements of up to 1000 rows and 65535 values; run it in a transaction to insert all of
+    /// them or none. The names are quoted, so they are case sensitive in PostgreSQL
+    pub async fn insert_many(&mut self, table: &str, columns: &[&str], rows: Vec<Vec<String>>) -> Result<usize, DbError> {
+        if columns.is_empty() {
+            return Err(DbError::QueryError("insert_many needs at least one column".to_string()));
+        }
+        if let Some(row) = rows.iter().find(|row| row.len() != columns.len()) {
+            return Err(DbError::QueryError(format!(
+                "Expected {} values per row, found {}",
+                columns.len(),
+                row.len()
+            )));
+        }
+        let mysql = self.protocol == Protocol::MySQL;
+        let columns = columns
+            .iter()
+            .map(|column| quote_identifier(column, mysql))
+            .collect::<Result<Vec<_>, _>>()?;
+        let head = format!("INSERT INTO {} ({}) VALUES ", quote_identifier(table, mysql)?, columns.join(", "));
+        let batch = (MAX_PARAMS / columns.len()).clamp(1, MAX_ROWS_PER_STATEMENT);
+
+        let mut inserted = 0;
+        let mut rows = rows.into_iter().peekable();
+        while rows.peek().is_some() {
+            let (sql, params) = insert_statement(&head, rows.by_ref().take(batch).collect(), mysql);
+            inserted += self.execute_query(&sql, params).await?.row_count();
+        }
+        Ok(inserted)
+    }
+
+    /// Runs a `COPY ... FROM STDIN` statement of PostgreSQL, streaming the data from the reader
+    /// in the format of the statement, e.g. lines made with `copy_row`. Returns the number of
+    /// rows copied. If the reader fails, the copy is aborted and nothing is inserted
+    pub async fn copy_in<R: AsyncRead + Unpin>(&mut self, statement: &str, mut reader: R) -> Result<u64, DbError> {
+        let stream = self.copy_stream()?;
+        let mut query = statement.as_bytes().to_vec();
+        query.push(0);
+        send_message(stream, b'Q', &query).await?;
+        stream.flush().await.map_err(|e| DbError::ProtocolError(e.to_string()))?;
+        loop {
+            let (tag, body) = read_message(stream).await?;
+            match tag {
+                // CopyInResponse
+                b'G' => break,
+                b'E' => return finish(stream, Some(error_message(&body))).await,
+                b'Z' => return Err(DbError::QueryError("Expected a COPY ... FROM STDIN statement".to_string())),
+                _ => {}
+            }
+        }
+
+        let mut chunk = vec![0u8; COPY_CHUNK];
+        loop {
+            match reader.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(n) => send_message(stream, b'd', &chunk[..n]).await?,
+                Err(e) => {
+                    // CopyFail, the server rolls the copy back
+                    let mut reason = format!("Reading the data failed: {}", e).into_bytes();
+                    reason.push(0);
+                    send_message(stream, b'f', &reason).await?;
+                    stream.flush().await.map_err(|e| DbError::ProtocolError(e.to_string()))?;
+                    return finish(stream, Some(DbError::QueryError(e.to_string()))).await;
+                }
+            }
+        }
+        // CopyDone
+        send_message(stream, b'c', &[]).await?;
+        stream.flush().await.map_err(|e| DbError::ProtocolError(e.to_string()))?;
+        finish(stream, None).await
+    }
+
+    /// Runs a `COPY ... TO STDOUT` statement of PostgreSQL, streaming the data into the writer.
+    /// Returns the number of rows copied
+    pub async fn copy_out<W: AsyncWrite + Unpin>(&mut self, statement: &str, writer: &mut W) -> Result<u64, DbError> {
+        let stream = self.copy_stream()?;
+        let mut query = statement.as_bytes().to_vec();
+        query.push(0);
+        send_message(stream, b'Q', &query).await?;
+        stream.flush().await.map_err(|e| DbError::ProtocolError(e.to_string()))?;
+
+        let mut count = 0;
+        let mut copying = false;
+        let mut error = None;
+        loop {
+            let (tag, body) = read_message(stream).await?;
+            match tag {
+                // CopyOutResponse
+                b'H' => copying = true,
+                // CopyData; after a failed write the rest is read, to keep the connection usable
+                b'd' if error.is_none() => {
+                    if let Err(e) = writer.write_all(&body).await {
+                        error = Some(DbError::OtherError(format!("Writing the data failed: {}", e)));
+                    }
+                }
+                b'C' => count = copy_count(&body).unwrap_or(0),
+                b'E' if error.is_none() => error = Some(error_message(&body)),
+                b'Z' => break,
+                _ => {}
+            }
+        }
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if !copying {
+            return Err(DbError::QueryError("Expected a COPY ... TO STDOUT statement".to_string()));
+        }
+        writer.flush().await.map_err(|e| DbError::OtherError(format!("Writing the data failed: {}", e)))?;
+        Ok(count)
+    }
+
+    fn copy_stream(&mut self) -> Result<&mut GenericConnection, DbError> {
+        if self.protocol != Protocol::Postgres {
+            return Err(DbError::QueryError("COPY is only supported by PostgreSQL".to_string()));
+        }
+        self.stream
+            .as_mut()
+            .ok_or_else(|| DbError::ConnectionError("No active connection".into()))
+    }
+}
+
+impl SqlPool {
+    /// Inserts the rows on a pooled connection, see `DbConnection::insert_many`
+    pub async fn insert_many(&self, table: &str, columns: &[&str], rows: Vec<Vec<String>>) -> Result<usize, DbError> {
+        let mut pooled = self.get().await?;
+        pooled.connection().insert_many(table, columns, rows).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("public.users", false).unwrap(), r#""public"."users""#);
+        assert_eq!(quote_identifier("order", true).unwrap(), "`order`");
+        assert!(quote_identifier("users; DROP TABLE users", false).is_err());
+        assert!(quote_identifier("a\"b", false).is_err());
+        assert!(quote_identifier("users.", false).is_err());
+    }
+
+    #[test]
+    fn test_insert_statement() {
+        let rows = vec![
+            vec!["1".to_string(), "alice".to_string()],
+            vec!["2".to_string(), "bob".to_string()],
+        ];
+        let (sql, params) = insert_statement("INSERT INTO \"users\" (\"id\", \"name\") VALUES ", rows.clone(), false);
+        assert_eq!(sql, "INSERT INTO \"users\" (\"id\", \"name\") VALUES ($1, $2), ($3, $4)");
+        assert_eq!(params, vec!["1", "alice", "2", "bob"]);
+        let (sql, _) = insert_statement("INSERT INTO `users` (`id`, `name`) VALUES ", rows, true);
+        assert_eq!(sql, "INSERT INTO `users` (`id`, `name`) VALUES (?, ?), (?, ?)");
+    }
+
+    #[test]
+    fn test_copy_row() {
+        assert_eq!(copy_row(&[Some("1"), Some("a\tb\\c\nd"), None]), "1\ta\\tb\\\\c\\nd\t\\N\n");
+        assert_eq!(copy_count(b"COPY 42\0"), Some(42));
+    }
+}
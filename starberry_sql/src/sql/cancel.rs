@@ -0,0 +1,126 @@
+//! Timeouts and cancellation of running queries. An interrupted query is stopped on the server,
+//! with a `CancelRequest` for PostgreSQL or `KILL QUERY` for MySQL, so an abandoned request does
+//! not keep it running.
+//!
+//! # Example
+//! ```rust,ignore
+//! let posts = SqlQuery::new("SELECT * FROM posts WHERE author = $1")
+//!     .bind(author)
+//!     .timeout(Duration::from_secs(2))
+//!     .cancel_on(req.cancellation_token())
+//!     .fetch_all_pool(&pool)
+//!     .await?;
+//! ```
+
+use std::future::pending;
+use std::time::Duration;
+use starberry_core::connection::{CancellationToken, Protocol};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use super::connection::DbConnection;
+use super::error::DbError;
+use super::mysql;
+use super::query::QueryResult;
+
+/// The request code of a PostgreSQL `CancelRequest`
+const CANCEL_REQUEST_CODE: u32 = 80877102;
+/// How long stopping a query on the server may take
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A PostgreSQL `CancelRequest` for the query of a backend
+fn cancel_request(process_id: u32, secret_key: u32) -> [u8; 16] {
+    let mut message = [0u8; 16];
+    message[..4].copy_from_slice(&16u32.to_be_bytes());
+    message[4..8].copy_from_slice(&CANCEL_REQUEST_CODE.to_be_bytes());
+    message[8..12].copy_from_slice(&process_id.to_be_bytes());
+    message[12..].copy_from_slice(&secret_key.to_be_bytes());
+    message
+}
+
+fn cancelled() -> DbError {
+    DbError::CancelledError("The request was cancelled".into())
+}
+
+impl DbConnection {
+    /// Executes the query, stopping it once it runs longer than `timeout`, or the `query_timeout`
+    /// of the connection if `None`, or once the token is cancelled.
+    /// An interrupted query closes the connection, as its response is left unread.
+    pub async fn execute_query_cancellable(
+        &mut self,
+        query: &str,
+        params: Vec<String>,
+        timeout: Option<Duration>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<QueryResult, DbError> {
+        let timeout = timeout.or(self.config.query_timeout);
+        if cancel.is_some_and(|token| token.is_cancelled()) {
+            return Err(cancelled());
+        }
+        if timeout.is_none() && cancel.is_none() {
+            return self.execute_query(query, params).await;
+        }
+        let deadline = async move {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => pending().await,
+            }
+        };
+        let cancellation = async move {
+            match cancel {
+                Some(token) => token.cancelled().await,
+                None => pending().await,
+            }
+        };
+        let error = tokio::select! {
+            result = self.execute_query(query, params) => return result,
+            _ = deadline => DbError::TimeoutError(format!("The query ran longer than {:?}", timeout.unwrap_or_default())),
+            _ = cancellation => cancelled(),
+        };
+        self.abandon_query().await;
+        Err(error)
+    }
+
+    /// Stops the query running on this connection, from a new connection to the server
+    async fn cancel_on_server(&self) -> Result<(), DbError> {
+        let key = self
+            .backend_key
+            .ok_or_else(|| DbError::OtherError("The server gave no key to cancel the query".into()))?;
+        let cancel = async {
+            if self.protocol == Protocol::MySQL {
+                return mysql::kill_query(&self.config, key.process_id).await;
+            }
+            let mut stream = TcpStream::connect((self.config.host.as_str(), self.config.port)).await?;
+            stream.write_all(&cancel_request(key.process_id, key.secret_key)).await?;
+            stream.shutdown().await?;
+            Ok(())
+        };
+        tokio::time::timeout(CANCEL_TIMEOUT, cancel)
+            .await
+            .map_err(|_| DbError::TimeoutError("Cancelling the query timed out".into()))?
+    }
+
+    /// Stops the interrupted query and closes the connection, whose stream stopped in the
+    /// middle of a response
+    async fn abandon_query(&mut self) {
+        // The connection is closed either way, a query the server could not stop ends with it
+        let _ = self.cancel_on_server().await;
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.shutdown().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_request() {
+        let message = cancel_request(1234, 0xdeadbeef);
+        assert_eq!(&message[..4], &[0, 0, 0, 16]);
+        assert_eq!(&message[4..8], &[0x04, 0xd2, 0x16, 0x2e]);
+        assert_eq!(&message[8..12], &1234u32.to_be_bytes());
+        assert_eq!(&message[12..], &[0xde, 0xad, 0xbe, 0xef]);
+    }
+}
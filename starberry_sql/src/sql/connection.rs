@@ -1,15 +1,18 @@
 use std::time::Duration;
 use starberry_core::connection::{Protocol, ConnectionBuilder, Connection as GenericConnection};
 use super::error::DbError;
+use super::mysql;
 use md5;
 use starberry_lib::random_alphanumeric_string;
-use base64::{engine::general_purpose, Engine as _};
+use starberry_lib::encoding::{base64_decode, base64_encode};
 use ring::{digest, hmac, pbkdf2};
 use std::num::NonZeroU32;
 use async_trait::async_trait;
 use starberry_core::connection::Tx;
+use starberry_core::app::secrets::SecretsProvider;
 
-/// Represents PostgreSQL SSL mode options for connection.
+/// Represents SSL mode options for connection. MySQL uses TLS when the server supports it
+/// unless the mode is `Disable`, and `Require`, `VerifyCa` and `VerifyFull` fail without it.
 #[derive(Debug, Clone, PartialEq)]
 pub enum SslMode {
     Disable,  // No SSL
@@ -19,21 +22,21 @@ pub enum SslMode {
     VerifyFull, // Require SSL, verify server certificate and hostname
 }
 
-/// Represents a database connection configuration with PostgreSQL specifics.
+/// Represents a database connection configuration, for PostgreSQL or, with `mysql`, MySQL.
 #[derive(Debug, Clone)]
 pub struct DbConnectionBuilder {
-    host: String,
-    port: u16,
+    pub(super) host: String,
+    pub(super) port: u16,
     protocol: Protocol,
-    database: Option<String>,
-    username: Option<String>,
-    password: Option<String>,
-    max_connection_time: Option<Duration>,
-    query_timeout: Option<Duration>,
-    ssl_mode: Option<SslMode>,
+    pub(super) database: Option<String>,
+    pub(super) username: Option<String>,
+    pub(super) password: Option<String>,
+    pub(super) max_connection_time: Option<Duration>,
+    pub(super) query_timeout: Option<Duration>,
+    pub(super) ssl_mode: Option<SslMode>,
     ssl_cert: Option<String>,  // Path to client certificate
     ssl_key: Option<String>,   // Path to client private key
-    ssl_root_cert: Option<String>,  // Path to server CA certificate
+    pub(super) ssl_root_cert: Option<String>,  // Path to server CA certificate
 }
 
 impl DbConnectionBuilder {
@@ -55,6 +58,16 @@ impl DbConnectionBuilder {
         }
     }
 
+    /// Creates a builder for a MySQL or MariaDB server, without a default database.
+    /// Its queries use `?` placeholders instead of `$1`.
+    pub fn mysql(host: &str, port: u16) -> Self {
+        Self {
+            protocol: Protocol::MySQL,
+            database: None,
+            ..Self::new(host, port)
+        }
+    }
+
     /// Sets the protocol for the database connection.
     pub fn protocol(mut self, protocol: Protocol) -> Self {
         self.protocol = protocol;
@@ -79,13 +92,23 @@ impl DbConnectionBuilder {
         self
     }
 
+    /// Sets the password for the database connection, read from a secrets provider.
+    pub async fn password_from_secrets(self, secrets: &dyn SecretsProvider, name: &str) -> Result<Self, DbError> {
+        let password = secrets
+            .require(name)
+            .await
+            .map_err(|e| DbError::ConnectionError(e.to_string()))?;
+        Ok(self.password(password.expose()))
+    }
+
     /// Sets the maximum connection time for the database connection.
     pub fn max_connection_time(mut self, duration: Duration) -> Self {
         self.max_connection_time = Some(duration);
         self
     }
 
-    /// Sets the query timeout for the database connection.
+    /// Sets the default timeout of the queries run through `SqlQuery`. A query running longer
+    /// is cancelled on the server and fails with `DbError::TimeoutError`.
     pub fn query_timeout(mut self, duration: Duration) -> Self {
         self.query_timeout = Some(duration);
         self
@@ -117,6 +140,10 @@ impl DbConnectionBuilder {
 
     /// Attempts to establish a connection to the database with PostgreSQL specifics.
     pub async fn connect(&self) -> Result<DbConnection, DbError> {
+        if self.protocol == Protocol::MySQL {
+            let (conn, id) = mysql::connect(self).await?;
+            return Ok(self.connection(conn, Some(BackendKey { process_id: id, secret_key: 0 })));
+        }
         // Use the generic ConnectionBuilder for TCP/TLS and handshake
         let mut builder = ConnectionBuilder::new(&self.host, self.port)
             .protocol(Protocol::Postgres)
@@ -132,6 +159,7 @@ impl DbConnectionBuilder {
         // Establish connection and map errors
         // Raw connection
         let mut conn = builder.connect().await.map_err(|e| DbError::ConnectionError(e.to_string()))?;
+        let mut backend_key = None;
         // Perform PostgreSQL startup handshake
         {
             use tokio::io::{AsyncWriteExt, AsyncReadExt};
@@ -276,7 +304,7 @@ impl DbConnectionBuilder {
                                     else if let Some(rest) = part.strip_prefix("s=") { salt_b64 = rest; }
                                     else if let Some(rest) = part.strip_prefix("i=") { iter = rest.parse::<u32>().map_err(|e| DbError::ProtocolError(e.to_string()))?; }
                                 }
-                                let salt = general_purpose::STANDARD.decode(salt_b64).map_err(|e| DbError::ProtocolError(e.to_string()))?;
+                                let salt = base64_decode(salt_b64).map_err(|e| DbError::ProtocolError(e.to_string()))?;
                                 let pw = self.password.as_ref().unwrap();
                                 // Derive salted password using ring
                                 let mut salted_password = [0u8; 32];
@@ -290,7 +318,7 @@ impl DbConnectionBuilder {
                                 scram_server_key = Some(server_key_raw.as_ref().to_vec());
                                 // Client-final-message without proof
                                 let gs2 = "n,,";
-                                let channel_binding = general_purpose::STANDARD.encode(gs2.as_bytes());
+                                let channel_binding = base64_encode(gs2.as_bytes());
                                 let client_final_without_proof = format!("c={},r={}", channel_binding, server_nonce);
                                 // Auth message
                                 let auth_msg = format!("{},{},{}", client_first_bare, server_first, client_final_without_proof);
@@ -301,7 +329,7 @@ impl DbConnectionBuilder {
                                 let client_signature = client_signature_raw.as_ref();
                                 // Client proof
                                 let client_proof: Vec<u8> = client_key.iter().zip(client_signature.iter()).map(|(&x, &y)| x ^ y).collect();
-                                let proof_b64 = general_purpose::STANDARD.encode(&client_proof);
+                                let proof_b64 = base64_encode(&client_proof);
                                 let client_final_msg = format!("{},p={}", client_final_without_proof, proof_b64);
                                 // Send SASLResponse
                                 let mut msg3 = Vec::new();
@@ -323,7 +351,7 @@ impl DbConnectionBuilder {
                             }
                             // Expect v=<serverSignature>
                             let server_sig_b64 = server_final.strip_prefix("v=").ok_or_else(|| DbError::ProtocolError("Missing server signature".to_string()))?;
-                            let server_sig = general_purpose::STANDARD.decode(server_sig_b64).map_err(|e| DbError::ProtocolError(e.to_string()))?;
+                            let server_sig = base64_decode(server_sig_b64).map_err(|e| DbError::ProtocolError(e.to_string()))?;
                             // Verify server signature
                             let server_key = scram_server_key.take().ok_or_else(|| DbError::ProtocolError("SCRAM server key missing".to_string()))?;
                             let auth_msg = scram_auth_msg.take().ok_or_else(|| DbError::ProtocolError("SCRAM auth message missing".to_string()))?;
@@ -341,38 +369,61 @@ impl DbConnectionBuilder {
                         let msg = String::from_utf8_lossy(&payload[..payload.len()-1]).to_string();
                         return Err(DbError::QueryError(msg));
                     }
+                    b'K' if payload.len() >= 8 => {
+                        // BackendKeyData, to cancel the queries of this connection
+                        backend_key = Some(BackendKey {
+                            process_id: u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]),
+                            secret_key: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+                        });
+                    }
                     b'Z' => {
                         // ReadyForQuery
                         break;
                     }
                     _ => {
-                        // Ignore other messages (ParameterStatus, BackendKeyData, etc.)
+                        // Ignore other messages (ParameterStatus, NoticeResponse, etc.)
                     }
                 }
             }
         }
         // Return connection with handshake completed
-        Ok(DbConnection {
+        Ok(self.connection(conn, backend_key))
+    }
+
+    fn connection(&self, stream: GenericConnection, backend_key: Option<BackendKey>) -> DbConnection {
+        DbConnection {
             host: self.host.clone(),
             port: self.port,
             protocol: self.protocol.clone(),
             database: self.database.clone(),
             username: self.username.clone(),
             password: self.password.clone(),
-            stream: Some(conn),
-        })
+            stream: Some(stream),
+            config: self.clone(),
+            backend_key,
+        }
     }
 }
 
-/// Represents an active database connection with PostgreSQL specifics.
+/// Identifies a connection on the server, to cancel its running query from another connection.
+/// For MySQL the process id is the connection id and the secret key is unused.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct BackendKey {
+    pub(super) process_id: u32,
+    pub(super) secret_key: u32,
+}
+
+/// Represents an active database connection to PostgreSQL or MySQL.
 pub struct DbConnection {
     host: String,
     port: u16,
-    protocol: Protocol,
+    pub(super) protocol: Protocol,
     database: Option<String>,
     username: Option<String>,
     password: Option<String>,
     pub(super) stream: Option<GenericConnection>,  // Expose stream to sql module for query access
+    pub(super) config: DbConnectionBuilder,
+    pub(super) backend_key: Option<BackendKey>,
 }
 
 impl DbConnection {
@@ -380,11 +431,20 @@ impl DbConnection {
     pub async fn close(&mut self) -> Result<(), DbError> {
         if let Some(mut conn) = self.stream.take() {
             use tokio::io::AsyncWriteExt;
+            if self.protocol == Protocol::MySQL {
+                // The server closes the connection on COM_QUIT, an error here is harmless
+                let _ = mysql::quit(&mut conn).await;
+            }
             conn.shutdown().await.map_err(|e| DbError::ConnectionError(e.to_string()))?;
         }
         Ok(())
     }
 
+    /// Whether the connection is still open, it is closed by `close` or an interrupted query.
+    pub fn is_open(&self) -> bool {
+        self.stream.is_some()
+    }
+
     // Additional methods for database operations will be added in query.rs
 }
 
@@ -49,6 +49,18 @@ impl Encode for f64 {
     }
 }
 
+impl Encode for starberry_lib::uuid::Uuid {
+    fn encode(&self) -> Result<String, DbError> {
+        Ok(self.to_string())
+    }
+}
+
+impl<T: Encode> Encode for &T {
+    fn encode(&self) -> Result<String, DbError> {
+        (**self).encode()
+    }
+}
+
 impl<T: Encode> Encode for Option<T> {
     fn encode(&self) -> Result<String, DbError> {
         match self {
@@ -9,6 +9,10 @@ pub enum DbError {
     QueryError(String),
     TimeoutError(String),
     ProtocolError(String),
+    /// A versioned statement matched no row, the row was changed or removed since it was read
+    ConflictError(String),
+    /// The query was cancelled before it finished
+    CancelledError(String),
     OtherError(String),
 }
 
@@ -19,6 +23,8 @@ impl fmt::Display for DbError {
             DbError::QueryError(msg) => write!(f, "Query error: {}", msg),
             DbError::TimeoutError(msg) => write!(f, "Timeout error: {}", msg),
             DbError::ProtocolError(msg) => write!(f, "Protocol error: {}", msg),
+            DbError::ConflictError(msg) => write!(f, "Conflict error: {}", msg),
+            DbError::CancelledError(msg) => write!(f, "Cancelled: {}", msg),
             DbError::OtherError(msg) => write!(f, "Other database error: {}", msg),
         }
     }
@@ -6,6 +6,11 @@ pub mod encode;
 pub mod builder;
 pub mod pool;
 pub mod context;
+pub mod bulk;
+pub mod cancel;
+pub mod session;
+pub mod outbox;
+mod mysql;
 pub mod test;
 
 pub use connection::*;
@@ -13,7 +18,10 @@ pub use query::*;
 pub use error::*;
 pub use row::*;
 pub use encode::*;
-pub use builder::SqlQuery;
+pub use builder::{SqlQuery, TypedQuery};
 pub use pool::SqlPool;
 pub use context::SqlContext;
+pub use bulk::copy_row;
+pub use session::{SqlSession, SqlSessionRW, SqlSessionStore};
+pub use outbox::{Outbox, OutboxEvent};
 
@@ -0,0 +1,803 @@
+//! The client protocol of MySQL and MariaDB, used by `DbConnectionBuilder` and `DbConnection`
+//! when the protocol is `Protocol::MySQL`.
+//!
+//! Users authenticate with `caching_sha2_password`, the default of MySQL 8, or
+//! `mysql_native_password`, the default of MariaDB. The first login of a `caching_sha2_password`
+//! user after a server restart sends the password, which is only done over TLS. Queries with
+//! parameters run as server prepared statements with `?` placeholders, the others as plain text
+//! queries. Values are returned as strings and NULL as an empty string, like PostgreSQL.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ring::digest;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use starberry_core::connection::{tls_handshake, Connection as GenericConnection, ConnectionBuilder, Protocol};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::TlsConnector;
+
+use super::connection::{DbConnectionBuilder, SslMode};
+use super::error::DbError;
+use super::query::QueryResult;
+
+const CLIENT_LONG_PASSWORD: u32 = 0x0000_0001;
+const CLIENT_CONNECT_WITH_DB: u32 = 0x0000_0008;
+const CLIENT_PROTOCOL_41: u32 = 0x0000_0200;
+const CLIENT_SSL: u32 = 0x0000_0800;
+const CLIENT_TRANSACTIONS: u32 = 0x0000_2000;
+const CLIENT_SECURE_CONNECTION: u32 = 0x0000_8000;
+const CLIENT_MULTI_RESULTS: u32 = 0x0002_0000;
+const CLIENT_PLUGIN_AUTH: u32 = 0x0008_0000;
+const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x0020_0000;
+
+const SERVER_MORE_RESULTS_EXISTS: u16 = 0x0008;
+const UNSIGNED_FLAG: u16 = 0x0020;
+
+/// The largest payload of a packet, longer payloads are split
+const MAX_PAYLOAD: usize = 0xff_ffff;
+/// utf8mb4_general_ci, known to MySQL 5.5+ and MariaDB
+const CHARSET_UTF8MB4: u8 = 45;
+
+const COM_QUIT: u8 = 0x01;
+const COM_QUERY: u8 = 0x03;
+const COM_STMT_PREPARE: u8 = 0x16;
+const COM_STMT_EXECUTE: u8 = 0x17;
+const COM_STMT_CLOSE: u8 = 0x19;
+
+const TYPE_TINY: u8 = 0x01;
+const TYPE_SHORT: u8 = 0x02;
+const TYPE_LONG: u8 = 0x03;
+const TYPE_FLOAT: u8 = 0x04;
+const TYPE_DOUBLE: u8 = 0x05;
+const TYPE_NULL: u8 = 0x06;
+const TYPE_TIMESTAMP: u8 = 0x07;
+const TYPE_LONGLONG: u8 = 0x08;
+const TYPE_INT24: u8 = 0x09;
+const TYPE_DATE: u8 = 0x0a;
+const TYPE_TIME: u8 = 0x0b;
+const TYPE_DATETIME: u8 = 0x0c;
+const TYPE_YEAR: u8 = 0x0d;
+const TYPE_VAR_STRING: u8 = 0xfd;
+
+fn protocol_error(e: std::io::Error) -> DbError {
+    DbError::ProtocolError(e.to_string())
+}
+
+/// Writes a payload as one or more packets, numbered from `seq`
+async fn write_packet(stream: &mut GenericConnection, seq: &mut u8, payload: &[u8]) -> Result<(), DbError> {
+    let mut rest = payload;
+    loop {
+        let len = rest.len().min(MAX_PAYLOAD);
+        let mut packet = Vec::with_capacity(len + 4);
+        packet.extend_from_slice(&(len as u32).to_le_bytes()[..3]);
+        packet.push(*seq);
+        packet.extend_from_slice(&rest[..len]);
+        stream.write_all(&packet).await.map_err(protocol_error)?;
+        *seq = seq.wrapping_add(1);
+        rest = &rest[len..];
+        // A payload of exactly the maximum length is followed by an empty packet
+        if len < MAX_PAYLOAD {
+            break;
+        }
+    }
+    stream.flush().await.map_err(protocol_error)
+}
+
+/// Reads a payload, joining the packets it was split into. `seq` is set to the number of the
+/// next packet
+async fn read_packet(stream: &mut GenericConnection, seq: &mut u8) -> Result<Vec<u8>, DbError> {
+    let mut payload = Vec::new();
+    loop {
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await.map_err(protocol_error)?;
+        let len = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+        *seq = header[3].wrapping_add(1);
+        let start = payload.len();
+        payload.resize(start + len, 0);
+        stream.read_exact(&mut payload[start..]).await.map_err(protocol_error)?;
+        if len < MAX_PAYLOAD {
+            return Ok(payload);
+        }
+    }
+}
+
+/// Reads the fields of a payload
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], DbError> {
+        let bytes = self.pos
+            .checked_add(n)
+            .and_then(|end| self.buf.get(self.pos..end))
+            .ok_or_else(|| DbError::ProtocolError("Truncated MySQL packet".to_string()))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, DbError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DbError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DbError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, DbError> {
+        let b = self.bytes(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    /// A string terminated by a NUL byte, or by the end of the payload
+    fn null_str(&mut self) -> Result<&'a [u8], DbError> {
+        let rest = &self.buf[self.pos.min(self.buf.len())..];
+        match rest.iter().position(|&b| b == 0) {
+            Some(end) => {
+                self.pos += end + 1;
+                Ok(&rest[..end])
+            }
+            None => {
+                self.pos = self.buf.len();
+                Ok(rest)
+            }
+        }
+    }
+
+    /// The remaining bytes of the payload
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.buf[self.pos.min(self.buf.len())..];
+        self.pos = self.buf.len();
+        rest
+    }
+
+    /// A length encoded integer, `None` for the NULL marker
+    fn lenenc_int(&mut self) -> Result<Option<u64>, DbError> {
+        Ok(match self.u8()? {
+            0xfb => None,
+            0xfc => Some(self.u16()? as u64),
+            0xfd => {
+                let b = self.bytes(3)?;
+                Some(u32::from_le_bytes([b[0], b[1], b[2], 0]) as u64)
+            }
+            0xfe => Some(self.u64()?),
+            n => Some(n as u64),
+        })
+    }
+
+    /// A length encoded string, `None` for NULL
+    fn lenenc_bytes(&mut self) -> Result<Option<&'a [u8]>, DbError> {
+        match self.lenenc_int()? {
+            Some(len) => Ok(Some(self.bytes(len as usize)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn put_lenenc_int(buf: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfa => buf.push(n as u8),
+        0xfb..=0xffff => {
+            buf.push(0xfc);
+            buf.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xff_ffff => {
+            buf.push(0xfd);
+            buf.extend_from_slice(&(n as u32).to_le_bytes()[..3]);
+        }
+        _ => {
+            buf.push(0xfe);
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+/// The message of an ERR packet, formatted like the `mysql` client
+fn server_message(packet: &[u8]) -> String {
+    let code = packet.get(1..3).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]));
+    let (state, message) = match packet.get(3) {
+        Some(b'#') if packet.len() >= 9 => (&packet[4..9], &packet[9..]),
+        _ => (&b"HY000"[..], packet.get(3..).unwrap_or_default()),
+    };
+    format!("ERROR {} ({}): {}", code, lossy(state), lossy(message))
+}
+
+/// The initial handshake packet of the server
+#[derive(Debug)]
+struct Greeting {
+    connection_id: u32,
+    capabilities: u32,
+    scramble: Vec<u8>,
+    auth_plugin: String,
+}
+
+fn parse_greeting(packet: &[u8]) -> Result<Greeting, DbError> {
+    let mut r = Reader::new(packet);
+    let version = r.u8()?;
+    if version != 10 {
+        return Err(DbError::ProtocolError(format!("Unsupported MySQL protocol version {}", version)));
+    }
+    r.null_str()?; // server version
+    let connection_id = r.u32()?;
+    let mut scramble = r.bytes(8)?.to_vec();
+    r.u8()?; // filler
+    let mut capabilities = r.u16()? as u32;
+    let mut auth_plugin = "mysql_native_password".to_string();
+    if !r.is_empty() {
+        r.u8()?; // character set
+        r.u16()?; // status flags
+        capabilities |= (r.u16()? as u32) << 16;
+        let scramble_len = r.u8()? as usize;
+        r.bytes(10)?; // reserved
+        if capabilities & CLIENT_SECURE_CONNECTION != 0 {
+            let part = r.bytes(scramble_len.saturating_sub(8).max(13))?;
+            scramble.extend_from_slice(part.strip_suffix(&[0]).unwrap_or(part));
+        }
+        if capabilities & CLIENT_PLUGIN_AUTH != 0 {
+            auth_plugin = lossy(r.null_str()?);
+        }
+    }
+    Ok(Greeting { connection_id, capabilities, scramble, auth_plugin })
+}
+
+fn digest_of(algorithm: &'static digest::Algorithm, parts: &[&[u8]]) -> digest::Digest {
+    let mut context = digest::Context::new(algorithm);
+    for part in parts {
+        context.update(part);
+    }
+    context.finish()
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+/// The scrambled password sent for an authentication plugin
+fn auth_response(plugin: &str, password: &str, scramble: &[u8]) -> Result<Vec<u8>, DbError> {
+    if password.is_empty() {
+        return Ok(Vec::new());
+    }
+    match plugin {
+        // XOR(SHA256(password), SHA256(SHA256(SHA256(password)), scramble))
+        "caching_sha2_password" => {
+            let stage1 = digest_of(&digest::SHA256, &[password.as_bytes()]);
+            let stage2 = digest_of(&digest::SHA256, &[stage1.as_ref()]);
+            let mix = digest_of(&digest::SHA256, &[stage2.as_ref(), scramble]);
+            Ok(xor(stage1.as_ref(), mix.as_ref()))
+        }
+        // XOR(SHA1(password), SHA1(scramble, SHA1(SHA1(password))))
+        "mysql_native_password" => {
+            let stage1 = digest_of(&digest::SHA1_FOR_LEGACY_USE_ONLY, &[password.as_bytes()]);
+            let stage2 = digest_of(&digest::SHA1_FOR_LEGACY_USE_ONLY, &[stage1.as_ref()]);
+            let mix = digest_of(&digest::SHA1_FOR_LEGACY_USE_ONLY, &[scramble, stage2.as_ref()]);
+            Ok(xor(stage1.as_ref(), mix.as_ref()))
+        }
+        _ => Err(DbError::ConnectionError(format!("Unsupported MySQL authentication plugin {}", plugin))),
+    }
+}
+
+/// Upgrades the connection to TLS, trusting the webpki roots and the `ssl_root_cert` if set
+async fn upgrade(builder: &DbConnectionBuilder, conn: GenericConnection) -> Result<GenericConnection, DbError> {
+    let GenericConnection::Tcp(tcp) = conn else {
+        return Err(DbError::ProtocolError("TLS is already established".to_string()));
+    };
+    let Some(path) = &builder.ssl_root_cert else {
+        return Ok(tls_handshake(&builder.host, tcp).await?);
+    };
+    let pem = tokio::fs::read(path).await?;
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for cert in CertificateDer::pem_slice_iter(&pem) {
+        let cert = cert.map_err(|e| DbError::ConnectionError(format!("Invalid ssl_root_cert {}: {}", path, e)))?;
+        roots.add(cert).map_err(|e| DbError::ConnectionError(format!("Invalid ssl_root_cert {}: {}", path, e)))?;
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| DbError::ProtocolError(e.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(builder.host.clone())
+        .map_err(|_| DbError::ConnectionError(format!("Invalid host name {}", builder.host)))?;
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(server_name, tcp)
+        .await
+        .map_err(|e| DbError::ProtocolError(e.to_string()))?;
+    Ok(GenericConnection::Tls(tls))
+}
+
+/// Connects and authenticates to the server, returning the connection and its id on the server.
+/// TLS is used when the server supports it, unless the SSL mode is `Disable`, and required by
+/// the modes `Require`, `VerifyCa` and `VerifyFull`
+pub(super) async fn connect(builder: &DbConnectionBuilder) -> Result<(GenericConnection, u32), DbError> {
+    let mut tcp = ConnectionBuilder::new(&builder.host, builder.port).protocol(Protocol::MySQL);
+    if let Some(timeout) = builder.max_connection_time {
+        tcp = tcp.max_connection_time(timeout);
+    }
+    let mut conn = tcp.connect().await?;
+    let mut seq = 0u8;
+
+    let packet = read_packet(&mut conn, &mut seq).await?;
+    if packet.first() == Some(&0xff) {
+        return Err(DbError::ConnectionError(server_message(&packet)));
+    }
+    let greeting = parse_greeting(&packet)?;
+    if greeting.capabilities & CLIENT_PROTOCOL_41 == 0 {
+        return Err(DbError::ProtocolError("The server does not support the protocol 4.1".to_string()));
+    }
+    let server_tls = greeting.capabilities & CLIENT_SSL != 0;
+    let tls = match builder.ssl_mode {
+        Some(SslMode::Disable) => false,
+        Some(SslMode::Require) | Some(SslMode::VerifyCa) | Some(SslMode::VerifyFull) if !server_tls => {
+            return Err(DbError::ConnectionError("The server does not support TLS".to_string()));
+        }
+        _ => server_tls,
+    };
+
+    let mut capabilities = CLIENT_LONG_PASSWORD
+        | CLIENT_PROTOCOL_41
+        | CLIENT_TRANSACTIONS
+        | CLIENT_SECURE_CONNECTION
+        | CLIENT_MULTI_RESULTS
+        | CLIENT_PLUGIN_AUTH
+        | CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA;
+    if builder.database.is_some() {
+        capabilities |= CLIENT_CONNECT_WITH_DB;
+    }
+    if tls {
+        capabilities |= CLIENT_SSL;
+    }
+    let mut response = Vec::new();
+    response.extend_from_slice(&capabilities.to_le_bytes());
+    response.extend_from_slice(&(MAX_PAYLOAD as u32).to_le_bytes());
+    response.push(CHARSET_UTF8MB4);
+    response.extend_from_slice(&[0u8; 23]);
+    if tls {
+        // SSLRequest, the start of the handshake response
+        write_packet(&mut conn, &mut seq, &response).await?;
+        conn = upgrade(builder, conn).await?;
+    }
+
+    let username = builder.username.as_deref().unwrap_or_default();
+    let password = builder.password.as_deref().unwrap_or_default();
+    let mut scramble = greeting.scramble;
+    let mut plugin = greeting.auth_plugin;
+    let auth = auth_response(&plugin, password, &scramble)?;
+    response.extend_from_slice(username.as_bytes());
+    response.push(0);
+    put_lenenc_int(&mut response, auth.len() as u64);
+    response.extend_from_slice(&auth);
+    if let Some(database) = &builder.database {
+        response.extend_from_slice(database.as_bytes());
+        response.push(0);
+    }
+    response.extend_from_slice(plugin.as_bytes());
+    response.push(0);
+    write_packet(&mut conn, &mut seq, &response).await?;
+
+    loop {
+        let packet = read_packet(&mut conn, &mut seq).await?;
+        match packet.first() {
+            Some(0x00) => break,
+            Some(0xff) => return Err(DbError::ConnectionError(server_message(&packet))),
+            Some(0xfe) => {
+                // AuthSwitchRequest: another plugin, with a new scramble
+                let mut r = Reader::new(&packet[1..]);
+                plugin = lossy(r.null_str()?);
+                let rest = r.rest();
+                scramble = rest.strip_suffix(&[0]).unwrap_or(rest).to_vec();
+                let auth = auth_response(&plugin, password, &scramble)?;
+                write_packet(&mut conn, &mut seq, &auth).await?;
+            }
+            Some(0x01) => match packet.get(1) {
+                // caching_sha2_password found the password in its cache, an OK packet follows
+                Some(0x03) => {}
+                // caching_sha2_password needs the password itself
+                Some(0x04) if tls => {
+                    let mut clear = password.as_bytes().to_vec();
+                    clear.push(0);
+                    write_packet(&mut conn, &mut seq, &clear).await?;
+                }
+                Some(0x04) => {
+                    return Err(DbError::ConnectionError(
+                        "caching_sha2_password requires TLS until the server has cached the password".to_string(),
+                    ));
+                }
+                _ => return Err(DbError::ProtocolError("Unexpected authentication data".to_string())),
+            },
+            _ => return Err(DbError::ProtocolError("Unexpected authentication packet".to_string())),
+        }
+    }
+    Ok((conn, greeting.connection_id))
+}
+
+/// Stops the statement running on another connection, from a new connection
+pub(super) async fn kill_query(builder: &DbConnectionBuilder, connection_id: u32) -> Result<(), DbError> {
+    let (mut conn, _) = connect(builder).await?;
+    let result = execute_query(&mut conn, &format!("KILL QUERY {}", connection_id), Vec::new()).await;
+    let _ = quit(&mut conn).await;
+    result.map(|_| ())
+}
+
+/// Ends the session, before the connection is shut down
+pub(super) async fn quit(stream: &mut GenericConnection) -> Result<(), DbError> {
+    write_packet(stream, &mut 0, &[COM_QUIT]).await
+}
+
+/// A column of a result set
+#[derive(Debug)]
+struct Column {
+    name: String,
+    kind: u8,
+    flags: u16,
+}
+
+fn parse_column(packet: &[u8]) -> Result<Column, DbError> {
+    let mut r = Reader::new(packet);
+    for _ in 0..4 {
+        r.lenenc_bytes()?; // catalog, schema, table, original table
+    }
+    let name = lossy(r.lenenc_bytes()?.unwrap_or_default());
+    r.lenenc_bytes()?; // original name
+    r.lenenc_int()?; // length of the fixed fields
+    r.u16()?; // character set
+    r.u32()?; // column length
+    let kind = r.u8()?;
+    let flags = r.u16()?;
+    Ok(Column { name, kind, flags })
+}
+
+/// A row of a text result set
+fn text_row(packet: &[u8], columns: &[Column]) -> Result<HashMap<String, String>, DbError> {
+    let mut r = Reader::new(packet);
+    columns
+        .iter()
+        .map(|column| Ok((column.name.clone(), r.lenenc_bytes()?.map(lossy).unwrap_or_default())))
+        .collect()
+}
+
+/// A row of a binary result set, the result of a prepared statement
+fn binary_row(packet: &[u8], columns: &[Column]) -> Result<HashMap<String, String>, DbError> {
+    let mut r = Reader::new(packet);
+    r.u8()?; // header
+    // The NULL bitmap of a row starts at the third bit
+    let nulls = r.bytes((columns.len() + 2).div_ceil(8))?;
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let bit = i + 2;
+            let value = if nulls[bit / 8] & (1 << (bit % 8)) != 0 {
+                String::new()
+            } else {
+                binary_value(&mut r, column)?
+            };
+            Ok((column.name.clone(), value))
+        })
+        .collect()
+}
+
+/// A binary value, formatted as the text protocol would
+fn binary_value(r: &mut Reader, column: &Column) -> Result<String, DbError> {
+    let unsigned = column.flags & UNSIGNED_FLAG != 0;
+    Ok(match column.kind {
+        TYPE_TINY => {
+            let v = r.u8()?;
+            if unsigned { v.to_string() } else { (v as i8).to_string() }
+        }
+        TYPE_SHORT | TYPE_YEAR => {
+            let v = r.u16()?;
+            if unsigned { v.to_string() } else { (v as i16).to_string() }
+        }
+        TYPE_LONG | TYPE_INT24 => {
+            let v = r.u32()?;
+            if unsigned { v.to_string() } else { (v as i32).to_string() }
+        }
+        TYPE_LONGLONG => {
+            let v = r.u64()?;
+            if unsigned { v.to_string() } else { (v as i64).to_string() }
+        }
+        TYPE_FLOAT => f32::from_bits(r.u32()?).to_string(),
+        TYPE_DOUBLE => f64::from_bits(r.u64()?).to_string(),
+        TYPE_DATE | TYPE_DATETIME | TYPE_TIMESTAMP => {
+            let len = r.u8()? as usize;
+            format_datetime(column.kind, r.bytes(len)?)
+        }
+        TYPE_TIME => {
+            let len = r.u8()? as usize;
+            format_time(r.bytes(len)?)
+        }
+        TYPE_NULL => String::new(),
+        // DECIMAL, strings, BLOB, JSON, ENUM, SET, BIT and GEOMETRY are sent as text
+        _ => r.lenenc_bytes()?.map(lossy).unwrap_or_default(),
+    })
+}
+
+/// `YYYY-MM-DD` or `YYYY-MM-DD hh:mm:ss[.ffffff]`, from 0, 4, 7 or 11 bytes
+fn format_datetime(kind: u8, data: &[u8]) -> String {
+    let part = |i: usize| data.get(i).copied().unwrap_or(0);
+    let year = u16::from_le_bytes([part(0), part(1)]);
+    let date = format!("{:04}-{:02}-{:02}", year, part(2), part(3));
+    if kind == TYPE_DATE {
+        return date;
+    }
+    let mut datetime = format!("{} {:02}:{:02}:{:02}", date, part(4), part(5), part(6));
+    if data.len() >= 11 {
+        let micros = u32::from_le_bytes([data[7], data[8], data[9], data[10]]);
+        datetime.push_str(&format!(".{:06}", micros));
+    }
+    datetime
+}
+
+/// `[-]hh:mm:ss[.ffffff]`, the hours including the days, from 0, 8 or 12 bytes
+fn format_time(data: &[u8]) -> String {
+    if data.len() < 8 {
+        return "00:00:00".to_string();
+    }
+    let sign = if data[0] == 1 { "-" } else { "" };
+    let days = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+    let mut time = format!("{}{:02}:{:02}:{:02}", sign, days * 24 + data[5] as u32, data[6], data[7]);
+    if data.len() >= 12 {
+        let micros = u32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+        time.push_str(&format!(".{:06}", micros));
+    }
+    time
+}
+
+/// The status flags of an EOF packet
+fn eof_status(packet: &[u8]) -> u16 {
+    packet.get(3..5).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+/// Reads the results of a command: the rows of its result sets, or the rows it affected
+async fn read_results(stream: &mut GenericConnection, seq: &mut u8, binary: bool) -> Result<QueryResult, DbError> {
+    let mut rows = Vec::new();
+    let mut affected = 0u64;
+    let mut result_set = false;
+    loop {
+        let packet = read_packet(stream, seq).await?;
+        let status = match packet.first() {
+            Some(0x00) => {
+                let mut r = Reader::new(&packet[1..]);
+                affected += r.lenenc_int()?.unwrap_or(0);
+                r.lenenc_int()?; // last insert id
+                r.u16()?
+            }
+            Some(0xff) => return Err(DbError::QueryError(server_message(&packet))),
+            Some(0xfb) => return Err(DbError::QueryError("LOAD DATA LOCAL INFILE is not supported".to_string())),
+            _ => {
+                result_set = true;
+                let count = Reader::new(&packet).lenenc_int()?.unwrap_or(0) as usize;
+                let mut columns = Vec::with_capacity(count);
+                for _ in 0..count {
+                    columns.push(parse_column(&read_packet(stream, seq).await?)?);
+                }
+                read_packet(stream, seq).await?; // EOF of the column definitions
+                loop {
+                    let packet = read_packet(stream, seq).await?;
+                    match packet.first() {
+                        Some(0xfe) if packet.len() < 9 => break eof_status(&packet),
+                        Some(0xff) => return Err(DbError::QueryError(server_message(&packet))),
+                        _ if binary => rows.push(binary_row(&packet, &columns)?),
+                        _ => rows.push(text_row(&packet, &columns)?),
+                    }
+                }
+            }
+        };
+        // e.g. the result sets of a stored procedure
+        if status & SERVER_MORE_RESULTS_EXISTS == 0 {
+            break;
+        }
+    }
+    if result_set {
+        Ok(QueryResult::Rows(rows))
+    } else {
+        Ok(QueryResult::Count(affected as usize))
+    }
+}
+
+/// Executes a query, as a prepared statement if it has parameters
+pub(super) async fn execute_query(stream: &mut GenericConnection, query: &str, params: Vec<String>) -> Result<QueryResult, DbError> {
+    if params.is_empty() {
+        let mut seq = 0u8;
+        let mut packet = vec![COM_QUERY];
+        packet.extend_from_slice(query.as_bytes());
+        write_packet(stream, &mut seq, &packet).await?;
+        return read_results(stream, &mut seq, false).await;
+    }
+    let statement = prepare(stream, query).await?;
+    let result = execute_statement(stream, statement, params).await;
+    close_statement(stream, statement).await?;
+    result
+}
+
+/// Prepares a statement, returning its id
+pub(super) async fn prepare(stream: &mut GenericConnection, query: &str) -> Result<u32, DbError> {
+    let mut seq = 0u8;
+    let mut packet = vec![COM_STMT_PREPARE];
+    packet.extend_from_slice(query.as_bytes());
+    write_packet(stream, &mut seq, &packet).await?;
+    let response = read_packet(stream, &mut seq).await?;
+    if response.first() == Some(&0xff) {
+        return Err(DbError::QueryError(server_message(&response)));
+    }
+    let mut r = Reader::new(&response);
+    r.u8()?; // status
+    let id = r.u32()?;
+    let columns = r.u16()?;
+    let params = r.u16()?;
+    // The definitions of the parameters and of the columns, each followed by an EOF packet
+    for count in [params, columns] {
+        if count > 0 {
+            for _ in 0..=count {
+                read_packet(stream, &mut seq).await?;
+            }
+        }
+    }
+    Ok(id)
+}
+
+/// Executes a prepared statement, its parameters sent as strings and converted by the server
+pub(super) async fn execute_statement(stream: &mut GenericConnection, statement: u32, params: Vec<String>) -> Result<QueryResult, DbError> {
+    let mut seq = 0u8;
+    let mut packet = vec![COM_STMT_EXECUTE];
+    packet.extend_from_slice(&statement.to_le_bytes());
+    packet.push(0); // no cursor
+    packet.extend_from_slice(&1u32.to_le_bytes()); // iteration count
+    if !params.is_empty() {
+        packet.extend_from_slice(&vec![0u8; params.len().div_ceil(8)]); // NULL bitmap
+        packet.push(1); // the types of the parameters follow
+        for _ in &params {
+            packet.extend_from_slice(&[TYPE_VAR_STRING, 0]);
+        }
+        for param in &params {
+            put_lenenc_int(&mut packet, param.len() as u64);
+            packet.extend_from_slice(param.as_bytes());
+        }
+    }
+    write_packet(stream, &mut seq, &packet).await?;
+    read_results(stream, &mut seq, true).await
+}
+
+/// Deallocates a prepared statement, the server does not answer
+pub(super) async fn close_statement(stream: &mut GenericConnection, statement: u32) -> Result<(), DbError> {
+    let mut packet = vec![COM_STMT_CLOSE];
+    packet.extend_from_slice(&statement.to_le_bytes());
+    write_packet(stream, &mut 0, &packet).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn greeting(plugin: &str) -> Vec<u8> {
+        let mut packet = vec![10];
+        packet.extend_from_slice(b"8.0.36\0");
+        packet.extend_from_slice(&7u32.to_le_bytes());
+        packet.extend_from_slice(b"abcdefgh");
+        packet.push(0);
+        let capabilities = CLIENT_PROTOCOL_41 | CLIENT_SSL | CLIENT_SECURE_CONNECTION | CLIENT_PLUGIN_AUTH;
+        packet.extend_from_slice(&(capabilities as u16).to_le_bytes());
+        packet.push(CHARSET_UTF8MB4);
+        packet.extend_from_slice(&2u16.to_le_bytes());
+        packet.extend_from_slice(&((capabilities >> 16) as u16).to_le_bytes());
+        packet.push(21);
+        packet.extend_from_slice(&[0; 10]);
+        packet.extend_from_slice(b"ijklmnopqrst\0");
+        packet.extend_from_slice(plugin.as_bytes());
+        packet.push(0);
+        packet
+    }
+
+    fn column(name: &str, kind: u8, flags: u16) -> Vec<u8> {
+        let mut packet = Vec::new();
+        for field in ["def", "db", "t", "t", name, name] {
+            put_lenenc_int(&mut packet, field.len() as u64);
+            packet.extend_from_slice(field.as_bytes());
+        }
+        packet.push(0x0c);
+        packet.extend_from_slice(&CHARSET_UTF8MB4.to_le_bytes());
+        packet.push(0);
+        packet.extend_from_slice(&11u32.to_le_bytes());
+        packet.push(kind);
+        packet.extend_from_slice(&flags.to_le_bytes());
+        packet.extend_from_slice(&[0, 0, 0]);
+        packet
+    }
+
+    #[test]
+    fn test_parse_greeting() {
+        let greeting = parse_greeting(&greeting("caching_sha2_password")).unwrap();
+        assert_eq!(greeting.connection_id, 7);
+        assert_eq!(greeting.scramble, b"abcdefghijklmnopqrst");
+        assert_eq!(greeting.auth_plugin, "caching_sha2_password");
+        assert_ne!(greeting.capabilities & CLIENT_SSL, 0);
+        assert!(parse_greeting(&[9]).is_err());
+    }
+
+    #[test]
+    fn test_auth_response() {
+        let scramble = b"abcdefghijklmnopqrst";
+        assert_eq!(auth_response("caching_sha2_password", "secret", scramble).unwrap().len(), 32);
+        assert_eq!(auth_response("mysql_native_password", "secret", scramble).unwrap().len(), 20);
+        assert_ne!(
+            auth_response("mysql_native_password", "secret", scramble).unwrap(),
+            auth_response("mysql_native_password", "secret", b"tsrqponmlkjihgfedcba").unwrap()
+        );
+        assert!(auth_response("caching_sha2_password", "", scramble).unwrap().is_empty());
+        assert!(auth_response("sha256_password", "secret", scramble).is_err());
+    }
+
+    #[test]
+    fn test_lenenc_round_trip() {
+        for n in [0, 250, 251, 0xffff, 0x1_0000, 0xff_ffff, 0x100_0000, u64::MAX] {
+            let mut buf = Vec::new();
+            put_lenenc_int(&mut buf, n);
+            assert_eq!(Reader::new(&buf).lenenc_int().unwrap(), Some(n));
+        }
+        assert_eq!(Reader::new(&[0xfb]).lenenc_int().unwrap(), None);
+        assert!(Reader::new(&[0xfc, 1]).lenenc_int().is_err());
+    }
+
+    #[test]
+    fn test_server_message() {
+        let mut packet = vec![0xff];
+        packet.extend_from_slice(&1045u16.to_le_bytes());
+        packet.extend_from_slice(b"#28000Access denied");
+        assert_eq!(server_message(&packet), "ERROR 1045 (28000): Access denied");
+    }
+
+    #[test]
+    fn test_text_row() {
+        let columns = vec![
+            parse_column(&column("id", TYPE_LONG, 0)).unwrap(),
+            parse_column(&column("name", TYPE_VAR_STRING, 0)).unwrap(),
+        ];
+        assert_eq!(columns[1].name, "name");
+        let row = text_row(&[2, b'4', b'2', 0xfb], &columns).unwrap();
+        assert_eq!(row.get("id").map(String::as_str), Some("42"));
+        assert_eq!(row.get("name").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn test_binary_row() {
+        let columns = vec![
+            parse_column(&column("id", TYPE_LONGLONG, UNSIGNED_FLAG)).unwrap(),
+            parse_column(&column("delta", TYPE_TINY, 0)).unwrap(),
+            parse_column(&column("note", TYPE_VAR_STRING, 0)).unwrap(),
+            parse_column(&column("at", TYPE_DATETIME, 0)).unwrap(),
+            parse_column(&column("took", TYPE_TIME, 0)).unwrap(),
+        ];
+        let mut packet = vec![0x00, 0b0001_0000]; // `note` is NULL
+        packet.extend_from_slice(&u64::MAX.to_le_bytes());
+        packet.push(0xfe);
+        packet.extend_from_slice(&[7, 0xe8, 0x07, 1, 2, 3, 4, 5]);
+        packet.extend_from_slice(&[8, 1, 1, 0, 0, 0, 2, 0, 0]);
+        let row = binary_row(&packet, &columns).unwrap();
+        assert_eq!(row.get("id").map(String::as_str), Some("18446744073709551615"));
+        assert_eq!(row.get("delta").map(String::as_str), Some("-2"));
+        assert_eq!(row.get("note").map(String::as_str), Some(""));
+        assert_eq!(row.get("at").map(String::as_str), Some("2024-01-02 03:04:05"));
+        assert_eq!(row.get("took").map(String::as_str), Some("-26:00:00"));
+    }
+}
@@ -0,0 +1,283 @@
+//! The transactional outbox: events are written to a table in the same transaction as the
+//! data they are about, so they are recorded exactly when the data is. A relay running as a
+//! background task of the application then publishes them on the `EventBus`, or hands them
+//! to a `WebhookQueue`, and deletes them once this succeeded.
+//!
+//! Delivery is at least once: a relay stopped between publishing an event and deleting it
+//! publishes it again, so consumers should ignore the ids they have already seen. Several
+//! instances of the application may run the relay, the rows are claimed with
+//! `FOR UPDATE SKIP LOCKED` (PostgreSQL 9.5, MySQL 8).
+//!
+//! # Example
+//! ```rust,ignore
+//! let outbox = Outbox::new(pool.clone()).webhooks(queue.clone());
+//! outbox.create_table().await?;
+//! outbox.start(&APP);
+//!
+//! // In a handler
+//! conn.begin_transaction().await?;
+//! SqlQuery::new("UPDATE orders SET paid = TRUE WHERE id = $1").bind(id).execute(&mut conn).await?;
+//! outbox.add(&mut conn, "order.paid", &object!({ id: id })).await?;
+//! outbox.add_webhook(&mut conn, "https://example.com/hooks", "order.paid", &object!({ id: id })).await?;
+//! conn.commit_transaction().await?;
+//!
+//! // Elsewhere
+//! let mut events = APP.events().subscribe::<OutboxEvent>();
+//! while let Some(event) = events.recv().await { /* ... */ }
+//! ```
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use akari::Value;
+use starberry_core::app::application::App;
+use starberry_core::app::events::EventBus;
+use starberry_core::app::webhook_queue::WebhookQueue;
+use starberry_core::connection::Protocol;
+use starberry_lib::uuid::uuid_v7;
+
+use super::connection::DbConnection;
+use super::error::DbError;
+use super::pool::SqlPool;
+use super::query::QueryResult;
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_BATCH: usize = 100;
+
+/// An event of the outbox, as published on the `EventBus`
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    /// Unique id, the same every time the event is published
+    pub id: String,
+    /// The type of the event, e.g. `order.paid`
+    pub event: String,
+    pub payload: Value,
+}
+
+/// Writes events to the outbox table and relays them, see the module documentation
+#[derive(Clone)]
+pub struct Outbox {
+    pool: SqlPool,
+    table: String,
+    webhooks: Option<WebhookQueue>,
+    poll_interval: Duration,
+    batch: usize,
+}
+
+impl Outbox {
+    /// An outbox in the `outbox` table
+    pub fn new(pool: SqlPool) -> Self {
+        Self {
+            pool,
+            table: "outbox".to_string(),
+            webhooks: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            batch: DEFAULT_BATCH,
+        }
+    }
+
+    /// Use another table. Panics if `table` is not a (schema qualified) table name
+    pub fn table(mut self, table: &str) -> Self {
+        assert!(
+            table.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_')),
+            "Invalid table {}",
+            table
+        );
+        self.table = table.to_string();
+        self
+    }
+
+    /// The queue the events added with `add_webhook` are handed to
+    pub fn webhooks(mut self, queue: WebhookQueue) -> Self {
+        self.webhooks = Some(queue);
+        self
+    }
+
+    /// Sets how often the relay looks for new events, 1 second by default
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Creates the outbox table if it does not exist
+    pub async fn create_table(&self) -> Result<(), DbError> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} (id VARCHAR(64) PRIMARY KEY, event VARCHAR(255) NOT NULL, \
+             payload TEXT NOT NULL, url TEXT, created_at BIGINT NOT NULL, attempts INT NOT NULL DEFAULT 0, last_error TEXT)",
+            self.table
+        );
+        let mut pooled = self.pool.get().await?;
+        pooled.connection().execute_query(&sql, Vec::new()).await.map(|_| ())
+    }
+
+    /// Writes an event to be published on the `EventBus`, returning its id. Run it on the
+    /// connection of the transaction changing the data, it is only published once committed
+    pub async fn add(&self, conn: &mut DbConnection, event: &str, payload: &Value) -> Result<String, DbError> {
+        self.insert(conn, event, payload, None).await
+    }
+
+    /// Writes an event to be delivered to the url by the `WebhookQueue`, returning its id.
+    /// Run it on the connection of the transaction changing the data
+    pub async fn add_webhook(&self, conn: &mut DbConnection, url: &str, event: &str, payload: &Value) -> Result<String, DbError> {
+        self.insert(conn, event, payload, Some(url)).await
+    }
+
+    async fn insert(&self, conn: &mut DbConnection, event: &str, payload: &Value, url: Option<&str>) -> Result<String, DbError> {
+        let id = uuid_v7();
+        let p = |n| placeholder(conn, n);
+        let (url_column, url_value) = if url.is_some() { (", url", format!(", {}", p(5))) } else { ("", String::new()) };
+        let sql = format!(
+            "INSERT INTO {} (id, event, payload, created_at{}) VALUES ({}, {}, {}, {}{})",
+            self.table, url_column, p(1), p(2), p(3), p(4), url_value
+        );
+        let mut params = vec![id.clone(), event.to_string(), payload.into_json(), now_millis().to_string()];
+        params.extend(url.map(str::to_string));
+        check(conn.execute_query(&sql, params).await?)?;
+        Ok(id)
+    }
+
+    /// Runs the relay as a background task of the application until it shuts down
+    pub fn start(&self, app: &Arc<App>) {
+        let outbox = self.clone();
+        let relay_app = app.clone();
+        let shutdown = app.shutdown_token();
+        app.spawn_task(async move {
+            loop {
+                let relayed = match outbox.run_once(relay_app.events()).await {
+                    Ok(relayed) => relayed,
+                    Err(e) => {
+                        eprintln!("Outbox relay: {}", e);
+                        0
+                    }
+                };
+                // A full batch means more events are waiting already
+                if relayed >= outbox.batch {
+                    continue;
+                }
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = tokio::time::sleep(outbox.poll_interval) => {}
+                }
+            }
+        });
+    }
+
+    /// Relays the oldest events once, returning how many were relayed. An event which cannot
+    /// be relayed stays in the outbox with its error, and the events after it wait for it
+    pub async fn run_once(&self, bus: &EventBus) -> Result<usize, DbError> {
+        let mut pooled = self.pool.get().await?;
+        let conn = pooled.connection();
+        conn.begin_transaction().await?;
+        let result = self.relay(conn, bus).await;
+        match result {
+            Ok(relayed) => conn.commit_transaction().await.map(|_| relayed),
+            Err(e) => {
+                let _ = conn.rollback_transaction().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn relay(&self, conn: &mut DbConnection, bus: &EventBus) -> Result<usize, DbError> {
+        let sql = format!(
+            "SELECT id, event, payload, url FROM {} ORDER BY created_at, id LIMIT {} FOR UPDATE SKIP LOCKED",
+            self.table, self.batch
+        );
+        let QueryResult::Rows(rows) = check(conn.execute_query(&sql, Vec::new()).await?)? else {
+            return Ok(0);
+        };
+        let mut relayed = 0;
+        for row in rows {
+            let field = |name: &str| row.get(name).cloned().unwrap_or_default();
+            let id = field("id");
+            let payload = Value::from_json(&field("payload")).unwrap_or(Value::None);
+            let outcome = match field("url") {
+                // NULL is read as an empty string
+                url if url.is_empty() => {
+                    bus.publish(OutboxEvent { id: id.clone(), event: field("event"), payload });
+                    Ok(())
+                }
+                url => match &self.webhooks {
+                    Some(queue) => queue.enqueue(url, field("event"), payload).await.map(|_| ()).map_err(|e| e.to_string()),
+                    None => Err("No WebhookQueue is set for the outbox".to_string()),
+                },
+            };
+            match outcome {
+                Ok(()) => {
+                    let sql = format!("DELETE FROM {} WHERE id = {}", self.table, placeholder(conn, 1));
+                    check(conn.execute_query(&sql, vec![id]).await?)?;
+                    relayed += 1;
+                }
+                Err(error) => {
+                    let sql = format!(
+                        "UPDATE {} SET attempts = attempts + 1, last_error = {} WHERE id = {}",
+                        self.table,
+                        placeholder(conn, 1),
+                        placeholder(conn, 2)
+                    );
+                    check(conn.execute_query(&sql, vec![error, id]).await?)?;
+                    break;
+                }
+            }
+        }
+        Ok(relayed)
+    }
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+}
+
+/// The `n`th placeholder for the database of the connection, `$n` or `?` for MySQL
+fn placeholder(conn: &DbConnection, n: usize) -> String {
+    if conn.protocol == Protocol::MySQL { "?".to_string() } else { format!("${}", n) }
+}
+
+fn check(result: QueryResult) -> Result<QueryResult, DbError> {
+    match result {
+        QueryResult::Error(e) => Err(e),
+        result => Ok(result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_outbox_relays_committed_events() {
+        use crate::sql::{DbConnectionBuilder, SslMode};
+        let builder = DbConnectionBuilder::new("127.0.0.1", 5432)
+            .ssl_mode(SslMode::Disable)
+            .database("postgres")
+            .username("postgres")
+            .password("JerrySu5379");
+        let pool = SqlPool::new(builder, 2);
+        let outbox = Outbox::new(pool.clone()).table("outbox_test");
+        outbox.create_table().await.expect("create table failed");
+
+        let bus = EventBus::new();
+        let mut events = bus.subscribe::<OutboxEvent>();
+        let mut conn = pool.get().await.expect("connection failed");
+        let conn = conn.connection();
+
+        conn.begin_transaction().await.unwrap();
+        outbox.add(conn, "order.cancelled", &Value::new("rolled back")).await.unwrap();
+        conn.rollback_transaction().await.unwrap();
+        conn.begin_transaction().await.unwrap();
+        let id = outbox.add(conn, "order.paid", &Value::new("42")).await.unwrap();
+        conn.commit_transaction().await.unwrap();
+
+        assert_eq!(outbox.run_once(&bus).await.unwrap(), 1);
+        let event = events.try_recv().expect("no event published");
+        assert_eq!((event.id.as_str(), event.event.as_str()), (id.as_str(), "order.paid"));
+        assert_eq!(outbox.run_once(&bus).await.unwrap(), 0);
+
+        // Without a queue a webhook stays in the outbox
+        conn.begin_transaction().await.unwrap();
+        outbox.add_webhook(conn, "https://example.com/hooks", "order.paid", &Value::new("42")).await.unwrap();
+        conn.commit_transaction().await.unwrap();
+        assert_eq!(outbox.run_once(&bus).await.unwrap(), 0);
+        conn.execute_query("DROP TABLE outbox_test", Vec::new()).await.unwrap();
+    }
+}
@@ -1,12 +1,22 @@
 use std::collections::VecDeque;
 use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, Semaphore, OwnedSemaphorePermit};
 use async_trait::async_trait;
+use starberry_core::connection::Protocol;
 use starberry_core::connection::transmit::Pool;
 
 use super::connection::{DbConnectionBuilder, DbConnection};
 use super::error::DbError;
+use super::query::QueryResult;
+
+/// How long the lag or the failure of a replica is trusted before it is checked again
+const REPLICA_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The replication lag of a PostgreSQL standby, 0 once it has replayed all it received
+const POSTGRES_LAG: &str = "SELECT COALESCE(CASE WHEN pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0 \
+    ELSE EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()) END, 0)::float8 AS lag";
 
 /// Async connection pool for database connections.
 #[derive(Clone)]
@@ -15,6 +25,29 @@ pub struct SqlPool {
     connections: Arc<Mutex<VecDeque<DbConnection>>>,
     semaphore: Arc<Semaphore>,
     max_size: usize,
+    replicas: Arc<Vec<Arc<Replica>>>,
+    next_replica: Arc<AtomicUsize>,
+    max_replica_lag: Option<Duration>,
+}
+
+/// A read replica, with whether it was last seen usable
+struct Replica {
+    pool: SqlPool,
+    state: std::sync::Mutex<ReplicaState>,
+}
+
+#[derive(Default)]
+struct ReplicaState {
+    checked: Option<Instant>,
+    usable: bool,
+}
+
+impl Replica {
+    fn record(&self, usable: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.checked = Some(Instant::now());
+        state.usable = usable;
+    }
 }
 
 impl SqlPool {
@@ -25,7 +58,63 @@ impl SqlPool {
             connections: Arc::new(Mutex::new(VecDeque::with_capacity(max_size))),
             semaphore: Arc::new(Semaphore::new(max_size)),
             max_size,
+            replicas: Arc::new(Vec::new()),
+            next_replica: Arc::new(AtomicUsize::new(0)),
+            max_replica_lag: None,
+        }
+    }
+
+    /// Add a read replica of the database with its own pool of at most `max_size` connections.
+    /// The builder of the pool stays the primary, which gets every write.
+    pub fn with_replica(mut self, builder: DbConnectionBuilder, max_size: usize) -> Self {
+        let mut replicas = self.replicas.as_ref().clone();
+        replicas.push(Arc::new(Replica {
+            pool: SqlPool::new(builder, max_size),
+            state: std::sync::Mutex::new(ReplicaState::default()),
+        }));
+        self.replicas = Arc::new(replicas);
+        self
+    }
+
+    /// Only read from the replicas at most this far behind the primary, their lag is checked
+    /// every few seconds. Without it they are read however far behind they are.
+    pub fn max_replica_lag(mut self, lag: Duration) -> Self {
+        self.max_replica_lag = Some(lag);
+        self
+    }
+
+    /// Acquire a connection for a read-only query: from the replicas in turn, skipping those
+    /// lagging or unreachable, and from the primary when none is usable.
+    pub async fn get_read(&self) -> Result<PooledSqlConnection, DbError> {
+        let count = self.replicas.len();
+        let start = self.next_replica.fetch_add(1, Ordering::Relaxed);
+        for i in 0..count {
+            let replica = &self.replicas[(start + i) % count];
+            if !self.is_usable(replica).await {
+                continue;
+            }
+            match replica.pool.get().await {
+                Ok(conn) => return Ok(conn),
+                Err(_) => replica.record(false),
+            }
+        }
+        self.get().await
+    }
+
+    /// Whether the replica can be read, checking its lag again once the last check is old
+    async fn is_usable(&self, replica: &Replica) -> bool {
+        {
+            let state = replica.state.lock().unwrap();
+            if state.checked.is_some_and(|checked| checked.elapsed() < REPLICA_CHECK_INTERVAL) {
+                return state.usable;
+            }
         }
+        let usable = match self.max_replica_lag {
+            Some(max_lag) => replica_lag(&replica.pool).await.is_ok_and(|lag| lag <= max_lag),
+            None => true,
+        };
+        replica.record(usable);
+        usable
     }
 
     /// Acquire a pooled connection, establishing a new one if necessary.
@@ -46,8 +135,20 @@ impl SqlPool {
         }
     }
 
+    /// Check that the database answers, by running `SELECT 1` on a pooled connection.
+    /// Suited to a readiness check: `APP.health_check("db", move || { let pool = pool.clone(); async move { pool.ping().await.into() } })`
+    pub async fn ping(&self) -> Result<(), DbError> {
+        let mut conn = self.get().await?;
+        conn.connection().execute_query("SELECT 1", Vec::new()).await?;
+        Ok(())
+    }
+
     /// Return a connection to the pool.
     async fn release(&self, conn: DbConnection) {
+        // A connection closed by an interrupted query is replaced on the next `get`
+        if !conn.is_open() {
+            return;
+        }
         let mut conns = self.connections.lock().await;
         if conns.len() < self.max_size {
             conns.push_back(conn);
@@ -56,6 +157,33 @@ impl SqlPool {
     }
 }
 
+/// How far the server of the pool is behind its primary, 0 if it is not a replica
+async fn replica_lag(pool: &SqlPool) -> Result<Duration, DbError> {
+    let mut pooled = pool.get().await?;
+    let conn = pooled.connection();
+    let mysql = conn.protocol == Protocol::MySQL;
+    let sql = if mysql { "SHOW REPLICA STATUS" } else { POSTGRES_LAG };
+    let rows = match conn.execute_query(sql, Vec::new()).await? {
+        QueryResult::Rows(rows) => rows,
+        _ => Vec::new(),
+    };
+    let Some(row) = rows.first() else {
+        return Ok(Duration::ZERO);
+    };
+    // MariaDB still names it after the master
+    let lag = row.get("lag").or_else(|| row.get("Seconds_Behind_Source")).or_else(|| row.get("Seconds_Behind_Master"));
+    match lag.map(String::as_str) {
+        // MySQL gives no lag while the replication is stopped
+        None | Some("") => Err(DbError::QueryError("The replica reports no replication lag".into())),
+        Some(seconds) => seconds
+            .parse::<f64>()
+            .ok()
+            .filter(|seconds| seconds.is_finite())
+            .map(|seconds| Duration::from_secs_f64(seconds.max(0.0)))
+            .ok_or_else(|| DbError::ProtocolError(format!("Invalid replication lag {}", seconds))),
+    }
+}
+
 /// Wrapper for a pooled connection that returns it to the pool on drop.
 pub struct PooledSqlConnection {
     pool: SqlPool,
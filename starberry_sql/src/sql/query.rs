@@ -1,8 +1,9 @@
 use super::connection::DbConnection;
 use super::error::DbError;
+use super::mysql;
 use std::collections::HashMap;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use starberry_core::connection::Connection as GenericConnection;
+use starberry_core::connection::{Connection as GenericConnection, Protocol};
 
 /// Represents a database query result with PostgreSQL specifics.
 #[derive(Debug, Clone)]
@@ -123,6 +124,9 @@ impl DbConnection {
             .stream
             .as_mut()
             .ok_or_else(|| DbError::ConnectionError("No active connection".into()))?;
+        if self.protocol == Protocol::MySQL {
+            return mysql::execute_query(stream, query, params).await;
+        }
 
         // ---- 3. Parse message ----
         // Format: 'P' | Int32(len) | statement_name\0 | query\0 | param_type_count(0)
@@ -262,6 +266,13 @@ impl DbConnection {
 
     /// Prepares a statement for repeated execution. `query` must be a compile-time, trusted SQL string; untrusted dynamic queries must be validated externally or whitelisted.
     pub async fn prepare_statement(&mut self, query: &'static str) -> Result<String, DbError> {
+        if self.protocol == Protocol::MySQL {
+            let stream = self
+                .stream
+                .as_mut()
+                .ok_or_else(|| DbError::ConnectionError("No active connection".into()))?;
+            return mysql::prepare(stream, query).await.map(|id| id.to_string());
+        }
         use starberry_lib::random_alphanumeric_string;
         // Generate a random statement name
         let stmt_name = format!("stmt_{}", random_alphanumeric_string(8));
@@ -279,6 +290,12 @@ impl DbConnection {
             .stream
             .as_mut()
             .ok_or_else(|| DbError::ConnectionError("No active connection".into()))?;
+        if self.protocol == Protocol::MySQL {
+            let id = statement_id
+                .parse()
+                .map_err(|_| DbError::QueryError(format!("Invalid statement id {}", statement_id)))?;
+            return mysql::execute_statement(stream, id, params).await;
+        }
         // 3. Bind message: portal="", statement=statement_id, params in text format
         let mut buf = Vec::new();
         buf.push(b'B');
@@ -5,4 +5,13 @@ use std::collections::HashMap;
 pub trait FromRow: Sized {
     /// Build an instance of the implementing type from a row map
     fn from_row(row: &HashMap<String, String>) -> Result<Self, DbError>;
-} 
\ No newline at end of file
+} 
+
+/// The value of a column of a row, looked up as written and then in lowercase, as PostgreSQL
+/// folds the unquoted names. Used by the rows generated by `sql!`
+pub fn column_value(row: &HashMap<String, String>, name: &str) -> Result<String, DbError> {
+    row.get(name)
+        .or_else(|| row.get(&name.to_lowercase()))
+        .cloned()
+        .ok_or_else(|| DbError::QueryError(format!("Missing column {}", name)))
+}
@@ -0,0 +1,352 @@
+//! Sessions kept in an SQL table, so they survive restarts and are shared by several instances
+//! of the application without Redis. The session is written only once it holds data, and its
+//! expiry is pushed back when half of its lifetime has passed, so most requests only read it.
+//!
+//! # Example
+//! ```rust,ignore
+//! let store = SqlSessionStore::new(pool.clone()).ttl(Duration::from_secs(86400));
+//! store.create_table().await?;
+//! store.schedule_cleanup(&APP, every(1).hours());
+//! App::new().set_config(store).append_middleware::<SqlSession>().build();
+//!
+//! // In an endpoint
+//! let session = req.params.get_mut::<SqlSessionRW>().unwrap();
+//! session.renew(); // On login, so an id planted before it is worthless
+//! session.set("user_id", "42");
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use akari::Value;
+use starberry_core::app::application::App;
+use starberry_core::app::middleware::AsyncMiddleware;
+use starberry_core::app::schedule::Schedule;
+use starberry_core::connection::Protocol;
+use starberry_core::http::context::HttpReqCtx;
+use starberry_core::http::cookie::Cookie;
+use starberry_lib::random_alphanumeric_string;
+use starberry_macro::middleware;
+
+use super::error::DbError;
+use super::pool::SqlPool;
+use super::query::QueryResult;
+
+/// The cookie holding the id of the session
+const SESSION_COOKIE: &str = "sql_session_id";
+/// The length of a session id, drawn from 62 characters
+const SESSION_ID_LENGTH: usize = 32;
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Stores the sessions in a table of an `SqlPool`, with PostgreSQL or MySQL.
+/// Set it with `AppBuilder::set_config` for `SqlSession` to use it.
+#[derive(Clone)]
+pub struct SqlSessionStore {
+    pool: SqlPool,
+    table: String,
+    ttl: Duration,
+}
+
+impl SqlSessionStore {
+    /// A store in the `sessions` table, whose sessions expire after 7 days without a request
+    pub fn new(pool: SqlPool) -> Self {
+        Self { pool, table: "sessions".to_string(), ttl: Duration::from_secs(3600 * 24 * 7) }
+    }
+
+    /// Use another table. Panics if `table` is not a (schema qualified) table name
+    pub fn table(mut self, table: &str) -> Self {
+        assert!(
+            table.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_')),
+            "Invalid table {}",
+            table
+        );
+        self.table = table.to_string();
+        self
+    }
+
+    /// How long a session lasts without a request
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Creates the table of the sessions if it does not exist. The expiry is in seconds since
+    /// the Unix epoch, which both databases compare the same way
+    pub async fn create_table(&self) -> Result<(), DbError> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} (id VARCHAR(64) PRIMARY KEY, data TEXT NOT NULL, expires_at BIGINT NOT NULL)",
+            self.table
+        );
+        self.run(|_| sql.clone(), Vec::new()).await.map(|_| ())
+    }
+
+    /// The data of the session, `None` if it does not exist or has expired, with its expiry
+    pub async fn load(&self, id: &str) -> Result<Option<(HashMap<String, String>, u64)>, DbError> {
+        let table = &self.table;
+        let result = self
+            .run(
+                |p| format!("SELECT data, expires_at FROM {} WHERE id = {} AND expires_at > {}", table, p(1), p(2)),
+                vec![id.to_string(), now().to_string()],
+            )
+            .await?;
+        let QueryResult::Rows(rows) = result else {
+            return Ok(None);
+        };
+        Ok(rows.into_iter().next().map(|row| {
+            let data = row.get("data").map(String::as_str).map(decode_data).unwrap_or_default();
+            let expires_at = row.get("expires_at").and_then(|at| at.parse().ok()).unwrap_or(0);
+            (data, expires_at)
+        }))
+    }
+
+    /// Writes the data of the session and pushes back its expiry, returning the new expiry
+    pub async fn save(&self, id: &str, data: &HashMap<String, String>) -> Result<u64, DbError> {
+        let expires_at = now() + self.ttl.as_secs();
+        let table = &self.table;
+        self.run(
+            |p| {
+                let upsert = if p(1) == "?" {
+                    "ON DUPLICATE KEY UPDATE data = VALUES(data), expires_at = VALUES(expires_at)"
+                } else {
+                    "ON CONFLICT (id) DO UPDATE SET data = EXCLUDED.data, expires_at = EXCLUDED.expires_at"
+                };
+                format!("INSERT INTO {} (id, data, expires_at) VALUES ({}, {}, {}) {}", table, p(1), p(2), p(3), upsert)
+            },
+            vec![id.to_string(), encode_data(data), expires_at.to_string()],
+        )
+        .await?;
+        Ok(expires_at)
+    }
+
+    /// Pushes back the expiry of the session without rewriting its data, returning the new expiry
+    pub async fn touch(&self, id: &str) -> Result<u64, DbError> {
+        let expires_at = now() + self.ttl.as_secs();
+        let table = &self.table;
+        self.run(
+            |p| format!("UPDATE {} SET expires_at = {} WHERE id = {}", table, p(1), p(2)),
+            vec![expires_at.to_string(), id.to_string()],
+        )
+        .await?;
+        Ok(expires_at)
+    }
+
+    /// Deletes the session, e.g. on logout
+    pub async fn destroy(&self, id: &str) -> Result<(), DbError> {
+        let table = &self.table;
+        self.run(|p| format!("DELETE FROM {} WHERE id = {}", table, p(1)), vec![id.to_string()]).await.map(|_| ())
+    }
+
+    /// Deletes the expired sessions, returning how many were deleted
+    pub async fn remove_expired(&self) -> Result<usize, DbError> {
+        let table = &self.table;
+        let result = self
+            .run(|p| format!("DELETE FROM {} WHERE expires_at <= {}", table, p(1)), vec![now().to_string()])
+            .await?;
+        Ok(match result {
+            QueryResult::Count(n) => n,
+            _ => 0,
+        })
+    }
+
+    /// Deletes the expired sessions on the schedule of the application, e.g. `every(1).hours()`
+    pub fn schedule_cleanup(&self, app: &Arc<App>, schedule: Schedule) {
+        let store = self.clone();
+        app.schedule(schedule, move || {
+            let store = store.clone();
+            async move {
+                if let Err(e) = store.remove_expired().await {
+                    eprintln!("Failed to remove the expired sessions: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Runs the SQL built with the placeholders of the database, `$n` or `?` for MySQL
+    async fn run(&self, sql: impl FnOnce(&dyn Fn(usize) -> String) -> String, params: Vec<String>) -> Result<QueryResult, DbError> {
+        let mut pooled = self.pool.get().await?;
+        let conn = pooled.connection();
+        let mysql = conn.protocol == Protocol::MySQL;
+        let sql = sql(&|n| if mysql { "?".to_string() } else { format!("${}", n) });
+        match conn.execute_query(&sql, params).await? {
+            QueryResult::Error(e) => Err(e),
+            result => Ok(result),
+        }
+    }
+}
+
+fn encode_data(data: &HashMap<String, String>) -> String {
+    Value::Dict(data.iter().map(|(k, v)| (k.clone(), Value::Str(v.clone()))).collect()).into_json()
+}
+
+fn decode_data(json: &str) -> HashMap<String, String> {
+    let Ok(Value::Dict(map)) = Value::from_json(json) else {
+        return HashMap::new();
+    };
+    map.into_iter()
+        .filter_map(|(k, v)| match v {
+            Value::Str(v) => Some((k, v)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The session of a request handled by `SqlSession`, saved once the request is handled if it
+/// was changed.
+#[derive(Debug, Clone)]
+pub struct SqlSessionRW {
+    id: String,
+    data: HashMap<String, String>,
+    expires_at: u64,
+    modified: bool,
+    destroyed: bool,
+    /// The id the session had when the request came in, if `renew` gave it a new one
+    renewed_from: Option<String>,
+}
+
+impl SqlSessionRW {
+    fn new(id: String, data: HashMap<String, String>, expires_at: u64) -> Self {
+        Self { id, data, expires_at, modified: false, destroyed: false, renewed_from: None }
+    }
+
+    /// The id of the session, also the value of its cookie
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get<T: AsRef<str>>(&self, key: T) -> Option<&String> {
+        self.data.get(key.as_ref())
+    }
+
+    pub fn set<T: Into<String>, U: Into<String>>(&mut self, key: T, value: U) {
+        self.data.insert(key.into(), value.into());
+        self.modified = true;
+    }
+
+    pub fn remove<T: AsRef<str>>(&mut self, key: T) -> Option<String> {
+        let removed = self.data.remove(key.as_ref());
+        self.modified |= removed.is_some();
+        removed
+    }
+
+    /// Moves the data to a new id once the request is handled, deleting the session under the
+    /// old one. Call it when the user logs in, against session fixation
+    pub fn renew(&mut self) {
+        let id = std::mem::replace(&mut self.id, random_alphanumeric_string(SESSION_ID_LENGTH));
+        self.renewed_from.get_or_insert(id);
+        self.modified = true;
+    }
+
+    /// Deletes the session once the request is handled, the next request starts a new one
+    pub fn destroy(&mut self) {
+        self.data.clear();
+        self.destroyed = true;
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+}
+
+#[middleware(HttpReqCtx)]
+pub async fn SqlSession() {
+    let Some(store) = req.app.config().get::<SqlSessionStore>().cloned() else {
+        eprintln!("SqlSession needs a SqlSessionStore set with set_config");
+        return next(req).await;
+    };
+    let cookie = req.get_cookie(SESSION_COOKIE).map(|c| c.get_value().to_owned());
+    let loaded = match &cookie {
+        Some(id) => match store.load(id).await {
+            Ok(loaded) => loaded.map(|(data, expires_at)| SqlSessionRW::new(id.clone(), data, expires_at)),
+            Err(e) => {
+                eprintln!("Failed to load the session: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    let existed = loaded.is_some();
+    let session = loaded.unwrap_or_else(|| SqlSessionRW::new(random_alphanumeric_string(SESSION_ID_LENGTH), HashMap::new(), 0));
+    req.params.set(session);
+    let mut req = next(req).await; // Continue middleware chain
+    let Some(session) = req.params.take::<SqlSessionRW>() else {
+        return req;
+    };
+
+    if existed
+        && let Some(previous) = &session.renewed_from
+        && let Err(e) = store.destroy(previous).await
+    {
+        eprintln!("Failed to delete the renewed session: {}", e);
+    }
+    let saved = if session.destroyed {
+        if existed
+            && let Err(e) = store.destroy(&session.id).await
+        {
+            eprintln!("Failed to delete the session: {}", e);
+        }
+        false
+    } else if session.modified {
+        store.save(&session.id, &session.data).await.map_err(|e| eprintln!("Failed to save the session: {}", e)).is_ok()
+    } else if existed && session.expires_at < now() + store.ttl.as_secs() / 2 {
+        // Half of the lifetime has passed
+        store.touch(&session.id).await.map_err(|e| eprintln!("Failed to refresh the session: {}", e)).is_ok()
+    } else {
+        false
+    };
+    if saved {
+        req.response = req.response.add_cookie(
+            SESSION_COOKIE,
+            Cookie::new(session.id.clone())
+                .path("/")
+                .secure(true)
+                .http_only(true)
+                .same_site("Lax")
+                .max_age(store.ttl.as_secs()),
+        );
+    } else if session.destroyed && cookie.is_some() {
+        req.response = req.response.add_cookie(SESSION_COOKIE, Cookie::new("").path("/").max_age(0));
+    }
+    req
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_data_round_trip() {
+        let mut data = HashMap::new();
+        data.insert("user_id".to_string(), "42".to_string());
+        data.insert("note".to_string(), "say \"hi\"".to_string());
+        assert_eq!(decode_data(&encode_data(&data)), data);
+        assert!(decode_data("not json").is_empty());
+    }
+
+    #[test]
+    fn test_session_rw_tracks_changes() {
+        let mut session = SqlSessionRW::new("id".to_string(), HashMap::new(), 0);
+        assert!(!session.is_modified());
+        assert_eq!(session.remove("missing"), None);
+        assert!(!session.is_modified());
+        session.set("user_id", "42");
+        assert_eq!(session.get("user_id").map(String::as_str), Some("42"));
+        assert!(session.is_modified());
+    }
+
+    #[test]
+    fn test_session_rw_renews_its_id() {
+        let data = HashMap::from([("user_id".to_string(), "42".to_string())]);
+        let mut session = SqlSessionRW::new("old".to_string(), data, 0);
+        session.renew();
+        session.renew();
+        assert_ne!(session.id(), "old");
+        assert_eq!(session.id().len(), SESSION_ID_LENGTH);
+        assert_eq!(session.renewed_from.as_deref(), Some("old"));
+        assert_eq!(session.get("user_id").map(String::as_str), Some("42"));
+        assert!(session.is_modified());
+    }
+}
@@ -213,4 +213,175 @@ async fn test_sqlpool_trait() {
     // Ensure we can access the inner connection
     let _conn_ref = item.connection();
     <SqlPool as Pool>::release(&pool, item).await;
-} 
\ No newline at end of file
+} 
+#[test]
+fn test_sql_query_paginate() {
+    use starberry_core::http::pagination::Pagination;
+    let query = SqlQuery::new("SELECT * FROM posts ORDER BY id;").paginate(&Pagination::new(3, 10));
+    assert_eq!(query.sql(), "SELECT * FROM posts ORDER BY id LIMIT 10 OFFSET 20");
+    let query = SqlQuery::new("SELECT * FROM posts WHERE id > $1 ORDER BY id")
+        .bind(42)
+        .paginate(&Pagination::after("42", 5));
+    assert_eq!(query.sql(), "SELECT * FROM posts WHERE id > $1 ORDER BY id LIMIT 5");
+}
+
+#[cfg(test)]
+fn map_row<T: FromRow>(_query: &TypedQuery<'_, T>, row: &HashMap<String, String>) -> T {
+    T::from_row(row).expect("row mapping failed")
+}
+
+#[test]
+fn test_sql_macro_parameters() {
+    let id = 7;
+    let query = crate::sql!("SELECT id, name FROM users WHERE id = {id} AND name <> '{x}' AND age > {}", 18);
+    assert_eq!(query.sql(), "SELECT id, name FROM users WHERE id = $1 AND name <> '{x}' AND age > $2");
+    let query = query.into_query();
+    assert_eq!(query.params(), &["7".to_string(), "18".to_string()]);
+    // The variables are bound by reference
+    assert_eq!(id, 7);
+}
+
+#[test]
+fn test_sql_macro_anonymous_row() {
+    let query = crate::sql!("SELECT u.id, count(*) AS total, \"Name\" FROM users u WHERE u.id = {}", 1);
+    let mut row = HashMap::new();
+    row.insert("id".to_string(), "1".to_string());
+    row.insert("total".to_string(), "3".to_string());
+    row.insert("Name".to_string(), "alice".to_string());
+    let mapped = map_row(&query, &row);
+    assert_eq!(mapped.id, "1");
+    assert_eq!(mapped.total, "3");
+    assert_eq!(mapped.Name, "alice");
+    assert_eq!(query.sql(), "SELECT u.id, count(*) AS total, \"Name\" FROM users u WHERE u.id = $1");
+}
+
+#[test]
+fn test_sql_macro_typed_and_untyped() {
+    let query = crate::sql!(TestRow => "SELECT * FROM users WHERE id = {}", 1);
+    let mut row = HashMap::new();
+    row.insert("id".to_string(), "5".to_string());
+    row.insert("name".to_string(), "bob".to_string());
+    assert_eq!(map_row(&query, &row), TestRow { id: 5, name: "bob".to_string() });
+
+    let name = "alice";
+    let query: SqlQuery = crate::sql!("SELECT * FROM users WHERE name = {name}");
+    assert_eq!(query.sql(), "SELECT * FROM users WHERE name = $1");
+    assert_eq!(query.params(), &["alice".to_string()]);
+}
+
+#[test]
+fn test_sql_query_with_version() {
+    let query = SqlQuery::new("UPDATE posts SET title = $1 WHERE id = $2 OR slug = 'where';")
+        .bind("title")
+        .bind(1)
+        .with_version("version", 3);
+    assert_eq!(
+        query.sql(),
+        "UPDATE posts SET title = $1, version = version + 1 WHERE (id = $2 OR slug = 'where') AND version = 3"
+    );
+    let query = SqlQuery::new("DELETE FROM posts RETURNING id").with_version("version", 1);
+    assert_eq!(query.sql(), "DELETE FROM posts WHERE version = 1 RETURNING id");
+}
+
+#[test]
+fn test_sql_query_soft_delete() {
+    let query = SqlQuery::new("SELECT * FROM posts WHERE author = $1 ORDER BY id").soft_delete("deleted_at");
+    assert_eq!(query.sql(), "SELECT * FROM posts WHERE (author = $1) AND deleted_at IS NULL ORDER BY id");
+    let query = SqlQuery::new("SELECT count(*) FROM (SELECT id FROM posts WHERE hidden) p").soft_delete("p.deleted_at");
+    assert_eq!(query.sql(), "SELECT count(*) FROM (SELECT id FROM posts WHERE hidden) p WHERE p.deleted_at IS NULL");
+    let query = SqlQuery::new("DELETE FROM posts WHERE id = $1").bind(1).soft_delete("deleted_at");
+    assert_eq!(query.sql(), "UPDATE posts SET deleted_at = CURRENT_TIMESTAMP WHERE (id = $1) AND deleted_at IS NULL");
+    let query = SqlQuery::new("DELETE FROM posts WHERE id = $1")
+        .soft_delete("deleted_at")
+        .with_version("version", 2);
+    assert_eq!(
+        query.sql(),
+        "UPDATE posts SET deleted_at = CURRENT_TIMESTAMP, version = version + 1 WHERE ((id = $1) AND deleted_at IS NULL) AND version = 2"
+    );
+}
+
+#[tokio::test]
+async fn test_sql_query_timeout_and_cancel() {
+    use starberry_core::connection::{CancelReason, CancellationToken};
+    use std::time::Duration;
+    let builder = DbConnectionBuilder::new("127.0.0.1", 5432)
+        .ssl_mode(SslMode::Disable)
+        .database("postgres")
+        .username("postgres")
+        .password("JerrySu5379");
+
+    let mut conn = builder.connect().await.expect("connect failed");
+    let result = SqlQuery::new("SELECT pg_sleep(5)")
+        .timeout(Duration::from_millis(200))
+        .execute(&mut conn)
+        .await;
+    assert!(matches!(result, Err(DbError::TimeoutError(_))));
+    assert!(!conn.is_open());
+
+    let mut conn = builder.connect().await.expect("connect failed");
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        canceller.cancel(CancelReason::ClientDisconnected);
+    });
+    let result = SqlQuery::new("SELECT pg_sleep(5)").cancel_on(token.clone()).execute(&mut conn).await;
+    assert!(matches!(result, Err(DbError::CancelledError(_))));
+    // A cancelled token stops the next queries before they are sent
+    let result = SqlQuery::new("SELECT 1").cancel_on(token).execute(&mut conn).await;
+    assert!(matches!(result, Err(DbError::CancelledError(_))));
+}
+
+#[test]
+fn test_sql_query_uses_replica() {
+    assert!(SqlQuery::new("SELECT * FROM posts WHERE id = $1").uses_replica());
+    assert!(SqlQuery::new("  with recent AS (SELECT * FROM posts) SELECT count(*) FROM recent").uses_replica());
+    assert!(!SqlQuery::new("SELECT * FROM posts WHERE id = $1 FOR UPDATE").uses_replica());
+    assert!(!SqlQuery::new("WITH moved AS (DELETE FROM posts RETURNING *) SELECT * FROM moved").uses_replica());
+    assert!(!SqlQuery::new("SELECT nextval('posts_id_seq')").uses_replica());
+    assert!(!SqlQuery::new("UPDATE posts SET title = $1").uses_replica());
+    assert!(!SqlQuery::new("SELECT * FROM posts").on_primary().uses_replica());
+    assert!(SqlQuery::new("CALL report()").on_replica().uses_replica());
+}
+
+#[tokio::test]
+async fn test_sqlpool_replica_fallback() {
+    let builder = DbConnectionBuilder::new("127.0.0.1", 5432)
+        .ssl_mode(SslMode::Disable)
+        .database("postgres")
+        .username("postgres")
+        .password("JerrySu5379");
+    // The replica cannot be reached, so reads fall back to the primary
+    let replica = DbConnectionBuilder::new("127.0.0.1", 1)
+        .ssl_mode(SslMode::Disable)
+        .username("postgres")
+        .password("JerrySu5379");
+    let pool = SqlPool::new(builder, 2)
+        .with_replica(replica, 2)
+        .max_replica_lag(std::time::Duration::from_secs(1));
+    let mut conn = pool.get_read().await.expect("read connection failed");
+    conn.connection().execute_query("SELECT 1", Vec::new()).await.expect("query failed");
+    drop(conn);
+    let rows = SqlQuery::new("SELECT 1 AS one").fetch_all_pool(&pool).await.expect("fetch failed");
+    assert_eq!(rows[0].get("one").map(String::as_str), Some("1"));
+}
+
+#[test]
+fn test_sql_macro_mysql_placeholders() {
+    use starberry_core::connection::Protocol;
+    let query: SqlQuery = crate::sql!(
+        "SELECT * FROM users /* {skip} 'it' */ WHERE id = {} -- {also}\n AND name = '$1' AND tag = {}",
+        1,
+        "a"
+    );
+    assert_eq!(
+        query.sql_for(Protocol::MySQL),
+        "SELECT * FROM users /* {skip} 'it' */ WHERE id = ? -- {also}\n AND name = '$1' AND tag = ?"
+    );
+    assert_eq!(query.sql_for(Protocol::Postgres), query.sql());
+    let query = SqlQuery::new("UPDATE t SET a$1 = $1 WHERE id = $2").numbered();
+    assert_eq!(query.sql_for(Protocol::MySQL), "UPDATE t SET a$1 = ? WHERE id = ?");
+    // Queries written by hand keep their placeholders
+    let query = SqlQuery::new("SELECT * FROM users WHERE id = $1").bind(1);
+    assert_eq!(query.sql_for(Protocol::MySQL), "SELECT * FROM users WHERE id = $1");
+}